@@ -0,0 +1,96 @@
+//! Encoding and decoding of the protobuf messages on the delivery hot paths.
+//!
+//! Each group benches the previous approach against the one the server uses now, so a change to
+//! either shows up as a shift between the two.
+
+use bytes::{Bytes, BytesMut};
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use obscura_server::proto::obscura::v1 as proto;
+use prost::Message;
+use std::hint::black_box;
+use uuid::Uuid;
+
+/// Messages in a batch, the default gateway fetch size.
+const BATCH: usize = 50;
+
+/// Ciphertext sizes: a short text message and one carrying an attachment pointer and padding.
+const SIZES: [usize; 2] = [256, 4096];
+
+fn envelope_frame(size: usize) -> proto::WebSocketFrame {
+    let envelopes = (0..BATCH)
+        .map(|_| proto::Envelope {
+            id: Uuid::now_v7().as_bytes().to_vec(),
+            sender_id: Uuid::new_v4().as_bytes().to_vec(),
+            sender_device_id: Uuid::new_v4().as_bytes().to_vec(),
+            timestamp: 1_700_000_000_000,
+            message: vec![0xA5; size],
+            ..Default::default()
+        })
+        .collect();
+    proto::WebSocketFrame {
+        payload: Some(proto::web_socket_frame::Payload::EnvelopeBatch(proto::EnvelopeBatch { envelopes })),
+    }
+}
+
+fn send_request(size: usize) -> Bytes {
+    let messages = (0..BATCH)
+        .map(|_| proto::send_message_request::Submission {
+            submission_id: Uuid::new_v4().as_bytes().to_vec(),
+            device_id: Uuid::new_v4().as_bytes().to_vec(),
+            message: vec![0x5A; size].into(),
+        })
+        .collect();
+    proto::SendMessageRequest { messages }.encode_to_vec().into()
+}
+
+fn throughput(len: usize) -> Throughput {
+    Throughput::Bytes(u64::try_from(len).unwrap_or(u64::MAX))
+}
+
+fn bench_frame_encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("envelope_batch_encode");
+    for size in SIZES {
+        let frame = envelope_frame(size);
+        group.throughput(throughput(frame.encoded_len()));
+
+        group.bench_with_input(BenchmarkId::new("growing_vec", size), &frame, |b, frame| {
+            b.iter(|| {
+                let mut buf = Vec::new();
+                frame.encode(&mut buf).expect("frame encodes");
+                black_box(Bytes::from(buf))
+            });
+        });
+
+        group.bench_with_input(BenchmarkId::new("reused_bytes_mut", size), &frame, |b, frame| {
+            let mut buf = BytesMut::new();
+            b.iter(|| {
+                buf.reserve(frame.encoded_len());
+                frame.encode(&mut buf).expect("frame encodes");
+                black_box(buf.split().freeze())
+            });
+        });
+    }
+    group.finish();
+}
+
+fn bench_send_request_decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("send_request_decode");
+    for size in SIZES {
+        let body = send_request(size);
+        group.throughput(throughput(body.len()));
+
+        // Decoding from a slice copies every message body out of the request.
+        group.bench_with_input(BenchmarkId::new("copied", size), &body, |b, body| {
+            b.iter(|| black_box(proto::SendMessageRequest::decode(body.as_ref()).expect("request decodes")));
+        });
+
+        // Decoding from the request's `Bytes` slices the bodies out of it.
+        group.bench_with_input(BenchmarkId::new("sliced", size), &body, |b, body| {
+            b.iter(|| black_box(proto::SendMessageRequest::decode(body.clone()).expect("request decodes")));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_frame_encode, bench_send_request_decode);
+criterion_main!(benches);
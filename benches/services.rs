@@ -0,0 +1,102 @@
+//! Core service paths against the containers from `just services`: message insert and fetch,
+//! acknowledgment flushes, and pre-key bundle fetches contending for the same device.
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use obscura_server::testing::bench::BenchApp;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use tokio::runtime::Runtime;
+
+/// Messages per send and per fetch, the default gateway fetch size.
+const BATCH: usize = 50;
+
+/// `BATCH` as a fetch limit.
+const FETCH_LIMIT: i64 = 50;
+
+/// A typical short text message once encrypted and padded.
+const PAYLOAD: [u8; 256] = [0xA5; 256];
+
+fn runtime() -> Runtime {
+    Runtime::new().expect("Tokio runtime starts")
+}
+
+fn bench_messages(c: &mut Criterion) {
+    let rt = runtime();
+    let bench = rt.block_on(BenchApp::spawn());
+    let mut group = c.benchmark_group("messages");
+    group.throughput(Throughput::Elements(BATCH as u64));
+
+    // Timed inserts; each batch is acknowledged away untimed so the inbox does not grow.
+    group.bench_function("send_batch", |b| {
+        b.to_async(&rt).iter_custom(|iters| {
+            let bench = &bench;
+            async move {
+                let mut elapsed = Duration::ZERO;
+                for _ in 0..iters {
+                    let start = Instant::now();
+                    let ids = bench.send_batch(BATCH, &PAYLOAD).await;
+                    elapsed += start.elapsed();
+                    bench.flush_acks(ids).await;
+                }
+                elapsed
+            }
+        });
+    });
+
+    let queued = rt.block_on(bench.send_batch(BATCH * 4, &PAYLOAD));
+    group.bench_function("fetch_pending_batch", |b| {
+        b.to_async(&rt).iter(|| async { black_box(bench.fetch_batch(FETCH_LIMIT).await) });
+    });
+    rt.block_on(bench.flush_acks(queued));
+
+    group.bench_function("ack_flush", |b| {
+        b.to_async(&rt).iter_custom(|iters| {
+            let bench = &bench;
+            async move {
+                let mut elapsed = Duration::ZERO;
+                for _ in 0..iters {
+                    let ids = bench.send_batch(BATCH, &PAYLOAD).await;
+                    let start = Instant::now();
+                    bench.flush_acks(ids).await;
+                    elapsed += start.elapsed();
+                }
+                elapsed
+            }
+        });
+    });
+    group.finish();
+}
+
+fn bench_bundle_fetch(c: &mut Criterion) {
+    let rt = runtime();
+    let bench = rt.block_on(BenchApp::spawn());
+    let mut group = c.benchmark_group("pre_key_bundle_fetch");
+
+    for concurrency in [1, 8, 32] {
+        group.throughput(Throughput::Elements(concurrency as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(concurrency), &concurrency, |b, &concurrency| {
+            b.to_async(&rt).iter_custom(|iters| {
+                let bench = &bench;
+                async move {
+                    let mut elapsed = Duration::ZERO;
+                    for _ in 0..iters {
+                        // Every fetch consumes a key, so the pool is topped up untimed first.
+                        bench.add_pre_keys(concurrency).await;
+                        let start = Instant::now();
+                        bench.fetch_bundles_concurrently(concurrency).await;
+                        elapsed += start.elapsed();
+                    }
+                    elapsed
+                }
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(20);
+    targets = bench_messages, bench_bundle_fetch
+}
+criterion_main!(benches);
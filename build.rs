@@ -1,3 +1,75 @@
+use prost::Message;
+use prost_types::{DescriptorProto, FileDescriptorSet};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
 fn main() {
-    prost_build::compile_protos(&["proto/obscura/v1/obscura.proto"], &["proto/"]).expect("Failed to compile protos");
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").expect("OUT_DIR is set by cargo"));
+    let descriptor_path = out_dir.join("obscura_descriptor.bin");
+
+    // Message bodies are decoded as slices of the request body instead of being copied out of it.
+    prost_build::Config::new()
+        .file_descriptor_set_path(&descriptor_path)
+        .bytes([".obscura.v1.SendMessageRequest.Submission.message"])
+        .compile_protos(&["proto/obscura/v1/obscura.proto"], &["proto/"])
+        .expect("Failed to compile protos");
+
+    write_message_registry(&descriptor_path, &out_dir.join("obscura_messages.rs"));
+}
+
+/// Writes a lookup from each message's full protobuf name to its generated type, so the wire
+/// compatibility tests cover a new message without being told about it.
+fn write_message_registry(descriptor_path: &Path, out: &Path) {
+    let bytes = std::fs::read(descriptor_path).expect("Failed to read the descriptor set");
+    let descriptors = FileDescriptorSet::decode(bytes.as_slice()).expect("Failed to decode the descriptor set");
+
+    let mut arms = String::new();
+    for file in &descriptors.file {
+        let module = format!("crate::proto::{}::", file.package().replace('.', "::"));
+        for message in &file.message_type {
+            collect_messages(message, file.package(), &module, &mut arms);
+        }
+    }
+
+    let mut source = String::from(
+        "/// Decodes `bytes` as the message named `full_name` and encodes it again, or `None` if there is no such message.\n\
+         #[must_use]\n#[allow(clippy::too_many_lines)]\n\
+         pub fn reencode(full_name: &str, bytes: &[u8]) -> Option<Result<Vec<u8>, prost::DecodeError>> {\n    Some(match full_name {\n",
+    );
+    source.push_str(&arms);
+    source.push_str("        _ => return None,\n    })\n}\n");
+    std::fs::write(out, source).expect("Failed to write the message registry");
+}
+
+fn collect_messages(message: &DescriptorProto, scope: &str, module: &str, arms: &mut String) {
+    // Map entries are not generated as types of their own
+    if message.options.as_ref().is_some_and(prost_types::MessageOptions::map_entry) {
+        return;
+    }
+    let name = message.name();
+    let full_name = format!("{scope}.{name}");
+    let _ = writeln!(arms, "        {full_name:?} => reencode_as::<{module}{name}>(bytes),");
+
+    let nested_module = format!("{module}{}::", to_snake_case(name));
+    for nested in &message.nested_type {
+        collect_messages(nested, &full_name, &nested_module, arms);
+    }
+}
+
+/// The module prost puts a message's nested types in, e.g. `send_message_request` for
+/// `SendMessageRequest`.
+fn to_snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut snake = String::with_capacity(name.len() + 4);
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_is_lower = chars.get(i + 1).is_some_and(char::is_ascii_lowercase);
+            if prev.is_ascii_lowercase() || prev.is_ascii_digit() || (prev.is_ascii_uppercase() && next_is_lower) {
+                snake.push('_');
+            }
+        }
+        snake.push(c.to_ascii_lowercase());
+    }
+    snake
 }
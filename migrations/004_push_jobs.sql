@@ -0,0 +1,10 @@
+-- Optional Postgres backend for the push notification job queue
+-- (selected with --notifications-push-queue-backend=postgres).
+CREATE TABLE push_jobs (
+    device_id UUID PRIMARY KEY REFERENCES devices(id) ON DELETE CASCADE,
+    run_at TIMESTAMPTZ NOT NULL,
+    leased_until TIMESTAMPTZ,
+    delivered BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE INDEX idx_push_jobs_due ON push_jobs ((COALESCE(leased_until, run_at)));
@@ -0,0 +1,7 @@
+-- Operator-managed client network blocklist, edited through the management API.
+-- Entries from --blocklist-file are not stored here.
+CREATE TABLE ip_blocklist (
+    network CIDR PRIMARY KEY,
+    reason TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,10 @@
+-- Records deleted accounts so that their usernames stay reserved for a grace period
+-- and former contacts can be told the account was deleted rather than never existed.
+CREATE TABLE account_tombstones (
+    user_id UUID PRIMARY KEY,
+    username VARCHAR(50) NOT NULL,
+    deleted_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    reusable_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_account_tombstones_username ON account_tombstones(username, reusable_at);
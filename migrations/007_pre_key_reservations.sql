@@ -0,0 +1,9 @@
+-- One-time pre-keys handed to a sender that prefetches bundles are reserved rather than
+-- deleted, and only removed once the sender redeems the reservation with its first message.
+-- A lapsed reservation returns the key to the pool.
+ALTER TABLE one_time_pre_keys
+    ADD COLUMN reservation_token UUID,
+    ADD COLUMN reserved_until TIMESTAMPTZ;
+
+CREATE INDEX idx_one_time_pre_keys_reservation ON one_time_pre_keys(reservation_token)
+    WHERE reservation_token IS NOT NULL;
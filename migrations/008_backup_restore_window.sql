@@ -0,0 +1,7 @@
+-- The version replaced by the latest upload or restore is kept, soft-deleted at deleted_at,
+-- until the restore window passes and the cleanup worker removes its object.
+ALTER TABLE backups
+    ADD COLUMN previous_version INT,
+    ADD COLUMN deleted_at TIMESTAMPTZ;
+
+CREATE INDEX idx_backups_deleted_at ON backups(deleted_at) WHERE previous_version IS NOT NULL;
@@ -0,0 +1,26 @@
+-- Operator announcements broadcast to every user. `payload` holds the signed announcement
+-- exactly as clients receive it, so the live frame and the queued envelope carry the same bytes.
+-- `queued_at` is set once the announcement has been queued for users who did not see it live.
+CREATE TABLE announcements (
+    id UUID PRIMARY KEY,
+    payload BYTEA NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    queued_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_announcements_unqueued ON announcements(created_at) WHERE queued_at IS NULL;
+CREATE INDEX idx_announcements_expires_at ON announcements(expires_at);
+
+-- One row per user an announcement was handed to, whether live or queued, so nobody gets it twice.
+CREATE TABLE announcement_receipts (
+    announcement_id UUID NOT NULL REFERENCES announcements(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (announcement_id, user_id)
+);
+
+-- Queued announcements are system envelopes, which have no sender.
+ALTER TABLE messages
+    ALTER COLUMN sender_id DROP NOT NULL,
+    ALTER COLUMN sender_device_id DROP NOT NULL;
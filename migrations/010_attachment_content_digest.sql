@@ -0,0 +1,5 @@
+-- Attachments uploaded with a client-supplied SHA-256 are stored under a digest-derived key, so
+-- several rows can share one object. The object is removed once the last row referencing it expires.
+ALTER TABLE attachments ADD COLUMN content_digest BYTEA;
+
+CREATE INDEX idx_attachments_content_digest ON attachments(content_digest) WHERE content_digest IS NOT NULL;
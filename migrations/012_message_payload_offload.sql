@@ -0,0 +1,13 @@
+-- Large message ciphertexts can be kept in object storage. The message row then holds an empty
+-- `content` and the payload size; the object is keyed by the message ID.
+ALTER TABLE messages ADD COLUMN payload_size INTEGER;
+
+-- Every offloaded object is recorded here before it is uploaded and stays recorded after its
+-- message is gone, so the cleanup worker can find objects whose message was acknowledged,
+-- expired, pruned or never inserted.
+CREATE TABLE message_payloads (
+    message_id UUID PRIMARY KEY,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_message_payloads_created_at ON message_payloads(created_at);
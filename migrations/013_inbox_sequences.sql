@@ -0,0 +1,9 @@
+-- Per-recipient counter behind the sequence numbers in submission receipts. A device's numbers
+-- only ever increase, and duplicate submissions that are ignored do not take one.
+CREATE TABLE inbox_sequences (
+    device_id UUID PRIMARY KEY REFERENCES devices(id) ON DELETE CASCADE,
+    last_seq BIGINT NOT NULL
+);
+
+-- Left NULL for system envelopes and for messages queued before sequencing existed.
+ALTER TABLE messages ADD COLUMN seq BIGINT;
@@ -0,0 +1,28 @@
+-- Per-(sender device, recipient device) counter, so each conversation pair has its own gapless
+-- sequence that clients can check their ordering against.
+CREATE TABLE pair_sequences (
+    sender_device_id UUID NOT NULL REFERENCES devices(id) ON DELETE CASCADE,
+    device_id UUID NOT NULL REFERENCES devices(id) ON DELETE CASCADE,
+    last_seq BIGINT NOT NULL,
+    PRIMARY KEY (sender_device_id, device_id)
+);
+
+-- NULL for system envelopes, which have no sender.
+ALTER TABLE messages ADD COLUMN pair_seq BIGINT;
+
+-- The inbox sequence becomes the fetch cursor. Messages queued without one are numbered below
+-- zero in their existing order, so they are still delivered first.
+UPDATE messages m
+SET seq = b.seq
+FROM (
+    SELECT id, -row_number() OVER (PARTITION BY device_id ORDER BY created_at DESC, id DESC) AS seq
+    FROM messages
+    WHERE seq IS NULL
+) b
+WHERE m.id = b.id;
+
+ALTER TABLE messages ALTER COLUMN seq SET NOT NULL;
+
+CREATE INDEX idx_messages_shard_seq ON messages(device_id, shard, seq) INCLUDE (expires_at);
+
+DROP INDEX idx_messages_shard_fetch;
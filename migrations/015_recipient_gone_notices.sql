@@ -0,0 +1,13 @@
+-- System envelopes record what they carry, so clients can tell an announcement from other
+-- server notices. Every system envelope queued so far is an announcement.
+ALTER TABLE messages ADD COLUMN system_code SMALLINT;
+UPDATE messages SET system_code = 1 WHERE sender_id IS NULL;
+
+-- Sender devices that were told a deleted account will never receive their pending messages.
+CREATE TABLE account_tombstone_notices (
+    user_id UUID NOT NULL REFERENCES account_tombstones(user_id) ON DELETE CASCADE,
+    sender_device_id UUID NOT NULL REFERENCES devices(id) ON DELETE CASCADE,
+    undelivered INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, sender_device_id)
+);
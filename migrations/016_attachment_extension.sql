@@ -0,0 +1,3 @@
+-- Recipients can extend an attachment's expiry up to a cap counted from when it was created.
+-- Attachments that predate this column count their lifetime from the migration.
+ALTER TABLE attachments ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
@@ -0,0 +1,12 @@
+-- Users can see how much storage they consume. Attachments record their uploader and size,
+-- backups the size of their current and previous version. Rows that predate these columns
+-- have no size and count as empty.
+ALTER TABLE attachments
+    ADD COLUMN owner_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    ADD COLUMN size_bytes BIGINT;
+
+CREATE INDEX idx_attachments_owner_id ON attachments(owner_id) WHERE owner_id IS NOT NULL;
+
+ALTER TABLE backups
+    ADD COLUMN size_bytes BIGINT,
+    ADD COLUMN previous_size_bytes BIGINT;
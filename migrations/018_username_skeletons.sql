@@ -0,0 +1,12 @@
+-- Usernames are stored normalized: the check on users.username only admits lowercase ASCII,
+-- which NFKC normalization and case folding leave unchanged, so its UNIQUE constraint already
+-- enforces uniqueness of the normalized form. Usernames that merely read alike, such as
+-- "paypa1" and "paypal", are told apart by their confusable skeleton, which must match
+-- domain::username::skeleton. Adding the stored column backfills it for existing users.
+-- Existing confusable pairs are kept, so the index is not unique; registration checks it
+-- when the confusable check is enabled.
+ALTER TABLE users
+    ADD COLUMN username_skeleton VARCHAR(50)
+        GENERATED ALWAYS AS (replace(replace(translate(username, '01i5', 'olls'), 'rn', 'm'), 'vv', 'w')) STORED;
+
+CREATE INDEX idx_users_username_skeleton ON users(username_skeleton);
@@ -0,0 +1,21 @@
+-- Optional email addresses and phone numbers bound to an account, used for recovery and, when the
+-- user opts in, discovery. Only a keyed hash of each identifier is stored.
+CREATE TABLE user_identifiers (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL CHECK (kind IN ('email', 'phone')),
+    identifier_hash BYTEA NOT NULL,
+    discoverable BOOLEAN NOT NULL DEFAULT FALSE,
+    -- Salted hash of the outstanding verification code, cleared once verified or exhausted.
+    code_salt BYTEA,
+    code_hash BYTEA,
+    code_expires_at TIMESTAMPTZ,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    verified_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, kind)
+);
+
+-- An identifier is verified for at most one account; unverified claims may overlap.
+CREATE UNIQUE INDEX idx_user_identifiers_verified
+    ON user_identifiers (kind, identifier_hash)
+    WHERE verified_at IS NOT NULL;
@@ -0,0 +1,2 @@
+-- Why a push job wakes its device, so pre-key refill pushes collapse separately from message checks.
+ALTER TABLE push_jobs ADD COLUMN kind TEXT NOT NULL DEFAULT 'check' CHECK (kind IN ('check', 'prekey_low'));
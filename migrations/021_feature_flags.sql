@@ -0,0 +1,10 @@
+-- Server-side feature flags, edited through the management API and cached by every instance.
+-- A flag that is not listed here is off.
+CREATE TABLE feature_flags (
+    name TEXT PRIMARY KEY,
+    enabled BOOLEAN NOT NULL DEFAULT FALSE,
+    -- Share of subjects the flag is on for while enabled, from 0 to 100.
+    rollout_percent SMALLINT NOT NULL DEFAULT 100 CHECK (rollout_percent BETWEEN 0 AND 100),
+    description TEXT,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
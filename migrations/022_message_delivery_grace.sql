@@ -0,0 +1,6 @@
+-- Acknowledged messages kept for a grace period so a client that crashed right after its ACK can
+-- ask for them again. Set on ACK when the grace period is enabled, cleared on redelivery, and the
+-- cleanup worker purges rows once it is older than the grace period.
+ALTER TABLE messages ADD COLUMN delivered_at TIMESTAMPTZ;
+
+CREATE INDEX idx_messages_delivered_at ON messages (delivered_at) WHERE delivered_at IS NOT NULL;
@@ -0,0 +1,4 @@
+-- Highest inbox sequence number written to one of the device's sessions. An envelope at or below it
+-- has been delivered before, so sending it again counts as a redelivery. NULL until the device's
+-- first session ends.
+ALTER TABLE inbox_sequences ADD COLUMN delivered_seq BIGINT;
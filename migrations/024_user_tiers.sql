@@ -0,0 +1,3 @@
+-- The plan a user is on, which selects the upload, inbox and rate limits that apply to them.
+-- Changed through the management API; existing accounts start on the free tier.
+ALTER TABLE users ADD COLUMN tier TEXT NOT NULL DEFAULT 'free' CHECK (tier IN ('free', 'paid', 'admin'));
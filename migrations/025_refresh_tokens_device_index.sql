@@ -0,0 +1,3 @@
+-- Revoking a session looks up whether the device still holds another one before clearing its
+-- push token.
+CREATE INDEX idx_refresh_tokens_device_id ON refresh_tokens(device_id);
@@ -0,0 +1,34 @@
+-- Who messaged whom, when and how much, so operators can answer requests scoped to metadata
+-- after the messages themselves are gone. Only filled with --metadata-index-enabled and never
+-- holds content. Entries outlive the messages, devices and accounts they describe until the
+-- retention period ends, so there are no foreign keys.
+CREATE TABLE message_metadata (
+    message_id UUID PRIMARY KEY,
+    sender_id UUID NOT NULL,
+    sender_device_id UUID NOT NULL,
+    recipient_id UUID NOT NULL,
+    recipient_device_id UUID NOT NULL,
+    sent_at TIMESTAMPTZ NOT NULL,
+    size_bytes INTEGER NOT NULL
+);
+
+CREATE INDEX idx_message_metadata_sender ON message_metadata(sender_id, sent_at);
+CREATE INDEX idx_message_metadata_recipient ON message_metadata(recipient_id, sent_at);
+CREATE INDEX idx_message_metadata_sent_at ON message_metadata(sent_at);
+
+-- Every query run against the index: who ran it, why, what it asked for and how many entries
+-- it returned. Not subject to the index retention.
+CREATE TABLE metadata_index_queries (
+    id UUID PRIMARY KEY DEFAULT uuidv7(),
+    operator TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    client_cert TEXT,
+    sender_id UUID,
+    recipient_id UUID,
+    since TIMESTAMPTZ,
+    until TIMESTAMPTZ,
+    result_count INTEGER NOT NULL,
+    queried_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_metadata_index_queries_queried_at ON metadata_index_queries(queried_at);
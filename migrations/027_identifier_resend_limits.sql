@@ -0,0 +1,6 @@
+-- Throttles verification code re-sends: when the last code went out, and how many codes have
+-- gone out in the current window. Wrong guesses now carry over between codes within a window.
+ALTER TABLE user_identifiers
+    ADD COLUMN code_sent_at TIMESTAMPTZ,
+    ADD COLUMN codes_sent INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN code_window_started_at TIMESTAMPTZ;
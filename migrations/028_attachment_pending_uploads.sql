@@ -0,0 +1,4 @@
+-- Content-addressed uploads record their attachment before writing the object, so the cleanup
+-- worker counts an upload in flight as a reference to the object. Pending rows are never linked
+-- to by digest or audited, and are removed if their upload fails.
+ALTER TABLE attachments ADD COLUMN pending BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,12 @@
+-- Pending queue sizes as of the moment an operator last started listing the largest inboxes.
+-- Later pages of the listing are read from here, so a walk aggregates the messages table once
+-- rather than once per page. Derived data: it is rebuilt on demand and not archived.
+CREATE TABLE inbox_size_snapshot (
+    device_id UUID PRIMARY KEY,
+    pending BIGINT NOT NULL,
+    bytes BIGINT NOT NULL,
+    oldest TIMESTAMPTZ,
+    next_expiry TIMESTAMPTZ
+);
+
+CREATE INDEX idx_inbox_size_snapshot_pending ON inbox_size_snapshot(pending DESC, device_id DESC);
@@ -0,0 +1,4 @@
+-- When the latest session that raised delivered_seq connected. Expired messages it delivered that
+-- were queued before then count as delivered by push, later ones by websocket. delivered_seq is
+-- now recorded with each ACK flush as well as when the session ends.
+ALTER TABLE inbox_sequences ADD COLUMN delivered_session_at TIMESTAMPTZ;
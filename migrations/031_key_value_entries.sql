@@ -0,0 +1,11 @@
+-- Shared short-lived state (gateway tickets, session ownership, resume state, the instance list
+-- and idempotency responses) for deployments that run without Redis, which is the case when both
+-- notification backends are postgres. Every entry expires; the janitor deletes expired rows.
+-- Unlogged, like the Redis keys it stands in for: a database crash empties it, as a Redis restart would.
+CREATE UNLOGGED TABLE key_value_entries (
+    key TEXT PRIMARY KEY,
+    value BYTEA NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_key_value_entries_expires_at ON key_value_entries(expires_at);
@@ -0,0 +1,6 @@
+-- The device that uploaded an attachment, so support can see what each device holds. Attachments
+-- uploaded before this column, or with a token that names no device, have none. There is no
+-- foreign key: an access token can outlive its device, and the upload should not fail over it.
+ALTER TABLE attachments ADD COLUMN device_id UUID;
+
+CREATE INDEX idx_attachments_device_id ON attachments(device_id) WHERE device_id IS NOT NULL;
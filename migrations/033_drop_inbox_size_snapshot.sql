@@ -0,0 +1,3 @@
+-- The largest inboxes are measured live for each page, so listings no longer share a snapshot
+-- that concurrent walks overwrite and that cannot be rebuilt while the database is read-only.
+DROP TABLE inbox_size_snapshot;
@@ -0,0 +1,453 @@
+use crate::adapters::push::{PushError, PushProvider};
+use crate::adapters::storage::{ObjectInfo, ObjectStorage, ObjectSummary, StorageError, StorageResult, StorageStream};
+use crate::config::CircuitBreakerConfig;
+use crate::domain::notification::PushKind;
+use async_trait::async_trait;
+use opentelemetry::{
+    KeyValue, global,
+    metrics::{Counter, Gauge},
+};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl CircuitState {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Closed => "closed",
+            Self::Open => "open",
+            Self::HalfOpen => "half_open",
+        }
+    }
+
+    const fn gauge_value(self) -> i64 {
+        match self {
+            Self::Closed => 0,
+            Self::HalfOpen => 1,
+            Self::Open => 2,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Metrics {
+    state: Gauge<i64>,
+    transitions_total: Counter<u64>,
+    rejected_total: Counter<u64>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let meter = global::meter("obscura-server");
+        Self {
+            state: meter
+                .i64_gauge("obscura_circuit_breaker_state")
+                .with_description("Circuit breaker state per dependency (0 closed, 1 half-open, 2 open)")
+                .build(),
+            transitions_total: meter
+                .u64_counter("obscura_circuit_breaker_transitions_total")
+                .with_description("Circuit breaker state changes, labelled by the new state")
+                .build(),
+            rejected_total: meter
+                .u64_counter("obscura_circuit_breaker_rejected_total")
+                .with_description("Calls rejected without reaching the dependency because the circuit was open")
+                .build(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Inner {
+    state: CircuitState,
+    window_start: Instant,
+    successes: u32,
+    failures: u32,
+    opened_at: Instant,
+    probes_in_flight: u32,
+    probe_successes: u32,
+    /// Counts half-open periods, so a probe from an earlier one never touches the current one.
+    half_open_epoch: u64,
+}
+
+/// A call admitted by the breaker. Dropping it without an outcome, e.g. when the caller's
+/// future is cancelled mid-call, gives a half-open probe slot back instead of leaking it.
+struct Admission<'a> {
+    breaker: &'a CircuitBreaker,
+    /// The half-open period this call probes, if it is a probe.
+    probe: Option<u64>,
+    recorded: bool,
+}
+
+impl Admission<'_> {
+    fn record(mut self, success: bool) {
+        self.recorded = true;
+        self.breaker.record(self.probe, success);
+    }
+}
+
+impl Drop for Admission<'_> {
+    fn drop(&mut self) {
+        if !self.recorded
+            && let Some(epoch) = self.probe
+        {
+            self.breaker.release_probe(epoch);
+        }
+    }
+}
+
+/// A failure-rate circuit breaker guarding calls to an external dependency.
+///
+/// While closed, outcomes are counted over a fixed window; once the window holds enough
+/// calls and the failure rate crosses the threshold the circuit opens and calls fail fast.
+/// After the open period a limited number of probes are let through (half-open): if they
+/// all succeed the circuit closes again, a single failure re-opens it.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    dependency: &'static str,
+    window: Duration,
+    failure_rate: f64,
+    min_requests: u32,
+    open_duration: Duration,
+    half_open_probes: u32,
+    inner: Mutex<Inner>,
+    metrics: Metrics,
+}
+
+impl CircuitBreaker {
+    #[must_use]
+    pub fn new(dependency: &'static str, config: &CircuitBreakerConfig) -> Self {
+        let now = Instant::now();
+        let metrics = Metrics::new();
+        metrics.state.record(CircuitState::Closed.gauge_value(), &[KeyValue::new("dependency", dependency)]);
+        Self {
+            dependency,
+            window: Duration::from_secs(config.window_secs),
+            failure_rate: config.failure_rate,
+            min_requests: config.min_requests.max(1),
+            open_duration: Duration::from_secs(config.open_secs),
+            half_open_probes: config.half_open_probes.max(1),
+            inner: Mutex::new(Inner {
+                state: CircuitState::Closed,
+                window_start: now,
+                successes: 0,
+                failures: 0,
+                opened_at: now,
+                probes_in_flight: 0,
+                probe_successes: 0,
+                half_open_epoch: 0,
+            }),
+            metrics,
+        }
+    }
+
+    #[must_use]
+    pub fn state(&self) -> CircuitState {
+        self.inner.lock().map_or(CircuitState::Closed, |inner| inner.state)
+    }
+
+    /// Runs `call` through the breaker. Returns `None` without polling the future if the
+    /// circuit is open; otherwise the outcome is recorded, using `is_failure` to decide
+    /// which errors count against the dependency (client errors usually should not).
+    pub async fn call<T, E, F>(&self, call: F, is_failure: impl Fn(&E) -> bool) -> Option<Result<T, E>>
+    where
+        F: Future<Output = Result<T, E>>,
+    {
+        let admission = self.try_acquire()?;
+        let result = call.await;
+        let failed = result.as_ref().err().is_some_and(is_failure);
+        admission.record(!failed);
+        Some(result)
+    }
+
+    /// Admits a call if the circuit allows it, as a probe while half-open.
+    fn try_acquire(&self) -> Option<Admission<'_>> {
+        let admit = |probe| Some(Admission { breaker: self, probe, recorded: false });
+        let Ok(mut inner) = self.inner.lock() else {
+            return admit(None);
+        };
+
+        match inner.state {
+            CircuitState::Closed => admit(None),
+            CircuitState::Open if inner.opened_at.elapsed() >= self.open_duration => {
+                self.transition(&mut inner, CircuitState::HalfOpen);
+                inner.probes_in_flight = 1;
+                admit(Some(inner.half_open_epoch))
+            }
+            CircuitState::HalfOpen if inner.probes_in_flight + inner.probe_successes < self.half_open_probes => {
+                inner.probes_in_flight += 1;
+                admit(Some(inner.half_open_epoch))
+            }
+            CircuitState::Open | CircuitState::HalfOpen => {
+                self.metrics.rejected_total.add(1, &[KeyValue::new("dependency", self.dependency)]);
+                None
+            }
+        }
+    }
+
+    fn record(&self, probe: Option<u64>, success: bool) {
+        let Ok(mut inner) = self.inner.lock() else {
+            return;
+        };
+
+        match inner.state {
+            CircuitState::HalfOpen if probe == Some(inner.half_open_epoch) => {
+                inner.probes_in_flight = inner.probes_in_flight.saturating_sub(1);
+                if success {
+                    inner.probe_successes += 1;
+                    if inner.probe_successes >= self.half_open_probes {
+                        self.transition(&mut inner, CircuitState::Closed);
+                    }
+                } else {
+                    self.transition(&mut inner, CircuitState::Open);
+                }
+            }
+            CircuitState::Closed => {
+                if inner.window_start.elapsed() >= self.window {
+                    inner.window_start = Instant::now();
+                    inner.successes = 0;
+                    inner.failures = 0;
+                }
+                if success {
+                    inner.successes += 1;
+                } else {
+                    inner.failures += 1;
+                }
+
+                let total = inner.successes + inner.failures;
+                if total >= self.min_requests && f64::from(inner.failures) / f64::from(total) >= self.failure_rate {
+                    self.transition(&mut inner, CircuitState::Open);
+                }
+            }
+            // Calls admitted before the circuit changed state do not affect the new state.
+            CircuitState::HalfOpen | CircuitState::Open => {}
+        }
+    }
+
+    /// Frees the slot of a probe that ended without an outcome.
+    fn release_probe(&self, epoch: u64) {
+        let Ok(mut inner) = self.inner.lock() else {
+            return;
+        };
+        if inner.state == CircuitState::HalfOpen && inner.half_open_epoch == epoch {
+            inner.probes_in_flight = inner.probes_in_flight.saturating_sub(1);
+        }
+    }
+
+    fn transition(&self, inner: &mut Inner, to: CircuitState) {
+        let now = Instant::now();
+        match to {
+            CircuitState::Open => inner.opened_at = now,
+            CircuitState::HalfOpen => {
+                inner.probes_in_flight = 0;
+                inner.probe_successes = 0;
+                inner.half_open_epoch += 1;
+            }
+            CircuitState::Closed => {
+                inner.window_start = now;
+                inner.successes = 0;
+                inner.failures = 0;
+            }
+        }
+        inner.state = to;
+
+        let dependency = KeyValue::new("dependency", self.dependency);
+        self.metrics.state.record(to.gauge_value(), std::slice::from_ref(&dependency));
+        self.metrics.transitions_total.add(1, &[dependency, KeyValue::new("state", to.as_str())]);
+
+        if to == CircuitState::Open {
+            tracing::warn!(dependency = self.dependency, "Circuit breaker opened, failing fast");
+        } else {
+            tracing::info!(dependency = self.dependency, state = to.as_str(), "Circuit breaker state changed");
+        }
+    }
+}
+
+/// Wraps a `PushProvider` so that an outage of the push service fails fast.
+/// Unregistered tokens, quota errors and rejected requests are answers from a healthy provider and do not
+/// trip the circuit.
+#[derive(Debug)]
+pub struct CircuitBreakerPushProvider {
+    inner: Arc<dyn PushProvider>,
+    breaker: CircuitBreaker,
+}
+
+impl CircuitBreakerPushProvider {
+    #[must_use]
+    pub fn new(inner: Arc<dyn PushProvider>, breaker: CircuitBreaker) -> Self {
+        Self { inner, breaker }
+    }
+}
+
+#[async_trait]
+impl PushProvider for CircuitBreakerPushProvider {
+    async fn send_push(&self, token: &str, kind: PushKind) -> Result<(), PushError> {
+        self.breaker
+            .call(self.inner.send_push(token, kind), PushError::is_transient)
+            .await
+            .unwrap_or(Err(PushError::Unavailable))
+    }
+
+    // Bypasses the breaker, like the credential check: validation never delivers anything
+    async fn validate_push(&self, token: &str, kind: PushKind) -> Result<(), PushError> {
+        self.inner.validate_push(token, kind).await
+    }
+
+    // Bypasses the breaker: a credential check should neither trip it nor be masked by it
+    async fn check_credentials(&self) -> Result<(), PushError> {
+        self.inner.check_credentials().await
+    }
+}
+
+/// Wraps an `ObjectStorage` so that an outage of the object store fails fast.
+/// Only internal errors count as failures; missing objects and size violations are client errors.
+pub struct CircuitBreakerStorage {
+    inner: Arc<dyn ObjectStorage>,
+    breaker: CircuitBreaker,
+}
+
+impl CircuitBreakerStorage {
+    #[must_use]
+    pub fn new(inner: Arc<dyn ObjectStorage>, breaker: CircuitBreaker) -> Self {
+        Self { inner, breaker }
+    }
+
+    async fn guard<T>(&self, call: impl Future<Output = StorageResult<T>>) -> StorageResult<T> {
+        self.breaker
+            .call(call, |e| matches!(e, StorageError::Internal(_)))
+            .await
+            .unwrap_or(Err(StorageError::Unavailable))
+    }
+}
+
+impl std::fmt::Debug for CircuitBreakerStorage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CircuitBreakerStorage").field("breaker", &self.breaker).finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl ObjectStorage for CircuitBreakerStorage {
+    async fn put(
+        &self,
+        key: &str,
+        stream: StorageStream,
+        content_len: Option<usize>,
+        min_size: usize,
+        max_size: usize,
+        sha256: Option<[u8; 32]>,
+    ) -> StorageResult<u64> {
+        self.guard(self.inner.put(key, stream, content_len, min_size, max_size, sha256)).await
+    }
+
+    async fn get(&self, key: &str) -> StorageResult<(u64, StorageStream)> {
+        self.guard(self.inner.get(key)).await
+    }
+
+    async fn head(&self, key: &str) -> StorageResult<ObjectInfo> {
+        self.guard(self.inner.head(key)).await
+    }
+
+    async fn delete(&self, key: &str) -> StorageResult<()> {
+        self.guard(self.inner.delete(key)).await
+    }
+
+    async fn delete_many(&self, keys: &[String]) -> StorageResult<Vec<String>> {
+        self.guard(self.inner.delete_many(keys)).await
+    }
+
+    async fn list(&self, prefix: &str, start_after: Option<&str>, limit: usize) -> StorageResult<Vec<ObjectSummary>> {
+        self.guard(self.inner.list(prefix, start_after, limit)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker(open_secs: u64) -> CircuitBreaker {
+        let config = CircuitBreakerConfig {
+            window_secs: 60,
+            failure_rate: 0.5,
+            min_requests: 4,
+            open_secs,
+            half_open_probes: 2,
+        };
+        CircuitBreaker::new("test", &config)
+    }
+
+    async fn outcome(breaker: &CircuitBreaker, ok: bool) -> Option<Result<(), ()>> {
+        breaker.call(async move { if ok { Ok(()) } else { Err(()) } }, |_| true).await
+    }
+
+    #[tokio::test]
+    async fn test_opens_after_failure_rate_exceeded() {
+        let b = breaker(60);
+        assert!(outcome(&b, true).await.is_some());
+        assert!(outcome(&b, false).await.is_some());
+        assert!(outcome(&b, true).await.is_some());
+        assert_eq!(b.state(), CircuitState::Closed, "Below min_requests the circuit stays closed");
+
+        assert!(outcome(&b, false).await.is_some());
+        assert_eq!(b.state(), CircuitState::Open);
+        assert!(outcome(&b, true).await.is_none(), "Open circuit must reject calls");
+    }
+
+    #[tokio::test]
+    async fn test_half_open_probes_close_circuit() {
+        let b = breaker(0);
+        for _ in 0..4 {
+            let _ = outcome(&b, false).await;
+        }
+        assert_eq!(b.state(), CircuitState::Open);
+
+        assert!(outcome(&b, true).await.is_some());
+        assert_eq!(b.state(), CircuitState::HalfOpen);
+        assert!(outcome(&b, true).await.is_some());
+        assert_eq!(b.state(), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_half_open_failure_reopens() {
+        let b = breaker(0);
+        for _ in 0..4 {
+            let _ = outcome(&b, false).await;
+        }
+        assert!(outcome(&b, false).await.is_some());
+        assert_eq!(b.state(), CircuitState::Open);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_probe_frees_its_slot() {
+        let b = breaker(0);
+        for _ in 0..4 {
+            let _ = outcome(&b, false).await;
+        }
+        assert_eq!(b.state(), CircuitState::Open);
+
+        // The probe's caller gives up before the dependency answers
+        let probe = b.call(std::future::pending::<Result<(), ()>>(), |_| true);
+        assert!(tokio::time::timeout(Duration::from_millis(10), probe).await.is_err());
+        assert_eq!(b.state(), CircuitState::HalfOpen);
+
+        // Both probe slots are available again, so the circuit can still close
+        assert!(outcome(&b, true).await.is_some());
+        assert!(outcome(&b, true).await.is_some(), "Cancelled probe must not hold a slot");
+        assert_eq!(b.state(), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_ignored_errors_do_not_trip() {
+        let b = breaker(60);
+        for _ in 0..10 {
+            let res = b.call(async { Err::<(), _>("not found") }, |_| false).await;
+            assert!(res.is_some());
+        }
+        assert_eq!(b.state(), CircuitState::Closed);
+    }
+}
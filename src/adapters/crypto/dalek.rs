@@ -0,0 +1,25 @@
+use crate::adapters::crypto::{SignatureVerifier, xeddsa_canonical_signature, xeddsa_edwards_key};
+use ed25519_dalek::Verifier;
+
+/// The default backend, on the pure-Rust `curve25519-dalek` and `ed25519-dalek` crates.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DalekVerifier;
+
+impl SignatureVerifier for DalekVerifier {
+    fn verify_xeddsa(&self, public_key: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> bool {
+        // We must clear the 255th bit of 's' for standard Ed25519 libraries.
+        // XEdDSA uses this bit to represent the sign of the recovered point.
+        let signature = ed25519_dalek::Signature::from_bytes(&xeddsa_canonical_signature(signature));
+
+        [0, 1].into_iter().any(|sign_bit| {
+            xeddsa_edwards_key(public_key, sign_bit)
+                .and_then(|key| ed25519_dalek::VerifyingKey::from_bytes(&key).ok())
+                .is_some_and(|key| key.verify(message, &signature).is_ok())
+        })
+    }
+
+    fn verify_ed25519(&self, public_key: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> bool {
+        ed25519_dalek::VerifyingKey::from_bytes(public_key)
+            .is_ok_and(|key| key.verify_strict(message, &ed25519_dalek::Signature::from_bytes(signature)).is_ok())
+    }
+}
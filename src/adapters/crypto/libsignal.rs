@@ -0,0 +1,28 @@
+use crate::adapters::crypto::{DalekVerifier, SignatureVerifier};
+use crate::domain::crypto::DJB_KEY_PREFIX;
+
+/// Backend on `libsignal-protocol`, so that `XEdDSA` keys are checked by the same code as in
+/// Signal clients.
+///
+/// libsignal takes the sign of the Edwards key from the signature alone, so a signature that fails
+/// is checked once more with its sign bit flipped, accepting clients that picked the other point
+/// as the other backends do. libsignal has no plain Ed25519 keys; those are checked by
+/// [`DalekVerifier`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LibsignalVerifier;
+
+impl SignatureVerifier for LibsignalVerifier {
+    fn verify_xeddsa(&self, public_key: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> bool {
+        let mut serialized = [0u8; 33];
+        serialized[0] = DJB_KEY_PREFIX;
+        serialized[1..].copy_from_slice(public_key);
+        let mut other_point = *signature;
+        other_point[63] ^= 0x80;
+        libsignal_protocol::PublicKey::deserialize(&serialized)
+            .is_ok_and(|key| key.verify_signature(message, signature) || key.verify_signature(message, &other_point))
+    }
+
+    fn verify_ed25519(&self, public_key: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> bool {
+        DalekVerifier.verify_ed25519(public_key, message, signature)
+    }
+}
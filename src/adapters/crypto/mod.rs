@@ -0,0 +1,235 @@
+use crate::config::CryptoBackend;
+use std::sync::Arc;
+
+pub mod dalek;
+#[cfg(feature = "crypto-libsignal")]
+pub mod libsignal;
+pub mod mac;
+#[cfg(any(feature = "crypto-ring", feature = "crypto-fips"))]
+pub mod ring;
+
+pub use dalek::DalekVerifier;
+#[cfg(feature = "crypto-libsignal")]
+pub use libsignal::LibsignalVerifier;
+pub use mac::{constant_time_eq, hmac_sha256};
+#[cfg(any(feature = "crypto-ring", feature = "crypto-fips"))]
+pub use ring::RingVerifier;
+
+/// Checks signatures over uploaded key material. Backends differ in the library doing the curve
+/// arithmetic, not in which signatures they accept; the test vectors below hold them to that.
+pub trait SignatureVerifier: Send + Sync + std::fmt::Debug {
+    /// Verifies an `XEdDSA` signature against a Curve25519 (Montgomery) public key.
+    fn verify_xeddsa(&self, public_key: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> bool;
+
+    /// Verifies an Ed25519 signature, rejecting weak keys and malleable signatures.
+    fn verify_ed25519(&self, public_key: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> bool;
+}
+
+/// Builds the verifier for the configured backend.
+///
+/// # Errors
+/// Returns an error if the backend was not compiled in.
+pub fn verifier(backend: CryptoBackend) -> anyhow::Result<Arc<dyn SignatureVerifier>> {
+    match backend {
+        CryptoBackend::Dalek => Ok(Arc::new(DalekVerifier)),
+        #[cfg(any(feature = "crypto-ring", feature = "crypto-fips"))]
+        CryptoBackend::Ring => Ok(Arc::new(RingVerifier)),
+        #[cfg(feature = "crypto-libsignal")]
+        CryptoBackend::Libsignal => Ok(Arc::new(LibsignalVerifier)),
+        #[allow(unreachable_patterns)]
+        other => anyhow::bail!("The {other} crypto backend is not compiled in; rebuild with its crypto-* feature"),
+    }
+}
+
+/// The Ed25519 public key an `XEdDSA` key stands for, with the given sign bit.
+///
+/// A Montgomery u-coordinate corresponds to two Edwards points. Signers are meant to put the sign
+/// in the top bit of the signature, but some client environments (like JS polyfills) choose the
+/// other point, so backends that follow this service's historical behaviour try both.
+pub(crate) fn xeddsa_edwards_key(public_key: &[u8; 32], sign_bit: u8) -> Option<[u8; 32]> {
+    use xeddsa::ConvertMont;
+    xeddsa::xed25519::PublicKey(*public_key).convert_mont(sign_bit).ok()
+}
+
+/// The signature with the `XEdDSA` sign bit cleared, as a standard Ed25519 signature.
+pub(crate) const fn xeddsa_canonical_signature(signature: &[u8; 64]) -> [u8; 64] {
+    let mut canonical = *signature;
+    canonical[63] &= 0x7F;
+    canonical
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use curve25519_dalek::edwards::CompressedEdwardsY;
+    use ed25519_dalek::Signer;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use xeddsa::xed25519::PrivateKey;
+    use xeddsa::{CalculateKeyPair, Sign};
+
+    #[derive(Debug)]
+    enum Scheme {
+        XEdDsa,
+        Ed25519,
+    }
+
+    struct Vector {
+        name: &'static str,
+        scheme: Scheme,
+        public_key: [u8; 32],
+        message: Vec<u8>,
+        signature: [u8; 64],
+        valid: bool,
+    }
+
+    /// Signatures from fixed seeds, each with the outcome every backend must agree on.
+    fn vectors() -> Vec<Vector> {
+        let mut rng = StdRng::seed_from_u64(0x0b5c_u64);
+        let message = [0x42u8; 33].to_vec();
+        let mut vectors = Vec::new();
+
+        let xed = PrivateKey([7u8; 32]);
+        let (_, ed_pub) = xed.calculate_key_pair(0);
+        let mont_pub = CompressedEdwardsY(ed_pub).decompress().expect("valid Edwards key").to_montgomery().to_bytes();
+        let xed_sig: [u8; 64] = xed.sign(&message, &mut rng);
+        vectors.push(Vector {
+            name: "xeddsa valid",
+            scheme: Scheme::XEdDsa,
+            public_key: mont_pub,
+            message: message.clone(),
+            signature: xed_sig,
+            valid: true,
+        });
+        vectors.push(Vector {
+            name: "xeddsa wrong message",
+            scheme: Scheme::XEdDsa,
+            public_key: mont_pub,
+            message: [0x43u8; 33].to_vec(),
+            signature: xed_sig,
+            valid: false,
+        });
+        let mut tampered = xed_sig;
+        tampered[0] ^= 1;
+        vectors.push(Vector {
+            name: "xeddsa tampered signature",
+            scheme: Scheme::XEdDsa,
+            public_key: mont_pub,
+            message: message.clone(),
+            signature: tampered,
+            valid: false,
+        });
+
+        // A key whose Edwards point has the sign bit set, as signed by clients that sign with their
+        // Ed25519 key directly. Valid with the sign bit in the signature set or, as from older
+        // clients, left clear.
+        let other_point = (0u8..)
+            .map(|seed| ed25519_dalek::SigningKey::from_bytes(&[seed; 32]))
+            .find(|key| key.verifying_key().to_bytes()[31] & 0x80 != 0)
+            .expect("a key on the negative point");
+        let other_mont_pub = CompressedEdwardsY(other_point.verifying_key().to_bytes())
+            .decompress()
+            .expect("valid Edwards key")
+            .to_montgomery()
+            .to_bytes();
+        let unflagged_sig = other_point.sign(&message).to_bytes();
+        let mut flagged_sig = unflagged_sig;
+        flagged_sig[63] |= 0x80;
+        vectors.push(Vector {
+            name: "xeddsa other point, sign bit set",
+            scheme: Scheme::XEdDsa,
+            public_key: other_mont_pub,
+            message: message.clone(),
+            signature: flagged_sig,
+            valid: true,
+        });
+        vectors.push(Vector {
+            name: "xeddsa other point, sign bit clear",
+            scheme: Scheme::XEdDsa,
+            public_key: other_mont_pub,
+            message: message.clone(),
+            signature: unflagged_sig,
+            valid: true,
+        });
+        let mut tampered = flagged_sig;
+        tampered[0] ^= 1;
+        vectors.push(Vector {
+            name: "xeddsa other point, tampered signature",
+            scheme: Scheme::XEdDsa,
+            public_key: other_mont_pub,
+            message: message.clone(),
+            signature: tampered,
+            valid: false,
+        });
+
+        let signing_key = ed25519_dalek::SigningKey::from_bytes(&[9u8; 32]);
+        let ed_sig = signing_key.sign(&message).to_bytes();
+        let ed_pub = signing_key.verifying_key().to_bytes();
+        vectors.push(Vector {
+            name: "ed25519 valid",
+            scheme: Scheme::Ed25519,
+            public_key: ed_pub,
+            message: message.clone(),
+            signature: ed_sig,
+            valid: true,
+        });
+        vectors.push(Vector {
+            name: "ed25519 wrong message",
+            scheme: Scheme::Ed25519,
+            public_key: ed_pub,
+            message: [0x43u8; 33].to_vec(),
+            signature: ed_sig,
+            valid: false,
+        });
+        vectors.push(Vector {
+            name: "ed25519 signature checked as xeddsa",
+            scheme: Scheme::XEdDsa,
+            public_key: ed_pub,
+            message: message.clone(),
+            signature: ed_sig,
+            valid: false,
+        });
+        // The identity point has small order, so a strict verifier refuses it as a key.
+        let mut identity = [0u8; 32];
+        identity[0] = 1;
+        vectors.push(Vector {
+            name: "ed25519 small-order key",
+            scheme: Scheme::Ed25519,
+            public_key: identity,
+            message,
+            signature: [0u8; 64],
+            valid: false,
+        });
+
+        vectors
+    }
+
+    fn backends() -> Vec<Arc<dyn SignatureVerifier>> {
+        let mut backends: Vec<Arc<dyn SignatureVerifier>> = vec![Arc::new(DalekVerifier)];
+        #[cfg(any(feature = "crypto-ring", feature = "crypto-fips"))]
+        backends.push(Arc::new(RingVerifier));
+        #[cfg(feature = "crypto-libsignal")]
+        backends.push(Arc::new(LibsignalVerifier));
+        backends
+    }
+
+    #[test]
+    fn test_backends_agree_on_vectors() {
+        for backend in backends() {
+            for vector in vectors() {
+                let valid = match vector.scheme {
+                    Scheme::XEdDsa => backend.verify_xeddsa(&vector.public_key, &vector.message, &vector.signature),
+                    Scheme::Ed25519 => backend.verify_ed25519(&vector.public_key, &vector.message, &vector.signature),
+                };
+                assert_eq!(valid, vector.valid, "{backend:?} disagrees on {}", vector.name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_uncompiled_backend_is_rejected() {
+        assert!(verifier(CryptoBackend::Dalek).is_ok());
+        #[cfg(not(feature = "crypto-libsignal"))]
+        assert!(verifier(CryptoBackend::Libsignal).is_err());
+    }
+}
@@ -0,0 +1,39 @@
+use crate::adapters::crypto::{SignatureVerifier, xeddsa_canonical_signature, xeddsa_edwards_key};
+#[cfg(feature = "crypto-fips")]
+use aws_lc_rs::signature::{ED25519, UnparsedPublicKey};
+use curve25519_dalek::edwards::CompressedEdwardsY;
+#[cfg(not(feature = "crypto-fips"))]
+use ring::signature::{ED25519, UnparsedPublicKey};
+
+/// Backend on `ring`, or on `aws-lc-rs` in its FIPS-validated mode when built with `crypto-fips`.
+///
+/// Only the signature check runs in the library; recovering the Edwards key of an `XEdDSA` key
+/// and the small-order checks of strict verification, which neither library offers, stay on
+/// `curve25519-dalek`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RingVerifier;
+
+impl SignatureVerifier for RingVerifier {
+    fn verify_xeddsa(&self, public_key: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> bool {
+        let signature = xeddsa_canonical_signature(signature);
+        [0, 1].into_iter().any(|sign_bit| {
+            xeddsa_edwards_key(public_key, sign_bit)
+                .is_some_and(|key| UnparsedPublicKey::new(&ED25519, key).verify(message, &signature).is_ok())
+        })
+    }
+
+    fn verify_ed25519(&self, public_key: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> bool {
+        !is_weak_point(public_key)
+            && !is_weak_point(&signature[..32])
+            && UnparsedPublicKey::new(&ED25519, public_key).verify(message, signature).is_ok()
+    }
+}
+
+/// Whether an Edwards point encoding is invalid or of small order, which strict Ed25519
+/// verification rejects for both the key and the `R` half of a signature.
+fn is_weak_point(bytes: &[u8]) -> bool {
+    <[u8; 32]>::try_from(bytes)
+        .ok()
+        .and_then(|bytes| CompressedEdwardsY(bytes).decompress())
+        .is_none_or(|point| point.is_small_order())
+}
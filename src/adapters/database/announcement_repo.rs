@@ -0,0 +1,164 @@
+use crate::domain::message::SystemCode;
+use crate::error::Result;
+use crate::telemetry;
+use sqlx::PgConnection;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+#[derive(Clone, Debug, Default)]
+pub struct AnnouncementRepository {}
+
+impl AnnouncementRepository {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {}
+    }
+
+    /// Stores a signed announcement.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the insert fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(?id, ?created_at, ?expires_at), err)]
+    pub(crate) async fn create(
+        &self,
+        conn: &mut PgConnection,
+        id: Uuid,
+        payload: &[u8],
+        created_at: OffsetDateTime,
+        expires_at: OffsetDateTime,
+    ) -> Result<()> {
+        sqlx::query("INSERT INTO announcements (id, payload, created_at, expires_at) VALUES ($1, $2, $3, $4)")
+            .bind(id)
+            .bind(payload)
+            .bind(created_at)
+            .bind(expires_at)
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Records receipts for the owner of a device and returns the payloads of every live,
+    /// not yet queued announcement that user had not received, oldest first.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id)),
+        err
+    )]
+    pub(crate) async fn claim_for_device(&self, conn: &mut PgConnection, device_id: Uuid) -> Result<Vec<Vec<u8>>> {
+        let rows: Vec<(Vec<u8>,)> = sqlx::query_as(
+            r#"
+            WITH claimed AS (
+                INSERT INTO announcement_receipts (announcement_id, user_id)
+                SELECT a.id, d.user_id
+                FROM announcements a
+                JOIN devices d ON d.id = $1
+                WHERE a.queued_at IS NULL AND a.expires_at > NOW()
+                ON CONFLICT DO NOTHING
+                RETURNING announcement_id
+            )
+            SELECT a.payload
+            FROM announcements a
+            JOIN claimed c ON c.announcement_id = a.id
+            ORDER BY a.created_at, a.id
+            "#,
+        )
+        .bind(device_id)
+        .fetch_all(conn)
+        .await?;
+
+        Ok(rows.into_iter().map(|(payload,)| payload).collect())
+    }
+
+    /// Marks up to `limit` announcements created before `created_before` as queued and returns them.
+    /// Rows locked by another instance are skipped.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(?created_before, %limit), err)]
+    pub(crate) async fn take_unqueued(
+        &self,
+        conn: &mut PgConnection,
+        created_before: OffsetDateTime,
+        limit: i64,
+    ) -> Result<Vec<(Uuid, Vec<u8>, OffsetDateTime)>> {
+        let rows = sqlx::query_as(
+            r#"
+            UPDATE announcements SET queued_at = NOW()
+            WHERE id IN (
+                SELECT id FROM announcements
+                WHERE queued_at IS NULL AND created_at <= $1 AND expires_at > NOW()
+                ORDER BY created_at
+                LIMIT $2
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, payload, expires_at
+            "#,
+        )
+        .bind(created_before)
+        .bind(limit)
+        .fetch_all(conn)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Records receipts for every user that has not received the announcement and queues it
+    /// as a system envelope for each of their devices, at the end of each device's inbox sequence.
+    /// Returns the number of envelopes queued.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(?announcement_id, ?expires_at), err)]
+    pub(crate) async fn queue_for_remaining_users(
+        &self,
+        conn: &mut PgConnection,
+        announcement_id: Uuid,
+        payload: &[u8],
+        expires_at: OffsetDateTime,
+    ) -> Result<u64> {
+        let result = sqlx::query(
+            r#"
+            WITH claimed AS (
+                INSERT INTO announcement_receipts (announcement_id, user_id)
+                SELECT $1, id FROM users
+                ON CONFLICT DO NOTHING
+                RETURNING user_id
+            ),
+            sequences AS (
+                INSERT INTO inbox_sequences (device_id, last_seq)
+                SELECT d.id, 1
+                FROM devices d
+                JOIN claimed c ON c.user_id = d.user_id
+                ORDER BY d.id
+                ON CONFLICT (device_id) DO UPDATE SET last_seq = inbox_sequences.last_seq + 1
+                RETURNING device_id, last_seq
+            )
+            INSERT INTO messages (device_id, submission_id, content, expires_at, seq, system_code)
+            SELECT device_id, $1, $2, $3, last_seq, $4
+            FROM sequences
+            "#,
+        )
+        .bind(announcement_id)
+        .bind(payload)
+        .bind(expires_at)
+        .bind(SystemCode::Announcement as i16)
+        .execute(conn)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Deletes expired announcements along with their receipts.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, err)]
+    pub(crate) async fn delete_expired(&self, conn: &mut PgConnection) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM announcements WHERE expires_at < NOW()").execute(conn).await?;
+        Ok(result.rows_affected())
+    }
+}
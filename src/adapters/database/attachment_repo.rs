@@ -1,8 +1,9 @@
 use crate::adapters::database::records::AttachmentRecord;
 use crate::domain::attachment::Attachment;
 use crate::error::Result;
+use crate::telemetry;
 use sqlx::PgConnection;
-use time::OffsetDateTime;
+use time::{Duration, OffsetDateTime};
 use uuid::Uuid;
 
 #[derive(Clone, Debug, Default)]
@@ -14,57 +15,363 @@ impl AttachmentRepository {
         Self {}
     }
 
-    /// Records a new attachment in the database.
+    /// Records a new attachment of `size_bytes` uploaded by `owner_id` from `device_id` in the database. Content-addressed
+    /// uploads are recorded with [`Self::create_pending`] before their object is written instead.
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the insert fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
-    pub(crate) async fn create(&self, conn: &mut PgConnection, id: Uuid, expires_at: OffsetDateTime) -> Result<()> {
-        sqlx::query("INSERT INTO attachments (id, expires_at) VALUES ($1, $2)")
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(user.id = %telemetry::id(owner_id), %size_bytes, ?expires_at),
+        err
+    )]
+    pub(crate) async fn create(
+        &self,
+        conn: &mut PgConnection,
+        id: Uuid,
+        owner_id: Uuid,
+        device_id: Option<Uuid>,
+        size_bytes: i64,
+        expires_at: OffsetDateTime,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO attachments (id, owner_id, device_id, size_bytes, expires_at) VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(id)
+        .bind(owner_id)
+        .bind(device_id)
+        .bind(size_bytes)
+        .bind(expires_at)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Takes transaction-scoped locks on `content_digests`, in a fixed order so concurrent callers
+    /// cannot deadlock. Every writer of a content-addressed object or of the rows referencing it
+    /// holds the lock, so an object is never deleted while an upload or link to it is being recorded.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(count = content_digests.len()), err)]
+    pub(crate) async fn lock_digests(&self, conn: &mut PgConnection, content_digests: &[Vec<u8>]) -> Result<()> {
+        sqlx::query(
+            r"
+            SELECT pg_advisory_xact_lock(hashtext('attachment_content:' || encode(digest, 'hex')))
+            FROM (SELECT DISTINCT digest FROM unnest($1::bytea[]) AS digest ORDER BY digest) AS digests
+            ",
+        )
+        .bind(content_digests)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Records a pending attachment for an upload of `content_digest` that has not been written
+    /// yet, so the object is counted as referenced while the upload is in flight.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the insert fails.
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(user.id = %telemetry::id(owner_id), ?expires_at),
+        err
+    )]
+    pub(crate) async fn create_pending(
+        &self,
+        conn: &mut PgConnection,
+        id: Uuid,
+        owner_id: Uuid,
+        device_id: Option<Uuid>,
+        expires_at: OffsetDateTime,
+        content_digest: &[u8; 32],
+    ) -> Result<()> {
+        sqlx::query(
+            r"
+            INSERT INTO attachments (id, owner_id, device_id, size_bytes, expires_at, content_digest, pending)
+            VALUES ($1, $2, $3, 0, $4, $5, TRUE)
+            ",
+        )
+        .bind(id)
+        .bind(owner_id)
+        .bind(device_id)
+        .bind(expires_at)
+        .bind(content_digest.as_slice())
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Marks a pending attachment as uploaded, with its final size.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the update fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(?id, %size_bytes), err)]
+    pub(crate) async fn finish_pending(&self, conn: &mut PgConnection, id: Uuid, size_bytes: i64) -> Result<()> {
+        sqlx::query("UPDATE attachments SET pending = FALSE, size_bytes = $2 WHERE id = $1 AND pending")
             .bind(id)
-            .bind(expires_at)
+            .bind(size_bytes)
             .execute(conn)
             .await?;
         Ok(())
     }
 
+    /// Deletes a pending attachment whose upload did not complete.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the deletion fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(?id), err)]
+    pub(crate) async fn delete_pending(&self, conn: &mut PgConnection, id: Uuid) -> Result<()> {
+        sqlx::query("DELETE FROM attachments WHERE id = $1 AND pending").bind(id).execute(conn).await?;
+        Ok(())
+    }
+
+    /// Records a new attachment for `owner_id` pointing at the existing object with `content_digest`,
+    /// taking its size from the attachments that already reference it.
+    ///
+    /// Only links to objects that a live, fully uploaded attachment still references, since the
+    /// cleanup worker may be about to delete an object whose rows have all expired, and a pending
+    /// upload may still fail. Returns `false` if there is no such object.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the insert fails.
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(user.id = %telemetry::id(owner_id), ?expires_at),
+        err
+    )]
+    pub(crate) async fn create_for_digest(
+        &self,
+        conn: &mut PgConnection,
+        id: Uuid,
+        owner_id: Uuid,
+        device_id: Option<Uuid>,
+        expires_at: OffsetDateTime,
+        content_digest: &[u8; 32],
+    ) -> Result<bool> {
+        let result = sqlx::query(
+            r"
+            INSERT INTO attachments (id, owner_id, device_id, size_bytes, expires_at, content_digest)
+            SELECT $1, $2, $3, MAX(size_bytes), $4, $5
+            FROM attachments
+            WHERE content_digest = $5 AND expires_at > NOW() AND NOT pending
+            HAVING COUNT(*) > 0
+            ",
+        )
+        .bind(id)
+        .bind(owner_id)
+        .bind(device_id)
+        .bind(expires_at)
+        .bind(content_digest.as_slice())
+        .execute(conn)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Returns those of `content_digests` that are still referenced by attachments outside `ids`,
+    /// pending uploads included. With no `ids`, returns the digests referenced by any attachment.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, err)]
+    pub(crate) async fn find_shared_digests(
+        &self,
+        conn: &mut PgConnection,
+        content_digests: &[Vec<u8>],
+        ids: &[Uuid],
+    ) -> Result<Vec<Vec<u8>>> {
+        let shared = sqlx::query_scalar(
+            "SELECT DISTINCT content_digest FROM attachments WHERE content_digest = ANY($1) AND NOT (id = ANY($2))",
+        )
+        .bind(content_digests)
+        .bind(ids)
+        .fetch_all(conn)
+        .await?;
+        Ok(shared)
+    }
+
+    /// Sums the sizes of the live attachments uploaded by `owner_id`.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(user.id = %telemetry::id(owner_id)), err)]
+    pub(crate) async fn total_size_for_owner(&self, conn: &mut PgConnection, owner_id: Uuid) -> Result<i64> {
+        let total: i64 = sqlx::query_scalar(
+            "SELECT COALESCE(SUM(size_bytes), 0)::BIGINT FROM attachments WHERE owner_id = $1 AND expires_at > NOW()",
+        )
+        .bind(owner_id)
+        .fetch_one(conn)
+        .await?;
+        Ok(total)
+    }
+
+    /// Counts the live, fully uploaded attachments of each of `device_ids`. Devices without any
+    /// are left out.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(count = device_ids.len()), err)]
+    pub(crate) async fn count_by_device(
+        &self,
+        conn: &mut PgConnection,
+        device_ids: &[Uuid],
+    ) -> Result<Vec<(Uuid, i64)>> {
+        let counts = sqlx::query_as(
+            r"
+            SELECT device_id, COUNT(*)
+            FROM attachments
+            WHERE device_id = ANY($1) AND expires_at > NOW() AND NOT pending
+            GROUP BY device_id
+            ",
+        )
+        .bind(device_ids)
+        .fetch_all(conn)
+        .await?;
+        Ok(counts)
+    }
+
     /// Finds an attachment by its ID.
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the query fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
+    #[tracing::instrument(level = "debug", skip_all, fields(?id), err)]
     pub(crate) async fn find_by_id(&self, conn: &mut PgConnection, id: Uuid) -> Result<Option<Attachment>> {
-        let record = sqlx::query_as::<_, AttachmentRecord>("SELECT id, expires_at FROM attachments WHERE id = $1")
-            .bind(id)
-            .fetch_optional(conn)
-            .await?;
+        let record = sqlx::query_as::<_, AttachmentRecord>(
+            "SELECT id, expires_at, content_digest FROM attachments WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(conn)
+        .await?;
 
         Ok(record.map(Into::into))
     }
 
-    /// Deletes an attachment record.
+    /// Moves the expiry of a live attachment to `requested`, never earlier than its current expiry
+    /// and never past `max_lifetime` after its creation. Returns the resulting expiry, or `None`
+    /// if the attachment does not exist or has already expired.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the update fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(?id, ?requested, ?max_lifetime), err)]
+    pub(crate) async fn extend(
+        &self,
+        conn: &mut PgConnection,
+        id: Uuid,
+        requested: OffsetDateTime,
+        max_lifetime: Duration,
+    ) -> Result<Option<OffsetDateTime>> {
+        let expires_at = sqlx::query_scalar(
+            r"
+            UPDATE attachments
+            SET expires_at = GREATEST(expires_at, LEAST($2, created_at + make_interval(secs => $3)))
+            WHERE id = $1 AND expires_at > NOW()
+            RETURNING expires_at
+            ",
+        )
+        .bind(id)
+        .bind(requested)
+        .bind(max_lifetime.as_seconds_f64())
+        .fetch_optional(conn)
+        .await?;
+        Ok(expires_at)
+    }
+
+    /// Deletes a batch of attachment records, returning how many were removed.
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the deletion fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
-    pub(crate) async fn delete(&self, conn: &mut PgConnection, id: Uuid) -> Result<()> {
-        sqlx::query("DELETE FROM attachments WHERE id = $1").bind(id).execute(conn).await?;
-        Ok(())
+    #[tracing::instrument(level = "debug", skip_all, fields(count = ids.len()), err)]
+    pub(crate) async fn delete_many(&self, conn: &mut PgConnection, ids: &[Uuid]) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM attachments WHERE id = ANY($1)").bind(ids).execute(conn).await?;
+        Ok(result.rows_affected())
     }
 
-    /// Fetches a batch of expired attachment IDs.
+    /// Deletes those of `ids` that are still expired, returning how many were removed.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the deletion fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(count = ids.len()), err)]
+    pub(crate) async fn delete_expired(&self, conn: &mut PgConnection, ids: &[Uuid]) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM attachments WHERE id = ANY($1) AND expires_at < NOW()")
+            .bind(ids)
+            .execute(conn)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Fetches a page of expired attachments ordered by ID, starting after `after`.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(?after, %limit), err)]
+    pub(crate) async fn fetch_expired(
+        &self,
+        conn: &mut PgConnection,
+        after: Option<Uuid>,
+        limit: i64,
+    ) -> Result<Vec<Attachment>> {
+        let rows = sqlx::query_as::<_, AttachmentRecord>(
+            r"
+            SELECT id, expires_at, content_digest FROM attachments
+            WHERE expires_at < NOW() AND ($1::uuid IS NULL OR id > $1)
+            ORDER BY id
+            LIMIT $2
+            ",
+        )
+        .bind(after)
+        .bind(limit)
+        .fetch_all(conn)
+        .await?;
+
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    /// Fetches live, fully uploaded attachments in ID order starting at `from`, wrapping around to
+    /// the lowest IDs.
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the query fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
-    pub(crate) async fn fetch_expired(&self, conn: &mut PgConnection, limit: i64) -> Result<Vec<Uuid>> {
+    #[tracing::instrument(level = "debug", skip_all, fields(?from, %limit), err)]
+    pub(crate) async fn sample_live(&self, conn: &mut PgConnection, from: Uuid, limit: i64) -> Result<Vec<Attachment>> {
         let rows = sqlx::query_as::<_, AttachmentRecord>(
-            "SELECT id, expires_at FROM attachments WHERE expires_at < NOW() LIMIT $1",
+            r"
+            (SELECT id, expires_at, content_digest FROM attachments
+             WHERE expires_at > NOW() AND NOT pending AND id >= $1 ORDER BY id LIMIT $2)
+            UNION ALL
+            (SELECT id, expires_at, content_digest FROM attachments
+             WHERE expires_at > NOW() AND NOT pending AND id < $1 ORDER BY id LIMIT $2)
+            LIMIT $2
+            ",
         )
+        .bind(from)
         .bind(limit)
         .fetch_all(conn)
         .await?;
 
-        Ok(rows.into_iter().map(|r| r.id).collect())
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    /// Returns the lowest attachment ID, if any attachment exists.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, err)]
+    pub(crate) async fn lowest_id(&self, conn: &mut PgConnection) -> Result<Option<Uuid>> {
+        let id = sqlx::query_scalar("SELECT id FROM attachments ORDER BY id LIMIT 1").fetch_optional(conn).await?;
+        Ok(id)
+    }
+
+    /// Returns those of `ids` that have an attachment record.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, err)]
+    pub(crate) async fn find_existing_ids(&self, conn: &mut PgConnection, ids: &[Uuid]) -> Result<Vec<Uuid>> {
+        let existing =
+            sqlx::query_scalar("SELECT id FROM attachments WHERE id = ANY($1)").bind(ids).fetch_all(conn).await?;
+        Ok(existing)
     }
 }
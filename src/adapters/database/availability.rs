@@ -0,0 +1,65 @@
+use crate::adapters::database::DbPool;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether the primary database currently takes writes, shared between the health service,
+/// which decides it, and the handlers and services that have to honour it.
+///
+/// While read-only, reads that can tolerate replication lag go to the replica if one is
+/// configured. Clones share the same state.
+#[derive(Clone, Debug, Default)]
+pub struct DbAvailability {
+    read_only: Arc<AtomicBool>,
+    replica: Option<DbPool>,
+}
+
+impl DbAvailability {
+    #[must_use]
+    pub fn new(replica: Option<DbPool>) -> Self {
+        Self { read_only: Arc::new(AtomicBool::new(false)), replica }
+    }
+
+    /// Whether writes are currently rejected.
+    #[must_use]
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed)
+    }
+
+    /// Enters or leaves read-only mode, as decided by the health service's write probe.
+    /// Returns whether this changed the mode.
+    pub fn set_read_only(&self, read_only: bool) -> bool {
+        self.read_only.swap(read_only, Ordering::Relaxed) != read_only
+    }
+
+    /// The pool to read from: the replica while read-only, if there is one, and `primary`
+    /// otherwise.
+    #[must_use]
+    pub fn read_pool<'a>(&'a self, primary: &'a DbPool) -> &'a DbPool {
+        match &self.replica {
+            Some(replica) if self.is_read_only() => replica,
+            _ => primary,
+        }
+    }
+
+    #[must_use]
+    pub const fn replica(&self) -> Option<&DbPool> {
+        self.replica.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clones_share_the_mode() {
+        let availability = DbAvailability::default();
+        let handle = availability.clone();
+
+        assert!(handle.set_read_only(true));
+        assert!(availability.is_read_only());
+        assert!(!handle.set_read_only(true));
+        assert!(availability.set_read_only(false));
+        assert!(!handle.is_read_only());
+    }
+}
@@ -1,6 +1,7 @@
 use crate::adapters::database::records::BackupRecord;
 use crate::domain::backup::Backup;
 use crate::error::Result;
+use crate::telemetry;
 use sqlx::PgConnection;
 use time::OffsetDateTime;
 use uuid::Uuid;
@@ -18,7 +19,12 @@ impl BackupRepository {
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the query fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id)),
+        err
+    )]
     pub(crate) async fn find_by_device_id(&self, conn: &mut PgConnection, device_id: Uuid) -> Result<Option<Backup>> {
         let record = sqlx::query_as::<_, BackupRecord>("SELECT * FROM backups WHERE device_id = $1")
             .bind(device_id)
@@ -32,7 +38,12 @@ impl BackupRepository {
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the query fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id)),
+        err
+    )]
     pub(crate) async fn create_if_not_exists(&self, conn: &mut PgConnection, device_id: Uuid) -> Result<Backup> {
         let record = sqlx::query_as::<_, BackupRecord>(
             r#"
@@ -54,7 +65,12 @@ impl BackupRepository {
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the query fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id), %expected_version),
+        err
+    )]
     pub(crate) async fn reserve_active_slot(
         &self,
         conn: &mut PgConnection,
@@ -66,7 +82,7 @@ impl BackupRepository {
             UPDATE backups
             SET 
                 state = 'UPLOADING',
-                pending_version = current_version + 1,
+                pending_version = GREATEST(current_version, COALESCE(previous_version, 0)) + 1,
                 pending_at = NOW()
             WHERE device_id = $1 AND current_version = $2 AND state = 'ACTIVE'
             RETURNING *
@@ -84,14 +100,19 @@ impl BackupRepository {
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the query fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id)),
+        err
+    )]
     pub(crate) async fn reserve_slot_force(&self, conn: &mut PgConnection, device_id: Uuid) -> Result<Backup> {
         let record = sqlx::query_as::<_, BackupRecord>(
             r#"
             UPDATE backups
             SET 
                 state = 'UPLOADING',
-                pending_version = current_version + 1,
+                pending_version = GREATEST(current_version, COALESCE(previous_version, 0)) + 1,
                 pending_at = NOW()
             WHERE device_id = $1
             RETURNING *
@@ -103,22 +124,33 @@ impl BackupRepository {
         Ok(record.into())
     }
 
-    /// Commits the pending version.
+    /// Commits the pending version of `size_bytes`, soft-deleting the version it replaces.
+    /// Returns `false` if the upload slot was taken over in the meantime.
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the query fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id), %pending_version, %size_bytes),
+        err
+    )]
     pub(crate) async fn commit_version(
         &self,
         conn: &mut PgConnection,
         device_id: Uuid,
         pending_version: i32,
-    ) -> Result<()> {
-        sqlx::query(
+        size_bytes: i64,
+    ) -> Result<bool> {
+        let result = sqlx::query(
             r#"
             UPDATE backups
             SET 
+                previous_version = NULLIF(current_version, 0),
+                previous_size_bytes = CASE WHEN current_version > 0 THEN size_bytes END,
+                deleted_at = CASE WHEN current_version > 0 THEN NOW() END,
                 current_version = $2,
+                size_bytes = $3,
                 pending_version = NULL,
                 state = 'ACTIVE',
                 updated_at = NOW(),
@@ -128,16 +160,98 @@ impl BackupRepository {
         )
         .bind(device_id)
         .bind(pending_version)
+        .bind(size_bytes)
         .execute(conn)
         .await?;
-        Ok(())
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Swaps the current version with the soft-deleted previous one, if it is still within
+    /// the restore window. `expected_version`, when given, must match the current version.
+    /// Returns the updated backup, or `None` if the conditions did not hold.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id), ?expected_version, ?window_start),
+        err
+    )]
+    pub(crate) async fn restore_previous(
+        &self,
+        conn: &mut PgConnection,
+        device_id: Uuid,
+        expected_version: Option<i32>,
+        window_start: OffsetDateTime,
+    ) -> Result<Option<Backup>> {
+        let record = sqlx::query_as::<_, BackupRecord>(
+            r#"
+            UPDATE backups
+            SET
+                current_version = previous_version,
+                previous_version = current_version,
+                size_bytes = previous_size_bytes,
+                previous_size_bytes = size_bytes,
+                deleted_at = NOW(),
+                updated_at = NOW()
+            WHERE device_id = $1
+                AND state = 'ACTIVE'
+                AND ($2::int IS NULL OR current_version = $2)
+                AND previous_version IS NOT NULL
+                AND deleted_at > $3
+            RETURNING *
+            "#,
+        )
+        .bind(device_id)
+        .bind(expected_version)
+        .bind(window_start)
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(record.map(Into::into))
+    }
+
+    /// Detaches previous versions soft-deleted before `threshold` so their objects can be removed.
+    /// Returns the `(device_id, version)` pairs that were detached.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(?threshold, %limit), err)]
+    pub(crate) async fn take_expired_previous_versions(
+        &self,
+        conn: &mut PgConnection,
+        threshold: OffsetDateTime,
+        limit: i64,
+    ) -> Result<Vec<(Uuid, i32)>> {
+        let rows = sqlx::query_as::<_, (Uuid, i32)>(
+            r#"
+            WITH expired AS (
+                SELECT device_id, previous_version FROM backups
+                WHERE previous_version IS NOT NULL AND deleted_at < $1
+                LIMIT $2
+                FOR UPDATE SKIP LOCKED
+            )
+            UPDATE backups b
+            SET previous_version = NULL, previous_size_bytes = NULL, deleted_at = NULL
+            FROM expired e
+            WHERE b.device_id = e.device_id
+            RETURNING e.device_id, e.previous_version
+            "#,
+        )
+        .bind(threshold)
+        .bind(limit)
+        .fetch_all(conn)
+        .await?;
+
+        Ok(rows)
     }
 
     /// Fetches stale uploads for cleanup.
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the query fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
+    #[tracing::instrument(level = "debug", skip_all, fields(?threshold, %limit), err)]
     pub(crate) async fn fetch_stale_uploads(
         &self,
         conn: &mut PgConnection,
@@ -159,7 +273,12 @@ impl BackupRepository {
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the query fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id)),
+        err
+    )]
     pub(crate) async fn reset_stale(&self, conn: &mut PgConnection, device_id: Uuid) -> Result<()> {
         sqlx::query(
             "UPDATE backups SET state = 'ACTIVE', pending_version = NULL, pending_at = NULL WHERE device_id = $1",
@@ -169,4 +288,87 @@ impl BackupRepository {
         .await?;
         Ok(())
     }
+
+    /// Releases the slot reserved for `pending_version`, if that upload still holds it.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id), %pending_version),
+        err
+    )]
+    pub(crate) async fn release_slot(
+        &self,
+        conn: &mut PgConnection,
+        device_id: Uuid,
+        pending_version: i32,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE backups
+            SET state = 'ACTIVE', pending_version = NULL, pending_at = NULL
+            WHERE device_id = $1 AND pending_version = $2 AND state = 'UPLOADING'
+            "#,
+        )
+        .bind(device_id)
+        .bind(pending_version)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns the lowest device ID with a backup, if any backup exists.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, err)]
+    pub(crate) async fn lowest_device_id(&self, conn: &mut PgConnection) -> Result<Option<Uuid>> {
+        let id =
+            sqlx::query_scalar("SELECT device_id FROM backups ORDER BY device_id LIMIT 1").fetch_optional(conn).await?;
+        Ok(id)
+    }
+
+    /// Fetches backups holding a committed version, in device ID order starting at `from`,
+    /// wrapping around to the lowest IDs.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(%limit), err)]
+    pub(crate) async fn sample_committed(
+        &self,
+        conn: &mut PgConnection,
+        from: Uuid,
+        limit: i64,
+    ) -> Result<Vec<Backup>> {
+        let records = sqlx::query_as::<_, BackupRecord>(
+            r"
+            (SELECT * FROM backups WHERE current_version > 0 AND device_id >= $1 ORDER BY device_id LIMIT $2)
+            UNION ALL
+            (SELECT * FROM backups WHERE current_version > 0 AND device_id < $1 ORDER BY device_id LIMIT $2)
+            LIMIT $2
+            ",
+        )
+        .bind(from)
+        .bind(limit)
+        .fetch_all(conn)
+        .await?;
+
+        Ok(records.into_iter().map(Into::into).collect())
+    }
+
+    /// Finds the backup records of several devices.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, err)]
+    pub(crate) async fn find_by_device_ids(&self, conn: &mut PgConnection, device_ids: &[Uuid]) -> Result<Vec<Backup>> {
+        let records = sqlx::query_as::<_, BackupRecord>("SELECT * FROM backups WHERE device_id = ANY($1)")
+            .bind(device_ids)
+            .fetch_all(conn)
+            .await?;
+
+        Ok(records.into_iter().map(Into::into).collect())
+    }
 }
@@ -0,0 +1,78 @@
+use crate::adapters::database::records::BlockedNetworkRecord;
+use crate::domain::blocklist::BlockedNetwork;
+use crate::error::{AppError, Result};
+use ipnetwork::IpNetwork;
+use sqlx::PgConnection;
+
+#[derive(Clone, Debug, Default)]
+pub struct BlocklistRepository {}
+
+impl BlocklistRepository {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {}
+    }
+
+    /// Lists every managed blocklist entry.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, err)]
+    pub(crate) async fn list(&self, conn: &mut PgConnection) -> Result<Vec<BlockedNetwork>> {
+        let records = sqlx::query_as::<_, BlockedNetworkRecord>(
+            "SELECT network::text AS network, reason, created_at FROM ip_blocklist ORDER BY created_at",
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok(records
+            .into_iter()
+            .filter_map(|record| {
+                BlockedNetwork::try_from(record)
+                    .map_err(|e| tracing::warn!(error = %e, "Skipping unreadable blocklist entry"))
+                    .ok()
+            })
+            .collect())
+    }
+
+    /// Adds a network to the blocklist, replacing the reason if it is already present.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(?network, ?reason), err)]
+    pub(crate) async fn upsert(
+        &self,
+        conn: &mut PgConnection,
+        network: IpNetwork,
+        reason: Option<&str>,
+    ) -> Result<BlockedNetwork> {
+        let record = sqlx::query_as::<_, BlockedNetworkRecord>(
+            r#"
+            INSERT INTO ip_blocklist (network, reason)
+            VALUES ($1::cidr, $2)
+            ON CONFLICT (network) DO UPDATE SET reason = EXCLUDED.reason
+            RETURNING network::text AS network, reason, created_at
+            "#,
+        )
+        .bind(network.to_string())
+        .bind(reason)
+        .fetch_one(conn)
+        .await?;
+
+        BlockedNetwork::try_from(record).map_err(AppError::InternalMsg)
+    }
+
+    /// Removes a network from the blocklist. Returns `true` if an entry was deleted.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(?network), err)]
+    pub(crate) async fn delete(&self, conn: &mut PgConnection, network: IpNetwork) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM ip_blocklist WHERE network = $1::cidr")
+            .bind(network.to_string())
+            .execute(conn)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -1,6 +1,7 @@
 use crate::adapters::database::records::DeviceRecord;
 use crate::domain::device::Device;
 use crate::error::Result;
+use crate::telemetry;
 use sqlx::PgConnection;
 use uuid::Uuid;
 
@@ -17,7 +18,7 @@ impl DeviceRepository {
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the insert fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
+    #[tracing::instrument(level = "debug", skip_all, fields(user.id = %telemetry::id(user_id), ?name), err)]
     pub(crate) async fn create(&self, conn: &mut PgConnection, user_id: Uuid, name: Option<&str>) -> Result<Device> {
         let record = sqlx::query_as::<_, DeviceRecord>(
             r#"
@@ -38,7 +39,7 @@ impl DeviceRepository {
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the query fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
+    #[tracing::instrument(level = "debug", skip_all, fields(user.id = %telemetry::id(user_id)), err)]
     pub(crate) async fn find_by_user(&self, conn: &mut PgConnection, user_id: Uuid) -> Result<Vec<Device>> {
         let records = sqlx::query_as::<_, DeviceRecord>(
             "SELECT id, user_id, name, created_at FROM devices WHERE user_id = $1 ORDER BY created_at ASC",
@@ -55,7 +56,12 @@ impl DeviceRepository {
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the deletion fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id), user.id = %telemetry::id(user_id)),
+        err
+    )]
     pub(crate) async fn delete(&self, conn: &mut PgConnection, device_id: Uuid, user_id: Uuid) -> Result<bool> {
         let result = sqlx::query("DELETE FROM devices WHERE id = $1 AND user_id = $2")
             .bind(device_id)
@@ -70,7 +76,12 @@ impl DeviceRepository {
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the query fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id), user.id = %telemetry::id(user_id)),
+        err
+    )]
     pub(crate) async fn find_by_id(
         &self,
         conn: &mut PgConnection,
@@ -93,7 +104,12 @@ impl DeviceRepository {
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the update fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id), user.id = %telemetry::id(user_id), ?name),
+        err
+    )]
     pub(crate) async fn update_name(
         &self,
         conn: &mut PgConnection,
@@ -121,7 +137,12 @@ impl DeviceRepository {
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the query fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id), user.id = %telemetry::id(user_id)),
+        err
+    )]
     pub(crate) async fn belongs_to_user(
         &self,
         conn: &mut PgConnection,
@@ -141,7 +162,7 @@ impl DeviceRepository {
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the query fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
+    #[tracing::instrument(level = "debug", skip_all, fields(user.id = %telemetry::id(user_id)), err)]
     pub(crate) async fn count_by_user(&self, conn: &mut PgConnection, user_id: Uuid) -> Result<i64> {
         let count: i64 =
             sqlx::query_scalar("SELECT count(*) FROM devices WHERE user_id = $1").bind(user_id).fetch_one(conn).await?;
@@ -0,0 +1,79 @@
+use crate::adapters::database::records::FeatureFlagRecord;
+use crate::domain::feature_flag::FeatureFlag;
+use crate::error::{AppError, Result};
+use sqlx::PgConnection;
+
+#[derive(Clone, Debug, Default)]
+pub struct FeatureFlagRepository {}
+
+impl FeatureFlagRepository {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {}
+    }
+
+    /// Lists every feature flag.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, err)]
+    pub(crate) async fn list(&self, conn: &mut PgConnection) -> Result<Vec<FeatureFlag>> {
+        let records = sqlx::query_as::<_, FeatureFlagRecord>(
+            "SELECT name, enabled, rollout_percent, description, updated_at FROM feature_flags ORDER BY name",
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok(records
+            .into_iter()
+            .filter_map(|record| {
+                FeatureFlag::try_from(record)
+                    .map_err(|e| tracing::warn!(error = %e, "Skipping unreadable feature flag"))
+                    .ok()
+            })
+            .collect())
+    }
+
+    /// Creates or replaces a feature flag.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(%name, %enabled, %rollout_percent), err)]
+    pub(crate) async fn upsert(
+        &self,
+        conn: &mut PgConnection,
+        name: &str,
+        enabled: bool,
+        rollout_percent: u8,
+        description: Option<&str>,
+    ) -> Result<FeatureFlag> {
+        let record = sqlx::query_as::<_, FeatureFlagRecord>(
+            r#"
+            INSERT INTO feature_flags (name, enabled, rollout_percent, description)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (name) DO UPDATE
+            SET enabled = EXCLUDED.enabled, rollout_percent = EXCLUDED.rollout_percent,
+                description = EXCLUDED.description, updated_at = NOW()
+            RETURNING name, enabled, rollout_percent, description, updated_at
+            "#,
+        )
+        .bind(name)
+        .bind(enabled)
+        .bind(i16::from(rollout_percent))
+        .bind(description)
+        .fetch_one(conn)
+        .await?;
+
+        FeatureFlag::try_from(record).map_err(AppError::InternalMsg)
+    }
+
+    /// Deletes a feature flag. Returns `true` if one was deleted.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(%name), err)]
+    pub(crate) async fn delete(&self, conn: &mut PgConnection, name: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM feature_flags WHERE name = $1").bind(name).execute(conn).await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
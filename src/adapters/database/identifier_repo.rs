@@ -0,0 +1,267 @@
+use crate::adapters::database::records::{IdentifierRecord, PendingVerificationRecord};
+use crate::domain::identifier::{Identifier, IdentifierKind};
+use crate::error::{AppError, Result};
+use crate::telemetry;
+use sqlx::PgConnection;
+use std::time::Duration;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+/// A freshly issued verification code, stored only as a salted hash.
+#[derive(Debug)]
+pub(crate) struct IssuedCode<'a> {
+    pub(crate) identifier_hash: &'a [u8],
+    pub(crate) code_salt: &'a [u8],
+    pub(crate) code_hash: &'a [u8],
+    pub(crate) expires_at: OffsetDateTime,
+    pub(crate) discoverable: bool,
+}
+
+/// How often new verification codes may be sent for one of a user's identifiers.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ResendLimits {
+    /// Minimum time between two codes.
+    pub(crate) cooldown: Duration,
+    /// Window the code and guess caps apply to.
+    pub(crate) window: Duration,
+    /// Codes sent per window.
+    pub(crate) max_codes: u32,
+    /// Wrong guesses per window, across all codes sent in it.
+    pub(crate) max_attempts: u32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct IdentifierRepository {}
+
+impl IdentifierRepository {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {}
+    }
+
+    /// Binds an identifier to the user, replacing any of the same kind, unverified until `code`
+    /// is confirmed. Returns `false`, changing nothing, if `limits` do not allow another code yet.
+    ///
+    /// Wrong guesses carry over to the new code until the window restarts, so re-sending does
+    /// not buy more guesses.
+    ///
+    /// # Errors
+    /// Returns a database error if the upsert fails.
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(user.id = %telemetry::id(user_id), ?kind, ?limits),
+        err
+    )]
+    pub(crate) async fn start_verification(
+        &self,
+        conn: &mut PgConnection,
+        user_id: Uuid,
+        kind: IdentifierKind,
+        code: IssuedCode<'_>,
+        limits: &ResendLimits,
+    ) -> Result<bool> {
+        let issued = sqlx::query_scalar::<_, bool>(
+            r#"
+            INSERT INTO user_identifiers
+                (user_id, kind, identifier_hash, discoverable, code_salt, code_hash, code_expires_at,
+                 code_sent_at, codes_sent, code_window_started_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, NOW(), 1, NOW())
+            ON CONFLICT (user_id, kind) DO UPDATE
+            SET identifier_hash = $3, discoverable = $4, code_salt = $5, code_hash = $6,
+                code_expires_at = $7, verified_at = NULL, created_at = NOW(), code_sent_at = NOW(),
+                attempts = CASE WHEN user_identifiers.code_window_started_at > NOW() - make_interval(secs => $9)
+                    THEN user_identifiers.attempts ELSE 0 END,
+                codes_sent = CASE WHEN user_identifiers.code_window_started_at > NOW() - make_interval(secs => $9)
+                    THEN user_identifiers.codes_sent + 1 ELSE 1 END,
+                code_window_started_at = CASE
+                    WHEN user_identifiers.code_window_started_at > NOW() - make_interval(secs => $9)
+                    THEN user_identifiers.code_window_started_at ELSE NOW() END
+            WHERE (user_identifiers.code_sent_at IS NULL
+                   OR user_identifiers.code_sent_at <= NOW() - make_interval(secs => $8))
+              AND (user_identifiers.code_window_started_at IS NULL
+                   OR user_identifiers.code_window_started_at <= NOW() - make_interval(secs => $9)
+                   OR (user_identifiers.codes_sent < $10 AND user_identifiers.attempts < $11))
+            RETURNING TRUE
+            "#,
+        )
+        .bind(user_id)
+        .bind(kind.as_str())
+        .bind(code.identifier_hash)
+        .bind(code.discoverable)
+        .bind(code.code_salt)
+        .bind(code.code_hash)
+        .bind(code.expires_at)
+        .bind(limits.cooldown.as_secs_f64())
+        .bind(limits.window.as_secs_f64())
+        .bind(i32::try_from(limits.max_codes).unwrap_or(i32::MAX))
+        .bind(i32::try_from(limits.max_attempts).unwrap_or(i32::MAX))
+        .fetch_optional(conn)
+        .await?;
+        Ok(issued.is_some())
+    }
+
+    /// Seconds until `limits` allow another code for the user's identifier of this kind.
+    ///
+    /// # Errors
+    /// Returns a database error if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(user.id = %telemetry::id(user_id), ?kind, ?limits), err)]
+    pub(crate) async fn resend_retry_after(
+        &self,
+        conn: &mut PgConnection,
+        user_id: Uuid,
+        kind: IdentifierKind,
+        limits: &ResendLimits,
+    ) -> Result<u64> {
+        let secs: Option<i64> = sqlx::query_scalar(
+            r#"
+            SELECT CEIL(EXTRACT(EPOCH FROM GREATEST(
+                code_sent_at + make_interval(secs => $3) - NOW(),
+                CASE WHEN codes_sent >= $5 OR attempts >= $6
+                     THEN code_window_started_at + make_interval(secs => $4) - NOW()
+                     ELSE INTERVAL '0' END
+            )))::BIGINT
+            FROM user_identifiers
+            WHERE user_id = $1 AND kind = $2
+            "#,
+        )
+        .bind(user_id)
+        .bind(kind.as_str())
+        .bind(limits.cooldown.as_secs_f64())
+        .bind(limits.window.as_secs_f64())
+        .bind(i32::try_from(limits.max_codes).unwrap_or(i32::MAX))
+        .bind(i32::try_from(limits.max_attempts).unwrap_or(i32::MAX))
+        .fetch_optional(conn)
+        .await?
+        .flatten();
+        Ok(secs.and_then(|s| u64::try_from(s).ok()).unwrap_or(0).max(1))
+    }
+
+    /// Locks and returns the outstanding verification code for one of the user's identifiers.
+    ///
+    /// # Errors
+    /// Returns a database error if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(user.id = %telemetry::id(user_id), ?kind), err)]
+    pub(crate) async fn lock_pending(
+        &self,
+        conn: &mut PgConnection,
+        user_id: Uuid,
+        kind: IdentifierKind,
+    ) -> Result<Option<PendingVerificationRecord>> {
+        let record = sqlx::query_as::<_, PendingVerificationRecord>(
+            r#"
+            SELECT identifier_hash, code_salt, code_hash, code_expires_at, attempts
+            FROM user_identifiers
+            WHERE user_id = $1 AND kind = $2 AND verified_at IS NULL AND code_hash IS NOT NULL
+            FOR UPDATE
+            "#,
+        )
+        .bind(user_id)
+        .bind(kind.as_str())
+        .fetch_optional(conn)
+        .await?;
+        Ok(record)
+    }
+
+    /// Counts a wrong guess, discarding the code once `max_attempts` is reached.
+    ///
+    /// # Errors
+    /// Returns a database error if the update fails.
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(user.id = %telemetry::id(user_id), ?kind, %max_attempts),
+        err
+    )]
+    pub(crate) async fn record_failed_attempt(
+        &self,
+        conn: &mut PgConnection,
+        user_id: Uuid,
+        kind: IdentifierKind,
+        max_attempts: u32,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE user_identifiers
+            SET attempts = attempts + 1,
+                code_hash = CASE WHEN attempts + 1 >= $3 THEN NULL ELSE code_hash END
+            WHERE user_id = $1 AND kind = $2
+            "#,
+        )
+        .bind(user_id)
+        .bind(kind.as_str())
+        .bind(i32::try_from(max_attempts).unwrap_or(i32::MAX))
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Marks the identifier verified and discards its code.
+    ///
+    /// # Errors
+    /// Returns `AppError::Conflict` if the identifier is already verified for another account.
+    /// Returns `AppError::Database` for other database failures.
+    #[tracing::instrument(level = "debug", skip_all, fields(user.id = %telemetry::id(user_id), ?kind), err)]
+    pub(crate) async fn mark_verified(
+        &self,
+        conn: &mut PgConnection,
+        user_id: Uuid,
+        kind: IdentifierKind,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE user_identifiers
+            SET verified_at = NOW(), code_salt = NULL, code_hash = NULL, code_expires_at = NULL, attempts = 0
+            WHERE user_id = $1 AND kind = $2
+            "#,
+        )
+        .bind(user_id)
+        .bind(kind.as_str())
+        .execute(conn)
+        .await
+        .map_err(|e| {
+            if let sqlx::Error::Database(ref db_err) = e
+                && db_err.code().as_deref() == Some("23505")
+            {
+                return AppError::Conflict("Identifier is already verified for another account".into());
+            }
+            AppError::Database(e)
+        })?;
+        Ok(())
+    }
+
+    /// Lists the identifiers bound to a user.
+    ///
+    /// # Errors
+    /// Returns a database error if the query fails, or `AppError::InternalMsg` if a row is corrupt.
+    #[tracing::instrument(level = "debug", skip_all, fields(user.id = %telemetry::id(user_id)), err)]
+    pub(crate) async fn list(&self, conn: &mut PgConnection, user_id: Uuid) -> Result<Vec<Identifier>> {
+        let records = sqlx::query_as::<_, IdentifierRecord>(
+            r#"
+            SELECT kind, discoverable, verified_at, created_at
+            FROM user_identifiers
+            WHERE user_id = $1
+            ORDER BY kind
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(conn)
+        .await?;
+
+        records.into_iter().map(|r| Identifier::try_from(r).map_err(AppError::InternalMsg)).collect()
+    }
+
+    /// Unbinds one of the user's identifiers. Returns whether one was bound.
+    ///
+    /// # Errors
+    /// Returns a database error if the deletion fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(user.id = %telemetry::id(user_id), ?kind), err)]
+    pub(crate) async fn delete(&self, conn: &mut PgConnection, user_id: Uuid, kind: IdentifierKind) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM user_identifiers WHERE user_id = $1 AND kind = $2")
+            .bind(user_id)
+            .bind(kind.as_str())
+            .execute(conn)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,309 @@
+use crate::adapters::database::DbPool;
+use opentelemetry::{
+    KeyValue, global,
+    metrics::{Counter, Histogram},
+};
+use sqlx::pool::PoolConnection;
+use sqlx::{Postgres, Transaction};
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tracing::span;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+
+/// Every repository method is instrumented with a span whose target lives under this module path.
+pub const REPOSITORY_TARGET: &str = "obscura_server::adapters::database";
+
+#[derive(Clone, Debug)]
+struct Metrics {
+    query_duration_seconds: Histogram<f64>,
+    slow_queries_total: Counter<u64>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let meter = global::meter("obscura-server");
+        Self {
+            query_duration_seconds: meter
+                .f64_histogram("obscura_db_query_duration_seconds")
+                .with_description("Duration of repository calls, labelled by repository and method")
+                .build(),
+            slow_queries_total: meter
+                .u64_counter("obscura_db_slow_queries_total")
+                .with_description("Repository calls that exceeded the slow query threshold")
+                .build(),
+        }
+    }
+}
+
+/// Start time and bind parameter names of an in-flight repository call.
+struct QueryTiming {
+    started: Instant,
+    binds: Vec<&'static str>,
+}
+
+/// A tracing layer that times the spans created by `#[tracing::instrument]` on repository
+/// methods, so query durations are measured without touching every call site.
+///
+/// Slow calls are logged with the names of their bind parameters; values are never recorded
+/// because they routinely contain user identifiers and key material.
+#[derive(Debug)]
+pub struct QueryInstrumentationLayer {
+    slow_query_threshold: Option<Duration>,
+    metrics: Metrics,
+}
+
+impl QueryInstrumentationLayer {
+    /// Creates the layer. A threshold of zero disables the slow query log.
+    ///
+    /// Must be called after the global meter provider is installed.
+    #[must_use]
+    pub fn new(slow_query_threshold: Duration) -> Self {
+        Self {
+            slow_query_threshold: (!slow_query_threshold.is_zero()).then_some(slow_query_threshold),
+            metrics: Metrics::new(),
+        }
+    }
+}
+
+/// Maps a span target such as `obscura_server::adapters::database::message_repo` to `message_repo`.
+fn repository_name(target: &str) -> Option<&str> {
+    target.strip_prefix(REPOSITORY_TARGET)?.strip_prefix("::").filter(|name| !name.is_empty())
+}
+
+impl<S> tracing_subscriber::Layer<S> for QueryInstrumentationLayer
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        if repository_name(attrs.metadata().target()).is_none() {
+            return;
+        }
+        if let Some(span) = ctx.span(id) {
+            let binds = attrs.metadata().fields().iter().map(|f| f.name()).collect();
+            span.extensions_mut().insert(QueryTiming { started: Instant::now(), binds });
+        }
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let metadata = span.metadata();
+        let Some(repository) = repository_name(metadata.target()) else {
+            return;
+        };
+        let extensions = span.extensions();
+        let Some(timing) = extensions.get::<QueryTiming>() else {
+            return;
+        };
+
+        let elapsed = timing.started.elapsed();
+        let attributes = [KeyValue::new("repository", repository), KeyValue::new("method", metadata.name())];
+        self.metrics.query_duration_seconds.record(elapsed.as_secs_f64(), &attributes);
+
+        if let Some(threshold) = self.slow_query_threshold
+            && elapsed >= threshold
+        {
+            self.metrics.slow_queries_total.add(1, &attributes);
+            tracing::warn!(
+                db.repository = repository,
+                db.method = metadata.name(),
+                db.binds = %timing.binds.join(","),
+                duration_ms = %elapsed.as_millis(),
+                threshold_ms = %threshold.as_millis(),
+                "Slow database query"
+            );
+        }
+    }
+}
+
+/// Publishes connection pool utilization gauges, sampled on every metrics collection.
+pub fn register_pool_metrics(pool: &DbPool) {
+    let meter = global::meter("obscura-server");
+
+    let observed = pool.clone();
+    meter
+        .u64_observable_gauge("obscura_db_pool_connections")
+        .with_description("Database pool connections by state (idle or in_use)")
+        .with_callback(move |observer| {
+            let size = u64::from(observed.size());
+            let idle = observed.num_idle() as u64;
+            observer.observe(idle, &[KeyValue::new("state", "idle")]);
+            observer.observe(size.saturating_sub(idle), &[KeyValue::new("state", "in_use")]);
+        })
+        .build();
+
+    let observed = pool.clone();
+    meter
+        .u64_observable_gauge("obscura_db_pool_max_connections")
+        .with_description("Configured maximum size of the database pool")
+        .with_callback(move |observer| {
+            observer.observe(u64::from(observed.options().get_max_connections()), &[]);
+        })
+        .build();
+}
+
+#[derive(Clone, Debug)]
+struct AcquireMetrics {
+    wait_seconds: Histogram<f64>,
+    timeouts_total: Counter<u64>,
+}
+
+impl AcquireMetrics {
+    fn new() -> Self {
+        let meter = global::meter("obscura-server");
+        Self {
+            wait_seconds: meter
+                .f64_histogram("obscura_db_pool_acquire_wait_seconds")
+                .with_description("Time spent waiting for a database pool connection")
+                .build(),
+            timeouts_total: meter
+                .u64_counter("obscura_db_pool_acquire_timeouts_total")
+                .with_description("Database pool acquires that gave up after the acquire timeout")
+                .build(),
+        }
+    }
+}
+
+/// Created on first use, which is after the global meter provider is installed.
+static ACQUIRE_METRICS: LazyLock<AcquireMetrics> = LazyLock::new(AcquireMetrics::new);
+
+static ACQUIRES: AtomicU64 = AtomicU64::new(0);
+static ACQUIRE_WAIT_NS: AtomicU64 = AtomicU64::new(0);
+static ACQUIRE_TIMEOUTS: AtomicU64 = AtomicU64::new(0);
+static ACQUIRE_WAITERS: AtomicU64 = AtomicU64::new(0);
+
+/// Timed acquires waiting for a connection right now.
+#[must_use]
+pub fn waiting_acquires() -> u64 {
+    ACQUIRE_WAITERS.load(Ordering::Relaxed)
+}
+
+/// Counts an acquire as waiting until it resolves or is cancelled.
+struct Waiting;
+
+impl Waiting {
+    fn start() -> Self {
+        ACQUIRE_WAITERS.fetch_add(1, Ordering::Relaxed);
+        Self
+    }
+}
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        ACQUIRE_WAITERS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Running totals of pool acquires since startup.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AcquireTotals {
+    pub acquires: u64,
+    pub wait_ns: u64,
+    pub timeouts: u64,
+}
+
+impl AcquireTotals {
+    #[must_use]
+    pub fn current() -> Self {
+        Self {
+            acquires: ACQUIRES.load(Ordering::Relaxed),
+            wait_ns: ACQUIRE_WAIT_NS.load(Ordering::Relaxed),
+            timeouts: ACQUIRE_TIMEOUTS.load(Ordering::Relaxed),
+        }
+    }
+
+    /// The acquires made between `earlier` and `self`.
+    #[must_use]
+    pub const fn since(self, earlier: Self) -> Self {
+        Self {
+            acquires: self.acquires.saturating_sub(earlier.acquires),
+            wait_ns: self.wait_ns.saturating_sub(earlier.wait_ns),
+            timeouts: self.timeouts.saturating_sub(earlier.timeouts),
+        }
+    }
+
+    /// Mean wait of the successful acquires, or zero if there were none.
+    #[must_use]
+    pub fn mean_wait(self) -> Duration {
+        Duration::from_nanos(self.wait_ns.checked_div(self.acquires).unwrap_or(0))
+    }
+}
+
+/// Pool checkouts that record how long the caller waited for a connection.
+///
+/// Use these instead of [`sqlx::Pool::acquire`] and [`sqlx::Pool::begin`] so that pool
+/// contention shows up in metrics and in the pool adjuster.
+pub(crate) trait TimedAcquire {
+    fn acquire_timed(&self) -> impl Future<Output = Result<PoolConnection<Postgres>, sqlx::Error>> + Send;
+
+    /// Also times the `BEGIN` round trip, which is small next to any real wait for a connection.
+    fn begin_timed(&self) -> impl Future<Output = Result<Transaction<'static, Postgres>, sqlx::Error>> + Send;
+}
+
+impl TimedAcquire for DbPool {
+    async fn acquire_timed(&self) -> Result<PoolConnection<Postgres>, sqlx::Error> {
+        let (started, waiting) = (Instant::now(), Waiting::start());
+        let result = self.acquire().await;
+        drop(waiting);
+        record_acquire(started, result)
+    }
+
+    async fn begin_timed(&self) -> Result<Transaction<'static, Postgres>, sqlx::Error> {
+        let (started, waiting) = (Instant::now(), Waiting::start());
+        let result = self.begin().await;
+        drop(waiting);
+        record_acquire(started, result)
+    }
+}
+
+fn record_acquire<T>(started: Instant, result: Result<T, sqlx::Error>) -> Result<T, sqlx::Error> {
+    match &result {
+        Ok(_) => {
+            let waited = started.elapsed();
+            ACQUIRES.fetch_add(1, Ordering::Relaxed);
+            ACQUIRE_WAIT_NS.fetch_add(u64::try_from(waited.as_nanos()).unwrap_or(u64::MAX), Ordering::Relaxed);
+            ACQUIRE_METRICS.wait_seconds.record(waited.as_secs_f64(), &[]);
+        }
+        Err(sqlx::Error::PoolTimedOut) => {
+            ACQUIRE_TIMEOUTS.fetch_add(1, Ordering::Relaxed);
+            ACQUIRE_METRICS.timeouts_total.add(1, &[]);
+        }
+        Err(_) => {}
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repository_name() {
+        assert_eq!(repository_name("obscura_server::adapters::database::message_repo"), Some("message_repo"));
+        assert_eq!(repository_name("obscura_server::adapters::database"), None);
+        assert_eq!(repository_name("obscura_server::adapters::redis::notification_repo"), None);
+        assert_eq!(repository_name("obscura_server::adapters::databases::x"), None);
+    }
+
+    #[test]
+    fn test_acquire_totals_since() {
+        let earlier = AcquireTotals { acquires: 10, wait_ns: 1_000, timeouts: 1 };
+        let later = AcquireTotals { acquires: 14, wait_ns: 9_000, timeouts: 1 };
+        let delta = later.since(earlier);
+        assert_eq!(delta, AcquireTotals { acquires: 4, wait_ns: 8_000, timeouts: 0 });
+        assert_eq!(delta.mean_wait(), Duration::from_nanos(2_000));
+        assert_eq!(AcquireTotals::default().mean_wait(), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_waiting_acquires_are_counted_until_dropped() {
+        let before = waiting_acquires();
+        let waiting = Waiting::start();
+        assert_eq!(waiting_acquires(), before + 1);
+        drop(waiting);
+        assert_eq!(waiting_acquires(), before);
+    }
+}
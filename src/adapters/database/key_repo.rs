@@ -1,10 +1,14 @@
 use crate::adapters::database::records::{ConsumedPreKeyRecord, IdentityKeyRecord, SignedPreKeyRecord};
 use crate::domain::crypto::{PublicKey, Signature};
-use crate::domain::keys::{OneTimePreKey, PreKeyBundle, SignedPreKey};
+use crate::domain::keys::{OneTimePreKey, OneTimePreKeyClaim, PreKeyBundle, PreKeyPoolPage, SignedPreKey};
 use crate::error::{AppError, Result};
+use crate::telemetry;
 use sqlx::PgConnection;
 use uuid::Uuid;
 
+/// Rows per `UNNEST` insert, keeping each statement's arrays bounded however many keys a device uploads.
+const PRE_KEY_INSERT_CHUNK_SIZE: usize = 500;
+
 #[derive(Clone, Debug, Default)]
 pub struct KeyRepository {}
 
@@ -18,7 +22,12 @@ impl KeyRepository {
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the database operation fails.
-    #[tracing::instrument(level = "debug", skip(self, conn, identity_key), err)]
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id), %registration_id),
+        err
+    )]
     pub(crate) async fn upsert_identity_key(
         &self,
         conn: &mut PgConnection,
@@ -46,7 +55,11 @@ impl KeyRepository {
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the database operation fails.
-    #[tracing::instrument(level = "debug", skip(self, conn, public_key, signature))]
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id), %key_id)
+    )]
     pub(crate) async fn upsert_signed_pre_key(
         &self,
         conn: &mut PgConnection,
@@ -72,11 +85,15 @@ impl KeyRepository {
         Ok(())
     }
 
-    /// Inserts a batch of one-time pre-keys.
+    /// Inserts a batch of one-time pre-keys, in chunks of `PRE_KEY_INSERT_CHUNK_SIZE`.
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the database operation fails.
-    #[tracing::instrument(level = "debug", skip(self, conn, keys))]
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id))
+    )]
     pub(crate) async fn insert_one_time_pre_keys(
         &self,
         conn: &mut PgConnection,
@@ -87,42 +104,45 @@ impl KeyRepository {
             return Ok(());
         }
 
-        let mut ids = Vec::with_capacity(keys.len());
-        let mut device_ids = Vec::with_capacity(keys.len());
-        let mut pub_keys = Vec::with_capacity(keys.len());
-
-        for k in keys {
-            ids.push(k.key_id);
-            device_ids.push(device_id);
-            pub_keys.push(k.public_key.as_bytes());
+        for chunk in keys.chunks(PRE_KEY_INSERT_CHUNK_SIZE) {
+            let ids: Vec<i32> = chunk.iter().map(|k| k.key_id).collect();
+            let device_ids = vec![device_id; chunk.len()];
+            let pub_keys: Vec<_> = chunk.iter().map(|k| k.public_key.as_bytes()).collect();
+
+            sqlx::query(
+                r#"
+                INSERT INTO one_time_pre_keys (id, device_id, public_key)
+                SELECT * FROM UNNEST($1::int4[], $2::uuid[], $3::bytea[])
+                ON CONFLICT (id, device_id) DO NOTHING
+                "#,
+            )
+            .bind(&ids)
+            .bind(&device_ids)
+            .bind(&pub_keys)
+            .execute(&mut *conn)
+            .await?;
         }
 
-        sqlx::query(
-            r#"
-            INSERT INTO one_time_pre_keys (id, device_id, public_key)
-            SELECT * FROM UNNEST($1::int4[], $2::uuid[], $3::bytea[])
-            ON CONFLICT (id, device_id) DO NOTHING
-            "#,
-        )
-        .bind(&ids)
-        .bind(&device_ids)
-        .bind(&pub_keys)
-        .execute(conn)
-        .await?;
-
         Ok(())
     }
 
-    /// Fetches a pre-key bundle for a single device and consumes one one-time pre-key.
+    /// Fetches a pre-key bundle for a single device, consuming, reserving or skipping one
+    /// one-time pre-key as `claim` says.
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the database operation fails.
     /// Returns `AppError::Internal` if stored data is corrupt.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id), ?claim),
+        err
+    )]
     pub(crate) async fn fetch_pre_key_bundle(
         &self,
         conn: &mut PgConnection,
         device_id: Uuid,
+        claim: OneTimePreKeyClaim<'_>,
     ) -> Result<Option<(PreKeyBundle, Option<i64>)>> {
         // Fetch identity
         let identity_rec = sqlx::query_as::<_, IdentityKeyRecord>(
@@ -163,23 +183,62 @@ impl KeyRepository {
             AppError::Internal
         })?;
 
-        // Fetch one one-time pre key and delete it
-        let otpk_rec = sqlx::query_as::<_, ConsumedPreKeyRecord>(
-            r#"
-            WITH target AS (
-                SELECT id FROM one_time_pre_keys
-                WHERE device_id = $1
-                LIMIT 1
-                FOR UPDATE SKIP LOCKED
-            )
-            DELETE FROM one_time_pre_keys
-            WHERE id IN (SELECT id FROM target) AND device_id = $1
-            RETURNING id, public_key, (SELECT COUNT(*) - 1 FROM one_time_pre_keys WHERE device_id = $1) AS remaining_count
-            "#,
-        )
-        .bind(device_id)
-        .fetch_optional(&mut *conn)
-        .await?;
+        // Take one available one-time pre key: delete it, or hold it for the reservation.
+        // Keys under a live reservation are neither handed out nor counted as remaining.
+        let otpk_rec = match claim {
+            // Without a claim nothing is known about the remaining count, so no refill is requested
+            OneTimePreKeyClaim::Skip => {
+                return Ok(Some((
+                    PreKeyBundle { device_id, registration_id, identity_key, signed_pre_key, one_time_pre_key: None },
+                    None,
+                )));
+            }
+            OneTimePreKeyClaim::Consume => {
+                sqlx::query_as::<_, ConsumedPreKeyRecord>(
+                    r#"
+                    WITH target AS (
+                        SELECT id FROM one_time_pre_keys
+                        WHERE device_id = $1 AND (reserved_until IS NULL OR reserved_until < NOW())
+                        LIMIT 1
+                        FOR UPDATE SKIP LOCKED
+                    )
+                    DELETE FROM one_time_pre_keys
+                    WHERE id IN (SELECT id FROM target) AND device_id = $1
+                    RETURNING id, public_key, (
+                        SELECT COUNT(*) - 1 FROM one_time_pre_keys
+                        WHERE device_id = $1 AND (reserved_until IS NULL OR reserved_until < NOW())
+                    ) AS remaining_count
+                    "#,
+                )
+                .bind(device_id)
+                .fetch_optional(&mut *conn)
+                .await?
+            }
+            OneTimePreKeyClaim::Reserve(reservation) => {
+                sqlx::query_as::<_, ConsumedPreKeyRecord>(
+                    r#"
+                    WITH target AS (
+                        SELECT id FROM one_time_pre_keys
+                        WHERE device_id = $1 AND (reserved_until IS NULL OR reserved_until < NOW())
+                        LIMIT 1
+                        FOR UPDATE SKIP LOCKED
+                    )
+                    UPDATE one_time_pre_keys
+                    SET reservation_token = $2, reserved_until = $3
+                    WHERE id IN (SELECT id FROM target) AND device_id = $1
+                    RETURNING id, public_key, (
+                        SELECT COUNT(*) - 1 FROM one_time_pre_keys
+                        WHERE device_id = $1 AND (reserved_until IS NULL OR reserved_until < NOW())
+                    ) AS remaining_count
+                    "#,
+                )
+                .bind(device_id)
+                .bind(reservation.token)
+                .bind(reservation.expires_at)
+                .fetch_optional(&mut *conn)
+                .await?
+            }
+        };
 
         let (one_time_pre_key, remaining_count) = match otpk_rec {
             Some(rec) => {
@@ -201,29 +260,31 @@ impl KeyRepository {
     }
 
     /// Fetches exactly one pre-key bundle for every device owned by a specific user.
-    /// Mutates the database to consume one one-time pre-key per device.
+    /// Mutates the database to consume (or reserve) one one-time pre-key per device, unless
+    /// `claim` skips them.
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the database operation fails.
     /// Returns `AppError::Internal` if stored data is corrupt.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
+    #[tracing::instrument(level = "debug", skip_all, fields(user.id = %telemetry::id(user_id), ?claim), err)]
     pub(crate) async fn get_all_bundles_for_user(
         &self,
         conn: &mut PgConnection,
         user_id: Uuid,
+        claim: OneTimePreKeyClaim<'_>,
     ) -> Result<Vec<(PreKeyBundle, Option<i64>)>> {
         let device_ids: Vec<Uuid> =
             sqlx::query_scalar("SELECT id FROM devices WHERE user_id = $1").bind(user_id).fetch_all(&mut *conn).await?;
 
-        tracing::info!(user.id = %user_id, count = %device_ids.len(), "Found devices for user");
+        tracing::info!(user.id = %telemetry::id(user_id), count = %device_ids.len(), "Found devices for user");
 
         let mut bundles = Vec::new();
 
         for id in device_ids {
-            if let Some(bundle_result) = self.fetch_pre_key_bundle(&mut *conn, id).await? {
+            if let Some(bundle_result) = self.fetch_pre_key_bundle(&mut *conn, id, claim).await? {
                 bundles.push(bundle_result);
             } else {
-                tracing::info!(device.id = %id, "fetch_pre_key_bundle returned None");
+                tracing::info!(device.id = %telemetry::id(id), "fetch_pre_key_bundle returned None");
             }
         }
 
@@ -235,7 +296,12 @@ impl KeyRepository {
     /// # Errors
     /// Returns `sqlx::Error` if the query fails.
     /// Returns `AppError::Internal` if stored data is corrupt.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id)),
+        err
+    )]
     pub(crate) async fn fetch_identity_key(
         &self,
         conn: &mut PgConnection,
@@ -265,7 +331,12 @@ impl KeyRepository {
     /// # Errors
     /// Returns `sqlx::Error` if the query fails.
     /// Returns `AppError::Internal` if stored data is corrupt.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id)),
+        err
+    )]
     pub(crate) async fn fetch_identity_key_for_update(
         &self,
         conn: &mut PgConnection,
@@ -294,7 +365,12 @@ impl KeyRepository {
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the deletion fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id)),
+        err
+    )]
     pub(crate) async fn delete_all_signed_pre_keys(&self, conn: &mut PgConnection, device_id: Uuid) -> Result<()> {
         sqlx::query("DELETE FROM signed_pre_keys WHERE device_id = $1").bind(device_id).execute(conn).await?;
         Ok(())
@@ -304,7 +380,12 @@ impl KeyRepository {
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the deletion fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id)),
+        err
+    )]
     pub(crate) async fn delete_all_one_time_pre_keys(&self, conn: &mut PgConnection, device_id: Uuid) -> Result<()> {
         sqlx::query("DELETE FROM one_time_pre_keys WHERE device_id = $1").bind(device_id).execute(conn).await?;
         Ok(())
@@ -314,7 +395,12 @@ impl KeyRepository {
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the query fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id)),
+        err
+    )]
     pub(crate) async fn count_one_time_pre_keys(&self, conn: &mut PgConnection, device_id: Uuid) -> Result<i64> {
         let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM one_time_pre_keys WHERE device_id = $1")
             .bind(device_id)
@@ -323,11 +409,85 @@ impl KeyRepository {
         Ok(count)
     }
 
+    /// Counts the remaining one-time pre-keys across several devices.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, err)]
+    pub(crate) async fn count_one_time_pre_keys_for_devices(
+        &self,
+        conn: &mut PgConnection,
+        device_ids: &[Uuid],
+    ) -> Result<i64> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM one_time_pre_keys WHERE device_id = ANY($1)")
+            .bind(device_ids)
+            .fetch_one(conn)
+            .await?;
+        Ok(count)
+    }
+
+    /// Counts the users, and their devices, holding fewer than `threshold` one-time pre-keys that
+    /// can still be handed out, among the next `limit` users after `after` in ID order. Keys under
+    /// a live reservation do not count, and no pool is read past the threshold.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(%threshold, ?after, %limit), err)]
+    pub(crate) async fn count_below_threshold(
+        &self,
+        conn: &mut PgConnection,
+        threshold: i32,
+        after: Option<Uuid>,
+        limit: i64,
+    ) -> Result<PreKeyPoolPage> {
+        let (scanned, last_user_id, users, devices): (i64, Option<Uuid>, i64, i64) = sqlx::query_as(
+            r#"
+            WITH page AS (
+                SELECT id FROM users
+                WHERE $2::uuid IS NULL OR id > $2
+                ORDER BY id
+                LIMIT $3
+            )
+            SELECT
+                (SELECT COUNT(*) FROM page),
+                (SELECT id FROM page ORDER BY id DESC LIMIT 1),
+                COUNT(DISTINCT d.user_id),
+                COUNT(*)
+            FROM page u
+            JOIN devices d ON d.user_id = u.id
+            CROSS JOIN LATERAL (
+                SELECT COUNT(*) AS available FROM (
+                    SELECT 1 FROM one_time_pre_keys k
+                    WHERE k.device_id = d.id AND (k.reserved_until IS NULL OR k.reserved_until < NOW())
+                    LIMIT $1
+                ) capped
+            ) pool
+            WHERE pool.available < $1
+            "#,
+        )
+        .bind(threshold)
+        .bind(after)
+        .bind(limit)
+        .fetch_one(conn)
+        .await?;
+        Ok(PreKeyPoolPage {
+            scanned_users: u64::try_from(scanned).unwrap_or(0),
+            last_user_id,
+            users_below_threshold: u64::try_from(users).unwrap_or(0),
+            devices_below_threshold: u64::try_from(devices).unwrap_or(0),
+        })
+    }
+
     /// Finds the maximum signed pre-key ID currently stored for a device.
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the query fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id)),
+        err
+    )]
     pub(crate) async fn find_max_signed_pre_key_id(
         &self,
         conn: &mut PgConnection,
@@ -344,7 +504,12 @@ impl KeyRepository {
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the deletion fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id), %threshold_id),
+        err
+    )]
     pub(crate) async fn delete_signed_pre_keys_older_than(
         &self,
         conn: &mut PgConnection,
@@ -363,7 +528,12 @@ impl KeyRepository {
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the deletion fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id), %limit),
+        err
+    )]
     pub(crate) async fn delete_oldest_one_time_pre_keys(
         &self,
         conn: &mut PgConnection,
@@ -375,7 +545,7 @@ impl KeyRepository {
             DELETE FROM one_time_pre_keys
             WHERE device_id = $1 AND id IN (
                 SELECT id FROM one_time_pre_keys
-                WHERE device_id = $1
+                WHERE device_id = $1 AND (reserved_until IS NULL OR reserved_until < NOW())
                 ORDER BY created_at ASC
                 LIMIT $2
             )
@@ -387,4 +557,18 @@ impl KeyRepository {
         .await?;
         Ok(())
     }
+
+    /// Deletes the one-time pre-keys held by any of the given reservations.
+    /// Returns the number of keys removed.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(tokens = tokens.len()), err)]
+    pub(crate) async fn redeem_reservations(&self, conn: &mut PgConnection, tokens: &[Uuid]) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM one_time_pre_keys WHERE reservation_token = ANY($1)")
+            .bind(tokens)
+            .execute(conn)
+            .await?;
+        Ok(result.rows_affected())
+    }
 }
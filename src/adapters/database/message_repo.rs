@@ -1,24 +1,50 @@
-use crate::adapters::database::records::MessageRecord;
-use crate::domain::message::Message;
+use crate::adapters::database::records::{MessageRecord, ReceiptRecord};
+use crate::domain::message::{ExpiredMessages, InboxSummary, Message, MessagePayload, SubmissionReceipt, SystemCode};
+use crate::domain::user::UserTier;
 use crate::error::{AppError, Result};
+use crate::telemetry;
 use sqlx::PgConnection;
 use time::{Duration, OffsetDateTime};
 use uuid::Uuid;
 
-#[derive(Clone, Debug, Default)]
-pub struct MessageRepository {}
+#[derive(Clone, Debug)]
+pub struct MessageRepository {
+    inbox_shards: i32,
+    ack_grace_secs: i64,
+}
+
+impl Default for MessageRepository {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl MessageRepository {
     #[must_use]
     pub const fn new() -> Self {
-        Self {}
+        Self { inbox_shards: 1, ack_grace_secs: 0 }
+    }
+
+    /// Spreads each device's inbox over `shards` index shards. Values below 1 are treated as 1.
+    #[must_use]
+    pub fn with_inbox_shards(mut self, shards: u16) -> Self {
+        self.inbox_shards = i32::from(shards.clamp(1, i16::MAX.unsigned_abs()));
+        self
+    }
+
+    /// Keeps acknowledged messages for `secs` before they are purged, so they can be redelivered.
+    /// Zero deletes them as soon as they are acknowledged.
+    #[must_use]
+    pub fn with_ack_grace_period(mut self, secs: u64) -> Self {
+        self.ack_grace_secs = i64::try_from(secs).unwrap_or(i64::MAX);
+        self
     }
 
     /// Checks which devices exist in the database.
     ///
     /// # Errors
     /// Returns `AppError::Database` if the query fails.
-    #[tracing::instrument(level = "debug", skip(self, conn))]
+    #[tracing::instrument(level = "debug", skip_all, fields(count = device_ids.len()))]
     pub(crate) async fn check_devices_exist(&self, conn: &mut PgConnection, device_ids: &[Uuid]) -> Result<Vec<Uuid>> {
         if device_ids.is_empty() {
             return Ok(Vec::new());
@@ -33,22 +59,139 @@ impl MessageRepository {
         Ok(rows.into_iter().map(|(id,)| id).collect())
     }
 
+    /// Lists the devices of the given users. Unknown users are skipped.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(count = user_ids.len()))]
+    pub(crate) async fn find_user_devices(&self, conn: &mut PgConnection, user_ids: &[Uuid]) -> Result<Vec<Uuid>> {
+        if user_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let rows: Vec<(Uuid,)> = sqlx::query_as("SELECT id FROM devices WHERE user_id = ANY($1)")
+            .bind(user_ids)
+            .fetch_all(conn)
+            .await
+            .map_err(AppError::Database)?;
+
+        Ok(rows.into_iter().map(|(id,)| id).collect())
+    }
+
+    /// Aggregates the pending queue of each device. Devices with an empty queue are omitted.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(count = device_ids.len()), err)]
+    pub(crate) async fn summarize_inboxes(
+        &self,
+        conn: &mut PgConnection,
+        device_ids: &[Uuid],
+    ) -> Result<Vec<InboxSummary>> {
+        let rows: Vec<(Uuid, i64, i64, Option<OffsetDateTime>, Option<OffsetDateTime>)> = sqlx::query_as(
+            r#"
+            SELECT device_id, COUNT(*), COALESCE(SUM(COALESCE(payload_size, octet_length(content))), 0)::BIGINT, MIN(created_at), MIN(expires_at)
+            FROM messages
+            WHERE device_id = ANY($1) AND expires_at > NOW() AND delivered_at IS NULL
+            GROUP BY device_id
+            "#,
+        )
+        .bind(device_ids)
+        .fetch_all(conn)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(device_id, pending_messages, pending_bytes, oldest_message_at, next_expiry_at)| InboxSummary {
+                device_id,
+                pending_messages,
+                pending_bytes,
+                oldest_message_at,
+                next_expiry_at,
+            })
+            .collect())
+    }
+
+    /// Lists the devices with the most pending messages, largest first, along with their owners.
+    ///
+    /// Pages continue strictly after the `after` cursor of `(pending_messages, device_id)` and are
+    /// measured live, so nothing is written and concurrent walks cannot disturb each other. A queue
+    /// that grows or shrinks past the cursor between pages may be skipped or repeated, but the walk
+    /// never breaks.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(?after, %limit), err)]
+    pub(crate) async fn largest_inboxes(
+        &self,
+        conn: &mut PgConnection,
+        after: Option<(i64, Uuid)>,
+        limit: i64,
+    ) -> Result<Vec<(Uuid, InboxSummary)>> {
+        let (after_count, after_device) = after.unzip();
+        let rows: Vec<(Uuid, Uuid, i64, i64, Option<OffsetDateTime>, Option<OffsetDateTime>)> = sqlx::query_as(
+            r#"
+            WITH queues AS (
+                SELECT device_id, COUNT(*) AS pending,
+                       COALESCE(SUM(COALESCE(payload_size, octet_length(content))), 0)::BIGINT AS bytes,
+                       MIN(created_at) AS oldest, MIN(expires_at) AS next_expiry
+                FROM messages
+                WHERE expires_at > NOW() AND delivered_at IS NULL
+                GROUP BY device_id
+            )
+            SELECT d.user_id, q.device_id, q.pending, q.bytes, q.oldest, q.next_expiry
+            FROM queues q
+            JOIN devices d ON d.id = q.device_id
+            WHERE $1::BIGINT IS NULL OR (q.pending, q.device_id) < ($1, $2::UUID)
+            ORDER BY q.pending DESC, q.device_id DESC
+            LIMIT $3
+            "#,
+        )
+        .bind(after_count)
+        .bind(after_device)
+        .bind(limit)
+        .fetch_all(conn)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(user_id, device_id, pending_messages, pending_bytes, oldest_message_at, next_expiry_at)| {
+                (
+                    user_id,
+                    InboxSummary { device_id, pending_messages, pending_bytes, oldest_message_at, next_expiry_at },
+                )
+            })
+            .collect())
+    }
+
     /// Inserts a batch of messages.
     ///
-    /// Ignores duplicate messages (based on `sender_device_id` and `submission_id`) via `ON CONFLICT DO NOTHING`.
-    /// Returns the list of `(device_id, submission_id)` that were successfully inserted.
+    /// Ignores duplicate messages (based on `sender_device_id` and `submission_id`), whether already
+    /// queued or repeated within the batch. Batches from one sending device are serialized, so
+    /// duplicates are dropped before numbering and never use up a sequence number.
+    /// Each message is placed in an inbox shard derived from its submission ID. Offloaded messages are
+    /// inserted under their preassigned ID with an empty `content`.
+    /// Each message takes the next numbers from its recipient's inbox sequence and from the sequence
+    /// of its sender and recipient pair, in batch order. The counters stay locked until the insert
+    /// commits, so no message becomes visible behind one with a higher number; they are locked in
+    /// device order so that concurrent batches cannot deadlock.
+    /// Returns a receipt for every message that was inserted.
     ///
     /// # Errors
     /// Returns `AppError::Database` if the insert fails.
-    #[tracing::instrument(level = "debug", skip(self, conn, messages))]
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(user.id = %telemetry::id(sender_id), device.id = %telemetry::id(sender_device_id), %ttl_days)
+    )]
     pub(crate) async fn create_batch(
         &self,
         conn: &mut PgConnection,
         sender_id: Uuid,
         sender_device_id: Uuid,
-        messages: Vec<(Uuid, Uuid, Vec<u8>)>,
+        messages: Vec<(Uuid, Uuid, MessagePayload)>,
         ttl_days: i64,
-    ) -> Result<Vec<(Uuid, Uuid)>> {
+    ) -> Result<Vec<SubmissionReceipt>> {
         if messages.is_empty() {
             return Ok(Vec::new());
         }
@@ -57,21 +200,75 @@ impl MessageRepository {
 
         let mut device_ids = Vec::with_capacity(messages.len());
         let mut submission_ids = Vec::with_capacity(messages.len());
-        let mut contents = Vec::with_capacity(messages.len());
+        let mut contents: Vec<&[u8]> = Vec::with_capacity(messages.len());
+        let mut message_ids = Vec::with_capacity(messages.len());
+        let mut payload_sizes = Vec::with_capacity(messages.len());
 
-        for (device_id, submission_id, content) in messages {
-            device_ids.push(device_id);
-            submission_ids.push(submission_id);
-            contents.push(content);
+        for (device_id, submission_id, payload) in &messages {
+            device_ids.push(*device_id);
+            submission_ids.push(*submission_id);
+            match payload {
+                MessagePayload::Inline(content) => {
+                    contents.push(content);
+                    message_ids.push(None);
+                    payload_sizes.push(None);
+                }
+                MessagePayload::Offloaded { message_id, size } => {
+                    contents.push(&[]);
+                    message_ids.push(Some(*message_id));
+                    payload_sizes.push(Some(*size));
+                }
+            }
         }
 
-        let inserted = sqlx::query_as::<_, (Uuid, Uuid)>(
+        // Held until commit, so a concurrent retry of the same submission sees this batch's rows
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext('submissions:' || $1::text))")
+            .bind(sender_device_id)
+            .execute(&mut *conn)
+            .await
+            .map_err(AppError::Database)?;
+
+        let inserted = sqlx::query_as::<_, ReceiptRecord>(
             r#"
-            INSERT INTO messages (sender_id, sender_device_id, device_id, submission_id, content, expires_at)
-            SELECT $1, $2, u.d_id, u.s_id, u.content, $6
-            FROM UNNEST($3::uuid[], $4::uuid[], $5::bytea[]) AS u(d_id, s_id, content)
+            WITH batch AS (
+                SELECT DISTINCT ON (u.s_id) u.*
+                FROM UNNEST($3::uuid[], $4::uuid[], $5::bytea[], $8::uuid[], $9::int4[])
+                    WITH ORDINALITY AS u(d_id, s_id, content, m_id, size, ord)
+                WHERE NOT EXISTS (
+                    SELECT 1 FROM messages m WHERE m.sender_device_id = $2 AND m.submission_id = u.s_id
+                )
+                ORDER BY u.s_id, u.ord
+            ),
+            input AS (
+                SELECT b.*,
+                       row_number() OVER (PARTITION BY b.d_id ORDER BY b.ord) AS pos,
+                       COUNT(*) OVER (PARTITION BY b.d_id) AS n
+                FROM batch b
+            ),
+            sequences AS (
+                INSERT INTO inbox_sequences (device_id, last_seq)
+                SELECT d_id, COUNT(*) FROM input GROUP BY d_id ORDER BY d_id
+                ON CONFLICT (device_id) DO UPDATE SET last_seq = inbox_sequences.last_seq + EXCLUDED.last_seq
+                RETURNING device_id, last_seq
+            ),
+            pair_sequences AS (
+                INSERT INTO pair_sequences (sender_device_id, device_id, last_seq)
+                SELECT $2, d_id, COUNT(*) FROM input GROUP BY d_id ORDER BY d_id
+                ON CONFLICT (sender_device_id, device_id)
+                    DO UPDATE SET last_seq = pair_sequences.last_seq + EXCLUDED.last_seq
+                RETURNING device_id, last_seq
+            )
+            INSERT INTO messages (id, sender_id, sender_device_id, device_id, submission_id, content, payload_size, expires_at, shard, seq, pair_seq)
+            SELECT COALESCE(i.m_id, uuidv7()), $1, $2, i.d_id, i.s_id, i.content, i.size, $6,
+                   (hashtext(i.s_id::text) & 2147483647) % $7,
+                   s.last_seq - i.n + i.pos,
+                   p.last_seq - i.n + i.pos
+            FROM input i
+            JOIN sequences s ON s.device_id = i.d_id
+            JOIN pair_sequences p ON p.device_id = i.d_id
+            ORDER BY i.ord
             ON CONFLICT (sender_device_id, submission_id) DO NOTHING
-            RETURNING device_id, submission_id
+            RETURNING id, device_id, submission_id, created_at, seq
             "#,
         )
         .bind(sender_id)
@@ -80,62 +277,266 @@ impl MessageRepository {
         .bind(submission_ids)
         .bind(contents)
         .bind(expires_at)
+        .bind(self.inbox_shards)
+        .bind(message_ids)
+        .bind(payload_sizes)
         .fetch_all(conn)
         .await
         .map_err(AppError::Database)?;
 
-        Ok(inserted)
+        Ok(inserted.into_iter().map(SubmissionReceipt::from).collect())
+    }
+
+    /// Groups the pending messages queued since `since` for `device_ids` by sending device,
+    /// returning each sender's distinct submission ids. Messages the devices sent to one another
+    /// and system envelopes are left out.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(count = device_ids.len(), ?since), err)]
+    pub(crate) async fn find_pending_senders(
+        &self,
+        conn: &mut PgConnection,
+        device_ids: &[Uuid],
+        since: OffsetDateTime,
+    ) -> Result<Vec<(Uuid, Vec<Uuid>)>> {
+        let rows = sqlx::query_as(
+            r#"
+            SELECT sender_device_id, array_agg(DISTINCT submission_id)
+            FROM messages
+            WHERE device_id = ANY($1)
+              AND sender_device_id IS NOT NULL
+              AND sender_device_id <> ALL($1)
+              AND expires_at > NOW()
+              AND delivered_at IS NULL
+              AND created_at >= $2
+            GROUP BY sender_device_id
+            "#,
+        )
+        .bind(device_ids)
+        .bind(since)
+        .fetch_all(conn)
+        .await?;
+
+        Ok(rows)
     }
 
-    /// Fetches a batch of pending messages for a device.
+    /// Queues one system envelope per entry, at the end of each device's inbox sequence.
+    /// Each device may appear at most once.
+    ///
+    /// With a `submission_id`, every envelope is queued under it, and devices that still hold
+    /// an envelope with that ID are skipped, so a retried submission is not queued twice.
+    /// Returns the number of envelopes queued.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if the insert fails.
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(count = envelopes.len(), ?code, ?expires_at, ?submission_id),
+        err
+    )]
+    pub(crate) async fn create_system_batch(
+        &self,
+        conn: &mut PgConnection,
+        code: SystemCode,
+        envelopes: Vec<(Uuid, Vec<u8>)>,
+        expires_at: OffsetDateTime,
+        submission_id: Option<Uuid>,
+    ) -> Result<u64> {
+        if envelopes.is_empty() {
+            return Ok(0);
+        }
+        let (device_ids, contents): (Vec<Uuid>, Vec<Vec<u8>>) = envelopes.into_iter().unzip();
+
+        if let Some(submission_id) = submission_id {
+            // Held until commit, so a concurrent retry of the same submission sees this batch's rows
+            sqlx::query("SELECT pg_advisory_xact_lock(hashtext('system_submissions:' || $1::text))")
+                .bind(submission_id)
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        let result = sqlx::query(
+            r#"
+            WITH input AS (
+                SELECT * FROM UNNEST($1::uuid[], $2::bytea[]) AS u(d_id, content)
+                WHERE $5::uuid IS NULL OR NOT EXISTS (
+                    SELECT 1 FROM messages m
+                    WHERE m.device_id = u.d_id AND m.submission_id = $5 AND m.sender_device_id IS NULL
+                )
+            ),
+            sequences AS (
+                INSERT INTO inbox_sequences (device_id, last_seq)
+                SELECT d_id, 1 FROM input ORDER BY d_id
+                ON CONFLICT (device_id) DO UPDATE SET last_seq = inbox_sequences.last_seq + 1
+                RETURNING device_id, last_seq
+            )
+            INSERT INTO messages (device_id, submission_id, content, expires_at, seq, system_code)
+            SELECT i.d_id, COALESCE($5, uuidv7()), i.content, $3, s.last_seq, $4
+            FROM input i
+            JOIN sequences s ON s.device_id = i.d_id
+            "#,
+        )
+        .bind(device_ids)
+        .bind(contents)
+        .bind(expires_at)
+        .bind(code as i16)
+        .bind(submission_id)
+        .execute(conn)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Fetches receipts for submissions from a sending device that are still queued.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if the query fails.
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(count = submission_ids.len(), device.id = %telemetry::id(sender_device_id)),
+        err
+    )]
+    pub(crate) async fn fetch_receipts(
+        &self,
+        conn: &mut PgConnection,
+        sender_device_id: Uuid,
+        submission_ids: &[Uuid],
+    ) -> Result<Vec<SubmissionReceipt>> {
+        if submission_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let records = sqlx::query_as::<_, ReceiptRecord>(
+            r#"
+            SELECT id, device_id, submission_id, created_at, seq
+            FROM messages
+            WHERE sender_device_id = $1 AND submission_id = ANY($2)
+            "#,
+        )
+        .bind(sender_device_id)
+        .bind(submission_ids)
+        .fetch_all(conn)
+        .await?;
+
+        Ok(records.into_iter().map(SubmissionReceipt::from).collect())
+    }
+
+    /// Records message IDs whose payloads are about to be uploaded to object storage.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if the insert fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(count = message_ids.len()), err)]
+    pub(crate) async fn register_payloads(&self, conn: &mut PgConnection, message_ids: &[Uuid]) -> Result<()> {
+        if message_ids.is_empty() {
+            return Ok(());
+        }
+        sqlx::query("INSERT INTO message_payloads (message_id) SELECT * FROM UNNEST($1::uuid[])")
+            .bind(message_ids)
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Fetches offloaded payloads whose message no longer exists, oldest first.
+    ///
+    /// Payloads registered less than `grace_secs` ago are skipped, as their message may still be
+    /// waiting for the upload to finish.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(%grace_secs, %limit), err)]
+    pub(crate) async fn fetch_orphaned_payloads(
+        &self,
+        conn: &mut PgConnection,
+        grace_secs: i64,
+        limit: i64,
+    ) -> Result<Vec<Uuid>> {
+        let rows: Vec<(Uuid,)> = sqlx::query_as(
+            r#"
+            SELECT p.message_id
+            FROM message_payloads p
+            WHERE p.created_at < NOW() - make_interval(secs => $1)
+              AND NOT EXISTS (SELECT 1 FROM messages m WHERE m.id = p.message_id)
+            ORDER BY p.created_at ASC
+            LIMIT $2
+            "#,
+        )
+        .bind(grace_secs)
+        .bind(limit)
+        .fetch_all(conn)
+        .await?;
+
+        Ok(rows.into_iter().map(|(id,)| id).collect())
+    }
+
+    /// Forgets offloaded payloads once their objects have been deleted.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if the deletion fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(count = message_ids.len()), err)]
+    pub(crate) async fn delete_payloads(&self, conn: &mut PgConnection, message_ids: &[Uuid]) -> Result<u64> {
+        if message_ids.is_empty() {
+            return Ok(0);
+        }
+        let result = sqlx::query("DELETE FROM message_payloads WHERE message_id = ANY($1)")
+            .bind(message_ids)
+            .execute(conn)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Fetches a batch of pending messages for a device after the inbox sequence `cursor`.
+    ///
+    /// Reads the first `limit` messages of every inbox shard the device has messages in and merges
+    /// them, so the result is ordered by inbox sequence. The shards are found in the index rather
+    /// than taken from the configured count, so messages written under a larger count are still
+    /// delivered after it is lowered.
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the query fails.
-    #[tracing::instrument(level = "debug", skip(self, conn))]
+    #[tracing::instrument(level = "debug", skip_all, fields(device.id = %telemetry::id(device_id), ?cursor, %limit))]
     pub(crate) async fn fetch_pending_batch(
         &self,
         conn: &mut PgConnection,
         device_id: Uuid,
-        cursor: Option<(OffsetDateTime, Uuid)>,
+        cursor: Option<i64>,
         limit: i64,
     ) -> Result<Vec<Message>> {
-        let messages = match cursor {
-            Some((last_ts, last_id)) => {
-                sqlx::query_as::<_, MessageRecord>(
-                    r#"
-                    SELECT id, sender_id, sender_device_id, content, created_at
-                    FROM messages
-                    WHERE device_id = $1
-                      AND expires_at > NOW()
-                      AND (created_at, id) > ($2, $3)
-                    ORDER BY created_at ASC, id ASC
-                    LIMIT $4
-                    "#,
-                )
-                .bind(device_id)
-                .bind(last_ts)
-                .bind(last_id)
-                .bind(limit)
-                .fetch_all(conn)
-                .await?
-            }
-            None => {
-                sqlx::query_as::<_, MessageRecord>(
-                    r#"
-                    SELECT id, sender_id, sender_device_id, content, created_at
-                    FROM messages
-                    WHERE device_id = $1
-                      AND expires_at > NOW()
-                    ORDER BY created_at ASC, id ASC
-                    LIMIT $2
-                    "#,
-                )
-                .bind(device_id)
-                .bind(limit)
-                .fetch_all(conn)
-                .await?
-            }
-        };
+        let messages = sqlx::query_as::<_, MessageRecord>(
+            r#"
+            WITH RECURSIVE shards(shard) AS (
+                SELECT MIN(shard) FROM messages WHERE device_id = $1
+                UNION ALL
+                SELECT (SELECT MIN(shard) FROM messages WHERE device_id = $1 AND shard > s.shard)
+                FROM shards s
+                WHERE s.shard IS NOT NULL
+            )
+            SELECT m.id, m.sender_id, m.sender_device_id, m.content, m.created_at, m.payload_size, m.seq, m.pair_seq,
+                   m.system_code
+            FROM shards s
+            CROSS JOIN LATERAL (
+                SELECT id, sender_id, sender_device_id, content, created_at, payload_size, seq, pair_seq, system_code
+                FROM messages
+                WHERE device_id = $1
+                  AND shard = s.shard
+                  AND expires_at > NOW()
+                  AND delivered_at IS NULL
+                  AND seq > $2
+                ORDER BY seq ASC
+                LIMIT $3
+            ) m
+            ORDER BY m.seq ASC
+            LIMIT $3
+            "#,
+        )
+        .bind(device_id)
+        // Legacy messages are numbered below zero, so a fresh fetch starts from the lowest sequence.
+        .bind(cursor.unwrap_or(i64::MIN))
+        .bind(limit)
+        .fetch_all(conn)
+        .await?;
 
         Ok(messages.into_iter().map(Into::into).collect())
     }
@@ -144,7 +545,11 @@ impl MessageRepository {
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the deletion fails.
-    #[tracing::instrument(level = "debug", skip(self, conn))]
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id), count = message_ids.len())
+    )]
     pub(crate) async fn delete_batch(
         &self,
         conn: &mut PgConnection,
@@ -162,47 +567,237 @@ impl MessageRepository {
         Ok(())
     }
 
-    /// Deletes all expired messages.
+    /// Settles a batch of messages a device acknowledged: while an ACK grace period is configured
+    /// they are flagged as delivered and kept for redelivery, otherwise they are deleted.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if the update or deletion fails.
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id), count = message_ids.len())
+    )]
+    pub(crate) async fn acknowledge_batch(
+        &self,
+        conn: &mut PgConnection,
+        device_id: Uuid,
+        message_ids: &[Uuid],
+    ) -> Result<()> {
+        if self.ack_grace_secs == 0 {
+            return self.delete_batch(conn, device_id, message_ids).await;
+        }
+        if message_ids.is_empty() {
+            return Ok(());
+        }
+        sqlx::query(
+            "UPDATE messages SET delivered_at = NOW() WHERE id = ANY($1) AND device_id = $2 AND delivered_at IS NULL",
+        )
+        .bind(message_ids)
+        .bind(device_id)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Puts messages the device acknowledged within the grace period back in its queue. Returns
+    /// how many were found.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if the update fails.
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(count = message_ids.len(), device.id = %telemetry::id(device_id)),
+        err
+    )]
+    pub(crate) async fn redeliver(
+        &self,
+        conn: &mut PgConnection,
+        device_id: Uuid,
+        message_ids: &[Uuid],
+    ) -> Result<u64> {
+        if message_ids.is_empty() {
+            return Ok(0);
+        }
+        let result = sqlx::query(
+            r#"
+            UPDATE messages SET delivered_at = NULL
+            WHERE id = ANY($1)
+              AND device_id = $2
+              AND delivered_at > NOW() - make_interval(secs => $3)
+              AND expires_at > NOW()
+            "#,
+        )
+        .bind(message_ids)
+        .bind(device_id)
+        .bind(self.ack_grace_secs)
+        .execute(conn)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Highest inbox sequence number written to one of the device's sessions, if any was recorded.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if the query fails.
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id)),
+        err
+    )]
+    pub(crate) async fn fetch_delivered_seq(&self, conn: &mut PgConnection, device_id: Uuid) -> Result<Option<i64>> {
+        let seq: Option<Option<i64>> =
+            sqlx::query_scalar("SELECT delivered_seq FROM inbox_sequences WHERE device_id = $1")
+                .bind(device_id)
+                .fetch_optional(conn)
+                .await?;
+        Ok(seq.flatten())
+    }
+
+    /// Raises the device's delivered sequence to `seq`, and the start of the latest session that
+    /// delivered to `session_started_at`. Neither moves backwards, so a session that ends after a
+    /// newer one cannot lower them.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if the update fails.
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id), %seq, ?session_started_at),
+        err
+    )]
+    pub(crate) async fn record_delivered_seq(
+        &self,
+        conn: &mut PgConnection,
+        device_id: Uuid,
+        seq: i64,
+        session_started_at: OffsetDateTime,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE inbox_sequences
+            SET delivered_seq = GREATEST(delivered_seq, $2),
+                delivered_session_at = GREATEST(delivered_session_at, $3)
+            WHERE device_id = $1
+            "#,
+        )
+        .bind(device_id)
+        .bind(seq)
+        .bind(session_started_at)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Deletes acknowledged messages whose grace period has passed.
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the deletion fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
-    pub async fn delete_expired(&self, conn: &mut PgConnection) -> Result<u64> {
-        let result = sqlx::query("DELETE FROM messages WHERE expires_at < NOW()").execute(conn).await?;
+    #[tracing::instrument(level = "debug", skip_all, err)]
+    pub async fn delete_delivered(&self, conn: &mut PgConnection) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM messages WHERE delivered_at < NOW() - make_interval(secs => $1)")
+            .bind(self.ack_grace_secs)
+            .execute(conn)
+            .await?;
         Ok(result.rows_affected())
     }
 
+    /// Deletes all expired messages, counting those that expired before they were acknowledged.
+    /// Like the session counters, a delivered message counts as `websocket` if it was queued after
+    /// the latest session that delivered to its device connected, and as `push` otherwise.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the deletion fails.
+    #[tracing::instrument(level = "debug", skip_all, err)]
+    pub async fn delete_expired(&self, conn: &mut PgConnection) -> Result<ExpiredMessages> {
+        let (total, delivered_push, delivered_websocket, undelivered): (i64, i64, i64, i64) = sqlx::query_as(
+            r#"
+            WITH deleted AS (
+                DELETE FROM messages WHERE expires_at < NOW() RETURNING device_id, seq, created_at, delivered_at
+            )
+            SELECT
+                COUNT(*),
+                COUNT(*) FILTER (
+                    WHERE d.delivered_at IS NULL AND d.seq <= s.delivered_seq
+                    AND NOT COALESCE(d.created_at >= s.delivered_session_at, FALSE)
+                ),
+                COUNT(*) FILTER (
+                    WHERE d.delivered_at IS NULL AND d.seq <= s.delivered_seq
+                    AND d.created_at >= s.delivered_session_at
+                ),
+                COUNT(*) FILTER (WHERE d.delivered_at IS NULL AND (s.delivered_seq IS NULL OR d.seq > s.delivered_seq))
+            FROM deleted d
+            LEFT JOIN inbox_sequences s ON s.device_id = d.device_id
+            "#,
+        )
+        .fetch_one(conn)
+        .await?;
+        Ok(ExpiredMessages {
+            total: u64::try_from(total).unwrap_or(0),
+            unacked_delivered_push: u64::try_from(delivered_push).unwrap_or(0),
+            unacked_delivered_websocket: u64::try_from(delivered_websocket).unwrap_or(0),
+            unacked_undelivered: u64::try_from(undelivered).unwrap_or(0),
+        })
+    }
+
     /// Enforces global inbox limits by pruning the oldest messages per device.
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the deletion fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
     pub async fn delete_global_overflow(&self, conn: &mut PgConnection, limit: i64) -> Result<u64> {
-        // Deletes messages that exceed the 'limit' per device
+        self.delete_tier_overflow(conn, &UserTier::ALL.map(|tier| (tier, limit))).await
+    }
+
+    /// Enforces per-tier inbox limits by pruning the oldest messages of each device beyond the
+    /// limit of its owner's tier. Devices of tiers that are not listed are left alone.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the deletion fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(?limits), err)]
+    pub async fn delete_tier_overflow(&self, conn: &mut PgConnection, limits: &[(UserTier, i64)]) -> Result<u64> {
+        let (tiers, max_sizes): (Vec<&str>, Vec<i64>) = limits.iter().map(|(tier, max)| (tier.as_str(), *max)).unzip();
         let result = sqlx::query(
             r#"
             DELETE FROM messages
             WHERE id IN (
                 SELECT id FROM (
-                    SELECT id, ROW_NUMBER() OVER (PARTITION BY device_id ORDER BY created_at DESC) as rn
-                    FROM messages
-                ) t WHERE t.rn > $1
+                    SELECT m.id, l.max_size,
+                           ROW_NUMBER() OVER (PARTITION BY m.device_id ORDER BY m.created_at DESC) as rn
+                    FROM messages m
+                    JOIN devices d ON d.id = m.device_id
+                    JOIN users u ON u.id = d.user_id
+                    JOIN UNNEST($1::text[], $2::int8[]) AS l(tier, max_size) ON l.tier = u.tier
+                    WHERE m.delivered_at IS NULL
+                ) t WHERE t.rn > t.max_size
             )
             "#,
         )
-        .bind(limit)
+        .bind(tiers)
+        .bind(max_sizes)
         .execute(conn)
         .await?;
         Ok(result.rows_affected())
     }
 
-    /// Deletes all messages for a specific device (Inbox wipe).
+    /// Deletes all messages for a specific device (Inbox wipe). Returns the IDs of the deleted
+    /// messages whose payload is offloaded to object storage.
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the deletion fails.
-    #[tracing::instrument(level = "debug", skip(self, conn))]
-    pub(crate) async fn delete_all_for_device(&self, conn: &mut PgConnection, device_id: Uuid) -> Result<u64> {
-        let result = sqlx::query("DELETE FROM messages WHERE device_id = $1").bind(device_id).execute(conn).await?;
-        Ok(result.rows_affected())
+    #[tracing::instrument(level = "debug", skip_all, fields(device.id = %telemetry::id(device_id)))]
+    pub(crate) async fn delete_all_for_device(&self, conn: &mut PgConnection, device_id: Uuid) -> Result<Vec<Uuid>> {
+        let rows: Vec<(Uuid,)> = sqlx::query_as(
+            r#"
+            WITH deleted AS (
+                DELETE FROM messages WHERE device_id = $1 RETURNING id, payload_size
+            )
+            SELECT id FROM deleted WHERE payload_size IS NOT NULL
+            "#,
+        )
+        .bind(device_id)
+        .fetch_all(conn)
+        .await?;
+        Ok(rows.into_iter().map(|(id,)| id).collect())
     }
 }
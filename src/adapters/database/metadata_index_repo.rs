@@ -0,0 +1,156 @@
+use crate::adapters::database::records::{MessageMetadataRecord, MetadataQueryRecord};
+use crate::domain::metadata_index::{MessageMetadata, MetadataQuery, MetadataQueryAudit};
+use crate::error::Result;
+use sqlx::PgConnection;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+#[derive(Clone, Debug, Default)]
+pub struct MetadataIndexRepository {}
+
+impl MetadataIndexRepository {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {}
+    }
+
+    /// Copies the parties, time and payload size of the given messages into the index.
+    /// Returns how many entries were added.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the insert fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(count = message_ids.len()), err)]
+    pub(crate) async fn record_messages(&self, conn: &mut PgConnection, message_ids: &[Uuid]) -> Result<u64> {
+        if message_ids.is_empty() {
+            return Ok(0);
+        }
+        let result = sqlx::query(
+            r#"
+            INSERT INTO message_metadata
+                (message_id, sender_id, sender_device_id, recipient_id, recipient_device_id, sent_at, size_bytes)
+            SELECT m.id, m.sender_id, m.sender_device_id, d.user_id, m.device_id, m.created_at,
+                   COALESCE(m.payload_size, octet_length(m.content))
+            FROM messages m
+            JOIN devices d ON d.id = m.device_id
+            WHERE m.id = ANY($1)
+            ON CONFLICT (message_id) DO NOTHING
+            "#,
+        )
+        .bind(message_ids)
+        .execute(conn)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Finds up to `limit` entries matching `query`, oldest first.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(?query, %limit), err)]
+    pub(crate) async fn search(
+        &self,
+        conn: &mut PgConnection,
+        query: &MetadataQuery,
+        limit: i64,
+    ) -> Result<Vec<MessageMetadata>> {
+        let rows = sqlx::query_as::<_, MessageMetadataRecord>(
+            r#"
+            SELECT message_id, sender_id, sender_device_id, recipient_id, recipient_device_id, sent_at, size_bytes
+            FROM message_metadata
+            WHERE ($1::uuid IS NULL OR sender_id = $1)
+              AND ($2::uuid IS NULL OR recipient_id = $2)
+              AND ($3::timestamptz IS NULL OR sent_at >= $3)
+              AND ($4::timestamptz IS NULL OR sent_at <= $4)
+            ORDER BY sent_at, message_id
+            LIMIT $5
+            "#,
+        )
+        .bind(query.sender_id)
+        .bind(query.recipient_id)
+        .bind(query.since)
+        .bind(query.until)
+        .bind(limit)
+        .fetch_all(conn)
+        .await?;
+
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    /// Appends a query to the audit log.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the insert fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(%operator, ?client_cert, ?query, %result_count), err)]
+    pub(crate) async fn record_query(
+        &self,
+        conn: &mut PgConnection,
+        operator: &str,
+        reason: &str,
+        client_cert: Option<&str>,
+        query: &MetadataQuery,
+        result_count: i32,
+    ) -> Result<MetadataQueryAudit> {
+        let record = sqlx::query_as::<_, MetadataQueryRecord>(
+            r#"
+            INSERT INTO metadata_index_queries
+                (operator, reason, client_cert, sender_id, recipient_id, since, until, result_count)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            RETURNING id, operator, reason, client_cert, sender_id, recipient_id, since, until, result_count, queried_at
+            "#,
+        )
+        .bind(operator)
+        .bind(reason)
+        .bind(client_cert)
+        .bind(query.sender_id)
+        .bind(query.recipient_id)
+        .bind(query.since)
+        .bind(query.until)
+        .bind(result_count)
+        .fetch_one(conn)
+        .await?;
+
+        Ok(record.into())
+    }
+
+    /// Lists the most recent `limit` audited queries, newest first.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(%limit), err)]
+    pub(crate) async fn list_queries(&self, conn: &mut PgConnection, limit: i64) -> Result<Vec<MetadataQueryAudit>> {
+        let rows = sqlx::query_as::<_, MetadataQueryRecord>(
+            r#"
+            SELECT id, operator, reason, client_cert, sender_id, recipient_id, since, until, result_count, queried_at
+            FROM metadata_index_queries
+            ORDER BY queried_at DESC, id DESC
+            LIMIT $1
+            "#,
+        )
+        .bind(limit)
+        .fetch_all(conn)
+        .await?;
+
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    /// Deletes up to `limit` entries for messages sent before `cutoff`. Returns how many were deleted.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the deletion fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(?cutoff, %limit), err)]
+    pub async fn delete_older_than(&self, conn: &mut PgConnection, cutoff: OffsetDateTime, limit: i64) -> Result<u64> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM message_metadata
+            WHERE message_id IN (
+                SELECT message_id FROM message_metadata WHERE sent_at < $1 LIMIT $2
+            )
+            "#,
+        )
+        .bind(cutoff)
+        .bind(limit)
+        .execute(conn)
+        .await?;
+        Ok(result.rows_affected())
+    }
+}
@@ -1,8 +1,15 @@
+pub mod announcement_repo;
 pub mod attachment_repo;
+pub mod availability;
 pub mod backup_repo;
+pub mod blocklist_repo;
 pub mod device_repo;
+pub mod feature_flag_repo;
+pub mod identifier_repo;
+pub mod instrumentation;
 pub mod key_repo;
 pub mod message_repo;
+pub mod metadata_index_repo;
 pub mod push_token_repo;
 pub mod records;
 pub mod refresh_token_repo;
@@ -29,3 +36,21 @@ pub async fn init_pool(config: &DatabaseConfig) -> Result<DbPool, sqlx::Error> {
         .connect(&config.url)
         .await
 }
+
+/// Initializes the read replica pool, if a replica is configured. The pool connects lazily, so
+/// a replica that is down at startup does not keep the server from starting.
+///
+/// # Errors
+/// Returns `sqlx::Error` if the replica URL is invalid.
+pub fn init_replica_pool(config: &DatabaseConfig) -> Result<Option<DbPool>, sqlx::Error> {
+    let Some(url) = &config.replica_url else {
+        return Ok(None);
+    };
+    PgPoolOptions::new()
+        .max_connections(config.max_connections)
+        .acquire_timeout(Duration::from_secs(config.acquire_timeout_secs))
+        .idle_timeout(Duration::from_secs(config.idle_timeout_secs))
+        .max_lifetime(Duration::from_secs(config.max_lifetime_secs))
+        .connect_lazy(url)
+        .map(Some)
+}
@@ -1,4 +1,5 @@
 use crate::error::Result;
+use crate::telemetry;
 use sqlx::PgConnection;
 use uuid::Uuid;
 
@@ -15,7 +16,12 @@ impl PushTokenRepository {
     ///
     /// # Errors
     /// Returns a database error if the upsert fails.
-    #[tracing::instrument(level = "debug", skip(self, conn, token), err)]
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id)),
+        err
+    )]
     pub async fn upsert_token(&self, conn: &mut PgConnection, device_id: Uuid, token: &str) -> Result<()> {
         sqlx::query(
             r#"
@@ -37,7 +43,7 @@ impl PushTokenRepository {
     ///
     /// # Errors
     /// Returns a database error if the query fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
+    #[tracing::instrument(level = "debug", skip_all, fields(count = device_ids.len()), err)]
     pub(crate) async fn find_tokens_for_devices(
         &self,
         conn: &mut PgConnection,
@@ -56,7 +62,7 @@ impl PushTokenRepository {
     ///
     /// # Errors
     /// Returns a database error if the deletion fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
+    #[tracing::instrument(level = "debug", skip_all, fields(count = tokens.len()), err)]
     pub async fn delete_tokens_batch(&self, conn: &mut PgConnection, tokens: &[String]) -> Result<()> {
         if tokens.is_empty() {
             return Ok(());
@@ -64,4 +70,34 @@ impl PushTokenRepository {
         sqlx::query("DELETE FROM push_tokens WHERE token = ANY($1)").bind(tokens).execute(conn).await?;
         Ok(())
     }
+
+    /// Deletes the push tokens of those `device_ids` that no longer hold an unexpired refresh
+    /// token, so signed-out devices stop receiving wakeups. Returns how many were deleted.
+    ///
+    /// # Errors
+    /// Returns a database error if the deletion fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(count = device_ids.len()), err)]
+    pub(crate) async fn delete_for_signed_out_devices(
+        &self,
+        conn: &mut PgConnection,
+        device_ids: &[Uuid],
+    ) -> Result<u64> {
+        if device_ids.is_empty() {
+            return Ok(0);
+        }
+        let result = sqlx::query(
+            r#"
+            DELETE FROM push_tokens p
+            WHERE p.device_id = ANY($1)
+            AND NOT EXISTS (
+                SELECT 1 FROM refresh_tokens r
+                WHERE r.device_id = p.device_id AND r.expires_at > NOW()
+            )
+            "#,
+        )
+        .bind(device_ids)
+        .execute(conn)
+        .await?;
+        Ok(result.rows_affected())
+    }
 }
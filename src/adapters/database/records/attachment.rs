@@ -6,10 +6,15 @@ use uuid::Uuid;
 pub struct AttachmentRecord {
     pub(crate) id: Uuid,
     pub(crate) expires_at: OffsetDateTime,
+    pub(crate) content_digest: Option<Vec<u8>>,
 }
 
 impl From<AttachmentRecord> for Attachment {
     fn from(record: AttachmentRecord) -> Self {
-        Self { id: record.id, expires_at: record.expires_at }
+        Self {
+            id: record.id,
+            expires_at: record.expires_at,
+            content_digest: record.content_digest.and_then(|digest| <[u8; 32]>::try_from(digest).ok()),
+        }
     }
 }
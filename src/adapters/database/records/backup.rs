@@ -12,6 +12,9 @@ pub struct BackupRecord {
     pub(crate) state: String,
     pub(crate) updated_at: OffsetDateTime,
     pub(crate) pending_at: Option<OffsetDateTime>,
+    pub(crate) previous_version: Option<i32>,
+    pub(crate) deleted_at: Option<OffsetDateTime>,
+    pub(crate) size_bytes: Option<i64>,
 }
 
 impl From<BackupRecord> for Backup {
@@ -23,6 +26,9 @@ impl From<BackupRecord> for Backup {
             state: BackupState::from_str(&record.state).unwrap_or(BackupState::Active),
             updated_at: record.updated_at,
             pending_at: record.pending_at,
+            previous_version: record.previous_version,
+            deleted_at: record.deleted_at,
+            size_bytes: record.size_bytes,
         }
     }
 }
@@ -0,0 +1,23 @@
+use crate::domain::blocklist::{BlockedNetwork, BlocklistSource, parse_network};
+use sqlx::FromRow;
+use time::OffsetDateTime;
+
+#[derive(Debug, FromRow)]
+pub struct BlockedNetworkRecord {
+    pub(crate) network: String,
+    pub(crate) reason: Option<String>,
+    pub(crate) created_at: OffsetDateTime,
+}
+
+impl TryFrom<BlockedNetworkRecord> for BlockedNetwork {
+    type Error = String;
+
+    fn try_from(record: BlockedNetworkRecord) -> Result<Self, Self::Error> {
+        Ok(Self {
+            network: parse_network(&record.network)?,
+            reason: record.reason,
+            source: BlocklistSource::Managed,
+            created_at: Some(record.created_at),
+        })
+    }
+}
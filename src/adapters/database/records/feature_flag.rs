@@ -0,0 +1,27 @@
+use crate::domain::feature_flag::FeatureFlag;
+use sqlx::FromRow;
+use time::OffsetDateTime;
+
+#[derive(Debug, FromRow)]
+pub struct FeatureFlagRecord {
+    pub(crate) name: String,
+    pub(crate) enabled: bool,
+    pub(crate) rollout_percent: i16,
+    pub(crate) description: Option<String>,
+    pub(crate) updated_at: OffsetDateTime,
+}
+
+impl TryFrom<FeatureFlagRecord> for FeatureFlag {
+    type Error = String;
+
+    fn try_from(record: FeatureFlagRecord) -> Result<Self, Self::Error> {
+        Ok(Self {
+            rollout_percent: u8::try_from(record.rollout_percent)
+                .map_err(|_| format!("Invalid rollout percent for flag {}", record.name))?,
+            name: record.name,
+            enabled: record.enabled,
+            description: record.description,
+            updated_at: record.updated_at,
+        })
+    }
+}
@@ -0,0 +1,33 @@
+use crate::domain::identifier::{Identifier, IdentifierKind};
+use sqlx::FromRow;
+use time::OffsetDateTime;
+
+#[derive(Debug, FromRow)]
+pub struct IdentifierRecord {
+    pub(crate) kind: String,
+    pub(crate) discoverable: bool,
+    pub(crate) verified_at: Option<OffsetDateTime>,
+    pub(crate) created_at: OffsetDateTime,
+}
+
+impl TryFrom<IdentifierRecord> for Identifier {
+    type Error = String;
+    fn try_from(record: IdentifierRecord) -> Result<Self, Self::Error> {
+        Ok(Self {
+            kind: record.kind.parse::<IdentifierKind>()?,
+            discoverable: record.discoverable,
+            verified_at: record.verified_at,
+            created_at: record.created_at,
+        })
+    }
+}
+
+/// A verification code waiting to be confirmed.
+#[derive(Debug, FromRow)]
+pub struct PendingVerificationRecord {
+    pub(crate) identifier_hash: Vec<u8>,
+    pub(crate) code_salt: Vec<u8>,
+    pub(crate) code_hash: Vec<u8>,
+    pub(crate) code_expires_at: OffsetDateTime,
+    pub(crate) attempts: i32,
+}
@@ -1,14 +1,18 @@
-use crate::domain::message::Message;
+use crate::domain::message::{Message, SubmissionReceipt, SystemCode};
 use time::OffsetDateTime;
 use uuid::Uuid;
 
 #[derive(Debug, sqlx::FromRow)]
 pub struct MessageRecord {
     pub(crate) id: Uuid,
-    pub(crate) sender_id: Uuid,
-    pub(crate) sender_device_id: Uuid,
+    pub(crate) sender_id: Option<Uuid>,
+    pub(crate) sender_device_id: Option<Uuid>,
     pub(crate) content: Vec<u8>,
     pub(crate) created_at: Option<OffsetDateTime>,
+    pub(crate) payload_size: Option<i32>,
+    pub(crate) seq: i64,
+    pub(crate) pair_seq: Option<i64>,
+    pub(crate) system_code: Option<i16>,
 }
 
 impl From<MessageRecord> for Message {
@@ -19,6 +23,31 @@ impl From<MessageRecord> for Message {
             sender_device_id: record.sender_device_id,
             content: record.content,
             created_at: record.created_at,
+            offloaded: record.payload_size.is_some(),
+            seq: record.seq,
+            pair_seq: record.pair_seq,
+            system_code: record.system_code.and_then(|code| SystemCode::try_from(code).ok()),
+        }
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct ReceiptRecord {
+    pub(crate) id: Uuid,
+    pub(crate) device_id: Uuid,
+    pub(crate) submission_id: Uuid,
+    pub(crate) created_at: Option<OffsetDateTime>,
+    pub(crate) seq: Option<i64>,
+}
+
+impl From<ReceiptRecord> for SubmissionReceipt {
+    fn from(record: ReceiptRecord) -> Self {
+        Self {
+            submission_id: record.submission_id,
+            message_id: record.id,
+            device_id: record.device_id,
+            timestamp: record.created_at.unwrap_or_else(OffsetDateTime::now_utc),
+            sequence: record.seq.unwrap_or(0),
         }
     }
 }
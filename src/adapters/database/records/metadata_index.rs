@@ -0,0 +1,62 @@
+use crate::domain::metadata_index::{MessageMetadata, MetadataQuery, MetadataQueryAudit};
+use sqlx::FromRow;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+#[derive(Debug, FromRow)]
+pub struct MessageMetadataRecord {
+    pub(crate) message_id: Uuid,
+    pub(crate) sender_id: Uuid,
+    pub(crate) sender_device_id: Uuid,
+    pub(crate) recipient_id: Uuid,
+    pub(crate) recipient_device_id: Uuid,
+    pub(crate) sent_at: OffsetDateTime,
+    pub(crate) size_bytes: i32,
+}
+
+impl From<MessageMetadataRecord> for MessageMetadata {
+    fn from(record: MessageMetadataRecord) -> Self {
+        Self {
+            message_id: record.message_id,
+            sender_id: record.sender_id,
+            sender_device_id: record.sender_device_id,
+            recipient_id: record.recipient_id,
+            recipient_device_id: record.recipient_device_id,
+            sent_at: record.sent_at,
+            size_bytes: record.size_bytes,
+        }
+    }
+}
+
+#[derive(Debug, FromRow)]
+pub struct MetadataQueryRecord {
+    pub(crate) id: Uuid,
+    pub(crate) operator: String,
+    pub(crate) reason: String,
+    pub(crate) client_cert: Option<String>,
+    pub(crate) sender_id: Option<Uuid>,
+    pub(crate) recipient_id: Option<Uuid>,
+    pub(crate) since: Option<OffsetDateTime>,
+    pub(crate) until: Option<OffsetDateTime>,
+    pub(crate) result_count: i32,
+    pub(crate) queried_at: OffsetDateTime,
+}
+
+impl From<MetadataQueryRecord> for MetadataQueryAudit {
+    fn from(record: MetadataQueryRecord) -> Self {
+        Self {
+            id: record.id,
+            operator: record.operator,
+            reason: record.reason,
+            client_cert: record.client_cert,
+            query: MetadataQuery {
+                sender_id: record.sender_id,
+                recipient_id: record.recipient_id,
+                since: record.since,
+                until: record.until,
+            },
+            result_count: record.result_count,
+            queried_at: record.queried_at,
+        }
+    }
+}
@@ -1,13 +1,21 @@
 pub mod attachment;
 pub mod backup;
+pub mod blocklist;
 pub mod device;
+pub mod feature_flag;
+pub mod identifier;
 pub mod keys;
 pub mod message;
+pub mod metadata_index;
 pub mod user;
 
 pub use attachment::AttachmentRecord;
 pub use backup::BackupRecord;
+pub use blocklist::BlockedNetworkRecord;
 pub use device::DeviceRecord;
+pub use feature_flag::FeatureFlagRecord;
+pub use identifier::{IdentifierRecord, PendingVerificationRecord};
 pub use keys::{ConsumedPreKeyRecord, IdentityKeyRecord, SignedPreKeyRecord};
-pub use message::MessageRecord;
+pub use message::{MessageRecord, ReceiptRecord};
+pub use metadata_index::{MessageMetadataRecord, MetadataQueryRecord};
 pub use user::UserRecord;
@@ -1,4 +1,4 @@
-use crate::domain::user::User;
+use crate::domain::user::{User, UserTier};
 use time::OffsetDateTime;
 use uuid::Uuid;
 
@@ -7,6 +7,7 @@ pub struct UserRecord {
     pub(crate) id: Uuid,
     pub(crate) username: String,
     pub(crate) password_hash: String,
+    pub(crate) tier: String,
     pub(crate) created_at: Option<OffsetDateTime>,
 }
 
@@ -16,6 +17,7 @@ impl From<UserRecord> for User {
             id: record.id,
             username: record.username,
             password_hash: record.password_hash,
+            tier: record.tier.parse().unwrap_or(UserTier::Free),
             created_at: record.created_at,
         }
     }
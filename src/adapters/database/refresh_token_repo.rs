@@ -1,4 +1,5 @@
 use crate::error::{AppError, Result};
+use crate::telemetry;
 use sqlx::PgConnection;
 use time::OffsetDateTime;
 use uuid::Uuid;
@@ -17,7 +18,16 @@ impl RefreshTokenRepository {
     ///
     /// # Errors
     /// Returns `AppError::Database` if the insert fails.
-    #[tracing::instrument(level = "debug", skip(self, conn, token_hash), err)]
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(
+            user.id = %telemetry::id(user_id),
+            device.id = device_id.map(telemetry::id).map(tracing::field::display),
+            %ttl_days
+        ),
+        err
+    )]
     pub(crate) async fn create(
         &self,
         conn: &mut PgConnection,
@@ -46,7 +56,7 @@ impl RefreshTokenRepository {
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the operation fails.
-    #[tracing::instrument(level = "debug", skip(self, conn, old_hash, new_hash), err)]
+    #[tracing::instrument(level = "debug", skip_all, fields(%ttl_days), err)]
     pub(crate) async fn rotate_unexpired(
         &self,
         conn: &mut PgConnection,
@@ -80,30 +90,53 @@ impl RefreshTokenRepository {
     }
 
     /// Revokes a specific refresh token owned by the user (Logout).
+    /// Returns the device the token was bound to, or None if it was user-only or did not exist.
     ///
     /// # Errors
     /// Returns `AppError::Database` if the deletion fails.
-    #[tracing::instrument(level = "debug", skip(self, conn, token_hash), err)]
-    pub(crate) async fn delete_owned(&self, conn: &mut PgConnection, token_hash: &str, user_id: Uuid) -> Result<()> {
-        sqlx::query("DELETE FROM refresh_tokens WHERE token_hash = $1 AND user_id = $2")
-            .bind(token_hash)
-            .bind(user_id)
-            .execute(conn)
-            .await
-            .map_err(AppError::Database)?;
-        Ok(())
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(user.id = %telemetry::id(user_id)),
+        err
+    )]
+    pub(crate) async fn delete_owned(
+        &self,
+        conn: &mut PgConnection,
+        token_hash: &str,
+        user_id: Uuid,
+    ) -> Result<Option<Uuid>> {
+        let device_id = sqlx::query_scalar::<_, Option<Uuid>>(
+            "DELETE FROM refresh_tokens WHERE token_hash = $1 AND user_id = $2 RETURNING device_id",
+        )
+        .bind(token_hash)
+        .bind(user_id)
+        .fetch_optional(conn)
+        .await
+        .map_err(AppError::Database)?;
+        Ok(device_id.flatten())
     }
 
-    /// Deletes all expired refresh tokens.
+    /// Deletes up to `limit` expired refresh tokens. Returns the devices the deleted tokens were
+    /// bound to, one entry per token (None for user-only tokens), so its length is the count.
     ///
     /// # Errors
     /// Returns `AppError::Database` if the deletion fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
-    pub async fn delete_expired(&self, conn: &mut PgConnection) -> Result<u64> {
-        let result = sqlx::query("DELETE FROM refresh_tokens WHERE expires_at < NOW()")
-            .execute(conn)
-            .await
-            .map_err(AppError::Database)?;
-        Ok(result.rows_affected())
+    #[tracing::instrument(level = "debug", skip_all, fields(%limit), err)]
+    pub async fn delete_expired(&self, conn: &mut PgConnection, limit: i64) -> Result<Vec<Option<Uuid>>> {
+        let devices = sqlx::query_scalar::<_, Option<Uuid>>(
+            r#"
+            DELETE FROM refresh_tokens
+            WHERE token_hash IN (
+                SELECT token_hash FROM refresh_tokens WHERE expires_at < NOW() LIMIT $1
+            )
+            RETURNING device_id
+            "#,
+        )
+        .bind(limit)
+        .fetch_all(conn)
+        .await
+        .map_err(AppError::Database)?;
+        Ok(devices)
     }
 }
@@ -1,7 +1,10 @@
 use crate::adapters::database::records::UserRecord;
-use crate::domain::user::User;
+use crate::domain::user::{User, UserTier};
 use crate::error::{AppError, Result};
+use crate::telemetry;
 use sqlx::PgConnection;
+use time::OffsetDateTime;
+use uuid::Uuid;
 
 #[derive(Clone, Debug, Default)]
 pub struct UserRepository {}
@@ -17,13 +20,13 @@ impl UserRepository {
     /// # Errors
     /// Returns `AppError::Conflict` if the username already exists.
     /// Returns `AppError::Database` for other database failures.
-    #[tracing::instrument(level = "debug", skip(self, conn, password_hash), err)]
+    #[tracing::instrument(level = "debug", skip_all, fields(%username), err)]
     pub(crate) async fn create(&self, conn: &mut PgConnection, username: &str, password_hash: &str) -> Result<User> {
         let user = sqlx::query_as::<_, UserRecord>(
             r#"
             INSERT INTO users (username, password_hash)
             VALUES ($1, $2)
-            RETURNING id, username, password_hash, created_at
+            RETURNING id, username, password_hash, tier, created_at
             "#,
         )
         .bind(username)
@@ -46,11 +49,11 @@ impl UserRepository {
     ///
     /// # Errors
     /// Returns `sqlx::Error` if the query fails.
-    #[tracing::instrument(level = "debug", skip(self, conn), err)]
+    #[tracing::instrument(level = "debug", skip_all, fields(%username), err)]
     pub(crate) async fn find_by_username(&self, conn: &mut PgConnection, username: &str) -> Result<Option<User>> {
         let user = sqlx::query_as::<_, UserRecord>(
             r#"
-            SELECT id, username, password_hash, created_at
+            SELECT id, username, password_hash, tier, created_at
             FROM users
             WHERE username = $1
             "#,
@@ -61,4 +64,221 @@ impl UserRepository {
 
         Ok(user.map(Into::into))
     }
+
+    /// Returns a user's tier, or `None` if the user does not exist.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(user.id = %telemetry::id(user_id)), err)]
+    pub(crate) async fn find_tier(&self, conn: &mut PgConnection, user_id: Uuid) -> Result<Option<UserTier>> {
+        let tier: Option<String> =
+            sqlx::query_scalar("SELECT tier FROM users WHERE id = $1").bind(user_id).fetch_optional(conn).await?;
+        Ok(tier.map(|t| t.parse().unwrap_or(UserTier::Free)))
+    }
+
+    /// Moves a user to another tier. Returns `false` if the user does not exist.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the update fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(user.id = %telemetry::id(user_id), ?tier), err)]
+    pub(crate) async fn set_tier(&self, conn: &mut PgConnection, user_id: Uuid, tier: UserTier) -> Result<bool> {
+        let result = sqlx::query("UPDATE users SET tier = $2 WHERE id = $1")
+            .bind(user_id)
+            .bind(tier.as_str())
+            .execute(conn)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Lists users in registration order, starting after the `after` cursor.
+    ///
+    /// User ids are time-ordered, so the primary key doubles as a stable keyset cursor.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(%limit), err)]
+    pub(crate) async fn list_after(
+        &self,
+        conn: &mut PgConnection,
+        after: Option<Uuid>,
+        limit: i64,
+    ) -> Result<Vec<User>> {
+        let users = sqlx::query_as::<_, UserRecord>(
+            r#"
+            SELECT id, username, password_hash, tier, created_at
+            FROM users
+            WHERE $1::UUID IS NULL OR id > $1
+            ORDER BY id ASC
+            LIMIT $2
+            "#,
+        )
+        .bind(after)
+        .bind(limit)
+        .fetch_all(conn)
+        .await?;
+
+        Ok(users.into_iter().map(Into::into).collect())
+    }
+
+    /// Lists users newest registration first, starting before the `before` cursor.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(%limit), err)]
+    pub(crate) async fn list_before(
+        &self,
+        conn: &mut PgConnection,
+        before: Option<Uuid>,
+        limit: i64,
+    ) -> Result<Vec<User>> {
+        let users = sqlx::query_as::<_, UserRecord>(
+            r#"
+            SELECT id, username, password_hash, tier, created_at
+            FROM users
+            WHERE $1::UUID IS NULL OR id < $1
+            ORDER BY id DESC
+            LIMIT $2
+            "#,
+        )
+        .bind(before)
+        .bind(limit)
+        .fetch_all(conn)
+        .await?;
+
+        Ok(users.into_iter().map(Into::into).collect())
+    }
+
+    /// Deletes a user. Devices, keys, sessions and queued messages are removed by cascade.
+    /// Returns the deleted user, or `None` if it did not exist.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(user.id = %telemetry::id(user_id)), err)]
+    pub(crate) async fn delete(&self, conn: &mut PgConnection, user_id: Uuid) -> Result<Option<User>> {
+        let user = sqlx::query_as::<_, UserRecord>(
+            r#"
+            DELETE FROM users
+            WHERE id = $1
+            RETURNING id, username, password_hash, tier, created_at
+            "#,
+        )
+        .bind(user_id)
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(user.map(Into::into))
+    }
+
+    /// Records that an account was deleted, reserving its username until `reusable_at`.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(user.id = %telemetry::id(user_id), ?reusable_at),
+        err
+    )]
+    pub(crate) async fn create_tombstone(
+        &self,
+        conn: &mut PgConnection,
+        user_id: Uuid,
+        username: &str,
+        reusable_at: OffsetDateTime,
+    ) -> Result<()> {
+        sqlx::query("INSERT INTO account_tombstones (user_id, username, reusable_at) VALUES ($1, $2, $3)")
+            .bind(user_id)
+            .bind(username)
+            .bind(reusable_at)
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Records which sender devices were told that a deleted account will not receive
+    /// their pending messages, and how many submissions each of them lost.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the insert fails.
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(count = notices.len(), user.id = %telemetry::id(user_id)),
+        err
+    )]
+    pub(crate) async fn create_tombstone_notices(
+        &self,
+        conn: &mut PgConnection,
+        user_id: Uuid,
+        notices: &[(Uuid, usize)],
+    ) -> Result<()> {
+        if notices.is_empty() {
+            return Ok(());
+        }
+        let (sender_device_ids, undelivered): (Vec<Uuid>, Vec<i32>) =
+            notices.iter().map(|(device_id, count)| (*device_id, i32::try_from(*count).unwrap_or(i32::MAX))).unzip();
+
+        sqlx::query(
+            r#"
+            INSERT INTO account_tombstone_notices (user_id, sender_device_id, undelivered)
+            SELECT $1, * FROM UNNEST($2::uuid[], $3::int4[])
+            "#,
+        )
+        .bind(user_id)
+        .bind(sender_device_ids)
+        .bind(undelivered)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns `true` if a deleted account still holds `username` within its grace period.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(%username), err)]
+    pub(crate) async fn is_username_reserved(&self, conn: &mut PgConnection, username: &str) -> Result<bool> {
+        let reserved: bool = sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM account_tombstones WHERE username = $1 AND reusable_at > NOW())",
+        )
+        .bind(username)
+        .fetch_one(conn)
+        .await?;
+        Ok(reserved)
+    }
+
+    /// Finds a user whose username has the given confusable skeleton, holding a transaction-level
+    /// lock on the skeleton so that two confusable usernames cannot be registered concurrently.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(%skeleton), err)]
+    pub(crate) async fn lock_and_find_by_skeleton(
+        &self,
+        conn: &mut PgConnection,
+        skeleton: &str,
+    ) -> Result<Option<String>> {
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext('username_skeleton:' || $1))")
+            .bind(skeleton)
+            .execute(&mut *conn)
+            .await?;
+        let username = sqlx::query_scalar("SELECT username FROM users WHERE username_skeleton = $1 LIMIT 1")
+            .bind(skeleton)
+            .fetch_optional(conn)
+            .await?;
+        Ok(username)
+    }
+
+    /// Returns `true` if `user_id` belonged to an account that has been deleted.
+    ///
+    /// # Errors
+    /// Returns `sqlx::Error` if the query fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(user.id = %telemetry::id(user_id)), err)]
+    pub(crate) async fn is_deleted(&self, conn: &mut PgConnection, user_id: Uuid) -> Result<bool> {
+        let deleted: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM account_tombstones WHERE user_id = $1)")
+            .bind(user_id)
+            .fetch_one(conn)
+            .await?;
+        Ok(deleted)
+    }
 }
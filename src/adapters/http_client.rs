@@ -0,0 +1,294 @@
+use crate::config::OutboundConfig;
+use anyhow::{Context, bail};
+use aws_sdk_s3::config::SharedHttpClient;
+use aws_smithy_runtime_api::client::http::{
+    HttpClient, HttpConnector, HttpConnectorFuture, HttpConnectorSettings, SharedHttpConnector,
+};
+use aws_smithy_runtime_api::client::orchestrator::{HttpRequest, HttpResponse};
+use aws_smithy_runtime_api::client::result::ConnectorError;
+use aws_smithy_runtime_api::client::runtime_components::RuntimeComponents;
+use aws_smithy_types::body::SdkBody;
+use base64::Engine;
+use rustls::client::WebPkiServerVerifier;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{CertificateError, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+/// Builds the HTTP client used for every outbound call the server makes.
+///
+/// Adapters must take their client from here rather than constructing one, so that
+/// operator policy (proxy, trusted roots and key pins) applies uniformly.
+///
+/// # Errors
+/// Returns an error if the proxy URL or a pin is malformed, the CA file cannot be read,
+/// or the client cannot be built.
+pub fn build_client(config: &OutboundConfig) -> anyhow::Result<reqwest::Client> {
+    client_builder(config)?.build().context("Failed to build outbound HTTP client")
+}
+
+/// A client builder with the outbound policy of [`build_client`] applied, for callers that need
+/// further settings such as a timeout.
+///
+/// # Errors
+/// Returns an error if the proxy URL or a pin is malformed, or the CA file cannot be read.
+pub fn client_builder(config: &OutboundConfig) -> anyhow::Result<reqwest::ClientBuilder> {
+    let mut builder = reqwest::Client::builder();
+
+    if let Some(url) = config.proxy_url() {
+        let proxy = reqwest::Proxy::all(url)
+            .with_context(|| format!("Invalid outbound proxy URL {url:?}"))?
+            .no_proxy(reqwest::NoProxy::from_string(&config.no_proxy.join(",")));
+        builder = builder.proxy(proxy);
+    }
+
+    if config.pinned_spki.is_empty() && config.ca_file.is_none() {
+        return Ok(builder);
+    }
+
+    let pins = parse_pins(&config.pinned_spki)?;
+    let roots = root_store(config.ca_file.as_deref())?;
+    let provider = tls_provider();
+    let inner = WebPkiServerVerifier::builder_with_provider(Arc::new(roots), Arc::clone(&provider))
+        .build()
+        .context("Failed to build outbound certificate verifier")?;
+
+    let tls = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .context("Outbound TLS provider supports no protocol version")?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(PinnedVerifier { inner, pins }))
+        .with_no_client_auth();
+
+    Ok(builder.tls_backend_preconfigured(tls))
+}
+
+/// The crypto provider for every TLS configuration the server builds itself. rustls is compiled
+/// with both `ring` and `aws-lc-rs` here, so it has no process default to fall back on and its
+/// plain `builder()`s panic.
+pub(crate) fn tls_provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::aws_lc_rs::default_provider())
+}
+
+/// Builds the HTTP client for the S3 SDK when outbound settings require one.
+///
+/// Returns `None` to keep the SDK's default client. Otherwise storage requests are sent through
+/// a client from [`build_client`], so the proxy, trusted roots and key pins apply to them as to
+/// every other outbound call.
+///
+/// # Errors
+/// Returns an error if the outbound client cannot be built.
+pub fn build_s3_http_client(config: &OutboundConfig) -> anyhow::Result<Option<SharedHttpClient>> {
+    if config.proxy_url().is_none() && config.pinned_spki.is_empty() && config.ca_file.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(SharedHttpClient::new(SdkHttpClient(build_client(config)?))))
+}
+
+/// Adapts an outbound client to the S3 SDK. The SDK's connect and read timeouts are not applied;
+/// storage calls are bounded by the storage timeouts instead.
+#[derive(Debug, Clone)]
+struct SdkHttpClient(reqwest::Client);
+
+impl HttpClient for SdkHttpClient {
+    fn http_connector(
+        &self,
+        _settings: &HttpConnectorSettings,
+        _components: &RuntimeComponents,
+    ) -> SharedHttpConnector {
+        SharedHttpConnector::new(self.clone())
+    }
+}
+
+impl HttpConnector for SdkHttpClient {
+    fn call(&self, request: HttpRequest) -> HttpConnectorFuture {
+        let client = self.0.clone();
+        HttpConnectorFuture::new(async move {
+            let request = request.try_into_http1x().map_err(|e| ConnectorError::user(e.into()))?;
+            let request = reqwest::Request::try_from(request.map(reqwest::Body::wrap))
+                .map_err(|e| ConnectorError::user(e.into()))?;
+            let response = client.execute(request).await.map_err(|e| {
+                if e.is_timeout() {
+                    ConnectorError::timeout(e.into())
+                } else if e.is_connect() {
+                    ConnectorError::io(e.into())
+                } else {
+                    ConnectorError::other(e.into(), None)
+                }
+            })?;
+            let response = axum::http::Response::from(response).map(SdkBody::from_body_1_x);
+            HttpResponse::try_from(response).map_err(|e| ConnectorError::other(e.into(), None))
+        })
+    }
+}
+
+fn parse_pins(pins: &[String]) -> anyhow::Result<Vec<[u8; 32]>> {
+    pins.iter()
+        .map(|pin| {
+            let bytes = base64::engine::general_purpose::STANDARD
+                .decode(pin.trim())
+                .with_context(|| format!("Invalid SPKI pin {pin:?}: not base64"))?;
+            <[u8; 32]>::try_from(bytes)
+                .map_err(|_| anyhow::anyhow!("Invalid SPKI pin {pin:?}: expected a SHA-256 hash"))
+        })
+        .collect()
+}
+
+/// The operator's CA file if one is set, otherwise the bundled WebPKI roots.
+pub(crate) fn root_store(ca_file: Option<&str>) -> anyhow::Result<RootCertStore> {
+    ca_file.map_or_else(|| Ok(RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() }), load_ca_file)
+}
+
+/// Reads a PEM file of CA certificates into a root store.
+///
+/// # Errors
+/// Returns an error if the file cannot be read or holds no valid certificate.
+pub(crate) fn load_ca_file(path: &str) -> anyhow::Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_file_iter(path).with_context(|| format!("Failed to read CA file {path}"))? {
+        roots.add(cert.with_context(|| format!("Invalid certificate in {path}"))?)?;
+    }
+    if roots.is_empty() {
+        bail!("CA file {path} contains no certificates");
+    }
+    Ok(roots)
+}
+
+/// Standard WebPKI validation, followed by a check that some certificate in the presented
+/// chain carries one of the pinned public keys.
+#[derive(Debug)]
+struct PinnedVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    pins: Vec<[u8; 32]>,
+}
+
+impl PinnedVerifier {
+    fn is_pinned(&self, cert: &CertificateDer<'_>) -> bool {
+        subject_public_key_info(cert).is_some_and(|spki| {
+            let hash = Sha256::digest(spki);
+            self.pins.iter().any(|pin| pin.as_slice() == hash.as_slice())
+        })
+    }
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)?;
+
+        if self.pins.is_empty() || std::iter::once(end_entity).chain(intermediates).any(|cert| self.is_pinned(cert)) {
+            return Ok(verified);
+        }
+
+        tracing::error!(server = ?server_name, "Outbound TLS peer does not match any pinned public key");
+        Err(rustls::Error::InvalidCertificate(CertificateError::ApplicationVerificationFailure))
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// Extracts the DER-encoded `SubjectPublicKeyInfo` of an X.509 certificate, the input to an SPKI pin.
+fn subject_public_key_info(cert: &[u8]) -> Option<&[u8]> {
+    let (_, parsed) = x509_parser::parse_x509_certificate(cert).ok()?;
+    Some(parsed.public_key().raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_CERT_PEM: &str = "\
+MIIBfjCCASOgAwIBAgIUUmks8s3LME/ypnYiTkpb4QbsAoQwCgYIKoZIzj0EAwIw
+EzERMA8GA1UEAwwIcGluLnRlc3QwIBcNMjYxMDE2MTM1ODAzWhgPMjEyNjA5MjIx
+MzU4MDNaMBMxETAPBgNVBAMMCHBpbi50ZXN0MFkwEwYHKoZIzj0CAQYIKoZIzj0D
+AQcDQgAEMuF+EZT7J5bNbsAliOq0Et0D4gcU2O6+N5A9nVmgPe/eS4mwcQDDQOSr
+9QmUk1mV4/0P5SMMD59cCJcb26PQoaNTMFEwHQYDVR0OBBYEFJwZq+Lvwn0MSF+c
+DD6WQcL5Axd9MB8GA1UdIwQYMBaAFJwZq+Lvwn0MSF+cDD6WQcL5Axd9MA8GA1Ud
+EwEB/wQFMAMBAf8wCgYIKoZIzj0EAwIDSQAwRgIhAJFg5IVsALCOBS2QjNVlNpD4
+RN4FwZHh41kBPNDsWLTFAiEAlUzSSkygc/LpJE7ouHRMTSivlxEWa8UwA22aoakB
+AoY=";
+
+    // openssl x509 -pubkey -noout | openssl pkey -pubin -outform der | openssl dgst -sha256 -binary | base64
+    const TEST_CERT_PIN: &str = "WNTJCR9nil9dgiD+ouslExycrFXCWbcie36D5fwz/9w=";
+
+    fn test_cert() -> Vec<u8> {
+        base64::engine::general_purpose::STANDARD.decode(TEST_CERT_PEM.replace('\n', "")).expect("valid base64")
+    }
+
+    #[test]
+    fn test_spki_hash_matches_openssl() {
+        let cert = test_cert();
+        let spki = subject_public_key_info(&cert).expect("certificate has an SPKI");
+        let pin = base64::engine::general_purpose::STANDARD.encode(Sha256::digest(spki));
+        assert_eq!(pin, TEST_CERT_PIN);
+    }
+
+    #[test]
+    fn test_truncated_certificate_is_rejected() {
+        let cert = test_cert();
+        assert!(subject_public_key_info(&cert[..cert.len() / 2]).is_none());
+        assert!(subject_public_key_info(&[]).is_none());
+    }
+
+    #[test]
+    fn test_build_client_with_pins_and_ca_file() {
+        let ca_file = std::env::temp_dir().join(format!("obscura-test-ca-{}.pem", uuid::Uuid::new_v4()));
+        std::fs::write(&ca_file, format!("-----BEGIN CERTIFICATE-----\n{TEST_CERT_PEM}\n-----END CERTIFICATE-----\n"))
+            .expect("write CA file");
+
+        let config = OutboundConfig {
+            pinned_spki: vec![TEST_CERT_PIN.to_string()],
+            ca_file: Some(ca_file.to_string_lossy().into_owned()),
+            ..OutboundConfig::default()
+        };
+        let result = build_client(&config);
+        let _ = std::fs::remove_file(&ca_file);
+        result.expect("client builds");
+
+        build_client(&OutboundConfig { pinned_spki: vec![TEST_CERT_PIN.to_string()], ..OutboundConfig::default() })
+            .expect("client builds with the bundled roots");
+    }
+
+    #[test]
+    fn test_s3_client_follows_outbound_policy() {
+        assert!(build_s3_http_client(&OutboundConfig::default()).expect("no client needed").is_none());
+        let pinned = OutboundConfig { pinned_spki: vec![TEST_CERT_PIN.to_string()], ..OutboundConfig::default() };
+        assert!(build_s3_http_client(&pinned).expect("client builds").is_some());
+    }
+
+    #[test]
+    fn test_parse_pins() {
+        assert_eq!(parse_pins(&[TEST_CERT_PIN.to_string()]).expect("valid pin").len(), 1);
+        assert!(parse_pins(&["not base64!".to_string()]).is_err());
+        assert!(parse_pins(&["AAAA".to_string()]).is_err());
+    }
+}
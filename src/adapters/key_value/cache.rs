@@ -0,0 +1,70 @@
+use crate::adapters::key_value::KeyValueStore;
+use crate::adapters::submission_cache::SubmissionStore;
+use async_trait::async_trait;
+use std::sync::Arc;
+
+#[derive(Debug, Clone)]
+pub struct KeyValueCache {
+    store: Arc<dyn KeyValueStore>,
+    prefix: String,
+    ttl_secs: u64,
+}
+
+impl KeyValueCache {
+    #[must_use]
+    pub fn new(store: Arc<dyn KeyValueStore>, prefix: String, ttl_secs: u64) -> Self {
+        Self { store, prefix, ttl_secs }
+    }
+
+    /// Retrieves a cached response for a key.
+    ///
+    /// # Errors
+    /// Returns an error if the store operation fails.
+    pub async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        self.store.get(&format!("{}{key}", self.prefix)).await
+    }
+
+    /// Saves a response for a key with the cache's configured TTL.
+    ///
+    /// # Errors
+    /// Returns an error if the store operation fails.
+    pub async fn set(&self, key: &str, value: &[u8]) -> anyhow::Result<()> {
+        self.store.set(&format!("{}{key}", self.prefix), value, self.ttl_secs).await
+    }
+
+    /// Deletes a key from the cache.
+    ///
+    /// # Errors
+    /// Returns an error if the store operation fails.
+    pub async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        self.store.delete(&format!("{}{key}", self.prefix)).await
+    }
+
+    /// Gives every key under the cache's prefix that has no expiry the configured TTL, and
+    /// returns how many there were. Entries are always written with a TTL, so any found here
+    /// were left behind by an interrupted write or a manual change.
+    ///
+    /// # Errors
+    /// Returns an error if the store operation fails.
+    pub async fn enforce_ttl(&self) -> anyhow::Result<u64> {
+        if self.ttl_secs == 0 {
+            return Ok(0);
+        }
+        self.store.enforce_ttl(&self.prefix, self.ttl_secs).await
+    }
+}
+
+#[async_trait]
+impl SubmissionStore for KeyValueCache {
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Self::get(self, key).await
+    }
+
+    async fn set(&self, key: &str, value: &[u8]) -> anyhow::Result<()> {
+        Self::set(self, key, value).await
+    }
+
+    async fn enforce_ttl(&self) -> anyhow::Result<u64> {
+        Self::enforce_ttl(self).await
+    }
+}
@@ -0,0 +1,94 @@
+use crate::adapters::key_value::KeyValueStore;
+use crate::domain::instance::InstanceInfo;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use time::OffsetDateTime;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InstanceRecord {
+    id: String,
+    version: String,
+    started_at: i64,
+    active_sessions: usize,
+    heartbeat_at: i64,
+}
+
+impl From<&InstanceInfo> for InstanceRecord {
+    fn from(info: &InstanceInfo) -> Self {
+        Self {
+            id: info.id.clone(),
+            version: info.version.clone(),
+            started_at: info.started_at.unix_timestamp(),
+            active_sessions: info.active_sessions,
+            heartbeat_at: info.heartbeat_at.unix_timestamp(),
+        }
+    }
+}
+
+impl TryFrom<InstanceRecord> for InstanceInfo {
+    type Error = time::error::ComponentRange;
+
+    fn try_from(record: InstanceRecord) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: record.id,
+            version: record.version,
+            started_at: OffsetDateTime::from_unix_timestamp(record.started_at)?,
+            active_sessions: record.active_sessions,
+            heartbeat_at: OffsetDateTime::from_unix_timestamp(record.heartbeat_at)?,
+        })
+    }
+}
+
+/// Cluster-wide list of the instances that are running, for diagnostics.
+///
+/// Each instance keeps its own entry alive with heartbeats; an entry expires once its instance
+/// stops sending them, so instances that died without deregistering drop off by themselves.
+#[derive(Debug, Clone)]
+pub struct InstanceRegistry {
+    store: Arc<dyn KeyValueStore>,
+    prefix: String,
+    ttl_secs: u64,
+}
+
+impl InstanceRegistry {
+    #[must_use]
+    pub fn new(store: Arc<dyn KeyValueStore>, prefix: String, ttl_secs: u64) -> Self {
+        Self { store, prefix, ttl_secs }
+    }
+
+    fn key(&self, id: &str) -> String {
+        format!("{}{id}", self.prefix)
+    }
+
+    /// Announces the instance, replacing its previous announcement.
+    ///
+    /// # Errors
+    /// Returns an error if the store operation fails.
+    pub async fn heartbeat(&self, info: &InstanceInfo) -> anyhow::Result<()> {
+        let record = serde_json::to_vec(&InstanceRecord::from(info))?;
+        self.store.set(&self.key(&info.id), &record, self.ttl_secs).await
+    }
+
+    /// Removes the instance's announcement.
+    ///
+    /// # Errors
+    /// Returns an error if the store operation fails.
+    pub async fn deregister(&self, id: &str) -> anyhow::Result<()> {
+        self.store.delete(&self.key(id)).await
+    }
+
+    /// Lists the instances whose announcement has not expired. Entries that cannot be read,
+    /// such as those written by an incompatible version, are skipped.
+    ///
+    /// # Errors
+    /// Returns an error if the store operation fails.
+    pub async fn list(&self) -> anyhow::Result<Vec<InstanceInfo>> {
+        let records = self.store.scan(&self.prefix).await?;
+        Ok(records
+            .into_iter()
+            .filter_map(|record| serde_json::from_slice::<InstanceRecord>(&record).ok())
+            .filter_map(|record| InstanceInfo::try_from(record).ok())
+            .collect())
+    }
+}
@@ -0,0 +1,58 @@
+use async_trait::async_trait;
+
+pub mod cache;
+pub mod instance_registry;
+pub mod postgres;
+pub mod session_registry;
+pub mod session_resume;
+
+pub use cache::KeyValueCache;
+pub use instance_registry::InstanceRegistry;
+pub use postgres::PostgresKeyValueStore;
+pub use session_registry::SessionRegistry;
+pub use session_resume::SessionResume;
+
+/// Shared short-lived state every instance sees: gateway tickets, session ownership, resume
+/// state, the instance list and idempotency responses. Every entry carries an expiry.
+///
+/// Redis holds it when one of the notification backends uses Redis, and Postgres otherwise.
+#[async_trait]
+pub trait KeyValueStore: Send + Sync + std::fmt::Debug {
+    /// Returns the value stored under `key`, if it has not expired.
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>>;
+
+    /// Stores `value` under `key` for `ttl_secs`, replacing any earlier value.
+    async fn set(&self, key: &str, value: &[u8], ttl_secs: u64) -> anyhow::Result<()>;
+
+    /// Stores `value` under `key` for `ttl_secs` unless a live value is already there.
+    /// Returns `false` if one was.
+    async fn set_if_absent(&self, key: &str, value: &[u8], ttl_secs: u64) -> anyhow::Result<bool>;
+
+    /// Removes and returns the value stored under `key`, if it has not expired.
+    async fn take(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>>;
+
+    /// Removes `key`.
+    async fn delete(&self, key: &str) -> anyhow::Result<()>;
+
+    /// Extends `key` for `ttl_secs` while it still holds `value`, and stores `value` again if
+    /// the entry expired. Returns `false` if the key holds something else.
+    async fn refresh_if(&self, key: &str, value: &[u8], ttl_secs: u64) -> anyhow::Result<bool>;
+
+    /// Removes `key` only if it still holds `value`.
+    async fn delete_if(&self, key: &str, value: &[u8]) -> anyhow::Result<()>;
+
+    /// Returns the live values of every key starting with `prefix`.
+    async fn scan(&self, prefix: &str) -> anyhow::Result<Vec<Vec<u8>>>;
+
+    /// Gives every key starting with `prefix` that has no expiry `ttl_secs`, and returns how
+    /// many there were. Stores that cannot hold such entries have nothing to do.
+    async fn enforce_ttl(&self, _prefix: &str, _ttl_secs: u64) -> anyhow::Result<u64> {
+        Ok(0)
+    }
+
+    /// Deletes the entries that have expired, returning how many there were. Stores that
+    /// expire entries by themselves have nothing to do.
+    async fn purge_expired(&self) -> anyhow::Result<u64> {
+        Ok(0)
+    }
+}
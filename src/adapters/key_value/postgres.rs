@@ -0,0 +1,130 @@
+use crate::adapters::database::DbPool;
+use crate::adapters::key_value::KeyValueStore;
+use async_trait::async_trait;
+use time::OffsetDateTime;
+
+/// Key-value store backed by the `key_value_entries` table, for deployments without Redis.
+///
+/// Expired rows are ignored by every read and deleted by [`KeyValueStore::purge_expired`],
+/// which the janitor runs.
+#[derive(Clone, Debug)]
+pub struct PostgresKeyValueStore {
+    pool: DbPool,
+}
+
+impl PostgresKeyValueStore {
+    #[must_use]
+    pub const fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+}
+
+fn seconds_from_now(secs: u64) -> OffsetDateTime {
+    OffsetDateTime::now_utc() + time::Duration::seconds(i64::try_from(secs).unwrap_or(i64::MAX))
+}
+
+#[async_trait]
+impl KeyValueStore for PostgresKeyValueStore {
+    #[tracing::instrument(level = "debug", skip_all, err)]
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let value = sqlx::query_scalar("SELECT value FROM key_value_entries WHERE key = $1 AND expires_at > NOW()")
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(value)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, err)]
+    async fn set(&self, key: &str, value: &[u8], ttl_secs: u64) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO key_value_entries (key, value, expires_at) VALUES ($1, $2, $3)
+            ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value, expires_at = EXCLUDED.expires_at
+            "#,
+        )
+        .bind(key)
+        .bind(value)
+        .bind(seconds_from_now(ttl_secs))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, err)]
+    async fn set_if_absent(&self, key: &str, value: &[u8], ttl_secs: u64) -> anyhow::Result<bool> {
+        // An expired row counts as absent and is taken over.
+        let stored = sqlx::query(
+            r#"
+            INSERT INTO key_value_entries (key, value, expires_at) VALUES ($1, $2, $3)
+            ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value, expires_at = EXCLUDED.expires_at
+            WHERE key_value_entries.expires_at <= NOW()
+            "#,
+        )
+        .bind(key)
+        .bind(value)
+        .bind(seconds_from_now(ttl_secs))
+        .execute(&self.pool)
+        .await?;
+        Ok(stored.rows_affected() > 0)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, err)]
+    async fn take(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let taken: Option<(Vec<u8>, OffsetDateTime)> =
+            sqlx::query_as("DELETE FROM key_value_entries WHERE key = $1 RETURNING value, expires_at")
+                .bind(key)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(taken.filter(|(_, expires_at)| *expires_at > OffsetDateTime::now_utc()).map(|(value, _)| value))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, err)]
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        sqlx::query("DELETE FROM key_value_entries WHERE key = $1").bind(key).execute(&self.pool).await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, err)]
+    async fn refresh_if(&self, key: &str, value: &[u8], ttl_secs: u64) -> anyhow::Result<bool> {
+        let refreshed = sqlx::query(
+            r#"
+            INSERT INTO key_value_entries (key, value, expires_at) VALUES ($1, $2, $3)
+            ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value, expires_at = EXCLUDED.expires_at
+            WHERE key_value_entries.value = EXCLUDED.value OR key_value_entries.expires_at <= NOW()
+            "#,
+        )
+        .bind(key)
+        .bind(value)
+        .bind(seconds_from_now(ttl_secs))
+        .execute(&self.pool)
+        .await?;
+        Ok(refreshed.rows_affected() > 0)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, err)]
+    async fn delete_if(&self, key: &str, value: &[u8]) -> anyhow::Result<()> {
+        sqlx::query("DELETE FROM key_value_entries WHERE key = $1 AND value = $2")
+            .bind(key)
+            .bind(value)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, err)]
+    async fn scan(&self, prefix: &str) -> anyhow::Result<Vec<Vec<u8>>> {
+        let values = sqlx::query_scalar(
+            "SELECT value FROM key_value_entries WHERE starts_with(key, $1) AND expires_at > NOW() ORDER BY key",
+        )
+        .bind(prefix)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(values)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, err)]
+    async fn purge_expired(&self) -> anyhow::Result<u64> {
+        let purged = sqlx::query("DELETE FROM key_value_entries WHERE expires_at <= NOW()").execute(&self.pool).await?;
+        Ok(purged.rows_affected())
+    }
+}
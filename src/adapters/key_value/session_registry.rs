@@ -0,0 +1,66 @@
+use crate::adapters::key_value::KeyValueStore;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Cluster-wide record of which gateway session currently owns each device.
+///
+/// Entries expire unless the owning session refreshes them, so an instance that dies
+/// without releasing its sessions does not lock devices out.
+#[derive(Debug, Clone)]
+pub struct SessionRegistry {
+    store: Arc<dyn KeyValueStore>,
+    prefix: String,
+    ttl_secs: u64,
+}
+
+impl SessionRegistry {
+    #[must_use]
+    pub fn new(store: Arc<dyn KeyValueStore>, prefix: String, ttl_secs: u64) -> Self {
+        Self { store, prefix, ttl_secs }
+    }
+
+    fn key(&self, device_id: Uuid) -> String {
+        format!("{}{device_id}", self.prefix)
+    }
+
+    /// Records `session_id` as the owner of the device, replacing any previous owner.
+    ///
+    /// # Errors
+    /// Returns an error if the store operation fails.
+    pub async fn claim(&self, device_id: Uuid, session_id: Uuid) -> anyhow::Result<()> {
+        self.store.set(&self.key(device_id), session_id.to_string().as_bytes(), self.ttl_secs).await
+    }
+
+    /// Records `session_id` as the owner of the device unless another session already owns it.
+    ///
+    /// # Errors
+    /// Returns an error if the store operation fails.
+    pub async fn try_claim(&self, device_id: Uuid, session_id: Uuid) -> anyhow::Result<bool> {
+        self.store.set_if_absent(&self.key(device_id), session_id.to_string().as_bytes(), self.ttl_secs).await
+    }
+
+    /// Returns the session that currently owns the device, if any.
+    ///
+    /// # Errors
+    /// Returns an error if the store operation fails.
+    pub async fn owner(&self, device_id: Uuid) -> anyhow::Result<Option<Uuid>> {
+        let owner = self.store.get(&self.key(device_id)).await?;
+        Ok(owner.and_then(|id| Uuid::try_parse_ascii(&id).ok()))
+    }
+
+    /// Keeps the session's entry alive. Returns `false` if another session has taken the device over.
+    ///
+    /// # Errors
+    /// Returns an error if the store operation fails.
+    pub async fn refresh(&self, device_id: Uuid, session_id: Uuid) -> anyhow::Result<bool> {
+        self.store.refresh_if(&self.key(device_id), session_id.to_string().as_bytes(), self.ttl_secs).await
+    }
+
+    /// Removes the session's entry, leaving a newer owner in place.
+    ///
+    /// # Errors
+    /// Returns an error if the store operation fails.
+    pub async fn release(&self, device_id: Uuid, session_id: Uuid) -> anyhow::Result<()> {
+        self.store.delete_if(&self.key(device_id), session_id.to_string().as_bytes()).await
+    }
+}
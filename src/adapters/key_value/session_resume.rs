@@ -0,0 +1,63 @@
+use crate::adapters::key_value::KeyValueStore;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Delivery state a gateway session leaves behind when its instance shuts down.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResumeState {
+    pub device_id: Uuid,
+    /// Highest inbox sequence written to the socket.
+    pub cursor: Option<i64>,
+    /// The resuming session fetches only above this sequence. It sits below every message written
+    /// to the socket but not acknowledged, so those are sent again in case they never arrived.
+    #[serde(default)]
+    pub fetch_after: Option<i64>,
+}
+
+/// Session state handed from a draining instance to the one a client reconnects to, keyed by
+/// the resume token issued with the client's connection ticket.
+///
+/// Entries are single use and expire, so a client that never comes back (or comes back too late)
+/// simply starts a fresh session and is sent its whole inbox again.
+#[derive(Debug, Clone)]
+pub struct SessionResume {
+    store: Arc<dyn KeyValueStore>,
+    prefix: String,
+    ttl_secs: u64,
+}
+
+impl SessionResume {
+    #[must_use]
+    pub fn new(store: Arc<dyn KeyValueStore>, prefix: String, ttl_secs: u64) -> Self {
+        Self { store, prefix, ttl_secs }
+    }
+
+    fn key(&self, token: &str) -> String {
+        format!("{}{token}", self.prefix)
+    }
+
+    /// Stores the state under `token`, replacing anything saved there before.
+    ///
+    /// # Errors
+    /// Returns an error if the state cannot be encoded or the store operation fails.
+    pub async fn save(&self, token: &str, state: &ResumeState) -> anyhow::Result<()> {
+        let payload = serde_json::to_vec(state)?;
+        self.store.set(&self.key(token), &payload, self.ttl_secs).await
+    }
+
+    /// Removes and returns the state saved under `token`, if any. An entry that cannot be
+    /// decoded is logged and discarded.
+    ///
+    /// # Errors
+    /// Returns an error if the store operation fails.
+    pub async fn take(&self, token: &str) -> anyhow::Result<Option<ResumeState>> {
+        let payload = self.store.take(&self.key(token)).await?;
+
+        Ok(payload.and_then(|payload| {
+            serde_json::from_slice(&payload)
+                .inspect_err(|e| tracing::warn!(error = %e, "Discarding undecodable session resume state"))
+                .ok()
+        }))
+    }
+}
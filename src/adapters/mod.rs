@@ -1,5 +1,14 @@
 #![allow(clippy::needless_raw_string_hashes)]
+pub mod circuit_breaker;
+pub mod crypto;
 pub mod database;
+pub mod http_client;
+pub mod key_value;
 pub mod push;
+pub mod push_queue;
+pub mod realtime;
 pub mod redis;
+pub mod retry;
 pub mod storage;
+pub mod submission_cache;
+pub mod verification;
@@ -1,10 +1,11 @@
 use crate::adapters::push::{PushError, PushProvider};
 use crate::config::FcmConfig;
+use crate::domain::notification::PushKind;
 use async_trait::async_trait;
 use jsonwebtoken::{Algorithm, EncodingKey, Header};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::sync::RwLock;
 
 /// How many seconds before expiry to proactively refresh the token.
@@ -19,6 +20,18 @@ const GOOGLE_TOKEN_URI: &str = "https://oauth2.googleapis.com/token";
 /// Grant type for JWT bearer assertion (RFC 7523).
 const JWT_BEARER_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:jwt-bearer";
 
+/// Topic addressed by credential checks. The messages are only validated, never delivered.
+const CREDENTIAL_CHECK_TOPIC: &str = "obscura-credential-check";
+
+/// The `action` data field and collapse key for each kind of push. Android and APNs keep only
+/// the newest pending push per collapse key, so a refill request survives later message checks.
+const fn push_action(kind: PushKind) -> (&'static str, &'static str) {
+    match kind {
+        PushKind::Check => ("check", "obscura_check"),
+        PushKind::PreKeyLow => ("refill_prekeys", "obscura_prekey_low"),
+    }
+}
+
 /// Fields parsed from a Google service account JSON file.
 #[derive(Deserialize)]
 struct ServiceAccountKey {
@@ -129,6 +142,9 @@ struct FieldViolation {
 /// FCM message payload.
 #[derive(Debug, Serialize)]
 struct FcmRequest {
+    /// Asks FCM to check the message without delivering it.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    validate_only: bool,
     message: FcmMessage,
 }
 
@@ -183,10 +199,11 @@ struct FcmAps {
 
 impl FcmPushProvider {
     /// Creates a new `FcmPushProvider` by reading the service account key from a file.
+    /// `http` should come from `adapters::http_client::build_client` so outbound policy applies.
     ///
     /// # Errors
     /// Returns an error if the credentials file cannot be read or parsed.
-    pub fn new(config: &FcmConfig) -> Result<Self, anyhow::Error> {
+    pub fn new(config: &FcmConfig, http: reqwest::Client) -> Result<Self, anyhow::Error> {
         let project_id = config
             .project_id
             .as_ref()
@@ -210,7 +227,7 @@ impl FcmPushProvider {
             project_id: project_id.clone(),
             client_email: sa_key.client_email,
             encoding_key,
-            http: reqwest::Client::new(),
+            http,
             token_cache: Arc::new(RwLock::new(None)),
             ttl_secs: config.ttl_secs,
             fcm_base_url: "https://fcm.googleapis.com".to_string(),
@@ -223,7 +240,7 @@ impl FcmPushProvider {
     /// Uses a read-biased caching strategy: most callers take a read lock and
     /// get the cached token. Only when the token is missing or near expiry does
     /// a single caller acquire a write lock and refresh.
-    #[tracing::instrument(level = "debug", skip(self), err)]
+    #[tracing::instrument(level = "debug", skip_all, err)]
     async fn get_access_token(&self) -> Result<String, PushError> {
         // Fast path: read lock
         {
@@ -253,7 +270,7 @@ impl FcmPushProvider {
     }
 
     /// Performs the JWT bearer assertion flow to obtain a new access token.
-    #[tracing::instrument(level = "debug", skip(self), err)]
+    #[tracing::instrument(level = "debug", skip_all, err)]
     async fn fetch_access_token(&self) -> Result<CachedToken, PushError> {
         let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
 
@@ -275,12 +292,12 @@ impl FcmPushProvider {
             .form(&[("grant_type", JWT_BEARER_GRANT_TYPE), ("assertion", &assertion)])
             .send()
             .await
-            .map_err(|e| PushError::Other(anyhow::anyhow!("Token exchange request failed: {e}")))?;
+            .map_err(|e| PushError::Transient(anyhow::anyhow!("Token exchange request failed: {e}")))?;
 
         if !resp.status().is_success() {
             let status = resp.status();
             let body = resp.text().await.unwrap_or_default();
-            return Err(PushError::Other(anyhow::anyhow!("Token exchange failed with HTTP {status}: {body}")));
+            return Err(http_error(status, anyhow::anyhow!("Token exchange failed with HTTP {status}: {body}")));
         }
 
         let token_resp: TokenResponse =
@@ -289,21 +306,24 @@ impl FcmPushProvider {
         Ok(CachedToken { access_token: token_resp.access_token, expires_at: now + token_resp.expires_in })
     }
 
-    /// Sends a data-only push notification via the FCM HTTP v1 API.
-    #[tracing::instrument(level = "debug", skip(self, device_token), err)]
-    async fn send_fcm_message(&self, device_token: &str) -> Result<(), PushError> {
+    /// Sends a data-only push notification via the FCM HTTP v1 API, or with `validate_only`
+    /// has FCM check it without delivering it.
+    #[tracing::instrument(level = "debug", skip_all, fields(?kind, %validate_only), err)]
+    async fn send_fcm_message(&self, device_token: &str, kind: PushKind, validate_only: bool) -> Result<(), PushError> {
         let access_token = self.get_access_token().await?;
 
         let url = format!("{}/v1/projects/{}/messages:send", self.fcm_base_url, self.project_id);
 
         let ttl_string = format!("{}s", self.ttl_secs);
+        let (action, collapse_key) = push_action(kind);
 
         let body = FcmRequest {
+            validate_only,
             message: FcmMessage {
                 token: device_token.to_string(),
-                data: FcmData { action: "check".to_string() },
+                data: FcmData { action: action.to_string() },
                 android: FcmAndroid {
-                    collapse_key: "obscura_check".to_string(),
+                    collapse_key: collapse_key.to_string(),
                     priority: "HIGH".to_string(),
                     ttl: ttl_string,
                 },
@@ -311,7 +331,7 @@ impl FcmPushProvider {
                     headers: FcmApnsHeaders {
                         push_type: "background".to_string(),
                         priority: "5".to_string(),
-                        collapse_id: "obscura_check".to_string(),
+                        collapse_id: collapse_key.to_string(),
                     },
                     payload: FcmApnsPayload { aps: FcmAps { content_available: 1 } },
                 },
@@ -325,7 +345,7 @@ impl FcmPushProvider {
             .json(&body)
             .send()
             .await
-            .map_err(|e| PushError::Other(anyhow::anyhow!("FCM request failed: {e}")))?;
+            .map_err(|e| PushError::Transient(anyhow::anyhow!("FCM request failed: {e}")))?;
 
         let status = resp.status();
 
@@ -335,7 +355,13 @@ impl FcmPushProvider {
 
         // Map HTTP status codes and FCM error codes to PushError variants
         if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
-            return Err(PushError::QuotaExceeded);
+            // FCM sends the delay in seconds; the HTTP-date form is not used
+            let retry_after = resp
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok()?.trim().parse().ok())
+                .map(Duration::from_secs);
+            return Err(PushError::QuotaExceeded { retry_after });
         }
 
         let body = resp.text().await.unwrap_or_default();
@@ -347,7 +373,7 @@ impl FcmPushProvider {
 
         // 403 SENDER_ID_MISMATCH: token belongs to a different sender and will never work
         if status == reqwest::StatusCode::FORBIDDEN {
-            return Err(PushError::Unregistered);
+            return Err(PushError::SenderMismatch);
         }
 
         // Parse the error body for specific FCM error codes
@@ -356,10 +382,13 @@ impl FcmPushProvider {
         {
             // Check top-level status for token-is-gone errors
             if let Some(ref s) = error.status
-                && (s == "NOT_FOUND" || s == "UNREGISTERED" || s == "SENDER_ID_MISMATCH")
+                && (s == "NOT_FOUND" || s == "UNREGISTERED")
             {
                 return Err(PushError::Unregistered);
             }
+            if error.status.as_deref() == Some("SENDER_ID_MISMATCH") {
+                return Err(PushError::SenderMismatch);
+            }
 
             // Check error details for UNREGISTERED error code
             if let Some(ref details) = error.details {
@@ -397,21 +426,60 @@ impl FcmPushProvider {
             }
         }
 
-        Err(PushError::Other(anyhow::anyhow!("FCM request failed with HTTP {status}: {body}")))
+        Err(http_error(status, anyhow::anyhow!("FCM request failed with HTTP {status}: {body}")))
     }
 }
 
+/// Classifies an unsuccessful HTTP response: server errors may pass if sent again, anything else
+/// was rejected and will be again.
+fn http_error(status: reqwest::StatusCode, error: anyhow::Error) -> PushError {
+    if status.is_server_error() { PushError::Transient(error) } else { PushError::Other(error) }
+}
+
 #[async_trait]
 impl PushProvider for FcmPushProvider {
-    #[tracing::instrument(level = "debug", skip(self, token), err)]
-    async fn send_push(&self, token: &str) -> Result<(), PushError> {
-        self.send_fcm_message(token).await
+    #[tracing::instrument(level = "debug", skip_all, fields(?kind), err)]
+    async fn send_push(&self, token: &str, kind: PushKind) -> Result<(), PushError> {
+        self.send_fcm_message(token, kind, false).await
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(?kind), err)]
+    async fn validate_push(&self, token: &str, kind: PushKind) -> Result<(), PushError> {
+        self.send_fcm_message(token, kind, true).await
+    }
+
+    /// Exchanges the service account for an access token, then has FCM validate a message to a
+    /// placeholder topic with `validate_only`, which exercises the project ID and the sender
+    /// permission without delivering anything.
+    #[tracing::instrument(level = "debug", skip_all, err)]
+    async fn check_credentials(&self) -> Result<(), PushError> {
+        let access_token = self.get_access_token().await?;
+
+        let url = format!("{}/v1/projects/{}/messages:send", self.fcm_base_url, self.project_id);
+        let body = serde_json::json!({ "validate_only": true, "message": { "topic": CREDENTIAL_CHECK_TOPIC } });
+
+        let resp = self
+            .http
+            .post(&url)
+            .bearer_auth(&access_token)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| PushError::Transient(anyhow::anyhow!("FCM validation request failed: {e}")))?;
+
+        let status = resp.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let body = resp.text().await.unwrap_or_default();
+        Err(http_error(status, anyhow::anyhow!("FCM rejected the credential check with HTTP {status}: {body}")))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::adapters::push::PushFeedback;
     use axum::Router;
     use axum::http::StatusCode;
     use axum::routing::post;
@@ -659,6 +727,7 @@ wxOWCSTHCchvQGrMpJlCSygGPUKmT/nl464SFJsQcyZLhrwKKW8=\n\
     #[test]
     fn fcm_request_serializes_to_v1_format() {
         let req = FcmRequest {
+            validate_only: false,
             message: FcmMessage {
                 token: "device_token".to_string(),
                 data: FcmData { action: "check".to_string() },
@@ -692,6 +761,9 @@ wxOWCSTHCchvQGrMpJlCSygGPUKmT/nl464SFJsQcyZLhrwKKW8=\n\
         assert_eq!(apns["headers"]["apns-priority"], "5");
         assert_eq!(apns["headers"]["apns-collapse-id"], "obscura_check");
         assert_eq!(apns["payload"]["aps"]["content-available"], 1);
+
+        // Real sends leave the validation flag out entirely
+        assert!(json.get("validate_only").is_none());
     }
 
     // ── send_fcm_message error-mapping tests ────────────────────────────
@@ -700,23 +772,100 @@ wxOWCSTHCchvQGrMpJlCSygGPUKmT/nl464SFJsQcyZLhrwKKW8=\n\
     async fn send_push_success_returns_ok() {
         let url = start_mock_fcm(StatusCode::OK, r#"{"name":"projects/test/messages/123"}"#).await;
         let provider = mock_provider(&url);
-        let result = provider.send_push("device_token_abc").await;
+        let result = provider.send_push("device_token_abc", PushKind::Check).await;
         assert!(result.is_ok());
     }
 
+    #[tokio::test]
+    async fn check_credentials_reports_rejection() {
+        let url = start_mock_fcm(StatusCode::OK, r#"{"name":"projects/test/messages/fake"}"#).await;
+        assert!(mock_provider(&url).check_credentials().await.is_ok());
+
+        let url = start_mock_fcm(StatusCode::FORBIDDEN, r#"{"error":{"status":"PERMISSION_DENIED"}}"#).await;
+        let result = mock_provider(&url).check_credentials().await;
+        assert!(matches!(result, Err(PushError::Other(_))));
+    }
+
+    #[tokio::test]
+    async fn validate_push_sends_validate_only_message() {
+        let (body_tx, mut body_rx) = tokio::sync::mpsc::unbounded_channel();
+        let app = Router::new().route(
+            "/v1/projects/{project_id}/messages:send",
+            post(move |axum::Json(body): axum::Json<serde_json::Value>| async move {
+                let _ = body_tx.send(body);
+                (StatusCode::NOT_FOUND, r#"{"error":{"status":"NOT_FOUND"}}"#)
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.expect("bind mock FCM");
+        let addr = listener.local_addr().expect("mock FCM address");
+        tokio::spawn(axum::serve(listener, app).into_future());
+
+        // Validation maps errors exactly like a real send
+        let result = mock_provider(&format!("http://{addr}")).validate_push("device_token_abc", PushKind::Check).await;
+        assert!(matches!(result, Err(PushError::Unregistered)));
+
+        let body = body_rx.recv().await.expect("request body");
+        assert_eq!(body["validate_only"], true);
+        assert_eq!(body["message"]["token"], "device_token_abc");
+    }
+
+    #[tokio::test]
+    async fn prekey_low_push_collapses_separately() {
+        let (body_tx, mut body_rx) = tokio::sync::mpsc::unbounded_channel();
+        let app = Router::new().route(
+            "/v1/projects/{project_id}/messages:send",
+            post(move |axum::Json(body): axum::Json<serde_json::Value>| async move {
+                let _ = body_tx.send(body);
+                (StatusCode::OK, r#"{"name":"projects/test/messages/123"}"#)
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.expect("bind mock FCM");
+        let addr = listener.local_addr().expect("mock FCM address");
+        tokio::spawn(axum::serve(listener, app).into_future());
+
+        let provider = mock_provider(&format!("http://{addr}"));
+        provider.send_push("device_token_abc", PushKind::PreKeyLow).await.expect("send succeeds");
+
+        let body = body_rx.recv().await.expect("request body");
+        assert_eq!(body["message"]["data"]["action"], "refill_prekeys");
+        assert_eq!(body["message"]["android"]["collapseKey"], "obscura_prekey_low");
+        assert_eq!(body["message"]["apns"]["headers"]["apns-collapse-id"], "obscura_prekey_low");
+    }
+
     #[tokio::test]
     async fn send_push_429_returns_quota_exceeded() {
         let url = start_mock_fcm(StatusCode::TOO_MANY_REQUESTS, r#"{"error":{"status":"RESOURCE_EXHAUSTED"}}"#).await;
         let provider = mock_provider(&url);
-        let result = provider.send_push("device_token_abc").await;
-        assert!(matches!(result, Err(PushError::QuotaExceeded)));
+        let result = provider.send_push("device_token_abc", PushKind::Check).await;
+        assert!(matches!(result, Err(PushError::QuotaExceeded { retry_after: None })));
+    }
+
+    #[tokio::test]
+    async fn send_push_429_carries_retry_after() {
+        let app = Router::new().route(
+            "/v1/projects/{project_id}/messages:send",
+            post(|| async {
+                (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    [(reqwest::header::RETRY_AFTER, "42")],
+                    r#"{"error":{"status":"RESOURCE_EXHAUSTED"}}"#,
+                )
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.expect("bind mock FCM");
+        let addr = listener.local_addr().expect("mock FCM address");
+        tokio::spawn(axum::serve(listener, app).into_future());
+
+        let result = mock_provider(&format!("http://{addr}")).send_push("device_token_abc", PushKind::Check).await;
+        let err = result.expect_err("Expected a quota error");
+        assert_eq!(err.feedback(), PushFeedback::RateLimited { retry_after: Some(Duration::from_secs(42)) });
     }
 
     #[tokio::test]
     async fn send_push_404_returns_unregistered() {
         let url = start_mock_fcm(StatusCode::NOT_FOUND, r#"{"error":{"status":"NOT_FOUND"}}"#).await;
         let provider = mock_provider(&url);
-        let result = provider.send_push("device_token_abc").await;
+        let result = provider.send_push("device_token_abc", PushKind::Check).await;
         assert!(matches!(result, Err(PushError::Unregistered)));
     }
 
@@ -725,7 +874,7 @@ wxOWCSTHCchvQGrMpJlCSygGPUKmT/nl464SFJsQcyZLhrwKKW8=\n\
         // 400 with NOT_FOUND in the body status field
         let url = start_mock_fcm(StatusCode::BAD_REQUEST, r#"{"error":{"status":"NOT_FOUND"}}"#).await;
         let provider = mock_provider(&url);
-        let result = provider.send_push("device_token_abc").await;
+        let result = provider.send_push("device_token_abc", PushKind::Check).await;
         assert!(matches!(result, Err(PushError::Unregistered)));
     }
 
@@ -734,7 +883,7 @@ wxOWCSTHCchvQGrMpJlCSygGPUKmT/nl464SFJsQcyZLhrwKKW8=\n\
         // 400 with UNREGISTERED in the body status field
         let url = start_mock_fcm(StatusCode::BAD_REQUEST, r#"{"error":{"status":"UNREGISTERED"}}"#).await;
         let provider = mock_provider(&url);
-        let result = provider.send_push("device_token_abc").await;
+        let result = provider.send_push("device_token_abc", PushKind::Check).await;
         assert!(matches!(result, Err(PushError::Unregistered)));
     }
 
@@ -746,32 +895,34 @@ wxOWCSTHCchvQGrMpJlCSygGPUKmT/nl464SFJsQcyZLhrwKKW8=\n\
         )
         .await;
         let provider = mock_provider(&url);
-        let result = provider.send_push("device_token_abc").await;
+        let result = provider.send_push("device_token_abc", PushKind::Check).await;
         assert!(matches!(result, Err(PushError::Unregistered)));
     }
 
     #[tokio::test]
-    async fn send_push_403_sender_id_mismatch_returns_unregistered() {
+    async fn send_push_403_sender_id_mismatch_returns_sender_mismatch() {
         let url = start_mock_fcm(StatusCode::FORBIDDEN, r#"{"error":{"status":"SENDER_ID_MISMATCH"}}"#).await;
         let provider = mock_provider(&url);
-        let result = provider.send_push("device_token_abc").await;
-        assert!(matches!(result, Err(PushError::Unregistered)));
+        let result = provider.send_push("device_token_abc", PushKind::Check).await;
+        let err = result.expect_err("Expected a sender mismatch");
+        assert!(matches!(err, PushError::SenderMismatch));
+        assert_eq!(err.feedback(), PushFeedback::InvalidToken);
     }
 
     #[tokio::test]
-    async fn send_push_body_sender_id_mismatch_status_returns_unregistered() {
+    async fn send_push_body_sender_id_mismatch_status_returns_sender_mismatch() {
         // 400 with SENDER_ID_MISMATCH in the body status field
         let url = start_mock_fcm(StatusCode::BAD_REQUEST, r#"{"error":{"status":"SENDER_ID_MISMATCH"}}"#).await;
         let provider = mock_provider(&url);
-        let result = provider.send_push("device_token_abc").await;
-        assert!(matches!(result, Err(PushError::Unregistered)));
+        let result = provider.send_push("device_token_abc", PushKind::Check).await;
+        assert!(matches!(result, Err(PushError::SenderMismatch)));
     }
 
     #[tokio::test]
     async fn send_push_400_invalid_argument_no_details_returns_other_error() {
         let url = start_mock_fcm(StatusCode::BAD_REQUEST, r#"{"error":{"status":"INVALID_ARGUMENT"}}"#).await;
         let provider = mock_provider(&url);
-        let result = provider.send_push("device_token_abc").await;
+        let result = provider.send_push("device_token_abc", PushKind::Check).await;
         assert!(matches!(result, Err(PushError::Other(_))));
     }
 
@@ -780,7 +931,7 @@ wxOWCSTHCchvQGrMpJlCSygGPUKmT/nl464SFJsQcyZLhrwKKW8=\n\
         let body = r#"{"error":{"status":"INVALID_ARGUMENT","details":[{"@type":"type.googleapis.com/google.rpc.BadRequest","fieldViolations":[{"field":"message.token","description":"Invalid registration token"}]}]}}"#;
         let url = start_mock_fcm(StatusCode::BAD_REQUEST, body).await;
         let provider = mock_provider(&url);
-        let result = provider.send_push("device_token_abc").await;
+        let result = provider.send_push("device_token_abc", PushKind::Check).await;
         assert!(matches!(result, Err(PushError::Unregistered)));
     }
 
@@ -789,31 +940,31 @@ wxOWCSTHCchvQGrMpJlCSygGPUKmT/nl464SFJsQcyZLhrwKKW8=\n\
         let body = r#"{"error":{"status":"INVALID_ARGUMENT","details":[{"@type":"type.googleapis.com/google.rpc.BadRequest","fieldViolations":[{"field":"message.data","description":"Payload too large"}]}]}}"#;
         let url = start_mock_fcm(StatusCode::BAD_REQUEST, body).await;
         let provider = mock_provider(&url);
-        let result = provider.send_push("device_token_abc").await;
+        let result = provider.send_push("device_token_abc", PushKind::Check).await;
         assert!(matches!(result, Err(PushError::Other(_))));
     }
 
     #[tokio::test]
-    async fn send_push_500_returns_other_error() {
+    async fn send_push_500_returns_transient_error() {
         let url = start_mock_fcm(StatusCode::INTERNAL_SERVER_ERROR, r#"{"error":{"status":"INTERNAL"}}"#).await;
         let provider = mock_provider(&url);
-        let result = provider.send_push("device_token_abc").await;
-        assert!(matches!(result, Err(PushError::Other(_))));
+        let result = provider.send_push("device_token_abc", PushKind::Check).await;
+        assert!(matches!(result, Err(PushError::Transient(_))));
     }
 
     #[tokio::test]
-    async fn send_push_503_unavailable_returns_other_error() {
+    async fn send_push_503_unavailable_returns_transient_error() {
         let url = start_mock_fcm(StatusCode::SERVICE_UNAVAILABLE, r#"{"error":{"status":"UNAVAILABLE"}}"#).await;
         let provider = mock_provider(&url);
-        let result = provider.send_push("device_token_abc").await;
-        assert!(matches!(result, Err(PushError::Other(_))));
+        let result = provider.send_push("device_token_abc", PushKind::Check).await;
+        assert!(matches!(result, Err(PushError::Transient(_))));
     }
 
     #[tokio::test]
     async fn send_push_401_third_party_auth_error_returns_other() {
         let url = start_mock_fcm(StatusCode::UNAUTHORIZED, r#"{"error":{"status":"THIRD_PARTY_AUTH_ERROR"}}"#).await;
         let provider = mock_provider(&url);
-        let result = provider.send_push("device_token_abc").await;
+        let result = provider.send_push("device_token_abc", PushKind::Check).await;
         assert!(matches!(result, Err(PushError::Other(_))));
     }
 
@@ -821,7 +972,7 @@ wxOWCSTHCchvQGrMpJlCSygGPUKmT/nl464SFJsQcyZLhrwKKW8=\n\
     async fn send_push_unparseable_error_body_returns_other() {
         let url = start_mock_fcm(StatusCode::BAD_REQUEST, "not json at all").await;
         let provider = mock_provider(&url);
-        let result = provider.send_push("device_token_abc").await;
+        let result = provider.send_push("device_token_abc", PushKind::Check).await;
         assert!(matches!(result, Err(PushError::Other(_))));
     }
 
@@ -834,7 +985,7 @@ wxOWCSTHCchvQGrMpJlCSygGPUKmT/nl464SFJsQcyZLhrwKKW8=\n\
             credentials_file: Some("/tmp/creds.json".to_string()),
             ..FcmConfig::default()
         };
-        let result = FcmPushProvider::new(&config);
+        let result = FcmPushProvider::new(&config, reqwest::Client::new());
         assert!(result.is_err());
         assert!(result.unwrap_err().to_string().contains("project ID"));
     }
@@ -843,7 +994,7 @@ wxOWCSTHCchvQGrMpJlCSygGPUKmT/nl464SFJsQcyZLhrwKKW8=\n\
     fn new_missing_credentials_file_returns_error() {
         let config =
             FcmConfig { project_id: Some("project".to_string()), credentials_file: None, ..FcmConfig::default() };
-        let result = FcmPushProvider::new(&config);
+        let result = FcmPushProvider::new(&config, reqwest::Client::new());
         assert!(result.is_err());
         assert!(result.unwrap_err().to_string().contains("credentials file"));
     }
@@ -855,7 +1006,7 @@ wxOWCSTHCchvQGrMpJlCSygGPUKmT/nl464SFJsQcyZLhrwKKW8=\n\
             credentials_file: Some("/tmp/does_not_exist_12345.json".to_string()),
             ..FcmConfig::default()
         };
-        let result = FcmPushProvider::new(&config);
+        let result = FcmPushProvider::new(&config, reqwest::Client::new());
         assert!(result.is_err());
         assert!(result.unwrap_err().to_string().contains("Failed to read"));
     }
@@ -869,7 +1020,7 @@ wxOWCSTHCchvQGrMpJlCSygGPUKmT/nl464SFJsQcyZLhrwKKW8=\n\
             credentials_file: Some(file.path().to_string_lossy().to_string()),
             ..FcmConfig::default()
         };
-        let result = FcmPushProvider::new(&config);
+        let result = FcmPushProvider::new(&config, reqwest::Client::new());
         assert!(result.is_err());
         assert!(result.unwrap_err().to_string().contains("parse FCM service account"));
     }
@@ -883,7 +1034,7 @@ wxOWCSTHCchvQGrMpJlCSygGPUKmT/nl464SFJsQcyZLhrwKKW8=\n\
             credentials_file: Some(file.path().to_string_lossy().to_string()),
             ..FcmConfig::default()
         };
-        let result = FcmPushProvider::new(&config);
+        let result = FcmPushProvider::new(&config, reqwest::Client::new());
         assert!(result.is_err());
         assert!(result.unwrap_err().to_string().contains("RSA private key"));
     }
@@ -901,7 +1052,7 @@ wxOWCSTHCchvQGrMpJlCSygGPUKmT/nl464SFJsQcyZLhrwKKW8=\n\
             credentials_file: Some(file.path().to_string_lossy().to_string()),
             ..FcmConfig::default()
         };
-        let provider = FcmPushProvider::new(&config).unwrap();
+        let provider = FcmPushProvider::new(&config, reqwest::Client::new()).unwrap();
         assert_eq!(provider.project_id, "my-project-123");
         assert_eq!(provider.client_email, "test@sa.iam.gserviceaccount.com");
         assert_eq!(provider.ttl_secs, 604_800);
@@ -912,7 +1063,7 @@ wxOWCSTHCchvQGrMpJlCSygGPUKmT/nl464SFJsQcyZLhrwKKW8=\n\
     #[tokio::test]
     async fn logging_provider_returns_ok() {
         let provider = super::super::LoggingPushProvider;
-        let result = provider.send_push("any_token").await;
+        let result = provider.send_push("any_token", PushKind::Check).await;
         assert!(result.is_ok());
     }
 }
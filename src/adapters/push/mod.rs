@@ -1,25 +1,101 @@
+use crate::domain::notification::PushKind;
 use async_trait::async_trait;
+use std::time::Duration;
 use thiserror::Error;
 
 pub mod fcm;
+pub mod multi;
 
 #[derive(Error, Debug)]
 pub enum PushError {
     #[error("Token is no longer registered")]
     Unregistered,
+    /// The token was registered for a different sender, so this provider can never reach it.
+    #[error("Token belongs to a different sender")]
+    SenderMismatch,
+    /// The provider is throttling this sender, and may have said for how long.
     #[error("Rate limit exceeded")]
-    QuotaExceeded,
+    QuotaExceeded { retry_after: Option<Duration> },
+    #[error("Push provider unavailable")]
+    Unavailable,
+    /// The request did not reach the provider or it failed on the provider's side, and the same
+    /// request may succeed if sent again.
+    #[error("Transient external service error: {0}")]
+    Transient(anyhow::Error),
+    /// Any other failure, such as a rejected request or response, which sending the same request
+    /// again will not fix.
     #[error("External service error: {0}")]
     Other(#[from] anyhow::Error),
 }
 
+impl PushError {
+    /// Whether sending the same request again may succeed.
+    #[must_use]
+    pub const fn is_transient(&self) -> bool {
+        matches!(self, Self::Transient(_))
+    }
+
+    /// What the push worker should do about this failure.
+    #[must_use]
+    pub const fn feedback(&self) -> PushFeedback {
+        match self {
+            Self::Unregistered | Self::SenderMismatch => PushFeedback::InvalidToken,
+            Self::QuotaExceeded { retry_after } => PushFeedback::RateLimited { retry_after: *retry_after },
+            Self::Unavailable | Self::Transient(_) | Self::Other(_) => PushFeedback::RetryLater { retry_after: None },
+        }
+    }
+}
+
+/// A provider's verdict on a failed send, which decides how the job and token are followed up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PushFeedback {
+    /// The token will never work again: delete it and drop the job.
+    InvalidToken,
+    /// Every send from this server is being throttled: pause sending, then retry the job.
+    RateLimited { retry_after: Option<Duration> },
+    /// This send failed transiently: retry the job after `retry_after`, or once its lease
+    /// expires if the provider gave no hint.
+    RetryLater { retry_after: Option<Duration> },
+}
+
+impl PushFeedback {
+    /// Metric label for the outcome.
+    #[must_use]
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::InvalidToken => "invalid_token",
+            Self::RateLimited { .. } => "rate_limited",
+            Self::RetryLater { .. } => "retry_later",
+        }
+    }
+}
+
 #[async_trait]
 pub trait PushProvider: Send + Sync + std::fmt::Debug {
-    /// Sends a push notification to a specific device token.
+    /// Sends a push notification to a specific device token. Pushes of different kinds are
+    /// collapsed separately, so a pending one of one kind never replaces another.
     ///
     /// # Errors
     /// Returns `PushError::Unregistered` if the token is invalid and should be deleted.
-    async fn send_push(&self, token: &str) -> Result<(), PushError>;
+    async fn send_push(&self, token: &str, kind: PushKind) -> Result<(), PushError>;
+
+    /// Has the provider check a push to `token` exactly as `send_push` would, without
+    /// delivering it. Providers that cannot validate without sending report success.
+    ///
+    /// # Errors
+    /// Returns the error `send_push` would have returned for this token.
+    async fn validate_push(&self, _token: &str, _kind: PushKind) -> Result<(), PushError> {
+        Ok(())
+    }
+
+    /// Verifies that the provider accepts the configured credentials, without notifying anyone.
+    /// Providers without credentials report success.
+    ///
+    /// # Errors
+    /// Returns an error if the credentials are rejected or the provider cannot be reached.
+    async fn check_credentials(&self) -> Result<(), PushError> {
+        Ok(())
+    }
 }
 
 /// A no-op push provider that logs instead of sending real notifications.
@@ -29,7 +105,7 @@ pub struct LoggingPushProvider;
 
 #[async_trait]
 impl PushProvider for LoggingPushProvider {
-    async fn send_push(&self, _token: &str) -> Result<(), PushError> {
+    async fn send_push(&self, _token: &str, _kind: PushKind) -> Result<(), PushError> {
         tracing::warn!("Push notification not sent: FCM is not configured");
         Ok(())
     }
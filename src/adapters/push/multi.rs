@@ -0,0 +1,189 @@
+use crate::adapters::push::{PushError, PushProvider};
+use crate::domain::notification::PushKind;
+use async_trait::async_trait;
+use opentelemetry::{KeyValue, global, metrics::Counter};
+use std::sync::Arc;
+use tracing::Instrument;
+
+#[derive(Clone, Debug)]
+struct Metrics {
+    sends_total: Counter<u64>,
+    shadow_mismatches_total: Counter<u64>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let meter = global::meter("obscura-server");
+        Self {
+            sends_total: meter
+                .u64_counter("obscura_push_provider_sends_total")
+                .with_description("Pushes handled by each provider, labelled by provider and outcome")
+                .build(),
+            shadow_mismatches_total: meter
+                .u64_counter("obscura_push_shadow_mismatches_total")
+                .with_description("Shadowed pushes where the shadow provider's outcome differed from the primary's")
+                .build(),
+        }
+    }
+}
+
+/// Metric label for the outcome of a send or validation.
+fn outcome(result: &Result<(), PushError>) -> &'static str {
+    result.as_ref().err().map_or("success", |e| e.feedback().as_str())
+}
+
+/// Shadow outcome label for tokens registered to another sender, which say nothing about the
+/// shadow provider.
+const SENDER_MISMATCH: &str = "sender_mismatch";
+
+/// Metric label for the outcome of a shadow validation.
+fn shadow_outcome(result: &Result<(), PushError>) -> &'static str {
+    if matches!(result, Err(PushError::SenderMismatch)) { SENDER_MISMATCH } else { outcome(result) }
+}
+
+/// Delivers through a primary provider while a shadow provider validates a sample of the same
+/// pushes, for trialling a provider before migrating to it.
+///
+/// The shadow only ever validates, so devices never receive a duplicate, and its outcome never
+/// affects the primary's: it runs in the background and is only recorded and compared.
+///
+/// Tokens are registered for the primary's sender, so a separate FCM project answers with a
+/// sender mismatch for every token not also registered for it. Those are counted as
+/// `sender_mismatch` and left out of the comparison, which then only covers tokens the shadow
+/// can actually reach.
+#[derive(Debug)]
+pub struct MultiPushProvider {
+    primary: Arc<dyn PushProvider>,
+    shadow: Option<Arc<dyn PushProvider>>,
+    shadow_percent: u8,
+    metrics: Metrics,
+}
+
+impl MultiPushProvider {
+    /// Wraps `primary`, validating `shadow_percent` percent of pushes with `shadow` if given.
+    #[must_use]
+    pub fn new(primary: Arc<dyn PushProvider>, shadow: Option<Arc<dyn PushProvider>>, shadow_percent: u8) -> Self {
+        Self { primary, shadow, shadow_percent: shadow_percent.min(100), metrics: Metrics::new() }
+    }
+
+    fn record(&self, provider: &'static str, outcome: &'static str) {
+        self.metrics.sends_total.add(1, &[KeyValue::new("provider", provider), KeyValue::new("outcome", outcome)]);
+    }
+
+    fn sampled(&self) -> bool {
+        rand::random_range(0..100) < self.shadow_percent
+    }
+}
+
+#[async_trait]
+impl PushProvider for MultiPushProvider {
+    async fn send_push(&self, token: &str, kind: PushKind) -> Result<(), PushError> {
+        let shadow = self.shadow.as_ref().filter(|_| self.sampled()).map(|shadow| {
+            let shadow = Arc::clone(shadow);
+            let token = token.to_string();
+            tokio::spawn(async move { shadow_outcome(&shadow.validate_push(&token, kind).await) }.in_current_span())
+        });
+
+        let result = self.primary.send_push(token, kind).await;
+        let primary = outcome(&result);
+        self.record("primary", primary);
+
+        if let Some(shadow) = shadow {
+            let metrics = self.metrics.clone();
+            tokio::spawn(
+                async move {
+                    let Ok(shadow) = shadow.await else { return };
+                    metrics
+                        .sends_total
+                        .add(1, &[KeyValue::new("provider", "shadow"), KeyValue::new("outcome", shadow)]);
+                    if shadow != primary && shadow != SENDER_MISMATCH {
+                        tracing::debug!(primary, shadow, "Shadow push provider disagreed with the primary");
+                        metrics.shadow_mismatches_total.add(
+                            1,
+                            &[KeyValue::new("primary_outcome", primary), KeyValue::new("shadow_outcome", shadow)],
+                        );
+                    }
+                }
+                .in_current_span(),
+            );
+        }
+
+        result
+    }
+
+    async fn validate_push(&self, token: &str, kind: PushKind) -> Result<(), PushError> {
+        self.primary.validate_push(token, kind).await
+    }
+
+    // Only the primary decides readiness; a failing shadow shows up in the send metrics
+    async fn check_credentials(&self) -> Result<(), PushError> {
+        self.primary.check_credentials().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[derive(Debug, Default)]
+    struct CountingProvider {
+        sends: AtomicUsize,
+        validations: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl PushProvider for CountingProvider {
+        async fn send_push(&self, _token: &str, _kind: PushKind) -> Result<(), PushError> {
+            self.sends.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        async fn validate_push(&self, _token: &str, _kind: PushKind) -> Result<(), PushError> {
+            self.validations.fetch_add(1, Ordering::SeqCst);
+            Err(PushError::Unregistered)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shadow_validates_without_delivering() {
+        let primary = Arc::new(CountingProvider::default());
+        let shadow = Arc::new(CountingProvider::default());
+        let provider = MultiPushProvider::new(Arc::clone(&primary), Some(Arc::clone(&shadow)), 100);
+
+        // The shadow's failure never reaches the caller
+        provider.send_push("token", PushKind::Check).await.expect("primary send succeeds");
+        assert_eq!(primary.sends.load(Ordering::SeqCst), 1);
+
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while shadow.validations.load(Ordering::SeqCst) == 0 {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("shadow validation runs in the background");
+        assert_eq!(shadow.sends.load(Ordering::SeqCst), 0);
+        assert_eq!(primary.validations.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_sender_mismatch_is_labelled_separately_for_the_shadow() {
+        assert_eq!(shadow_outcome(&Err(PushError::SenderMismatch)), "sender_mismatch");
+        assert_eq!(shadow_outcome(&Err(PushError::Unregistered)), "invalid_token");
+        assert_eq!(outcome(&Err(PushError::SenderMismatch)), "invalid_token");
+        assert_eq!(shadow_outcome(&Ok(())), "success");
+    }
+
+    #[tokio::test]
+    async fn test_unsampled_pushes_skip_the_shadow() {
+        let shadow = Arc::new(CountingProvider::default());
+        let provider = MultiPushProvider::new(Arc::new(CountingProvider::default()), Some(Arc::clone(&shadow)), 0);
+
+        for _ in 0..10 {
+            provider.send_push("token", PushKind::Check).await.expect("primary send succeeds");
+        }
+        tokio::task::yield_now().await;
+        assert_eq!(shadow.validations.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,58 @@
+use crate::domain::notification::PushKind;
+use async_trait::async_trait;
+use uuid::Uuid;
+
+pub mod postgres;
+
+pub use postgres::PostgresPushJobQueue;
+
+/// A leased job: the device to wake and why.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PushJob {
+    pub device_id: Uuid,
+    pub kind: PushKind,
+}
+
+/// Outcome of [`PushJobQueue::claim_delivery`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeliveryClaim {
+    /// The push may be sent.
+    Claimed,
+    /// An earlier lease already handed the push to the provider.
+    AlreadySent,
+    /// The job is no longer leased, because it was cancelled or completed meanwhile.
+    LeaseLost,
+}
+
+/// Durable queue of delayed push notification jobs, keyed by device.
+///
+/// A device has at most one pending job. Workers lease due jobs for a visibility timeout,
+/// claim delivery right before contacting the push provider, and complete the job afterwards.
+#[async_trait]
+pub trait PushJobQueue: Send + Sync + std::fmt::Debug {
+    /// Schedules a job of `kind` for each device to run after `delay_secs`.
+    /// Devices that already have a waiting job keep their original due time; a job that is
+    /// currently leased is made due again so the in-flight completion does not drop it.
+    /// A [`PushKind::PreKeyLow`] job upgrades the device's job to that kind and never runs
+    /// later than it would have on its own.
+    async fn push_jobs(&self, device_ids: &[Uuid], delay_secs: u64, kind: PushKind) -> anyhow::Result<()>;
+
+    /// Removes the job for a device regardless of its state.
+    async fn cancel_job(&self, device_id: Uuid) -> anyhow::Result<()>;
+
+    /// Leases up to `limit` due jobs for `timeout_secs`.
+    async fn lease_due_jobs(&self, limit: isize, timeout_secs: u64) -> anyhow::Result<Vec<PushJob>>;
+
+    /// Completes a leased job. Jobs rescheduled while leased are kept.
+    async fn delete_job(&self, device_id: Uuid) -> anyhow::Result<()>;
+
+    /// Extends the lease of a job. Returns `false` if the job is no longer leased.
+    async fn extend_lease(&self, device_id: Uuid, timeout_secs: u64) -> anyhow::Result<bool>;
+
+    /// Marks a leased job as handed to the push provider, unless an earlier lease already did so
+    /// or the job is no longer leased.
+    async fn claim_delivery(&self, device_id: Uuid) -> anyhow::Result<DeliveryClaim>;
+
+    /// Clears the delivery marker after a retryable failure.
+    async fn release_delivery(&self, device_id: Uuid) -> anyhow::Result<()>;
+}
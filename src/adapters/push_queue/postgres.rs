@@ -0,0 +1,149 @@
+use crate::adapters::database::DbPool;
+use crate::adapters::push_queue::{DeliveryClaim, PushJob, PushJobQueue};
+use crate::domain::notification::PushKind;
+use crate::telemetry;
+use async_trait::async_trait;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+/// Push job queue backed by the `push_jobs` table.
+///
+/// Workers poll with `FOR UPDATE SKIP LOCKED` so concurrent instances never lease the same
+/// row, and jobs survive a Redis flush. A job is due when its lease has expired, or when it
+/// has never been leased and its `run_at` has passed.
+#[derive(Clone, Debug)]
+pub struct PostgresPushJobQueue {
+    pool: DbPool,
+}
+
+impl PostgresPushJobQueue {
+    #[must_use]
+    pub const fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+}
+
+fn seconds_from_now(secs: u64) -> OffsetDateTime {
+    OffsetDateTime::now_utc() + time::Duration::seconds(i64::try_from(secs).unwrap_or(i64::MAX))
+}
+
+#[async_trait]
+impl PushJobQueue for PostgresPushJobQueue {
+    #[tracing::instrument(level = "debug", skip_all, fields(%delay_secs, ?kind), err)]
+    async fn push_jobs(&self, device_ids: &[Uuid], delay_secs: u64, kind: PushKind) -> anyhow::Result<()> {
+        if device_ids.is_empty() {
+            return Ok(());
+        }
+
+        // Joining against devices skips IDs deleted since the notify was issued instead of
+        // failing the whole batch on the foreign key. A waiting check job is left alone; any
+        // other kind upgrades it and pulls it forward.
+        sqlx::query(
+            r#"
+            INSERT INTO push_jobs (device_id, run_at, kind)
+            SELECT id, $2, $3 FROM devices WHERE id = ANY($1)
+            ON CONFLICT (device_id) DO UPDATE
+            SET run_at = CASE
+                    WHEN push_jobs.leased_until IS NULL THEN LEAST(push_jobs.run_at, EXCLUDED.run_at)
+                    ELSE EXCLUDED.run_at
+                END,
+                kind = CASE WHEN EXCLUDED.kind = 'check' THEN push_jobs.kind ELSE EXCLUDED.kind END,
+                leased_until = NULL,
+                delivered = FALSE
+            WHERE push_jobs.leased_until IS NOT NULL OR EXCLUDED.kind <> 'check'
+            "#,
+        )
+        .bind(device_ids)
+        .bind(seconds_from_now(delay_secs))
+        .bind(kind.as_str())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(device.id = %telemetry::id(device_id)), err)]
+    async fn cancel_job(&self, device_id: Uuid) -> anyhow::Result<()> {
+        sqlx::query("DELETE FROM push_jobs WHERE device_id = $1").bind(device_id).execute(&self.pool).await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(%limit, %timeout_secs), err)]
+    async fn lease_due_jobs(&self, limit: isize, timeout_secs: u64) -> anyhow::Result<Vec<PushJob>> {
+        let leased = sqlx::query_as::<_, (Uuid, String)>(
+            r#"
+            UPDATE push_jobs SET leased_until = $2
+            WHERE device_id IN (
+                SELECT device_id FROM push_jobs
+                WHERE COALESCE(leased_until, run_at) <= NOW()
+                ORDER BY COALESCE(leased_until, run_at)
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING device_id, kind
+            "#,
+        )
+        .bind(i64::try_from(limit).unwrap_or(0))
+        .bind(seconds_from_now(timeout_secs))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(leased
+            .into_iter()
+            .map(|(device_id, kind)| PushJob { device_id, kind: PushKind::from_stored(&kind) })
+            .collect())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(device.id = %telemetry::id(device_id)), err)]
+    async fn delete_job(&self, device_id: Uuid) -> anyhow::Result<()> {
+        sqlx::query("DELETE FROM push_jobs WHERE device_id = $1 AND leased_until IS NOT NULL")
+            .bind(device_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(device.id = %telemetry::id(device_id), %timeout_secs), err)]
+    async fn extend_lease(&self, device_id: Uuid, timeout_secs: u64) -> anyhow::Result<bool> {
+        let result =
+            sqlx::query("UPDATE push_jobs SET leased_until = $2 WHERE device_id = $1 AND leased_until IS NOT NULL")
+                .bind(device_id)
+                .bind(seconds_from_now(timeout_secs))
+                .execute(&self.pool)
+                .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(device.id = %telemetry::id(device_id)), err)]
+    async fn claim_delivery(&self, device_id: Uuid) -> anyhow::Result<DeliveryClaim> {
+        // The locking read sees the marker as a concurrent claim left it.
+        let was_delivered: Option<bool> = sqlx::query_scalar(
+            r#"
+            UPDATE push_jobs p SET delivered = TRUE
+            FROM (
+                SELECT device_id, delivered FROM push_jobs
+                WHERE device_id = $1 AND leased_until IS NOT NULL
+                FOR UPDATE
+            ) old
+            WHERE p.device_id = old.device_id
+            RETURNING old.delivered
+            "#,
+        )
+        .bind(device_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(match was_delivered {
+            Some(false) => DeliveryClaim::Claimed,
+            Some(true) => DeliveryClaim::AlreadySent,
+            None => DeliveryClaim::LeaseLost,
+        })
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(device.id = %telemetry::id(device_id)), err)]
+    async fn release_delivery(&self, device_id: Uuid) -> anyhow::Result<()> {
+        sqlx::query("UPDATE push_jobs SET delivered = FALSE WHERE device_id = $1")
+            .bind(device_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
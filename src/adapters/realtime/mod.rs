@@ -0,0 +1,29 @@
+use crate::domain::notification::{EventContext, RealtimeNotification, UserEvent};
+use async_trait::async_trait;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+pub mod postgres;
+
+pub use postgres::PostgresRealtimeBus;
+
+/// Carries realtime events between instances, so a session is woken wherever it is connected.
+///
+/// Delivery is best effort: an event published while an instance is disconnected from the bus
+/// is lost to it, and the push fallback covers the device instead.
+#[async_trait]
+pub trait RealtimeBus: Send + Sync + std::fmt::Debug {
+    /// Publishes an event with its context to each device.
+    async fn publish_realtime(
+        &self,
+        device_ids: &[Uuid],
+        event: UserEvent,
+        context: &EventContext,
+    ) -> anyhow::Result<()>;
+
+    /// Publishes an event addressed to every device on every instance.
+    async fn publish_broadcast(&self, event: UserEvent) -> anyhow::Result<()>;
+
+    /// Subscribes to the events published for all devices by every instance.
+    async fn subscribe_realtime(&self) -> anyhow::Result<broadcast::Receiver<RealtimeNotification>>;
+}
@@ -0,0 +1,146 @@
+use crate::adapters::database::DbPool;
+use crate::adapters::realtime::RealtimeBus;
+use crate::adapters::redis::event_payload;
+use crate::config::NotificationConfig;
+use crate::domain::notification::{EventContext, RealtimeNotification, UserEvent};
+use async_trait::async_trait;
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use sqlx::postgres::PgListener;
+use std::time::Duration;
+use tokio::sync::{broadcast, watch};
+use tracing::Instrument;
+use uuid::Uuid;
+
+/// Target, in place of a device ID, of events addressed to every device.
+const BROADCAST_TARGET: &str = "broadcast";
+
+/// How long to wait before listening again after the connection could not be restored.
+const RELISTEN_DELAY: Duration = Duration::from_secs(1);
+
+/// Realtime bus backed by Postgres `LISTEN`/`NOTIFY`, for deployments without Redis.
+///
+/// Every event goes out on one channel as `<device id or "broadcast">:<base64 event>`, in the
+/// same event encoding as the Redis bus. `NOTIFY` payloads are capped at 8000 bytes, far above
+/// any event, and each instance holds one extra connection outside the pool for listening.
+#[derive(Clone, Debug)]
+pub struct PostgresRealtimeBus {
+    pool: DbPool,
+    channel: String,
+    global_channel_capacity: usize,
+    shutdown: watch::Receiver<bool>,
+}
+
+impl PostgresRealtimeBus {
+    #[must_use]
+    pub fn new(pool: DbPool, config: &NotificationConfig, shutdown: watch::Receiver<bool>) -> Self {
+        Self {
+            pool,
+            channel: config.postgres_channel.clone(),
+            global_channel_capacity: config.global_channel_capacity,
+            shutdown,
+        }
+    }
+
+    fn encode(target: &str, event: UserEvent, context: &EventContext) -> String {
+        format!("{target}:{}", STANDARD.encode(event_payload::encode(event, context)))
+    }
+
+    fn decode(payload: &str) -> Option<RealtimeNotification> {
+        let (target, event) = payload.split_once(':')?;
+        let device_id = if target == BROADCAST_TARGET { None } else { Some(Uuid::parse_str(target).ok()?) };
+        let (event, context) = event_payload::decode(&STANDARD.decode(event).ok()?)?;
+        Some(RealtimeNotification { device_id, event, context })
+    }
+}
+
+#[async_trait]
+impl RealtimeBus for PostgresRealtimeBus {
+    #[tracing::instrument(level = "debug", skip_all, fields(?event), err)]
+    async fn publish_realtime(
+        &self,
+        device_ids: &[Uuid],
+        event: UserEvent,
+        context: &EventContext,
+    ) -> anyhow::Result<()> {
+        if device_ids.is_empty() {
+            return Ok(());
+        }
+        let payloads: Vec<String> =
+            device_ids.iter().map(|device_id| Self::encode(&device_id.to_string(), event, context)).collect();
+
+        sqlx::query("SELECT pg_notify($1, payload) FROM UNNEST($2::text[]) AS payload")
+            .bind(&self.channel)
+            .bind(&payloads)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(?event), err)]
+    async fn publish_broadcast(&self, event: UserEvent) -> anyhow::Result<()> {
+        sqlx::query("SELECT pg_notify($1, $2)")
+            .bind(&self.channel)
+            .bind(Self::encode(BROADCAST_TARGET, event, &EventContext::default()))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, err)]
+    async fn subscribe_realtime(&self) -> anyhow::Result<broadcast::Receiver<RealtimeNotification>> {
+        let mut listener = PgListener::connect_with(&self.pool).await?;
+        listener.listen(&self.channel).await?;
+
+        let (tx, rx) = broadcast::channel(self.global_channel_capacity);
+        let mut shutdown = self.shutdown.clone();
+        tokio::spawn(
+            async move {
+                loop {
+                    tokio::select! {
+                        _ = shutdown.changed() => break,
+                        // The listener reconnects and listens again by itself; events sent
+                        // while it was disconnected are lost.
+                        result = listener.recv() => match result {
+                            Ok(notification) => {
+                                let Some(notification) = Self::decode(notification.payload()) else {
+                                    continue;
+                                };
+                                if tx.send(notification).is_err() {
+                                    break;
+                                }
+                            }
+                            Err(e) => {
+                                tracing::warn!(error = %e, "Lost the notification listener connection, retrying");
+                                tokio::time::sleep(RELISTEN_DELAY).await;
+                            }
+                        },
+                    }
+                }
+            }
+            .instrument(tracing::debug_span!("pg_notification_listener")),
+        );
+
+        Ok(rx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload_round_trip() {
+        let device_id = Uuid::new_v4();
+        let context = EventContext::with_count(3);
+        let payload = PostgresRealtimeBus::encode(&device_id.to_string(), UserEvent::PreKeyLow, &context);
+
+        let notification = PostgresRealtimeBus::decode(&payload).expect("payload decodes");
+        assert_eq!(notification.device_id, Some(device_id));
+        assert_eq!(notification.event, UserEvent::PreKeyLow);
+        assert_eq!(notification.context, context);
+
+        let payload = PostgresRealtimeBus::encode(BROADCAST_TARGET, UserEvent::Announcement, &EventContext::default());
+        assert_eq!(PostgresRealtimeBus::decode(&payload).map(|n| n.device_id), Some(None));
+        assert!(PostgresRealtimeBus::decode("not-a-device:AQ==").is_none());
+    }
+}
@@ -0,0 +1,113 @@
+use crate::adapters::redis::RedisClient;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// A batch of acknowledged messages whose delete failed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpilledAck {
+    pub device_id: Uuid,
+    pub message_ids: Vec<Uuid>,
+}
+
+/// Acknowledged messages whose delete kept failing, held in a Redis list until the spill worker
+/// deletes them. Without it an acknowledged message would be delivered again on every
+/// reconnect.
+///
+/// The list is capped: when it is full, the oldest batches are dropped, and those messages are
+/// redelivered once, to be acknowledged again. Batches put back after a failed replay wait in a
+/// separate list that is not capped and is taken from first, so the cap never drops work the
+/// spill worker already held.
+#[derive(Debug, Clone)]
+pub struct AckSpill {
+    redis: Arc<RedisClient>,
+    key: String,
+    max_batches: usize,
+}
+
+impl AckSpill {
+    #[must_use]
+    pub const fn new(redis: Arc<RedisClient>, key: String, max_batches: usize) -> Self {
+        Self { redis, key, max_batches }
+    }
+
+    /// Appends a batch, dropping the oldest ones beyond the cap.
+    ///
+    /// # Errors
+    /// Returns an error if the Redis operation fails.
+    pub async fn push(&self, batch: &SpilledAck) -> anyhow::Result<()> {
+        let payload = serde_json::to_string(batch)?;
+        let mut conn = self.redis.request_conn();
+        let max_batches = isize::try_from(self.max_batches.max(1)).unwrap_or(isize::MAX);
+        let _: () = redis::pipe()
+            .atomic()
+            .rpush(&self.key, payload)
+            .ignore()
+            .ltrim(&self.key, -max_batches, -1)
+            .ignore()
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Puts batches taken with [`Self::take`] back, in their original order, ahead of everything
+    /// else and out of reach of the cap.
+    ///
+    /// # Errors
+    /// Returns an error if the Redis operation fails.
+    pub async fn requeue(&self, batches: &[SpilledAck]) -> anyhow::Result<()> {
+        if batches.is_empty() {
+            return Ok(());
+        }
+        // LPUSH puts each value at the head in turn, so the last one pushed ends up first
+        let payloads =
+            batches.iter().rev().map(serde_json::to_string).collect::<Result<Vec<_>, serde_json::Error>>()?;
+        let mut conn = self.redis.request_conn();
+        let _: () = redis::cmd("LPUSH").arg(self.requeued_key()).arg(payloads).query_async(&mut conn).await?;
+        Ok(())
+    }
+
+    fn requeued_key(&self) -> String {
+        format!("{}:requeued", self.key)
+    }
+
+    /// Removes and returns up to `count` of the oldest batches, requeued ones first. Entries that
+    /// cannot be decoded are logged and discarded.
+    ///
+    /// # Errors
+    /// Returns an error if the Redis operation fails.
+    pub async fn take(&self, count: usize) -> anyhow::Result<Vec<SpilledAck>> {
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        let mut conn = self.redis.publisher();
+        let requeued: Option<Vec<String>> =
+            redis::cmd("LPOP").arg(self.requeued_key()).arg(count).query_async(&mut conn).await?;
+        let mut entries = requeued.unwrap_or_default();
+        if entries.len() < count {
+            let spilled: Option<Vec<String>> =
+                redis::cmd("LPOP").arg(&self.key).arg(count - entries.len()).query_async(&mut conn).await?;
+            entries.extend(spilled.unwrap_or_default());
+        }
+
+        Ok(entries
+            .into_iter()
+            .filter_map(|entry| {
+                serde_json::from_str(&entry)
+                    .inspect_err(|e| tracing::warn!(error = %e, "Discarding undecodable spilled ACK batch"))
+                    .ok()
+            })
+            .collect())
+    }
+
+    /// Number of batches waiting to be replayed.
+    ///
+    /// # Errors
+    /// Returns an error if the Redis operation fails.
+    pub async fn len(&self) -> anyhow::Result<usize> {
+        let mut conn = self.redis.publisher();
+        let (spilled, requeued): (usize, usize) =
+            redis::pipe().llen(&self.key).llen(self.requeued_key()).query_async(&mut conn).await?;
+        Ok(spilled + requeued)
+    }
+}
@@ -0,0 +1,217 @@
+//! Wire format of realtime events on `PubSub`.
+//!
+//! Events were first published as their bare [`UserEvent`] byte. Events with context are an
+//! `EventPayload` protobuf, which always takes at least two bytes, so the length tells the two
+//! apart. Instances that predate the protobuf drop it, so `NotificationService` only publishes
+//! context with `--notifications-event-context`, to be enabled once every instance decodes it.
+//! Until then events go out as the single byte, except those that name a fallback event, which
+//! only the envelope can carry.
+//!
+//! The protobuf is a versioned envelope. Fields are only ever added, so a payload from a newer
+//! version is still read for the fields this instance knows, and an event kind this instance
+//! does not know is raised as the fallback event the publisher named, rather than dropped.
+//! Payloads read that way, or dropped anyway, are counted by reason.
+
+use crate::domain::notification::{EventContext, UserEvent};
+use opentelemetry::{KeyValue, global, metrics::Counter};
+use prost::Message;
+use std::sync::LazyLock;
+use uuid::Uuid;
+
+/// Envelope version this instance publishes. Envelopes without one predate versioning.
+const ENVELOPE_VERSION: u32 = 1;
+
+const COUNT_CONFIDENTIAL: u32 = 1;
+const SENDER_CONFIDENTIAL: u32 = 1 << 1;
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+#[derive(Clone, Debug)]
+struct Metrics {
+    unrecognized_total: Counter<u64>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let meter = global::meter("obscura-server");
+        Self {
+            unrecognized_total: meter
+                .u64_counter("obscura_pubsub_payloads_unrecognized_total")
+                .with_description("PubSub payloads not fully understood by this version, by how they were handled")
+                .build(),
+        }
+    }
+
+    fn unrecognized(&self, reason: &'static str) {
+        self.unrecognized_total.add(1, &[KeyValue::new("reason", reason)]);
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct EventPayload {
+    #[prost(uint32, tag = "1")]
+    event: u32,
+    #[prost(uint32, optional, tag = "2")]
+    count: Option<u32>,
+    /// Raw device ID bytes, empty without a hint.
+    #[prost(bytes = "vec", tag = "3")]
+    sender_hint: Vec<u8>,
+    #[prost(uint32, tag = "4")]
+    privacy_flags: u32,
+    /// Envelope version the publisher wrote, 0 before versioning.
+    #[prost(uint32, tag = "5")]
+    version: u32,
+    /// Event to raise on instances that do not know `event`.
+    #[prost(uint32, optional, tag = "6")]
+    fallback_event: Option<u32>,
+}
+
+/// The event an instance that does not know `event` should raise instead. Wakeups fall back to
+/// `MessageReceived`, which every version answers by fetching; the events every version knows,
+/// and those that only make sense as themselves, have none.
+const fn fallback(event: UserEvent) -> Option<UserEvent> {
+    match event {
+        UserEvent::PreKeyLow | UserEvent::Announcement => Some(UserEvent::MessageReceived),
+        UserEvent::MessageReceived | UserEvent::Disconnect | UserEvent::SessionReplaced => None,
+    }
+}
+
+fn known_event(event: u32) -> Option<UserEvent> {
+    u8::try_from(event).ok().and_then(|event| UserEvent::try_from(event).ok())
+}
+
+/// Encodes an event for publishing.
+pub(crate) fn encode(event: UserEvent, context: &EventContext) -> Vec<u8> {
+    let fallback_event = fallback(event);
+    if context.is_empty() && fallback_event.is_none() {
+        return vec![event as u8];
+    }
+
+    let mut privacy_flags = 0;
+    if context.count_confidential {
+        privacy_flags |= COUNT_CONFIDENTIAL;
+    }
+    if context.sender_confidential {
+        privacy_flags |= SENDER_CONFIDENTIAL;
+    }
+    EventPayload {
+        event: u32::from(event as u8),
+        count: context.count,
+        sender_hint: context.sender_hint.map(|id| id.as_bytes().to_vec()).unwrap_or_default(),
+        privacy_flags,
+        version: ENVELOPE_VERSION,
+        fallback_event: fallback_event.map(|event| u32::from(event as u8)),
+    }
+    .encode_to_vec()
+}
+
+/// Decodes a published event in either format. Returns `None` for payloads this instance
+/// cannot act on, such as event kinds added by a newer version that name no fallback.
+pub(crate) fn decode(payload: &[u8]) -> Option<(UserEvent, EventContext)> {
+    if let [byte] = payload {
+        let event = UserEvent::try_from(*byte).ok();
+        if event.is_none() {
+            METRICS.unrecognized("unknown_event");
+        }
+        return event.map(|event| (event, EventContext::default()));
+    }
+
+    let Ok(payload) = EventPayload::decode(payload) else {
+        METRICS.unrecognized("malformed");
+        return None;
+    };
+    if payload.version > ENVELOPE_VERSION {
+        METRICS.unrecognized("newer_version");
+    }
+    let Some(event) = known_event(payload.event) else {
+        // The context describes the original event, so it does not carry over to the fallback.
+        let fallback = payload.fallback_event.and_then(known_event);
+        METRICS.unrecognized(if fallback.is_some() { "fallback" } else { "unknown_event" });
+        return fallback.map(|event| (event, EventContext::default()));
+    };
+    let context = EventContext {
+        count: payload.count,
+        sender_hint: Uuid::from_slice(&payload.sender_hint).ok(),
+        count_confidential: payload.privacy_flags & COUNT_CONFIDENTIAL != 0,
+        sender_confidential: payload.privacy_flags & SENDER_CONFIDENTIAL != 0,
+    };
+    Some((event, context))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_without_context_stays_a_single_byte() {
+        let payload = encode(UserEvent::MessageReceived, &EventContext::default());
+        assert_eq!(payload, vec![UserEvent::MessageReceived as u8]);
+        assert_eq!(decode(&payload), Some((UserEvent::MessageReceived, EventContext::default())));
+    }
+
+    #[test]
+    fn test_events_with_a_fallback_keep_it_without_context() {
+        let payload = encode(UserEvent::Announcement, &EventContext::default());
+        assert_eq!(decode(&payload), Some((UserEvent::Announcement, EventContext::default())));
+
+        // An instance that does not know the event still raises its fallback.
+        let mut envelope = EventPayload::decode(payload.as_slice()).expect("an envelope");
+        assert_eq!(envelope.fallback_event, Some(u32::from(UserEvent::MessageReceived as u8)));
+        envelope.event = 200;
+        assert_eq!(decode(&envelope.encode_to_vec()), Some((UserEvent::MessageReceived, EventContext::default())));
+    }
+
+    #[test]
+    fn test_context_round_trips() {
+        let context = EventContext {
+            count: Some(7),
+            sender_hint: Some(Uuid::new_v4()),
+            count_confidential: false,
+            sender_confidential: true,
+        };
+        let payload = encode(UserEvent::PreKeyLow, &context);
+        assert!(payload.len() > 1);
+        assert_eq!(decode(&payload), Some((UserEvent::PreKeyLow, context)));
+    }
+
+    #[test]
+    fn test_unknown_events_are_rejected() {
+        assert_eq!(decode(&[]), None);
+        assert_eq!(decode(&[0xff]), None);
+        let unknown = EventPayload { event: 200, count: Some(1), ..EventPayload::default() };
+        assert_eq!(decode(&unknown.encode_to_vec()), None);
+    }
+
+    #[test]
+    fn test_unknown_events_raise_their_fallback() {
+        let newer = EventPayload {
+            event: 200,
+            count: Some(3),
+            version: ENVELOPE_VERSION + 1,
+            fallback_event: Some(u32::from(UserEvent::MessageReceived as u8)),
+            ..EventPayload::default()
+        };
+        assert_eq!(decode(&newer.encode_to_vec()), Some((UserEvent::MessageReceived, EventContext::default())));
+    }
+
+    #[test]
+    fn test_newer_versions_are_read_for_known_fields() {
+        let mut newer = EventPayload {
+            event: u32::from(UserEvent::PreKeyLow as u8),
+            count: Some(4),
+            version: ENVELOPE_VERSION + 1,
+            ..EventPayload::default()
+        }
+        .encode_to_vec();
+        // A field this version has never heard of.
+        newer.extend_from_slice(&[0xf8, 0x01, 0x2a]);
+        assert_eq!(decode(&newer), Some((UserEvent::PreKeyLow, EventContext::with_count(4))));
+    }
+
+    #[test]
+    fn test_envelopes_before_versioning_still_decode() {
+        let unversioned =
+            EventPayload { event: u32::from(UserEvent::PreKeyLow as u8), count: Some(2), ..EventPayload::default() };
+        assert_eq!(decode(&unversioned.encode_to_vec()), Some((UserEvent::PreKeyLow, EventContext::with_count(2))));
+    }
+}
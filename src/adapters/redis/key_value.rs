@@ -0,0 +1,140 @@
+use crate::adapters::key_value::KeyValueStore;
+use crate::adapters::redis::RedisClient;
+use async_trait::async_trait;
+use redis::AsyncCommands;
+use std::sync::LazyLock;
+
+/// Keys examined per `SCAN` round trip.
+const SCAN_COUNT: usize = 500;
+
+/// ARGV[1] = expected value, ARGV[2] = TTL in seconds.
+/// Extends the entry while it still holds the expected value and stores it again if it
+/// expired, so a Redis restart does not drop a live owner. Returns 0 if the key holds
+/// something else.
+static REFRESH_SCRIPT: LazyLock<redis::Script> = LazyLock::new(|| {
+    redis::Script::new(
+        r#"
+        local current = redis.call('GET', KEYS[1])
+        if current == ARGV[1] then
+            return redis.call('EXPIRE', KEYS[1], ARGV[2])
+        elseif not current then
+            redis.call('SET', KEYS[1], ARGV[1], 'EX', ARGV[2])
+            return 1
+        end
+        return 0
+        "#,
+    )
+});
+
+/// ARGV[1] = expected value. Deletes the entry only if it still holds the expected value.
+static DELETE_IF_SCRIPT: LazyLock<redis::Script> = LazyLock::new(|| {
+    redis::Script::new(
+        r#"
+        if redis.call('GET', KEYS[1]) == ARGV[1] then
+            return redis.call('DEL', KEYS[1])
+        end
+        return 0
+        "#,
+    )
+});
+
+impl RedisClient {
+    /// Collects every key matching `pattern`, each once.
+    async fn scan_keys(&self, pattern: &str) -> anyhow::Result<Vec<String>> {
+        let mut conn = self.publisher();
+        let mut keys = Vec::new();
+        let mut cursor = 0_u64;
+        loop {
+            let (next, batch): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(pattern)
+                .arg("COUNT")
+                .arg(SCAN_COUNT)
+                .query_async(&mut conn)
+                .await?;
+            keys.extend(batch);
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        // SCAN may return a key more than once.
+        keys.sort_unstable();
+        keys.dedup();
+        Ok(keys)
+    }
+}
+
+#[async_trait]
+impl KeyValueStore for RedisClient {
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let mut conn = self.request_conn();
+        let value: Option<Vec<u8>> = conn.get(key).await?;
+        Ok(value)
+    }
+
+    async fn set(&self, key: &str, value: &[u8], ttl_secs: u64) -> anyhow::Result<()> {
+        let mut conn = self.request_conn();
+        let _: () = conn.set_ex(key, value, ttl_secs).await?;
+        Ok(())
+    }
+
+    async fn set_if_absent(&self, key: &str, value: &[u8], ttl_secs: u64) -> anyhow::Result<bool> {
+        let mut conn = self.request_conn();
+        let stored: Option<String> =
+            redis::cmd("SET").arg(key).arg(value).arg("NX").arg("EX").arg(ttl_secs).query_async(&mut conn).await?;
+        Ok(stored.is_some())
+    }
+
+    async fn take(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let mut conn = self.request_conn();
+        let value: Option<Vec<u8>> = redis::cmd("GETDEL").arg(key).query_async(&mut conn).await?;
+        Ok(value)
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        let mut conn = self.request_conn();
+        let _: () = conn.del(key).await?;
+        Ok(())
+    }
+
+    async fn refresh_if(&self, key: &str, value: &[u8], ttl_secs: u64) -> anyhow::Result<bool> {
+        let mut conn = self.request_conn();
+        let refreshed: i64 = REFRESH_SCRIPT.key(key).arg(value).arg(ttl_secs).invoke_async(&mut conn).await?;
+        Ok(refreshed == 1)
+    }
+
+    async fn delete_if(&self, key: &str, value: &[u8]) -> anyhow::Result<()> {
+        let mut conn = self.request_conn();
+        let _: i64 = DELETE_IF_SCRIPT.key(key).arg(value).invoke_async(&mut conn).await?;
+        Ok(())
+    }
+
+    async fn scan(&self, prefix: &str) -> anyhow::Result<Vec<Vec<u8>>> {
+        let keys = self.scan_keys(&format!("{prefix}*")).await?;
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+        // Entries that expired since the scan come back empty.
+        let mut conn = self.request_conn();
+        let values: Vec<Option<Vec<u8>>> = redis::cmd("MGET").arg(&keys).query_async(&mut conn).await?;
+        Ok(values.into_iter().flatten().collect())
+    }
+
+    async fn enforce_ttl(&self, prefix: &str, ttl_secs: u64) -> anyhow::Result<u64> {
+        let keys = self.scan_keys(&format!("{prefix}*")).await?;
+        let mut repaired = 0;
+        let mut conn = self.publisher();
+        for chunk in keys.chunks(SCAN_COUNT) {
+            // NX only sets an expiry on keys without one, so a concurrent write keeps its own.
+            let mut pipe = redis::pipe();
+            for key in chunk {
+                pipe.cmd("EXPIRE").arg(key).arg(ttl_secs).arg("NX");
+            }
+            let updated: Vec<u64> = pipe.query_async(&mut conn).await?;
+            repaired += updated.iter().sum::<u64>();
+        }
+        Ok(repaired)
+    }
+}
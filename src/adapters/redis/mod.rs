@@ -1,17 +1,27 @@
+use crate::adapters::retry::RetryPolicy;
 use crate::config::PubSubConfig;
-use backon::{ExponentialBuilder, Retryable};
 use dashmap::DashMap;
 use futures::StreamExt;
+use futures::future::try_join_all;
+use redis::aio::{ConnectionManager, ConnectionManagerConfig};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 use tokio::sync::{broadcast, watch};
 use tracing::Instrument;
 
-pub mod cache;
+pub mod ack_spill;
+pub(crate) mod event_payload;
+mod key_value;
 pub mod notification_repo;
 
-pub use cache::RedisCache;
+pub use ack_spill::AckSpill;
 pub use notification_repo::NotificationRepository;
 
+/// Attempts to subscribe a pattern, or to resubscribe after losing the connection, before its
+/// listener gives up.
+const SUBSCRIBE_ATTEMPTS: u32 = 4;
+
 #[derive(Debug, Clone)]
 pub struct PubSubMessage {
     pub channel: String,
@@ -20,7 +30,10 @@ pub struct PubSubMessage {
 
 #[derive(Debug)]
 pub struct RedisClient {
-    publisher: redis::aio::ConnectionManager,
+    publisher: ConnectionManager,
+    /// Connections for commands issued while serving requests, handed out in turn.
+    request_conns: Vec<ConnectionManager>,
+    next_request_conn: AtomicUsize,
     // Maps patterns (e.g. "user:*") to broadcast senders
     subscriptions: Arc<DashMap<String, broadcast::Sender<PubSubMessage>>>,
     client: redis::Client,
@@ -41,20 +54,51 @@ impl RedisClient {
     ) -> anyhow::Result<Arc<Self>> {
         let client = redis::Client::open(config.url.as_str())?;
         let publisher = client.get_connection_manager().await?;
+        let request_conns = try_join_all(
+            (0..config.request_pool_size.max(1))
+                .map(|_| client.get_connection_manager_with_config(Self::request_conn_config(config))),
+        )
+        .await?;
         let subscriptions = Arc::new(DashMap::new());
 
-        let redis_client =
-            Arc::new(Self { publisher, subscriptions, client, shutdown, channel_capacity, config: config.clone() });
+        let redis_client = Arc::new(Self {
+            publisher,
+            request_conns,
+            next_request_conn: AtomicUsize::new(0),
+            subscriptions,
+            client,
+            shutdown,
+            channel_capacity,
+            config: config.clone(),
+        });
 
         Ok(redis_client)
     }
 
+    fn request_conn_config(config: &PubSubConfig) -> ConnectionManagerConfig {
+        let timeout = |ms: u64| (ms > 0).then(|| Duration::from_millis(ms));
+        ConnectionManagerConfig::new()
+            .set_response_timeout(timeout(config.command_timeout_ms))
+            .set_connection_timeout(timeout(config.connect_timeout_ms))
+    }
+
     /// Returns a publisher connection that can be used for standard Redis commands.
     #[must_use]
-    pub fn publisher(&self) -> redis::aio::ConnectionManager {
+    pub fn publisher(&self) -> ConnectionManager {
         self.publisher.clone()
     }
 
+    /// Returns the next connection of the request pool, for commands a client is waiting on.
+    ///
+    /// Each connection multiplexes its callers over one socket, so spreading them over a few
+    /// keeps one slow reply from holding up every request behind it. Commands fail once the
+    /// configured command timeout passes without a reply.
+    #[must_use]
+    pub fn request_conn(&self) -> ConnectionManager {
+        let next = self.next_request_conn.fetch_add(1, Ordering::Relaxed);
+        self.request_conns[next % self.request_conns.len()].clone()
+    }
+
     /// Subscribes to a Redis pattern.
     /// If a background listener for this pattern isn't already running, it will be started.
     ///
@@ -101,27 +145,26 @@ impl RedisClient {
         config: PubSubConfig,
         ready_tx: tokio::sync::oneshot::Sender<()>,
     ) {
-        let retry_strategy = ExponentialBuilder::default()
-            .with_min_delay(std::time::Duration::from_secs(config.min_backoff_secs))
-            .with_max_delay(std::time::Duration::from_secs(config.max_backoff_secs));
+        let retry = RetryPolicy::with_delays(
+            "redis_pubsub",
+            SUBSCRIBE_ATTEMPTS,
+            Duration::from_secs(config.min_backoff_secs),
+            Duration::from_secs(config.max_backoff_secs),
+        );
 
         let mut ready_tx = Some(ready_tx);
 
         loop {
-            let pubsub_result = (|| async {
-                let mut pubsub = client.get_async_pubsub().await?;
-                pubsub.psubscribe(&pattern).await?;
-                Ok::<redis::aio::PubSub, redis::RedisError>(pubsub)
-            })
-            .retry(&retry_strategy)
-            .when(|e| {
-                tracing::warn!(error = %e, "Failed to subscribe to pubsub, retrying...");
-                true
-            })
-            .notify(|e, duration| {
-                tracing::debug!("Pubsub subscription retry in {:?} due to error: {:?}", duration, e);
-            })
-            .await;
+            let pubsub_result = retry
+                .run(
+                    || async {
+                        let mut pubsub = client.get_async_pubsub().await?;
+                        pubsub.psubscribe(&pattern).await?;
+                        Ok::<redis::aio::PubSub, redis::RedisError>(pubsub)
+                    },
+                    |_| true,
+                )
+                .await;
 
             let pubsub: redis::aio::PubSub = match pubsub_result {
                 Ok(ps) => ps,
@@ -1,16 +1,165 @@
+use crate::adapters::push_queue::{DeliveryClaim, PushJob, PushJobQueue};
+use crate::adapters::realtime::RealtimeBus;
 use crate::adapters::redis::RedisClient;
+use crate::adapters::redis::event_payload;
 use crate::config::NotificationConfig;
-use crate::domain::notification::{RealtimeNotification, UserEvent};
-use redis::AsyncCommands;
-use std::sync::Arc;
+use crate::domain::notification::{EventContext, PushKind, RealtimeNotification, UserEvent};
+use crate::telemetry;
+use async_trait::async_trait;
+use std::sync::{Arc, LazyLock};
 use tokio::sync::broadcast;
 use uuid::Uuid;
 
+// Push job state lives in four keys that the scripts below always touch together:
+//   KEYS[1] - sorted set of device IDs scored by the unix time the job becomes due
+//   KEYS[2] - hash of device IDs that are currently leased by a worker
+//   KEYS[3] - hash of device IDs whose current job has already been handed to the push provider
+//   KEYS[4] - hash of device IDs whose job is of a kind other than a plain check, to that kind
+// Running every transition server-side keeps a concurrent notify, ACK and lease from
+// interleaving between round trips. `redis::Script` invokes via EVALSHA and only falls
+// back to loading the script when Redis reports it missing.
+
+/// ARGV[1] = `run_at`, ARGV[2] = kind, empty for a plain check, ARGV[3..] = device IDs.
+/// A job already waiting keeps its original due time, unless a kind is given, which pulls it
+/// forward to `run_at` if that is sooner. A job that is currently leased is pulled back to
+/// `run_at` so the in-flight completion does not swallow the new event. New and rescheduled
+/// jobs start without a delivery marker.
+static SCHEDULE_SCRIPT: LazyLock<redis::Script> = LazyLock::new(|| {
+    redis::Script::new(
+        r#"
+        for i = 3, #ARGV do
+            if redis.call('HDEL', KEYS[2], ARGV[i]) == 1 then
+                redis.call('ZADD', KEYS[1], ARGV[1], ARGV[i])
+                redis.call('HDEL', KEYS[3], ARGV[i])
+            elseif redis.call('ZADD', KEYS[1], 'NX', ARGV[1], ARGV[i]) == 1 then
+                redis.call('HDEL', KEYS[3], ARGV[i])
+            elseif ARGV[2] ~= '' then
+                redis.call('ZADD', KEYS[1], 'LT', ARGV[1], ARGV[i])
+            end
+            if ARGV[2] ~= '' then
+                redis.call('HSET', KEYS[4], ARGV[i], ARGV[2])
+            end
+        end
+        return 0
+        "#,
+    )
+});
+
+/// ARGV[1] = device ID. Removes the job regardless of its lease state.
+static CANCEL_SCRIPT: LazyLock<redis::Script> = LazyLock::new(|| {
+    redis::Script::new(
+        r#"
+        redis.call('HDEL', KEYS[2], ARGV[1])
+        redis.call('HDEL', KEYS[3], ARGV[1])
+        redis.call('HDEL', KEYS[4], ARGV[1])
+        return redis.call('ZREM', KEYS[1], ARGV[1])
+        "#,
+    )
+});
+
+/// ARGV[1] = now, ARGV[2] = limit, ARGV[3] = lease expiry.
+/// Moves due jobs to the lease expiry score and records them as leased.
+/// Returns device IDs alternating with their kind, empty for a plain check.
+static LEASE_SCRIPT: LazyLock<redis::Script> = LazyLock::new(|| {
+    redis::Script::new(
+        r#"
+        local jobs = redis.call('ZRANGEBYSCORE', KEYS[1], '-inf', ARGV[1], 'LIMIT', 0, ARGV[2])
+        local leased = {}
+        for _, job in ipairs(jobs) do
+            redis.call('ZADD', KEYS[1], ARGV[3], job)
+            redis.call('HSET', KEYS[2], job, ARGV[3])
+            table.insert(leased, job)
+            table.insert(leased, redis.call('HGET', KEYS[4], job) or '')
+        end
+        return leased
+        "#,
+    )
+});
+
+/// ARGV[1] = device ID. Removes the job only if it is still leased; a job that was
+/// rescheduled while the push was in flight stays queued.
+static COMPLETE_SCRIPT: LazyLock<redis::Script> = LazyLock::new(|| {
+    redis::Script::new(
+        r#"
+        if redis.call('HDEL', KEYS[2], ARGV[1]) == 1 then
+            redis.call('HDEL', KEYS[3], ARGV[1])
+            redis.call('HDEL', KEYS[4], ARGV[1])
+            return redis.call('ZREM', KEYS[1], ARGV[1])
+        end
+        return 0
+        "#,
+    )
+});
+
+/// ARGV[1] = device ID, ARGV[2] = new lease expiry.
+/// Pushes the lease of a job out while its push is still in flight. Returns 0 if the job is
+/// no longer leased (completed, cancelled or rescheduled), in which case nothing changes.
+static EXTEND_LEASE_SCRIPT: LazyLock<redis::Script> = LazyLock::new(|| {
+    redis::Script::new(
+        r#"
+        if redis.call('HEXISTS', KEYS[2], ARGV[1]) == 0 then
+            return 0
+        end
+        redis.call('ZADD', KEYS[1], 'XX', ARGV[2], ARGV[1])
+        redis.call('HSET', KEYS[2], ARGV[1], ARGV[2])
+        return 1
+        "#,
+    )
+});
+
+/// ARGV[1] = device ID. Marks the current job as handed to the push provider.
+/// Returns 0 if a previous lease already did so, meaning the push must not be sent again,
+/// and -1 if the job is no longer leased.
+static CLAIM_DELIVERY_SCRIPT: LazyLock<redis::Script> = LazyLock::new(|| {
+    redis::Script::new(
+        r#"
+        if redis.call('HEXISTS', KEYS[2], ARGV[1]) == 0 then
+            return -1
+        end
+        return redis.call('HSETNX', KEYS[3], ARGV[1], 1)
+        "#,
+    )
+});
+
+/// KEYS[1] = the queue, KEYS[2] = one of the per-job hashes, ARGV = fields of that hash.
+/// Deletes the fields whose device has no job in the queue, which only a worker or instance
+/// dying between round trips can leave behind. Returns how many were deleted.
+static PRUNE_ORPHANS_SCRIPT: LazyLock<redis::Script> = LazyLock::new(|| {
+    redis::Script::new(
+        r#"
+        local pruned = 0
+        for i = 1, #ARGV do
+            if not redis.call('ZSCORE', KEYS[1], ARGV[i]) then
+                pruned = pruned + redis.call('HDEL', KEYS[2], ARGV[i])
+            end
+        end
+        return pruned
+        "#,
+    )
+});
+
+/// Hash fields examined per `HSCAN` round trip when pruning orphans.
+const HSCAN_COUNT: usize = 500;
+
+/// Per-job hash entries whose job was no longer queued, found by one orphan sweep.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OrphanedJobState {
+    pub leases: u64,
+    pub delivery_markers: u64,
+    pub kinds: u64,
+}
+
+/// Channel suffix, after the configured prefix, for events addressed to every device.
+const BROADCAST_CHANNEL: &str = "broadcast";
+
 #[derive(Debug, Clone)]
 pub struct NotificationRepository {
     redis: Arc<RedisClient>,
     channel_prefix: String,
     push_queue_key: String,
+    lease_key: String,
+    delivered_key: String,
+    kinds_key: String,
     global_channel_capacity: usize,
 }
 
@@ -21,20 +170,28 @@ impl NotificationRepository {
             redis,
             channel_prefix: config.channel_prefix.clone(),
             push_queue_key: config.push_queue_key.clone(),
+            lease_key: format!("{}:leases", config.push_queue_key),
+            delivered_key: format!("{}:delivered", config.push_queue_key),
+            kinds_key: format!("{}:kinds", config.push_queue_key),
             global_channel_capacity: config.global_channel_capacity,
         }
     }
 
-    /// Publishes a realtime event to multiple devices using a pipeline.
+    /// Publishes a realtime event with its context to multiple devices using a pipeline.
     ///
     /// # Errors
     /// Returns an error if the Redis operation fails.
-    #[tracing::instrument(level = "debug", skip(self, device_ids), err)]
-    pub async fn publish_realtime(&self, device_ids: &[Uuid], event: UserEvent) -> anyhow::Result<()> {
+    #[tracing::instrument(level = "debug", skip_all, fields(?event), err)]
+    pub async fn publish_realtime(
+        &self,
+        device_ids: &[Uuid],
+        event: UserEvent,
+        context: &EventContext,
+    ) -> anyhow::Result<()> {
         if device_ids.is_empty() {
             return Ok(());
         }
-        let payload = [event as u8];
+        let payload = event_payload::encode(event, context);
         let mut pipe = redis::pipe();
 
         for device_id in device_ids {
@@ -42,7 +199,23 @@ impl NotificationRepository {
             pipe.publish(&channel_name, &payload);
         }
 
-        let mut conn = self.redis.publisher();
+        let mut conn = self.redis.request_conn();
+        let _: () = pipe.query_async(&mut conn).await?;
+        Ok(())
+    }
+
+    /// Publishes a realtime event addressed to every device on every instance.
+    ///
+    /// # Errors
+    /// Returns an error if the Redis operation fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(?event), err)]
+    pub async fn publish_broadcast(&self, event: UserEvent) -> anyhow::Result<()> {
+        let channel_name = format!("{}{BROADCAST_CHANNEL}", self.channel_prefix);
+        let payload = event_payload::encode(event, &EventContext::default());
+        let mut pipe = redis::pipe();
+        pipe.publish(&channel_name, &payload);
+
+        let mut conn = self.redis.request_conn();
         let _: () = pipe.query_async(&mut conn).await?;
         Ok(())
     }
@@ -51,7 +224,7 @@ impl NotificationRepository {
     ///
     /// # Errors
     /// Returns an error if the subscription fails.
-    #[tracing::instrument(level = "debug", skip(self), err)]
+    #[tracing::instrument(level = "debug", skip_all, err)]
     pub async fn subscribe_realtime(&self) -> anyhow::Result<broadcast::Receiver<RealtimeNotification>> {
         let pattern = format!("{}*", self.channel_prefix);
         let mut redis_rx = self.redis.subscribe(&pattern).await?;
@@ -61,12 +234,18 @@ impl NotificationRepository {
         // Spawn a mapper task to translate technical PubSubMessages into domain RealtimeNotifications
         tokio::spawn(async move {
             while let Ok(msg) = redis_rx.recv().await {
-                if let Some(device_id_str) = msg.channel.strip_prefix(&prefix)
-                    && let Ok(device_id) = Uuid::parse_str(device_id_str)
-                    && let Some(payload_byte) = msg.payload.first()
-                    && let Ok(event) = UserEvent::try_from(*payload_byte)
-                {
-                    let _ = tx.send(RealtimeNotification { device_id, event });
+                let Some(suffix) = msg.channel.strip_prefix(&prefix) else {
+                    continue;
+                };
+                let device_id = if suffix == BROADCAST_CHANNEL {
+                    None
+                } else if let Ok(device_id) = Uuid::parse_str(suffix) {
+                    Some(device_id)
+                } else {
+                    continue;
+                };
+                if let Some((event, context)) = event_payload::decode(&msg.payload) {
+                    let _ = tx.send(RealtimeNotification { device_id, event, context });
                 }
             }
         });
@@ -74,26 +253,33 @@ impl NotificationRepository {
         Ok(rx)
     }
 
-    /// Schedules push notification jobs for multiple devices using a pipeline.
+    /// Schedules push notification jobs for multiple devices in a single atomic script call.
     ///
     /// # Errors
     /// Returns an error if the Redis operation fails.
-    #[allow(clippy::cast_precision_loss)]
-    #[tracing::instrument(level = "debug", skip(self, device_ids), err)]
-    pub async fn push_jobs(&self, device_ids: &[Uuid], delay_secs: u64) -> anyhow::Result<()> {
+    #[tracing::instrument(level = "debug", skip_all, fields(%delay_secs, ?kind), err)]
+    pub async fn push_jobs(&self, device_ids: &[Uuid], delay_secs: u64, kind: PushKind) -> anyhow::Result<()> {
         if device_ids.is_empty() {
             return Ok(());
         }
 
         let run_at = time::OffsetDateTime::now_utc().unix_timestamp() + i64::try_from(delay_secs).unwrap_or(0);
-        let mut pipe = redis::pipe();
 
+        let kind = if kind == PushKind::Check { "" } else { kind.as_str() };
+        let mut invocation = SCHEDULE_SCRIPT.prepare_invoke();
+        invocation
+            .key(&self.push_queue_key)
+            .key(&self.lease_key)
+            .key(&self.delivered_key)
+            .key(&self.kinds_key)
+            .arg(run_at)
+            .arg(kind);
         for device_id in device_ids {
-            pipe.cmd("ZADD").arg(&self.push_queue_key).arg("NX").arg(run_at as f64).arg(device_id.to_string());
+            invocation.arg(device_id.to_string());
         }
 
-        let mut conn = self.redis.publisher();
-        let _: () = pipe.query_async(&mut conn).await?;
+        let mut conn = self.redis.request_conn();
+        let _: i64 = invocation.invoke_async(&mut conn).await?;
         Ok(())
     }
 
@@ -101,10 +287,17 @@ impl NotificationRepository {
     ///
     /// # Errors
     /// Returns an error if the Redis operation fails.
-    #[tracing::instrument(level = "debug", skip(self), err)]
+    #[tracing::instrument(level = "debug", skip_all, fields(device.id = %telemetry::id(device_id)), err)]
     pub async fn cancel_job(&self, device_id: Uuid) -> anyhow::Result<()> {
-        let mut conn = self.redis.publisher();
-        let _: i64 = conn.zrem(&self.push_queue_key, device_id.to_string()).await?;
+        let mut conn = self.redis.request_conn();
+        let _: i64 = CANCEL_SCRIPT
+            .key(&self.push_queue_key)
+            .key(&self.lease_key)
+            .key(&self.delivered_key)
+            .key(&self.kinds_key)
+            .arg(device_id.to_string())
+            .invoke_async(&mut conn)
+            .await?;
         Ok(())
     }
 
@@ -112,45 +305,202 @@ impl NotificationRepository {
     ///
     /// # Errors
     /// Returns an error if the Redis operation fails.
-    #[allow(clippy::cast_precision_loss)]
-    #[tracing::instrument(level = "debug", skip(self), err)]
-    pub async fn lease_due_jobs(&self, limit: isize, timeout_secs: u64) -> anyhow::Result<Vec<Uuid>> {
-        let now = time::OffsetDateTime::now_utc().unix_timestamp() as f64;
-        let lease_until = now + timeout_secs as f64;
+    #[tracing::instrument(level = "debug", skip_all, fields(%limit, %timeout_secs), err)]
+    pub async fn lease_due_jobs(&self, limit: isize, timeout_secs: u64) -> anyhow::Result<Vec<PushJob>> {
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        let lease_until = now + i64::try_from(timeout_secs).unwrap_or(i64::MAX - now);
         let mut conn = self.redis.publisher();
 
-        // Lua Script:
-        // 1. Get candidates using ZRANGEBYSCORE
-        // 2. Loop through candidates and ZADD them with the new score
-        // 3. Return the candidates
-        let script = redis::Script::new(
-            r#"
-            local jobs = redis.call('ZRANGEBYSCORE', KEYS[1], '-inf', ARGV[1], 'LIMIT', 0, ARGV[2])
-            if #jobs > 0 then
-                for _, job in ipairs(jobs) do
-                    redis.call('ZADD', KEYS[1], ARGV[3], job)
-                end
-            end
-            return jobs
-            "#,
-        );
-
-        let candidates: Vec<String> =
-            script.key(&self.push_queue_key).arg(now).arg(limit).arg(lease_until).invoke_async(&mut conn).await?;
+        let candidates: Vec<(String, String)> = LEASE_SCRIPT
+            .key(&self.push_queue_key)
+            .key(&self.lease_key)
+            .key(&self.delivered_key)
+            .key(&self.kinds_key)
+            .arg(now)
+            .arg(limit)
+            .arg(lease_until)
+            .invoke_async(&mut conn)
+            .await?;
 
-        let leased = candidates.into_iter().filter_map(|s| Uuid::parse_str(&s).ok()).collect();
+        let leased = candidates
+            .into_iter()
+            .filter_map(|(id, kind)| {
+                Uuid::parse_str(&id).ok().map(|device_id| PushJob { device_id, kind: PushKind::from_stored(&kind) })
+            })
+            .collect();
 
         Ok(leased)
     }
 
-    /// Deletes a push notification job from the queue (finalizing it).
+    /// Deletes a leased push notification job from the queue (finalizing it).
+    /// Jobs that were rescheduled while leased are left in place to be delivered again.
     ///
     /// # Errors
     /// Returns an error if the Redis operation fails.
-    #[tracing::instrument(level = "debug", skip(self), err)]
+    #[tracing::instrument(level = "debug", skip_all, fields(device.id = %telemetry::id(device_id)), err)]
     pub async fn delete_job(&self, device_id: Uuid) -> anyhow::Result<()> {
         let mut conn = self.redis.publisher();
-        let _: i64 = conn.zrem(&self.push_queue_key, device_id.to_string()).await?;
+        let _: i64 = COMPLETE_SCRIPT
+            .key(&self.push_queue_key)
+            .key(&self.lease_key)
+            .key(&self.delivered_key)
+            .key(&self.kinds_key)
+            .arg(device_id.to_string())
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Extends the lease on a job whose push is still in flight.
+    /// Returns `false` if the job is no longer leased and the heartbeat should stop.
+    ///
+    /// # Errors
+    /// Returns an error if the Redis operation fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(device.id = %telemetry::id(device_id), %timeout_secs), err)]
+    pub async fn extend_lease(&self, device_id: Uuid, timeout_secs: u64) -> anyhow::Result<bool> {
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        let lease_until = now + i64::try_from(timeout_secs).unwrap_or(i64::MAX - now);
+        let mut conn = self.redis.publisher();
+        let extended: i64 = EXTEND_LEASE_SCRIPT
+            .key(&self.push_queue_key)
+            .key(&self.lease_key)
+            .key(&self.delivered_key)
+            .key(&self.kinds_key)
+            .arg(device_id.to_string())
+            .arg(lease_until)
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(extended == 1)
+    }
+
+    /// Records that the push for a leased job is about to be sent.
+    /// Returns [`DeliveryClaim::AlreadySent`] if an earlier lease already sent it, e.g. before a
+    /// worker crashed without completing the job, in which case the caller should complete it
+    /// without sending, and [`DeliveryClaim::LeaseLost`] if the job is no longer leased.
+    ///
+    /// # Errors
+    /// Returns an error if the Redis operation fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(device.id = %telemetry::id(device_id)), err)]
+    pub async fn claim_delivery(&self, device_id: Uuid) -> anyhow::Result<DeliveryClaim> {
+        let mut conn = self.redis.publisher();
+        let claimed: i64 = CLAIM_DELIVERY_SCRIPT
+            .key(&self.push_queue_key)
+            .key(&self.lease_key)
+            .key(&self.delivered_key)
+            .key(&self.kinds_key)
+            .arg(device_id.to_string())
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(match claimed {
+            1 => DeliveryClaim::Claimed,
+            0 => DeliveryClaim::AlreadySent,
+            _ => DeliveryClaim::LeaseLost,
+        })
+    }
+
+    /// Clears the delivery marker after a retryable failure so the next lease can send again.
+    ///
+    /// # Errors
+    /// Returns an error if the Redis operation fails.
+    #[tracing::instrument(level = "debug", skip_all, fields(device.id = %telemetry::id(device_id)), err)]
+    pub async fn release_delivery(&self, device_id: Uuid) -> anyhow::Result<()> {
+        let mut conn = self.redis.publisher();
+        let _: i64 =
+            redis::cmd("HDEL").arg(&self.delivered_key).arg(device_id.to_string()).query_async(&mut conn).await?;
         Ok(())
     }
+
+    /// Deletes lease, delivery marker and kind entries whose device no longer has a queued job.
+    ///
+    /// # Errors
+    /// Returns an error if the Redis operation fails.
+    #[tracing::instrument(level = "debug", skip_all, err)]
+    pub async fn prune_orphaned_job_state(&self) -> anyhow::Result<OrphanedJobState> {
+        Ok(OrphanedJobState {
+            leases: self.prune_orphans(&self.lease_key).await?,
+            delivery_markers: self.prune_orphans(&self.delivered_key).await?,
+            kinds: self.prune_orphans(&self.kinds_key).await?,
+        })
+    }
+
+    async fn prune_orphans(&self, hash_key: &str) -> anyhow::Result<u64> {
+        let mut conn = self.redis.publisher();
+        let mut cursor = 0_u64;
+        let mut pruned = 0;
+        loop {
+            let (next, entries): (u64, Vec<(String, String)>) = redis::cmd("HSCAN")
+                .arg(hash_key)
+                .arg(cursor)
+                .arg("COUNT")
+                .arg(HSCAN_COUNT)
+                .query_async(&mut conn)
+                .await?;
+
+            if !entries.is_empty() {
+                let mut invocation = PRUNE_ORPHANS_SCRIPT.prepare_invoke();
+                invocation.key(&self.push_queue_key).key(hash_key);
+                for (device_id, _) in &entries {
+                    invocation.arg(device_id);
+                }
+                let count: u64 = invocation.invoke_async(&mut conn).await?;
+                pruned += count;
+            }
+
+            if next == 0 {
+                return Ok(pruned);
+            }
+            cursor = next;
+        }
+    }
+}
+
+#[async_trait]
+impl PushJobQueue for NotificationRepository {
+    async fn push_jobs(&self, device_ids: &[Uuid], delay_secs: u64, kind: PushKind) -> anyhow::Result<()> {
+        Self::push_jobs(self, device_ids, delay_secs, kind).await
+    }
+
+    async fn cancel_job(&self, device_id: Uuid) -> anyhow::Result<()> {
+        Self::cancel_job(self, device_id).await
+    }
+
+    async fn lease_due_jobs(&self, limit: isize, timeout_secs: u64) -> anyhow::Result<Vec<PushJob>> {
+        Self::lease_due_jobs(self, limit, timeout_secs).await
+    }
+
+    async fn delete_job(&self, device_id: Uuid) -> anyhow::Result<()> {
+        Self::delete_job(self, device_id).await
+    }
+
+    async fn extend_lease(&self, device_id: Uuid, timeout_secs: u64) -> anyhow::Result<bool> {
+        Self::extend_lease(self, device_id, timeout_secs).await
+    }
+
+    async fn claim_delivery(&self, device_id: Uuid) -> anyhow::Result<DeliveryClaim> {
+        Self::claim_delivery(self, device_id).await
+    }
+
+    async fn release_delivery(&self, device_id: Uuid) -> anyhow::Result<()> {
+        Self::release_delivery(self, device_id).await
+    }
+}
+
+#[async_trait]
+impl RealtimeBus for NotificationRepository {
+    async fn publish_realtime(
+        &self,
+        device_ids: &[Uuid],
+        event: UserEvent,
+        context: &EventContext,
+    ) -> anyhow::Result<()> {
+        Self::publish_realtime(self, device_ids, event, context).await
+    }
+
+    async fn publish_broadcast(&self, event: UserEvent) -> anyhow::Result<()> {
+        Self::publish_broadcast(self, event).await
+    }
+
+    async fn subscribe_realtime(&self) -> anyhow::Result<broadcast::Receiver<RealtimeNotification>> {
+        Self::subscribe_realtime(self).await
+    }
 }
@@ -0,0 +1,286 @@
+use crate::adapters::push::{PushError, PushProvider};
+use crate::adapters::storage::{ObjectInfo, ObjectStorage, ObjectSummary, StorageError, StorageResult, StorageStream};
+use crate::config::RetryConfig;
+use crate::domain::notification::PushKind;
+use crate::error::AppError;
+use async_trait::async_trait;
+use backon::{ExponentialBuilder, Retryable};
+use opentelemetry::{KeyValue, global, metrics::Counter};
+use std::fmt::Display;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// SQLSTATE codes for transactions Postgres aborted and that are safe to run again from the start.
+const SERIALIZATION_FAILURE: &str = "40001";
+const DEADLOCK_DETECTED: &str = "40P01";
+
+#[derive(Clone, Debug)]
+struct Metrics {
+    retries_total: Counter<u64>,
+    exhausted_total: Counter<u64>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let meter = global::meter("obscura-server");
+        Self {
+            retries_total: meter
+                .u64_counter("obscura_retry_attempts_total")
+                .with_description("Retries of transient dependency failures, labelled by dependency")
+                .build(),
+            exhausted_total: meter
+                .u64_counter("obscura_retry_exhausted_total")
+                .with_description("Calls that still failed transiently after using their whole retry budget")
+                .build(),
+        }
+    }
+}
+
+/// Jittered exponential backoff with a per-dependency attempt budget.
+///
+/// Every adapter that retries goes through this type so backoff behaviour and the retry
+/// metrics stay consistent; only the decision of which errors are transient is left to the caller.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    dependency: &'static str,
+    max_attempts: u32,
+    backoff: ExponentialBuilder,
+    metrics: Metrics,
+}
+
+impl RetryPolicy {
+    /// Creates a policy allowing `max_attempts` calls in total. One attempt disables retries.
+    #[must_use]
+    pub fn new(dependency: &'static str, max_attempts: u32, config: &RetryConfig) -> Self {
+        Self::with_delays(
+            dependency,
+            max_attempts,
+            Duration::from_millis(config.min_delay_ms),
+            Duration::from_millis(config.max_delay_ms),
+        )
+    }
+
+    /// Like [`Self::new`], for a dependency with backoff delays of its own.
+    #[must_use]
+    pub fn with_delays(dependency: &'static str, max_attempts: u32, min_delay: Duration, max_delay: Duration) -> Self {
+        let max_attempts = max_attempts.max(1);
+        let backoff = ExponentialBuilder::default()
+            .with_min_delay(min_delay)
+            .with_max_delay(max_delay.max(min_delay))
+            .with_max_times((max_attempts - 1) as usize)
+            .with_jitter();
+        Self { dependency, max_attempts, backoff, metrics: Metrics::new() }
+    }
+
+    /// Runs `op`, calling it again after a backoff while it fails with an error `is_transient` accepts.
+    ///
+    /// # Errors
+    /// Returns the last error once it is not transient or the attempt budget is spent.
+    pub async fn run<T, E, F, Fut>(&self, op: F, is_transient: impl Fn(&E) -> bool) -> Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: Display,
+    {
+        let mut retries = 0u32;
+        let result = op
+            .retry(&self.backoff)
+            .when(&is_transient)
+            .notify(|e, delay| {
+                retries += 1;
+                self.metrics.retries_total.add(1, &[KeyValue::new("dependency", self.dependency)]);
+                tracing::debug!(
+                    dependency = self.dependency,
+                    error = %e,
+                    delay_ms = %delay.as_millis(),
+                    "Retrying transient failure"
+                );
+            })
+            .await;
+
+        if let Err(e) = &result
+            && is_transient(e)
+            && retries + 1 >= self.max_attempts
+        {
+            self.metrics.exhausted_total.add(1, &[KeyValue::new("dependency", self.dependency)]);
+            tracing::warn!(
+                dependency = self.dependency,
+                attempts = self.max_attempts,
+                error = %e,
+                "Retry budget exhausted"
+            );
+        }
+
+        result
+    }
+}
+
+/// Returns `true` if Postgres aborted the transaction because of a serialization failure or deadlock.
+#[must_use]
+pub fn is_transient_db_error(error: &AppError) -> bool {
+    match error {
+        AppError::Database(sqlx::Error::Database(db)) => {
+            matches!(db.code().as_deref(), Some(SERIALIZATION_FAILURE | DEADLOCK_DETECTED))
+        }
+        _ => false,
+    }
+}
+
+/// Retries push sends that failed with a transport or provider-side error.
+/// Unregistered tokens, quota errors and rejected requests are definitive answers and are returned immediately.
+#[derive(Debug)]
+pub struct RetryingPushProvider {
+    inner: Arc<dyn PushProvider>,
+    policy: RetryPolicy,
+}
+
+impl RetryingPushProvider {
+    #[must_use]
+    pub fn new(inner: Arc<dyn PushProvider>, policy: RetryPolicy) -> Self {
+        Self { inner, policy }
+    }
+}
+
+#[async_trait]
+impl PushProvider for RetryingPushProvider {
+    async fn send_push(&self, token: &str, kind: PushKind) -> Result<(), PushError> {
+        self.policy.run(|| self.inner.send_push(token, kind), PushError::is_transient).await
+    }
+
+    async fn validate_push(&self, token: &str, kind: PushKind) -> Result<(), PushError> {
+        self.inner.validate_push(token, kind).await
+    }
+
+    async fn check_credentials(&self) -> Result<(), PushError> {
+        self.inner.check_credentials().await
+    }
+}
+
+/// Retries idempotent object storage calls that failed with an internal error.
+/// Uploads pass straight through: their body is a one-shot stream that cannot be replayed.
+pub struct RetryingStorage {
+    inner: Arc<dyn ObjectStorage>,
+    policy: RetryPolicy,
+}
+
+impl RetryingStorage {
+    #[must_use]
+    pub fn new(inner: Arc<dyn ObjectStorage>, policy: RetryPolicy) -> Self {
+        Self { inner, policy }
+    }
+}
+
+impl std::fmt::Debug for RetryingStorage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RetryingStorage").field("policy", &self.policy).finish_non_exhaustive()
+    }
+}
+
+const fn is_transient_storage_error(error: &StorageError) -> bool {
+    matches!(error, StorageError::Internal(_))
+}
+
+#[async_trait]
+impl ObjectStorage for RetryingStorage {
+    async fn put(
+        &self,
+        key: &str,
+        stream: StorageStream,
+        content_len: Option<usize>,
+        min_size: usize,
+        max_size: usize,
+        sha256: Option<[u8; 32]>,
+    ) -> StorageResult<u64> {
+        self.inner.put(key, stream, content_len, min_size, max_size, sha256).await
+    }
+
+    async fn get(&self, key: &str) -> StorageResult<(u64, StorageStream)> {
+        self.policy.run(|| self.inner.get(key), is_transient_storage_error).await
+    }
+
+    async fn head(&self, key: &str) -> StorageResult<ObjectInfo> {
+        self.policy.run(|| self.inner.head(key), is_transient_storage_error).await
+    }
+
+    async fn delete(&self, key: &str) -> StorageResult<()> {
+        self.policy.run(|| self.inner.delete(key), is_transient_storage_error).await
+    }
+
+    async fn delete_many(&self, keys: &[String]) -> StorageResult<Vec<String>> {
+        self.policy.run(|| self.inner.delete_many(keys), is_transient_storage_error).await
+    }
+
+    async fn list(&self, prefix: &str, start_after: Option<&str>, limit: usize) -> StorageResult<Vec<ObjectSummary>> {
+        self.policy.run(|| self.inner.list(prefix, start_after, limit), is_transient_storage_error).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn policy(max_attempts: u32) -> RetryPolicy {
+        let config = RetryConfig { min_delay_ms: 1, max_delay_ms: 1, ..RetryConfig::default() };
+        RetryPolicy::new("test", max_attempts, &config)
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_errors_until_success() {
+        let calls = AtomicU32::new(0);
+        let result = policy(3)
+            .run(
+                || async { if calls.fetch_add(1, Ordering::SeqCst) < 2 { Err("transient") } else { Ok(()) } },
+                |_| true,
+            )
+            .await;
+        assert!(result.is_ok());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_stops_at_attempt_budget() {
+        let calls = AtomicU32::new(0);
+        let result: Result<(), _> = policy(2)
+            .run(
+                || async {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    Err("transient")
+                },
+                |_| true,
+            )
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_permanent_errors_are_not_retried() {
+        let calls = AtomicU32::new(0);
+        let result: Result<(), _> = policy(5)
+            .run(
+                || async {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    Err("permanent")
+                },
+                |_| false,
+            )
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_only_transient_push_errors_are_retried() {
+        assert!(PushError::Transient(anyhow::anyhow!("connection reset")).is_transient());
+        assert!(!PushError::Other(anyhow::anyhow!("HTTP 400")).is_transient());
+        assert!(!PushError::Unregistered.is_transient());
+        assert!(!PushError::QuotaExceeded { retry_after: None }.is_transient());
+    }
+
+    #[test]
+    fn test_non_database_errors_are_not_transient() {
+        assert!(!is_transient_db_error(&AppError::NotFound));
+        assert!(!is_transient_db_error(&AppError::Database(sqlx::Error::RowNotFound)));
+    }
+}
@@ -2,19 +2,27 @@ use async_trait::async_trait;
 use bytes::Bytes;
 use futures::stream::BoxStream;
 use thiserror::Error;
+use time::OffsetDateTime;
 
 pub mod s3;
 
 pub use s3::S3Storage;
 
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum StorageError {
     #[error("Storage limit exceeded")]
     ExceedsLimit,
     #[error("Upload size below minimum required")]
     BelowMinSize,
+    #[error("Content checksum mismatch")]
+    ChecksumMismatch,
     #[error("Object not found")]
     NotFound,
+    #[error("Storage unavailable")]
+    Unavailable,
+    #[error("Storage transfer timed out")]
+    TimedOut,
     #[error("Internal storage error: {0}")]
     Internal(String),
 }
@@ -23,6 +31,21 @@ pub type StorageResult<T> = Result<T, StorageError>;
 /// A neutral byte stream that uses `std::io::Error` to avoid coupling to the application's error types.
 pub type StorageStream = BoxStream<'static, Result<Bytes, std::io::Error>>;
 
+/// Metadata returned by a `HEAD` on a stored object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectInfo {
+    pub len: u64,
+    /// Hex-encoded SHA-256 of the content, if the uploader supplied one that was verified.
+    pub sha256: Option<String>,
+}
+
+/// An entry of a prefix listing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectSummary {
+    pub key: String,
+    pub last_modified: Option<OffsetDateTime>,
+}
+
 #[async_trait]
 pub trait ObjectStorage: Send + Sync + 'static {
     async fn put(
@@ -32,8 +55,14 @@ pub trait ObjectStorage: Send + Sync + 'static {
         content_len: Option<usize>,
         min_size: usize,
         max_size: usize,
+        sha256: Option<[u8; 32]>,
     ) -> StorageResult<u64>;
     async fn get(&self, key: &str) -> StorageResult<(u64, StorageStream)>;
-    async fn head(&self, key: &str) -> StorageResult<u64>;
+    async fn head(&self, key: &str) -> StorageResult<ObjectInfo>;
     async fn delete(&self, key: &str) -> StorageResult<()>;
+    /// Deletes several objects with as few requests as the backend allows.
+    /// Missing objects count as deleted. Returns the keys that could not be deleted.
+    async fn delete_many(&self, keys: &[String]) -> StorageResult<Vec<String>>;
+    /// Lists up to `limit` objects under `prefix` in key order, starting after `start_after`.
+    async fn list(&self, prefix: &str, start_after: Option<&str>, limit: usize) -> StorageResult<Vec<ObjectSummary>>;
 }
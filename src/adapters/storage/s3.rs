@@ -1,24 +1,201 @@
-use crate::adapters::storage::{ObjectStorage, StorageError, StorageResult, StorageStream};
+use crate::adapters::storage::{ObjectInfo, ObjectStorage, ObjectSummary, StorageError, StorageResult, StorageStream};
 use async_trait::async_trait;
 use aws_sdk_s3::Client;
 use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::{Delete, ObjectIdentifier};
+use base64::Engine;
+use bytes::Bytes;
 use futures::StreamExt;
 use http_body_util::StreamBody;
+use opentelemetry::{global, metrics::UpDownCounter};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use tokio::sync::mpsc;
+use std::time::Duration;
+use time::OffsetDateTime;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, mpsc};
+use tokio::time::{Instant, error::Elapsed};
 use tracing::Instrument;
 
+/// User metadata key under which a verified client-supplied SHA-256 is stored.
+const SHA256_METADATA_KEY: &str = "sha256";
+
+/// Most keys S3 accepts in a single `DeleteObjects` request.
+const MAX_DELETE_BATCH: usize = 1000;
+
+/// `DeleteObjects` requests in flight at once for a single `delete_many`.
+const DELETE_CONCURRENCY: usize = 4;
+
+#[derive(Clone, Debug)]
+struct Metrics {
+    upload_buffered_bytes: UpDownCounter<i64>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let meter = global::meter("obscura-server");
+        Self {
+            upload_buffered_bytes: meter
+                .i64_up_down_counter("obscura_storage_upload_buffered_bytes")
+                .with_description("Bytes of uploads read from clients that S3 has not taken yet")
+                .build(),
+        }
+    }
+}
+
+/// Aborts the task when dropped, where a bare [`tokio::task::JoinHandle`] would detach it.
+struct AbortOnDrop<T>(tokio::task::JoinHandle<T>);
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct S3Storage {
     client: Client,
     bucket: String,
+    timeouts: TransferTimeouts,
+    upload_buffer: UploadBuffer,
+    metrics: Metrics,
 }
 
 impl S3Storage {
     #[must_use]
-    pub const fn new(client: Client, bucket: String) -> Self {
-        Self { client, bucket }
+    pub fn new(client: Client, bucket: String) -> Self {
+        Self {
+            client,
+            bucket,
+            timeouts: TransferTimeouts { idle: None, total: None },
+            upload_buffer: UploadBuffer::DEFAULT,
+            metrics: Metrics::new(),
+        }
+    }
+
+    /// Deletes up to [`MAX_DELETE_BATCH`] keys with one `DeleteObjects` request.
+    async fn delete_batch(&self, keys: &[String]) -> StorageResult<Vec<String>> {
+        let objects = keys
+            .iter()
+            .map(|key| ObjectIdentifier::builder().key(key).build())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| StorageError::Internal(e.to_string()))?;
+        let delete = Delete::builder()
+            .set_objects(Some(objects))
+            .quiet(true)
+            .build()
+            .map_err(|e| StorageError::Internal(e.to_string()))?;
+
+        let output = self
+            .client
+            .delete_objects()
+            .bucket(&self.bucket)
+            .delete(delete)
+            .send()
+            .await
+            .map_err(|e| StorageError::Internal(e.to_string()))?;
+
+        // Quiet mode only reports the keys that failed
+        Ok(output
+            .errors()
+            .iter()
+            .filter_map(|error| {
+                tracing::warn!(key = ?error.key(), code = ?error.code(), "S3 batch delete failed for key");
+                error.key().map(ToOwned::to_owned)
+            })
+            .collect())
+    }
+
+    /// Aborts streamed transfers that make no progress for `idle`, or that run longer than `total`.
+    /// A zero duration disables the respective limit.
+    #[must_use]
+    pub const fn with_timeouts(mut self, idle: Duration, total: Duration) -> Self {
+        self.timeouts = TransferTimeouts {
+            idle: if idle.is_zero() { None } else { Some(idle) },
+            total: if total.is_zero() { None } else { Some(total) },
+        };
+        self
+    }
+
+    /// Splits upload chunks larger than `max_chunk` bytes, and stops reading an upload from the
+    /// client while `max_buffered` of its bytes wait for S3. The buffer is raised to twice the
+    /// chunk size if it is smaller, since the last chunk is held back until the checksum is known.
+    #[must_use]
+    pub fn with_upload_buffer(mut self, max_chunk: usize, max_buffered: usize) -> Self {
+        let max_chunk = max_chunk.clamp(1, usize::try_from(u32::MAX).unwrap_or(usize::MAX));
+        self.upload_buffer = UploadBuffer {
+            max_chunk,
+            max_buffered: max_buffered.max(max_chunk.saturating_mul(2)).min(Semaphore::MAX_PERMITS),
+        };
+        self
+    }
+}
+
+/// Memory a single `put` may hold between the client and S3.
+#[derive(Clone, Copy, Debug)]
+struct UploadBuffer {
+    max_chunk: usize,
+    max_buffered: usize,
+}
+
+impl UploadBuffer {
+    const DEFAULT: Self = Self { max_chunk: 256 * 1024, max_buffered: 1024 * 1024 };
+
+    /// Frames queued for S3 at most; the byte budget is what normally fills up first.
+    fn frames(self) -> usize {
+        (self.max_buffered / self.max_chunk).max(1)
+    }
+}
+
+/// A chunk of an upload between the client and S3, counted against the upload's buffer until
+/// S3 takes it.
+struct BufferedChunk {
+    _permit: OwnedSemaphorePermit,
+    len: i64,
+    gauge: UpDownCounter<i64>,
+}
+
+impl BufferedChunk {
+    fn new(permit: OwnedSemaphorePermit, len: usize, gauge: &UpDownCounter<i64>) -> Self {
+        let len = i64::try_from(len).unwrap_or(i64::MAX);
+        gauge.add(len, &[]);
+        Self { _permit: permit, len, gauge: gauge.clone() }
+    }
+}
+
+impl Drop for BufferedChunk {
+    fn drop(&mut self) {
+        self.gauge.add(-self.len, &[]);
+    }
+}
+
+/// Limits applied to the byte streams of a single `put` or `get`.
+#[derive(Clone, Copy, Debug)]
+struct TransferTimeouts {
+    idle: Option<Duration>,
+    total: Option<Duration>,
+}
+
+impl TransferTimeouts {
+    fn deadline(self) -> Option<Instant> {
+        self.total.map(|total| Instant::now() + total)
+    }
+
+    /// Deadline for the next step of a transfer: the idle timeout, capped by the transfer deadline.
+    fn step_deadline(self, deadline: Option<Instant>) -> Option<Instant> {
+        let idle = self.idle.map(|idle| Instant::now() + idle);
+        match (idle, deadline) {
+            (Some(idle), Some(deadline)) => Some(idle.min(deadline)),
+            (idle, deadline) => idle.or(deadline),
+        }
+    }
+}
+
+async fn until<F: Future>(deadline: Option<Instant>, future: F) -> Result<F::Output, Elapsed> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, future).await,
+        None => Ok(future.await),
     }
 }
 
@@ -27,8 +204,8 @@ impl ObjectStorage for S3Storage {
     #[tracing::instrument(
         level = "debug",
         err,
-        skip(self, stream),
-        fields(key = %key, bucket = %self.bucket)
+        skip_all,
+        fields(key = %key, bucket = %self.bucket, ?content_len, %min_size, %max_size, ?sha256)
     )]
     async fn put(
         &self,
@@ -37,77 +214,189 @@ impl ObjectStorage for S3Storage {
         content_len: Option<usize>,
         min_size: usize,
         max_size: usize,
+        sha256: Option<[u8; 32]>,
     ) -> StorageResult<u64> {
-        let (tx, rx) = mpsc::channel(2);
+        let upload_buffer = self.upload_buffer;
+        let (tx, rx) = mpsc::channel(upload_buffer.frames());
+        let budget = Arc::new(Semaphore::new(upload_buffer.max_buffered));
+        let buffered_gauge = self.metrics.upload_buffered_bytes.clone();
         let limit_exceeded = Arc::new(AtomicBool::new(false));
+        let checksum_mismatch = Arc::new(AtomicBool::new(false));
+        let timed_out = Arc::new(AtomicBool::new(false));
         let total_uploaded = Arc::new(AtomicU64::new(0));
 
         let limit_signal = Arc::clone(&limit_exceeded);
+        let checksum_signal = Arc::clone(&checksum_mismatch);
+        let timeout_signal = Arc::clone(&timed_out);
         let total_signal = Arc::clone(&total_uploaded);
 
-        let bridge_handle = tokio::spawn(
+        let timeouts = self.timeouts;
+        let deadline = timeouts.deadline();
+
+        // Aborted if this upload is dropped, e.g. by a request timeout, so the bridge stops reading
+        // the client's body and the unfinished request to S3 is abandoned with it.
+        let mut bridge_handle = AbortOnDrop(tokio::spawn(
             async move {
                 let mut current_total = 0;
-                while let Some(item) = stream.next().await {
-                    match item {
-                        Ok(bytes) => {
+                let mut hasher = sha256.map(|_| Sha256::new());
+                // The newest chunk is held back until the next one arrives, so a checksum mismatch found at
+                // the end of the stream can still abort the request before S3 has received the whole object.
+                let mut held_back: Option<(Bytes, BufferedChunk)> = None;
+                loop {
+                    // Neither the client nor S3 may stall the transfer: both reading the next chunk
+                    // and handing the previous one over must finish within the idle timeout. Once S3
+                    // drops the body, e.g. after rejecting the request, the client is not read any further.
+                    let next = tokio::select! {
+                        next = until(timeouts.step_deadline(deadline), stream.next()) => next,
+                        () = tx.closed() => return,
+                    };
+                    let Ok(next) = next else {
+                        tracing::warn!("Upload stream stalled in bridge task");
+                        timeout_signal.store(true, Ordering::SeqCst);
+                        let err: Box<dyn std::error::Error + Send + Sync> =
+                            Box::new(std::io::Error::from(std::io::ErrorKind::TimedOut));
+                        let _ = tx.try_send(Err(err));
+                        return;
+                    };
+                    match next {
+                        Some(Ok(mut bytes)) => {
                             current_total += u64::try_from(bytes.len()).unwrap_or(0);
                             if current_total > u64::try_from(max_size).unwrap_or(u64::MAX) {
                                 tracing::warn!(current_total = %current_total, max_size = %max_size, "Size limit exceeded in bridge task");
                                 limit_signal.store(true, Ordering::SeqCst);
-                                break;
+                                return;
                             }
                             total_signal.store(current_total, Ordering::SeqCst);
-                            if tx.send(Ok(http_body::Frame::data(bytes))).await.is_err() {
-                                break;
+                            if let Some(hasher) = hasher.as_mut() {
+                                hasher.update(&bytes);
+                            }
+                            while !bytes.is_empty() {
+                                let piece = bytes.split_to(bytes.len().min(upload_buffer.max_chunk));
+                                // Waits for S3 to take earlier chunks while the upload's buffer is full
+                                let permits = u32::try_from(piece.len()).unwrap_or(u32::MAX);
+                                let admit = Arc::clone(&budget).acquire_many_owned(permits);
+                                let admitted = tokio::select! {
+                                    admitted = until(timeouts.step_deadline(deadline), admit) => admitted,
+                                    () = tx.closed() => return,
+                                };
+                                let Ok(Ok(permit)) = admitted else {
+                                    tracing::warn!("Storage stopped accepting upload data in bridge task");
+                                    timeout_signal.store(true, Ordering::SeqCst);
+                                    return;
+                                };
+                                let buffered = BufferedChunk::new(permit, piece.len(), &buffered_gauge);
+                                if let Some((previous, buffered)) = held_back.replace((piece, buffered)) {
+                                    let send = tx.send(Ok((http_body::Frame::data(previous), buffered)));
+                                    match until(timeouts.step_deadline(deadline), send).await {
+                                        Ok(Ok(())) => {}
+                                        Ok(Err(_)) => return,
+                                        Err(_) => {
+                                            tracing::warn!("Storage stopped accepting upload data in bridge task");
+                                            timeout_signal.store(true, Ordering::SeqCst);
+                                            return;
+                                        }
+                                    }
+                                }
                             }
                         }
-                        Err(e) => {
+                        Some(Err(e)) => {
                             let err: Box<dyn std::error::Error + Send + Sync> = Box::new(e);
                             let _ = tx.send(Err(err)).await;
-                            break;
+                            return;
                         }
+                        None => break,
                     }
                 }
+
+                if let (Some(expected), Some(hasher)) = (sha256, hasher)
+                    && hasher.finalize().as_slice() != expected
+                {
+                    tracing::warn!("Content checksum mismatch in bridge task");
+                    checksum_signal.store(true, Ordering::SeqCst);
+                    let err: Box<dyn std::error::Error + Send + Sync> =
+                        Box::new(std::io::Error::other("content checksum mismatch"));
+                    let _ = tx.send(Err(err)).await;
+                    return;
+                }
+
+                if let Some((last, buffered)) = held_back {
+                    let _ = tx.send(Ok((http_body::Frame::data(last), buffered))).await;
+                }
             }
             .instrument(tracing::info_span!("s3_upload_bridge")),
-        );
+        ));
 
-        let stream_body = StreamBody::new(tokio_stream::wrappers::ReceiverStream::new(rx));
+        // A chunk leaves the upload's buffer as soon as the SDK takes it off the stream
+        let frames = tokio_stream::wrappers::ReceiverStream::new(rx).map(|item| item.map(|(frame, _buffered)| frame));
+        let stream_body = StreamBody::new(frames);
         let byte_stream = ByteStream::from_body_1_x(stream_body);
 
-        let res = self
+        let request = self
             .client
             .put_object()
             .bucket(&self.bucket)
             .key(key)
             .set_content_length(content_len.map(|l| i64::try_from(l).unwrap_or(i64::MAX)))
+            .set_metadata(sha256.map(|digest| HashMap::from([(SHA256_METADATA_KEY.to_string(), hex::encode(digest))])))
+            // S3 checks the digest itself, so it never stores other content under a content-addressed key
+            .set_checksum_sha256(sha256.map(|digest| base64::engine::general_purpose::STANDARD.encode(digest)))
             .body(byte_stream)
-            .send()
-            .await;
+            .send();
+
+        let Ok(res) = until(deadline, request).await else {
+            tracing::warn!(key = %key, "S3 upload exceeded the transfer deadline");
+            bridge_handle.0.abort();
+            return Err(StorageError::TimedOut);
+        };
+
+        // A content-addressed object S3 accepted matches its digest, so it is complete, and it may
+        // back other attachments: only objects under a key of their own are deleted on failure.
+        let owns_key = sha256.is_none();
 
         // PRIORITIZE: Check if we manually triggered a size limit abortion
         if limit_exceeded.load(Ordering::SeqCst) {
             return Err(StorageError::ExceedsLimit);
         }
+        if timed_out.load(Ordering::SeqCst) {
+            // The body may have ended early without S3 noticing; never keep a truncated object
+            if res.is_ok() && owns_key {
+                let _ = self.delete(key).await;
+            }
+            return Err(StorageError::TimedOut);
+        }
+        if checksum_mismatch.load(Ordering::SeqCst) {
+            if res.is_ok() && owns_key {
+                let _ = self.delete(key).await;
+            }
+            return Err(StorageError::ChecksumMismatch);
+        }
 
         match res {
             Ok(_) => {
                 let final_total = total_uploaded.load(Ordering::SeqCst);
                 if final_total < u64::try_from(min_size).unwrap_or(0) {
                     // Cleanup failed upload
-                    let _ = self.delete(key).await;
+                    if owns_key {
+                        let _ = self.delete(key).await;
+                    }
                     return Err(StorageError::BelowMinSize);
                 }
                 Ok(final_total)
             }
             Err(e) => {
-                // If S3 failed but our flag wasn't set yet, wait a tiny bit for the bridge task to finish its check
-                if !bridge_handle.is_finished() {
-                    let _ = bridge_handle.await;
+                // If S3 failed but our flag wasn't set yet, let the bridge task finish its check. The failed request
+                // dropped the body, so the bridge stops at once instead of reading the rest of the client's upload.
+                if !bridge_handle.0.is_finished() {
+                    let _ = (&mut bridge_handle.0).await;
                     if limit_exceeded.load(Ordering::SeqCst) {
                         return Err(StorageError::ExceedsLimit);
                     }
+                    if checksum_mismatch.load(Ordering::SeqCst) {
+                        return Err(StorageError::ChecksumMismatch);
+                    }
+                    if timed_out.load(Ordering::SeqCst) {
+                        return Err(StorageError::TimedOut);
+                    }
                 }
 
                 tracing::error!(error = ?e, key = %key, "S3 Upload failed");
@@ -119,30 +408,47 @@ impl ObjectStorage for S3Storage {
     #[tracing::instrument(
         level = "debug",
         err,
-        skip(self),
+        skip_all,
         fields(key = %key, bucket = %self.bucket)
     )]
     async fn get(&self, key: &str) -> StorageResult<(u64, StorageStream)> {
-        let output = self.client.get_object().bucket(&self.bucket).key(key).send().await.map_err(|e| {
-            if let aws_sdk_s3::error::SdkError::ServiceError(ref err) = e
-                && err.err().is_no_such_key()
-            {
-                return StorageError::NotFound;
-            }
-            StorageError::Internal(e.to_string())
-        })?;
+        let timeouts = self.timeouts;
+        let deadline = timeouts.deadline();
+
+        let request = self.client.get_object().bucket(&self.bucket).key(key).send();
+        let output = until(timeouts.step_deadline(deadline), request)
+            .await
+            .map_err(|_| {
+                tracing::warn!(key = %key, "S3 download did not start in time");
+                StorageError::TimedOut
+            })?
+            .map_err(|e| {
+                if let aws_sdk_s3::error::SdkError::ServiceError(ref err) = e
+                    && err.err().is_no_such_key()
+                {
+                    return StorageError::NotFound;
+                }
+                StorageError::Internal(e.to_string())
+            })?;
 
         let content_length = output.content_length.unwrap_or(0);
 
+        // The response is already streaming when a timeout hits, so the stream ends with an error
+        // and the connection is cut rather than reporting a status.
         let sdk_stream = output.body;
-        let stream = futures::stream::unfold(sdk_stream, |mut s| async move {
-            match s.next().await {
-                Some(Ok(bytes)) => Some((Ok(bytes), s)),
-                Some(Err(e)) => {
+        let stream = futures::stream::unfold(Some(sdk_stream), move |state| async move {
+            let mut s = state?;
+            match until(timeouts.step_deadline(deadline), s.next()).await {
+                Ok(Some(Ok(bytes))) => Some((Ok(bytes), Some(s))),
+                Ok(Some(Err(e))) => {
                     tracing::error!(error = ?e, "S3 Stream error");
-                    Some((Err(std::io::Error::other(e.to_string())), s))
+                    Some((Err(std::io::Error::other(e.to_string())), Some(s)))
+                }
+                Ok(None) => None,
+                Err(_) => {
+                    tracing::warn!("S3 download stream timed out");
+                    Some((Err(std::io::Error::from(std::io::ErrorKind::TimedOut)), None))
                 }
-                None => None,
             }
         })
         .boxed();
@@ -153,10 +459,10 @@ impl ObjectStorage for S3Storage {
     #[tracing::instrument(
         level = "debug",
         err,
-        skip(self),
+        skip_all,
         fields(key = %key, bucket = %self.bucket)
     )]
-    async fn head(&self, key: &str) -> StorageResult<u64> {
+    async fn head(&self, key: &str) -> StorageResult<ObjectInfo> {
         let output = self.client.head_object().bucket(&self.bucket).key(key).send().await.map_err(|e| {
             if let aws_sdk_s3::error::SdkError::ServiceError(ref err) = e
                 && err.err().is_not_found()
@@ -165,14 +471,15 @@ impl ObjectStorage for S3Storage {
             }
             StorageError::Internal(e.to_string())
         })?;
-        let len = output.content_length.unwrap_or(0);
-        Ok(u64::try_from(len).unwrap_or(0))
+        let len = u64::try_from(output.content_length.unwrap_or(0)).unwrap_or(0);
+        let sha256 = output.metadata().and_then(|m| m.get(SHA256_METADATA_KEY)).cloned();
+        Ok(ObjectInfo { len, sha256 })
     }
 
     #[tracing::instrument(
         level = "debug",
         err,
-        skip(self),
+        skip_all,
         fields(key = %key, bucket = %self.bucket)
     )]
     async fn delete(&self, key: &str) -> StorageResult<()> {
@@ -185,4 +492,54 @@ impl ObjectStorage for S3Storage {
             .map_err(|e| StorageError::Internal(e.to_string()))?;
         Ok(())
     }
+
+    #[tracing::instrument(
+        level = "debug",
+        err,
+        skip_all,
+        fields(key_count = keys.len(), bucket = %self.bucket)
+    )]
+    async fn delete_many(&self, keys: &[String]) -> StorageResult<Vec<String>> {
+        let batches = keys.chunks(MAX_DELETE_BATCH).map(|batch| self.delete_batch(batch));
+        let results: Vec<StorageResult<Vec<String>>> =
+            futures::stream::iter(batches).buffer_unordered(DELETE_CONCURRENCY).collect().await;
+
+        let mut failed = Vec::new();
+        for result in results {
+            failed.extend(result?);
+        }
+        Ok(failed)
+    }
+
+    #[tracing::instrument(
+        level = "debug",
+        err,
+        skip_all,
+        fields(bucket = %self.bucket, %prefix, ?start_after, %limit)
+    )]
+    async fn list(&self, prefix: &str, start_after: Option<&str>, limit: usize) -> StorageResult<Vec<ObjectSummary>> {
+        let output = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .prefix(prefix)
+            .set_start_after(start_after.map(ToOwned::to_owned))
+            .max_keys(i32::try_from(limit).unwrap_or(i32::MAX))
+            .send()
+            .await
+            .map_err(|e| StorageError::Internal(e.to_string()))?;
+
+        Ok(output
+            .contents()
+            .iter()
+            .filter_map(|object| {
+                Some(ObjectSummary {
+                    key: object.key()?.to_owned(),
+                    last_modified: object
+                        .last_modified()
+                        .and_then(|at| OffsetDateTime::from_unix_timestamp(at.secs()).ok()),
+                })
+            })
+            .collect())
+    }
 }
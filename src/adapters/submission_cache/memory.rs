@@ -0,0 +1,187 @@
+use crate::adapters::submission_cache::SubmissionStore;
+use async_trait::async_trait;
+use opentelemetry::{
+    KeyValue, global,
+    metrics::{Counter, UpDownCounter},
+};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug)]
+struct Metrics {
+    evictions_total: Counter<u64>,
+    bytes: UpDownCounter<i64>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let meter = global::meter("obscura-server");
+        Self {
+            evictions_total: meter
+                .u64_counter("obscura_submission_cache_evictions_total")
+                .with_description("Responses dropped from the in-process idempotency cache, labelled by reason")
+                .build(),
+            bytes: meter
+                .i64_up_down_counter("obscura_submission_cache_bytes")
+                .with_description("Bytes held by the in-process idempotency cache")
+                .build(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    value: Vec<u8>,
+    expires_at: Instant,
+    last_used: u64,
+}
+
+impl Entry {
+    fn size(&self, key: &str) -> usize {
+        key.len() + self.value.len()
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    entries: HashMap<String, Entry>,
+    /// Keys by the tick of their last use, least recently used first.
+    recency: BTreeMap<u64, String>,
+    bytes: usize,
+    tick: u64,
+}
+
+impl State {
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn remove(&mut self, key: &str) -> Option<usize> {
+        let entry = self.entries.remove(key)?;
+        self.recency.remove(&entry.last_used);
+        let size = entry.size(key);
+        self.bytes -= size;
+        Some(size)
+    }
+}
+
+/// In-process idempotency cache holding at most `capacity_bytes` of keys and responses,
+/// evicting the least recently used responses first.
+///
+/// Each instance has its own cache, so a retry that reaches another instance is only
+/// deduplicated by the database.
+#[derive(Debug)]
+pub struct LruSubmissionStore {
+    state: Mutex<State>,
+    ttl: Duration,
+    capacity_bytes: usize,
+    metrics: Metrics,
+}
+
+impl LruSubmissionStore {
+    #[must_use]
+    pub fn new(ttl_secs: u64, capacity_bytes: usize) -> Self {
+        Self {
+            state: Mutex::new(State::default()),
+            ttl: Duration::from_secs(ttl_secs),
+            capacity_bytes,
+            metrics: Metrics::new(),
+        }
+    }
+
+    fn record_eviction(&self, size: usize, reason: &'static str) {
+        self.metrics.evictions_total.add(1, &[KeyValue::new("reason", reason)]);
+        self.metrics.bytes.add(-i64::try_from(size).unwrap_or(i64::MAX), &[]);
+    }
+}
+
+#[async_trait]
+impl SubmissionStore for LruSubmissionStore {
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let tick = state.next_tick();
+        let Some(entry) = state.entries.get_mut(key) else { return Ok(None) };
+
+        if entry.expires_at <= Instant::now() {
+            if let Some(size) = state.remove(key) {
+                self.record_eviction(size, "expired");
+            }
+            return Ok(None);
+        }
+
+        let previous = std::mem::replace(&mut entry.last_used, tick);
+        let value = entry.value.clone();
+        state.recency.remove(&previous);
+        state.recency.insert(tick, key.to_string());
+        Ok(Some(value))
+    }
+
+    async fn set(&self, key: &str, value: &[u8]) -> anyhow::Result<()> {
+        let size = key.len() + value.len();
+        if size > self.capacity_bytes {
+            return Ok(());
+        }
+
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(replaced) = state.remove(key) {
+            self.metrics.bytes.add(-i64::try_from(replaced).unwrap_or(i64::MAX), &[]);
+        }
+
+        while state.bytes + size > self.capacity_bytes {
+            let Some((_, oldest)) = state.recency.pop_first() else { break };
+            let expired = state.entries.get(&oldest).is_some_and(|entry| entry.expires_at <= Instant::now());
+            // The recency entry is already gone, so only the map and byte count are left to update.
+            if let Some(entry) = state.entries.remove(&oldest) {
+                let evicted = entry.size(&oldest);
+                state.bytes -= evicted;
+                self.record_eviction(evicted, if expired { "expired" } else { "capacity" });
+            }
+        }
+
+        let tick = state.next_tick();
+        state.entries.insert(
+            key.to_string(),
+            Entry { value: value.to_vec(), expires_at: Instant::now() + self.ttl, last_used: tick },
+        );
+        state.recency.insert(tick, key.to_string());
+        state.bytes += size;
+        self.metrics.bytes.add(i64::try_from(size).unwrap_or(i64::MAX), &[]);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_least_recently_used_response_is_evicted_first() {
+        // Room for two 1-byte keys with 9-byte responses
+        let store = LruSubmissionStore::new(60, 20);
+        store.set("a", &[1; 9]).await.expect("in-process store");
+        store.set("b", &[2; 9]).await.expect("in-process store");
+
+        // Reading "a" makes "b" the eviction candidate
+        assert!(store.get("a").await.expect("in-process store").is_some());
+        store.set("c", &[3; 9]).await.expect("in-process store");
+
+        assert!(store.get("b").await.expect("in-process store").is_none());
+        assert_eq!(store.get("a").await.expect("in-process store"), Some(vec![1; 9]));
+        assert_eq!(store.get("c").await.expect("in-process store"), Some(vec![3; 9]));
+
+        // Responses that could never fit are not cached at all
+        store.set("d", &[4; 20]).await.expect("in-process store");
+        assert!(store.get("d").await.expect("in-process store").is_none());
+        assert_eq!(store.state.lock().expect("state lock").bytes, 20);
+    }
+
+    #[tokio::test]
+    async fn test_expired_responses_are_not_returned() {
+        let store = LruSubmissionStore::new(0, 1024);
+        store.set("a", b"response").await.expect("in-process store");
+        assert!(store.get("a").await.expect("in-process store").is_none());
+        assert_eq!(store.state.lock().expect("state lock").bytes, 0);
+    }
+}
@@ -0,0 +1,100 @@
+use async_trait::async_trait;
+use opentelemetry::{KeyValue, global, metrics::Counter};
+use std::sync::Arc;
+
+pub mod memory;
+
+pub use memory::LruSubmissionStore;
+
+/// Storage behind the idempotency cache of send responses.
+#[async_trait]
+pub trait SubmissionStore: Send + Sync + std::fmt::Debug {
+    /// Returns the response cached for `key`, if it is still live.
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>>;
+
+    /// Caches a response for the store's TTL, replacing any earlier one.
+    async fn set(&self, key: &str, value: &[u8]) -> anyhow::Result<()>;
+
+    /// Gives entries stored without an expiry the store's TTL, returning how many there were.
+    /// Stores that cannot hold such entries have nothing to do.
+    async fn enforce_ttl(&self) -> anyhow::Result<u64> {
+        Ok(0)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Metrics {
+    lookups_total: Counter<u64>,
+    oversized_total: Counter<u64>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let meter = global::meter("obscura-server");
+        Self {
+            lookups_total: meter
+                .u64_counter("obscura_submission_cache_lookups_total")
+                .with_description("Idempotency cache lookups, labelled by result (hit, miss or error)")
+                .build(),
+            oversized_total: meter
+                .u64_counter("obscura_submission_cache_oversized_total")
+                .with_description("Send responses not cached because they exceed the size limit")
+                .build(),
+        }
+    }
+}
+
+/// Idempotency cache of send responses, keyed by the client's idempotency key.
+///
+/// Responses above `max_response_bytes` are not cached; a retry of such a request is still
+/// deduplicated by the database and answered with the receipts of the stored messages.
+#[derive(Clone, Debug)]
+pub struct SubmissionCache {
+    store: Arc<dyn SubmissionStore>,
+    max_response_bytes: usize,
+    metrics: Metrics,
+}
+
+impl SubmissionCache {
+    #[must_use]
+    pub fn new(store: Arc<dyn SubmissionStore>, max_response_bytes: usize) -> Self {
+        Self { store, max_response_bytes, metrics: Metrics::new() }
+    }
+
+    /// Retrieves the cached response for a key.
+    ///
+    /// # Errors
+    /// Returns an error if the store cannot be reached.
+    pub async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let result = self.store.get(key).await;
+        let label = match &result {
+            Ok(Some(_)) => "hit",
+            Ok(None) => "miss",
+            Err(_) => "error",
+        };
+        self.metrics.lookups_total.add(1, &[KeyValue::new("result", label)]);
+        result
+    }
+
+    /// Caches a response, unless it is larger than the configured limit.
+    ///
+    /// # Errors
+    /// Returns an error if the store cannot be reached.
+    pub async fn set(&self, key: &str, value: &[u8]) -> anyhow::Result<()> {
+        if value.len() > self.max_response_bytes {
+            tracing::debug!(size = value.len(), "Send response too large to cache");
+            self.metrics.oversized_total.add(1, &[]);
+            return Ok(());
+        }
+        self.store.set(key, value).await
+    }
+
+    /// Gives cached responses left without an expiry the cache's TTL, returning how many
+    /// there were.
+    ///
+    /// # Errors
+    /// Returns an error if the store cannot be reached.
+    pub async fn enforce_ttl(&self) -> anyhow::Result<u64> {
+        self.store.enforce_ttl().await
+    }
+}
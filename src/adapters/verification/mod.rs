@@ -0,0 +1,27 @@
+use crate::domain::identifier::IdentifierKind;
+use async_trait::async_trait;
+
+pub mod webhook;
+
+/// Delivers identifier verification codes over email or SMS.
+#[async_trait]
+pub trait VerificationSender: Send + Sync + std::fmt::Debug {
+    /// Sends `code` to `destination`, a normalized email address or E.164 phone number.
+    ///
+    /// # Errors
+    /// Returns an error if the code could not be handed off for delivery.
+    async fn send_code(&self, kind: IdentifierKind, destination: &str, code: &str) -> anyhow::Result<()>;
+}
+
+/// A sender that logs instead of delivering codes, so identifiers can never be verified.
+/// Used when no delivery webhook is configured.
+#[derive(Debug)]
+pub struct LoggingVerificationSender;
+
+#[async_trait]
+impl VerificationSender for LoggingVerificationSender {
+    async fn send_code(&self, kind: IdentifierKind, _destination: &str, _code: &str) -> anyhow::Result<()> {
+        tracing::warn!(kind = kind.as_str(), "Verification code not sent: no delivery webhook is configured");
+        Ok(())
+    }
+}
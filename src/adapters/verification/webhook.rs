@@ -0,0 +1,112 @@
+use crate::adapters::verification::VerificationSender;
+use crate::config::IdentifierConfig;
+use crate::domain::identifier::IdentifierKind;
+use anyhow::{Context, bail};
+use async_trait::async_trait;
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct DeliveryRequest<'a> {
+    kind: &'static str,
+    destination: &'a str,
+    code: &'a str,
+}
+
+/// Hands verification codes to an operator-run delivery service, which sends them by email or
+/// SMS through whichever provider it is wired to.
+///
+/// Each code is posted as `{"kind", "destination", "code"}` JSON, with the configured token as a
+/// bearer credential. Any 2xx response counts as accepted.
+pub struct WebhookVerificationSender {
+    http: reqwest::Client,
+    url: String,
+    token: Option<String>,
+}
+
+impl std::fmt::Debug for WebhookVerificationSender {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebhookVerificationSender").field("url", &self.url).finish_non_exhaustive()
+    }
+}
+
+impl WebhookVerificationSender {
+    /// Returns `None` when no webhook URL is configured.
+    #[must_use]
+    pub fn new(config: &IdentifierConfig, http: reqwest::Client) -> Option<Self> {
+        let url = config.webhook_url.clone()?;
+        Some(Self { http, url, token: config.webhook_token.clone() })
+    }
+}
+
+#[async_trait]
+impl VerificationSender for WebhookVerificationSender {
+    #[tracing::instrument(level = "debug", skip_all, fields(kind = kind.as_str()), err)]
+    async fn send_code(&self, kind: IdentifierKind, destination: &str, code: &str) -> anyhow::Result<()> {
+        let mut request = self.http.post(&self.url).json(&DeliveryRequest { kind: kind.as_str(), destination, code });
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+
+        let resp = request.send().await.context("Verification webhook request failed")?;
+        let status = resp.status();
+        if !status.is_success() {
+            bail!("Verification webhook rejected the code with HTTP {status}");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Router;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::post;
+
+    async fn start_webhook(status: StatusCode) -> (String, tokio::sync::mpsc::UnboundedReceiver<serde_json::Value>) {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let app = Router::new().route(
+            "/deliver",
+            post(move |headers: HeaderMap, axum::Json(body): axum::Json<serde_json::Value>| async move {
+                let authorized = headers.get("authorization").is_some_and(|v| v == "Bearer hook-token");
+                let _ = tx.send(body);
+                if authorized { status } else { StatusCode::UNAUTHORIZED }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.expect("bind mock webhook");
+        let addr = listener.local_addr().expect("mock webhook address");
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (format!("http://{addr}/deliver"), rx)
+    }
+
+    fn sender(url: String) -> WebhookVerificationSender {
+        let config = IdentifierConfig {
+            webhook_url: Some(url),
+            webhook_token: Some("hook-token".to_string()),
+            ..Default::default()
+        };
+        WebhookVerificationSender::new(&config, reqwest::Client::new()).expect("webhook URL is set")
+    }
+
+    #[tokio::test]
+    async fn test_posts_code_to_webhook() {
+        let (url, mut rx) = start_webhook(StatusCode::ACCEPTED).await;
+        let result = sender(url).send_code(IdentifierKind::Phone, "+15551234567", "123456").await;
+        assert!(result.is_ok());
+
+        let body = rx.recv().await.expect("webhook received the code");
+        assert_eq!(body, serde_json::json!({ "kind": "phone", "destination": "+15551234567", "code": "123456" }));
+    }
+
+    #[tokio::test]
+    async fn test_reports_rejected_delivery() {
+        let (url, _rx) = start_webhook(StatusCode::SERVICE_UNAVAILABLE).await;
+        let result = sender(url).send_code(IdentifierKind::Email, "alice@example.org", "123456").await;
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_unconfigured_webhook_builds_no_sender() {
+        assert!(WebhookVerificationSender::new(&IdentifierConfig::default(), reqwest::Client::new()).is_none());
+    }
+}
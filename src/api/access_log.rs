@@ -0,0 +1,136 @@
+use crate::config::ClientIpMode;
+use crate::services::rate_limit_service::IpKeyExtractor;
+use axum::body::Body;
+use axum::extract::{ConnectInfo, MatchedPath, State};
+use axum::http::{Request, header};
+use axum::middleware::Next;
+use axum::response::Response;
+use http_body::Body as _;
+use sha2::{Digest, Sha256};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Instant;
+
+/// Tracing target of access log records. The telemetry setup routes it to its own writer
+/// and keeps it out of the regular log.
+pub const ACCESS_LOG_TARGET: &str = "obscura::access";
+
+/// Domain separation for the client IP hash, so it cannot be matched against other digests of the secret.
+const CLIENT_IP_HASH_LABEL: &[u8] = b"obscura-access-log-client-ip-v1";
+
+/// Resolves and anonymizes client addresses for access log records.
+#[derive(Clone, Debug)]
+pub struct AccessLogger {
+    extractor: IpKeyExtractor,
+    ip_mode: ClientIpMode,
+    hash_key: [u8; 32],
+}
+
+impl AccessLogger {
+    #[must_use]
+    pub fn new(extractor: IpKeyExtractor, ip_mode: ClientIpMode, secret: &str) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(CLIENT_IP_HASH_LABEL);
+        hasher.update(secret.as_bytes());
+        Self { extractor, ip_mode, hash_key: hasher.finalize().into() }
+    }
+
+    fn client_ip(&self, req: &Request<Body>) -> Option<String> {
+        let ConnectInfo(peer) = req.extensions().get::<ConnectInfo<SocketAddr>>()?;
+        let ip = self.extractor.identify_client_ip(req.headers(), peer.ip());
+        match self.ip_mode {
+            ClientIpMode::Truncate => Some(truncate_ip(ip).to_string()),
+            ClientIpMode::Hash => Some(hash_ip(&self.hash_key, ip)),
+            ClientIpMode::Omit => None,
+        }
+    }
+}
+
+/// Middleware emitting one access log record per request once the response head is ready.
+///
+/// Only the route template is logged, so identifiers in the path never reach the log.
+/// Byte counts are taken from `Content-Length` or an exact body size hint and are left
+/// out for streamed bodies of unknown length.
+pub(crate) async fn log_access(State(logger): State<AccessLogger>, req: Request<Body>, next: Next) -> Response {
+    let started = Instant::now();
+    let method = req.method().clone();
+    let route = req.extensions().get::<MatchedPath>().map(|path| path.as_str().to_owned());
+    let request_id = req.headers().get("x-request-id").and_then(|v| v.to_str().ok()).map(ToOwned::to_owned);
+    let request_bytes = content_length(req.headers()).or_else(|| req.body().size_hint().exact());
+    let client_ip = logger.client_ip(&req);
+
+    let response = next.run(req).await;
+
+    let response_bytes = content_length(response.headers()).or_else(|| response.body().size_hint().exact());
+    tracing::info!(
+        target: ACCESS_LOG_TARGET,
+        http.request.method = %method,
+        http.route = route.as_deref().unwrap_or("unmatched"),
+        http.response.status_code = response.status().as_u16(),
+        duration_ms = started.elapsed().as_secs_f64() * 1000.0,
+        http.request.body.size = request_bytes,
+        http.response.body.size = response_bytes,
+        client.address = client_ip.as_deref(),
+        request.id = request_id.as_deref(),
+        "access"
+    );
+
+    response
+}
+
+fn content_length(headers: &axum::http::HeaderMap) -> Option<u64> {
+    headers.get(header::CONTENT_LENGTH)?.to_str().ok()?.parse().ok()
+}
+
+/// Keeps the /24 of an IPv4 address (including IPv4-mapped IPv6) and the /48 of an IPv6 address.
+fn truncate_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(v4) => IpAddr::V4(truncate_ipv4(v4)),
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or_else(
+            || IpAddr::V6(Ipv6Addr::from_bits(v6.to_bits() & (u128::MAX << 80))),
+            |v4| IpAddr::V4(truncate_ipv4(v4)),
+        ),
+    }
+}
+
+const fn truncate_ipv4(ip: Ipv4Addr) -> Ipv4Addr {
+    Ipv4Addr::from_bits(ip.to_bits() & 0xffff_ff00)
+}
+
+/// Hashes the full address with a server-side key; 16 bytes keep collisions negligible for correlation.
+fn hash_ip(key: &[u8; 32], ip: IpAddr) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(key);
+    match ip {
+        IpAddr::V4(v4) => hasher.update(v4.octets()),
+        IpAddr::V6(v6) => hasher.update(v6.octets()),
+    }
+    hex::encode(&hasher.finalize()[..16])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().expect("valid IP")
+    }
+
+    #[test]
+    fn test_truncate_ip() {
+        assert_eq!(truncate_ip(ip("203.0.113.77")), ip("203.0.113.0"));
+        assert_eq!(truncate_ip(ip("::ffff:203.0.113.77")), ip("203.0.113.0"));
+        assert_eq!(truncate_ip(ip("2001:db8:1234:5678::1")), ip("2001:db8:1234::"));
+    }
+
+    #[test]
+    fn test_hash_ip_is_keyed_and_stable() {
+        let a = AccessLogger::new(IpKeyExtractor::new(vec![], 64), ClientIpMode::Hash, "secret-a");
+        let b = AccessLogger::new(IpKeyExtractor::new(vec![], 64), ClientIpMode::Hash, "secret-b");
+        let client = ip("203.0.113.77");
+
+        assert_eq!(hash_ip(&a.hash_key, client), hash_ip(&a.hash_key, client));
+        assert_ne!(hash_ip(&a.hash_key, client), hash_ip(&b.hash_key, client));
+        assert_ne!(hash_ip(&a.hash_key, client), hash_ip(&a.hash_key, ip("203.0.113.78")));
+        assert_eq!(hash_ip(&a.hash_key, client).len(), 32);
+    }
+}
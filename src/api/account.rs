@@ -0,0 +1,24 @@
+use crate::api::AppState;
+use crate::api::middleware::AuthUser;
+use crate::api::schemas::account::UsageResponse;
+use crate::error::Result;
+use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
+
+/// Deletes the authenticated user's account along with all of its devices.
+///
+/// # Errors
+/// Returns `AppError::NotFound` if the account no longer exists.
+pub(crate) async fn delete_account(auth_user: AuthUser, State(state): State<AppState>) -> Result<impl IntoResponse> {
+    state.account_service.delete_account(auth_user.user_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Reports the storage the authenticated user holds: attachments, backups, pending messages
+/// and one-time pre-keys.
+///
+/// # Errors
+/// Returns `AppError::Database` if the usage cannot be computed.
+pub(crate) async fn get_usage(auth_user: AuthUser, State(state): State<AppState>) -> Result<impl IntoResponse> {
+    let usage = state.usage_service.usage(auth_user.user_id).await?;
+    Ok(Json(UsageResponse::from(usage)))
+}
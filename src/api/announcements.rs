@@ -0,0 +1,34 @@
+use crate::api::MgmtState;
+use crate::api::schemas::announcements::{AnnouncementResponse, BroadcastAnnouncementRequest};
+use crate::domain::announcement::AnnouncementKind;
+use crate::error::{AppError, Result};
+use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use time::format_description::well_known::Rfc3339;
+
+/// Broadcasts a signed announcement to every user: live to connected sessions, and queued
+/// as a system envelope for everyone else once the offline delay has passed.
+///
+/// # Errors
+/// Returns `AppError::BadRequest` if the kind is unknown, the body is empty or too long,
+/// or the lifetime is zero.
+pub(crate) async fn broadcast_announcement(
+    State(state): State<MgmtState>,
+    Json(payload): Json<BroadcastAnnouncementRequest>,
+) -> Result<impl IntoResponse> {
+    let kind: AnnouncementKind = payload.kind.parse().map_err(AppError::BadRequest)?;
+    let announcement = state.announcement_service.broadcast(kind, &payload.body, payload.ttl_secs).await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(AnnouncementResponse {
+            id: announcement.id.to_string(),
+            kind: announcement.kind.to_string(),
+            body: announcement.body,
+            created_at: announcement.created_at.format(&Rfc3339).unwrap_or_default(),
+            expires_at: announcement.expires_at.format(&Rfc3339).unwrap_or_default(),
+            signature: STANDARD.encode(&announcement.signature),
+            public_key: STANDARD.encode(state.announcement_service.public_key()),
+        }),
+    ))
+}
@@ -1,6 +1,6 @@
 use crate::api::AppState;
-use crate::api::middleware::AuthUser;
-use crate::api::schemas::attachments::AttachmentResponse;
+use crate::api::middleware::{AuthUser, ContentSha256};
+use crate::api::schemas::attachments::{AttachmentResponse, ExtendAttachmentRequest, ExtendAttachmentResponse};
 use crate::error::{AppError, Result};
 use axum::{
     Json,
@@ -12,14 +12,20 @@ use axum::{
 use futures::StreamExt;
 use uuid::Uuid;
 
+const UPLOAD_PROGRESS_HEADER: &str = "x-upload-progress";
+
 /// Uploads an attachment to storage.
 ///
+/// Devices that send `X-Upload-Progress: true` receive progress frames over their gateway session.
+///
 /// # Errors
 /// Returns `AppError::LengthRequired` if the Content-Length header is missing.
+/// Returns `AppError::UnprocessableEntity` if the content does not match `X-Content-SHA256`.
 /// Returns `AppError::Internal` if there is an error during upload.
 pub(crate) async fn upload_attachment(
-    _auth_user: AuthUser,
+    auth_user: AuthUser,
     State(state): State<AppState>,
+    ContentSha256(sha256): ContentSha256,
     headers: HeaderMap,
     body: Body,
 ) -> Result<impl IntoResponse> {
@@ -28,12 +34,68 @@ pub(crate) async fn upload_attachment(
         .and_then(|v| v.to_str().ok().and_then(|s| s.parse::<usize>().ok()))
         .ok_or(AppError::LengthRequired)?;
 
+    let wants_progress = headers
+        .get(UPLOAD_PROGRESS_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("true") || v == "1");
+
     // Bridge Axum Body -> StorageStream (using neutral std::io::Error)
     let stream = body.into_data_stream().map(|res| res.map_err(|e| std::io::Error::other(e.to_string()))).boxed();
 
-    let (id, expires_at) = state.attachment_service.upload(Some(content_len), stream).await?;
+    let (id, expires_at) = state
+        .attachment_service
+        .upload(
+            auth_user.user_id,
+            auth_user.device_id,
+            auth_user.tier,
+            Some(content_len),
+            sha256,
+            stream,
+            wants_progress,
+        )
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(AttachmentResponse { id, expires_at, content_key: sha256.map(hex::encode) })))
+}
+
+/// Registers a new attachment for content the server already stores, so the client can skip the upload.
+///
+/// # Errors
+/// Returns `AppError::BadRequest` if the digest is not 64 hex characters.
+/// Returns `AppError::NotFound` if no live attachment holds content with this digest.
+/// Returns `AppError::Internal` if there is an error during registration.
+pub(crate) async fn register_by_digest(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Path(digest): Path<String>,
+) -> Result<impl IntoResponse> {
+    let sha256 = hex::decode(&digest)
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .ok_or_else(|| AppError::BadRequest("Invalid digest, expected 64 hex characters".into()))?;
+
+    let (id, expires_at) = state
+        .attachment_service
+        .register_by_digest(auth_user.user_id, auth_user.device_id, sha256)
+        .await?
+        .ok_or(AppError::NotFound)?;
 
-    Ok((StatusCode::CREATED, Json(AttachmentResponse { id, expires_at })))
+    Ok((StatusCode::CREATED, Json(AttachmentResponse { id, expires_at, content_key: Some(hex::encode(sha256)) })))
+}
+
+/// Keeps an attachment available for a recipient that has not fetched it yet.
+///
+/// # Errors
+/// Returns `AppError::BadRequest` if `ttlSecs` is zero.
+/// Returns `AppError::NotFound` if the attachment does not exist or has already expired.
+pub(crate) async fn extend_attachment(
+    _auth_user: AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<ExtendAttachmentRequest>,
+) -> Result<impl IntoResponse> {
+    let expires_at = state.attachment_service.extend(id, payload.ttl_secs).await?;
+    Ok(Json(ExtendAttachmentResponse { id, expires_at }))
 }
 
 /// Downloads an attachment from storage.
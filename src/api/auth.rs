@@ -1,68 +1,82 @@
 use crate::api::AppState;
 use crate::api::middleware::AuthUser;
+use crate::api::protobuf::{Negotiated, ResponseFormat};
 use crate::api::schemas::auth::{AuthResponse, LoginRequest, LogoutRequest, RefreshRequest, RegistrationRequest};
+use crate::api::schemas::validation::Validate;
 use crate::domain::auth_session::AuthSession;
+use crate::domain::username;
 use crate::error::{AppError, Result};
-use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
 
 /// Authenticates a user and returns a session.
 ///
 /// # Errors
-/// Returns `AppError::AuthError` if the credentials are invalid.
-/// Returns `AppError::BadRequest` if the device ID is invalid.
+/// Returns `AppError::Auth` if the credentials are invalid.
+/// Returns `AppError::Validation` if a field is malformed, e.g. the device ID.
 pub(crate) async fn login(
     State(state): State<AppState>,
-    Json(payload): Json<LoginRequest>,
+    format: ResponseFormat,
+    Negotiated(payload): Negotiated<LoginRequest>,
 ) -> Result<impl IntoResponse> {
+    payload.validate()?;
+
     let device_id = payload
         .device_id
         .map(|s| uuid::Uuid::parse_str(&s).map_err(|_| AppError::BadRequest("Invalid device_id".to_string())))
         .transpose()?;
 
-    let session = state.auth_service.login(payload.username.to_lowercase(), payload.password, device_id).await?;
+    let session = state.auth_service.login(username::normalize(&payload.username), payload.password, device_id).await?;
     let auth_response = map_session(session);
-    Ok(Json(auth_response))
+    Ok(format.render(StatusCode::OK, auth_response))
 }
 
 /// Registers a new user. Returns a user-only JWT (no `device_id`).
 ///
 /// # Errors
-/// Returns `AppError::BadRequest` if validation fails.
+/// Returns `AppError::Validation` if the username or password is invalid.
 /// Returns `AppError::Conflict` if the username is already taken.
 pub(crate) async fn register(
     State(state): State<AppState>,
-    Json(payload): Json<RegistrationRequest>,
+    format: ResponseFormat,
+    Negotiated(payload): Negotiated<RegistrationRequest>,
 ) -> Result<impl IntoResponse> {
-    payload.validate().map_err(AppError::BadRequest)?;
+    payload.validate()?;
 
-    let session = state.auth_service.register(payload.username.to_lowercase(), payload.password).await?;
+    let session = state.auth_service.register(username::normalize(&payload.username), payload.password).await?;
 
     let auth_response = map_session(session);
-    Ok((StatusCode::CREATED, Json(auth_response)))
+    Ok(format.render(StatusCode::CREATED, auth_response))
 }
 
 /// Rotates a session using a refresh token.
 ///
 /// # Errors
-/// Returns `AppError::AuthError` if the refresh token is invalid or expired.
+/// Returns `AppError::Validation` if the refresh token is malformed.
+/// Returns `AppError::Auth` if the refresh token is invalid or expired.
 pub(crate) async fn refresh(
     State(state): State<AppState>,
-    Json(payload): Json<RefreshRequest>,
+    format: ResponseFormat,
+    Negotiated(payload): Negotiated<RefreshRequest>,
 ) -> Result<impl IntoResponse> {
+    payload.validate()?;
+
     let session = state.auth_service.refresh_session(payload.refresh_token).await?;
     let auth_response = map_session(session);
-    Ok(Json(auth_response))
+    Ok(format.render(StatusCode::OK, auth_response))
 }
 
 /// Invalidates a refresh token.
 ///
 /// # Errors
-/// Returns `AppError::AuthError` if the user is not authorized.
+/// Returns `AppError::Validation` if the refresh token is malformed.
+/// Returns `AppError::Auth` if the user is not authorized.
 pub(crate) async fn logout(
     auth_user: AuthUser,
     State(state): State<AppState>,
-    Json(payload): Json<LogoutRequest>,
+    Negotiated(payload): Negotiated<LogoutRequest>,
 ) -> Result<impl IntoResponse> {
+    payload.validate()?;
+
     state.auth_service.logout(auth_user.user_id, payload.refresh_token).await?;
     Ok(StatusCode::OK)
 }
@@ -1,5 +1,5 @@
 use crate::api::AppState;
-use crate::api::middleware::AuthUser;
+use crate::api::middleware::{AuthUser, CONTENT_SHA256_HEADER, ContentSha256};
 use crate::error::{AppError, Result};
 use axum::{
     body::Body,
@@ -15,32 +15,19 @@ use futures::StreamExt;
 /// Returns `AppError::Forbidden` if a device-scoped token is not provided.
 /// Returns `AppError::BadRequest` if headers are invalid.
 /// Returns `AppError::LengthRequired` if the Content-Length header is missing.
+/// Returns `AppError::UnprocessableEntity` if the content does not match `X-Content-SHA256`.
 /// Returns `AppError::Internal` if the upload fails.
 pub(crate) async fn upload_backup(
     auth_user: AuthUser,
     State(state): State<AppState>,
+    ContentSha256(sha256): ContentSha256,
     headers: HeaderMap,
     body: Body,
 ) -> Result<impl IntoResponse> {
     let device_id = auth_user.device_id.ok_or(AppError::Forbidden("Device-scoped token required".to_string()))?;
 
     // 1. Determine target version using Optimistic Locking headers
-    let if_match_version = if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH) {
-        if if_none_match == "*" {
-            0 // Standard way to say "only if it doesn't exist"
-        } else {
-            return Err(AppError::BadRequest("Invalid If-None-Match header".into()));
-        }
-    } else {
-        let if_match_header = headers
-            .get(header::IF_MATCH)
-            .ok_or(AppError::BadRequest("Missing If-Match or If-None-Match header".into()))?
-            .to_str()
-            .map_err(|_| AppError::BadRequest("Invalid If-Match header".into()))?;
-
-        let if_match_str = if_match_header.trim_matches('"');
-        if_match_str.parse::<i32>().map_err(|_| AppError::BadRequest("Invalid version in If-Match header".into()))?
-    };
+    let if_match_version = upload_precondition(&headers)?;
 
     let content_len = headers
         .get(header::CONTENT_LENGTH)
@@ -50,8 +37,10 @@ pub(crate) async fn upload_backup(
     // Bridge Axum Body -> StorageStream (using neutral std::io::Error)
     let stream = body.into_data_stream().map(|res| res.map_err(|e| std::io::Error::other(e.to_string()))).boxed();
 
-    let new_version =
-        state.backup_service.handle_upload(device_id, if_match_version, Some(content_len), stream).await?;
+    let new_version = state
+        .backup_service
+        .handle_upload(device_id, auth_user.tier, if_match_version, Some(content_len), sha256, stream)
+        .await?;
 
     let mut response = Response::new(Body::empty());
     *response.status_mut() = StatusCode::OK;
@@ -76,15 +65,12 @@ pub(crate) async fn download_backup(
     let device_id = auth_user.device_id.ok_or(AppError::Forbidden("Device-scoped token required".to_string()))?;
 
     // 1. Check If-None-Match for caching optimization
-    if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH).and_then(|v| v.to_str().ok()) {
-        let if_none_match_version = if_none_match.trim_matches('"');
-        if let Ok(version) = if_none_match_version.parse::<i32>() {
-            // Fast-path: Check DB version before touching S3
-            if let Some(current_version) = state.backup_service.get_current_version(device_id).await?
-                && current_version == version
-            {
-                return Ok(StatusCode::NOT_MODIFIED.into_response());
-            }
+    if let Some(version) = cached_version(&headers) {
+        // Fast-path: Check DB version before touching S3
+        if let Some(current_version) = state.backup_service.get_current_version(device_id).await?
+            && current_version == version
+        {
+            return Ok(StatusCode::NOT_MODIFIED.into_response());
         }
     }
 
@@ -115,16 +101,121 @@ pub(crate) async fn download_backup(
 pub(crate) async fn head_backup(auth_user: AuthUser, State(state): State<AppState>) -> Result<impl IntoResponse> {
     let device_id = auth_user.device_id.ok_or(AppError::Forbidden("Device-scoped token required".to_string()))?;
 
-    let (version, len) = state.backup_service.head(device_id).await?;
+    let (version, info) = state.backup_service.head(device_id).await?;
 
     let mut response = Response::new(Body::empty());
     response.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static("application/octet-stream"));
     response
         .headers_mut()
-        .insert(header::CONTENT_LENGTH, HeaderValue::from_str(&len.to_string()).map_err(|_| AppError::Internal)?);
+        .insert(header::CONTENT_LENGTH, HeaderValue::from_str(&info.len.to_string()).map_err(|_| AppError::Internal)?);
+    if let Some(sha256) = info.sha256 {
+        response
+            .headers_mut()
+            .insert(CONTENT_SHA256_HEADER, HeaderValue::from_str(&sha256).map_err(|_| AppError::Internal)?);
+    }
+    response
+        .headers_mut()
+        .insert(header::ETAG, HeaderValue::from_str(&format!("\"{version}\"")).map_err(|_| AppError::Internal)?);
+
+    Ok(response)
+}
+
+/// Restores the backup version replaced by the last upload or restore.
+///
+/// # Errors
+/// Returns `AppError::Forbidden` if a device-scoped token is not provided.
+/// Returns `AppError::BadRequest` if the If-Match header is invalid.
+/// Returns `AppError::NotFound` if there is no version within the restore window.
+/// Returns `AppError::PreconditionFailed` if If-Match does not match the current version.
+pub(crate) async fn restore_backup(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse> {
+    let device_id = auth_user.device_id.ok_or(AppError::Forbidden("Device-scoped token required".to_string()))?;
+
+    let if_match_version = restore_precondition(&headers)?;
+
+    let version = state.backup_service.restore(device_id, if_match_version).await?;
+
+    let mut response = Response::new(Body::empty());
     response
         .headers_mut()
         .insert(header::ETAG, HeaderValue::from_str(&format!("\"{version}\"")).map_err(|_| AppError::Internal)?);
 
     Ok(response)
 }
+
+/// Version an upload replaces: `0` for `If-None-Match: *`, meaning no backup exists yet, otherwise
+/// the version in the required `If-Match` header.
+///
+/// # Errors
+/// Returns `AppError::BadRequest` if neither header is usable.
+pub(crate) fn upload_precondition(headers: &HeaderMap) -> Result<i32> {
+    if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH) {
+        if if_none_match == "*" {
+            return Ok(0); // Standard way to say "only if it doesn't exist"
+        }
+        return Err(AppError::BadRequest("Invalid If-None-Match header".into()));
+    }
+
+    let if_match_header = headers
+        .get(header::IF_MATCH)
+        .ok_or(AppError::BadRequest("Missing If-Match or If-None-Match header".into()))?
+        .to_str()
+        .map_err(|_| AppError::BadRequest("Invalid If-Match header".into()))?;
+
+    if_match_header
+        .trim_matches('"')
+        .parse::<i32>()
+        .map_err(|_| AppError::BadRequest("Invalid version in If-Match header".into()))
+}
+
+/// Version the client already holds, from `If-None-Match`. Anything unparsable is ignored, since
+/// the header only enables a shortcut.
+pub(crate) fn cached_version(headers: &HeaderMap) -> Option<i32> {
+    headers.get(header::IF_NONE_MATCH)?.to_str().ok()?.trim_matches('"').parse::<i32>().ok()
+}
+
+/// Version a restore must replace, from the optional `If-Match` header.
+///
+/// # Errors
+/// Returns `AppError::BadRequest` if the header is present but not a version.
+pub(crate) fn restore_precondition(headers: &HeaderMap) -> Result<Option<i32>> {
+    headers
+        .get(header::IF_MATCH)
+        .map(|v| {
+            v.to_str()
+                .ok()
+                .and_then(|s| s.trim_matches('"').parse::<i32>().ok())
+                .ok_or(AppError::BadRequest("Invalid version in If-Match header".into()))
+        })
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(header::HeaderName, &str)]) -> HeaderMap {
+        pairs.iter().map(|(name, value)| (name.clone(), HeaderValue::from_str(value).unwrap())).collect()
+    }
+
+    #[test]
+    fn test_upload_precondition() {
+        assert_eq!(upload_precondition(&headers(&[(header::IF_NONE_MATCH, "*")])).ok(), Some(0));
+        assert_eq!(upload_precondition(&headers(&[(header::IF_MATCH, "\"7\"")])).ok(), Some(7));
+        assert!(upload_precondition(&headers(&[(header::IF_NONE_MATCH, "\"7\"")])).is_err());
+        assert!(upload_precondition(&headers(&[(header::IF_MATCH, "\"\"")])).is_err());
+        assert!(upload_precondition(&HeaderMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_cached_and_restore_versions() {
+        assert_eq!(cached_version(&headers(&[(header::IF_NONE_MATCH, "\"3\"")])), Some(3));
+        assert_eq!(cached_version(&headers(&[(header::IF_NONE_MATCH, "*")])), None);
+        assert_eq!(restore_precondition(&HeaderMap::new()).ok(), Some(None));
+        assert_eq!(restore_precondition(&headers(&[(header::IF_MATCH, "4")])).ok(), Some(Some(4)));
+        assert!(restore_precondition(&headers(&[(header::IF_MATCH, "four")])).is_err());
+    }
+}
@@ -0,0 +1,65 @@
+use crate::api::schemas::blocklist::{BlockNetworkRequest, BlocklistEntry, BlocklistResponse, UnblockNetworkQuery};
+use crate::api::{AppState, MgmtState};
+use crate::domain::blocklist::BlockedNetwork;
+use crate::error::{AppError, Result};
+use axum::Json;
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Query, State};
+use axum::http::{Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::net::SocketAddr;
+use time::format_description::well_known::Rfc3339;
+
+/// Middleware rejecting requests from blocklisted networks before they reach rate limiting.
+pub(crate) async fn reject_blocked_clients(State(state): State<AppState>, req: Request<Body>, next: Next) -> Response {
+    if let Some(ConnectInfo(peer)) = req.extensions().get::<ConnectInfo<SocketAddr>>() {
+        let client_ip = state.rate_limit_service.extractor.identify_client_ip(req.headers(), peer.ip());
+        if state.blocklist_service.is_blocked(client_ip) {
+            tracing::debug!(client.ip = %client_ip, "Rejected request from blocklisted network");
+            return AppError::Forbidden("Access denied".to_string()).into_response();
+        }
+    }
+
+    next.run(req).await
+}
+
+/// Lists every blocked network, including those loaded from the blocklist file.
+pub(crate) async fn list_blocklist(State(state): State<MgmtState>) -> impl IntoResponse {
+    let entries = state.blocklist_service.entries().into_iter().map(entry_to_response).collect();
+    Json(BlocklistResponse { entries })
+}
+
+/// Blocks a network on every instance.
+///
+/// # Errors
+/// Returns `AppError::BadRequest` if the network is not a valid CIDR or address.
+pub(crate) async fn block_network(
+    State(state): State<MgmtState>,
+    Json(payload): Json<BlockNetworkRequest>,
+) -> Result<impl IntoResponse> {
+    let entry = state.blocklist_service.add(&payload.network, payload.reason.as_deref()).await?;
+    Ok((StatusCode::CREATED, Json(entry_to_response(entry))))
+}
+
+/// Unblocks a network added through this API.
+///
+/// # Errors
+/// Returns `AppError::NotFound` if the network is not blocked.
+/// Returns `AppError::Conflict` if the network is defined in the blocklist file.
+pub(crate) async fn unblock_network(
+    State(state): State<MgmtState>,
+    Query(query): Query<UnblockNetworkQuery>,
+) -> Result<impl IntoResponse> {
+    state.blocklist_service.remove(&query.network).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn entry_to_response(entry: BlockedNetwork) -> BlocklistEntry {
+    BlocklistEntry {
+        network: entry.network.to_string(),
+        reason: entry.reason,
+        source: entry.source.to_string(),
+        created_at: entry.created_at.and_then(|ts| ts.format(&Rfc3339).ok()),
+    }
+}
@@ -0,0 +1,61 @@
+use crate::api::AppState;
+use crate::config::{CompressionConfig, RouteClass};
+use axum::Router;
+use axum::http::{Response, header};
+use tower_http::compression::CompressionLayer;
+use tower_http::compression::predicate::{Predicate, SizeAbove};
+
+/// Content types worth compressing. Attachment and backup blobs are end-to-end encrypted
+/// and would not shrink, so everything else is passed through untouched.
+const COMPRESSIBLE_CONTENT_TYPES: &[&str] = &["application/json", "application/x-protobuf"];
+
+#[derive(Clone, Copy, Debug)]
+struct CompressibleContentType;
+
+impl Predicate for CompressibleContentType {
+    fn should_compress<B>(&self, response: &Response<B>) -> bool
+    where
+        B: http_body::Body,
+    {
+        response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(';').next())
+            .is_some_and(|mime| COMPRESSIBLE_CONTENT_TYPES.contains(&mime.trim()))
+    }
+}
+
+/// Adds zstd/gzip response compression to `router` unless it is disabled for `class`.
+pub(crate) fn compress(router: Router<AppState>, config: &CompressionConfig, class: RouteClass) -> Router<AppState> {
+    if config.disabled_routes.contains(&class) {
+        return router;
+    }
+
+    router.layer(
+        CompressionLayer::new().compress_when(SizeAbove::new(config.min_size_bytes).and(CompressibleContentType)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(content_type: &str) -> Response<String> {
+        Response::builder().header(header::CONTENT_TYPE, content_type).body(String::new()).expect("valid response")
+    }
+
+    #[test]
+    fn test_allowlisted_content_types_are_compressible() {
+        assert!(CompressibleContentType.should_compress(&response("application/json")));
+        assert!(CompressibleContentType.should_compress(&response("application/json; charset=utf-8")));
+        assert!(CompressibleContentType.should_compress(&response("application/x-protobuf")));
+    }
+
+    #[test]
+    fn test_other_content_types_are_not_compressed() {
+        assert!(!CompressibleContentType.should_compress(&response("application/octet-stream")));
+        assert!(!CompressibleContentType.should_compress(&response("text/yaml")));
+        assert!(!CompressibleContentType.should_compress(&Response::new(String::new())));
+    }
+}
@@ -0,0 +1,104 @@
+use crate::error::AppError;
+use axum::BoxError;
+use axum::error_handling::HandleErrorLayer;
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use axum::routing::MethodRouter;
+use opentelemetry::{KeyValue, global, metrics::Counter};
+use tower::ServiceBuilder;
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower::load_shed::LoadShedLayer;
+use tower::load_shed::error::Overloaded;
+
+#[derive(Clone, Debug)]
+struct Metrics {
+    shed_total: Counter<u64>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let meter = global::meter("obscura-server");
+        Self {
+            shed_total: meter
+                .u64_counter("obscura_http_requests_shed_total")
+                .with_description("Requests rejected because their route was at its concurrency limit")
+                .build(),
+        }
+    }
+}
+
+/// Caps `route` at `max_in_flight` concurrent requests across all clients. Requests over the
+/// cap are not queued but answered at once with `503 Service Unavailable` and `Retry-After`.
+/// A limit of zero leaves the route unlimited.
+pub(crate) fn limit<S>(route: MethodRouter<S>, name: &'static str, max_in_flight: usize) -> MethodRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    if max_in_flight == 0 {
+        return route;
+    }
+
+    let metrics = Metrics::new();
+    route.layer(
+        ServiceBuilder::new()
+            .layer(HandleErrorLayer::new(move |err: BoxError| {
+                let metrics = metrics.clone();
+                async move { shed(&err, name, &metrics) }
+            }))
+            .layer(LoadShedLayer::new())
+            .layer(GlobalConcurrencyLimitLayer::new(max_in_flight)),
+    )
+}
+
+fn shed(err: &BoxError, route: &'static str, metrics: &Metrics) -> Response {
+    if !err.is::<Overloaded>() {
+        tracing::error!(error = %err, route, "Concurrency limiter failed");
+        return AppError::Internal.into_response();
+    }
+
+    tracing::warn!(route, "Route is at its concurrency limit, shedding request");
+    metrics.shed_total.add(1, &[KeyValue::new("route", route)]);
+    ([(header::RETRY_AFTER, "1")], AppError::ServiceUnavailable).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+    use std::sync::Arc;
+    use tokio::sync::Notify;
+
+    #[tokio::test]
+    async fn test_requests_over_the_limit_are_shed() {
+        let entered = Arc::new(Notify::new());
+        let release = Arc::new(Notify::new());
+        let handler = {
+            let (entered, release) = (Arc::clone(&entered), Arc::clone(&release));
+            move || {
+                let (entered, release) = (Arc::clone(&entered), Arc::clone(&release));
+                async move {
+                    entered.notify_one();
+                    release.notified().await;
+                }
+            }
+        };
+        let router = axum::Router::new().route("/", limit(axum::routing::get(handler), "test", 1));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.expect("bind test listener");
+        let addr = listener.local_addr().expect("listener address");
+        tokio::spawn(axum::serve(listener, router).into_future());
+
+        let client = reqwest::Client::new();
+        let url = format!("http://{addr}/");
+        let first = tokio::spawn(client.get(&url).send());
+        entered.notified().await;
+
+        // The first request holds the only slot
+        let second = client.get(&url).send().await.expect("second request");
+        assert_eq!(second.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(second.headers().get(header::RETRY_AFTER).expect("retry-after header"), "1");
+
+        release.notify_one();
+        let first = first.await.expect("first request task").expect("first request");
+        assert_eq!(first.status(), StatusCode::OK);
+    }
+}
@@ -1,6 +1,8 @@
 use crate::api::AppState;
+use crate::api::keys::ensure_pre_key_batch_size;
 use crate::api::middleware::AuthUser;
 use crate::api::schemas::devices::{CreateDeviceRequest, DeviceListResponse, DeviceResponse, UpdateDeviceRequest};
+use crate::api::schemas::validation::Validate;
 use crate::error::{AppError, Result};
 use axum::{
     Json,
@@ -14,13 +16,16 @@ use uuid::Uuid;
 /// Creates a new device for the authenticated user and returns a full JWT.
 ///
 /// # Errors
-/// Returns `AppError::BadRequest` if validation fails or keys are malformed.
+/// Returns `AppError::TooManyPreKeys` if the request carries more one-time prekeys than allowed.
+/// Returns `AppError::Validation` if a field is invalid.
+/// Returns `AppError::BadRequest` if keys are malformed.
 pub(crate) async fn create_device(
     auth_user: AuthUser,
     State(state): State<AppState>,
     Json(payload): Json<CreateDeviceRequest>,
 ) -> Result<impl IntoResponse> {
-    payload.validate().map_err(AppError::BadRequest)?;
+    ensure_pre_key_batch_size(&state.config.messaging, payload.one_time_pre_keys.len())?;
+    payload.validate()?;
 
     let session = state
         .device_service
@@ -71,6 +76,7 @@ pub(crate) async fn get_device(
 /// Updates a device's metadata.
 ///
 /// # Errors
+/// Returns `AppError::Validation` if the name is too long.
 /// Returns `AppError::NotFound` if the device doesn't exist or isn't owned by the user.
 pub(crate) async fn update_device(
     auth_user: AuthUser,
@@ -78,6 +84,7 @@ pub(crate) async fn update_device(
     Path(device_id): Path<Uuid>,
     Json(payload): Json<UpdateDeviceRequest>,
 ) -> Result<impl IntoResponse> {
+    payload.validate()?;
     let device = state.device_service.update_device(device_id, auth_user.user_id, payload.name).await?;
     Ok(Json(device_to_response(device)))
 }
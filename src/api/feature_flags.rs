@@ -0,0 +1,53 @@
+use crate::api::MgmtState;
+use crate::api::schemas::feature_flags::{FeatureFlagEntry, FeatureFlagsResponse, SetFeatureFlagRequest};
+use crate::domain::feature_flag::FeatureFlag;
+use crate::error::Result;
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use time::format_description::well_known::Rfc3339;
+
+/// Lists every feature flag.
+pub(crate) async fn list_feature_flags(State(state): State<MgmtState>) -> impl IntoResponse {
+    let flags = state.feature_flag_service.flags().into_iter().map(flag_to_response).collect();
+    Json(FeatureFlagsResponse { flags })
+}
+
+/// Creates or replaces a feature flag on every instance.
+///
+/// # Errors
+/// Returns `AppError::BadRequest` if the name or rollout percent is invalid.
+pub(crate) async fn set_feature_flag(
+    State(state): State<MgmtState>,
+    Path(name): Path<String>,
+    Json(payload): Json<SetFeatureFlagRequest>,
+) -> Result<impl IntoResponse> {
+    let flag = state
+        .feature_flag_service
+        .set(&name, payload.enabled, payload.rollout_percent.unwrap_or(100), payload.description.as_deref())
+        .await?;
+    Ok(Json(flag_to_response(flag)))
+}
+
+/// Deletes a feature flag, turning it off.
+///
+/// # Errors
+/// Returns `AppError::NotFound` if the flag does not exist.
+pub(crate) async fn delete_feature_flag(
+    State(state): State<MgmtState>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse> {
+    state.feature_flag_service.remove(&name).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn flag_to_response(flag: FeatureFlag) -> FeatureFlagEntry {
+    FeatureFlagEntry {
+        name: flag.name,
+        enabled: flag.enabled,
+        rollout_percent: flag.rollout_percent,
+        description: flag.description,
+        updated_at: flag.updated_at.format(&Rfc3339).ok(),
+    }
+}
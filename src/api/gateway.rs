@@ -1,11 +1,16 @@
-use crate::api::AppState;
-use crate::api::schemas::gateway::{TicketResponse, WsParams};
+use crate::api::schemas::gateway::{RouteResponse, SessionStatsResponse, TicketResponse, WsParams};
+use crate::api::{AppState, MgmtState};
+use crate::error::AppError;
+use crate::services::gateway::session_limits::SessionLimit;
+use crate::services::gateway::{SessionOptions, SessionRefusal};
+use crate::telemetry;
 use axum::{
     extract::{Query, State, ws::WebSocketUpgrade},
-    http::Extensions,
+    http::{Extensions, header},
     response::IntoResponse,
 };
 use tower_http::request_id::RequestId;
+use uuid::Uuid;
 
 /// Generates a connection ticket for the WebSocket gateway.
 ///
@@ -15,18 +20,45 @@ use tower_http::request_id::RequestId;
 pub(crate) async fn generate_ticket(
     auth_user: crate::api::middleware::AuthUser,
     State(state): State<AppState>,
-) -> Result<impl IntoResponse, crate::error::AppError> {
-    let device_id = auth_user
-        .device_id
-        .ok_or_else(|| crate::error::AppError::Forbidden("Device-scoped token required".to_string()))?;
+) -> Result<impl IntoResponse, AppError> {
+    let device_id =
+        auth_user.device_id.ok_or_else(|| AppError::Forbidden("Device-scoped token required".to_string()))?;
 
-    let ticket = uuid::Uuid::new_v4().to_string();
-    state.ws_ticket_cache.set(&ticket, device_id.to_string().as_bytes()).await.map_err(|e| {
+    // The token's expiry travels with the ticket so the session knows when it must be re-authenticated,
+    // and so does the resume token the session leaves its delivery state under on shutdown. The user,
+    // whose sessions are capped per instance, comes last (after an empty resume token if there is none)
+    // so that tickets from instances that do not record it still parse.
+    let ticket = Uuid::new_v4().to_string();
+    let resume_token = state.gateway_service.resumable().then(|| Uuid::new_v4().to_string());
+    let value = format!(
+        "{device_id}:{}:{}:{}",
+        auth_user.expires_at,
+        resume_token.as_deref().unwrap_or_default(),
+        auth_user.user_id
+    );
+    state.ws_ticket_cache.set(&ticket, value.as_bytes()).await.map_err(|e| {
         tracing::error!(error = %e, "Failed to cache websocket ticket");
-        crate::error::AppError::InternalMsg("Failed to generate ticket".to_string())
+        AppError::InternalMsg("Failed to generate ticket".to_string())
     })?;
 
-    Ok((axum::http::StatusCode::CREATED, axum::Json(TicketResponse { ticket })))
+    Ok((axum::http::StatusCode::CREATED, axum::Json(TicketResponse { ticket, resume_token })))
+}
+
+/// Returns the signed routing hint for the authenticated user.
+///
+/// Load balancers can hash on the routing key so that a user's HTTP requests and
+/// WebSocket sessions land on the same instance and skip the Redis fan-out hop.
+pub(crate) async fn get_route(
+    auth_user: crate::api::middleware::AuthUser,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    let hint = state.gateway_service.routing_hint(auth_user.user_id);
+    axum::Json(RouteResponse { routing_key: hint.key, signature: hint.signature })
+}
+
+/// Reports the number of WebSocket sessions held by this instance.
+pub(crate) async fn session_stats(State(state): State<MgmtState>) -> impl IntoResponse {
+    axum::Json(SessionStatsResponse { active_sessions: state.sessions.active() })
 }
 
 pub(crate) async fn websocket_handler(
@@ -39,16 +71,16 @@ pub(crate) async fn websocket_handler(
         .get::<RequestId>()
         .map_or_else(|| "unknown".to_string(), |id| id.header_value().to_str().unwrap_or_default().to_string());
 
-    // Validate ticket — contains device_id
-    let device_id_res = match state.ws_ticket_cache.get(&params.ticket).await {
+    // Validate ticket — contains device_id, the token expiry, the session's resume token and the user
+    let ticket_res = match state.ws_ticket_cache.get(&params.ticket).await {
         Ok(Some(bytes)) => match String::from_utf8(bytes) {
-            Ok(id_str) => match uuid::Uuid::parse_str(&id_str) {
-                Ok(id) => {
+            Ok(value) => match parse_ticket(&value) {
+                Some(ticket) => {
                     // Delete ticket so it can only be used once
                     let _ = state.ws_ticket_cache.delete(&params.ticket).await;
-                    Ok(id)
+                    Ok(ticket)
                 }
-                Err(_) => Err("Invalid device ID format in cache".to_string()),
+                None => Err("Invalid ticket format in cache".to_string()),
             },
             Err(_) => Err("Invalid UTF-8 in cache".to_string()),
         },
@@ -59,17 +91,63 @@ pub(crate) async fn websocket_handler(
         }
     };
 
-    match device_id_res {
-        Ok(device_id) => ws.on_upgrade(move |socket| {
-            let service = state.gateway_service.clone();
-            let shutdown = state.shutdown_rx.clone();
-            async move {
-                service.handle_socket(socket, device_id, request_id, shutdown).await;
-            }
-        }),
+    match ticket_res {
+        Ok(Ticket { device_id, user_id, auth_expires_at, resume_token }) => {
+            let claim = match state.gateway_service.open_session(device_id, user_id).await {
+                Ok(claim) => claim,
+                Err(SessionRefusal::DeviceConnected) => {
+                    tracing::info!(
+                        device.id = %telemetry::id(device_id),
+                        "WebSocket handshake refused: device already has a session"
+                    );
+                    return axum::http::StatusCode::CONFLICT.into_response();
+                }
+                Err(SessionRefusal::Limit(limit)) => {
+                    tracing::info!(
+                        device.id = %telemetry::id(device_id),
+                        limit = limit.as_str(),
+                        "WebSocket handshake refused: session limit reached"
+                    );
+                    let retry_after_secs = state.gateway_service.refusal_retry_after_secs();
+                    return match limit {
+                        SessionLimit::Instance => {
+                            ([(header::RETRY_AFTER, retry_after_secs.to_string())], AppError::ServiceUnavailable)
+                                .into_response()
+                        }
+                        SessionLimit::User => AppError::RateLimited { retry_after_secs }.into_response(),
+                    };
+                }
+            };
+            let options = SessionOptions { credit_flow: params.credit, resume_token, resume_from: params.resume };
+            ws.on_upgrade(move |socket| {
+                let service = state.gateway_service.clone();
+                let shutdown = state.shutdown_rx.clone();
+                async move {
+                    service.handle_socket(socket, claim, auth_expires_at, request_id, options, shutdown).await;
+                }
+            })
+        }
         Err(e) => {
             tracing::warn!(error = %e, "WebSocket handshake failed: invalid ticket");
             axum::http::StatusCode::UNAUTHORIZED.into_response()
         }
     }
 }
+
+/// What a connection ticket was issued for.
+struct Ticket {
+    device_id: Uuid,
+    /// Missing from tickets issued by instances that do not record it.
+    user_id: Option<Uuid>,
+    auth_expires_at: usize,
+    resume_token: Option<String>,
+}
+
+fn parse_ticket(value: &str) -> Option<Ticket> {
+    let mut parts = value.splitn(4, ':');
+    let device_id = Uuid::parse_str(parts.next()?).ok()?;
+    let auth_expires_at = parts.next()?.parse().ok()?;
+    let resume_token = parts.next().filter(|token| !token.is_empty()).map(str::to_string);
+    let user_id = parts.next().and_then(|user_id| Uuid::parse_str(user_id).ok());
+    Some(Ticket { device_id, user_id, auth_expires_at, resume_token })
+}
@@ -1,5 +1,6 @@
 use crate::api::MgmtState;
-use crate::api::schemas::health::HealthResponse;
+use crate::api::schemas::health::{DatabasePoolResponse, HealthResponse, MigrationStatusResponse, PendingMigration};
+use crate::error::Result;
 use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
 
 /// Liveness probe: returns 200 OK as long as the server is running.
@@ -7,12 +8,17 @@ pub(crate) async fn livez() -> impl IntoResponse {
     StatusCode::OK
 }
 
-/// Readiness probe: checks connectivity to the database, S3, and `PubSub`.
+/// Readiness probe: checks connectivity to the database, S3, and `PubSub` when Redis is used.
+/// The push provider check, when enabled, is reported without affecting readiness: a rejected
+/// credential breaks push notifications only, and this instance can still serve every request.
+/// Database pool statistics are included for sizing and never affect readiness either, and
+/// neither does read-only mode, in which the database check runs against the pool serving reads.
 pub(crate) async fn readyz(State(state): State<MgmtState>) -> impl IntoResponse {
-    let (db_res, storage_res, pubsub_res) = tokio::join!(
+    let (db_res, storage_res, pubsub_res, push_res) = tokio::join!(
         state.health_service.check_db(),
         state.health_service.check_storage(),
-        state.health_service.check_pubsub()
+        state.health_service.check_pubsub(),
+        state.health_service.check_push()
     );
 
     let mut status_code = StatusCode::OK;
@@ -32,20 +38,64 @@ pub(crate) async fn readyz(State(state): State<MgmtState>) -> impl IntoResponse
         "ok"
     };
 
-    let pubsub_status = if let Err(e) = pubsub_res {
-        tracing::warn!(error = %e, component = "pubsub", "Readiness probe failed");
-        status_code = StatusCode::SERVICE_UNAVAILABLE;
-        "error"
-    } else {
-        "ok"
-    };
+    let pubsub_status = pubsub_res.map(|res| {
+        if let Err(e) = res {
+            tracing::warn!(error = %e, component = "pubsub", "Readiness probe failed");
+            status_code = StatusCode::SERVICE_UNAVAILABLE;
+            "error"
+        } else {
+            "ok"
+        }
+    });
+
+    let push_status = push_res.map(|res| {
+        if let Err(e) = res {
+            tracing::warn!(error = %e, component = "push", "Push provider check failed");
+            "error"
+        } else {
+            "ok"
+        }
+    });
 
+    let pool = state.health_service.pool_stats();
     let response = HealthResponse {
         status: if status_code == StatusCode::OK { "ok" } else { "error" }.to_string(),
         database: db_status.to_string(),
+        database_mode: state.health_service.database_mode().as_str().to_string(),
         storage: storage_status.to_string(),
-        pubsub: pubsub_status.to_string(),
+        pubsub: pubsub_status.map(ToString::to_string),
+        push: push_status.map(ToString::to_string),
+        database_pool: DatabasePoolResponse {
+            size: pool.size,
+            idle: pool.idle,
+            max_connections: pool.max_connections,
+            acquires: pool.acquires,
+            mean_acquire_wait_ms: pool.mean_acquire_wait.as_secs_f64() * 1000.0,
+            acquire_timeouts: pool.acquire_timeouts,
+        },
     };
 
     (status_code, Json(response))
 }
+
+/// Reports the schema version and how it compares to the migrations in this build, for
+/// checking that every replica of a rollout agrees.
+///
+/// # Errors
+/// Returns `AppError::Database` if the migrations table cannot be read.
+pub(crate) async fn migrations(State(state): State<MgmtState>) -> Result<impl IntoResponse> {
+    let status = state.health_service.migration_status().await?;
+    Ok(Json(MigrationStatusResponse {
+        up_to_date: status.is_up_to_date(),
+        current_version: status.current_version,
+        latest_version: status.latest_version,
+        pending: status
+            .pending
+            .into_iter()
+            .map(|m| PendingMigration { version: m.version, description: m.description })
+            .collect(),
+        dirty: status.dirty,
+        modified: status.modified,
+        unknown: status.unknown,
+    }))
+}
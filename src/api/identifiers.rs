@@ -0,0 +1,80 @@
+use crate::api::AppState;
+use crate::api::middleware::AuthUser;
+use crate::api::schemas::identifiers::{
+    AddIdentifierRequest, IdentifierListResponse, IdentifierResponse, VerifyIdentifierRequest,
+};
+use crate::api::schemas::validation::Validate;
+use crate::domain::identifier::{Identifier, IdentifierKind};
+use crate::error::Result;
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use time::format_description::well_known::Rfc3339;
+
+/// Lists the email address and phone number bound to the authenticated user.
+///
+/// # Errors
+/// Returns `AppError::Database` if the query fails.
+pub(crate) async fn list_identifiers(auth_user: AuthUser, State(state): State<AppState>) -> Result<impl IntoResponse> {
+    let identifiers = state.identifier_service.list(auth_user.user_id).await?;
+    Ok(Json(IdentifierListResponse { identifiers: identifiers.into_iter().map(identifier_to_response).collect() }))
+}
+
+/// Binds an identifier to the authenticated user and sends it a verification code.
+///
+/// # Errors
+/// Returns `AppError::Validation` if the value is not a valid identifier of its kind.
+/// Returns `AppError::ServiceUnavailable` if the code could not be sent.
+pub(crate) async fn add_identifier(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Json(payload): Json<AddIdentifierRequest>,
+) -> Result<impl IntoResponse> {
+    payload.validate()?;
+    state.identifier_service.add(auth_user.user_id, payload.kind, &payload.value, payload.discoverable).await?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Confirms the verification code sent to one of the user's identifiers.
+///
+/// # Errors
+/// Returns `AppError::NotFound` if no code is outstanding.
+/// Returns `AppError::Gone` if the code has expired.
+/// Returns `AppError::BadRequest` if the code is wrong.
+/// Returns `AppError::Conflict` if the identifier is verified for another account.
+pub(crate) async fn verify_identifier(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Path(kind): Path<IdentifierKind>,
+    Json(payload): Json<VerifyIdentifierRequest>,
+) -> Result<impl IntoResponse> {
+    payload.validate()?;
+    state.identifier_service.verify(auth_user.user_id, kind, &payload.code).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Unbinds one of the user's identifiers.
+///
+/// # Errors
+/// Returns `AppError::NotFound` if none of this kind is bound.
+pub(crate) async fn remove_identifier(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Path(kind): Path<IdentifierKind>,
+) -> Result<impl IntoResponse> {
+    state.identifier_service.remove(auth_user.user_id, kind).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn identifier_to_response(identifier: Identifier) -> IdentifierResponse {
+    IdentifierResponse {
+        kind: identifier.kind,
+        verified: identifier.verified_at.is_some(),
+        discoverable: identifier.discoverable,
+        verified_at: identifier.verified_at.map(|ts| ts.format(&Rfc3339).unwrap_or_default()),
+        created_at: identifier.created_at.format(&Rfc3339).unwrap_or_default(),
+    }
+}
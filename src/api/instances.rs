@@ -0,0 +1,33 @@
+use crate::api::MgmtState;
+use crate::api::schemas::instances::{InstanceEntry, InstancesResponse};
+use crate::domain::instance::InstanceInfo;
+use crate::error::{AppError, Result};
+use axum::Json;
+use axum::extract::State;
+use axum::response::IntoResponse;
+use time::format_description::well_known::Rfc3339;
+
+/// Lists the live instances with their version and session count, and names the one answering.
+///
+/// # Errors
+/// Returns `AppError::ServiceUnavailable` if the peer list cannot be read.
+pub(crate) async fn list_instances(State(state): State<MgmtState>) -> Result<impl IntoResponse> {
+    let peers = state.instance_service.peers().await.map_err(|e| {
+        tracing::warn!(error = %e, "Failed to list instances");
+        AppError::ServiceUnavailable
+    })?;
+    Ok(Json(InstancesResponse {
+        instance_id: state.instance_service.id().to_string(),
+        instances: peers.into_iter().map(instance_to_response).collect(),
+    }))
+}
+
+fn instance_to_response(instance: InstanceInfo) -> InstanceEntry {
+    InstanceEntry {
+        id: instance.id,
+        version: instance.version,
+        started_at: instance.started_at.format(&Rfc3339).ok(),
+        active_sessions: instance.active_sessions,
+        last_heartbeat_at: instance.heartbeat_at.format(&Rfc3339).ok(),
+    }
+}
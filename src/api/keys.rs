@@ -1,52 +1,70 @@
 use crate::api::AppState;
 use crate::api::middleware::AuthUser;
-use crate::api::schemas::keys::{PreKeyBundleResponse, PreKeyUploadRequest};
+use crate::api::protobuf::{Negotiated, ResponseFormat};
+use crate::api::schemas::keys::{KeyValidationResponse, PreKeyBundleQuery, PreKeyBundleResponse, PreKeyUploadRequest};
+use crate::api::schemas::validation::Validate;
+use crate::config::MessagingConfig;
+use crate::domain::crypto::PublicKey;
+use crate::domain::keys::{KeyField, KeyIssue, SignedPreKey};
 use crate::error::{AppError, Result};
 use crate::services::key_service::KeyUploadParams;
 use axum::{
     Json,
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     response::IntoResponse,
 };
+use std::collections::HashSet;
 use std::convert::TryInto;
 use uuid::Uuid;
 
 /// Fetches all pre-key bundles for a user (one per device).
+/// With `?reserve=true` the one-time pre-keys are reserved instead of consumed.
+/// Responds with a `PreKeyBundles` protobuf to clients that ask for one.
 ///
 /// # Errors
 /// Returns `AppError::Forbidden` if a device-scoped token is not provided.
 /// Returns `AppError::NotFound` if the user has no registered devices or keys.
+/// Returns `AppError::Gone` if the user deleted their account.
 pub(crate) async fn get_pre_key_bundles(
     auth_user: AuthUser,
     State(state): State<AppState>,
     Path(user_id): Path<Uuid>,
+    Query(query): Query<PreKeyBundleQuery>,
+    format: ResponseFormat,
 ) -> Result<impl IntoResponse> {
     let _ = auth_user.device_id.ok_or_else(|| AppError::Forbidden("Device-scoped token required".to_string()))?;
 
-    let bundles = state.key_service.get_pre_key_bundles_for_user(user_id).await?;
+    let (bundles, reservation) = state.key_service.get_pre_key_bundles_for_user(user_id, query.reserve).await?;
 
     if bundles.is_empty() {
+        state.account_service.ensure_not_deleted(user_id).await?;
         return Err(AppError::NotFound);
     }
 
-    let response: Vec<PreKeyBundleResponse> = bundles.into_iter().map(PreKeyBundleResponse::from).collect();
-    Ok(Json(response))
+    let response: Vec<PreKeyBundleResponse> = bundles
+        .into_iter()
+        .map(|bundle| PreKeyBundleResponse::from(bundle).with_reservation(reservation.as_ref()))
+        .collect();
+    Ok(format.render(StatusCode::OK, response))
 }
 
-/// Uploads new pre-keys for the authenticated device.
+/// Uploads new pre-keys for the authenticated device, as JSON or an `UploadKeysRequest` protobuf.
 ///
 /// # Errors
 /// Returns `AppError::Forbidden` if a device-scoped token is not provided.
-/// Returns `AppError::BadRequest` if the keys are malformed or validation fails.
+/// Returns `AppError::TooManyPreKeys` if the request carries more one-time prekeys than allowed.
+/// Returns `AppError::Validation` if a field is invalid.
+/// Returns `AppError::BadRequest` if the keys are malformed.
 pub(crate) async fn upload_keys(
     auth_user: AuthUser,
     State(state): State<AppState>,
-    Json(payload): Json<PreKeyUploadRequest>,
+    Negotiated(payload): Negotiated<PreKeyUploadRequest>,
 ) -> Result<impl IntoResponse> {
     let device_id = auth_user.device_id.ok_or(AppError::Forbidden("Device-scoped token required".to_string()))?;
 
-    payload.validate().map_err(AppError::BadRequest)?;
+    ensure_pre_key_batch_size(&state.config.messaging, payload.one_time_pre_keys.len())?;
+    payload.validate()?;
 
     let params = KeyUploadParams {
         device_id,
@@ -65,3 +83,80 @@ pub(crate) async fn upload_keys(
 
     Ok(StatusCode::OK)
 }
+
+/// Checks a pre-key upload the way `upload_keys` would, without storing anything, and reports
+/// every problem with the batch rather than only the first.
+///
+/// # Errors
+/// Returns `AppError::Forbidden` if a device-scoped token is not provided.
+/// Returns `AppError::TooManyPreKeys` if the request carries more one-time prekeys than allowed.
+pub(crate) async fn validate_keys(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Negotiated(payload): Negotiated<PreKeyUploadRequest>,
+) -> Result<impl IntoResponse> {
+    let device_id = auth_user.device_id.ok_or(AppError::Forbidden("Device-scoped token required".to_string()))?;
+
+    ensure_pre_key_batch_size(&state.config.messaging, payload.one_time_pre_keys.len())?;
+
+    let mut issues = Vec::new();
+    if payload.identity_key.is_some() && payload.registration_id.is_none() {
+        issues.push(KeyIssue::new(
+            KeyField::RegistrationId,
+            None,
+            "registrationId is required when identityKey is provided",
+        ));
+    }
+
+    let identity_key = payload.identity_key.map(PublicKey::try_from).transpose().unwrap_or_else(|e| {
+        issues.push(KeyIssue::new(KeyField::IdentityKey, None, e));
+        None
+    });
+
+    let signed_key_id = payload.signed_pre_key.key_id;
+    let signed_pre_key = match SignedPreKey::try_from(payload.signed_pre_key) {
+        Ok(key) => Some(key),
+        Err(e) => {
+            issues.push(KeyIssue::new(KeyField::SignedPreKey, Some(signed_key_id), e));
+            None
+        }
+    };
+
+    let mut seen = HashSet::with_capacity(payload.one_time_pre_keys.len());
+    let mut one_time_pre_keys = Vec::with_capacity(payload.one_time_pre_keys.len());
+    for key in payload.one_time_pre_keys {
+        let key_id = key.key_id;
+        if !seen.insert(key_id) {
+            issues.push(KeyIssue::new(
+                KeyField::OneTimePreKeys,
+                Some(key_id),
+                format!("Duplicate prekey ID: {key_id}"),
+            ));
+            continue;
+        }
+        match key.try_into() {
+            Ok(key) => one_time_pre_keys.push(key),
+            Err(e) => issues.push(KeyIssue::new(KeyField::OneTimePreKeys, Some(key_id), e)),
+        }
+    }
+
+    let report = state
+        .key_service
+        .validate_upload(device_id, identity_key.as_ref(), signed_pre_key.as_ref(), &one_time_pre_keys)
+        .await?;
+    issues.extend(report.issues);
+
+    Ok(Json(KeyValidationResponse::new(report.is_takeover, issues)))
+}
+
+/// Rejects a request carrying more one-time prekeys than a single request may import,
+/// before any of them are decoded.
+///
+/// # Errors
+/// Returns `AppError::TooManyPreKeys` if `count` exceeds the per-request limit.
+pub(crate) const fn ensure_pre_key_batch_size(config: &MessagingConfig, count: usize) -> Result<()> {
+    if count > config.max_pre_keys_per_request {
+        return Err(AppError::TooManyPreKeys { limit: config.max_pre_keys_per_request });
+    }
+    Ok(())
+}
@@ -0,0 +1,275 @@
+use crate::adapters::database::DbPool;
+use crate::config::LoadShedConfig;
+use crate::error::AppError;
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{Request, StatusCode, header};
+use axum::middleware::{Next, from_fn_with_state};
+use axum::response::{IntoResponse, Response};
+use axum::routing::MethodRouter;
+use opentelemetry::{
+    KeyValue, global,
+    metrics::{Counter, Gauge},
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Latency samples kept per window. Beyond it, samples replace random earlier ones, so the
+/// p99 still covers the whole window at a fixed memory cost.
+const MAX_SAMPLES: usize = 10_000;
+
+#[derive(Clone, Debug)]
+struct Metrics {
+    shed_total: Counter<u64>,
+    p99_latency_seconds: Gauge<f64>,
+    shedding: Gauge<u64>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let meter = global::meter("obscura-server");
+        Self {
+            shed_total: meter
+                .u64_counter("obscura_http_requests_load_shed_total")
+                .with_description(
+                    "Low-priority requests rejected because the instance was overloaded, by route and reason",
+                )
+                .build(),
+            p99_latency_seconds: meter
+                .f64_gauge("obscura_http_p99_latency_seconds")
+                .with_description("p99 latency of API requests over the last load shedding window")
+                .build(),
+            shedding: meter
+                .u64_gauge("obscura_http_load_shedding")
+                .with_description("1 while low-priority routes are being shed, 0 otherwise")
+                .build(),
+        }
+    }
+}
+
+/// Request latencies of the current window, with the p99 of the last complete one.
+#[derive(Debug)]
+struct LatencyWindow {
+    started: Instant,
+    samples: Vec<Duration>,
+    seen: usize,
+    p99: Option<Duration>,
+}
+
+impl LatencyWindow {
+    fn record(&mut self, latency: Duration) {
+        self.seen += 1;
+        if self.samples.len() < MAX_SAMPLES {
+            self.samples.push(latency);
+        } else {
+            let slot = rand::random_range(0..self.seen);
+            if let Some(sample) = self.samples.get_mut(slot) {
+                *sample = latency;
+            }
+        }
+    }
+
+    /// Closes the window once it is `length` old. A window with fewer than `min_samples`
+    /// requests has no p99, so an idle instance stops shedding.
+    fn roll(&mut self, now: Instant, length: Duration, min_samples: usize) -> bool {
+        if now.duration_since(self.started) < length {
+            return false;
+        }
+        self.p99 = (self.seen >= min_samples.max(1)).then(|| {
+            self.samples.sort_unstable();
+            let rank = (self.samples.len() * 99).div_ceil(100).max(1);
+            self.samples[rank - 1]
+        });
+        self.samples.clear();
+        self.seen = 0;
+        self.started = now;
+        true
+    }
+}
+
+/// Watches API latency and database pool use, and tells low-priority routes when to turn
+/// requests away so that interactive ones (sending messages, fetching keys) keep their headroom.
+#[derive(Clone, Debug)]
+pub struct LoadShedder {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    window: Mutex<LatencyWindow>,
+    shedding: AtomicBool,
+    pool: DbPool,
+    config: LoadShedConfig,
+    metrics: Metrics,
+}
+
+impl LoadShedder {
+    #[must_use]
+    pub fn new(pool: DbPool, config: &LoadShedConfig) -> Self {
+        let window = LatencyWindow { started: Instant::now(), samples: Vec::new(), seen: 0, p99: None };
+        Self {
+            inner: Arc::new(Inner {
+                window: Mutex::new(window),
+                shedding: AtomicBool::new(false),
+                pool,
+                config: config.clone(),
+                metrics: Metrics::new(),
+            }),
+        }
+    }
+
+    fn window_length(&self) -> Duration {
+        Duration::from_secs(self.inner.config.window_secs.max(1))
+    }
+
+    /// The p99 of the last complete window, rolling the current one over if it is due.
+    fn p99(&self, latency: Option<Duration>) -> Option<Duration> {
+        let mut window = self.inner.window.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(latency) = latency {
+            window.record(latency);
+        }
+        if window.roll(Instant::now(), self.window_length(), self.inner.config.min_samples) {
+            let seconds = window.p99.map_or(0.0, |p99| p99.as_secs_f64());
+            self.inner.metrics.p99_latency_seconds.record(seconds, &[]);
+        }
+        window.p99
+    }
+
+    fn observe(&self, latency: Duration) {
+        self.p99(Some(latency));
+    }
+
+    /// Share of the database pool's maximum size that is checked out, in percent.
+    fn pool_percent(&self) -> u64 {
+        let max = u64::from(self.inner.pool.options().get_max_connections().max(1));
+        let size = u64::from(self.inner.pool.size());
+        let idle = u64::try_from(self.inner.pool.num_idle()).unwrap_or(u64::MAX);
+        size.saturating_sub(idle) * 100 / max
+    }
+
+    /// Why low-priority requests should be shed right now, if they should.
+    fn overload(&self) -> Option<&'static str> {
+        let config = &self.inner.config;
+        let threshold = Duration::from_millis(config.p99_latency_ms);
+        let reason = if config.p99_latency_ms > 0 && self.p99(None).is_some_and(|p99| p99 > threshold) {
+            Some("latency")
+        } else if config.db_pool_percent > 0 && self.pool_percent() >= u64::from(config.db_pool_percent) {
+            Some("db_pool")
+        } else {
+            None
+        };
+
+        let shedding = reason.is_some();
+        if self.inner.shedding.swap(shedding, Ordering::Relaxed) != shedding {
+            self.inner.metrics.shedding.record(u64::from(shedding), &[]);
+            match reason {
+                Some(reason) => tracing::warn!(reason, "Instance is overloaded, shedding low-priority requests"),
+                None => tracing::info!("Load recovered, serving low-priority requests again"),
+            }
+        }
+        reason
+    }
+}
+
+/// Middleware timing the requests of the routes it wraps; their p99 latency decides whether
+/// low-priority routes are shed. Rejections (`429` and `503`) are answered without doing the
+/// work, so they are left out.
+pub(crate) async fn monitor_latency(State(shedder): State<LoadShedder>, req: Request<Body>, next: Next) -> Response {
+    let started = Instant::now();
+    let response = next.run(req).await;
+    if !matches!(response.status(), StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE) {
+        shedder.observe(started.elapsed());
+    }
+    response
+}
+
+#[derive(Clone, Debug)]
+struct ShedRoute {
+    shedder: LoadShedder,
+    name: &'static str,
+}
+
+/// Marks `route` as low priority: while the instance is overloaded, its requests are answered
+/// at once with `503 Service Unavailable` and a `Retry-After` of one window.
+pub(crate) fn shed_under_load<S>(route: MethodRouter<S>, name: &'static str, shedder: LoadShedder) -> MethodRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    route.layer(from_fn_with_state(ShedRoute { shedder, name }, reject_when_overloaded))
+}
+
+async fn reject_when_overloaded(State(route): State<ShedRoute>, req: Request<Body>, next: Next) -> Response {
+    let Some(reason) = route.shedder.overload() else {
+        return next.run(req).await;
+    };
+
+    tracing::debug!(route = route.name, reason, "Shedding low-priority request");
+    let attributes = [KeyValue::new("route", route.name), KeyValue::new("reason", reason)];
+    route.shedder.inner.metrics.shed_total.add(1, &attributes);
+    let retry_after = route.shedder.window_length().as_secs().to_string();
+    ([(header::RETRY_AFTER, retry_after)], AppError::ServiceUnavailable).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window() -> LatencyWindow {
+        LatencyWindow { started: Instant::now(), samples: Vec::new(), seen: 0, p99: None }
+    }
+
+    fn shedder(config: &LoadShedConfig) -> LoadShedder {
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .max_connections(4)
+            .connect_lazy("postgres://localhost/unused")
+            .expect("lazy pool");
+        LoadShedder::new(pool, config)
+    }
+
+    #[test]
+    fn test_window_reports_p99_once_it_rolls() {
+        let mut window = window();
+        for ms in 1..=100 {
+            window.record(Duration::from_millis(ms));
+        }
+        let start = window.started;
+
+        assert!(!window.roll(start + Duration::from_secs(5), Duration::from_secs(10), 10));
+        assert_eq!(window.p99, None);
+
+        assert!(window.roll(start + Duration::from_secs(10), Duration::from_secs(10), 10));
+        assert_eq!(window.p99, Some(Duration::from_millis(99)));
+        assert!(window.samples.is_empty());
+
+        // Too few requests in the next window to say anything
+        window.record(Duration::from_secs(30));
+        assert!(window.roll(start + Duration::from_secs(20), Duration::from_secs(10), 10));
+        assert_eq!(window.p99, None);
+    }
+
+    #[test]
+    fn test_window_samples_are_capped() {
+        let mut window = window();
+        for _ in 0..MAX_SAMPLES + 500 {
+            window.record(Duration::from_millis(1));
+        }
+        assert_eq!(window.samples.len(), MAX_SAMPLES);
+        assert_eq!(window.seen, MAX_SAMPLES + 500);
+    }
+
+    #[tokio::test]
+    async fn test_slow_window_triggers_shedding() {
+        let config = LoadShedConfig { p99_latency_ms: 100, db_pool_percent: 0, window_secs: 10, min_samples: 1 };
+        let shedder = shedder(&config);
+        assert_eq!(shedder.overload(), None);
+
+        shedder.observe(Duration::from_millis(500));
+        shedder.inner.window.lock().expect("window lock").started -= Duration::from_secs(10);
+        assert_eq!(shedder.overload(), Some("latency"));
+
+        // An idle window clears it again
+        shedder.inner.window.lock().expect("window lock").started -= Duration::from_secs(10);
+        assert_eq!(shedder.overload(), None);
+    }
+}
@@ -0,0 +1,15 @@
+use crate::api::MgmtState;
+use crate::api::schemas::maintenance::CleanupResponse;
+use crate::error::Result;
+use axum::Json;
+use axum::extract::State;
+use axum::response::IntoResponse;
+
+/// Deletes expired refresh tokens now instead of waiting for the next scheduled run.
+///
+/// # Errors
+/// Returns `AppError::Database` if a batch fails.
+pub(crate) async fn cleanup_refresh_tokens(State(state): State<MgmtState>) -> Result<impl IntoResponse> {
+    let deleted = state.auth_service.purge_expired_refresh_tokens("manual").await?;
+    Ok(Json(CleanupResponse { deleted }))
+}
@@ -1,9 +1,17 @@
 use crate::api::AppState;
 use crate::api::middleware::AuthUser;
+use crate::api::schemas::messaging::{RedeliverRequest, RedeliverResponse};
+use crate::api::schemas::validation::Validate;
 use crate::domain::message::RawSubmission;
-use crate::error::{AppError, Result};
+use crate::error::{AppError, MessagingError, Result};
 use crate::proto::obscura::v1 as proto;
-use axum::{body::Bytes, extract::State, http::HeaderMap, response::IntoResponse};
+use axum::{
+    Json,
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, header},
+    response::IntoResponse,
+};
 use prost::Message;
 use uuid::Uuid;
 
@@ -11,8 +19,11 @@ use uuid::Uuid;
 ///
 /// # Errors
 /// Returns `AppError::Forbidden` if a device-scoped token is not provided.
-/// Returns `AppError::BadRequest` if the request protobuf is malformed or missing the idempotency key.
-/// Returns `AppError::PayloadTooLarge` if the batch size exceeds the limit.
+/// Returns `AppError::Messaging` if the request protobuf is malformed, the idempotency key or a
+/// consumption token is missing or invalid, or the batch size exceeds the limit.
+///
+/// Consumption tokens from prefetched bundles may be passed in `consumption-token` headers;
+/// they are redeemed once the batch has been accepted.
 pub(crate) async fn send_messages(
     auth_user: AuthUser,
     State(state): State<AppState>,
@@ -25,21 +36,32 @@ pub(crate) async fn send_messages(
     let idempotency_key = headers
         .get("idempotency-key")
         .and_then(|v| v.to_str().ok())
-        .ok_or_else(|| AppError::BadRequest("Missing idempotency-key header".to_string()))
-        .and_then(|s| Uuid::parse_str(s).map_err(|e| AppError::BadRequest(format!("Invalid idempotency-key: {e}"))))?;
+        .ok_or(MessagingError::MissingIdempotencyKey)
+        .and_then(|s| Uuid::parse_str(s).map_err(|e| MessagingError::InvalidIdempotencyKey(e.to_string())))?;
+
+    let consumption_tokens = headers
+        .get_all("consumption-token")
+        .iter()
+        .flat_map(|v| v.to_str().unwrap_or_default().split(','))
+        .map(|s| {
+            Uuid::parse_str(s.trim())
+                .map_err(|e| AppError::from(MessagingError::InvalidConsumptionToken(e.to_string())))
+        })
+        .collect::<Result<Vec<_>>>()?;
 
     // 1. Check Idempotency Cache
     if let Ok(Some(cached)) = state.submission_cache.get(&idempotency_key.to_string()).await {
         tracing::info!(key = %idempotency_key, "Returning cached idempotency response");
-        return Ok(cached);
+        return Ok(protobuf_response(cached));
     }
 
     // 2. Protocol Validation & Decoding
-    let request = proto::SendMessageRequest::decode(body)
-        .map_err(|e| AppError::BadRequest(format!("Invalid SendMessageRequest protobuf: {e}")))?;
+    let request =
+        proto::SendMessageRequest::decode(body).map_err(|e| MessagingError::MalformedRequest(e.to_string()))?;
 
-    if request.messages.len() > usize::try_from(state.config.messaging.send_batch_limit).unwrap_or(0) {
-        return Err(AppError::PayloadTooLarge);
+    let limit = state.config.messaging.send_batch_limit;
+    if request.messages.len() > usize::try_from(limit).unwrap_or(0) {
+        return Err(MessagingError::BatchTooLarge { limit: u64::try_from(limit).unwrap_or(0) }.into());
     }
 
     // 3. Simple Domain Mapping (moves only)
@@ -48,6 +70,11 @@ pub(crate) async fn send_messages(
     // 4. Domain Logic: Call Pure Service
     let outcome = state.message_service.send(auth_user.user_id, sender_device_id, submissions).await?;
 
+    // The messages are stored, so a failure here only leaves the reserved keys to lapse.
+    if let Err(e) = state.key_service.redeem_reservations(&consumption_tokens).await {
+        tracing::warn!(error = %e, "Failed to redeem pre-key reservations");
+    }
+
     // 5. Result Mapping
     let response = proto::SendMessageResponse::from(outcome);
     let response_bytes = response.encode_to_vec();
@@ -57,5 +84,29 @@ pub(crate) async fn send_messages(
         tracing::error!(error = %e, "Failed to cache idempotency response");
     }
 
-    Ok(response_bytes)
+    Ok(protobuf_response(response_bytes))
+}
+
+/// Puts messages the device acknowledged within the ACK grace period back in its queue, for a
+/// client that crashed before storing them. They are delivered again on the next gateway
+/// connection.
+///
+/// # Errors
+/// Returns `AppError::Forbidden` if a device-scoped token is not provided.
+/// Returns `AppError::Validation` if no message IDs, or too many, are given.
+pub(crate) async fn redeliver_messages(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Json(payload): Json<RedeliverRequest>,
+) -> Result<impl IntoResponse> {
+    let device_id =
+        auth_user.device_id.ok_or_else(|| AppError::Forbidden("Device-scoped token required".to_string()))?;
+    payload.validate()?;
+
+    let redelivered = state.message_service.redeliver(device_id, &payload.message_ids).await?;
+    Ok(Json(RedeliverResponse { redelivered }))
+}
+
+fn protobuf_response(bytes: Vec<u8>) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "application/x-protobuf")], bytes)
 }
@@ -0,0 +1,99 @@
+use crate::api::MgmtState;
+use crate::api::mgmt_auth::VerifiedClientCert;
+use crate::api::schemas::metadata_index::{
+    MessageMetadataEntry, MetadataQueryEntry, MetadataQueryListQuery, MetadataQueryListResponse, MetadataSearchRequest,
+    MetadataSearchResponse,
+};
+use crate::domain::metadata_index::{MessageMetadata, MetadataQuery, MetadataQueryAudit};
+use crate::error::{AppError, Result};
+use axum::extract::{Query, State};
+use axum::response::IntoResponse;
+use axum::{Extension, Json};
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use uuid::Uuid;
+
+/// Searches the message metadata index for one account's messages and records the query,
+/// with its operator and reason, in the audit log.
+///
+/// # Errors
+/// Returns `AppError::BadRequest` if the operator or reason is missing, an id or timestamp is
+/// malformed, or the query names no account.
+pub(crate) async fn search_metadata(
+    State(state): State<MgmtState>,
+    client_cert: Option<Extension<VerifiedClientCert>>,
+    Json(payload): Json<MetadataSearchRequest>,
+) -> Result<impl IntoResponse> {
+    let query = MetadataQuery {
+        sender_id: parse_id("senderId", payload.sender_id.as_deref())?,
+        recipient_id: parse_id("recipientId", payload.recipient_id.as_deref())?,
+        since: parse_timestamp("since", payload.since.as_deref())?,
+        until: parse_timestamp("until", payload.until.as_deref())?,
+    };
+    let client_cert = client_cert.map(|Extension(cert)| cert.fingerprint);
+    let (audit, entries) = state
+        .metadata_index_service
+        .search(&payload.operator, &payload.reason, client_cert.as_deref(), query, payload.limit)
+        .await?;
+
+    Ok(Json(MetadataSearchResponse {
+        query: audit_to_response(audit),
+        messages: entries.into_iter().map(entry_to_response).collect(),
+    }))
+}
+
+/// Lists the most recent queries run against the metadata index, newest first.
+///
+/// # Errors
+/// Returns `AppError::Database` if the audit log cannot be read.
+pub(crate) async fn list_metadata_queries(
+    State(state): State<MgmtState>,
+    Query(query): Query<MetadataQueryListQuery>,
+) -> Result<impl IntoResponse> {
+    let queries = state.metadata_index_service.list_queries(query.limit).await?;
+    Ok(Json(MetadataQueryListResponse { queries: queries.into_iter().map(audit_to_response).collect() }))
+}
+
+fn parse_id(field: &str, value: Option<&str>) -> Result<Option<Uuid>> {
+    value.map(|v| v.parse().map_err(|_| AppError::BadRequest(format!("{field} is not a valid id")))).transpose()
+}
+
+fn parse_timestamp(field: &str, value: Option<&str>) -> Result<Option<OffsetDateTime>> {
+    value
+        .map(|v| {
+            OffsetDateTime::parse(v, &Rfc3339)
+                .map_err(|_| AppError::BadRequest(format!("{field} is not an RFC 3339 timestamp")))
+        })
+        .transpose()
+}
+
+fn format_timestamp(ts: OffsetDateTime) -> String {
+    ts.format(&Rfc3339).unwrap_or_default()
+}
+
+fn entry_to_response(entry: MessageMetadata) -> MessageMetadataEntry {
+    MessageMetadataEntry {
+        message_id: entry.message_id.to_string(),
+        sender_id: entry.sender_id.to_string(),
+        sender_device_id: entry.sender_device_id.to_string(),
+        recipient_id: entry.recipient_id.to_string(),
+        recipient_device_id: entry.recipient_device_id.to_string(),
+        sent_at: format_timestamp(entry.sent_at),
+        size_bytes: entry.size_bytes,
+    }
+}
+
+fn audit_to_response(audit: MetadataQueryAudit) -> MetadataQueryEntry {
+    MetadataQueryEntry {
+        id: audit.id.to_string(),
+        operator: audit.operator,
+        reason: audit.reason,
+        client_cert: audit.client_cert,
+        sender_id: audit.query.sender_id.map(|id| id.to_string()),
+        recipient_id: audit.query.recipient_id.map(|id| id.to_string()),
+        since: audit.query.since.map(format_timestamp),
+        until: audit.query.until.map(format_timestamp),
+        result_count: audit.result_count,
+        queried_at: format_timestamp(audit.queried_at),
+    }
+}
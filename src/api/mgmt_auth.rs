@@ -0,0 +1,130 @@
+use crate::adapters::crypto::constant_time_eq;
+use crate::config::ServerConfig;
+use crate::error::{AppError, AuthError};
+use axum::body::Body;
+use axum::extract::{MatchedPath, State};
+use axum::http::{Request, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use sha2::{Digest, Sha256};
+
+/// Inserted into request extensions by the management TLS listener when the client presented
+/// a certificate that chains to the configured client CA.
+#[derive(Clone, Debug)]
+pub struct VerifiedClientCert {
+    /// Hex SHA-256 of the client's leaf certificate, for audit logging.
+    pub fingerprint: String,
+}
+
+/// Authentication policy for the management API.
+///
+/// The bearer token is compared by digest so the check runs over fixed-length values
+/// without an early exit on the first differing byte.
+#[derive(Clone, Debug)]
+pub struct MgmtAuth {
+    token_digest: Option<Vec<u8>>,
+    require_client_cert: bool,
+}
+
+impl MgmtAuth {
+    #[must_use]
+    pub fn new(config: &ServerConfig) -> Self {
+        Self {
+            token_digest: config
+                .mgmt_auth_token
+                .as_deref()
+                .filter(|token| !token.is_empty())
+                .map(|token| Sha256::digest(token).to_vec()),
+            require_client_cert: config.mgmt_client_ca.is_some(),
+        }
+    }
+
+    #[must_use]
+    pub const fn is_enabled(&self) -> bool {
+        self.token_digest.is_some() || self.require_client_cert
+    }
+
+    fn authorize<B>(&self, req: &Request<B>) -> bool {
+        if self.require_client_cert && req.extensions().get::<VerifiedClientCert>().is_none() {
+            return false;
+        }
+
+        let Some(expected) = &self.token_digest else {
+            return true;
+        };
+        let presented = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(|token| Sha256::digest(token));
+
+        presented.is_some_and(|presented| constant_time_eq(&presented, expected))
+    }
+}
+
+/// Middleware guarding administrative management endpoints.
+pub(crate) async fn require_mgmt_auth(State(auth): State<MgmtAuth>, req: Request<Body>, next: Next) -> Response {
+    let route = req.extensions().get::<MatchedPath>().map_or("unmatched", MatchedPath::as_str);
+    if !auth.authorize(&req) {
+        tracing::warn!(http.route = route, "Rejected unauthenticated management request");
+        return AppError::from(AuthError::InvalidToken).into_response();
+    }
+
+    if let Some(cert) = req.extensions().get::<VerifiedClientCert>() {
+        tracing::info!(
+            http.route = route,
+            client.cert = %cert.fingerprint,
+            "Management request authenticated by client certificate"
+        );
+    }
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auth(token: Option<&str>, client_ca: bool) -> MgmtAuth {
+        MgmtAuth::new(&ServerConfig {
+            mgmt_auth_token: token.map(ToString::to_string),
+            mgmt_client_ca: client_ca.then(|| "ca.pem".to_string()),
+            ..ServerConfig::default()
+        })
+    }
+
+    fn request(bearer: Option<&str>, cert: bool) -> Request<()> {
+        let mut req = Request::builder();
+        if let Some(token) = bearer {
+            req = req.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        let mut req = req.body(()).expect("valid request");
+        if cert {
+            req.extensions_mut().insert(VerifiedClientCert { fingerprint: "ab".to_string() });
+        }
+        req
+    }
+
+    #[test]
+    fn test_disabled_allows_everything() {
+        let auth = auth(None, false);
+        assert!(!auth.is_enabled());
+        assert!(auth.authorize(&request(None, false)));
+    }
+
+    #[test]
+    fn test_token_must_match() {
+        let auth = auth(Some("s3cret"), false);
+        assert!(auth.authorize(&request(Some("s3cret"), false)));
+        assert!(!auth.authorize(&request(Some("s3cre"), false)));
+        assert!(!auth.authorize(&request(None, false)));
+    }
+
+    #[test]
+    fn test_client_cert_required_in_addition_to_token() {
+        let auth = auth(Some("s3cret"), true);
+        assert!(auth.authorize(&request(Some("s3cret"), true)));
+        assert!(!auth.authorize(&request(Some("s3cret"), false)));
+        assert!(!auth.authorize(&request(None, true)));
+    }
+}
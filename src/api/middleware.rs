@@ -1,11 +1,21 @@
 use crate::api::AppState;
 use crate::domain::auth::Jwt;
-use crate::error::AppError;
+use crate::domain::user::UserTier;
+use crate::error::{AppError, AuthError};
+use crate::telemetry;
+use axum::body::Body;
 use axum::http::HeaderValue;
 use axum::{
-    extract::FromRequestParts,
+    extract::{FromRequestParts, Request, State},
     http::{header, request::Parts},
+    middleware::Next,
+    response::Response,
 };
+use bytes::Bytes;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{Context, Poll};
 use tower_http::request_id::{MakeRequestId, RequestId};
 use uuid::Uuid;
 
@@ -13,32 +23,68 @@ use uuid::Uuid;
 pub struct AuthUser {
     pub(crate) user_id: Uuid,
     pub(crate) device_id: Option<Uuid>,
+    /// Expiry of the access token as a Unix timestamp.
+    pub(crate) expires_at: usize,
+    /// The user's tier when the access token was issued.
+    pub(crate) tier: UserTier,
 }
 
 impl FromRequestParts<AppState> for AuthUser {
     type Rejection = AppError;
 
-    #[tracing::instrument(err, skip(parts, state))]
+    /// Authenticates the request and spends one request of the user's tier rate limit.
+    #[tracing::instrument(err, skip_all)]
     async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
-        let auth_header = parts.headers.get(header::AUTHORIZATION).ok_or(AppError::AuthError)?;
+        let auth_header = parts.headers.get(header::AUTHORIZATION).ok_or(AuthError::MissingCredentials)?;
 
-        let auth_str = auth_header.to_str().map_err(|_| AppError::AuthError)?;
+        let auth_str = auth_header.to_str().map_err(|_| AuthError::MissingCredentials)?;
 
         if !auth_str.starts_with("Bearer ") {
-            return Err(AppError::AuthError);
+            return Err(AuthError::MissingCredentials.into());
         }
 
         let token = &auth_str[7..];
         let jwt = Jwt::new(token.to_string());
 
-        let (user_id, device_id) = state.auth_service.verify_token(&jwt).map_err(|_| AppError::AuthError)?;
+        let claims = state.auth_service.verify_token(&jwt)?;
+        let (user_id, device_id, tier) = (claims.sub, claims.device_id, claims.tier);
 
-        tracing::Span::current().record("user.id", tracing::field::display(user_id));
+        tracing::Span::current().record("user.id", tracing::field::display(telemetry::id(user_id)));
         if let Some(did) = device_id {
-            tracing::Span::current().record("device.id", tracing::field::display(did));
+            tracing::Span::current().record("device.id", tracing::field::display(telemetry::id(did)));
         }
 
-        Ok(Self { user_id, device_id })
+        state.rate_limit_service.check_user(user_id, tier)?;
+
+        Ok(Self { user_id, device_id, expires_at: claims.exp, tier })
+    }
+}
+
+/// Header carrying the hex-encoded SHA-256 of an upload body.
+pub(crate) const CONTENT_SHA256_HEADER: &str = "x-content-sha256";
+
+/// Optional client-supplied digest of an upload body, verified while the body is streamed to storage.
+#[derive(Clone, Copy, Debug)]
+pub struct ContentSha256(pub(crate) Option<[u8; 32]>);
+
+impl<S: Send + Sync> FromRequestParts<S> for ContentSha256 {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(value) = parts.headers.get(CONTENT_SHA256_HEADER) else {
+            return Ok(Self(None));
+        };
+
+        let digest = value
+            .to_str()
+            .ok()
+            .and_then(|s| hex::decode(s.trim()).ok())
+            .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+            .ok_or_else(|| {
+                AppError::BadRequest("Invalid X-Content-SHA256 header, expected 64 hex characters".into())
+            })?;
+
+        Ok(Self(Some(digest)))
     }
 }
 
@@ -55,3 +101,67 @@ impl MakeRequestId for MakeRequestUuidOrHeader {
         Some(RequestId::new(header_value))
     }
 }
+
+/// Requests the main port is handling right now, reported while shutdown drains them.
+#[derive(Clone, Debug, Default)]
+pub struct InFlightRequests {
+    active: Arc<AtomicUsize>,
+}
+
+impl InFlightRequests {
+    #[must_use]
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+}
+
+/// Takes a request off the in-flight count when its response is done or dropped.
+#[derive(Debug)]
+struct InFlightGuard(InFlightRequests);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A response body that holds its request's place in the in-flight count until it is sent.
+#[derive(Debug)]
+struct TrackedBody {
+    inner: Body,
+    _guard: InFlightGuard,
+}
+
+impl http_body::Body for TrackedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<http_body::Frame<Self::Data>, Self::Error>>> {
+        Pin::new(&mut self.inner).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Counts the request as in flight until its response body has been sent or the client went
+/// away. A WebSocket upgrade stops counting after the `101`; the gateway counts the session from
+/// there.
+pub(crate) async fn track_in_flight(
+    State(requests): State<InFlightRequests>,
+    request: Request,
+    next: Next,
+) -> Response {
+    requests.active.fetch_add(1, Ordering::Relaxed);
+    let guard = InFlightGuard(requests);
+    let (parts, body) = next.run(request).await.into_parts();
+    Response::from_parts(parts, Body::new(TrackedBody { inner: body, _guard: guard }))
+}
@@ -1,49 +1,90 @@
 use crate::Services;
-use crate::adapters::redis::RedisCache;
+use crate::adapters::database::availability::DbAvailability;
+use crate::adapters::key_value::KeyValueCache;
+use crate::adapters::submission_cache::SubmissionCache;
+use crate::api::access_log::{AccessLogger, log_access};
+use crate::api::blocklist::reject_blocked_clients;
+use crate::api::load_shed::LoadShedder;
+use crate::api::mgmt_auth::{MgmtAuth, require_mgmt_auth};
+use crate::api::middleware::InFlightRequests;
 use crate::api::rate_limit::log_rate_limit_events;
-use crate::config::Config;
+use crate::api::read_only::{ReadOnlyGuard, reject_writes_when_read_only};
+use crate::api::route_spec::{AppRoutes, GlobalLayer, GroupLayer, RateLimitTier, RouteGroup, RouteSpec};
+use crate::api::webhooks::WebhookAuth;
+use crate::config::{AccessLogOutput, Config, RouteClass};
+use crate::services::account_service::AccountService;
+use crate::services::announcement_service::AnnouncementService;
 use crate::services::attachment_service::AttachmentService;
 use crate::services::auth_service::AuthService;
 use crate::services::backup_service::BackupService;
+use crate::services::blocklist_service::BlocklistService;
 use crate::services::device_service::DeviceService;
+use crate::services::feature_flag_service::FeatureFlagService;
 use crate::services::gateway::GatewayService;
+use crate::services::gateway::routing::SessionCounter;
 use crate::services::health_service::HealthService;
+use crate::services::identifier_service::IdentifierService;
+use crate::services::instance_service::InstanceService;
 use crate::services::key_service::KeyService;
 use crate::services::message_service::MessageService;
+use crate::services::metadata_index_service::MetadataIndexService;
 use crate::services::push_token_service::PushTokenService;
 use crate::services::rate_limit_service::RateLimitService;
+use crate::services::support_service::SupportService;
+use crate::services::usage_service::UsageService;
 use axum::body::Body;
+use axum::extract::MatchedPath;
 use axum::http::{Request, StatusCode};
 use axum::{
     Router,
     middleware::from_fn_with_state,
-    routing::{delete, get, head, post, put},
+    routing::{get, post, put},
 };
 use std::sync::Arc;
 use std::time::Duration;
-use tower_governor::GovernorLayer;
-use tower_governor::governor::GovernorConfigBuilder;
 use tower_http::request_id::{PropagateRequestIdLayer, SetRequestIdLayer};
 use tower_http::timeout::TimeoutLayer;
 use tower_http::trace::TraceLayer;
 
+pub mod access_log;
+pub mod account;
+pub mod announcements;
 pub mod attachments;
 pub mod auth;
 pub mod backup;
+pub mod blocklist;
+pub mod compression;
+pub mod concurrency;
 pub mod devices;
 pub mod docs;
+pub mod feature_flags;
 pub mod gateway;
 pub mod health;
+pub mod identifiers;
+pub mod instances;
 pub mod keys;
+pub mod load_shed;
+pub mod maintenance;
 pub mod messages;
+pub mod metadata_index;
+pub mod mgmt_auth;
+pub mod mgmt_tls;
 pub mod middleware;
+pub mod protobuf;
 pub mod push_tokens;
 pub mod rate_limit;
+pub mod read_only;
+pub mod route_spec;
 pub mod schemas;
+pub mod server;
+pub mod support;
+pub mod tiers;
+pub mod webhooks;
 
 #[derive(Clone, Debug)]
 pub(crate) struct AppState {
     pub(crate) config: Config,
+    pub(crate) account_service: AccountService,
     pub(crate) key_service: KeyService,
     pub(crate) attachment_service: AttachmentService,
     pub(crate) backup_service: BackupService,
@@ -52,9 +93,17 @@ pub(crate) struct AppState {
     pub(crate) message_service: MessageService,
     pub(crate) gateway_service: GatewayService,
     pub(crate) push_token_service: PushTokenService,
+    pub(crate) identifier_service: IdentifierService,
     pub(crate) rate_limit_service: RateLimitService,
-    pub(crate) submission_cache: RedisCache,
-    pub(crate) ws_ticket_cache: RedisCache,
+    pub(crate) blocklist_service: BlocklistService,
+    pub(crate) feature_flag_service: FeatureFlagService,
+    pub(crate) usage_service: UsageService,
+    pub(crate) db_availability: DbAvailability,
+    pub(crate) submission_cache: SubmissionCache,
+    pub(crate) ws_ticket_cache: KeyValueCache,
+    pub(crate) in_flight_requests: InFlightRequests,
+    pub(crate) load_shedder: LoadShedder,
+    pub(crate) webhook_auth: WebhookAuth,
     pub(crate) shutdown_rx: tokio::sync::watch::Receiver<bool>,
 }
 
@@ -62,6 +111,7 @@ impl AppState {
     pub(crate) fn new(config: &Config, services: Services, shutdown_rx: tokio::sync::watch::Receiver<bool>) -> Self {
         Self {
             config: config.clone(),
+            account_service: services.account_service,
             key_service: services.key_service,
             attachment_service: services.attachment_service,
             backup_service: services.backup_service,
@@ -70,9 +120,17 @@ impl AppState {
             message_service: services.message_service,
             gateway_service: services.gateway_service,
             push_token_service: services.push_token_service,
+            identifier_service: services.identifier_service,
             rate_limit_service: services.rate_limit_service,
+            blocklist_service: services.blocklist_service,
+            feature_flag_service: services.feature_flag_service,
+            usage_service: services.usage_service,
+            db_availability: services.db_availability,
             submission_cache: services.submission_cache,
             ws_ticket_cache: services.ws_ticket_cache,
+            in_flight_requests: services.in_flight_requests,
+            load_shedder: services.load_shedder,
+            webhook_auth: WebhookAuth::new(&config.webhooks),
             shutdown_rx,
         }
     }
@@ -81,160 +139,229 @@ impl AppState {
 #[derive(Clone, Debug)]
 pub struct MgmtState {
     pub health_service: HealthService,
+    pub sessions: SessionCounter,
+    pub support_service: SupportService,
+    pub blocklist_service: BlocklistService,
+    pub feature_flag_service: FeatureFlagService,
+    pub announcement_service: AnnouncementService,
+    pub auth_service: AuthService,
+    pub instance_service: InstanceService,
+    pub metadata_index_service: MetadataIndexService,
 }
 
-fn auth_router(
-    config: &Config,
-    rate_limit_extractor: crate::services::rate_limit_service::IpKeyExtractor,
-) -> Router<AppState> {
-    // Auth Tier: Stricter limits for expensive/sensitive registration & login
-    let auth_interval_ns = 1_000_000_000 / config.rate_limit.auth_per_second.max(1);
-    let auth_conf = Arc::new(
-        GovernorConfigBuilder::default()
-            .per_nanosecond(u64::from(auth_interval_ns))
-            .burst_size(config.rate_limit.auth_burst)
-            .key_extractor(rate_limit_extractor)
-            .finish()
-            .expect("Failed to build auth rate limiter config"),
-    );
-
-    let standard_timeout = TimeoutLayer::with_status_code(
-        StatusCode::REQUEST_TIMEOUT,
-        Duration::from_secs(config.server.request_timeout_secs),
-    );
-
-    Router::new()
-        .route("/users", post(auth::register))
-        .route("/sessions", post(auth::login))
-        .route("/sessions", delete(auth::logout))
-        .route("/sessions/refresh", post(auth::refresh))
-        .layer(GovernorLayer::new(auth_conf))
-        .layer(standard_timeout)
-}
+/// The public API's routes and the layers around them, innermost first. The router and the
+/// route dump on the management port are both built from this.
+fn app_routes(config: &Config) -> AppRoutes {
+    let request_timeout = Duration::from_secs(config.server.request_timeout_secs);
+    let shed_enabled = config.load_shed.enabled();
 
-fn api_router(
-    config: &Config,
-    rate_limit_extractor: crate::services::rate_limit_service::IpKeyExtractor,
-) -> Router<AppState> {
-    let std_interval_ns = 1_000_000_000 / config.rate_limit.per_second.max(1);
-    let standard_conf = Arc::new(
-        GovernorConfigBuilder::default()
-            .per_nanosecond(u64::from(std_interval_ns))
-            .burst_size(config.rate_limit.burst)
-            .key_extractor(rate_limit_extractor)
-            .finish()
-            .expect("Failed to build standard rate limiter config"),
-    );
+    let auth_routes = RouteGroup::new(Some(RouteClass::Auth))
+        .route(
+            RouteSpec::new("/users")
+                .post(auth::register)
+                .concurrency_limit("register", config.concurrency.registration_limit),
+        )
+        .route(RouteSpec::new("/sessions").post(auth::login).delete(auth::logout))
+        .route(RouteSpec::new("/sessions/refresh").post(auth::refresh))
+        .compressed(&config.compression)
+        // Auth Tier: Stricter limits for expensive/sensitive registration & login
+        .layer(GroupLayer::RateLimit {
+            tier: RateLimitTier::Auth,
+            per_second: config.rate_limit.auth_per_second,
+            burst: config.rate_limit.auth_burst,
+        })
+        .layer(GroupLayer::Timeout(request_timeout));
 
-    let standard_routes = Router::new()
-        .route("/devices", post(devices::create_device))
-        .route("/devices", get(devices::list_devices))
+    let standard_routes = RouteGroup::new(Some(RouteClass::Api))
+        .route(RouteSpec::new("/devices").post(devices::create_device).get(devices::list_devices))
         .route(
-            "/devices/{deviceId}",
-            delete(devices::delete_device).get(devices::get_device).put(devices::update_device),
+            RouteSpec::new("/devices/{deviceId}")
+                .delete(devices::delete_device)
+                .get(devices::get_device)
+                .put(devices::update_device),
         )
-        .route("/devices/keys", post(keys::upload_keys))
-        .route("/users/{userId}", get(keys::get_pre_key_bundles))
-        .route("/messages", post(messages::send_messages))
-        .route("/gateway", get(gateway::websocket_handler))
-        .route("/gateway/ticket", post(gateway::generate_ticket))
-        .route("/push-tokens", put(push_tokens::register_token));
+        .route(RouteSpec::new("/devices/keys").post(keys::upload_keys))
+        .route(
+            RouteSpec::new("/keys/validate").post(keys::validate_keys).shed_under_load("key_validation", shed_enabled),
+        )
+        .route(RouteSpec::new("/users/me").delete(account::delete_account))
+        .route(RouteSpec::new("/users/me/usage").get(account::get_usage))
+        .route(
+            RouteSpec::new("/users/{userId}")
+                .get(keys::get_pre_key_bundles)
+                .concurrency_limit("bundle_fetch", config.concurrency.bundle_fetch_limit),
+        )
+        .route(RouteSpec::new("/messages").post(messages::send_messages))
+        .route(
+            RouteSpec::new("/messages/redeliver")
+                .post(messages::redeliver_messages)
+                .shed_under_load("message_redeliver", shed_enabled),
+        )
+        .route(RouteSpec::new("/gateway").get(gateway::websocket_handler))
+        .route(RouteSpec::new("/gateway/ticket").post(gateway::generate_ticket))
+        .route(RouteSpec::new("/gateway/route").get(gateway::get_route))
+        .route(RouteSpec::new("/push-tokens").put(push_tokens::register_token))
+        .route(RouteSpec::new("/identifiers").get(identifiers::list_identifiers).post(identifiers::add_identifier))
+        .route(RouteSpec::new("/identifiers/{kind}").delete(identifiers::remove_identifier))
+        .route(RouteSpec::new("/identifiers/{kind}/verify").post(identifiers::verify_identifier))
+        .compressed(&config.compression)
+        .layer(GroupLayer::Timeout(request_timeout))
+        .layer(GroupLayer::RateLimit {
+            tier: RateLimitTier::Api,
+            per_second: config.rate_limit.per_second,
+            burst: config.rate_limit.burst,
+        })
+        // Outermost, so requests cut off by the timeout count towards the p99 with their full latency.
+        .monitored_for_load(shed_enabled);
 
-    Router::new()
-        .merge(standard_routes)
-        .layer(TimeoutLayer::with_status_code(
-            StatusCode::REQUEST_TIMEOUT,
-            Duration::from_secs(config.server.request_timeout_secs),
-        ))
-        .layer(GovernorLayer::new(standard_conf))
-}
+    let attachment_routes = RouteGroup::new(Some(RouteClass::Storage))
+        .route(
+            RouteSpec::new("/attachments")
+                .post(attachments::upload_attachment)
+                .concurrency_limit("attachment_upload", config.concurrency.attachment_upload_limit),
+        )
+        .route(RouteSpec::new("/attachments/{id}").get(attachments::download_attachment))
+        .route(RouteSpec::new("/attachments/{id}/extend").post(attachments::extend_attachment))
+        .route(RouteSpec::new("/attachments/by-digest/{digest}").post(attachments::register_by_digest))
+        .layer(GroupLayer::Timeout(Duration::from_secs(config.attachment.request_timeout_secs)))
+        .compressed(&config.compression);
 
-fn storage_router(config: &Config) -> Router<AppState> {
-    let attachment_timeout = TimeoutLayer::with_status_code(
-        StatusCode::REQUEST_TIMEOUT,
-        Duration::from_secs(config.attachment.request_timeout_secs),
-    );
-    let backup_timeout = TimeoutLayer::with_status_code(
-        StatusCode::REQUEST_TIMEOUT,
-        Duration::from_secs(config.backup.request_timeout_secs),
-    );
+    let backup_routes = RouteGroup::new(Some(RouteClass::Storage))
+        .route(
+            RouteSpec::new("/backup")
+                .get(backup::download_backup)
+                .post(backup::upload_backup)
+                .head(backup::head_backup),
+        )
+        .route(RouteSpec::new("/backup/restore").post(backup::restore_backup))
+        .layer(GroupLayer::Timeout(Duration::from_secs(config.backup.request_timeout_secs)))
+        .compressed(&config.compression);
 
-    let attachment_routes = Router::new()
-        .route("/attachments", post(attachments::upload_attachment))
-        .route("/attachments/{id}", get(attachments::download_attachment))
-        .layer(attachment_timeout);
+    let mut versioned = vec![auth_routes, standard_routes, attachment_routes, backup_routes];
+    // Server-to-server, so it is neither compressed nor rate limited per client address
+    if !config.webhooks.services.is_empty() {
+        versioned.push(
+            RouteGroup::new(None)
+                .route(RouteSpec::new("/webhooks/messages").post(webhooks::enqueue_service_message))
+                .layer(GroupLayer::Timeout(request_timeout)),
+        );
+    }
 
-    let backup_routes = Router::new()
-        .route("/backup", get(backup::download_backup))
-        .route("/backup", post(backup::upload_backup))
-        .route("/backup", head(backup::head_backup))
-        .layer(backup_timeout);
+    let mut layers = vec![
+        GlobalLayer::ReadOnlyGuard,
+        GlobalLayer::RateLimitEvents,
+        GlobalLayer::Blocklist,
+        GlobalLayer::PropagateRequestId,
+        GlobalLayer::Timeout(Duration::from_secs(config.server.global_timeout_secs)),
+    ];
+    // Outside the timeout so that requests it cuts off are logged with their 408.
+    if config.telemetry.access_log != AccessLogOutput::Off {
+        layers.push(GlobalLayer::AccessLog);
+    }
+    // Outermost, so shutdown waits for requests until their last layer is done with them.
+    layers.extend([GlobalLayer::Trace, GlobalLayer::SetRequestId, GlobalLayer::InFlight]);
 
-    attachment_routes.merge(backup_routes)
+    AppRoutes {
+        layers,
+        unversioned: RouteGroup::new(None).route(RouteSpec::new("/openapi.yaml").get(docs::openapi_yaml)),
+        versioned,
+    }
 }
 
-fn apply_middleware(router: Router<AppState>, config: &Config, state: AppState) -> Router {
-    router
-        .layer(from_fn_with_state(state.clone(), log_rate_limit_events))
-        .layer(PropagateRequestIdLayer::new(axum::http::HeaderName::from_static("x-request-id")))
-        .layer(TimeoutLayer::with_status_code(
-            StatusCode::REQUEST_TIMEOUT,
-            Duration::from_secs(config.server.global_timeout_secs),
-        ))
-        .layer(
-            TraceLayer::new_for_http()
-                .make_span_with(move |request: &Request<Body>| {
-                    let request_id = request
-                        .extensions()
-                        .get::<tower_http::request_id::RequestId>()
-                        .map(|id| id.header_value().to_str().unwrap_or_default())
-                        .unwrap_or_default()
-                        .to_string();
+fn apply_middleware(
+    mut router: Router<AppState>,
+    layers: &[GlobalLayer],
+    config: &Config,
+    state: AppState,
+    instance_id: &str,
+) -> Router {
+    for layer in layers {
+        router = match *layer {
+            GlobalLayer::ReadOnlyGuard => router.layer(from_fn_with_state(
+                ReadOnlyGuard::new(state.db_availability.clone(), &config.health),
+                reject_writes_when_read_only,
+            )),
+            GlobalLayer::RateLimitEvents => router.layer(from_fn_with_state(state.clone(), log_rate_limit_events)),
+            GlobalLayer::Blocklist => router.layer(from_fn_with_state(state.clone(), reject_blocked_clients)),
+            GlobalLayer::PropagateRequestId => {
+                router.layer(PropagateRequestIdLayer::new(axum::http::HeaderName::from_static("x-request-id")))
+            }
+            GlobalLayer::Timeout(timeout) => {
+                router.layer(TimeoutLayer::with_status_code(StatusCode::REQUEST_TIMEOUT, timeout))
+            }
+            GlobalLayer::AccessLog => {
+                let logger = AccessLogger::new(
+                    state.rate_limit_service.extractor.clone(),
+                    config.telemetry.access_log_client_ip,
+                    &config.auth.jwt_secret,
+                );
+                router.layer(from_fn_with_state(logger, log_access))
+            }
+            GlobalLayer::Trace => {
+                let instance_id = instance_id.to_string();
+                router.layer(
+                    TraceLayer::new_for_http()
+                        .make_span_with(move |request: &Request<Body>| {
+                            let request_id = request
+                                .extensions()
+                                .get::<tower_http::request_id::RequestId>()
+                                .map(|id| id.header_value().to_str().unwrap_or_default())
+                                .unwrap_or_default();
+                            // The template rather than the path, so IDs in the URL stay out of traces
+                            let route =
+                                request.extensions().get::<MatchedPath>().map_or("unmatched", MatchedPath::as_str);
 
-                    tracing::info_span!(
-                        "request",
-                        "request.id" = %request_id,
-                        "http.request.method" = %request.method(),
-                        "url.path" = %request.uri().path(),
-                        "http.response.status_code" = tracing::field::Empty,
-                        "otel.kind" = "server",
-                        "user.id" = tracing::field::Empty,
-                        "device.id" = tracing::field::Empty,
-                    )
-                })
-                .on_response(|response: &axum::http::Response<_>, latency: Duration, _span: &tracing::Span| {
-                    let status = response.status();
-                    tracing::Span::current().record("http.response.status_code", status.as_u16());
+                            tracing::info_span!(
+                                "request",
+                                "request.id" = crate::telemetry::bounded(request_id),
+                                "service.instance.id" = %instance_id,
+                                "http.request.method" = %request.method(),
+                                "http.route" = route,
+                                "http.response.status_code" = tracing::field::Empty,
+                                "otel.kind" = "server",
+                                "user.id" = tracing::field::Empty,
+                                "device.id" = tracing::field::Empty,
+                            )
+                        })
+                        .on_response(|response: &axum::http::Response<_>, latency: Duration, _span: &tracing::Span| {
+                            let status = response.status();
+                            tracing::Span::current().record("http.response.status_code", status.as_u16());
 
-                    if status.is_server_error() {
-                        tracing::error!(
-                            latency_ms = %latency.as_millis(),
-                            status = %status.as_u16(),
-                            "request completed with server error"
-                        );
-                    } else if status.is_client_error() {
-                        tracing::warn!(
-                            latency_ms = %latency.as_millis(),
-                            status = %status.as_u16(),
-                            "request completed with client error"
-                        );
-                    } else {
-                        tracing::info!(
-                            latency_ms = %latency.as_millis(),
-                            status = %status.as_u16(),
-                            "request completed"
-                        );
-                    }
-                })
-                .on_failure(|error, _latency, _span: &tracing::Span| {
-                    tracing::error!(error = %error, "request failed");
-                }),
-        )
-        .layer(SetRequestIdLayer::new(
-            axum::http::HeaderName::from_static("x-request-id"),
-            middleware::MakeRequestUuidOrHeader,
-        ))
-        .with_state(state)
+                            if status.is_server_error() {
+                                tracing::error!(
+                                    latency_ms = %latency.as_millis(),
+                                    status = %status.as_u16(),
+                                    "request completed with server error"
+                                );
+                            } else if status.is_client_error() {
+                                tracing::warn!(
+                                    latency_ms = %latency.as_millis(),
+                                    status = %status.as_u16(),
+                                    "request completed with client error"
+                                );
+                            } else {
+                                tracing::info!(
+                                    latency_ms = %latency.as_millis(),
+                                    status = %status.as_u16(),
+                                    "request completed"
+                                );
+                            }
+                        })
+                        .on_failure(|error, _latency, _span: &tracing::Span| {
+                            tracing::error!(error = %error, "request failed");
+                        }),
+                )
+            }
+            GlobalLayer::SetRequestId => router.layer(SetRequestIdLayer::new(
+                axum::http::HeaderName::from_static("x-request-id"),
+                middleware::MakeRequestUuidOrHeader,
+            )),
+            GlobalLayer::InFlight => {
+                router.layer(from_fn_with_state(state.in_flight_requests.clone(), middleware::track_in_flight))
+            }
+        };
+    }
+    router.with_state(state)
 }
 
 /// Configures and returns the primary application router.
@@ -242,17 +369,54 @@ fn apply_middleware(router: Router<AppState>, config: &Config, state: AppState)
 /// # Panics
 /// Panics if the rate limiter configuration cannot be constructed.
 pub fn app_router(config: &Config, services: Services, shutdown_rx: tokio::sync::watch::Receiver<bool>) -> Router {
-    let extractor = services.rate_limit_service.extractor.clone();
+    let instance_id = services.instance_service.id().to_string();
     let state = AppState::new(config, services, shutdown_rx);
 
-    let routes = Router::new().route("/openapi.yaml", get(docs::openapi_yaml)).nest(
-        "/v1",
-        auth_router(config, extractor.clone()).merge(api_router(config, extractor)).merge(storage_router(config)),
-    );
+    let routes = app_routes(config);
+    let layers = routes.layers.clone();
+    let router = routes.into_router(&config.compression, &state.rate_limit_service, &state.load_shedder);
 
-    apply_middleware(routes, config, state)
+    apply_middleware(router, &layers, config, state, &instance_id)
 }
 
-pub fn mgmt_router(state: MgmtState) -> Router {
-    Router::new().route("/livez", get(health::livez)).route("/readyz", get(health::readyz)).with_state(state)
+/// Configures the management router. Health probes stay open for orchestrators;
+/// every other endpoint goes through `require_mgmt_auth`.
+pub fn mgmt_router(config: &Config, state: MgmtState) -> Router {
+    let route_tree: Router<MgmtState> = Router::new()
+        .route("/debug/routes", get(route_spec::inspect_routes))
+        .with_state(Arc::new(app_routes(config).describe()));
+
+    let admin_routes = Router::new()
+        .route("/sessions", get(gateway::session_stats))
+        .route("/instances", get(instances::list_instances))
+        .route("/migrations", get(health::migrations))
+        .route("/debug/users/{userId}/inbox", get(support::inspect_inbox))
+        .route("/users", get(support::list_users))
+        .route("/users/recent", get(support::list_recent_users))
+        .route("/users/{userId}/tier", put(tiers::set_user_tier))
+        .route("/inboxes/largest", get(support::list_largest_inboxes))
+        .route("/announcements", post(announcements::broadcast_announcement))
+        .route(
+            "/blocklist",
+            get(blocklist::list_blocklist).post(blocklist::block_network).delete(blocklist::unblock_network),
+        )
+        .route("/feature-flags", get(feature_flags::list_feature_flags))
+        .route("/feature-flags/{name}", put(feature_flags::set_feature_flag).delete(feature_flags::delete_feature_flag))
+        .route("/maintenance/refresh-tokens/cleanup", post(maintenance::cleanup_refresh_tokens))
+        .merge(route_tree);
+    // The metadata index and its audit log are only reachable while --metadata-index-enabled is set
+    let admin_routes = if config.metadata_index.enabled {
+        admin_routes
+            .route("/metadata-index/search", post(metadata_index::search_metadata))
+            .route("/metadata-index/queries", get(metadata_index::list_metadata_queries))
+    } else {
+        admin_routes
+    }
+    .route_layer(from_fn_with_state(MgmtAuth::new(&config.server), require_mgmt_auth));
+
+    Router::new()
+        .route("/livez", get(health::livez))
+        .route("/readyz", get(health::readyz))
+        .merge(admin_routes)
+        .with_state(state)
 }
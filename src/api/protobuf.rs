@@ -0,0 +1,122 @@
+use crate::error::AppError;
+use axum::{
+    Json,
+    body::Bytes,
+    extract::{FromRequest, FromRequestParts, Request},
+    http::{HeaderMap, StatusCode, header, request::Parts},
+    response::{IntoResponse, Response},
+};
+use prost::Message;
+use serde::{Serialize, de::DeserializeOwned};
+use std::convert::Infallible;
+
+pub(crate) const PROTOBUF_CONTENT_TYPE: &str = "application/x-protobuf";
+
+/// A JSON schema with an equivalent protobuf message, for endpoints that speak both encodings.
+///
+/// Requests convert with `TryFrom<Self::Proto>`, responses with `From<Self> for Self::Proto`.
+pub(crate) trait ProtoCodec: Sized {
+    type Proto: Message + Default;
+}
+
+fn is_protobuf(headers: &HeaderMap, name: header::HeaderName) -> bool {
+    headers.get(name).and_then(|v| v.to_str().ok()).is_some_and(|v| v.contains(PROTOBUF_CONTENT_TYPE))
+}
+
+/// Request body decoded as protobuf when sent with `Content-Type: application/x-protobuf`,
+/// and as JSON otherwise.
+#[derive(Debug)]
+pub(crate) struct Negotiated<T>(pub(crate) T);
+
+impl<T, S> FromRequest<S> for Negotiated<T>
+where
+    T: ProtoCodec + DeserializeOwned + TryFrom<T::Proto, Error = String>,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        if !is_protobuf(req.headers(), header::CONTENT_TYPE) {
+            let Json(value) = Json::<T>::from_request(req, state).await.map_err(IntoResponse::into_response)?;
+            return Ok(Self(value));
+        }
+
+        let body = Bytes::from_request(req, state).await.map_err(IntoResponse::into_response)?;
+        let message = T::Proto::decode(body)
+            .map_err(|e| AppError::BadRequest(format!("Invalid protobuf body: {e}")).into_response())?;
+        T::try_from(message).map(Self).map_err(|e| AppError::BadRequest(e).into_response())
+    }
+}
+
+/// Encoding of the response body: protobuf if the client accepts it, or sent a protobuf body
+/// without stating a preference; JSON otherwise. Errors are always JSON.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ResponseFormat {
+    Json,
+    Protobuf,
+}
+
+impl ResponseFormat {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let accepts_json =
+            headers.get(header::ACCEPT).and_then(|v| v.to_str().ok()).is_some_and(|v| v.contains("application/json"));
+
+        if is_protobuf(headers, header::ACCEPT) || (!accepts_json && is_protobuf(headers, header::CONTENT_TYPE)) {
+            Self::Protobuf
+        } else {
+            Self::Json
+        }
+    }
+
+    pub(crate) fn render<T>(self, status: StatusCode, value: T) -> Response
+    where
+        T: ProtoCodec + Serialize,
+        T::Proto: From<T>,
+    {
+        match self {
+            Self::Json => (status, Json(value)).into_response(),
+            Self::Protobuf => {
+                (status, [(header::CONTENT_TYPE, PROTOBUF_CONTENT_TYPE)], T::Proto::from(value).encode_to_vec())
+                    .into_response()
+            }
+        }
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for ResponseFormat {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self::from_headers(&parts.headers))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn headers(pairs: &[(header::HeaderName, &'static str)]) -> HeaderMap {
+        pairs.iter().map(|(name, value)| (name.clone(), HeaderValue::from_static(value))).collect()
+    }
+
+    #[test]
+    fn test_response_format_negotiation() {
+        assert_eq!(ResponseFormat::from_headers(&headers(&[])), ResponseFormat::Json);
+        assert_eq!(
+            ResponseFormat::from_headers(&headers(&[(header::ACCEPT, PROTOBUF_CONTENT_TYPE)])),
+            ResponseFormat::Protobuf
+        );
+        assert_eq!(
+            ResponseFormat::from_headers(&headers(&[(header::CONTENT_TYPE, PROTOBUF_CONTENT_TYPE)])),
+            ResponseFormat::Protobuf
+        );
+        assert_eq!(
+            ResponseFormat::from_headers(&headers(&[
+                (header::CONTENT_TYPE, PROTOBUF_CONTENT_TYPE),
+                (header::ACCEPT, "application/json")
+            ])),
+            ResponseFormat::Json
+        );
+    }
+}
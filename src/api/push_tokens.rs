@@ -1,6 +1,7 @@
 use crate::api::AppState;
 use crate::api::middleware::AuthUser;
 use crate::api::schemas::push_tokens::RegisterPushTokenRequest;
+use crate::api::schemas::validation::Validate;
 use crate::error::{AppError, Result};
 use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
 
@@ -8,7 +9,7 @@ use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
 ///
 /// # Errors
 /// Returns `AppError::Forbidden` if a device-scoped token is not provided.
-/// Returns `AppError::BadRequest` if the token format is invalid or no `device_id` in token.
+/// Returns `AppError::Validation` if the token format is invalid.
 /// Returns `AppError::Database` if the database operation fails.
 pub(crate) async fn register_token(
     auth_user: AuthUser,
@@ -17,7 +18,7 @@ pub(crate) async fn register_token(
 ) -> Result<impl IntoResponse> {
     let device_id = auth_user.device_id.ok_or(AppError::Forbidden("Device-scoped token required".to_string()))?;
 
-    payload.validate().map_err(AppError::BadRequest)?;
+    payload.validate()?;
 
     state.push_token_service.register_token(device_id, payload.token).await?;
     Ok(StatusCode::OK)
@@ -1,31 +1,131 @@
 use crate::api::AppState;
+use crate::services::rate_limit_service::IpKeyExtractor;
+use axum::Router;
 use axum::body::Body;
 use axum::extract::State;
-use axum::http::{Request, StatusCode};
-use axum::middleware::Next;
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Request, StatusCode, header};
+use axum::middleware::{Next, map_response_with_state};
 use axum::response::Response;
+use std::sync::Arc;
+use tower_governor::GovernorLayer;
+use tower_governor::governor::GovernorConfigBuilder;
 
-/// Middleware to log rate limit events and add Retry-After headers.
+const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+/// A client's request budget: `burst` requests, refilled one every `interval_ns`.
+#[derive(Clone, Copy, Debug)]
+struct Quota {
+    burst: u32,
+    interval_ns: u64,
+}
+
+impl Quota {
+    /// Whole seconds until a client with `remaining` requests left has its full burst back.
+    fn reset_secs(self, remaining: u64) -> u64 {
+        let used = u64::from(self.burst).saturating_sub(remaining);
+        used.saturating_mul(self.interval_ns).div_ceil(1_000_000_000)
+    }
+}
+
+/// Limits each client of `router` to `per_second` requests with bursts of up to `burst`.
+///
+/// Every limited response describes the client's budget with `RateLimit-Limit`,
+/// `RateLimit-Remaining` and `RateLimit-Reset` (seconds until the burst is fully refilled),
+/// and rejected requests also carry `Retry-After`.
 ///
 /// # Panics
-/// Panics if the `x-ratelimit-after` header value cannot be parsed as a string.
+/// Panics if `burst` is zero.
+pub(crate) fn limit(
+    router: Router<AppState>,
+    per_second: u32,
+    burst: u32,
+    extractor: IpKeyExtractor,
+) -> Router<AppState> {
+    let quota = Quota { burst, interval_ns: u64::from(1_000_000_000 / per_second.max(1)) };
+    let config = Arc::new(
+        GovernorConfigBuilder::default()
+            .per_nanosecond(quota.interval_ns)
+            .burst_size(burst)
+            .key_extractor(extractor)
+            .use_headers()
+            .finish()
+            .expect("Failed to build rate limiter config"),
+    );
+
+    router.layer(GovernorLayer::new(config)).layer(map_response_with_state(quota, describe_quota))
+}
+
+/// Replaces the governor's `x-ratelimit-*` headers with their standard equivalents.
+async fn describe_quota(State(quota): State<Quota>, mut response: Response) -> Response {
+    let headers = response.headers_mut();
+    let retry_after = take_secs(headers, "x-ratelimit-after");
+    let remaining = take_secs(headers, "x-ratelimit-remaining");
+    headers.remove("x-ratelimit-limit");
+
+    // Requests the governor could not key (no peer address) carry no budget to describe
+    let Some(remaining) = remaining else { return response };
+    headers.insert(RATELIMIT_LIMIT, HeaderValue::from(quota.burst));
+    headers.insert(RATELIMIT_REMAINING, HeaderValue::from(remaining));
+    headers.insert(RATELIMIT_RESET, HeaderValue::from(quota.reset_secs(remaining)));
+    if let Some(after) = retry_after {
+        // The governor rounds the wait down, so a sub-second wait would read as "retry now"
+        headers.insert(header::RETRY_AFTER, HeaderValue::from(after.max(1)));
+    }
+
+    response
+}
+
+fn take_secs(headers: &mut HeaderMap, name: &str) -> Option<u64> {
+    headers.remove(name).and_then(|v| v.to_str().ok()?.parse().ok())
+}
+
+/// Middleware to log rate limit decisions.
 pub(crate) async fn log_rate_limit_events(State(state): State<AppState>, req: Request<Body>, next: Next) -> Response {
-    let mut response = next.run(req).await;
+    let response = next.run(req).await;
 
     let status = response.status();
-    let ratelimit_after = if status == StatusCode::TOO_MANY_REQUESTS {
-        response.headers().get("x-ratelimit-after").and_then(|v| v.to_str().ok().map(ToString::to_string))
+    let retry_after = if status == StatusCode::TOO_MANY_REQUESTS {
+        response.headers().get(header::RETRY_AFTER).and_then(|v| v.to_str().ok().map(ToString::to_string))
     } else {
         None
     };
 
-    state.rate_limit_service.log_decision(status, ratelimit_after.clone());
+    state.rate_limit_service.log_decision(status, retry_after);
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if status == StatusCode::TOO_MANY_REQUESTS
-        && let Some(after) = ratelimit_after
-    {
-        response.headers_mut().insert("retry-after", after.parse().expect("Failed to parse retry-after header value"));
+    #[test]
+    fn test_reset_counts_time_to_refill_the_burst() {
+        let quota = Quota { burst: 20, interval_ns: 100_000_000 };
+        assert_eq!(quota.reset_secs(20), 0);
+        assert_eq!(quota.reset_secs(19), 1);
+        assert_eq!(quota.reset_secs(10), 1);
+        assert_eq!(quota.reset_secs(0), 2);
     }
 
-    response
+    #[tokio::test]
+    async fn test_governor_headers_are_translated() {
+        let quota = Quota { burst: 3, interval_ns: 1_000_000_000 };
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = StatusCode::TOO_MANY_REQUESTS;
+        let headers = response.headers_mut();
+        headers.insert("x-ratelimit-after", HeaderValue::from(0u64));
+        headers.insert("x-ratelimit-limit", HeaderValue::from(3u64));
+        headers.insert("x-ratelimit-remaining", HeaderValue::from(0u64));
+
+        let response = describe_quota(State(quota), response).await;
+        let headers = response.headers();
+        assert_eq!(headers.get(RATELIMIT_LIMIT).expect("limit header"), "3");
+        assert_eq!(headers.get(RATELIMIT_REMAINING).expect("remaining header"), "0");
+        assert_eq!(headers.get(RATELIMIT_RESET).expect("reset header"), "3");
+        assert_eq!(headers.get(header::RETRY_AFTER).expect("retry-after header"), "1");
+        assert!(headers.keys().all(|name| !name.as_str().starts_with("x-ratelimit")));
+    }
 }
@@ -0,0 +1,61 @@
+use crate::adapters::database::availability::DbAvailability;
+use crate::config::HealthConfig;
+use crate::error::AppError;
+use axum::body::Body;
+use axum::extract::{MatchedPath, State};
+use axum::http::{Method, Request, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use opentelemetry::{global, metrics::Counter};
+
+#[derive(Clone, Debug)]
+struct Metrics {
+    rejected_total: Counter<u64>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let meter = global::meter("obscura-server");
+        Self {
+            rejected_total: meter
+                .u64_counter("obscura_http_requests_rejected_read_only_total")
+                .with_description("Requests rejected because the instance was in read-only mode")
+                .build(),
+        }
+    }
+}
+
+/// State for [`reject_writes_when_read_only`].
+#[derive(Clone, Debug)]
+pub(crate) struct ReadOnlyGuard {
+    availability: DbAvailability,
+    retry_after_secs: u64,
+    metrics: Metrics,
+}
+
+impl ReadOnlyGuard {
+    /// Clients are told to retry once the write probe could have seen the primary recover.
+    pub(crate) fn new(availability: DbAvailability, config: &HealthConfig) -> Self {
+        let retry_after_secs =
+            config.db_write_probe_interval_secs.max(1) * u64::from(config.db_write_probe_threshold.max(1));
+        Self { availability, retry_after_secs, metrics: Metrics::new() }
+    }
+}
+
+/// Middleware rejecting requests that write while the primary database cannot take them.
+/// `GET` and `HEAD` requests pass, so clients keep fetching keys, downloading messages over
+/// the gateway and downloading attachments and backups.
+pub(crate) async fn reject_writes_when_read_only(
+    State(guard): State<ReadOnlyGuard>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    if !guard.availability.is_read_only() || matches!(*req.method(), Method::GET | Method::HEAD) {
+        return next.run(req).await;
+    }
+
+    let route = req.extensions().get::<MatchedPath>().map_or("unmatched", MatchedPath::as_str);
+    tracing::debug!(method = %req.method(), http.route = route, "Rejected write in read-only mode");
+    guard.metrics.rejected_total.add(1, &[]);
+    ([(header::RETRY_AFTER, guard.retry_after_secs.to_string())], AppError::ServiceUnavailable).into_response()
+}
@@ -0,0 +1,384 @@
+use crate::api::load_shed::{self, LoadShedder};
+use crate::api::schemas::routes::{LayerEntry, RouteEntry, RouteTreeResponse};
+use crate::api::{AppState, compression, concurrency, rate_limit};
+use crate::config::{CompressionConfig, RouteClass};
+use crate::services::rate_limit_service::RateLimitService;
+use axum::extract::State;
+use axum::handler::Handler;
+use axum::http::StatusCode;
+use axum::middleware::from_fn_with_state;
+use axum::response::IntoResponse;
+use axum::routing::MethodRouter;
+use axum::{Json, Router};
+use std::sync::Arc;
+use std::time::Duration;
+use tower_http::timeout::TimeoutLayer;
+
+/// Prefix of every versioned API route.
+pub(crate) const API_PREFIX: &str = "/v1";
+
+/// Middleware around every route. Applied by `apply_middleware`, which holds the app state
+/// most of them need.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum GlobalLayer {
+    ReadOnlyGuard,
+    RateLimitEvents,
+    Blocklist,
+    PropagateRequestId,
+    Timeout(Duration),
+    AccessLog,
+    Trace,
+    SetRequestId,
+    InFlight,
+}
+
+impl GlobalLayer {
+    fn describe(self) -> LayerEntry {
+        let name = match self {
+            Self::ReadOnlyGuard => "read_only_guard",
+            Self::RateLimitEvents => "rate_limit_events",
+            Self::Blocklist => "blocklist",
+            Self::PropagateRequestId => "propagate_request_id",
+            Self::Timeout(timeout) => return LayerEntry { timeout_secs: Some(timeout.as_secs()), ..layer("timeout") },
+            Self::AccessLog => "access_log",
+            Self::Trace => "trace",
+            Self::SetRequestId => "set_request_id",
+            Self::InFlight => "in_flight",
+        };
+        layer(name)
+    }
+}
+
+/// How a rate limit keys its clients.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum RateLimitTier {
+    /// Registration and login, with IPv6 clients aggregated by the wider auth prefix.
+    Auth,
+    Api,
+}
+
+impl RateLimitTier {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Auth => "auth",
+            Self::Api => "api",
+        }
+    }
+}
+
+/// Middleware around one group of routes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum GroupLayer {
+    Compression(RouteClass),
+    Timeout(Duration),
+    RateLimit {
+        tier: RateLimitTier,
+        per_second: u32,
+        burst: u32,
+    },
+    /// Times the group's requests for load shedding, see [`load_shed::monitor_latency`].
+    LoadMonitor,
+}
+
+impl GroupLayer {
+    fn describe(self) -> LayerEntry {
+        match self {
+            Self::Compression(_) => layer("compression"),
+            Self::Timeout(timeout) => LayerEntry { timeout_secs: Some(timeout.as_secs()), ..layer("timeout") },
+            Self::RateLimit { tier, per_second, burst } => LayerEntry {
+                tier: Some(tier.as_str().to_string()),
+                per_second: Some(per_second),
+                burst: Some(burst),
+                ..layer("rate_limit")
+            },
+            Self::LoadMonitor => layer("load_monitor"),
+        }
+    }
+}
+
+fn layer(name: &str) -> LayerEntry {
+    LayerEntry {
+        name: name.to_string(),
+        timeout_secs: None,
+        tier: None,
+        per_second: None,
+        burst: None,
+        max_in_flight: None,
+    }
+}
+
+/// One path and its handlers, recording the methods it answers as they are added.
+#[derive(Debug)]
+pub(crate) struct RouteSpec {
+    path: &'static str,
+    methods: Vec<&'static str>,
+    handler: MethodRouter<AppState>,
+    concurrency_limit: Option<(&'static str, usize)>,
+    shed_under_load: Option<&'static str>,
+}
+
+impl RouteSpec {
+    pub(crate) fn new(path: &'static str) -> Self {
+        Self { path, methods: Vec::new(), handler: MethodRouter::new(), concurrency_limit: None, shed_under_load: None }
+    }
+
+    pub(crate) fn get<H: Handler<T, AppState>, T: 'static>(mut self, handler: H) -> Self {
+        self.methods.push("GET");
+        self.handler = self.handler.get(handler);
+        self
+    }
+
+    pub(crate) fn post<H: Handler<T, AppState>, T: 'static>(mut self, handler: H) -> Self {
+        self.methods.push("POST");
+        self.handler = self.handler.post(handler);
+        self
+    }
+
+    pub(crate) fn put<H: Handler<T, AppState>, T: 'static>(mut self, handler: H) -> Self {
+        self.methods.push("PUT");
+        self.handler = self.handler.put(handler);
+        self
+    }
+
+    pub(crate) fn delete<H: Handler<T, AppState>, T: 'static>(mut self, handler: H) -> Self {
+        self.methods.push("DELETE");
+        self.handler = self.handler.delete(handler);
+        self
+    }
+
+    pub(crate) fn head<H: Handler<T, AppState>, T: 'static>(mut self, handler: H) -> Self {
+        self.methods.push("HEAD");
+        self.handler = self.handler.head(handler);
+        self
+    }
+
+    /// Caps the route at `max_in_flight` concurrent requests, see [`concurrency::limit`].
+    /// A limit of zero leaves it unlimited.
+    pub(crate) fn concurrency_limit(mut self, name: &'static str, max_in_flight: usize) -> Self {
+        self.concurrency_limit = (max_in_flight > 0).then_some((name, max_in_flight));
+        self
+    }
+
+    /// Marks the route as low priority, shed first when the instance is overloaded, see
+    /// [`load_shed::shed_under_load`]. Left alone when `enabled` is false.
+    pub(crate) fn shed_under_load(mut self, name: &'static str, enabled: bool) -> Self {
+        self.shed_under_load = enabled.then_some(name);
+        self
+    }
+}
+
+/// Routes that share one set of layers.
+#[derive(Debug)]
+pub(crate) struct RouteGroup {
+    class: Option<RouteClass>,
+    routes: Vec<RouteSpec>,
+    /// Innermost first, the order they are applied in.
+    layers: Vec<GroupLayer>,
+}
+
+impl RouteGroup {
+    pub(crate) const fn new(class: Option<RouteClass>) -> Self {
+        Self { class, routes: Vec::new(), layers: Vec::new() }
+    }
+
+    pub(crate) fn route(mut self, route: RouteSpec) -> Self {
+        self.routes.push(route);
+        self
+    }
+
+    pub(crate) fn layer(mut self, layer: GroupLayer) -> Self {
+        self.layers.push(layer);
+        self
+    }
+
+    /// Adds [`GroupLayer::LoadMonitor`] when load shedding is enabled, so requests are only timed
+    /// when something reads the measurement.
+    pub(crate) fn monitored_for_load(self, enabled: bool) -> Self {
+        if enabled { self.layer(GroupLayer::LoadMonitor) } else { self }
+    }
+
+    /// Adds response compression unless it is disabled for the group's class.
+    pub(crate) fn compressed(self, config: &CompressionConfig) -> Self {
+        match self.class {
+            Some(class) if !config.disabled_routes.contains(&class) => self.layer(GroupLayer::Compression(class)),
+            _ => self,
+        }
+    }
+
+    fn into_router(
+        self,
+        compression: &CompressionConfig,
+        rate_limits: &RateLimitService,
+        load_shedder: &LoadShedder,
+    ) -> Router<AppState> {
+        let mut router = Router::new();
+        for route in self.routes {
+            let handler = match route.concurrency_limit {
+                Some((name, max_in_flight)) => concurrency::limit(route.handler, name, max_in_flight),
+                None => route.handler,
+            };
+            // Outside the concurrency limit, so a shed request never takes a slot.
+            let handler = match route.shed_under_load {
+                Some(name) => load_shed::shed_under_load(handler, name, load_shedder.clone()),
+                None => handler,
+            };
+            router = router.route(route.path, handler);
+        }
+
+        for layer in self.layers {
+            router = match layer {
+                GroupLayer::Compression(class) => compression::compress(router, compression, class),
+                GroupLayer::Timeout(timeout) => {
+                    router.layer(TimeoutLayer::with_status_code(StatusCode::REQUEST_TIMEOUT, timeout))
+                }
+                GroupLayer::RateLimit { tier, per_second, burst } => {
+                    let extractor = match tier {
+                        RateLimitTier::Auth => rate_limits.auth_extractor.clone(),
+                        RateLimitTier::Api => rate_limits.extractor.clone(),
+                    };
+                    rate_limit::limit(router, per_second, burst, extractor)
+                }
+                GroupLayer::LoadMonitor => {
+                    router.layer(from_fn_with_state(load_shedder.clone(), load_shed::monitor_latency))
+                }
+            };
+        }
+        router
+    }
+}
+
+/// Every route of the public API and the layers around it.
+///
+/// Both the router and `GET /debug/routes` on the management port are built from this, so the
+/// dump always shows the layer order that is actually served.
+#[derive(Debug)]
+pub(crate) struct AppRoutes {
+    /// Around every route, innermost first.
+    pub(crate) layers: Vec<GlobalLayer>,
+    pub(crate) unversioned: RouteGroup,
+    /// Served under [`API_PREFIX`].
+    pub(crate) versioned: Vec<RouteGroup>,
+}
+
+impl AppRoutes {
+    /// Builds the routes with their group and route layers. The global layers are left to
+    /// the caller.
+    pub(crate) fn into_router(
+        self,
+        compression: &CompressionConfig,
+        rate_limits: &RateLimitService,
+        load_shedder: &LoadShedder,
+    ) -> Router<AppState> {
+        let versioned = self.versioned.into_iter().fold(Router::new(), |router, group| {
+            router.merge(group.into_router(compression, rate_limits, load_shedder))
+        });
+        self.unversioned.into_router(compression, rate_limits, load_shedder).nest(API_PREFIX, versioned)
+    }
+
+    pub(crate) fn describe(&self) -> RouteTreeResponse {
+        let global: Vec<LayerEntry> = self.layers.iter().rev().map(|layer| layer.describe()).collect();
+        let groups = std::iter::once(("", &self.unversioned)).chain(self.versioned.iter().map(|g| (API_PREFIX, g)));
+
+        let mut routes = Vec::new();
+        for (prefix, group) in groups {
+            for route in &group.routes {
+                let mut layers = global.clone();
+                layers.extend(group.layers.iter().rev().map(|layer| layer.describe()));
+                if route.shed_under_load.is_some() {
+                    layers.push(layer("load_shed"));
+                }
+                if let Some((_, max_in_flight)) = route.concurrency_limit {
+                    layers.push(LayerEntry { max_in_flight: Some(max_in_flight), ..layer("concurrency_limit") });
+                }
+                routes.push(RouteEntry {
+                    path: format!("{prefix}{}", route.path),
+                    methods: route.methods.iter().map(ToString::to_string).collect(),
+                    class: group.class.map(|class| class.as_str().to_string()),
+                    layers,
+                });
+            }
+        }
+        RouteTreeResponse { routes }
+    }
+}
+
+/// Dumps every public route with the layers a request passes through on its way in, for
+/// auditing middleware order.
+pub(crate) async fn inspect_routes(State(routes): State<Arc<RouteTreeResponse>>) -> impl IntoResponse {
+    Json(RouteTreeResponse::clone(&routes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn ok() -> StatusCode {
+        StatusCode::OK
+    }
+
+    #[test]
+    fn test_layers_are_described_outermost_first() {
+        let routes = AppRoutes {
+            layers: vec![GlobalLayer::Timeout(Duration::from_secs(60)), GlobalLayer::SetRequestId],
+            unversioned: RouteGroup::new(None).route(RouteSpec::new("/openapi.yaml").get(ok)),
+            versioned: vec![
+                RouteGroup::new(Some(RouteClass::Api))
+                    .route(RouteSpec::new("/things").get(ok).post(ok).concurrency_limit("things", 4))
+                    .layer(GroupLayer::Timeout(Duration::from_secs(5)))
+                    .layer(GroupLayer::RateLimit { tier: RateLimitTier::Api, per_second: 10, burst: 20 }),
+            ],
+        };
+
+        let tree = routes.describe();
+        assert_eq!(tree.routes.len(), 2);
+        assert_eq!(tree.routes[0].path, "/openapi.yaml");
+        assert_eq!(tree.routes[0].class, None);
+
+        let things = &tree.routes[1];
+        assert_eq!(things.path, "/v1/things");
+        assert_eq!(things.methods, ["GET", "POST"]);
+        assert_eq!(things.class.as_deref(), Some("api"));
+        let names: Vec<&str> = things.layers.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, ["set_request_id", "timeout", "rate_limit", "timeout", "concurrency_limit"]);
+        assert_eq!(things.layers[1].timeout_secs, Some(60));
+        assert_eq!(things.layers[2].burst, Some(20));
+        assert_eq!(things.layers[3].timeout_secs, Some(5));
+        assert_eq!(things.layers[4].max_in_flight, Some(4));
+    }
+
+    #[test]
+    fn test_load_shedding_layers_are_listed() {
+        let routes = AppRoutes {
+            layers: Vec::new(),
+            unversioned: RouteGroup::new(None),
+            versioned: vec![
+                RouteGroup::new(Some(RouteClass::Api))
+                    .route(
+                        RouteSpec::new("/chores")
+                            .post(ok)
+                            .shed_under_load("chores", true)
+                            .concurrency_limit("chores", 2),
+                    )
+                    .layer(GroupLayer::RateLimit { tier: RateLimitTier::Api, per_second: 10, burst: 20 })
+                    .monitored_for_load(true),
+            ],
+        };
+
+        let tree = routes.describe();
+        let names: Vec<&str> = tree.routes[0].layers.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, ["load_monitor", "rate_limit", "load_shed", "concurrency_limit"]);
+    }
+
+    #[test]
+    fn test_disabled_limits_are_not_listed() {
+        let compression = CompressionConfig { disabled_routes: vec![RouteClass::Storage], min_size_bytes: 1024 };
+        let group = RouteGroup::new(Some(RouteClass::Storage))
+            .route(RouteSpec::new("/backup").get(ok).concurrency_limit("backup", 0).shed_under_load("backup", false))
+            .compressed(&compression)
+            .monitored_for_load(false);
+
+        assert!(group.layers.is_empty());
+        assert!(group.routes[0].concurrency_limit.is_none());
+        assert!(group.routes[0].shed_under_load.is_none());
+    }
+}
@@ -0,0 +1,25 @@
+use crate::domain::usage::StorageUsage;
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageResponse {
+    pub attachment_bytes: i64,
+    pub backup_bytes: i64,
+    pub pending_messages: i64,
+    pub one_time_pre_keys: i64,
+    /// UNIX timestamp of when the figures were computed; they may be cached briefly.
+    pub computed_at: i64,
+}
+
+impl From<StorageUsage> for UsageResponse {
+    fn from(usage: StorageUsage) -> Self {
+        Self {
+            attachment_bytes: usage.attachment_bytes,
+            backup_bytes: usage.backup_bytes,
+            pending_messages: usage.pending_messages,
+            one_time_pre_keys: usage.one_time_pre_keys,
+            computed_at: usage.computed_at,
+        }
+    }
+}
@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BroadcastAnnouncementRequest {
+    pub kind: String,
+    pub body: String,
+    pub ttl_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnnouncementResponse {
+    pub id: String,
+    pub kind: String,
+    pub body: String,
+    pub created_at: String,
+    pub expires_at: String,
+    /// Base64 Ed25519 signature over the encoded `Announcement` message.
+    pub signature: String,
+    /// Base64 Ed25519 public key clients verify the signature with.
+    pub public_key: String,
+}
@@ -1,4 +1,4 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 #[derive(Debug, Serialize)]
@@ -6,4 +6,21 @@ use uuid::Uuid;
 pub struct AttachmentResponse {
     pub id: Uuid,
     pub expires_at: i64,
+    /// Hex-encoded SHA-256 the content is stored under, for `POST /v1/attachments/by-digest/{digest}`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_key: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtendAttachmentRequest {
+    /// Seconds from now the attachment should stay available; the server default when absent.
+    pub ttl_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtendAttachmentResponse {
+    pub id: Uuid,
+    pub expires_at: i64,
 }
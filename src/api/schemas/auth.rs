@@ -1,9 +1,9 @@
-use regex::Regex;
+use crate::api::protobuf::ProtoCodec;
+use crate::api::schemas::validation::{MAX_PASSWORD_LEN, MAX_USERNAME_LEN, Validate, ValidationErrors};
+use crate::domain::username;
+use crate::proto::obscura::v1 as proto;
 use serde::{Deserialize, Serialize};
-use std::sync::LazyLock;
-
-static USERNAME_REGEX: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"^[a-zA-Z0-9_]{3,50}$").expect("Hardcoded username validation regex should compile"));
+use uuid::Uuid;
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -12,28 +12,12 @@ pub struct RegistrationRequest {
     pub password: String,
 }
 
-impl RegistrationRequest {
-    /// Validates the registration payload.
-    ///
-    /// # Errors
-    /// Returns an error if the password is too short or username is invalid.
-    pub fn validate(&self) -> Result<(), String> {
-        if self.username.trim().is_empty() {
-            return Err("Username cannot be empty".into());
-        }
-
-        if !USERNAME_REGEX.is_match(&self.username) {
-            return Err(
-                "Username must be between 3 and 50 characters and can only contain letters, numbers, and underscores"
-                    .into(),
-            );
-        }
-
-        if self.password.len() < 12 {
-            return Err("Password must be at least 12 characters long".into());
-        }
-
-        Ok(())
+impl Validate for RegistrationRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        errors.check_username("username", &username::normalize(&self.username));
+        errors.check_password("password", &self.password);
+        errors.into_result()
     }
 }
 
@@ -56,7 +40,10 @@ mod tests {
         let reg = mock_registration("short");
         let res = reg.validate();
         assert!(res.is_err());
-        assert_eq!(res.expect_err("Password too short should fail"), "Password must be at least 12 characters long");
+        assert_eq!(
+            res.expect_err("Password too short should fail").to_string(),
+            "Password must be at least 12 characters long"
+        );
     }
 
     #[test]
@@ -65,7 +52,7 @@ mod tests {
         reg.username = String::new();
         let res = reg.validate();
         assert!(res.is_err());
-        assert_eq!(res.expect_err("Empty username should fail"), "Username cannot be empty");
+        assert_eq!(res.expect_err("Empty username should fail").to_string(), "Username cannot be empty");
     }
 
     #[test]
@@ -74,7 +61,7 @@ mod tests {
         reg.username = "   ".into();
         let res = reg.validate();
         assert!(res.is_err());
-        assert_eq!(res.expect_err("Whitespace username should fail"), "Username cannot be empty");
+        assert_eq!(res.expect_err("Whitespace username should fail").to_string(), "Username cannot be empty");
     }
 
     #[test]
@@ -84,10 +71,28 @@ mod tests {
         let res = reg.validate();
         assert!(res.is_err());
         assert_eq!(
-            res.expect_err("Username too long should fail"),
+            res.expect_err("Username too long should fail").to_string(),
             "Username must be between 3 and 50 characters and can only contain letters, numbers, and underscores"
         );
     }
+
+    #[test]
+    fn test_registration_validation_reports_each_field() {
+        let reg = RegistrationRequest { username: "a b".into(), password: "short".into() };
+        let errors = reg.validate().expect_err("Both fields are invalid");
+        let fields: Vec<&str> = errors.fields().iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, ["username", "password"]);
+    }
+
+    #[test]
+    fn test_login_validation_device_id() {
+        let mut login = LoginRequest { username: "testuser".into(), password: "password12345".into(), device_id: None };
+        assert!(login.validate().is_ok());
+
+        login.device_id = Some("not-a-uuid".into());
+        let errors = login.validate().expect_err("Malformed device ID should fail");
+        assert_eq!(errors.fields()[0].field, "deviceId");
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -110,6 +115,41 @@ pub struct LogoutRequest {
     pub refresh_token: String,
 }
 
+impl Validate for LoginRequest {
+    /// Only caps the credentials, since accounts may predate the current username and password
+    /// rules; wrong credentials are still rejected by authentication.
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        if self.username.is_empty() {
+            errors.add("username", "Username cannot be empty");
+        }
+        errors.check_max_len("username", &self.username, MAX_USERNAME_LEN);
+        errors.check_max_len("password", &self.password, MAX_PASSWORD_LEN);
+        if let Some(device_id) = &self.device_id
+            && Uuid::parse_str(device_id).is_err()
+        {
+            errors.add("deviceId", "Invalid device_id");
+        }
+        errors.into_result()
+    }
+}
+
+impl Validate for RefreshRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        errors.check_opaque_token("refreshToken", &self.refresh_token);
+        errors.into_result()
+    }
+}
+
+impl Validate for LogoutRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        errors.check_opaque_token("refreshToken", &self.refresh_token);
+        errors.into_result()
+    }
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AuthResponse {
@@ -119,3 +159,72 @@ pub struct AuthResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub device_id: Option<String>,
 }
+
+impl ProtoCodec for RegistrationRequest {
+    type Proto = proto::RegisterRequest;
+}
+
+impl TryFrom<proto::RegisterRequest> for RegistrationRequest {
+    type Error = String;
+    fn try_from(proto: proto::RegisterRequest) -> Result<Self, Self::Error> {
+        Ok(Self { username: proto.username, password: proto.password })
+    }
+}
+
+impl ProtoCodec for LoginRequest {
+    type Proto = proto::LoginRequest;
+}
+
+impl TryFrom<proto::LoginRequest> for LoginRequest {
+    type Error = String;
+    fn try_from(proto: proto::LoginRequest) -> Result<Self, Self::Error> {
+        // An empty device ID logs in without a device, like an absent `deviceId` in JSON.
+        let device_id = if proto.device_id.is_empty() {
+            None
+        } else {
+            Some(Uuid::from_slice(&proto.device_id).map_err(|_| "Invalid device_id".to_string())?.to_string())
+        };
+        Ok(Self { username: proto.username, password: proto.password, device_id })
+    }
+}
+
+impl ProtoCodec for RefreshRequest {
+    type Proto = proto::RefreshSessionRequest;
+}
+
+impl TryFrom<proto::RefreshSessionRequest> for RefreshRequest {
+    type Error = String;
+    fn try_from(proto: proto::RefreshSessionRequest) -> Result<Self, Self::Error> {
+        Ok(Self { refresh_token: proto.refresh_token })
+    }
+}
+
+impl ProtoCodec for LogoutRequest {
+    type Proto = proto::LogoutRequest;
+}
+
+impl TryFrom<proto::LogoutRequest> for LogoutRequest {
+    type Error = String;
+    fn try_from(proto: proto::LogoutRequest) -> Result<Self, Self::Error> {
+        Ok(Self { refresh_token: proto.refresh_token })
+    }
+}
+
+impl ProtoCodec for AuthResponse {
+    type Proto = proto::AuthResponse;
+}
+
+impl From<AuthResponse> for proto::AuthResponse {
+    fn from(response: AuthResponse) -> Self {
+        Self {
+            token: response.token,
+            refresh_token: response.refresh_token,
+            expires_at: response.expires_at,
+            device_id: response
+                .device_id
+                .and_then(|id| Uuid::parse_str(&id).ok())
+                .map(|id| id.as_bytes().to_vec())
+                .unwrap_or_default(),
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use crate::api::schemas::crypto::PublicKey;
-use crate::api::schemas::keys::{OneTimePreKey, SignedPreKey};
+use crate::api::schemas::keys::{OneTimePreKey, SignedPreKey, check_pre_keys};
+use crate::api::schemas::validation::{MAX_DEVICE_NAME_LEN, PUBLIC_KEY_BYTES, Validate, ValidationErrors};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Deserialize)]
@@ -12,19 +13,15 @@ pub struct CreateDeviceRequest {
     pub one_time_pre_keys: Vec<OneTimePreKey>,
 }
 
-impl CreateDeviceRequest {
-    /// Validates the device creation payload.
-    ///
-    /// # Errors
-    /// Returns an error if there are duplicate pre-key IDs.
-    pub fn validate(&self) -> Result<(), String> {
-        let mut unique_ids = std::collections::HashSet::with_capacity(self.one_time_pre_keys.len());
-        for pk in &self.one_time_pre_keys {
-            if !unique_ids.insert(pk.key_id) {
-                return Err(format!("Duplicate prekey ID: {}", pk.key_id));
-            }
+impl Validate for CreateDeviceRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        if let Some(name) = &self.name {
+            errors.check_max_len("name", name, MAX_DEVICE_NAME_LEN);
         }
-        Ok(())
+        errors.check_base64_len("identityKey", &self.identity_key.0, PUBLIC_KEY_BYTES);
+        check_pre_keys(&mut errors, &self.signed_pre_key, &self.one_time_pre_keys);
+        errors.into_result()
     }
 }
 
@@ -48,6 +45,16 @@ pub struct UpdateDeviceRequest {
     pub name: Option<String>,
 }
 
+impl Validate for UpdateDeviceRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        if let Some(name) = &self.name {
+            errors.check_max_len("name", name, MAX_DEVICE_NAME_LEN);
+        }
+        errors.into_result()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -81,7 +88,7 @@ mod tests {
         let req = mock_device_request(vec![1, 2, 1]);
         let result = req.validate();
         assert!(result.is_err());
-        assert!(result.expect_err("should fail for duplicate IDs").contains("Duplicate prekey ID: 1"));
+        assert!(result.expect_err("should fail for duplicate IDs").to_string().contains("Duplicate prekey ID: 1"));
     }
 
     #[test]
@@ -89,4 +96,14 @@ mod tests {
         let req = mock_device_request(vec![]);
         assert!(req.validate().is_ok());
     }
+
+    #[test]
+    fn test_validate_field_paths() {
+        let mut req = mock_device_request(vec![1, 2]);
+        req.name = Some("n".repeat(MAX_DEVICE_NAME_LEN + 1));
+        req.one_time_pre_keys[1].public_key = PublicKey("D".repeat(400));
+        let errors = req.validate().expect_err("name and key are invalid");
+        let fields: Vec<&str> = errors.fields().iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, ["name", "oneTimePreKeys[1].publicKey"]);
+    }
 }
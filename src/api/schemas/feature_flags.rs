@@ -0,0 +1,26 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeatureFlagsResponse {
+    pub flags: Vec<FeatureFlagEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeatureFlagEntry {
+    pub name: String,
+    pub enabled: bool,
+    pub rollout_percent: u8,
+    pub description: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetFeatureFlagRequest {
+    pub enabled: bool,
+    /// Defaults to every subject.
+    pub rollout_percent: Option<u8>,
+    pub description: Option<String>,
+}
@@ -3,9 +3,34 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Deserialize)]
 pub struct WsParams {
     pub ticket: String,
+    /// Opts the session into credit-based flow control: envelopes are only delivered
+    /// up to the credit granted by the client's `Credit` frames.
+    #[serde(default)]
+    pub credit: bool,
+    /// Resume token of a session its previous instance closed on shutdown, whose delivery
+    /// state this session picks up.
+    pub resume: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct TicketResponse {
     pub ticket: String,
+    /// Token under which the session opened with this ticket leaves its delivery state when
+    /// the server shuts down. Absent when resumption is disabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resume_token: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RouteResponse {
+    pub routing_key: String,
+    pub signature: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionStatsResponse {
+    pub active_sessions: usize,
 }
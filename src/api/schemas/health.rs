@@ -5,6 +5,50 @@ use serde::{Deserialize, Serialize};
 pub struct HealthResponse {
     pub status: String,
     pub database: String,
+    /// `read_write`, or `read_only` while the primary cannot take writes. Does not affect `status`.
+    pub database_mode: String,
     pub storage: String,
-    pub pubsub: String,
+    /// Redis connectivity, when Redis is used.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pubsub: Option<String>,
+    /// Push provider credential check, when enabled. Does not affect `status`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub push: Option<String>,
+    pub database_pool: DatabasePoolResponse,
+}
+
+/// Database pool occupancy and acquire outcomes since startup. Informational only.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatabasePoolResponse {
+    pub size: u32,
+    pub idle: u32,
+    pub max_connections: u32,
+    pub acquires: u64,
+    pub mean_acquire_wait_ms: f64,
+    pub acquire_timeouts: u64,
+}
+
+/// How the database schema compares to the migrations this instance was built with.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationStatusResponse {
+    /// True when nothing is pending, dirty or modified.
+    pub up_to_date: bool,
+    pub current_version: Option<i64>,
+    pub latest_version: Option<i64>,
+    pub pending: Vec<PendingMigration>,
+    /// Migrations that failed partway and must be repaired by hand.
+    pub dirty: Vec<i64>,
+    /// Applied migrations whose file has changed since.
+    pub modified: Vec<i64>,
+    /// Applied migrations this build does not include, usually from a newer release.
+    pub unknown: Vec<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingMigration {
+    pub version: i64,
+    pub description: String,
 }
@@ -0,0 +1,88 @@
+use crate::api::schemas::validation::{Validate, ValidationErrors};
+use crate::domain::identifier::{IdentifierKind, MAX_EMAIL_LEN};
+use serde::{Deserialize, Serialize};
+
+/// Longest code accepted for checking; real codes are six digits.
+const MAX_CODE_LEN: usize = 16;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddIdentifierRequest {
+    pub kind: IdentifierKind,
+    pub value: String,
+    /// Whether other users may find this account by the identifier once it is verified.
+    #[serde(default)]
+    pub discoverable: bool,
+}
+
+impl Validate for AddIdentifierRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        errors.check_max_len("value", &self.value, MAX_EMAIL_LEN);
+        if errors.fields().is_empty()
+            && let Err(message) = self.kind.normalize(&self.value)
+        {
+            errors.add("value", message);
+        }
+        errors.into_result()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyIdentifierRequest {
+    pub code: String,
+}
+
+impl Validate for VerifyIdentifierRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        if self.code.trim().is_empty() {
+            errors.add("code", "Code cannot be empty");
+        } else {
+            errors.check_max_len("code", &self.code, MAX_CODE_LEN);
+        }
+        errors.into_result()
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IdentifierResponse {
+    pub kind: IdentifierKind,
+    pub verified: bool,
+    pub discoverable: bool,
+    pub verified_at: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IdentifierListResponse {
+    pub identifiers: Vec<IdentifierResponse>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_request_checks_value_against_kind() {
+        let req = AddIdentifierRequest {
+            kind: IdentifierKind::Phone,
+            value: "alice@example.org".to_string(),
+            discoverable: false,
+        };
+        let errors = req.validate().expect_err("an email is not a phone number");
+        assert_eq!(errors.fields()[0].field, "value");
+
+        let req = AddIdentifierRequest { kind: IdentifierKind::Email, ..req };
+        assert!(req.validate().is_ok());
+    }
+
+    #[test]
+    fn test_verify_request_rejects_empty_code() {
+        assert!(VerifyIdentifierRequest { code: " ".to_string() }.validate().is_err());
+        assert!(VerifyIdentifierRequest { code: "123456".to_string() }.validate().is_ok());
+    }
+}
@@ -0,0 +1,19 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstancesResponse {
+    /// The instance that answered.
+    pub instance_id: String,
+    pub instances: Vec<InstanceEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstanceEntry {
+    pub id: String,
+    pub version: String,
+    pub started_at: Option<String>,
+    pub active_sessions: usize,
+    pub last_heartbeat_at: Option<String>,
+}
@@ -1,7 +1,13 @@
+use crate::api::protobuf::ProtoCodec;
 use crate::api::schemas::crypto::{PublicKey, Signature};
+use crate::api::schemas::validation::{PUBLIC_KEY_BYTES, SIGNATURE_BYTES, Validate, ValidationErrors};
 use crate::domain::crypto;
 use crate::domain::keys;
+use crate::proto::obscura::v1 as proto;
+use base64::{Engine as _, engine::general_purpose::STANDARD};
 use serde::{Deserialize, Serialize};
+use time::format_description::well_known::Rfc3339;
+use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -57,24 +63,35 @@ pub struct PreKeyUploadRequest {
     pub one_time_pre_keys: Vec<OneTimePreKey>,
 }
 
-impl PreKeyUploadRequest {
-    /// Validates the pre-key upload payload.
-    ///
-    /// # Errors
-    /// Returns an error if the registration ID is missing during a takeover or if there are duplicate pre-key IDs.
-    pub fn validate(&self) -> Result<(), String> {
-        if self.identity_key.is_some() && self.registration_id.is_none() {
-            return Err("registrationId is required when identityKey is provided".into());
-        }
-
-        let mut unique_ids = std::collections::HashSet::with_capacity(self.one_time_pre_keys.len());
-        for pk in &self.one_time_pre_keys {
-            if !unique_ids.insert(pk.key_id) {
-                return Err(format!("Duplicate prekey ID: {}", pk.key_id));
+impl Validate for PreKeyUploadRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        if let Some(identity_key) = &self.identity_key {
+            errors.check_base64_len("identityKey", &identity_key.0, PUBLIC_KEY_BYTES);
+            if self.registration_id.is_none() {
+                errors.add("registrationId", "registrationId is required when identityKey is provided");
             }
         }
+        check_pre_keys(&mut errors, &self.signed_pre_key, &self.one_time_pre_keys);
+        errors.into_result()
+    }
+}
 
-        Ok(())
+/// Checks the key fields shared by device creation and pre-key uploads.
+pub(crate) fn check_pre_keys(
+    errors: &mut ValidationErrors,
+    signed_pre_key: &SignedPreKey,
+    one_time_pre_keys: &[OneTimePreKey],
+) {
+    errors.check_base64_len("signedPreKey.publicKey", &signed_pre_key.public_key.0, PUBLIC_KEY_BYTES);
+    errors.check_base64_len("signedPreKey.signature", &signed_pre_key.signature.0, SIGNATURE_BYTES);
+
+    let mut unique_ids = std::collections::HashSet::with_capacity(one_time_pre_keys.len());
+    for (i, pk) in one_time_pre_keys.iter().enumerate() {
+        if !unique_ids.insert(pk.key_id) {
+            errors.add(format!("oneTimePreKeys[{i}].keyId"), format!("Duplicate prekey ID: {}", pk.key_id));
+        }
+        errors.check_base64_len(&format!("oneTimePreKeys[{i}].publicKey"), &pk.public_key.0, PUBLIC_KEY_BYTES);
     }
 }
 
@@ -118,7 +135,7 @@ mod tests {
         let res = upload.validate();
         assert!(res.is_err());
         assert_eq!(
-            res.expect_err("Missing registrationId should fail"),
+            res.expect_err("Missing registrationId should fail").to_string(),
             "registrationId is required when identityKey is provided"
         );
     }
@@ -132,10 +149,20 @@ mod tests {
         ];
         let res = upload.validate();
         assert!(res.is_err());
-        assert_eq!(res.expect_err("Duplicate prekey IDs should fail"), "Duplicate prekey ID: 1");
+        let errors = res.expect_err("Duplicate prekey IDs should fail");
+        assert_eq!(errors.to_string(), "Duplicate prekey ID: 1");
+        assert_eq!(errors.fields()[0].field, "oneTimePreKeys[1].keyId");
     }
 }
 
+#[derive(Debug, Deserialize)]
+pub struct PreKeyBundleQuery {
+    /// Holds the one-time pre-keys until the caller redeems the returned consumption token,
+    /// so a bundle can be cached before the first message is sent.
+    #[serde(default)]
+    pub reserve: bool,
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PreKeyBundleResponse {
@@ -144,6 +171,25 @@ pub struct PreKeyBundleResponse {
     pub identity_key: PublicKey,
     pub signed_pre_key: SignedPreKey,
     pub one_time_pre_key: Option<OneTimePreKey>,
+    /// Present on reserved bundles that hold a one-time pre-key.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub consumption_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reserved_until: Option<String>,
+}
+
+impl PreKeyBundleResponse {
+    /// Attaches a reservation to the bundle, if it holds a one-time pre-key the reservation covers.
+    #[must_use]
+    pub fn with_reservation(mut self, reservation: Option<&keys::PreKeyReservation>) -> Self {
+        if let Some(reservation) = reservation
+            && self.one_time_pre_key.is_some()
+        {
+            self.consumption_token = Some(reservation.token.to_string());
+            self.reserved_until = reservation.expires_at.format(&Rfc3339).ok();
+        }
+        self
+    }
 }
 
 impl From<keys::PreKeyBundle> for PreKeyBundleResponse {
@@ -154,6 +200,120 @@ impl From<keys::PreKeyBundle> for PreKeyBundleResponse {
             identity_key: b.identity_key.into(),
             signed_pre_key: b.signed_pre_key.into(),
             one_time_pre_key: b.one_time_pre_key.map(Into::into),
+            consumption_token: None,
+            reserved_until: None,
+        }
+    }
+}
+
+/// Result of a dry-run key upload. `valid` is true when the upload would be accepted as is.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyValidationResponse {
+    pub valid: bool,
+    /// The upload carries a new identity key and would take over the device.
+    pub takeover: bool,
+    pub errors: Vec<KeyValidationError>,
+}
+
+impl KeyValidationResponse {
+    #[must_use]
+    pub fn new(takeover: bool, issues: Vec<keys::KeyIssue>) -> Self {
+        Self { valid: issues.is_empty(), takeover, errors: issues.into_iter().map(Into::into).collect() }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyValidationError {
+    /// The request field at fault, e.g. `signedPreKey`.
+    pub field: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<i32>,
+    pub message: String,
+}
+
+impl From<keys::KeyIssue> for KeyValidationError {
+    fn from(issue: keys::KeyIssue) -> Self {
+        let field = match issue.field {
+            keys::KeyField::IdentityKey => "identityKey",
+            keys::KeyField::RegistrationId => "registrationId",
+            keys::KeyField::SignedPreKey => "signedPreKey",
+            keys::KeyField::OneTimePreKeys => "oneTimePreKeys",
+        };
+        Self { field, key_id: issue.key_id, message: issue.message }
+    }
+}
+
+impl From<proto::SignedPreKey> for SignedPreKey {
+    fn from(proto: proto::SignedPreKey) -> Self {
+        Self {
+            key_id: proto.key_id,
+            public_key: PublicKey(STANDARD.encode(proto.public_key)),
+            signature: Signature(STANDARD.encode(proto.signature)),
         }
     }
 }
+
+impl From<SignedPreKey> for proto::SignedPreKey {
+    fn from(key: SignedPreKey) -> Self {
+        Self { key_id: key.key_id, public_key: decode(&key.public_key.0), signature: decode(&key.signature.0) }
+    }
+}
+
+impl From<proto::OneTimePreKey> for OneTimePreKey {
+    fn from(proto: proto::OneTimePreKey) -> Self {
+        Self { key_id: proto.key_id, public_key: PublicKey(STANDARD.encode(proto.public_key)) }
+    }
+}
+
+impl From<OneTimePreKey> for proto::OneTimePreKey {
+    fn from(key: OneTimePreKey) -> Self {
+        Self { key_id: key.key_id, public_key: decode(&key.public_key.0) }
+    }
+}
+
+impl ProtoCodec for PreKeyUploadRequest {
+    type Proto = proto::UploadKeysRequest;
+}
+
+impl TryFrom<proto::UploadKeysRequest> for PreKeyUploadRequest {
+    type Error = String;
+    fn try_from(proto: proto::UploadKeysRequest) -> Result<Self, Self::Error> {
+        Ok(Self {
+            // An empty identity key is a plain refill, like an absent `identityKey` in JSON.
+            identity_key: (!proto.identity_key.is_empty()).then(|| PublicKey(STANDARD.encode(proto.identity_key))),
+            registration_id: proto.registration_id,
+            signed_pre_key: proto.signed_pre_key.ok_or_else(|| "signedPreKey is required".to_string())?.into(),
+            one_time_pre_keys: proto.one_time_pre_keys.into_iter().map(Into::into).collect(),
+        })
+    }
+}
+
+impl ProtoCodec for Vec<PreKeyBundleResponse> {
+    type Proto = proto::PreKeyBundles;
+}
+
+impl From<Vec<PreKeyBundleResponse>> for proto::PreKeyBundles {
+    fn from(bundles: Vec<PreKeyBundleResponse>) -> Self {
+        Self {
+            bundles: bundles
+                .into_iter()
+                .map(|b| proto::PreKeyBundle {
+                    device_id: Uuid::parse_str(&b.device_id).map(|id| id.as_bytes().to_vec()).unwrap_or_default(),
+                    registration_id: b.registration_id,
+                    identity_key: decode(&b.identity_key.0),
+                    signed_pre_key: Some(b.signed_pre_key.into()),
+                    one_time_pre_key: b.one_time_pre_key.map(Into::into),
+                    consumption_token: b.consumption_token.unwrap_or_default(),
+                    reserved_until: b.reserved_until.unwrap_or_default(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Decodes key material that the server encoded itself, so it is always valid base64.
+fn decode(b64: &str) -> Vec<u8> {
+    STANDARD.decode(b64).unwrap_or_default()
+}
@@ -1,5 +1,11 @@
+use crate::api::schemas::validation::{Validate, ValidationErrors};
 use crate::domain::message::{RawSubmission, SubmissionErrorCode, SubmissionOutcome};
 use crate::proto::obscura::v1 as proto;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Most message IDs accepted in one redelivery request.
+pub const MAX_REDELIVERY_IDS: usize = 1000;
 
 impl From<proto::send_message_request::Submission> for RawSubmission {
     fn from(proto: proto::send_message_request::Submission) -> Self {
@@ -10,6 +16,17 @@ impl From<proto::send_message_request::Submission> for RawSubmission {
 impl From<SubmissionOutcome> for proto::SendMessageResponse {
     fn from(outcome: SubmissionOutcome) -> Self {
         Self {
+            receipts: outcome
+                .receipts
+                .into_iter()
+                .map(|r| proto::send_message_response::Receipt {
+                    submission_id: r.submission_id.as_bytes().to_vec(),
+                    message_id: r.message_id.as_bytes().to_vec(),
+                    device_id: r.device_id.as_bytes().to_vec(),
+                    timestamp: u64::try_from(r.timestamp.unix_timestamp_nanos() / 1_000_000).unwrap_or(0),
+                    sequence: u64::try_from(r.sequence).unwrap_or(0),
+                })
+                .collect(),
             failed_submissions: outcome
                 .failed_submissions
                 .into_iter()
@@ -31,3 +48,29 @@ impl From<SubmissionOutcome> for proto::SendMessageResponse {
         }
     }
 }
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RedeliverRequest {
+    pub message_ids: Vec<Uuid>,
+}
+
+impl Validate for RedeliverRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        if self.message_ids.is_empty() {
+            errors.add("messageIds", "At least one message ID is required");
+        } else if self.message_ids.len() > MAX_REDELIVERY_IDS {
+            errors.add("messageIds", format!("Too many message IDs (max {MAX_REDELIVERY_IDS})"));
+        }
+        errors.into_result()
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RedeliverResponse {
+    /// Messages put back in the queue. IDs that were never acknowledged, or whose grace period
+    /// has passed, are not counted.
+    pub redelivered: u64,
+}
@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataSearchRequest {
+    /// Who is running the query, recorded in the audit log.
+    pub operator: String,
+    /// Why the query is run, e.g. a case or request reference, recorded in the audit log.
+    pub reason: String,
+    pub sender_id: Option<String>,
+    pub recipient_id: Option<String>,
+    /// RFC 3339 timestamp of the earliest message to return.
+    pub since: Option<String>,
+    /// RFC 3339 timestamp of the latest message to return.
+    pub until: Option<String>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataSearchResponse {
+    pub query: MetadataQueryEntry,
+    pub messages: Vec<MessageMetadataEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageMetadataEntry {
+    pub message_id: String,
+    pub sender_id: String,
+    pub sender_device_id: String,
+    pub recipient_id: String,
+    pub recipient_device_id: String,
+    pub sent_at: String,
+    pub size_bytes: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataQueryListQuery {
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataQueryListResponse {
+    pub queries: Vec<MetadataQueryEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataQueryEntry {
+    pub id: String,
+    pub operator: String,
+    pub reason: String,
+    pub client_cert: Option<String>,
+    pub sender_id: Option<String>,
+    pub recipient_id: Option<String>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub result_count: i32,
+    pub queried_at: String,
+}
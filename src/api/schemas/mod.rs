@@ -1,10 +1,23 @@
+pub mod account;
+pub mod announcements;
 pub mod attachments;
 pub mod auth;
+pub mod blocklist;
 pub mod common;
 pub mod crypto;
 pub mod devices;
+pub mod feature_flags;
 pub mod gateway;
 pub mod health;
+pub mod identifiers;
+pub mod instances;
 pub mod keys;
+pub mod maintenance;
 pub mod messaging;
+pub mod metadata_index;
 pub mod push_tokens;
+pub mod routes;
+pub mod support;
+pub mod tiers;
+pub mod validation;
+pub mod webhooks;
@@ -1,3 +1,4 @@
+use crate::api::schemas::validation::{MAX_PUSH_TOKEN_LEN, Validate, ValidationErrors};
 use serde::Deserialize;
 
 #[derive(Debug, Deserialize)]
@@ -5,20 +6,17 @@ pub struct RegisterPushTokenRequest {
     pub token: String,
 }
 
-impl RegisterPushTokenRequest {
-    /// Validates the token registration payload.
-    ///
-    /// # Errors
-    /// Returns an error if the token is empty or excessively large (anti-abuse).
-    pub fn validate(&self) -> Result<(), String> {
+impl Validate for RegisterPushTokenRequest {
+    /// Rejects empty tokens and excessively large ones (anti-abuse).
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
         let trimmed = self.token.trim();
         if trimmed.is_empty() {
-            return Err("Token cannot be empty".into());
+            errors.add("token", "Token cannot be empty");
+        } else if trimmed.len() > MAX_PUSH_TOKEN_LEN {
+            errors.add("token", format!("Token is too long (max {MAX_PUSH_TOKEN_LEN} characters)"));
         }
-        if trimmed.len() > 4096 {
-            return Err("Token is too long (max 4096 characters)".into());
-        }
-        Ok(())
+        errors.into_result()
     }
 }
 
@@ -37,7 +35,7 @@ mod tests {
         let req = RegisterPushTokenRequest { token: "   ".into() };
         let res = req.validate();
         assert!(res.is_err());
-        assert_eq!(res.expect_err("Token should be empty"), "Token cannot be empty");
+        assert_eq!(res.expect_err("Token should be empty").to_string(), "Token cannot be empty");
     }
 
     #[test]
@@ -45,6 +43,6 @@ mod tests {
         let req = RegisterPushTokenRequest { token: "A".repeat(4097) };
         let res = req.validate();
         assert!(res.is_err());
-        assert_eq!(res.expect_err("Token should be too long"), "Token is too long (max 4096 characters)");
+        assert_eq!(res.expect_err("Token should be too long").to_string(), "Token is too long (max 4096 characters)");
     }
 }
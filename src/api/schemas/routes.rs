@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+
+/// Every route of the public API, as composed from the route spec.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RouteTreeResponse {
+    pub routes: Vec<RouteEntry>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RouteEntry {
+    pub path: String,
+    pub methods: Vec<String>,
+    /// Route class the route's timeout, rate limit and compression settings come from.
+    pub class: Option<String>,
+    /// Layers in the order a request passes through them, outermost first.
+    pub layers: Vec<LayerEntry>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LayerEntry {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+    /// Rate limit tier, which decides how clients are keyed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tier: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub per_second: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub burst: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_in_flight: Option<usize>,
+}
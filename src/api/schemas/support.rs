@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InboxDiagnosticsResponse {
+    pub user_id: String,
+    pub devices: Vec<DeviceInboxDiagnostics>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceInboxDiagnostics {
+    pub device_id: String,
+    pub pending_messages: i64,
+    pub pending_bytes: i64,
+    pub oldest_message_age_secs: Option<i64>,
+    pub next_expiry_in_secs: Option<i64>,
+    pub attachments: i64,
+    pub push_token_registered: bool,
+    pub backup: Option<BackupDiagnostics>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupDiagnostics {
+    pub current_version: i32,
+    pub pending_version: Option<i32>,
+    pub state: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListingQuery {
+    pub cursor: Option<String>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserListResponse {
+    pub users: Vec<UserListEntry>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserListEntry {
+    pub user_id: String,
+    pub username: String,
+    pub tier: String,
+    pub created_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InboxListResponse {
+    pub inboxes: Vec<InboxListEntry>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InboxListEntry {
+    pub user_id: String,
+    pub device_id: String,
+    pub pending_messages: i64,
+    pub pending_bytes: i64,
+    pub oldest_message_age_secs: Option<i64>,
+}
@@ -0,0 +1,15 @@
+use crate::domain::user::UserTier;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetUserTierRequest {
+    pub tier: UserTier,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserTierResponse {
+    pub user_id: String,
+    pub tier: UserTier,
+}
@@ -0,0 +1,170 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
+
+static USERNAME_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[a-zA-Z0-9_]{3,50}$").expect("Hardcoded username validation regex should compile"));
+
+static OPAQUE_TOKEN_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[A-Za-z0-9_-]+$").expect("Hardcoded token validation regex should compile"));
+
+pub const MAX_USERNAME_LEN: usize = 50;
+pub const MIN_PASSWORD_LEN: usize = 12;
+/// Longer passwords only make hashing more expensive for an attacker to trigger.
+pub const MAX_PASSWORD_LEN: usize = 1024;
+/// Refresh tokens are 32 random bytes, encoded as 43 characters of unpadded URL-safe base64.
+pub const MAX_OPAQUE_TOKEN_LEN: usize = 256;
+pub const MAX_PUSH_TOKEN_LEN: usize = 4096;
+pub const MAX_DEVICE_NAME_LEN: usize = 64;
+/// Public keys are 33 bytes on the wire, with their type prefix.
+pub const PUBLIC_KEY_BYTES: usize = 33;
+pub const SIGNATURE_BYTES: usize = 64;
+
+/// A request type whose fields can be checked before the handler acts on it.
+pub trait Validate {
+    /// Checks every field, reporting all problems rather than only the first.
+    ///
+    /// # Errors
+    /// Returns the field errors if any field is invalid.
+    fn validate(&self) -> Result<(), ValidationErrors>;
+}
+
+/// One invalid field. `field` is the path in the JSON body, e.g. `signedPreKey.publicKey` or
+/// `oneTimePreKeys[2].publicKey`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// The field errors found while validating a request, in the order the fields were checked.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationErrors(Vec<FieldError>);
+
+impl ValidationErrors {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    pub fn add(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.0.push(FieldError { field: field.into(), message: message.into() });
+    }
+
+    #[must_use]
+    pub fn fields(&self) -> &[FieldError] {
+        &self.0
+    }
+
+    #[must_use]
+    pub fn into_fields(self) -> Vec<FieldError> {
+        self.0
+    }
+
+    /// `Ok` if nothing was added.
+    ///
+    /// # Errors
+    /// Returns `self` if any field error was added.
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.0.is_empty() { Ok(()) } else { Err(self) }
+    }
+
+    pub fn check_username(&mut self, field: &str, username: &str) {
+        if username.trim().is_empty() {
+            self.add(field, "Username cannot be empty");
+        } else if !USERNAME_REGEX.is_match(username) {
+            self.add(
+                field,
+                "Username must be between 3 and 50 characters and can only contain letters, numbers, and underscores",
+            );
+        }
+    }
+
+    pub fn check_password(&mut self, field: &str, password: &str) {
+        if password.len() < MIN_PASSWORD_LEN {
+            self.add(field, format!("Password must be at least {MIN_PASSWORD_LEN} characters long"));
+        } else if password.len() > MAX_PASSWORD_LEN {
+            self.add(field, format!("Password must be at most {MAX_PASSWORD_LEN} characters long"));
+        }
+    }
+
+    /// An opaque server-issued token, such as a refresh token.
+    pub fn check_opaque_token(&mut self, field: &str, token: &str) {
+        if token.is_empty() {
+            self.add(field, "Token cannot be empty");
+        } else if token.len() > MAX_OPAQUE_TOKEN_LEN {
+            self.add(field, format!("Token is too long (max {MAX_OPAQUE_TOKEN_LEN} characters)"));
+        } else if !OPAQUE_TOKEN_REGEX.is_match(token) {
+            self.add(field, "Token is not URL-safe base64");
+        }
+    }
+
+    /// Caps the length of a base64 field before it is decoded. The exact decoded length is
+    /// checked when the field is converted to its domain type.
+    pub fn check_base64_len(&mut self, field: &str, value: &str, max_bytes: usize) {
+        let max_len = max_bytes.div_ceil(3) * 4;
+        if value.is_empty() {
+            self.add(field, "Value cannot be empty");
+        } else if value.len() > max_len {
+            self.add(field, format!("Value is too long (max {max_len} base64 characters)"));
+        }
+    }
+
+    pub fn check_max_len(&mut self, field: &str, value: &str, max_len: usize) {
+        if value.chars().count() > max_len {
+            self.add(field, format!("Value is too long (max {max_len} characters)"));
+        }
+    }
+}
+
+impl std::fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut first = true;
+        for error in &self.0 {
+            if !first {
+                f.write_str("; ")?;
+            }
+            f.write_str(&error.message)?;
+            first = false;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collects_every_field_error_in_order() {
+        let mut errors = ValidationErrors::new();
+        errors.check_username("username", "a!");
+        errors.check_password("password", "short");
+        errors.check_opaque_token("refreshToken", "abc-_DEF");
+
+        let errors = errors.into_result().expect_err("two fields are invalid");
+        let fields: Vec<&str> = errors.fields().iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, ["username", "password"]);
+        assert!(errors.to_string().ends_with("; Password must be at least 12 characters long"));
+    }
+
+    #[test]
+    fn test_base64_length_cap() {
+        let mut errors = ValidationErrors::new();
+        errors.check_base64_len("identityKey", &"A".repeat(44), PUBLIC_KEY_BYTES);
+        assert!(errors.fields().is_empty());
+
+        errors.check_base64_len("identityKey", &"A".repeat(48), PUBLIC_KEY_BYTES);
+        errors.check_base64_len("signedPreKey.signature", "", SIGNATURE_BYTES);
+        assert_eq!(errors.fields().len(), 2);
+    }
+
+    #[test]
+    fn test_opaque_token_rejects_foreign_characters() {
+        let mut errors = ValidationErrors::new();
+        errors.check_opaque_token("refreshToken", "not a token!");
+        errors.check_opaque_token("refreshToken", &"a".repeat(MAX_OPAQUE_TOKEN_LEN + 1));
+        assert_eq!(errors.fields().len(), 2);
+    }
+}
@@ -0,0 +1,50 @@
+use crate::api::schemas::validation::{Validate, ValidationErrors};
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceMessageRequest {
+    pub user_ids: Vec<Uuid>,
+    /// Base64 message sealed by the service for the recipients; the server never decrypts it.
+    pub message: String,
+}
+
+impl Validate for ServiceMessageRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        if self.user_ids.is_empty() {
+            errors.add("userIds", "At least one user ID is required");
+        }
+        match STANDARD.decode(&self.message) {
+            Ok(message) if message.is_empty() => errors.add("message", "Message cannot be empty"),
+            Ok(_) => {}
+            Err(_) => errors.add("message", "Message must be valid base64"),
+        }
+        errors.into_result()
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceMessageResponse {
+    /// Devices the message was queued for. Unknown user IDs add nothing.
+    pub queued: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_message_request_validation() {
+        let valid = ServiceMessageRequest { user_ids: vec![Uuid::new_v4()], message: STANDARD.encode(b"sealed") };
+        assert!(valid.validate().is_ok());
+
+        let invalid = ServiceMessageRequest { user_ids: Vec::new(), message: "not base64!".to_string() };
+        let errors = invalid.validate().expect_err("both fields are invalid");
+        let fields: Vec<&str> = errors.fields().iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, ["userIds", "message"]);
+    }
+}
@@ -0,0 +1,263 @@
+use crate::api::middleware::InFlightRequests;
+use crate::config::ServerConfig;
+use crate::services::gateway::routing::SessionCounter;
+use axum::Router;
+use axum::extract::ConnectInfo;
+use axum::http::Request;
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto;
+use hyper_util::server::graceful::GracefulShutdown;
+use hyper_util::service::TowerToHyperService;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::{TcpListener, TcpSocket};
+use tokio::sync::watch;
+use tower::ServiceExt;
+
+/// How often a drain checks whether everything has finished.
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How often a drain logs what is still open.
+const DRAIN_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Binds the main listener with the configured accept backlog.
+///
+/// # Errors
+/// Returns an error if the socket cannot be created, bound or put into listening mode.
+pub fn bind(addr: SocketAddr, config: &ServerConfig) -> std::io::Result<TcpListener> {
+    let socket = if addr.is_ipv4() { TcpSocket::new_v4()? } else { TcpSocket::new_v6()? };
+    // Lets a restarted server rebind while the previous one's connections sit in TIME_WAIT
+    socket.set_reuseaddr(true)?;
+    socket.bind(addr)?;
+    socket.listen(config.tcp_backlog)
+}
+
+/// Builds the per-connection HTTP settings for the main port.
+fn connection_builder(config: &ServerConfig) -> auto::Builder<TokioExecutor> {
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    builder
+        .http1()
+        .timer(TokioTimer::new())
+        .keep_alive(config.http1_keep_alive)
+        .header_read_timeout(Duration::from_secs(config.http1_header_read_timeout_secs.max(1)));
+
+    if !config.http2_enabled {
+        return builder.http1_only();
+    }
+
+    let ping_interval =
+        (config.http2_keep_alive_interval_secs > 0).then(|| Duration::from_secs(config.http2_keep_alive_interval_secs));
+    builder
+        .http2()
+        .timer(TokioTimer::new())
+        .max_concurrent_streams(config.http2_max_concurrent_streams)
+        .keep_alive_interval(ping_interval)
+        .keep_alive_timeout(Duration::from_secs(config.http2_keep_alive_timeout_secs));
+    builder
+}
+
+/// Serves `router` on the main port until `shutdown` fires, then waits for open connections
+/// to finish.
+///
+/// Equivalent to `axum::serve` with `ConnectInfo<SocketAddr>`, but applies the HTTP/2,
+/// keep-alive and TCP settings from `config`.
+///
+/// # Errors
+/// Accept failures are logged and retried, so this currently always returns `Ok`; the
+/// signature matches `axum::serve` for use in `try_join!`.
+pub async fn serve(
+    listener: TcpListener,
+    router: Router,
+    config: &ServerConfig,
+    mut shutdown: watch::Receiver<bool>,
+) -> std::io::Result<()> {
+    let builder = Arc::new(connection_builder(config));
+    let graceful = GracefulShutdown::new();
+
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(conn) => conn,
+                Err(e) => {
+                    // Usually file descriptor exhaustion; back off instead of spinning on it
+                    tracing::warn!(error = %e, "Failed to accept connection");
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            },
+            _ = shutdown.wait_for(|&s| s) => break,
+        };
+
+        if let Err(e) = stream.set_nodelay(config.tcp_nodelay) {
+            tracing::debug!(error = %e, client.address = %peer, "Failed to set TCP_NODELAY");
+        }
+
+        let builder = Arc::clone(&builder);
+        let router = router.clone();
+        let watcher = graceful.watcher();
+        tokio::spawn(async move {
+            let service = router.map_request(move |mut req: Request<Incoming>| {
+                req.extensions_mut().insert(ConnectInfo(peer));
+                req
+            });
+
+            let conn = builder
+                .serve_connection_with_upgrades(TokioIo::new(stream), TowerToHyperService::new(service))
+                .into_owned();
+            if let Err(e) = watcher.watch(conn).await {
+                tracing::debug!(error = %e, client.address = %peer, "Connection ended with error");
+            }
+        });
+    }
+
+    graceful.shutdown().await;
+    Ok(())
+}
+
+/// Watches the requests and WebSocket sessions the main port still holds once shutdown begins.
+#[derive(Clone, Debug)]
+pub struct DrainWatch {
+    requests: InFlightRequests,
+    sessions: SessionCounter,
+}
+
+impl DrainWatch {
+    #[must_use]
+    pub const fn new(requests: InFlightRequests, sessions: SessionCounter) -> Self {
+        Self { requests, sessions }
+    }
+
+    /// Once `shutdown` fires, waits for in-flight requests and WebSocket sessions to finish,
+    /// logging how many are left every second. Returns `true` as soon as both are gone, or
+    /// `false` when `timeout` runs out first.
+    pub async fn wait(self, mut shutdown: watch::Receiver<bool>, timeout: Duration) -> bool {
+        let _ = shutdown.wait_for(|&s| s).await;
+        let started = Instant::now();
+        let mut last_report: Option<Instant> = None;
+        loop {
+            let (requests, sessions) = (self.requests.active(), self.sessions.active());
+            let elapsed = started.elapsed();
+            let elapsed_ms = u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX);
+            if requests == 0 && sessions == 0 {
+                tracing::info!(elapsed_ms, "Drained all requests and WebSocket sessions");
+                return true;
+            }
+            if elapsed >= timeout {
+                tracing::warn!(
+                    http.in_flight_requests = requests,
+                    websocket.sessions = sessions,
+                    elapsed_ms,
+                    "Drain timed out, dropping what is left"
+                );
+                return false;
+            }
+            if last_report.is_none_or(|at| at.elapsed() >= DRAIN_REPORT_INTERVAL) {
+                let remaining_ms = u64::try_from((timeout - elapsed).as_millis()).unwrap_or(u64::MAX);
+                tracing::info!(
+                    http.in_flight_requests = requests,
+                    websocket.sessions = sessions,
+                    elapsed_ms,
+                    remaining_ms,
+                    "Draining requests and WebSocket sessions"
+                );
+                last_report = Some(Instant::now());
+            }
+            tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    const H2_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+    const SETTINGS_FRAME: u8 = 0x4;
+    const SETTINGS_MAX_CONCURRENT_STREAMS: u16 = 0x3;
+
+    async fn start(config: ServerConfig) -> (SocketAddr, watch::Sender<bool>) {
+        let listener = bind("127.0.0.1:0".parse().expect("valid address"), &config).expect("bind test listener");
+        let addr = listener.local_addr().expect("listener address");
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let router = Router::new().route("/", get(|| async { "ok" }));
+        tokio::spawn(async move { serve(listener, router, &config, shutdown_rx).await });
+        (addr, shutdown_tx)
+    }
+
+    /// Sends the HTTP/2 connection preface and returns the first bytes the server answers with.
+    async fn open_h2(addr: SocketAddr) -> Vec<u8> {
+        let mut stream = TcpStream::connect(addr).await.expect("connect");
+        stream.write_all(H2_PREFACE).await.expect("write preface");
+        // An empty SETTINGS frame completes the client preface
+        stream.write_all(&[0, 0, 0, SETTINGS_FRAME, 0, 0, 0, 0, 0]).await.expect("write settings");
+
+        let mut buf = vec![0; 256];
+        let read = tokio::time::timeout(Duration::from_secs(2), stream.read(&mut buf))
+            .await
+            .expect("server answers")
+            .expect("read response");
+        buf.truncate(read);
+        buf
+    }
+
+    #[tokio::test]
+    async fn test_http2_settings_advertise_stream_limit() {
+        let config = ServerConfig { http2_max_concurrent_streams: 7, ..ServerConfig::default() };
+        let (addr, _shutdown) = start(config).await;
+
+        let frame = open_h2(addr).await;
+        assert!(frame.len() >= 9, "Expected a SETTINGS frame, got {frame:?}");
+        assert_eq!(frame[3], SETTINGS_FRAME);
+
+        let len = usize::try_from(u32::from_be_bytes([0, frame[0], frame[1], frame[2]])).expect("frame length");
+        let max_streams = frame[9..9 + len.min(frame.len() - 9)]
+            .chunks_exact(6)
+            .find(|setting| u16::from_be_bytes([setting[0], setting[1]]) == SETTINGS_MAX_CONCURRENT_STREAMS)
+            .map(|setting| u32::from_be_bytes([setting[2], setting[3], setting[4], setting[5]]));
+        assert_eq!(max_streams, Some(7));
+    }
+
+    #[tokio::test]
+    async fn test_http2_can_be_disabled() {
+        let config = ServerConfig { http2_enabled: false, ..ServerConfig::default() };
+        let (addr, _shutdown) = start(config).await;
+
+        // An HTTP/1-only server treats the preface as a malformed request
+        let response = open_h2(addr).await;
+        assert!(response.is_empty() || response.starts_with(b"HTTP/1.1 "), "Unexpected response {response:?}");
+    }
+
+    #[tokio::test]
+    async fn test_drain_returns_once_sessions_close() {
+        let sessions = SessionCounter::default();
+        sessions.increment();
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let drain = tokio::spawn(
+            DrainWatch::new(InFlightRequests::default(), sessions.clone()).wait(shutdown_rx, Duration::from_secs(10)),
+        );
+
+        shutdown_tx.send(true).expect("drain is listening");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!drain.is_finished(), "A session is still open");
+
+        sessions.decrement();
+        let drained = tokio::time::timeout(Duration::from_secs(1), drain).await.expect("drain ends early");
+        assert!(drained.expect("drain task"));
+    }
+
+    #[tokio::test]
+    async fn test_drain_gives_up_at_the_timeout() {
+        let sessions = SessionCounter::default();
+        sessions.increment();
+        let (_shutdown_tx, shutdown_rx) = watch::channel(true);
+
+        let drained =
+            DrainWatch::new(InFlightRequests::default(), sessions).wait(shutdown_rx, Duration::from_millis(200)).await;
+        assert!(!drained);
+    }
+}
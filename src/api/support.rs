@@ -0,0 +1,117 @@
+use crate::api::MgmtState;
+use crate::api::schemas::support::{
+    BackupDiagnostics, DeviceInboxDiagnostics, InboxDiagnosticsResponse, InboxListEntry, InboxListResponse,
+    ListingQuery, UserListEntry, UserListResponse,
+};
+use crate::error::Result;
+use crate::services::support_service::{DeviceDiagnostics, Page, UserListing};
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    response::IntoResponse,
+};
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use uuid::Uuid;
+
+/// Describes the pending queue of every device of a user, for debugging delivery reports.
+///
+/// # Errors
+/// Returns `AppError::NotFound` if the user has no devices.
+pub(crate) async fn inspect_inbox(
+    State(state): State<MgmtState>,
+    Path(user_id): Path<Uuid>,
+) -> Result<impl IntoResponse> {
+    let devices = state.support_service.inspect_user(user_id).await?;
+    let now = OffsetDateTime::now_utc();
+
+    Ok(Json(InboxDiagnosticsResponse {
+        user_id: user_id.to_string(),
+        devices: devices.into_iter().map(|d| device_to_response(d, now)).collect(),
+    }))
+}
+
+/// Lists every account in registration order.
+///
+/// # Errors
+/// Returns `AppError::BadRequest` if the cursor is malformed.
+pub(crate) async fn list_users(
+    State(state): State<MgmtState>,
+    Query(query): Query<ListingQuery>,
+) -> Result<impl IntoResponse> {
+    let page = state.support_service.list_users(query.cursor.as_deref(), query.limit).await?;
+    Ok(Json(users_to_response(page)))
+}
+
+/// Lists accounts newest registration first.
+///
+/// # Errors
+/// Returns `AppError::BadRequest` if the cursor is malformed.
+pub(crate) async fn list_recent_users(
+    State(state): State<MgmtState>,
+    Query(query): Query<ListingQuery>,
+) -> Result<impl IntoResponse> {
+    let page = state.support_service.list_recent_users(query.cursor.as_deref(), query.limit).await?;
+    Ok(Json(users_to_response(page)))
+}
+
+/// Lists the devices with the most pending messages, for spotting stuck or abandoned queues.
+///
+/// # Errors
+/// Returns `AppError::BadRequest` if the cursor is malformed.
+pub(crate) async fn list_largest_inboxes(
+    State(state): State<MgmtState>,
+    Query(query): Query<ListingQuery>,
+) -> Result<impl IntoResponse> {
+    let page = state.support_service.list_largest_inboxes(query.cursor.as_deref(), query.limit).await?;
+    let now = OffsetDateTime::now_utc();
+
+    Ok(Json(InboxListResponse {
+        inboxes: page
+            .items
+            .into_iter()
+            .map(|listing| InboxListEntry {
+                user_id: listing.user_id.to_string(),
+                device_id: listing.inbox.device_id.to_string(),
+                pending_messages: listing.inbox.pending_messages,
+                pending_bytes: listing.inbox.pending_bytes,
+                oldest_message_age_secs: listing.inbox.oldest_message_at.map(|ts| (now - ts).whole_seconds()),
+            })
+            .collect(),
+        next_cursor: page.next_cursor,
+    }))
+}
+
+fn users_to_response(page: Page<UserListing>) -> UserListResponse {
+    UserListResponse {
+        users: page
+            .items
+            .into_iter()
+            .map(|u| UserListEntry {
+                user_id: u.user_id.to_string(),
+                username: u.username,
+                tier: u.tier.to_string(),
+                created_at: u.created_at.and_then(|ts| ts.format(&Rfc3339).ok()),
+            })
+            .collect(),
+        next_cursor: page.next_cursor,
+    }
+}
+
+fn device_to_response(d: DeviceDiagnostics, now: OffsetDateTime) -> DeviceInboxDiagnostics {
+    DeviceInboxDiagnostics {
+        device_id: d.device_id.to_string(),
+        pending_messages: d.inbox.pending_messages,
+        pending_bytes: d.inbox.pending_bytes,
+        oldest_message_age_secs: d.inbox.oldest_message_at.map(|ts| (now - ts).whole_seconds()),
+        next_expiry_in_secs: d.inbox.next_expiry_at.map(|ts| (ts - now).whole_seconds()),
+        attachments: d.attachments,
+        push_token_registered: d.push_token_registered,
+        backup: d.backup.map(|b| BackupDiagnostics {
+            current_version: b.current_version,
+            pending_version: b.pending_version,
+            state: b.state.to_string(),
+            updated_at: b.updated_at.format(&Rfc3339).unwrap_or_default(),
+        }),
+    }
+}
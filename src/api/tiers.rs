@@ -0,0 +1,20 @@
+use crate::api::MgmtState;
+use crate::api::schemas::tiers::{SetUserTierRequest, UserTierResponse};
+use crate::error::Result;
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::response::IntoResponse;
+use uuid::Uuid;
+
+/// Moves a user to another tier. The user's limits change once their access token is refreshed.
+///
+/// # Errors
+/// Returns `AppError::NotFound` if the user does not exist.
+pub(crate) async fn set_user_tier(
+    State(state): State<MgmtState>,
+    Path(user_id): Path<Uuid>,
+    Json(payload): Json<SetUserTierRequest>,
+) -> Result<impl IntoResponse> {
+    state.auth_service.set_user_tier(user_id, payload.tier).await?;
+    Ok(Json(UserTierResponse { user_id: user_id.to_string(), tier: payload.tier }))
+}
@@ -0,0 +1,144 @@
+use crate::adapters::crypto::constant_time_eq;
+use crate::api::AppState;
+use crate::api::schemas::validation::{Validate, ValidationErrors};
+use crate::api::schemas::webhooks::{ServiceMessageRequest, ServiceMessageResponse};
+use crate::config::WebhookConfig;
+use crate::error::{AppError, AuthError, MessagingError, Result};
+use axum::{
+    Json,
+    extract::{FromRequestParts, State},
+    http::{HeaderMap, StatusCode, header, request::Parts},
+    response::{IntoResponse, Response},
+};
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Bearer tokens of the services allowed to call the inbound webhook.
+///
+/// As with the management token, tokens are compared by digest, and every configured service
+/// is checked so the time taken does not reveal which one came closest.
+#[derive(Clone, Debug)]
+pub(crate) struct WebhookAuth {
+    services: Arc<[(String, Vec<u8>)]>,
+}
+
+impl WebhookAuth {
+    pub(crate) fn new(config: &WebhookConfig) -> Self {
+        Self {
+            services: config
+                .services
+                .iter()
+                .map(|service| (service.name.clone(), Sha256::digest(&service.token).to_vec()))
+                .collect(),
+        }
+    }
+
+    /// Name of the service the request's bearer token belongs to.
+    fn authenticate(&self, headers: &HeaderMap) -> Option<&str> {
+        let presented = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(|token| Sha256::digest(token))?;
+
+        let mut matched = None;
+        for (name, expected) in self.services.iter() {
+            if constant_time_eq(&presented, expected) {
+                matched = Some(name.as_str());
+            }
+        }
+        matched
+    }
+}
+
+/// The configured service a webhook request is authenticated as. Extracted ahead of the body,
+/// so requests with an unknown token are refused before their payload is read.
+#[derive(Debug)]
+pub(crate) struct WebhookCaller {
+    service: String,
+}
+
+impl FromRequestParts<AppState> for WebhookCaller {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self> {
+        let Some(service) = state.webhook_auth.authenticate(&parts.headers) else {
+            tracing::warn!("Rejected webhook request with an unknown service token");
+            return Err(AuthError::InvalidToken.into());
+        };
+        Ok(Self { service: service.to_string() })
+    }
+}
+
+/// Queues a message from an external service, such as billing or policy notices, for every
+/// device of the given users. The service seals the message with its own identity key; the
+/// server wraps it in a `ServiceMessage` system envelope naming the service and delivers it
+/// like any other queued message.
+///
+/// Requests carry an `Idempotency-Key`; a retry with the same key from the same service is
+/// answered from the idempotency cache. One the cache cannot answer, because it raced the
+/// original or the cache lost it, is not queued again for devices that still hold the message.
+///
+/// # Errors
+/// Returns `AuthError::InvalidToken` if the bearer token belongs to no configured service.
+/// Returns `AppError::Messaging` if the idempotency key is missing or not a UUID.
+/// Returns `AppError::Validation` if no users, or too many, are given or the message is not
+/// base64.
+/// Returns `AppError::PayloadTooLarge` if the message exceeds `--webhook-max-message-bytes`.
+pub(crate) async fn enqueue_service_message(
+    WebhookCaller { service }: WebhookCaller,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<ServiceMessageRequest>,
+) -> Result<Response> {
+    let idempotency_key = headers
+        .get("idempotency-key")
+        .and_then(|v| v.to_str().ok())
+        .ok_or(MessagingError::MissingIdempotencyKey)
+        .and_then(|s| Uuid::parse_str(s).map_err(|e| MessagingError::InvalidIdempotencyKey(e.to_string())))?;
+    // Scoped to the service, so keys never collide with another service's or a client's
+    let cache_key = format!("webhook:{service}:{idempotency_key}");
+    if let Ok(Some(cached)) = state.submission_cache.get(&cache_key).await {
+        tracing::info!(key = %idempotency_key, service = %service, "Returning cached webhook response");
+        return Ok(json_response(cached));
+    }
+
+    payload.validate()?;
+
+    let limits = &state.config.webhooks;
+    if payload.user_ids.len() > limits.max_recipients {
+        let mut errors = ValidationErrors::new();
+        errors.add("userIds", format!("Too many user IDs (max {})", limits.max_recipients));
+        return Err(errors.into());
+    }
+    let message = STANDARD.decode(&payload.message).map_err(|e| AppError::BadRequest(e.to_string()))?;
+    if message.len() > limits.max_message_bytes {
+        return Err(AppError::PayloadTooLarge);
+    }
+
+    let queued = state
+        .message_service
+        .queue_service_message(&service, submission_id(&cache_key), &payload.user_ids, message)
+        .await?;
+    let response =
+        serde_json::to_vec(&ServiceMessageResponse { queued }).map_err(|e| AppError::InternalMsg(e.to_string()))?;
+    if let Err(e) = state.submission_cache.set(&cache_key, &response).await {
+        tracing::error!(error = %e, "Failed to cache webhook response");
+    }
+    Ok(json_response(response))
+}
+
+/// Submission ID the service message is queued under, the same for every retry of a request,
+/// so one that misses the idempotency cache, or races the original, is not queued twice.
+fn submission_id(cache_key: &str) -> Uuid {
+    let digest = Sha256::digest(cache_key);
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    uuid::Builder::from_custom_bytes(bytes).into_uuid()
+}
+
+fn json_response(body: Vec<u8>) -> Response {
+    (StatusCode::ACCEPTED, [(header::CONTENT_TYPE, "application/json")], body).into_response()
+}
@@ -0,0 +1,747 @@
+//! Protocol conformance suite (`conformance`): drives a running deployment through its public
+//! API the way a client would, to check that an upgrade, or a proxy or load balancer in front of
+//! it, did not break the protocol.
+//!
+//! The suite registers two throwaway accounts and deletes them again at the end, so it can run
+//! against production. Cases run in order and build on each other; a case whose prerequisite
+//! failed is reported as skipped instead of failing for the same reason twice. Reports are JSON
+//! or `JUnit` XML so CI systems can show the cases like any other test run.
+
+use crate::adapters::http_client::{client_builder, root_store, tls_provider};
+use crate::config::{OutboundConfig, ReportFormat};
+use crate::dev::montgomery_public_key;
+use crate::proto::obscura::v1 as proto;
+use anyhow::{Context, bail, ensure};
+use base64::{Engine as _, engine::general_purpose::STANDARD, engine::general_purpose::URL_SAFE_NO_PAD};
+use futures::{SinkExt, StreamExt};
+use prost::Message as _;
+use reqwest::StatusCode;
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::protocol::Message;
+use tokio_tungstenite::{Connector, MaybeTlsStream, WebSocketStream};
+use uuid::Uuid;
+use xeddsa::Sign;
+use xeddsa::xed25519::PrivateKey;
+
+/// Every case, in the order the suite runs them.
+pub const CASES: [&str; 8] = [
+    "registration",
+    "key_exchange",
+    "messaging",
+    "acknowledgement",
+    "backup",
+    "push_token",
+    "session_refresh",
+    "account_deletion",
+];
+
+/// Size of the backup the suite uploads, inside the default backup size limits.
+const BACKUP_SIZE_BYTES: usize = 1024;
+
+/// How often a rate limited request is retried before its response is taken as is.
+const RATE_LIMIT_RETRIES: usize = 5;
+
+/// How long acknowledgements get to be flushed after the gateway connection closes.
+const ACK_SETTLE: Duration = Duration::from_secs(1);
+
+/// The result of a conformance run.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    pub base_url: String,
+    pub started_at: String,
+    pub duration_ms: u64,
+    pub cases: Vec<CaseResult>,
+}
+
+/// One case of a run.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaseResult {
+    pub name: &'static str,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+    pub duration_ms: u64,
+}
+
+/// How a case ended. Skipped cases never ran because a case they depend on failed.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum Outcome {
+    Passed,
+    Failed { message: String },
+    Skipped { reason: String },
+}
+
+impl Report {
+    fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.to_string(),
+            started_at: OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default(),
+            duration_ms: 0,
+            cases: Vec::with_capacity(CASES.len()),
+        }
+    }
+
+    /// Runs one case and records its outcome, returning what it produced if it passed.
+    async fn case<T>(&mut self, name: &'static str, flow: impl Future<Output = anyhow::Result<T>>) -> Option<T> {
+        let started = Instant::now();
+        let result = flow.await;
+        let duration_ms = millis(started.elapsed());
+        let outcome = match &result {
+            Ok(_) => {
+                tracing::info!(case = name, duration_ms, "Conformance case passed");
+                Outcome::Passed
+            }
+            Err(e) => {
+                let message = format!("{e:#}");
+                tracing::error!(case = name, duration_ms, error = %message, "Conformance case failed");
+                Outcome::Failed { message }
+            }
+        };
+        self.cases.push(CaseResult { name, outcome, duration_ms });
+        result.ok()
+    }
+
+    /// Marks the cases that never ran as skipped and stamps the total duration.
+    fn finish(mut self, started: Instant) -> Self {
+        for name in CASES {
+            if !self.cases.iter().any(|case| case.name == name) {
+                tracing::warn!(case = name, "Conformance case skipped");
+                let reason = "A case it depends on failed".to_string();
+                self.cases.push(CaseResult { name, outcome: Outcome::Skipped { reason }, duration_ms: 0 });
+            }
+        }
+        self.cases.sort_by_key(|case| CASES.iter().position(|name| *name == case.name));
+        self.duration_ms = millis(started.elapsed());
+        self
+    }
+
+    #[must_use]
+    pub fn failures(&self) -> usize {
+        self.cases.iter().filter(|case| matches!(case.outcome, Outcome::Failed { .. })).count()
+    }
+
+    #[must_use]
+    pub fn skipped(&self) -> usize {
+        self.cases.iter().filter(|case| matches!(case.outcome, Outcome::Skipped { .. })).count()
+    }
+
+    /// Renders the report in `format`.
+    ///
+    /// # Errors
+    /// Returns an error if the report cannot be serialized.
+    pub fn render(&self, format: ReportFormat) -> anyhow::Result<String> {
+        match format {
+            ReportFormat::Json => Ok(serde_json::to_string_pretty(self)?),
+            ReportFormat::Junit => Ok(self.to_junit()),
+        }
+    }
+
+    fn to_junit(&self) -> String {
+        let counts = format!(
+            r#"tests="{}" failures="{}" skipped="{}" time="{}""#,
+            self.cases.len(),
+            self.failures(),
+            self.skipped(),
+            seconds(self.duration_ms)
+        );
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str(&format!("<testsuites {counts}>\n"));
+        xml.push_str(&format!(
+            "  <testsuite name=\"obscura-conformance\" {counts} timestamp=\"{}\" hostname=\"{}\">\n",
+            escape_xml(&self.started_at),
+            escape_xml(&self.base_url)
+        ));
+        for case in &self.cases {
+            let open = format!(
+                "    <testcase classname=\"conformance\" name=\"{}\" time=\"{}\"",
+                case.name,
+                seconds(case.duration_ms)
+            );
+            match &case.outcome {
+                Outcome::Passed => xml.push_str(&format!("{open}/>\n")),
+                Outcome::Failed { message } => xml.push_str(&format!(
+                    "{open}>\n      <failure message=\"{}\"/>\n    </testcase>\n",
+                    escape_xml(message)
+                )),
+                Outcome::Skipped { reason } => xml.push_str(&format!(
+                    "{open}>\n      <skipped message=\"{}\"/>\n    </testcase>\n",
+                    escape_xml(reason)
+                )),
+            }
+        }
+        xml.push_str("  </testsuite>\n</testsuites>\n");
+        xml
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+fn seconds(millis: u64) -> String {
+    format!("{}.{:03}", millis / 1000, millis % 1000)
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\n' => escaped.push_str("&#10;"),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A throwaway account with one device, as the suite registered it.
+#[derive(Debug, Clone)]
+struct Participant {
+    username: String,
+    password: String,
+    user_id: Uuid,
+    device_id: Uuid,
+    token: String,
+    identity_key: String,
+    signed_pre_key: Value,
+}
+
+/// A message as it arrived on the gateway.
+#[derive(Debug)]
+struct Delivered {
+    gateway: Gateway,
+    envelope: proto::Envelope,
+}
+
+/// The conformance suite, pointed at one deployment.
+#[derive(Debug)]
+pub struct Suite {
+    client: reqwest::Client,
+    base_url: String,
+    gateway_url: String,
+    /// TLS for `wss` gateway URLs, built with an explicit provider for the same reason as
+    /// the server's own TLS configurations.
+    gateway_tls: Arc<rustls::ClientConfig>,
+    timeout: Duration,
+}
+
+impl Suite {
+    /// Points the suite at the public API under `base_url`. The gateway is reached on the same
+    /// host, over `wss` when the base URL is `https`. Requests follow the outbound settings the
+    /// server uses for its own calls, and the gateway trusts the same CA file.
+    ///
+    /// # Errors
+    /// Returns an error if the base URL is not HTTP(S), the outbound settings are invalid or the
+    /// HTTP client cannot be built.
+    pub fn new(base_url: &str, timeout: Duration, outbound: &OutboundConfig) -> anyhow::Result<Self> {
+        let base_url = base_url.trim_end_matches('/').to_string();
+        let gateway_url = if let Some(host) = base_url.strip_prefix("https://") {
+            format!("wss://{host}/v1/gateway")
+        } else if let Some(host) = base_url.strip_prefix("http://") {
+            format!("ws://{host}/v1/gateway")
+        } else {
+            bail!("Base URL {base_url} must start with http:// or https://");
+        };
+        let client = client_builder(outbound)?
+            .timeout(timeout)
+            .user_agent(concat!("obscura-conformance/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .context("Failed to build the HTTP client")?;
+        let gateway_tls = rustls::ClientConfig::builder_with_provider(tls_provider())
+            .with_safe_default_protocol_versions()
+            .context("Gateway TLS provider supports no protocol version")?
+            .with_root_certificates(root_store(outbound.ca_file.as_deref())?)
+            .with_no_client_auth();
+        Ok(Self { client, base_url, gateway_url, gateway_tls: Arc::new(gateway_tls), timeout })
+    }
+
+    /// Runs every case and reports how each went. Failures are recorded in the report, never
+    /// returned.
+    pub async fn run(&self) -> Report {
+        let started = Instant::now();
+        let mut report = Report::new(&self.base_url);
+
+        let Some((alice, bob)) = report.case("registration", self.registration()).await else {
+            return report.finish(started);
+        };
+        report.case("key_exchange", self.key_exchange(&alice, &bob)).await;
+        if let Some(delivered) = report.case("messaging", self.messaging(&alice, &bob)).await {
+            report.case("acknowledgement", self.acknowledgement(&alice, &bob, delivered)).await;
+        }
+        report.case("backup", self.backup(&alice)).await;
+        report.case("push_token", self.push_token(&bob)).await;
+        report.case("session_refresh", self.session_refresh(&alice)).await;
+        report.case("account_deletion", self.account_deletion(&[&alice, &bob])).await;
+
+        report.finish(started)
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.base_url)
+    }
+
+    async fn registration(&self) -> anyhow::Result<(Participant, Participant)> {
+        let alice = self.register("alice").await.context("Registering the sender")?;
+        let bob = self.register("bob").await.context("Registering the recipient")?;
+        Ok((alice, bob))
+    }
+
+    /// Registers an account and creates its device with a signed pre-key and one one-time
+    /// pre-key.
+    async fn register(&self, role: &str) -> anyhow::Result<Participant> {
+        let username = format!("conformance_{role}_{}", &Uuid::new_v4().simple().to_string()[..12]);
+        let password = Uuid::new_v4().simple().to_string();
+        let resp = self
+            .execute(
+                self.client.post(self.url("/v1/users")).json(&json!({ "username": username, "password": password })),
+            )
+            .await?;
+        let body: Value = expect_status(resp, StatusCode::CREATED, "Registration").await?.json().await?;
+        let user_token = string_field(&body, "token")?;
+        let user_id = token_subject(&user_token)?;
+
+        let identity = rand::random::<[u8; 32]>();
+        let identity_key = STANDARD.encode(montgomery_public_key(identity)?.as_bytes());
+        let signed = montgomery_public_key(rand::random())?;
+        let signature: [u8; 64] = PrivateKey(identity).sign(signed.as_bytes(), &mut rand::rng());
+        let signed_pre_key = json!({
+            "keyId": 1,
+            "publicKey": STANDARD.encode(signed.as_bytes()),
+            "signature": STANDARD.encode(signature),
+        });
+        let one_time_pre_key = montgomery_public_key(rand::random())?;
+        let resp = self
+            .execute(self.client.post(self.url("/v1/devices")).bearer_auth(&user_token).json(&json!({
+                "registrationId": rand::random_range(1..16380),
+                "identityKey": identity_key,
+                "signedPreKey": signed_pre_key,
+                "oneTimePreKeys": [{ "keyId": 1, "publicKey": STANDARD.encode(one_time_pre_key.as_bytes()) }],
+            })))
+            .await?;
+        let body: Value = expect_status(resp, StatusCode::CREATED, "Device creation").await?.json().await?;
+        let device_id = string_field(&body, "deviceId")?.parse().context("Device ID is not a UUID")?;
+
+        Ok(Participant {
+            username,
+            password,
+            user_id,
+            device_id,
+            token: string_field(&body, "token")?,
+            identity_key,
+            signed_pre_key,
+        })
+    }
+
+    /// Fetches the recipient's bundle twice: the first carries the one-time pre-key, the second
+    /// must not hand it out again.
+    async fn key_exchange(&self, alice: &Participant, bob: &Participant) -> anyhow::Result<()> {
+        let first = self.fetch_bundle(alice, bob).await?;
+        ensure!(first["deviceId"] == bob.device_id.to_string(), "Bundle is for device {}", first["deviceId"]);
+        ensure!(first["identityKey"] == bob.identity_key, "Bundle carries a different identity key");
+        ensure!(
+            first["signedPreKey"]["publicKey"] == bob.signed_pre_key["publicKey"]
+                && first["signedPreKey"]["signature"] == bob.signed_pre_key["signature"],
+            "Bundle carries a different signed pre-key"
+        );
+        ensure!(first["oneTimePreKey"]["keyId"] == 1, "Bundle is missing the uploaded one-time pre-key");
+
+        let second = self.fetch_bundle(alice, bob).await?;
+        ensure!(second["oneTimePreKey"].is_null(), "The one-time pre-key was handed out twice");
+        Ok(())
+    }
+
+    async fn fetch_bundle(&self, alice: &Participant, bob: &Participant) -> anyhow::Result<Value> {
+        let resp = self
+            .execute(
+                self.client
+                    .get(self.url(&format!("/v1/users/{}", bob.user_id)))
+                    .bearer_auth(&alice.token)
+                    .header("Accept", "application/json"),
+            )
+            .await?;
+        let bundles: Vec<Value> = expect_status(resp, StatusCode::OK, "Bundle fetch").await?.json().await?;
+        ensure!(bundles.len() == 1, "Expected one bundle, got {}", bundles.len());
+        bundles.into_iter().next().context("Bundle fetch returned no bundles")
+    }
+
+    /// Delivers a message over an open gateway connection and checks what arrives.
+    async fn messaging(&self, alice: &Participant, bob: &Participant) -> anyhow::Result<Delivered> {
+        let mut gateway = self.connect(bob).await?;
+        let content = Uuid::new_v4().as_bytes().to_vec();
+        self.send(alice, bob, &content).await?;
+
+        let envelope = gateway.next_envelope(self.timeout).await?;
+        ensure!(envelope.message == content, "The delivered message has different content");
+        ensure!(envelope.sender_id == alice.user_id.as_bytes(), "The delivered message names another sender");
+        ensure!(
+            envelope.sender_device_id == alice.device_id.as_bytes(),
+            "The delivered message names another sending device"
+        );
+        Ok(Delivered { gateway, envelope })
+    }
+
+    /// Acknowledges the delivered message, leaves a second one unacknowledged and reconnects:
+    /// only the second may be delivered again.
+    async fn acknowledgement(
+        &self,
+        alice: &Participant,
+        bob: &Participant,
+        delivered: Delivered,
+    ) -> anyhow::Result<()> {
+        let Delivered { mut gateway, envelope } = delivered;
+        gateway.ack(envelope.id.clone()).await?;
+
+        let content = Uuid::new_v4().as_bytes().to_vec();
+        self.send(alice, bob, &content).await?;
+        let unacked = gateway.next_envelope(self.timeout).await?;
+        ensure!(unacked.message == content, "The second message has different content");
+        gateway.close().await;
+        tokio::time::sleep(ACK_SETTLE).await;
+
+        let mut gateway = self.connect(bob).await?;
+        let redelivered = gateway.next_envelope(self.timeout).await.context("Unacknowledged message was lost")?;
+        ensure!(redelivered.id != envelope.id, "The acknowledged message was delivered again");
+        ensure!(redelivered.id == unacked.id, "Expected the unacknowledged message to be delivered again");
+        gateway.ack(redelivered.id).await?;
+        gateway.close().await;
+        Ok(())
+    }
+
+    /// Uploads a backup, downloads it again and checks that a stale upload is refused.
+    async fn backup(&self, alice: &Participant) -> anyhow::Result<()> {
+        let content: Vec<u8> = (0..BACKUP_SIZE_BYTES).map(|_| rand::random()).collect();
+        let resp = self
+            .execute(
+                self.client
+                    .post(self.url("/v1/backup"))
+                    .bearer_auth(&alice.token)
+                    .header("If-None-Match", "*")
+                    .body(content.clone()),
+            )
+            .await?;
+        let resp = expect_status(resp, StatusCode::OK, "Backup upload").await?;
+        let etag = resp.headers().get("ETag").context("Backup upload returned no ETag")?.to_str()?.to_string();
+
+        let resp = self.execute(self.client.get(self.url("/v1/backup")).bearer_auth(&alice.token)).await?;
+        let resp = expect_status(resp, StatusCode::OK, "Backup download").await?;
+        ensure!(
+            resp.headers().get("ETag").and_then(|v| v.to_str().ok()) == Some(etag.as_str()),
+            "Backup download returned a different ETag than the upload"
+        );
+        ensure!(resp.bytes().await?.as_ref() == content.as_slice(), "The downloaded backup has different content");
+
+        let resp = self
+            .execute(
+                self.client
+                    .post(self.url("/v1/backup"))
+                    .bearer_auth(&alice.token)
+                    .header("If-None-Match", "*")
+                    .body(content),
+            )
+            .await?;
+        ensure!(
+            resp.status().is_client_error(),
+            "A second first-time upload was accepted with {} instead of being refused",
+            resp.status()
+        );
+        Ok(())
+    }
+
+    /// Registers and replaces a push token, and checks that a blank one is refused.
+    async fn push_token(&self, bob: &Participant) -> anyhow::Result<()> {
+        for token in [format!("conformance-{}", Uuid::new_v4()), format!("conformance-{}", Uuid::new_v4())] {
+            let resp = self
+                .execute(
+                    self.client
+                        .put(self.url("/v1/push-tokens"))
+                        .bearer_auth(&bob.token)
+                        .json(&json!({ "token": token })),
+                )
+                .await?;
+            expect_status(resp, StatusCode::OK, "Push token registration").await?;
+        }
+        let resp = self
+            .execute(
+                self.client.put(self.url("/v1/push-tokens")).bearer_auth(&bob.token).json(&json!({ "token": " " })),
+            )
+            .await?;
+        ensure!(resp.status().is_client_error(), "A blank push token was accepted with {}", resp.status());
+        Ok(())
+    }
+
+    /// Signs in on a second session, rotates its refresh token and signs out again. Refresh
+    /// tokens that were rotated or signed out must be refused.
+    async fn session_refresh(&self, alice: &Participant) -> anyhow::Result<()> {
+        let resp = self
+            .execute(self.client.post(self.url("/v1/sessions")).json(&json!({
+                "username": alice.username,
+                "password": alice.password,
+                "deviceId": alice.device_id.to_string(),
+            })))
+            .await?;
+        let session: Value = expect_status(resp, StatusCode::OK, "Login").await?.json().await?;
+        let first = string_field(&session, "refreshToken")?;
+
+        let resp = self.refresh(&first).await?;
+        let session: Value = expect_status(resp, StatusCode::OK, "Refresh").await?.json().await?;
+        let (token, second) = (string_field(&session, "token")?, string_field(&session, "refreshToken")?);
+        ensure!(second != first, "Refresh returned the same refresh token");
+
+        let resp = self.refresh(&first).await?;
+        expect_status(resp, StatusCode::UNAUTHORIZED, "Refresh with a rotated token").await?;
+
+        // Reusing a rotated token may revoke the whole session, so sign in again to sign out
+        let resp = self
+            .execute(
+                self.client
+                    .post(self.url("/v1/sessions"))
+                    .json(&json!({ "username": alice.username, "password": alice.password })),
+            )
+            .await?;
+        let session: Value = expect_status(resp, StatusCode::OK, "Login").await?.json().await?;
+        let third = string_field(&session, "refreshToken")?;
+        let resp = self
+            .execute(
+                self.client
+                    .delete(self.url("/v1/sessions"))
+                    .bearer_auth(&token)
+                    .json(&json!({ "refreshToken": third })),
+            )
+            .await?;
+        expect_status(resp, StatusCode::OK, "Logout").await?;
+
+        let resp = self.refresh(&third).await?;
+        expect_status(resp, StatusCode::UNAUTHORIZED, "Refresh after logout").await?;
+        Ok(())
+    }
+
+    async fn refresh(&self, refresh_token: &str) -> anyhow::Result<reqwest::Response> {
+        self.execute(self.client.post(self.url("/v1/sessions/refresh")).json(&json!({ "refreshToken": refresh_token })))
+            .await
+    }
+
+    /// Sends a request, waiting out rate limits: auth routes allow only a few requests per
+    /// address, and the suite is not the place to fail on them.
+    async fn execute(&self, request: reqwest::RequestBuilder) -> anyhow::Result<reqwest::Response> {
+        for _ in 0..RATE_LIMIT_RETRIES {
+            let attempt = request.try_clone().context("Request body cannot be retried")?;
+            let resp = attempt.send().await?;
+            if resp.status() != StatusCode::TOO_MANY_REQUESTS {
+                return Ok(resp);
+            }
+            let retry_after = resp
+                .headers()
+                .get("Retry-After")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1)
+                .min(self.timeout.as_secs().max(1));
+            tokio::time::sleep(Duration::from_secs(retry_after)).await;
+        }
+        Ok(request.send().await?)
+    }
+
+    /// Deletes the throwaway accounts.
+    async fn account_deletion(&self, participants: &[&Participant]) -> anyhow::Result<()> {
+        for participant in participants {
+            let resp =
+                self.execute(self.client.delete(self.url("/v1/users/me")).bearer_auth(&participant.token)).await?;
+            expect_status(resp, StatusCode::NO_CONTENT, &format!("Deleting {}", participant.username)).await?;
+        }
+        Ok(())
+    }
+
+    async fn send(&self, sender: &Participant, recipient: &Participant, content: &[u8]) -> anyhow::Result<()> {
+        let request = proto::SendMessageRequest {
+            messages: vec![proto::send_message_request::Submission {
+                submission_id: Uuid::new_v4().as_bytes().to_vec(),
+                device_id: recipient.device_id.as_bytes().to_vec(),
+                message: content.to_vec().into(),
+            }],
+        };
+        let resp = self
+            .execute(
+                self.client
+                    .post(self.url("/v1/messages"))
+                    .bearer_auth(&sender.token)
+                    .header("Idempotency-Key", Uuid::new_v4().to_string())
+                    .header("Content-Type", "application/x-protobuf")
+                    .body(request.encode_to_vec()),
+            )
+            .await?;
+        let body = expect_status(resp, StatusCode::OK, "Send").await?.bytes().await?;
+        let response = proto::SendMessageResponse::decode(body).context("Send returned an invalid response")?;
+        if let Some(failed) = response.failed_submissions.first() {
+            bail!("Send rejected the submission with error code {}", failed.error_code);
+        }
+        Ok(())
+    }
+
+    /// Opens a gateway connection with a ticket issued for the participant's device.
+    async fn connect(&self, participant: &Participant) -> anyhow::Result<Gateway> {
+        let resp =
+            self.execute(self.client.post(self.url("/v1/gateway/ticket")).bearer_auth(&participant.token)).await?;
+        let body: Value = expect_status(resp, StatusCode::CREATED, "Gateway ticket").await?.json().await?;
+        let ticket = string_field(&body, "ticket")?;
+        self.open_gateway(&format!("{}?ticket={ticket}", self.gateway_url)).await
+    }
+
+    async fn open_gateway(&self, url: &str) -> anyhow::Result<Gateway> {
+        let connector = Connector::Rustls(Arc::clone(&self.gateway_tls));
+        let connecting = tokio_tungstenite::connect_async_tls_with_config(url, None, false, Some(connector));
+        let (socket, _) = tokio::time::timeout(self.timeout, connecting)
+            .await
+            .context("Timed out connecting to the gateway")?
+            .context("Failed to connect to the gateway")?;
+        Ok(Gateway { socket, pending: VecDeque::new() })
+    }
+}
+
+/// A gateway connection that only cares about envelopes.
+#[derive(Debug)]
+struct Gateway {
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    pending: VecDeque<proto::Envelope>,
+}
+
+impl Gateway {
+    async fn next_envelope(&mut self, timeout: Duration) -> anyhow::Result<proto::Envelope> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            if let Some(envelope) = self.pending.pop_front() {
+                return Ok(envelope);
+            }
+            let message = tokio::time::timeout_at(deadline, self.socket.next())
+                .await
+                .context("Timed out waiting for a message on the gateway")?
+                .context("The gateway closed the connection")?
+                .context("Failed to read from the gateway")?;
+            if let Message::Binary(bytes) = message {
+                let frame = proto::WebSocketFrame::decode(bytes).context("The gateway sent an invalid frame")?;
+                if let Some(proto::web_socket_frame::Payload::EnvelopeBatch(batch)) = frame.payload {
+                    self.pending.extend(batch.envelopes);
+                }
+            }
+        }
+    }
+
+    async fn ack(&mut self, message_id: Vec<u8>) -> anyhow::Result<()> {
+        let ack = proto::AckMessage { message_ids: vec![message_id] };
+        let frame = proto::WebSocketFrame { payload: Some(proto::web_socket_frame::Payload::Ack(ack)) };
+        self.socket.send(Message::Binary(frame.encode_to_vec().into())).await.context("Failed to send an ack")
+    }
+
+    async fn close(mut self) {
+        let _ = self.socket.close(None).await;
+    }
+}
+
+/// Passes the response through if it has the expected status, otherwise fails with the status
+/// and the start of the body.
+async fn expect_status(
+    resp: reqwest::Response,
+    expected: StatusCode,
+    action: &str,
+) -> anyhow::Result<reqwest::Response> {
+    let status = resp.status();
+    if status == expected {
+        return Ok(resp);
+    }
+    let body = resp.text().await.unwrap_or_default();
+    bail!("{action} returned {status} instead of {expected}: {}", body.chars().take(200).collect::<String>());
+}
+
+fn string_field(body: &Value, field: &str) -> anyhow::Result<String> {
+    body[field].as_str().map(str::to_string).with_context(|| format!("Response is missing {field}"))
+}
+
+/// The user ID in an access token's `sub` claim. The signature is the server's business.
+fn token_subject(token: &str) -> anyhow::Result<Uuid> {
+    let payload = token.split('.').nth(1).context("Access token is not a JWT")?;
+    let claims: Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload)?)?;
+    string_field(&claims, "sub")?.parse().context("Access token subject is not a UUID")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> Report {
+        let mut report = Report::new("https://chat.example.com");
+        report.cases.push(CaseResult { name: "registration", outcome: Outcome::Passed, duration_ms: 1250 });
+        report.cases.push(CaseResult {
+            name: "key_exchange",
+            outcome: Outcome::Failed { message: "Bundle fetch returned 502 <html> & \"more\"".to_string() },
+            duration_ms: 7,
+        });
+        report.finish(Instant::now())
+    }
+
+    #[test]
+    fn test_cases_that_never_ran_are_skipped_in_order() {
+        let report = report();
+        let names: Vec<&str> = report.cases.iter().map(|case| case.name).collect();
+        assert_eq!(names, CASES);
+        assert_eq!(report.failures(), 1);
+        assert_eq!(report.skipped(), CASES.len() - 2);
+    }
+
+    #[test]
+    fn test_json_report_tags_each_case() {
+        let json: Value = serde_json::from_str(&report().render(ReportFormat::Json).expect("renders")).expect("valid");
+        assert_eq!(json["baseUrl"], "https://chat.example.com");
+        assert_eq!(json["cases"][0], json!({ "name": "registration", "status": "passed", "durationMs": 1250 }));
+        assert_eq!(json["cases"][1]["status"], "failed");
+        assert_eq!(json["cases"][2]["status"], "skipped");
+    }
+
+    #[test]
+    fn test_junit_report_escapes_messages() {
+        let xml = report().render(ReportFormat::Junit).expect("renders");
+        assert!(xml.contains(r#"<testsuites tests="8" failures="1" skipped="6""#));
+        assert!(xml.contains(r#"<testcase classname="conformance" name="registration" time="1.250"/>"#));
+        assert!(xml.contains(r#"<failure message="Bundle fetch returned 502 &lt;html&gt; &amp; &quot;more&quot;"/>"#));
+    }
+
+    #[test]
+    fn test_gateway_url_follows_the_scheme() {
+        let timeout = Duration::from_secs(1);
+        let outbound = OutboundConfig::default();
+        let suite = Suite::new("https://chat.example.com/", timeout, &outbound).expect("valid");
+        assert_eq!(suite.gateway_url, "wss://chat.example.com/v1/gateway");
+        let suite = Suite::new("http://localhost:3000", timeout, &outbound).expect("valid");
+        assert_eq!(suite.gateway_url, "ws://localhost:3000/v1/gateway");
+        assert!(Suite::new("localhost:3000", timeout, &outbound).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_gateway_connects_over_tls() {
+        // A peer that hangs up mid-handshake: connecting must fail, not panic building the TLS config
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let addr = listener.local_addr().expect("address");
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                drop(stream);
+            }
+        });
+
+        let suite =
+            Suite::new(&format!("https://{addr}"), Duration::from_secs(5), &OutboundConfig::default()).expect("valid");
+        let error = suite.open_gateway(&format!("{}?ticket=t", suite.gateway_url)).await.expect_err("no TLS peer");
+        assert!(format!("{error:#}").contains("Failed to connect"), "{error:#}");
+    }
+}
@@ -0,0 +1,189 @@
+//! Development mode (`--dev`): one binary that brings up a usable server against local
+//! Postgres, Redis and `MinIO`, with a few demo accounts to exercise the full flow.
+
+use crate::Services;
+use crate::config::Config;
+use crate::domain::auth::Jwt;
+use crate::domain::crypto::{DJB_KEY_PREFIX, PublicKey, Signature};
+use crate::domain::keys::{OneTimePreKey, SignedPreKey};
+use crate::error::AppError;
+use crate::telemetry;
+use anyhow::Context;
+use uuid::Uuid;
+use xeddsa::xed25519::PrivateKey;
+use xeddsa::{CalculateKeyPair, Sign};
+
+/// Usernames of the seeded demo accounts.
+pub const DEV_USERS: [&str; 2] = ["alice", "bob"];
+
+/// Password of every seeded demo account.
+pub const DEV_PASSWORD: &str = "obscura-dev-password";
+
+/// One-time pre-keys uploaded for each seeded device.
+const DEV_ONE_TIME_PRE_KEYS: i32 = 20;
+
+/// A seeded demo account and its device.
+#[derive(Debug, Clone)]
+pub struct DevUser {
+    pub username: &'static str,
+    pub user_id: Uuid,
+    pub device_id: Uuid,
+}
+
+/// Points storage at a local `MinIO` with its stock credentials unless an endpoint was given, and
+/// keys identifier hashes with a throwaway secret unless one was given; the database and Redis
+/// defaults already target localhost.
+pub fn apply_defaults(config: &mut Config) {
+    let storage = &mut config.storage;
+    if storage.endpoint.is_none() {
+        storage.endpoint = Some("http://localhost:9000".to_string());
+        storage.access_key.get_or_insert_with(|| "minioadmin".to_string());
+        storage.secret_key.get_or_insert_with(|| "minioadmin".to_string());
+        storage.force_path_style = true;
+    }
+    config.identifiers.hash_secret.get_or_insert_with(|| "obscura-dev-identifier-secret".to_string());
+}
+
+/// Creates the storage bucket unless it already exists.
+///
+/// # Errors
+/// Returns an error if the bucket is missing and cannot be created.
+#[tracing::instrument(skip_all, fields(%bucket))]
+pub async fn ensure_bucket(s3_client: &aws_sdk_s3::Client, bucket: &str) -> anyhow::Result<()> {
+    if s3_client.head_bucket().bucket(bucket).send().await.is_ok() {
+        return Ok(());
+    }
+    s3_client
+        .create_bucket()
+        .bucket(bucket)
+        .send()
+        .await
+        .with_context(|| format!("Failed to create dev bucket {bucket}. Is MinIO running?"))?;
+    tracing::info!(bucket, "Created dev storage bucket");
+    Ok(())
+}
+
+/// Registers the [`DEV_USERS`] with one device each, complete with a signed pre-key and a pool
+/// of one-time pre-keys, so clients can fetch bundles and send to them right away.
+///
+/// Accounts and devices that already exist are reused, so restarting in dev mode keeps their
+/// data.
+///
+/// # Errors
+/// Returns an error if an account or its device cannot be created, or if a demo username
+/// is taken by an account with a different password.
+pub async fn seed(services: &Services) -> anyhow::Result<Vec<DevUser>> {
+    let mut seeded = Vec::with_capacity(DEV_USERS.len());
+    for username in DEV_USERS {
+        let auth = &services.auth_service;
+        let session = match auth.register(username.to_string(), DEV_PASSWORD.to_string()).await {
+            Err(AppError::Conflict(_)) => auth
+                .login(username.to_string(), DEV_PASSWORD.to_string(), None)
+                .await
+                .with_context(|| format!("Dev user {username} exists but does not accept the dev password"))?,
+            session => session.with_context(|| format!("Failed to register dev user {username}"))?,
+        };
+        let user_id = auth.verify_token(&Jwt::new(session.token))?.sub;
+
+        let existing = services.device_service.list_devices(user_id).await?;
+        let device_id = if let Some(device) = existing.first() {
+            device.id
+        } else {
+            let keys = DevKeys::generate().context("Failed to generate dev keys")?;
+            services
+                .device_service
+                .create_device(
+                    user_id,
+                    Some("Seeded device".to_string()),
+                    keys.identity_key,
+                    rand::random_range(1..16380),
+                    keys.signed_pre_key,
+                    keys.one_time_pre_keys,
+                )
+                .await
+                .with_context(|| format!("Failed to create a device for dev user {username}"))?
+                .device_id
+                .context("Device session has no device")?
+        };
+
+        tracing::info!(username, password = DEV_PASSWORD, user.id = %telemetry::id(user_id), device.id = %telemetry::id(device_id), "Dev user ready");
+        seeded.push(DevUser { username, user_id, device_id });
+    }
+    Ok(seeded)
+}
+
+/// Public keys for a seeded device. The private halves are thrown away: nothing can decrypt
+/// messages sent to it, but it answers bundle fetches like a real device.
+struct DevKeys {
+    identity_key: PublicKey,
+    signed_pre_key: SignedPreKey,
+    one_time_pre_keys: Vec<OneTimePreKey>,
+}
+
+impl DevKeys {
+    fn generate() -> anyhow::Result<Self> {
+        let identity = rand::random::<[u8; 32]>();
+        let identity_key = montgomery_public_key(identity)?;
+
+        let signed = montgomery_public_key(rand::random())?;
+        let signature: [u8; 64] = PrivateKey(identity).sign(signed.as_bytes(), &mut rand::rng());
+        let signed_pre_key = SignedPreKey { key_id: 1, public_key: signed, signature: Signature::new(signature) };
+
+        let one_time_pre_keys = (1..=DEV_ONE_TIME_PRE_KEYS)
+            .map(|key_id| Ok(OneTimePreKey { key_id, public_key: montgomery_public_key(rand::random())? }))
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self { identity_key, signed_pre_key, one_time_pre_keys })
+    }
+}
+
+/// The wire-format Montgomery public key for an `XEd25519` private key.
+pub(crate) fn montgomery_public_key(private: [u8; 32]) -> anyhow::Result<PublicKey> {
+    let (_, edwards) = PrivateKey(private).calculate_key_pair(0);
+    let montgomery = curve25519_dalek::edwards::CompressedEdwardsY(edwards)
+        .decompress()
+        .context("Invalid Edwards point")?
+        .to_montgomery()
+        .to_bytes();
+
+    let mut wire = [0u8; 33];
+    wire[0] = DJB_KEY_PREFIX;
+    wire[1..].copy_from_slice(&montgomery);
+    Ok(PublicKey::new(wire))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_defaults_targets_local_minio() {
+        let mut config = Config::default();
+        apply_defaults(&mut config);
+        assert_eq!(config.storage.endpoint.as_deref(), Some("http://localhost:9000"));
+        assert!(config.storage.force_path_style);
+    }
+
+    #[test]
+    fn test_apply_defaults_keeps_explicit_storage() {
+        let mut config = Config::default();
+        config.storage.endpoint = Some("https://s3.example.com".to_string());
+        apply_defaults(&mut config);
+        assert_eq!(config.storage.endpoint.as_deref(), Some("https://s3.example.com"));
+        assert!(config.storage.access_key.is_none());
+    }
+
+    #[test]
+    fn test_generated_keys_are_well_formed() {
+        let keys = DevKeys::generate().expect("keys generate");
+        assert_eq!(keys.identity_key.as_bytes()[0], DJB_KEY_PREFIX);
+        assert_eq!(keys.one_time_pre_keys.len(), 20);
+        crate::services::crypto_service::CryptoService::default()
+            .verify_signature(
+                &keys.identity_key,
+                keys.signed_pre_key.public_key.as_bytes(),
+                &keys.signed_pre_key.signature,
+            )
+            .expect("signed pre-key verifies against the identity key");
+    }
+}
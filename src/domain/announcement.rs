@@ -0,0 +1,58 @@
+use std::str::FromStr;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+/// What an announcement is about, so clients can choose how prominently to show it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnnouncementKind {
+    Notice,
+    Maintenance,
+    Deprecation,
+}
+
+impl std::fmt::Display for AnnouncementKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Notice => write!(f, "NOTICE"),
+            Self::Maintenance => write!(f, "MAINTENANCE"),
+            Self::Deprecation => write!(f, "DEPRECATION"),
+        }
+    }
+}
+
+impl FromStr for AnnouncementKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "NOTICE" => Ok(Self::Notice),
+            "MAINTENANCE" => Ok(Self::Maintenance),
+            "DEPRECATION" => Ok(Self::Deprecation),
+            _ => Err(format!("Unknown announcement kind: {s}")),
+        }
+    }
+}
+
+/// An announcement as broadcast, with the Ed25519 signature clients check before showing it.
+#[derive(Debug, Clone)]
+pub struct Announcement {
+    pub id: Uuid,
+    pub kind: AnnouncementKind,
+    pub body: String,
+    pub created_at: OffsetDateTime,
+    pub expires_at: OffsetDateTime,
+    pub signature: Vec<u8>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kind_round_trips_through_display() {
+        for kind in [AnnouncementKind::Notice, AnnouncementKind::Maintenance, AnnouncementKind::Deprecation] {
+            assert_eq!(kind.to_string().parse::<AnnouncementKind>(), Ok(kind));
+        }
+        assert!("maintenance".parse::<AnnouncementKind>().is_err());
+    }
+}
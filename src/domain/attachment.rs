@@ -5,6 +5,8 @@ use uuid::Uuid;
 pub struct Attachment {
     pub id: Uuid,
     pub expires_at: OffsetDateTime,
+    /// SHA-256 of the content, set when the object is stored content-addressed and may be shared.
+    pub content_digest: Option<[u8; 32]>,
 }
 
 impl Attachment {
@@ -12,4 +14,16 @@ impl Attachment {
     pub fn is_expired_at(&self, now: OffsetDateTime) -> bool {
         self.expires_at < now
     }
+
+    #[must_use]
+    pub fn storage_key(&self, prefix: &str) -> String {
+        storage_key(prefix, self.id, self.content_digest.as_ref())
+    }
+}
+
+/// Object key of an attachment: derived from the content digest when there is one, so identical
+/// uploads land on the same object, and from the attachment ID otherwise.
+#[must_use]
+pub fn storage_key(prefix: &str, id: Uuid, content_digest: Option<&[u8; 32]>) -> String {
+    content_digest.map_or_else(|| format!("{prefix}{id}"), |digest| format!("{prefix}sha256/{}", hex::encode(digest)))
 }
@@ -1,3 +1,4 @@
+use crate::domain::user::UserTier;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -7,11 +8,14 @@ pub(crate) struct Claims {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub device_id: Option<Uuid>,
     pub exp: usize,
+    /// The user's tier when the token was issued. A tier change applies from the next refresh.
+    #[serde(default, skip_serializing_if = "UserTier::is_free")]
+    pub tier: UserTier,
 }
 impl Claims {
     #[must_use]
-    pub(crate) const fn new(user_id: Uuid, device_id: Option<Uuid>, exp: usize) -> Self {
-        Self { sub: user_id, device_id, exp }
+    pub(crate) const fn new(user_id: Uuid, device_id: Option<Uuid>, exp: usize, tier: UserTier) -> Self {
+        Self { sub: user_id, device_id, exp, tier }
     }
 }
 
@@ -77,16 +81,17 @@ mod tests {
     fn test_claims_new_with_device_id() {
         let user_id = Uuid::new_v4();
         let device_id = Uuid::new_v4();
-        let claims = Claims::new(user_id, Some(device_id), 3600);
+        let claims = Claims::new(user_id, Some(device_id), 3600, UserTier::Paid);
         assert_eq!(claims.sub, user_id);
         assert_eq!(claims.device_id, Some(device_id));
         assert_eq!(claims.exp, 3600);
+        assert_eq!(claims.tier, UserTier::Paid);
     }
 
     #[test]
     fn test_claims_new_without_device_id() {
         let user_id = Uuid::new_v4();
-        let claims = Claims::new(user_id, None, 7200);
+        let claims = Claims::new(user_id, None, 7200, UserTier::Free);
         assert_eq!(claims.sub, user_id);
         assert_eq!(claims.device_id, None);
         assert_eq!(claims.exp, 7200);
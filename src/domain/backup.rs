@@ -36,6 +36,12 @@ pub struct Backup {
     pub state: BackupState,
     pub updated_at: OffsetDateTime,
     pub pending_at: Option<OffsetDateTime>,
+    /// The version replaced by the last upload or restore, restorable until the window passes.
+    pub previous_version: Option<i32>,
+    /// When `previous_version` was replaced.
+    pub deleted_at: Option<OffsetDateTime>,
+    /// Size of the current version, unknown for versions uploaded before sizes were recorded.
+    pub size_bytes: Option<i64>,
 }
 
 #[cfg(test)]
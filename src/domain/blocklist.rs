@@ -0,0 +1,59 @@
+use ipnetwork::IpNetwork;
+use time::OffsetDateTime;
+
+/// Where a blocklist entry was defined. File entries can only be removed by editing the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlocklistSource {
+    File,
+    Managed,
+}
+
+impl std::fmt::Display for BlocklistSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::File => write!(f, "FILE"),
+            Self::Managed => write!(f, "MANAGED"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BlockedNetwork {
+    pub network: IpNetwork,
+    pub reason: Option<String>,
+    pub source: BlocklistSource,
+    pub created_at: Option<OffsetDateTime>,
+}
+
+/// Parses a CIDR or a bare address (treated as a single host) into its canonical network form,
+/// clearing any host bits so that `10.1.2.3/8` and `10.0.0.0/8` name the same entry.
+///
+/// # Errors
+/// Returns a message describing the problem if the input is not a valid address or CIDR.
+pub fn parse_network(input: &str) -> Result<IpNetwork, String> {
+    let parsed: IpNetwork = input.trim().parse().map_err(|_| format!("Invalid network: {input}"))?;
+    IpNetwork::new(parsed.network(), parsed.prefix()).map_err(|e| format!("Invalid network {input}: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_network_clears_host_bits() {
+        let network = parse_network("10.1.2.3/8").expect("valid CIDR");
+        assert_eq!(network.to_string(), "10.0.0.0/8");
+    }
+
+    #[test]
+    fn test_parse_network_bare_address_is_single_host() {
+        assert_eq!(parse_network("203.0.113.7").expect("valid IP").to_string(), "203.0.113.7/32");
+        assert_eq!(parse_network(" 2001:db8::1 ").expect("valid IP").to_string(), "2001:db8::1/128");
+    }
+
+    #[test]
+    fn test_parse_network_rejects_garbage() {
+        assert!(parse_network("not-an-ip").is_err());
+        assert!(parse_network("10.0.0.0/33").is_err());
+    }
+}
@@ -1,8 +1,20 @@
 /// Prefix byte used by Signal/DJB for Montgomery (X25519) keys.
 pub const DJB_KEY_PREFIX: u8 = 0x05;
 
+/// Prefix byte for Edwards (Ed25519) identity keys, uploaded by clients that sign with plain Ed25519.
+pub const ED25519_KEY_PREFIX: u8 = 0x06;
+
+/// The curve form of a public key, taken from its prefix byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyType {
+    /// Montgomery key; signatures by the matching identity are `XEdDSA`.
+    Curve25519,
+    /// Edwards key; signatures are standard Ed25519.
+    Ed25519,
+}
+
 /// Strong type for public keys.
-/// We store the full 33-byte wire format (`DJB_KEY_PREFIX` + 32-byte Montgomery key).
+/// We store the full 33-byte wire format (prefix byte + 32-byte key).
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PublicKey([u8; 33]);
 
@@ -26,16 +38,21 @@ impl PublicKey {
         &self.0
     }
 
-    /// Tries to create a `PublicKey` from wire bytes (MUST be 33 bytes with 0x05 prefix).
+    #[must_use]
+    pub const fn key_type(&self) -> KeyType {
+        if self.0[0] == ED25519_KEY_PREFIX { KeyType::Ed25519 } else { KeyType::Curve25519 }
+    }
+
+    /// Tries to create a `PublicKey` from wire bytes (MUST be 33 bytes with a 0x05 or 0x06 prefix).
     ///
     /// # Errors
     /// Returns an error if the length is not 33 bytes or the prefix is incorrect.
     pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, String> {
         if bytes.len() != 33 {
-            return Err(format!("Invalid key length: {} (expected 33 bytes with 0x05 prefix)", bytes.len()));
+            return Err(format!("Invalid key length: {} (expected 33 bytes with a type prefix)", bytes.len()));
         }
-        if bytes[0] != DJB_KEY_PREFIX {
-            return Err(format!("Invalid key prefix (expected 0x{DJB_KEY_PREFIX:02x})"));
+        if bytes[0] != DJB_KEY_PREFIX && bytes[0] != ED25519_KEY_PREFIX {
+            return Err(format!("Invalid key prefix (expected 0x{DJB_KEY_PREFIX:02x} or 0x{ED25519_KEY_PREFIX:02x})"));
         }
         let mut arr = [0u8; 33];
         arr.copy_from_slice(bytes);
@@ -134,6 +151,15 @@ mod tests {
         assert!(result.expect_err("should fail for wrong prefix").contains("Invalid key prefix"));
     }
 
+    #[test]
+    fn test_public_key_type_from_prefix() {
+        let mut bytes = [0u8; 33];
+        bytes[0] = DJB_KEY_PREFIX;
+        assert_eq!(PublicKey::try_from_bytes(&bytes).expect("Valid Curve25519 key").key_type(), KeyType::Curve25519);
+        bytes[0] = ED25519_KEY_PREFIX;
+        assert_eq!(PublicKey::try_from_bytes(&bytes).expect("Valid Ed25519 key").key_type(), KeyType::Ed25519);
+    }
+
     #[test]
     fn test_public_key_from_vec() {
         let mut bytes = vec![0u8; 33];
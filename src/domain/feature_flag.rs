@@ -0,0 +1,88 @@
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+/// Longest flag name, which also keeps the evaluation metric's label short.
+pub const MAX_FLAG_NAME_LEN: usize = 64;
+
+/// Wakes a device running low on pre-keys with the dedicated `refill_prekeys` push rather than an
+/// ordinary check, for users whose clients understand it. Evaluated per key owner.
+pub const PREKEY_REFILL_PUSH: &str = "prekey_refill_push";
+
+/// A server-side switch for rolling a feature out without a restart.
+///
+/// An enabled flag is on for `rollout_percent` of subjects. Each subject falls in a fixed bucket
+/// per flag, so raising the percentage only ever adds subjects, and different flags reach
+/// different cohorts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeatureFlag {
+    pub name: String,
+    pub enabled: bool,
+    pub rollout_percent: u8,
+    pub description: Option<String>,
+    pub updated_at: OffsetDateTime,
+}
+
+impl FeatureFlag {
+    /// Whether the flag is on for `subject`, usually a user ID.
+    #[must_use]
+    pub fn is_enabled_for(&self, subject: Uuid) -> bool {
+        self.enabled && (self.rollout_percent >= 100 || bucket(&self.name, subject) < self.rollout_percent)
+    }
+}
+
+/// The subject's bucket for a flag, from 0 to 99.
+fn bucket(name: &str, subject: Uuid) -> u8 {
+    let digest = Sha256::new().chain_update(name.as_bytes()).chain_update(subject.as_bytes()).finalize();
+    let value = u16::from_be_bytes([digest[0], digest[1]]) % 100;
+    u8::try_from(value).unwrap_or_default()
+}
+
+/// Checks that a flag name is lowercase ASCII letters, digits and underscores.
+///
+/// # Errors
+/// Returns a message describing why the name is not valid.
+pub fn validate_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > MAX_FLAG_NAME_LEN {
+        return Err(format!("Flag name must be 1 to {MAX_FLAG_NAME_LEN} characters"));
+    }
+    if !name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_') {
+        return Err("Flag name may only contain lowercase letters, digits and underscores".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flag(enabled: bool, rollout_percent: u8) -> FeatureFlag {
+        FeatureFlag {
+            name: "sealed_sender".to_string(),
+            enabled,
+            rollout_percent,
+            description: None,
+            updated_at: OffsetDateTime::UNIX_EPOCH,
+        }
+    }
+
+    #[test]
+    fn test_rollout_is_stable_and_monotonic() {
+        let subjects: Vec<Uuid> = (0..1000).map(|_| Uuid::new_v4()).collect();
+        let on_at = |percent| subjects.iter().filter(|s| flag(true, percent).is_enabled_for(**s)).count();
+
+        assert_eq!(on_at(0), 0);
+        assert_eq!(on_at(100), subjects.len());
+        assert!((350..=650).contains(&on_at(50)), "about half the subjects should be on at 50%");
+        assert!(subjects.iter().all(|s| !flag(true, 20).is_enabled_for(*s) || flag(true, 60).is_enabled_for(*s)));
+        assert!(subjects.iter().all(|s| !flag(false, 100).is_enabled_for(*s)));
+    }
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("ack_response_frames_v2").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("Sealed-Sender").is_err());
+        assert!(validate_name(&"a".repeat(MAX_FLAG_NAME_LEN + 1)).is_err());
+    }
+}
@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+/// Longest email address SMTP can deliver to (RFC 5321).
+pub const MAX_EMAIL_LEN: usize = 254;
+
+/// The kind of out-of-band identifier an account can bind. An account binds at most one of each.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdentifierKind {
+    Email,
+    Phone,
+}
+
+impl IdentifierKind {
+    /// The stored form, also used as a metric label and path segment.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Email => "email",
+            Self::Phone => "phone",
+        }
+    }
+
+    /// Brings an identifier to the one form it is hashed in, so that the same address or number
+    /// always hashes the same way.
+    ///
+    /// Emails are trimmed and lowercased, and must have a local part and a dotted domain. Phone
+    /// numbers must be in E.164 form; spaces, dashes and parentheses are dropped first.
+    ///
+    /// # Errors
+    /// Returns a message describing why the value is not a valid identifier of this kind.
+    pub fn normalize(self, raw: &str) -> Result<String, String> {
+        match self {
+            Self::Email => {
+                let email = raw.trim().to_lowercase();
+                let valid = email.len() <= MAX_EMAIL_LEN
+                    && !email.chars().any(char::is_whitespace)
+                    && email.split_once('@').is_some_and(|(local, domain)| {
+                        !local.is_empty()
+                            && !domain.contains('@')
+                            && domain.split('.').count() >= 2
+                            && domain.split('.').all(|label| !label.is_empty())
+                    });
+                if valid { Ok(email) } else { Err("Email address is not valid".to_string()) }
+            }
+            Self::Phone => {
+                let phone: String = raw.chars().filter(|c| !matches!(c, ' ' | '-' | '(' | ')')).collect();
+                let digits = phone.strip_prefix('+').unwrap_or_default();
+                let valid = (8..=15).contains(&digits.len())
+                    && !digits.starts_with('0')
+                    && digits.chars().all(|c| c.is_ascii_digit());
+                if valid { Ok(phone) } else { Err("Phone number must be in E.164 form, e.g. +15551234567".to_string()) }
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for IdentifierKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for IdentifierKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "email" => Ok(Self::Email),
+            "phone" => Ok(Self::Phone),
+            _ => Err(format!("Invalid identifier kind: {s}")),
+        }
+    }
+}
+
+/// An identifier bound to an account. The identifier itself is never stored, only its keyed hash,
+/// so this carries no value to show back.
+#[derive(Debug, Clone)]
+pub struct Identifier {
+    pub kind: IdentifierKind,
+    pub discoverable: bool,
+    pub verified_at: Option<OffsetDateTime>,
+    pub created_at: OffsetDateTime,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_email_normalization() {
+        assert_eq!(IdentifierKind::Email.normalize("  Alice@Example.ORG ").as_deref(), Ok("alice@example.org"));
+        assert!(IdentifierKind::Email.normalize("alice@localhost").is_err());
+        assert!(IdentifierKind::Email.normalize("alice@@example.org").is_err());
+        assert!(IdentifierKind::Email.normalize("al ice@example.org").is_err());
+        assert!(IdentifierKind::Email.normalize("@example.org").is_err());
+    }
+
+    #[test]
+    fn test_phone_normalization() {
+        assert_eq!(IdentifierKind::Phone.normalize("+1 (555) 123-4567").as_deref(), Ok("+15551234567"));
+        assert!(IdentifierKind::Phone.normalize("5551234567").is_err());
+        assert!(IdentifierKind::Phone.normalize("+0551234567").is_err());
+        assert!(IdentifierKind::Phone.normalize("+1555123456789012").is_err());
+    }
+}
@@ -0,0 +1,11 @@
+use time::OffsetDateTime;
+
+/// A running server instance, as it last announced itself to its peers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstanceInfo {
+    pub id: String,
+    pub version: String,
+    pub started_at: OffsetDateTime,
+    pub active_sessions: usize,
+    pub heartbeat_at: OffsetDateTime,
+}
@@ -1,4 +1,5 @@
 use crate::domain::crypto::{PublicKey, Signature};
+use time::OffsetDateTime;
 use uuid::Uuid;
 
 #[derive(Debug, Clone)]
@@ -23,8 +24,73 @@ pub struct PreKeyBundle {
     pub one_time_pre_key: Option<OneTimePreKey>,
 }
 
+/// Holds the one-time pre-keys handed out by a prefetch until the sender redeems `token`.
+#[derive(Debug, Clone, Copy)]
+pub struct PreKeyReservation {
+    pub token: Uuid,
+    pub expires_at: OffsetDateTime,
+}
+
+/// What a bundle fetch does with the one-time pre-key it hands out.
+#[derive(Debug, Clone, Copy)]
+pub enum OneTimePreKeyClaim<'a> {
+    /// Deletes it, so no other sender gets the same key.
+    Consume,
+    /// Holds it until the prefetching sender redeems the reservation.
+    Reserve(&'a PreKeyReservation),
+    /// Hands out none and leaves the pool untouched, for when the database takes no writes.
+    /// Senders can still start a session from the signed pre-key alone.
+    Skip,
+}
+
+/// One page of the prekey pool sample: the users it walked and those of them running low.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PreKeyPoolPage {
+    pub scanned_users: u64,
+    /// Highest user ID in the page, where the next page starts; `None` once no users are left.
+    pub last_user_id: Option<Uuid>,
+    pub users_below_threshold: u64,
+    pub devices_below_threshold: u64,
+}
+
 #[derive(Debug, Clone)]
 pub struct PreKeyStatus {
     pub one_time_pre_key_count: i32,
     pub min_threshold: i32,
+    /// Most one-time pre-keys the server keeps for a device; uploads beyond it evict the oldest keys.
+    pub max_pre_keys: i32,
+    /// Keys to upload to fill the pool without evicting any, limited to what one request accepts.
+    pub recommended_upload_count: i32,
+}
+
+/// The part of a key upload a [`KeyIssue`] refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyField {
+    IdentityKey,
+    RegistrationId,
+    SignedPreKey,
+    OneTimePreKeys,
+}
+
+/// One reason a key upload would be rejected. `key_id` names the offending pre-key, if any.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyIssue {
+    pub field: KeyField,
+    pub key_id: Option<i32>,
+    pub message: String,
+}
+
+impl KeyIssue {
+    #[must_use]
+    pub fn new(field: KeyField, key_id: Option<i32>, message: impl Into<String>) -> Self {
+        Self { field, key_id, message: message.into() }
+    }
+}
+
+/// The outcome of validating a key upload without applying it.
+#[derive(Debug, Clone, Default)]
+pub struct KeyValidationReport {
+    /// Whether the upload would replace the device's identity key.
+    pub is_takeover: bool,
+    pub issues: Vec<KeyIssue>,
 }
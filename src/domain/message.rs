@@ -1,29 +1,150 @@
+use bytes::Bytes;
 use time::OffsetDateTime;
 use uuid::Uuid;
 
 #[derive(Debug, Clone)]
 pub(crate) struct Message {
     pub id: Uuid,
-    pub sender_id: Uuid,
-    pub sender_device_id: Uuid,
+    /// `None` for system envelopes, which the server queues on its own behalf.
+    pub sender_id: Option<Uuid>,
+    pub sender_device_id: Option<Uuid>,
+    /// Empty until loaded from object storage when `offloaded` is set.
     pub content: Vec<u8>,
     pub created_at: Option<OffsetDateTime>,
+    pub offloaded: bool,
+    /// Position in the recipient's inbox, used as the delivery cursor.
+    pub seq: i64,
+    /// Position among the messages from the same sender device. `None` for system envelopes.
+    pub pair_seq: Option<i64>,
+    /// What a system envelope carries. `None` for messages from users.
+    pub system_code: Option<SystemCode>,
 }
 
 impl Message {}
 
+/// Kind of system envelope, stored as a small integer alongside the message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SystemCode {
+    /// A signed operator announcement.
+    Announcement = 1,
+    /// An account the sender had pending messages for was deleted.
+    RecipientGone = 2,
+    /// A notice sealed by an external service and queued through the inbound webhook.
+    ServiceMessage = 3,
+}
+
+impl TryFrom<i16> for SystemCode {
+    type Error = ();
+
+    fn try_from(value: i16) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::Announcement),
+            2 => Ok(Self::RecipientGone),
+            3 => Ok(Self::ServiceMessage),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Where the ciphertext of a message being queued is kept.
+#[derive(Debug, Clone)]
+pub(crate) enum MessagePayload {
+    Inline(Bytes),
+    /// Stored in object storage under the message ID, which is assigned before the upload.
+    Offloaded {
+        message_id: Uuid,
+        size: i32,
+    },
+}
+
+/// Object storage key of an offloaded message payload.
+#[must_use]
+pub(crate) fn payload_storage_key(prefix: &str, message_id: Uuid) -> String {
+    format!("{prefix}{message_id}")
+}
+
+/// Messages removed by one expiry pass. Those still unacknowledged are split by whether they
+/// were ever written to one of the device's sessions and, if so, by the transport that wrote them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExpiredMessages {
+    pub total: u64,
+    pub unacked_delivered_push: u64,
+    pub unacked_delivered_websocket: u64,
+    pub unacked_undelivered: u64,
+}
+
+/// Shape of a device's pending queue, without any message content.
+#[derive(Debug, Clone)]
+pub struct InboxSummary {
+    pub device_id: Uuid,
+    pub pending_messages: i64,
+    pub pending_bytes: i64,
+    pub oldest_message_at: Option<OffsetDateTime>,
+    pub next_expiry_at: Option<OffsetDateTime>,
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct RawSubmission {
     pub submission_id: Vec<u8>,
     pub device_id: Vec<u8>,
-    pub message: Vec<u8>,
+    /// A slice of the request body, which is not copied.
+    pub message: Bytes,
+}
+
+impl RawSubmission {
+    /// Checks the structure of the submission, returning its submission ID, target device ID and
+    /// message. Whether the device exists is checked separately.
+    ///
+    /// # Errors
+    /// Returns the rejection to report if either ID is not 16 bytes or the message is empty.
+    pub(crate) fn validate(self) -> Result<(Uuid, Uuid, Bytes), FailedSubmission> {
+        let Ok(submission_id) = Uuid::from_slice(&self.submission_id) else {
+            return Err(FailedSubmission {
+                submission_id: self.submission_id,
+                error_code: SubmissionErrorCode::MalformedSubmissionId,
+                error_message: "Invalid submission_id UUID bytes (expected 16)".to_string(),
+            });
+        };
+
+        let Ok(device_id) = Uuid::from_slice(&self.device_id) else {
+            return Err(FailedSubmission {
+                submission_id: self.submission_id,
+                error_code: SubmissionErrorCode::MalformedDeviceId,
+                error_message: "Invalid device_id UUID bytes (expected 16)".to_string(),
+            });
+        };
+
+        if self.message.is_empty() {
+            return Err(FailedSubmission {
+                submission_id: self.submission_id,
+                error_code: SubmissionErrorCode::MessageMissing,
+                error_message: "Missing message payload".to_string(),
+            });
+        }
+
+        Ok((submission_id, device_id, self.message))
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct SubmissionOutcome {
+    pub receipts: Vec<SubmissionReceipt>,
     pub failed_submissions: Vec<FailedSubmission>,
 }
 
+/// Server-assigned identity and position of an accepted submission.
+#[derive(Debug, Clone)]
+pub struct SubmissionReceipt {
+    pub submission_id: Uuid,
+    pub message_id: Uuid,
+    pub device_id: Uuid,
+    /// Authoritative time the message was queued.
+    pub timestamp: OffsetDateTime,
+    /// Position in the recipient device's inbox. Increases with every message queued for the
+    /// device, but is not contiguous. `0` for messages queued before sequencing existed.
+    pub sequence: i64,
+}
+
 #[derive(Debug, Clone)]
 pub struct FailedSubmission {
     pub submission_id: Vec<u8>, // Use raw bytes to preserve whatever the client sent
@@ -0,0 +1,83 @@
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+/// One message as the metadata index records it: the parties, when it was sent and how large
+/// it was. Content is never part of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageMetadata {
+    pub message_id: Uuid,
+    pub sender_id: Uuid,
+    pub sender_device_id: Uuid,
+    pub recipient_id: Uuid,
+    pub recipient_device_id: Uuid,
+    pub sent_at: OffsetDateTime,
+    pub size_bytes: i32,
+}
+
+/// What an operator query asks the index for. Every query names at least one account, so the
+/// index cannot be browsed wholesale.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetadataQuery {
+    pub sender_id: Option<Uuid>,
+    pub recipient_id: Option<Uuid>,
+    pub since: Option<OffsetDateTime>,
+    pub until: Option<OffsetDateTime>,
+}
+
+impl MetadataQuery {
+    /// Checks that the query names an account and that its time range is not inverted.
+    ///
+    /// # Errors
+    /// Returns a message describing the problem.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.sender_id.is_none() && self.recipient_id.is_none() {
+            return Err("A query must name a senderId or a recipientId".to_string());
+        }
+        if let (Some(since), Some(until)) = (self.since, self.until)
+            && since > until
+        {
+            return Err("since must not be after until".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// The audit record of one query: who ran it and why, what it asked for and how many entries
+/// it returned.
+#[derive(Debug, Clone)]
+pub struct MetadataQueryAudit {
+    pub id: Uuid,
+    pub operator: String,
+    pub reason: String,
+    /// Fingerprint of the client certificate the request was authenticated with, if any.
+    pub client_cert: Option<String>,
+    pub query: MetadataQuery,
+    pub result_count: i32,
+    pub queried_at: OffsetDateTime,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::Duration;
+
+    #[test]
+    fn test_query_must_name_an_account() {
+        assert!(MetadataQuery::default().validate().is_err());
+        assert!(MetadataQuery { sender_id: Some(Uuid::new_v4()), ..MetadataQuery::default() }.validate().is_ok());
+        assert!(MetadataQuery { recipient_id: Some(Uuid::new_v4()), ..MetadataQuery::default() }.validate().is_ok());
+    }
+
+    #[test]
+    fn test_query_range_must_not_be_inverted() {
+        let now = OffsetDateTime::now_utc();
+        let query = MetadataQuery {
+            sender_id: Some(Uuid::new_v4()),
+            since: Some(now),
+            until: Some(now - Duration::hours(1)),
+            ..MetadataQuery::default()
+        };
+        assert!(query.validate().is_err());
+        assert!(MetadataQuery { until: Some(now), ..query }.validate().is_ok());
+    }
+}
@@ -1,10 +1,18 @@
+pub mod announcement;
 pub mod attachment;
 pub mod auth;
 pub mod auth_session;
 pub mod backup;
+pub mod blocklist;
 pub mod crypto;
 pub mod device;
+pub mod feature_flag;
+pub mod identifier;
+pub mod instance;
 pub mod keys;
 pub mod message;
+pub mod metadata_index;
 pub mod notification;
+pub mod usage;
 pub mod user;
+pub mod username;
@@ -6,12 +6,55 @@ pub enum UserEvent {
     MessageReceived = 1,
     Disconnect = 2,
     PreKeyLow = 3,
+    Announcement = 4,
+    /// Another session claimed the device; sessions that no longer own it close.
+    SessionReplaced = 5,
+}
+
+/// Optional detail about why an event was raised, carried with it between instances.
+///
+/// The confidentiality flags mark parts the server may act on but must not log or pass on
+/// to the woken device.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EventContext {
+    /// How many items the event stands for, e.g. the one-time pre-keys left for `PreKeyLow`.
+    pub count: Option<u32>,
+    /// The device whose action raised the event.
+    pub sender_hint: Option<Uuid>,
+    pub count_confidential: bool,
+    pub sender_confidential: bool,
+}
+
+impl EventContext {
+    #[must_use]
+    pub const fn with_count(count: u32) -> Self {
+        Self { count: Some(count), sender_hint: None, count_confidential: false, sender_confidential: false }
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.count.is_none() && self.sender_hint.is_none()
+    }
+
+    /// The count, unless it is confidential.
+    #[must_use]
+    pub fn visible_count(&self) -> Option<u32> {
+        self.count.filter(|_| !self.count_confidential)
+    }
+
+    /// The sender hint, unless it is confidential.
+    #[must_use]
+    pub fn visible_sender(&self) -> Option<Uuid> {
+        self.sender_hint.filter(|_| !self.sender_confidential)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct RealtimeNotification {
-    pub device_id: Uuid,
+    /// `None` addresses every connected device.
+    pub device_id: Option<Uuid>,
     pub event: UserEvent,
+    pub context: EventContext,
 }
 
 impl TryFrom<u8> for UserEvent {
@@ -22,7 +65,52 @@ impl TryFrom<u8> for UserEvent {
             1 => Ok(Self::MessageReceived),
             2 => Ok(Self::Disconnect),
             3 => Ok(Self::PreKeyLow),
+            4 => Ok(Self::Announcement),
+            5 => Ok(Self::SessionReplaced),
             _ => Err(()),
         }
     }
 }
+
+/// Why a device is being woken by push, which decides how the provider collapses it.
+///
+/// A device has one pending push at a time; when reasons pile up the most specific one wins, so
+/// a refill request is never collapsed into an ordinary check.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum PushKind {
+    /// Something is waiting on the server; the device should connect and look.
+    #[default]
+    Check,
+    /// The device's one-time pre-keys are running out; it should connect and upload more.
+    PreKeyLow,
+}
+
+impl PushKind {
+    /// The stored form, also used as a metric label.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Check => "check",
+            Self::PreKeyLow => "prekey_low",
+        }
+    }
+
+    /// Parses the stored form. Unknown values fall back to a plain check.
+    #[must_use]
+    pub fn from_stored(s: &str) -> Self {
+        match s {
+            "prekey_low" => Self::PreKeyLow,
+            _ => Self::Check,
+        }
+    }
+
+    /// The push that wakes a device for `event`, if the event warrants one.
+    #[must_use]
+    pub const fn for_event(event: UserEvent) -> Option<Self> {
+        match event {
+            UserEvent::MessageReceived => Some(Self::Check),
+            UserEvent::PreKeyLow => Some(Self::PreKeyLow),
+            UserEvent::Disconnect | UserEvent::Announcement | UserEvent::SessionReplaced => None,
+        }
+    }
+}
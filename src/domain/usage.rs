@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+
+/// Storage a user currently holds on the server, across all of their devices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageUsage {
+    /// Bytes of the live attachments the user uploaded.
+    pub attachment_bytes: i64,
+    /// Bytes of the current backup of each device. Restorable previous versions are not counted.
+    pub backup_bytes: i64,
+    /// Messages waiting for delivery to the user's devices.
+    pub pending_messages: i64,
+    /// One-time pre-keys left for the user's devices.
+    pub one_time_pre_keys: i64,
+    /// When the figures were computed, as a UNIX timestamp.
+    pub computed_at: i64,
+}
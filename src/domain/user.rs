@@ -1,10 +1,71 @@
+use serde::{Deserialize, Serialize};
 use time::OffsetDateTime;
 use uuid::Uuid;
 
+/// The plan a user is on, which selects the upload, inbox and rate limits that apply to them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UserTier {
+    #[default]
+    Free,
+    Paid,
+    Admin,
+}
+
+impl UserTier {
+    pub const ALL: [Self; 3] = [Self::Free, Self::Paid, Self::Admin];
+
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Free => "free",
+            Self::Paid => "paid",
+            Self::Admin => "admin",
+        }
+    }
+
+    #[must_use]
+    pub fn is_free(&self) -> bool {
+        *self == Self::Free
+    }
+}
+
+impl std::fmt::Display for UserTier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for UserTier {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|tier| tier.as_str() == s)
+            .ok_or_else(|| format!("unknown tier '{s}', expected free, paid or admin"))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct User {
     pub id: Uuid,
     pub username: String,
     pub password_hash: String,
+    pub tier: UserTier,
     pub created_at: Option<OffsetDateTime>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tier_round_trips_through_its_name() {
+        for tier in UserTier::ALL {
+            assert_eq!(tier.as_str().parse::<UserTier>(), Ok(tier));
+        }
+        assert!("gold".parse::<UserTier>().is_err());
+        assert!("Paid".parse::<UserTier>().is_err());
+    }
+}
@@ -0,0 +1,51 @@
+use unicode_normalization::UnicodeNormalization;
+
+/// Sequences that read as a single other character, replaced after the character mappings.
+const CONFUSABLE_SEQUENCES: &[(&str, &str)] = &[("rn", "m"), ("vv", "w")];
+
+/// The form a username is stored, looked up and compared in: NFKC-normalized, so full-width and
+/// other compatibility characters collapse to their plain equivalents, then case-folded.
+#[must_use]
+pub fn normalize(raw: &str) -> String {
+    raw.trim().nfkc().flat_map(char::to_lowercase).collect()
+}
+
+/// Maps a normalized username to its confusable skeleton: usernames that a reader could mistake
+/// for one another, such as `paypa1` and `paypal`, share a skeleton.
+///
+/// Only covers the characters a username may contain; see
+/// [Unicode TR39](https://www.unicode.org/reports/tr39/#Confusable_Detection) for the general case.
+/// The generated `users.username_skeleton` column applies the same mapping, so the two must
+/// change together.
+#[must_use]
+pub fn skeleton(normalized: &str) -> String {
+    let mapped: String = normalized
+        .chars()
+        .map(|c| match c {
+            '0' => 'o',
+            '1' | 'i' => 'l',
+            '5' => 's',
+            _ => c,
+        })
+        .collect();
+    CONFUSABLE_SEQUENCES.iter().fold(mapped, |s, (from, to)| s.replace(from, to))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_folds_width_and_case() {
+        assert_eq!(normalize("Ａｌｉｃｅ_42"), "alice_42");
+        assert_eq!(normalize("  BOB "), "bob");
+    }
+
+    #[test]
+    fn test_confusable_usernames_share_a_skeleton() {
+        assert_eq!(skeleton("paypa1"), skeleton("paypal"));
+        assert_eq!(skeleton("m0dern"), skeleton("modem"));
+        assert_eq!(skeleton("vvalter"), skeleton("walter"));
+        assert_ne!(skeleton("alice"), skeleton("alicia"));
+    }
+}
@@ -1,25 +1,43 @@
+use crate::adapters::storage::StorageError;
 use crate::api::schemas::common::ErrorResponse;
+use crate::api::schemas::validation::ValidationErrors;
+use crate::services::gateway::close_reason::CloseReason;
 use axum::{
     Json,
-    http::StatusCode,
+    http::{StatusCode, header},
     response::{IntoResponse, Response},
 };
 use thiserror::Error;
 
+/// Errors from the service layer and the handlers in front of it.
+///
+/// Each error maps to an HTTP status ([`Self::status`]), an optional machine-readable code
+/// ([`Self::code`]), a gateway close code ([`Self::close_reason`]) and a retryability class
+/// ([`Self::is_retryable`]), so that callers embedding the crate can react without matching on
+/// messages.
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum AppError {
     #[error("Database error: {0}")]
     Database(#[from] sqlx::Error),
-    #[error("Authentication failed")]
-    AuthError,
+    #[error("Authentication failed: {0}")]
+    Auth(#[from] AuthError),
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+    #[error("Invalid message submission: {0}")]
+    Messaging(#[from] MessagingError),
     #[error("Not found")]
     NotFound,
     #[error("Invalid request: {0}")]
     BadRequest(String),
+    #[error("Invalid request: {0}")]
+    Validation(#[from] ValidationErrors),
     #[error("Conflict: {0}")]
     Conflict(String),
     #[error("Forbidden: {0}")]
     Forbidden(String),
+    #[error("Gone: {0}")]
+    Gone(String),
     #[error("Precondition failed")]
     PreconditionFailed,
     #[error("Request timeout")]
@@ -28,6 +46,16 @@ pub enum AppError {
     LengthRequired,
     #[error("Payload too large")]
     PayloadTooLarge,
+    #[error("Too many one-time prekeys in one request (limit {limit})")]
+    TooManyPreKeys { limit: usize },
+    #[error("Rate limited, retry after {retry_after_secs}s")]
+    RateLimited { retry_after_secs: u64 },
+    #[error("Unprocessable entity: {0}")]
+    UnprocessableEntity(String),
+    #[error("Service unavailable")]
+    ServiceUnavailable,
+    #[error("Upstream timed out")]
+    GatewayTimeout,
     #[error("Internal server error")]
     Internal,
     #[error("Internal error: {0}")]
@@ -36,29 +64,216 @@ pub enum AppError {
 
 pub type Result<T> = std::result::Result<T, AppError>;
 
+/// Why a request could not be authenticated. Every kind is answered with 401; the code tells a
+/// client whether to sign in again or refresh its token.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AuthError {
+    /// No bearer token, or one that is not well-formed.
+    #[error("missing credentials")]
+    MissingCredentials,
+    /// Wrong username or password. Which of the two is never revealed.
+    #[error("invalid credentials")]
+    InvalidCredentials,
+    /// An access, refresh or management token that is invalid, expired or revoked.
+    #[error("invalid token")]
+    InvalidToken,
+}
+
+impl AuthError {
+    const fn code(self) -> &'static str {
+        match self {
+            Self::MissingCredentials => "missing_credentials",
+            Self::InvalidCredentials => "invalid_credentials",
+            Self::InvalidToken => "invalid_token",
+        }
+    }
+}
+
+/// Why a message submission request was rejected as a whole. Rejections of single messages in a
+/// batch are reported per submission instead.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MessagingError {
+    #[error("Missing idempotency-key header")]
+    MissingIdempotencyKey,
+    #[error("Invalid idempotency-key: {0}")]
+    InvalidIdempotencyKey(String),
+    #[error("Invalid consumption-token: {0}")]
+    InvalidConsumptionToken(String),
+    #[error("Invalid SendMessageRequest protobuf: {0}")]
+    MalformedRequest(String),
+    #[error("Too many messages in one request (limit {limit})")]
+    BatchTooLarge { limit: u64 },
+}
+
+impl MessagingError {
+    const fn code(&self) -> &'static str {
+        match self {
+            Self::MissingIdempotencyKey => "missing_idempotency_key",
+            Self::InvalidIdempotencyKey(_) => "invalid_idempotency_key",
+            Self::InvalidConsumptionToken(_) => "invalid_consumption_token",
+            Self::MalformedRequest(_) => "malformed_request",
+            Self::BatchTooLarge { .. } => "batch_too_large",
+        }
+    }
+}
+
+impl AppError {
+    /// The HTTP status the error is answered with.
+    #[must_use]
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::Auth(_) => StatusCode::UNAUTHORIZED,
+            Self::NotFound | Self::Storage(StorageError::NotFound) => StatusCode::NOT_FOUND,
+            Self::BadRequest(_)
+            | Self::Validation(_)
+            | Self::Storage(StorageError::BelowMinSize)
+            | Self::Messaging(
+                MessagingError::MissingIdempotencyKey
+                | MessagingError::InvalidIdempotencyKey(_)
+                | MessagingError::InvalidConsumptionToken(_)
+                | MessagingError::MalformedRequest(_),
+            ) => StatusCode::BAD_REQUEST,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::Gone(_) => StatusCode::GONE,
+            Self::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+            Self::Timeout => StatusCode::REQUEST_TIMEOUT,
+            Self::LengthRequired => StatusCode::LENGTH_REQUIRED,
+            Self::PayloadTooLarge
+            | Self::TooManyPreKeys { .. }
+            | Self::Storage(StorageError::ExceedsLimit)
+            | Self::Messaging(MessagingError::BatchTooLarge { .. }) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::UnprocessableEntity(_) | Self::Storage(StorageError::ChecksumMismatch) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            Self::ServiceUnavailable | Self::Storage(StorageError::Unavailable) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::GatewayTimeout | Self::Storage(StorageError::TimedOut) => StatusCode::GATEWAY_TIMEOUT,
+            // A write that reached a read-only primary before the write probe noticed
+            Self::Database(e) => {
+                if is_read_only_transaction(e) {
+                    StatusCode::SERVICE_UNAVAILABLE
+                } else {
+                    StatusCode::INTERNAL_SERVER_ERROR
+                }
+            }
+            Self::Storage(_) | Self::Internal | Self::InternalMsg(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Machine-readable reason sent as `code`, for errors a client is expected to act on.
+    #[must_use]
+    pub const fn code(&self) -> Option<&'static str> {
+        match self {
+            Self::Auth(e) => Some(e.code()),
+            Self::Messaging(e) => Some(e.code()),
+            Self::TooManyPreKeys { .. } => Some("too_many_pre_keys"),
+            Self::RateLimited { .. } => Some("rate_limited"),
+            Self::Validation(_) => Some("validation_failed"),
+            _ => None,
+        }
+    }
+
+    /// The reason a gateway session ended by this error is closed with, so that clients get a
+    /// close code to act on rather than a dropped connection.
+    #[must_use]
+    pub fn close_reason(&self) -> CloseReason {
+        match self {
+            Self::Auth(_) => CloseReason::AuthExpired,
+            Self::RateLimited { .. } => CloseReason::RateLimited,
+            Self::Messaging(_)
+            | Self::BadRequest(_)
+            | Self::Validation(_)
+            | Self::PayloadTooLarge
+            | Self::TooManyPreKeys { .. }
+            | Self::UnprocessableEntity(_) => CloseReason::ProtocolError,
+            _ if self.is_retryable() => CloseReason::Unavailable,
+            _ => CloseReason::InternalError,
+        }
+    }
+
+    /// Whether the same request may succeed if sent again later: the failure was an overload,
+    /// an outage or a timeout rather than something about the request.
+    #[must_use]
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Timeout | Self::ServiceUnavailable | Self::GatewayTimeout | Self::RateLimited { .. } => true,
+            Self::Storage(e) => matches!(e, StorageError::Unavailable | StorageError::TimedOut),
+            Self::Database(e) => {
+                is_read_only_transaction(e)
+                    || matches!(e, sqlx::Error::PoolTimedOut | sqlx::Error::PoolClosed | sqlx::Error::Io(_))
+            }
+            _ => false,
+        }
+    }
+
+    /// The `error` message of the response body. Server-side failures share one generic message
+    /// so that their details stay in the logs.
+    fn public_message(&self) -> String {
+        match self {
+            Self::BadRequest(msg)
+            | Self::Conflict(msg)
+            | Self::Forbidden(msg)
+            | Self::Gone(msg)
+            | Self::UnprocessableEntity(msg) => msg.clone(),
+            Self::Validation(errors) => errors.to_string(),
+            Self::Messaging(e) => e.to_string(),
+            Self::TooManyPreKeys { limit } => format!("Too many one-time prekeys in one request. Limit is {limit}"),
+            Self::Auth(_) => "Unauthorized".to_string(),
+            Self::Storage(StorageError::BelowMinSize) => "Upload too small".to_string(),
+            Self::Storage(StorageError::ChecksumMismatch) => "Content checksum mismatch".to_string(),
+            _ => match self.status() {
+                StatusCode::NOT_FOUND => "Not found",
+                StatusCode::PRECONDITION_FAILED => "Precondition failed",
+                StatusCode::REQUEST_TIMEOUT => "Request timeout",
+                StatusCode::LENGTH_REQUIRED => "Length required",
+                StatusCode::PAYLOAD_TOO_LARGE => "Payload too large",
+                StatusCode::TOO_MANY_REQUESTS => "Too many requests",
+                StatusCode::SERVICE_UNAVAILABLE => "Service unavailable",
+                StatusCode::GATEWAY_TIMEOUT => "Upstream timed out",
+                _ => "Internal server error",
+            }
+            .to_string(),
+        }
+    }
+}
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let (status, message) = match self {
-            Self::AuthError => (StatusCode::UNAUTHORIZED, "Unauthorized".to_string()),
-            Self::NotFound => (StatusCode::NOT_FOUND, "Not found".to_string()),
-            Self::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg),
-            Self::Conflict(msg) => (StatusCode::CONFLICT, msg),
-            Self::Forbidden(msg) => (StatusCode::FORBIDDEN, msg),
-            Self::PreconditionFailed => (StatusCode::PRECONDITION_FAILED, "Precondition failed".to_string()),
-            Self::Timeout => (StatusCode::REQUEST_TIMEOUT, "Request timeout".to_string()),
-            Self::LengthRequired => (StatusCode::LENGTH_REQUIRED, "Length required".to_string()),
-            Self::PayloadTooLarge => (StatusCode::PAYLOAD_TOO_LARGE, "Payload too large".to_string()),
-            Self::Database(_) | Self::Internal | Self::InternalMsg(_) => {
-                (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error".to_string())
-            }
+        let limit = match &self {
+            Self::TooManyPreKeys { limit } => u64::try_from(*limit).ok(),
+            Self::Messaging(MessagingError::BatchTooLarge { limit }) => Some(*limit),
+            _ => None,
+        };
+        let fields = match &self {
+            Self::Validation(errors) => errors.fields().to_vec(),
+            _ => Vec::new(),
         };
 
-        let body = Json(ErrorResponse { error: message });
+        let body = Json(ErrorResponse {
+            error: self.public_message(),
+            code: self.code().map(str::to_string),
+            limit,
+            fields,
+            retryable: self.is_retryable(),
+        });
 
-        (status, body).into_response()
+        if let Self::RateLimited { retry_after_secs } = self {
+            return (self.status(), [(header::RETRY_AFTER, retry_after_secs)], body).into_response();
+        }
+        (self.status(), body).into_response()
     }
 }
 
+/// SQLSTATE of a write attempted in a read-only transaction, e.g. on a standby.
+const READ_ONLY_SQL_TRANSACTION: &str = "25006";
+
+fn is_read_only_transaction(e: &sqlx::Error) -> bool {
+    e.as_database_error().and_then(|db| db.code()).is_some_and(|code| code == READ_ONLY_SQL_TRANSACTION)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -71,17 +286,79 @@ mod tests {
 
     #[test]
     fn test_error_status_codes() {
-        assert_eq!(status_of(AppError::AuthError), StatusCode::UNAUTHORIZED);
+        assert_eq!(status_of(AuthError::InvalidToken.into()), StatusCode::UNAUTHORIZED);
         assert_eq!(status_of(AppError::NotFound), StatusCode::NOT_FOUND);
         assert_eq!(status_of(AppError::BadRequest("bad".into())), StatusCode::BAD_REQUEST);
         assert_eq!(status_of(AppError::Conflict("dup".into())), StatusCode::CONFLICT);
         assert_eq!(status_of(AppError::Forbidden("no".into())), StatusCode::FORBIDDEN);
+        assert_eq!(status_of(AppError::Gone("deleted".into())), StatusCode::GONE);
         assert_eq!(status_of(AppError::PreconditionFailed), StatusCode::PRECONDITION_FAILED);
         assert_eq!(status_of(AppError::Timeout), StatusCode::REQUEST_TIMEOUT);
         assert_eq!(status_of(AppError::LengthRequired), StatusCode::LENGTH_REQUIRED);
         assert_eq!(status_of(AppError::PayloadTooLarge), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(status_of(AppError::UnprocessableEntity("bad".into())), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(status_of(AppError::ServiceUnavailable), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(status_of(AppError::GatewayTimeout), StatusCode::GATEWAY_TIMEOUT);
         assert_eq!(status_of(AppError::Internal), StatusCode::INTERNAL_SERVER_ERROR);
         assert_eq!(status_of(AppError::InternalMsg("oops".into())), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(status_of(StorageError::NotFound.into()), StatusCode::NOT_FOUND);
+        assert_eq!(status_of(StorageError::ChecksumMismatch.into()), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(status_of(StorageError::TimedOut.into()), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(status_of(StorageError::Internal("s3".into()).into()), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(status_of(MessagingError::MissingIdempotencyKey.into()), StatusCode::BAD_REQUEST);
+        assert_eq!(status_of(MessagingError::BatchTooLarge { limit: 100 }.into()), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[test]
+    fn test_retryable_classification() {
+        assert!(AppError::ServiceUnavailable.is_retryable());
+        assert!(AppError::GatewayTimeout.is_retryable());
+        assert!(AppError::from(StorageError::Unavailable).is_retryable());
+        assert!(AppError::from(sqlx::Error::PoolTimedOut).is_retryable());
+
+        assert!(!AppError::NotFound.is_retryable());
+        assert!(!AppError::Internal.is_retryable());
+        assert!(!AppError::from(AuthError::InvalidCredentials).is_retryable());
+        assert!(!AppError::from(StorageError::ChecksumMismatch).is_retryable());
+        assert!(!AppError::from(sqlx::Error::RowNotFound).is_retryable());
+    }
+
+    #[test]
+    fn test_gateway_close_reasons() {
+        assert_eq!(AppError::from(AuthError::InvalidToken).close_reason(), CloseReason::AuthExpired);
+        assert_eq!(AppError::RateLimited { retry_after_secs: 1 }.close_reason(), CloseReason::RateLimited);
+        assert_eq!(AppError::from(MessagingError::MissingIdempotencyKey).close_reason(), CloseReason::ProtocolError);
+        assert_eq!(AppError::from(sqlx::Error::PoolTimedOut).close_reason(), CloseReason::Unavailable);
+        assert_eq!(AppError::from(StorageError::TimedOut).close_reason(), CloseReason::Unavailable);
+        assert_eq!(AppError::from(sqlx::Error::RowNotFound).close_reason(), CloseReason::InternalError);
+        assert_eq!(AppError::Internal.close_reason(), CloseReason::InternalError);
+    }
+
+    #[tokio::test]
+    async fn test_domain_errors_are_structured() {
+        let response = AppError::from(AuthError::InvalidToken).into_response();
+        let body = response.into_body().collect().await.expect("body").to_bytes();
+        let json: serde_json::Value = serde_json::from_slice(&body).expect("valid JSON");
+        assert_eq!(json["error"], "Unauthorized");
+        assert_eq!(json["code"], "invalid_token");
+        assert!(json.get("retryable").is_none());
+
+        let response = AppError::from(MessagingError::BatchTooLarge { limit: 100 }).into_response();
+        let body = response.into_body().collect().await.expect("body").to_bytes();
+        let json: serde_json::Value = serde_json::from_slice(&body).expect("valid JSON");
+        assert_eq!(json["code"], "batch_too_large");
+        assert_eq!(json["limit"], 100);
+
+        let response = AppError::from(StorageError::Internal("bucket policy denied".into())).into_response();
+        let body = response.into_body().collect().await.expect("body").to_bytes();
+        let json: serde_json::Value = serde_json::from_slice(&body).expect("valid JSON");
+        assert_eq!(json["error"], "Internal server error");
+
+        let response = AppError::from(StorageError::Unavailable).into_response();
+        let body = response.into_body().collect().await.expect("body").to_bytes();
+        let json: serde_json::Value = serde_json::from_slice(&body).expect("valid JSON");
+        assert_eq!(json["error"], "Service unavailable");
+        assert_eq!(json["retryable"], true);
     }
 
     #[tokio::test]
@@ -90,5 +367,42 @@ mod tests {
         let body = response.into_body().collect().await.expect("body").to_bytes();
         let json: serde_json::Value = serde_json::from_slice(&body).expect("valid JSON");
         assert_eq!(json["error"], "Not found");
+        assert!(json.get("code").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_validation_errors_list_each_field() {
+        let mut errors = ValidationErrors::new();
+        errors.add("username", "Username cannot be empty");
+        errors.add("password", "Password must be at least 12 characters long");
+
+        let response = AppError::from(errors).into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = response.into_body().collect().await.expect("body").to_bytes();
+        let json: serde_json::Value = serde_json::from_slice(&body).expect("valid JSON");
+        assert_eq!(json["code"], "validation_failed");
+        assert_eq!(json["fields"][0]["field"], "username");
+        assert_eq!(json["fields"][1]["message"], "Password must be at least 12 characters long");
+    }
+
+    #[tokio::test]
+    async fn test_too_many_pre_keys_is_structured() {
+        let response = AppError::TooManyPreKeys { limit: 100 }.into_response();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let body = response.into_body().collect().await.expect("body").to_bytes();
+        let json: serde_json::Value = serde_json::from_slice(&body).expect("valid JSON");
+        assert_eq!(json["code"], "too_many_pre_keys");
+        assert_eq!(json["limit"], 100);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_says_when_to_retry() {
+        let response = AppError::RateLimited { retry_after_secs: 3 }.into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers().get(header::RETRY_AFTER).expect("retry-after header"), "3");
+        let body = response.into_body().collect().await.expect("body").to_bytes();
+        let json: serde_json::Value = serde_json::from_slice(&body).expect("valid JSON");
+        assert_eq!(json["code"], "rate_limited");
+        assert_eq!(json["retryable"], true);
     }
 }
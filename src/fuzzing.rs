@@ -0,0 +1,65 @@
+//! Entry points for the cargo-fuzz targets in `fuzz/`, exposed by the `fuzzing` feature so that
+//! packagers can build and run them against this crate.
+//!
+//! Each one drives a parser of untrusted client input the way the server does and discards the
+//! result: a target only fails by panicking.
+
+use crate::api::backup;
+use crate::api::schemas::crypto::{PublicKey, Signature};
+use crate::api::schemas::validation::{PUBLIC_KEY_BYTES, SIGNATURE_BYTES, ValidationErrors};
+use crate::domain::crypto;
+use crate::domain::message::RawSubmission;
+use crate::proto::obscura::v1 as proto;
+use crate::services::gateway::session;
+use axum::http::{HeaderMap, HeaderValue, header};
+use bytes::Bytes;
+use prost::Message as ProstMessage;
+
+/// Decodes an inbound WebSocket frame as a session does, including the message IDs of an ACK.
+pub fn websocket_frame(data: &[u8]) {
+    if let Ok(frame) = proto::WebSocketFrame::decode(data)
+        && let Some(proto::web_socket_frame::Payload::Ack(ack)) = frame.payload
+    {
+        session::parse_ack_ids(ack.message_ids);
+    }
+}
+
+/// Decodes a `SendMessageRequest` body and validates the structure of each submission.
+pub fn send_message_request(data: &[u8]) {
+    if let Ok(request) = proto::SendMessageRequest::decode(Bytes::copy_from_slice(data)) {
+        for submission in request.messages {
+            let _ = RawSubmission::from(submission).validate();
+        }
+    }
+}
+
+/// Parses the conditional headers of the backup endpoints. The input is split at the first
+/// newline into `If-Match` and `If-None-Match` values; a half that is not a valid header value
+/// is left out.
+pub fn backup_headers(data: &[u8]) {
+    let mut headers = HeaderMap::new();
+    let mut halves = data.splitn(2, |b| *b == b'\n');
+    for name in [header::IF_MATCH, header::IF_NONE_MATCH] {
+        if let Some(value) = halves.next().and_then(|v| HeaderValue::from_bytes(v).ok()) {
+            headers.insert(name, value);
+        }
+    }
+
+    let _ = backup::upload_precondition(&headers);
+    let _ = backup::cached_version(&headers);
+    let _ = backup::restore_precondition(&headers);
+}
+
+/// Checks and decodes base64 key material as the request schemas do, as both a public key and a
+/// signature.
+pub fn key_material(data: &[u8]) {
+    let Ok(value) = std::str::from_utf8(data) else {
+        return;
+    };
+
+    let mut errors = ValidationErrors::new();
+    errors.check_base64_len("key", value, PUBLIC_KEY_BYTES);
+    errors.check_base64_len("signature", value, SIGNATURE_BYTES);
+    let _ = crypto::PublicKey::try_from(PublicKey(value.to_string()));
+    let _ = crypto::Signature::try_from(Signature(value.to_string()));
+}
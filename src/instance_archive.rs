@@ -0,0 +1,535 @@
+//! Instance archives (`export-instance` and `import-instance`): a portable snapshot of the
+//! accounts on one deployment, for moving them to another without `pg_dump` surgery on a live
+//! schema.
+//!
+//! An archive is JSON Lines: a header, then one line per row, then a trailer with the row counts
+//! so a truncated archive is never half imported. Rows carry what accounts need to keep working
+//! on the new instance: users, their verified identifiers, devices, identity keys, pre-keys,
+//! sequence counters and backup metadata. Pending messages, attachments, sessions and push tokens
+//! are left behind; clients sign in and register for pushes again. Backup objects stay in the
+//! storage bucket, which is copied or reused separately.
+
+use crate::adapters::database::DbPool;
+use anyhow::{Context, bail, ensure};
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use futures::TryStreamExt;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sqlx::PgConnection;
+use time::OffsetDateTime;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufWriter};
+use uuid::Uuid;
+
+/// Version of the archive layout, bumped whenever a row changes shape.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Rows written to or read from an archive, by table.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveCounts {
+    pub users: u64,
+    pub identifiers: u64,
+    pub devices: u64,
+    pub identity_keys: u64,
+    pub signed_pre_keys: u64,
+    pub one_time_pre_keys: u64,
+    pub inbox_sequences: u64,
+    pub pair_sequences: u64,
+    pub backups: u64,
+}
+
+impl ArchiveCounts {
+    fn count(&mut self, entry: &Entry) {
+        let counter = match entry {
+            Entry::Header(_) | Entry::Trailer(_) => return,
+            Entry::User(_) => &mut self.users,
+            Entry::Identifier(_) => &mut self.identifiers,
+            Entry::Device(_) => &mut self.devices,
+            Entry::IdentityKey(_) => &mut self.identity_keys,
+            Entry::SignedPreKey(_) => &mut self.signed_pre_keys,
+            Entry::OneTimePreKey(_) => &mut self.one_time_pre_keys,
+            Entry::InboxSequence(_) => &mut self.inbox_sequences,
+            Entry::PairSequence(_) => &mut self.pair_sequences,
+            Entry::Backup(_) => &mut self.backups,
+        };
+        *counter += 1;
+    }
+}
+
+/// One line of an archive. Rows follow their parents, so they can be inserted in archive order.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum Entry {
+    Header(Header),
+    User(UserRow),
+    Identifier(IdentifierRow),
+    Device(DeviceRow),
+    IdentityKey(IdentityKeyRow),
+    SignedPreKey(SignedPreKeyRow),
+    OneTimePreKey(OneTimePreKeyRow),
+    InboxSequence(InboxSequenceRow),
+    PairSequence(PairSequenceRow),
+    Backup(BackupRow),
+    Trailer(ArchiveCounts),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Header {
+    format_version: u32,
+    server_version: String,
+    #[serde(with = "time::serde::timestamp::microseconds")]
+    exported_at: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+struct UserRow {
+    id: Uuid,
+    username: String,
+    password_hash: String,
+    tier: String,
+    #[serde(with = "time::serde::timestamp::microseconds::option")]
+    created_at: Option<OffsetDateTime>,
+}
+
+/// A verified identifier. Its hash is keyed by `--identifier-hash-secret`, so it only matches
+/// lookups on an instance that keeps the same secret.
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+struct IdentifierRow {
+    user_id: Uuid,
+    kind: String,
+    #[serde(with = "base64_bytes")]
+    identifier_hash: Vec<u8>,
+    discoverable: bool,
+    #[serde(with = "time::serde::timestamp::microseconds")]
+    verified_at: OffsetDateTime,
+    #[serde(with = "time::serde::timestamp::microseconds")]
+    created_at: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+struct DeviceRow {
+    id: Uuid,
+    user_id: Uuid,
+    name: Option<String>,
+    #[serde(with = "time::serde::timestamp::microseconds::option")]
+    created_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+struct IdentityKeyRow {
+    device_id: Uuid,
+    #[serde(with = "base64_bytes")]
+    identity_key: Vec<u8>,
+    registration_id: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+struct SignedPreKeyRow {
+    id: i32,
+    device_id: Uuid,
+    #[serde(with = "base64_bytes")]
+    public_key: Vec<u8>,
+    #[serde(with = "base64_bytes")]
+    signature: Vec<u8>,
+    #[serde(with = "time::serde::timestamp::microseconds::option")]
+    created_at: Option<OffsetDateTime>,
+}
+
+/// An unreserved one-time pre-key. Keys reserved for a sender are left out: the sender may still
+/// redeem them on the old instance, and handing them out again would reuse a key.
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+struct OneTimePreKeyRow {
+    id: i32,
+    device_id: Uuid,
+    #[serde(with = "base64_bytes")]
+    public_key: Vec<u8>,
+    #[serde(with = "time::serde::timestamp::microseconds::option")]
+    created_at: Option<OffsetDateTime>,
+}
+
+/// Carried over so sequence numbers keep increasing for clients that check them.
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+struct InboxSequenceRow {
+    device_id: Uuid,
+    last_seq: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+struct PairSequenceRow {
+    sender_device_id: Uuid,
+    device_id: Uuid,
+    last_seq: i64,
+}
+
+/// The committed versions of a device's backup. An upload in progress is not carried over.
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+struct BackupRow {
+    device_id: Uuid,
+    current_version: i32,
+    size_bytes: Option<i64>,
+    previous_version: Option<i32>,
+    previous_size_bytes: Option<i64>,
+    #[serde(with = "time::serde::timestamp::microseconds")]
+    updated_at: OffsetDateTime,
+    #[serde(with = "time::serde::timestamp::microseconds::option")]
+    deleted_at: Option<OffsetDateTime>,
+}
+
+/// Writes a snapshot of the instance to `output`.
+///
+/// Every table is read in one `REPEATABLE READ` transaction, so the archive is consistent even
+/// while the server keeps running.
+///
+/// # Errors
+/// Returns an error if a query fails or the archive cannot be written.
+#[tracing::instrument(skip_all, err)]
+pub async fn export<W: AsyncWrite + Unpin + Send>(pool: &DbPool, output: W) -> anyhow::Result<ArchiveCounts> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY").execute(&mut *tx).await?;
+
+    let mut writer = ArchiveWriter { output: BufWriter::new(output), counts: ArchiveCounts::default() };
+    writer
+        .write(&Entry::Header(Header {
+            format_version: FORMAT_VERSION,
+            server_version: env!("CARGO_PKG_VERSION").to_string(),
+            exported_at: OffsetDateTime::now_utc(),
+        }))
+        .await?;
+
+    writer
+        .copy(&mut tx, Entry::User, "SELECT id, username, password_hash, tier, created_at FROM users ORDER BY id")
+        .await?;
+    writer
+        .copy(
+            &mut tx,
+            Entry::Identifier,
+            r#"
+            SELECT user_id, kind, identifier_hash, discoverable, verified_at, created_at
+            FROM user_identifiers
+            WHERE verified_at IS NOT NULL
+            ORDER BY user_id, kind
+            "#,
+        )
+        .await?;
+    writer.copy(&mut tx, Entry::Device, "SELECT id, user_id, name, created_at FROM devices ORDER BY id").await?;
+    writer
+        .copy(
+            &mut tx,
+            Entry::IdentityKey,
+            "SELECT device_id, identity_key, registration_id FROM identity_keys ORDER BY device_id",
+        )
+        .await?;
+    writer
+        .copy(
+            &mut tx,
+            Entry::SignedPreKey,
+            "SELECT id, device_id, public_key, signature, created_at FROM signed_pre_keys ORDER BY device_id, id",
+        )
+        .await?;
+    writer
+        .copy(
+            &mut tx,
+            Entry::OneTimePreKey,
+            r#"
+            SELECT id, device_id, public_key, created_at
+            FROM one_time_pre_keys
+            WHERE reservation_token IS NULL OR reserved_until < NOW()
+            ORDER BY device_id, id
+            "#,
+        )
+        .await?;
+    writer
+        .copy(&mut tx, Entry::InboxSequence, "SELECT device_id, last_seq FROM inbox_sequences ORDER BY device_id")
+        .await?;
+    writer
+        .copy(
+            &mut tx,
+            Entry::PairSequence,
+            "SELECT sender_device_id, device_id, last_seq FROM pair_sequences ORDER BY sender_device_id, device_id",
+        )
+        .await?;
+    writer
+        .copy(
+            &mut tx,
+            Entry::Backup,
+            r#"
+            SELECT device_id, current_version, size_bytes, previous_version, previous_size_bytes, updated_at, deleted_at
+            FROM backups
+            ORDER BY device_id
+            "#,
+        )
+        .await?;
+
+    let counts = writer.counts;
+    writer.write(&Entry::Trailer(counts)).await?;
+    writer.output.flush().await?;
+    tx.rollback().await?;
+
+    tracing::info!(?counts, "Exported instance archive");
+    Ok(counts)
+}
+
+/// Loads an archive written by [`export`] into an instance that has no accounts yet.
+///
+/// Runs in one transaction: an archive that is malformed, truncated or from an unknown format
+/// version leaves the database untouched.
+///
+/// # Errors
+/// Returns an error if the database already has accounts, the archive is not valid, or an insert
+/// fails.
+#[tracing::instrument(skip_all, err)]
+pub async fn import<R: AsyncBufRead + Unpin + Send>(pool: &DbPool, input: R) -> anyhow::Result<ArchiveCounts> {
+    let mut tx = pool.begin().await?;
+    let has_users: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM users)").fetch_one(&mut *tx).await?;
+    ensure!(!has_users, "The target database already has accounts; import into a fresh instance");
+
+    let mut lines = input.lines();
+    let header = match read_entry(lines.next_line().await?, 1)? {
+        Some(Entry::Header(header)) => header,
+        _ => bail!("Not an instance archive: the first line is not a header"),
+    };
+    ensure!(
+        header.format_version == FORMAT_VERSION,
+        "Archive format version {} is not supported (expected {FORMAT_VERSION})",
+        header.format_version
+    );
+
+    let mut counts = ArchiveCounts::default();
+    let mut line_number = 1;
+    loop {
+        line_number += 1;
+        let entry = read_entry(lines.next_line().await?, line_number)?
+            .context("Archive is truncated: it ends without a trailer")?;
+        match entry {
+            Entry::Trailer(expected) => {
+                ensure!(
+                    expected == counts,
+                    "Archive is corrupt: it holds {counts:?} but its trailer lists {expected:?}"
+                );
+                break;
+            }
+            Entry::Header(_) => bail!("Archive is corrupt: unexpected header on line {line_number}"),
+            entry => {
+                counts.count(&entry);
+                insert(&mut tx, entry).await.with_context(|| format!("Failed to import line {line_number}"))?;
+            }
+        }
+    }
+    ensure!(lines.next_line().await?.is_none(), "Archive is corrupt: data follows the trailer");
+
+    tx.commit().await?;
+    tracing::info!(
+        ?counts,
+        server_version = %header.server_version,
+        exported_at = %header.exported_at,
+        "Imported instance archive"
+    );
+    Ok(counts)
+}
+
+struct ArchiveWriter<W> {
+    output: BufWriter<W>,
+    counts: ArchiveCounts,
+}
+
+impl<W: AsyncWrite + Unpin + Send> ArchiveWriter<W> {
+    async fn write(&mut self, entry: &Entry) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        self.output.write_all(&line).await?;
+        self.counts.count(entry);
+        Ok(())
+    }
+
+    /// Streams the rows of `sql` into the archive without holding them all in memory.
+    async fn copy<T>(&mut self, conn: &mut PgConnection, wrap: fn(T) -> Entry, sql: &str) -> anyhow::Result<()>
+    where
+        T: for<'r> sqlx::FromRow<'r, sqlx::postgres::PgRow> + Send + Unpin,
+    {
+        let mut rows = sqlx::query_as::<_, T>(sql).fetch(&mut *conn);
+        while let Some(row) = rows.try_next().await? {
+            self.write(&wrap(row)).await?;
+        }
+        Ok(())
+    }
+}
+
+fn read_entry(line: Option<String>, line_number: usize) -> anyhow::Result<Option<Entry>> {
+    line.map(|line| {
+        serde_json::from_str(&line).with_context(|| format!("Archive is corrupt: line {line_number} is not valid"))
+    })
+    .transpose()
+}
+
+async fn insert(conn: &mut PgConnection, entry: Entry) -> anyhow::Result<()> {
+    match entry {
+        Entry::User(row) => {
+            sqlx::query(
+                "INSERT INTO users (id, username, password_hash, tier, created_at) VALUES ($1, $2, $3, $4, $5)",
+            )
+            .bind(row.id)
+            .bind(row.username)
+            .bind(row.password_hash)
+            .bind(row.tier)
+            .bind(row.created_at)
+            .execute(conn)
+            .await?;
+        }
+        Entry::Identifier(row) => {
+            sqlx::query(
+                r#"
+                INSERT INTO user_identifiers (user_id, kind, identifier_hash, discoverable, verified_at, created_at)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                "#,
+            )
+            .bind(row.user_id)
+            .bind(row.kind)
+            .bind(row.identifier_hash)
+            .bind(row.discoverable)
+            .bind(row.verified_at)
+            .bind(row.created_at)
+            .execute(conn)
+            .await?;
+        }
+        Entry::Device(row) => {
+            sqlx::query("INSERT INTO devices (id, user_id, name, created_at) VALUES ($1, $2, $3, $4)")
+                .bind(row.id)
+                .bind(row.user_id)
+                .bind(row.name)
+                .bind(row.created_at)
+                .execute(conn)
+                .await?;
+        }
+        Entry::IdentityKey(row) => {
+            sqlx::query("INSERT INTO identity_keys (device_id, identity_key, registration_id) VALUES ($1, $2, $3)")
+                .bind(row.device_id)
+                .bind(row.identity_key)
+                .bind(row.registration_id)
+                .execute(conn)
+                .await?;
+        }
+        Entry::SignedPreKey(row) => {
+            sqlx::query(
+                "INSERT INTO signed_pre_keys (id, device_id, public_key, signature, created_at) VALUES ($1, $2, $3, $4, $5)",
+            )
+            .bind(row.id)
+            .bind(row.device_id)
+            .bind(row.public_key)
+            .bind(row.signature)
+            .bind(row.created_at)
+            .execute(conn)
+            .await?;
+        }
+        Entry::OneTimePreKey(row) => {
+            sqlx::query(
+                "INSERT INTO one_time_pre_keys (id, device_id, public_key, created_at) VALUES ($1, $2, $3, $4)",
+            )
+            .bind(row.id)
+            .bind(row.device_id)
+            .bind(row.public_key)
+            .bind(row.created_at)
+            .execute(conn)
+            .await?;
+        }
+        Entry::InboxSequence(row) => {
+            sqlx::query("INSERT INTO inbox_sequences (device_id, last_seq) VALUES ($1, $2)")
+                .bind(row.device_id)
+                .bind(row.last_seq)
+                .execute(conn)
+                .await?;
+        }
+        Entry::PairSequence(row) => {
+            sqlx::query("INSERT INTO pair_sequences (sender_device_id, device_id, last_seq) VALUES ($1, $2, $3)")
+                .bind(row.sender_device_id)
+                .bind(row.device_id)
+                .bind(row.last_seq)
+                .execute(conn)
+                .await?;
+        }
+        Entry::Backup(row) => {
+            sqlx::query(
+                r#"
+                INSERT INTO backups
+                    (device_id, current_version, size_bytes, previous_version, previous_size_bytes, updated_at, deleted_at)
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
+                "#,
+            )
+            .bind(row.device_id)
+            .bind(row.current_version)
+            .bind(row.size_bytes)
+            .bind(row.previous_version)
+            .bind(row.previous_size_bytes)
+            .bind(row.updated_at)
+            .bind(row.deleted_at)
+            .execute(conn)
+            .await?;
+        }
+        Entry::Header(_) | Entry::Trailer(_) => bail!("The header and trailer are not rows"),
+    }
+    Ok(())
+}
+
+/// Byte columns as standard base64, rather than serde's default array of numbers.
+mod base64_bytes {
+    use super::{Deserialize, Deserializer, STANDARD, Serializer};
+    use base64::Engine as _;
+
+    pub(super) fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rows_round_trip_through_json() {
+        let created_at = OffsetDateTime::from_unix_timestamp_nanos(1_700_000_000_123_456_000).expect("valid timestamp");
+        let row = SignedPreKeyRow {
+            id: 7,
+            device_id: Uuid::new_v4(),
+            public_key: vec![5; 33],
+            signature: vec![9; 64],
+            created_at: Some(created_at),
+        };
+        let json = serde_json::to_string(&Entry::SignedPreKey(row)).expect("row serializes");
+        assert!(json.starts_with(r#"{"signedPreKey":{"id":7,"#));
+        assert!(json.contains(&format!(r#""publicKey":"{}""#, STANDARD.encode([5; 33]))));
+
+        let decoded = serde_json::from_str::<Entry>(&json).expect("row deserializes");
+        assert!(matches!(
+            decoded,
+            Entry::SignedPreKey(row) if row.signature == vec![9; 64] && row.created_at == Some(created_at)
+        ));
+    }
+
+    #[test]
+    fn test_counts_skip_header_and_trailer() {
+        let mut counts = ArchiveCounts::default();
+        counts.count(&Entry::InboxSequence(InboxSequenceRow { device_id: Uuid::nil(), last_seq: 3 }));
+        counts.count(&Entry::Trailer(ArchiveCounts::default()));
+        assert_eq!(counts, ArchiveCounts { inbox_sequences: 1, ..ArchiveCounts::default() });
+    }
+
+    #[test]
+    fn test_malformed_lines_are_rejected() {
+        assert!(read_entry(Some(r#"{"header":{"formatVersion":1}}"#.to_string()), 1).is_err());
+        assert!(read_entry(Some(r#"{"message":{}}"#.to_string()), 2).is_err());
+        assert!(read_entry(None, 3).is_ok_and(|entry| entry.is_none()));
+    }
+}
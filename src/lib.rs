@@ -1,48 +1,88 @@
 pub mod adapters;
 pub mod api;
 pub mod config;
+pub mod conformance;
+pub mod dev;
 pub mod domain;
 pub mod error;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+pub mod instance_archive;
 pub mod proto;
 pub mod services;
 pub mod telemetry;
+#[cfg(feature = "testing")]
+pub mod testing;
 pub mod workers;
 
+use crate::adapters::circuit_breaker::{CircuitBreaker, CircuitBreakerPushProvider, CircuitBreakerStorage};
+use crate::adapters::database::announcement_repo::AnnouncementRepository;
 use crate::adapters::database::attachment_repo::AttachmentRepository;
+use crate::adapters::database::availability::DbAvailability;
 use crate::adapters::database::backup_repo::BackupRepository;
+use crate::adapters::database::blocklist_repo::BlocklistRepository;
 use crate::adapters::database::device_repo::DeviceRepository;
+use crate::adapters::database::feature_flag_repo::FeatureFlagRepository;
+use crate::adapters::database::identifier_repo::IdentifierRepository;
 use crate::adapters::database::key_repo::KeyRepository;
 use crate::adapters::database::message_repo::MessageRepository;
+use crate::adapters::database::metadata_index_repo::MetadataIndexRepository;
 use crate::adapters::database::push_token_repo::PushTokenRepository;
 use crate::adapters::database::refresh_token_repo::RefreshTokenRepository;
 use crate::adapters::database::user_repo::UserRepository;
+use crate::adapters::key_value::{
+    InstanceRegistry, KeyValueCache, KeyValueStore, PostgresKeyValueStore, SessionRegistry, SessionResume,
+};
 use crate::adapters::push::PushProvider;
-use crate::adapters::redis::RedisCache;
+use crate::adapters::push_queue::{PostgresPushJobQueue, PushJobQueue};
+use crate::adapters::realtime::{PostgresRealtimeBus, RealtimeBus};
+use crate::adapters::redis::AckSpill;
+use crate::adapters::retry::{RetryPolicy, RetryingPushProvider, RetryingStorage};
 use crate::adapters::storage::S3Storage;
-use crate::config::{Config, StorageConfig};
+use crate::adapters::submission_cache::{LruSubmissionStore, SubmissionCache, SubmissionStore};
+use crate::adapters::verification::{LoggingVerificationSender, VerificationSender};
+use crate::api::load_shed::LoadShedder;
+use crate::api::middleware::InFlightRequests;
+use crate::config::{Config, IdempotencyBackend, OutboundConfig, PushQueueBackend, RealtimeBackend, StorageConfig};
+use crate::services::account_service::AccountService;
+use crate::services::announcement_service::AnnouncementService;
 use crate::services::attachment_service::AttachmentService;
 use crate::services::auth_service::AuthService;
 use crate::services::backup_service::BackupService;
+use crate::services::blocklist_service::BlocklistService;
 use crate::services::crypto_service::CryptoService;
 use crate::services::device_service::DeviceService;
+use crate::services::feature_flag_service::FeatureFlagService;
 use crate::services::gateway::GatewayService;
+use crate::services::gateway::routing::SessionCounter;
+use crate::services::gateway::upload_progress::UploadProgress;
 use crate::services::health_service::HealthService;
+use crate::services::identifier_service::IdentifierService;
+use crate::services::instance_service::InstanceService;
 use crate::services::key_service::KeyService;
 use crate::services::message_service::MessageService;
+use crate::services::metadata_index_service::MetadataIndexService;
 use crate::services::notification_service::NotificationService;
 use crate::services::push_token_service::PushTokenService;
 use crate::services::rate_limit_service::RateLimitService;
+use crate::services::support_service::SupportService;
+use crate::services::usage_service::UsageService;
 use crate::workers::{
-    AttachmentCleanupWorker, BackupCleanupWorker, MessageCleanupWorker, NotificationWorker, PushNotificationWorker,
-    RefreshTokenCleanupWorker,
+    AckSpillWorker, AnnouncementWorker, AttachmentCleanupWorker, BackupCleanupWorker, BlocklistRefreshWorker,
+    DbWriteProbeWorker, DeliverySloWorker, FeatureFlagRefreshWorker, InstanceHeartbeatWorker, MessageCleanupWorker,
+    MetadataIndexCleanupWorker, NotificationWorker, PoolAdjusterWorker, PreKeySamplerWorker, PushNotificationWorker,
+    RedisJanitorWorker, RefreshTokenCleanupWorker, StorageAuditWorker,
 };
+use anyhow::Context;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::watch;
 
 #[derive(Clone, Debug)]
 pub struct Resources {
     pub pool: adapters::database::DbPool,
-    pub pubsub: Arc<adapters::redis::RedisClient>,
+    /// `None` when Redis is not used.
+    pub pubsub: Option<Arc<adapters::redis::RedisClient>>,
     pub s3_client: aws_sdk_s3::Client,
 }
 
@@ -55,8 +95,17 @@ pub struct Adapters {
     pub refresh: RefreshTokenRepository,
     pub attachment: AttachmentRepository,
     pub backup: BackupRepository,
+    pub blocklist: BlocklistRepository,
+    pub feature_flag: FeatureFlagRepository,
+    pub announcement: AnnouncementRepository,
     pub push_token: PushTokenRepository,
-    pub notification: Arc<adapters::redis::NotificationRepository>,
+    pub identifier: IdentifierRepository,
+    pub metadata_index: MetadataIndexRepository,
+    /// `None` when Redis is not used.
+    pub notification: Option<Arc<adapters::redis::NotificationRepository>>,
+    pub key_value: Arc<dyn KeyValueStore>,
+    pub realtime: Arc<dyn RealtimeBus>,
+    pub push_queue: Arc<dyn PushJobQueue>,
     pub storage: Arc<dyn adapters::storage::ObjectStorage>,
     pub push: Arc<dyn PushProvider>,
 }
@@ -71,14 +120,23 @@ impl std::fmt::Debug for Adapters {
             .field("refresh", &self.refresh)
             .field("attachment", &self.attachment)
             .field("backup", &self.backup)
+            .field("blocklist", &self.blocklist)
+            .field("feature_flag", &self.feature_flag)
+            .field("announcement", &self.announcement)
             .field("push_token", &self.push_token)
+            .field("identifier", &self.identifier)
+            .field("metadata_index", &self.metadata_index)
             .field("notification", &self.notification)
+            .field("key_value", &self.key_value)
+            .field("realtime", &self.realtime)
+            .field("push_queue", &self.push_queue)
             .finish_non_exhaustive()
     }
 }
 
 #[derive(Debug)]
 pub struct Services {
+    pub account_service: AccountService,
     pub key_service: KeyService,
     pub attachment_service: AttachmentService,
     pub backup_service: BackupService,
@@ -88,9 +146,17 @@ pub struct Services {
     pub(crate) gateway_service: GatewayService,
     pub notification_service: NotificationService,
     pub push_token_service: PushTokenService,
+    pub identifier_service: IdentifierService,
+    pub instance_service: InstanceService,
     pub rate_limit_service: RateLimitService,
-    pub submission_cache: RedisCache,
-    pub ws_ticket_cache: RedisCache,
+    pub blocklist_service: BlocklistService,
+    pub feature_flag_service: FeatureFlagService,
+    pub usage_service: UsageService,
+    pub db_availability: DbAvailability,
+    pub submission_cache: SubmissionCache,
+    pub ws_ticket_cache: KeyValueCache,
+    pub in_flight_requests: InFlightRequests,
+    pub load_shedder: LoadShedder,
 }
 
 #[derive(Debug)]
@@ -98,17 +164,35 @@ pub struct App {
     pub resources: Resources,
     pub services: Services,
     pub health_service: HealthService,
+    pub sessions: SessionCounter,
+    pub support_service: SupportService,
+    pub blocklist_service: BlocklistService,
+    pub feature_flag_service: FeatureFlagService,
+    pub announcement_service: AnnouncementService,
+    pub metadata_index_service: MetadataIndexService,
     pub workers: Workers,
 }
 
 #[derive(Debug)]
 pub struct Workers {
     pub message_worker: MessageCleanupWorker,
+    pub metadata_index_worker: MetadataIndexCleanupWorker,
     pub attachment_worker: AttachmentCleanupWorker,
     pub backup_worker: BackupCleanupWorker,
     pub push_worker: PushNotificationWorker,
     pub notification_worker: NotificationWorker,
     pub refresh_token_worker: RefreshTokenCleanupWorker,
+    pub blocklist_worker: BlocklistRefreshWorker,
+    pub feature_flag_worker: FeatureFlagRefreshWorker,
+    pub announcement_worker: AnnouncementWorker,
+    pub storage_audit_worker: StorageAuditWorker,
+    pub pool_adjuster_worker: PoolAdjusterWorker,
+    pub db_write_probe_worker: DbWriteProbeWorker,
+    pub ack_spill_worker: AckSpillWorker,
+    pub prekey_sampler_worker: PreKeySamplerWorker,
+    pub delivery_slo_worker: DeliverySloWorker,
+    pub instance_heartbeat_worker: InstanceHeartbeatWorker,
+    pub redis_janitor_worker: RedisJanitorWorker,
 }
 
 impl Workers {
@@ -122,6 +206,12 @@ impl Workers {
             message_worker.run(message_rx).await;
         }));
 
+        let metadata_index_worker = self.metadata_index_worker;
+        let metadata_index_rx = shutdown_rx.clone();
+        tasks.push(tokio::spawn(async move {
+            metadata_index_worker.run(metadata_index_rx).await;
+        }));
+
         let attachment_worker = self.attachment_worker;
         let attachment_rx = shutdown_rx.clone();
         tasks.push(tokio::spawn(async move {
@@ -147,8 +237,74 @@ impl Workers {
         }));
 
         let refresh_token_worker = self.refresh_token_worker;
+        let refresh_token_rx = shutdown_rx.clone();
         tasks.push(tokio::spawn(async move {
-            refresh_token_worker.run(shutdown_rx).await;
+            refresh_token_worker.run(refresh_token_rx).await;
+        }));
+
+        let blocklist_worker = self.blocklist_worker;
+        let blocklist_rx = shutdown_rx.clone();
+        tasks.push(tokio::spawn(async move {
+            blocklist_worker.run(blocklist_rx).await;
+        }));
+
+        let feature_flag_worker = self.feature_flag_worker;
+        let feature_flag_rx = shutdown_rx.clone();
+        tasks.push(tokio::spawn(async move {
+            feature_flag_worker.run(feature_flag_rx).await;
+        }));
+
+        let announcement_worker = self.announcement_worker;
+        let announcement_rx = shutdown_rx.clone();
+        tasks.push(tokio::spawn(async move {
+            announcement_worker.run(announcement_rx).await;
+        }));
+
+        let storage_audit_worker = self.storage_audit_worker;
+        let storage_audit_rx = shutdown_rx.clone();
+        tasks.push(tokio::spawn(async move {
+            storage_audit_worker.run(storage_audit_rx).await;
+        }));
+
+        let pool_adjuster_worker = self.pool_adjuster_worker;
+        let pool_adjuster_rx = shutdown_rx.clone();
+        tasks.push(tokio::spawn(async move {
+            pool_adjuster_worker.run(pool_adjuster_rx).await;
+        }));
+
+        let db_write_probe_worker = self.db_write_probe_worker;
+        let db_write_probe_rx = shutdown_rx.clone();
+        tasks.push(tokio::spawn(async move {
+            db_write_probe_worker.run(db_write_probe_rx).await;
+        }));
+
+        let ack_spill_worker = self.ack_spill_worker;
+        let ack_spill_rx = shutdown_rx.clone();
+        tasks.push(tokio::spawn(async move {
+            ack_spill_worker.run(ack_spill_rx).await;
+        }));
+
+        let prekey_sampler_worker = self.prekey_sampler_worker;
+        let prekey_sampler_rx = shutdown_rx.clone();
+        tasks.push(tokio::spawn(async move {
+            prekey_sampler_worker.run(prekey_sampler_rx).await;
+        }));
+
+        let delivery_slo_worker = self.delivery_slo_worker;
+        let delivery_slo_rx = shutdown_rx.clone();
+        tasks.push(tokio::spawn(async move {
+            delivery_slo_worker.run(delivery_slo_rx).await;
+        }));
+
+        let redis_janitor_worker = self.redis_janitor_worker;
+        let redis_janitor_rx = shutdown_rx.clone();
+        tasks.push(tokio::spawn(async move {
+            redis_janitor_worker.run(redis_janitor_rx).await;
+        }));
+
+        let instance_heartbeat_worker = self.instance_heartbeat_worker;
+        tasks.push(tokio::spawn(async move {
+            instance_heartbeat_worker.run(shutdown_rx).await;
         }));
 
         tasks
@@ -160,9 +316,11 @@ impl Workers {
 pub struct AppBuilder {
     config: Config,
     pool: Option<adapters::database::DbPool>,
+    replica_pool: Option<adapters::database::DbPool>,
     pubsub: Option<Arc<adapters::redis::RedisClient>>,
     s3_client: Option<aws_sdk_s3::Client>,
     push_provider: Option<Arc<dyn PushProvider>>,
+    verification_sender: Option<Arc<dyn VerificationSender>>,
     shutdown_rx: Option<watch::Receiver<bool>>,
 }
 
@@ -170,7 +328,16 @@ impl AppBuilder {
     /// Creates a new builder with the provided configuration.
     #[must_use]
     pub fn new(config: Config) -> Self {
-        Self { config, pool: None, pubsub: None, s3_client: None, push_provider: None, shutdown_rx: None }
+        Self {
+            config,
+            pool: None,
+            replica_pool: None,
+            pubsub: None,
+            s3_client: None,
+            push_provider: None,
+            verification_sender: None,
+            shutdown_rx: None,
+        }
     }
 
     /// Sets the database connection pool.
@@ -180,7 +347,15 @@ impl AppBuilder {
         self
     }
 
-    /// Sets the `PubSub` (Redis) client.
+    /// Sets the read replica pool, used for reads while the primary cannot take writes.
+    #[must_use]
+    pub fn with_replica_database(mut self, pool: adapters::database::DbPool) -> Self {
+        self.replica_pool = Some(pool);
+        self
+    }
+
+    /// Sets the `PubSub` (Redis) client. Required when either notification backend is `redis`;
+    /// without it, the shared state Redis would hold is kept in Postgres.
     #[must_use]
     pub fn with_pubsub(mut self, pubsub: Arc<adapters::redis::RedisClient>) -> Self {
         self.pubsub = Some(pubsub);
@@ -201,6 +376,14 @@ impl AppBuilder {
         self
     }
 
+    /// Sets the sender that delivers identifier verification codes. Codes are only logged when
+    /// none is set.
+    #[must_use]
+    pub fn with_verification_sender(mut self, sender: Arc<dyn VerificationSender>) -> Self {
+        self.verification_sender = Some(sender);
+        self
+    }
+
     /// Sets the shutdown receiver for coordinating graceful exit.
     #[must_use]
     pub fn with_shutdown_rx(mut self, rx: watch::Receiver<bool>) -> Self {
@@ -211,109 +394,289 @@ impl AppBuilder {
     /// Builds the application components by wiring all services and repositories.
     ///
     /// # Errors
-    /// Returns an error if mandatory dependencies (pool, pubsub, etc.) are missing,
+    /// Returns an error if mandatory dependencies (pool, pubsub when Redis is used, etc.) are missing,
     /// or if any service fails to initialize.
-    #[tracing::instrument(skip(self))]
+    #[tracing::instrument(skip_all)]
     #[allow(clippy::too_many_lines)]
     pub async fn initialize(self) -> anyhow::Result<App> {
         let pool = self.pool.ok_or_else(|| anyhow::anyhow!("Database pool is required"))?;
-        let pubsub = self.pubsub.ok_or_else(|| anyhow::anyhow!("PubSub client is required"))?;
+        let pubsub = self.pubsub;
+        if self.config.uses_redis() && pubsub.is_none() {
+            anyhow::bail!("PubSub client is required by the redis notification backends");
+        }
         let s3_client = self.s3_client.ok_or_else(|| anyhow::anyhow!("S3 client is required"))?;
         let push_provider = self.push_provider.ok_or_else(|| anyhow::anyhow!("Push provider is required"))?;
-        let _shutdown_rx = self.shutdown_rx.clone().ok_or_else(|| anyhow::anyhow!("Shutdown receiver is required"))?;
-
-        let config = &self.config;
-
-        let resources = Resources { pool: pool.clone(), pubsub: Arc::clone(&pubsub), s3_client: s3_client.clone() };
+        let verification_sender = self.verification_sender.unwrap_or_else(|| Arc::new(LoggingVerificationSender));
+        let shutdown_rx = self.shutdown_rx.clone().ok_or_else(|| anyhow::anyhow!("Shutdown receiver is required"))?;
+
+        let mut config = self.config;
+        let instance_id = config.instance.ensure_id().to_string();
+        let config = &config;
+        let db_availability = DbAvailability::new(self.replica_pool);
+
+        let resources = Resources { pool: pool.clone(), pubsub: pubsub.clone(), s3_client: s3_client.clone() };
+        adapters::database::instrumentation::register_pool_metrics(&pool);
+
+        let notification_repo = pubsub.as_ref().map(|pubsub| {
+            Arc::new(adapters::redis::NotificationRepository::new(Arc::clone(pubsub), &config.notifications))
+        });
+        let redis_notifications =
+            || notification_repo.clone().ok_or_else(|| anyhow::anyhow!("PubSub client is required"));
+        let push_queue: Arc<dyn PushJobQueue> = match config.notifications.push_queue_backend {
+            PushQueueBackend::Redis => redis_notifications()?,
+            PushQueueBackend::Postgres => Arc::new(PostgresPushJobQueue::new(pool.clone())),
+        };
+        let realtime: Arc<dyn RealtimeBus> = match config.notifications.realtime_backend {
+            RealtimeBackend::Redis => redis_notifications()?,
+            RealtimeBackend::Postgres => {
+                Arc::new(PostgresRealtimeBus::new(pool.clone(), &config.notifications, shutdown_rx))
+            }
+        };
+        let key_value: Arc<dyn KeyValueStore> = match &pubsub {
+            Some(pubsub) => Arc::clone(pubsub) as Arc<dyn KeyValueStore>,
+            None => Arc::new(PostgresKeyValueStore::new(pool.clone())),
+        };
 
         // Initialize Adapters (Trait implementations and Repositories)
         let adapters = Adapters {
             device: DeviceRepository::new(),
             key: KeyRepository::new(),
-            message: MessageRepository::new(),
+            message: MessageRepository::new()
+                .with_inbox_shards(config.messaging.inbox_shards)
+                .with_ack_grace_period(config.messaging.ack_grace_period_secs),
             user: UserRepository::new(),
             refresh: RefreshTokenRepository::new(),
             attachment: AttachmentRepository::new(),
             backup: BackupRepository::new(),
+            blocklist: BlocklistRepository::new(),
+            feature_flag: FeatureFlagRepository::new(),
+            announcement: AnnouncementRepository::new(),
             push_token: PushTokenRepository::new(),
-            notification: Arc::new(adapters::redis::NotificationRepository::new(
-                Arc::clone(&pubsub),
-                &config.notifications,
+            identifier: IdentifierRepository::new(),
+            metadata_index: MetadataIndexRepository::new(),
+            notification: notification_repo,
+            key_value,
+            realtime,
+            push_queue,
+            storage: Arc::new(CircuitBreakerStorage::new(
+                Arc::new(RetryingStorage::new(
+                    Arc::new(
+                        S3Storage::new(s3_client.clone(), config.storage.bucket.clone())
+                            .with_timeouts(
+                                Duration::from_secs(config.storage.stream_idle_timeout_secs),
+                                Duration::from_secs(config.storage.transfer_timeout_secs),
+                            )
+                            .with_upload_buffer(config.storage.upload_chunk_bytes, config.storage.upload_buffer_bytes),
+                    ),
+                    RetryPolicy::new("s3", config.retry.storage_max_attempts, &config.retry),
+                )),
+                CircuitBreaker::new("s3", &config.circuit_breaker),
+            )),
+            push: Arc::new(CircuitBreakerPushProvider::new(
+                Arc::new(RetryingPushProvider::new(
+                    push_provider,
+                    RetryPolicy::new("push", config.retry.push_max_attempts, &config.retry),
+                )),
+                CircuitBreaker::new("push", &config.circuit_breaker),
             )),
-            storage: Arc::new(S3Storage::new(s3_client.clone(), config.storage.bucket.clone())),
-            push: push_provider,
         };
 
         // Initialize Core Services
-        let crypto_service = CryptoService::new();
-        let notifier = NotificationService::new(Arc::clone(&adapters.notification), &config.notifications);
+        let crypto_service = CryptoService::new(crate::adapters::crypto::verifier(config.crypto.backend)?);
+        let notifier = NotificationService::new(
+            Arc::clone(&adapters.realtime),
+            Arc::clone(&adapters.push_queue),
+            &config.notifications,
+        );
+        let feature_flag_service = FeatureFlagService::new(pool.clone(), adapters.feature_flag.clone());
+        feature_flag_service.refresh().await.context("Failed to load feature flags")?;
         let key_service = KeyService::new(
             pool.clone(),
             adapters.key.clone(),
             crypto_service,
             notifier.clone(),
             config.messaging.clone(),
-        );
+            RetryPolicy::new("postgres", config.retry.database_max_attempts, &config.retry),
+        )
+        .with_availability(db_availability.clone())
+        .with_feature_flags(feature_flag_service.clone());
         let auth_service = AuthService::new(
             config.auth.clone(),
             pool.clone(),
             adapters.user.clone(),
             adapters.refresh.clone(),
             adapters.device.clone(),
+            adapters.push_token.clone(),
         );
-        let submission_cache = RedisCache::new(
-            Arc::clone(&pubsub),
-            "idempotency:submission:".to_string(),
-            config.messaging.idempotency_ttl_secs,
+        let submission_store: Arc<dyn SubmissionStore> = match config.messaging.idempotency_backend {
+            IdempotencyBackend::Redis => Arc::new(KeyValueCache::new(
+                Arc::clone(&adapters.key_value),
+                "idempotency:submission:".to_string(),
+                config.messaging.idempotency_ttl_secs,
+            )),
+            IdempotencyBackend::Memory => Arc::new(LruSubmissionStore::new(
+                config.messaging.idempotency_ttl_secs,
+                config.messaging.idempotency_memory_cap_bytes,
+            )),
+            IdempotencyBackend::Postgres => Arc::new(KeyValueCache::new(
+                Arc::new(PostgresKeyValueStore::new(pool.clone())),
+                "idempotency:submission:".to_string(),
+                config.messaging.idempotency_ttl_secs,
+            )),
+        };
+        let submission_cache = SubmissionCache::new(submission_store, config.messaging.idempotency_max_response_bytes);
+        let ws_ticket_cache = KeyValueCache::new(
+            Arc::clone(&adapters.key_value),
+            "ws:ticket:".to_string(),
+            config.websocket.ticket_ttl_secs,
         );
-        let ws_ticket_cache =
-            RedisCache::new(Arc::clone(&pubsub), "ws:ticket:".to_string(), config.websocket.ticket_ttl_secs);
         let message_service = MessageService::new(
             pool.clone(),
             adapters.message.clone(),
+            Arc::clone(&adapters.storage),
             notifier.clone(),
             config.messaging.clone(),
             config.ttl_days,
-        );
+        )
+        .with_availability(db_availability.clone());
+        let message_service = if config.metadata_index.enabled {
+            message_service.with_metadata_index(adapters.metadata_index.clone())
+        } else {
+            message_service
+        };
+        let account_service = AccountService::new(
+            pool.clone(),
+            adapters.user.clone(),
+            adapters.device.clone(),
+            notifier.clone(),
+            config.auth.username_reuse_grace_days,
+            config.auth.deletion_notice_window_hours,
+        )
+        .with_message_service(message_service.clone());
         let device_service = DeviceService::new(
             pool.clone(),
             adapters.device.clone(),
-            adapters.message.clone(),
+            message_service.clone(),
             key_service.clone(),
             auth_service.clone(),
             notifier.clone(),
             config.auth.max_devices_per_user,
         );
+        let announcement_service = AnnouncementService::new(
+            pool.clone(),
+            adapters.announcement.clone(),
+            notifier.clone(),
+            AnnouncementService::signing_key(&config.announcements, &config.auth.jwt_secret)?,
+            &config.announcements,
+        );
+        let ack_spill = pubsub.as_ref().map(|pubsub| {
+            AckSpill::new(
+                Arc::clone(pubsub),
+                config.websocket.ack_spill_key.clone(),
+                config.websocket.ack_spill_max_batches,
+            )
+        });
+        let upload_progress = UploadProgress::new(config.attachment.progress_step_percent);
         let gateway_service = GatewayService::new(
+            auth_service.clone(),
             message_service.clone(),
             key_service.clone(),
+            announcement_service.clone(),
             notifier.clone(),
             config.websocket.clone(),
+            config.websocket.routing_secret.clone().unwrap_or_else(|| config.auth.jwt_secret.clone()),
+        )
+        .with_session_registry(SessionRegistry::new(
+            Arc::clone(&adapters.key_value),
+            "ws:session:".to_string(),
+            // Outlives one ping interval, so only sessions that stopped refreshing lose their entry.
+            config.websocket.ping_interval_secs.max(1) + config.websocket.ping_timeout_secs,
+        ))
+        .with_upload_progress(upload_progress.clone())
+        .with_ack_persistence(
+            RetryPolicy::new("ack_delete", config.retry.ack_delete_max_attempts, &config.retry),
+            ack_spill.clone(),
+        );
+        let gateway_service = if config.websocket.resume_ttl_secs > 0 {
+            gateway_service.with_session_resume(SessionResume::new(
+                Arc::clone(&adapters.key_value),
+                "ws:resume:".to_string(),
+                config.websocket.resume_ttl_secs,
+            ))
+        } else {
+            gateway_service
+        };
+        let sessions = gateway_service.sessions();
+        let instance_service = InstanceService::new(
+            InstanceRegistry::new(
+                Arc::clone(&adapters.key_value),
+                "instance:".to_string(),
+                config.instance.heartbeat_ttl_secs,
+            ),
+            sessions.clone(),
+            instance_id,
         );
         let push_token_service = PushTokenService::new(pool.clone(), adapters.push_token.clone());
+        let identifier_service =
+            IdentifierService::new(pool.clone(), adapters.identifier.clone(), verification_sender, &config.identifiers);
         let attachment_service = AttachmentService::new(
             pool.clone(),
             adapters.attachment.clone(),
             Arc::clone(&adapters.storage),
             config.attachment.clone(),
+            config.tier_limits(),
             config.ttl_days,
-        );
+        )
+        .with_upload_progress(upload_progress);
         let backup_service = BackupService::new(
             pool.clone(),
             adapters.backup.clone(),
             Arc::clone(&adapters.storage),
             config.backup.clone(),
+            config.tier_limits(),
+        );
+        let usage_service = UsageService::new(
+            pool.clone(),
+            adapters.device.clone(),
+            adapters.attachment.clone(),
+            adapters.backup.clone(),
+            adapters.message.clone(),
+            adapters.key.clone(),
+            (config.usage_cache_ttl_secs > 0).then(|| {
+                KeyValueCache::new(Arc::clone(&adapters.key_value), "usage:".to_string(), config.usage_cache_ttl_secs)
+            }),
         );
-        let rate_limit_service = RateLimitService::new(config.server.trusted_proxies.clone());
+        let rate_limit_service =
+            RateLimitService::new(config.server.trusted_proxies.clone(), &config.rate_limit, config.tier_limits());
+        let blocklist_file_entries = match &config.blocklist.file {
+            Some(path) => BlocklistService::read_file(path)?,
+            None => Vec::new(),
+        };
+        let blocklist_service = BlocklistService::new(pool.clone(), adapters.blocklist.clone(), blocklist_file_entries);
+        blocklist_service.refresh().await.context("Failed to load the IP blocklist")?;
         let health_service = HealthService::new(
             pool.clone(),
             s3_client,
-            Arc::clone(&pubsub),
+            pubsub,
+            Arc::clone(&adapters.push),
             config.storage.bucket.clone(),
             config.health.clone(),
+        )
+        .with_availability(db_availability.clone());
+        let support_service = SupportService::new(
+            pool.clone(),
+            adapters.user.clone(),
+            adapters.device.clone(),
+            adapters.message.clone(),
+            adapters.backup.clone(),
+            adapters.push_token.clone(),
+            adapters.attachment.clone(),
         );
+        let metadata_index_service =
+            MetadataIndexService::new(pool.clone(), adapters.metadata_index.clone(), &config.metadata_index);
 
         let services = Services {
+            account_service,
             key_service,
             attachment_service,
             backup_service,
@@ -321,26 +684,63 @@ impl AppBuilder {
             auth_service,
             message_service,
             gateway_service,
-            notification_service: notifier.clone(),
+            notification_service: notifier,
             push_token_service,
+            identifier_service,
+            instance_service,
             rate_limit_service,
+            blocklist_service: blocklist_service.clone(),
+            feature_flag_service: feature_flag_service.clone(),
+            usage_service,
+            db_availability,
             submission_cache,
             ws_ticket_cache,
+            in_flight_requests: InFlightRequests::default(),
+            load_shedder: LoadShedder::new(pool.clone(), &config.load_shed),
         };
 
-        let workers = Self::init_workers(config, &pool, &adapters, notifier);
+        let workers = Self::init_workers(
+            config,
+            &pool,
+            &adapters,
+            &services,
+            health_service.clone(),
+            ack_spill,
+            metadata_index_service.clone(),
+        );
 
-        Ok(App { resources, services, health_service, workers })
+        Ok(App {
+            resources,
+            services,
+            health_service,
+            sessions,
+            support_service,
+            blocklist_service,
+            feature_flag_service,
+            announcement_service,
+            metadata_index_service,
+            workers,
+        })
     }
 
     fn init_workers(
         config: &Config,
         pool: &adapters::database::DbPool,
         adapters: &Adapters,
-        notifier: NotificationService,
+        services: &Services,
+        health_service: HealthService,
+        ack_spill: Option<AckSpill>,
+        metadata_index_service: MetadataIndexService,
     ) -> Workers {
         Workers {
-            message_worker: MessageCleanupWorker::new(pool.clone(), adapters.message.clone(), config.messaging.clone()),
+            message_worker: MessageCleanupWorker::new(
+                pool.clone(),
+                adapters.message.clone(),
+                Arc::clone(&adapters.storage),
+                config.messaging.clone(),
+            )
+            .with_tier_limits(&config.tier_limits()),
+            metadata_index_worker: MetadataIndexCleanupWorker::new(metadata_index_service, &config.metadata_index),
             attachment_worker: AttachmentCleanupWorker::new(
                 pool.clone(),
                 adapters.attachment.clone(),
@@ -355,20 +755,71 @@ impl AppBuilder {
             ),
             push_worker: PushNotificationWorker::new(
                 pool.clone(),
-                Arc::clone(&adapters.notification),
+                Arc::clone(&adapters.push_queue),
                 Arc::clone(&adapters.push),
                 adapters.push_token.clone(),
                 &config.notifications,
             ),
             notification_worker: NotificationWorker::new(
-                notifier,
-                Arc::clone(&adapters.notification),
+                services.notification_service.clone(),
+                Arc::clone(&adapters.realtime),
                 config.notifications.cleanup_interval_secs,
             ),
-            refresh_token_worker: RefreshTokenCleanupWorker::new(
+            refresh_token_worker: RefreshTokenCleanupWorker::new(services.auth_service.clone(), &config.auth),
+            blocklist_worker: BlocklistRefreshWorker::new(
+                services.blocklist_service.clone(),
+                config.blocklist.refresh_interval_secs,
+            ),
+            feature_flag_worker: FeatureFlagRefreshWorker::new(
+                services.feature_flag_service.clone(),
+                config.feature_flags.refresh_interval_secs,
+            ),
+            announcement_worker: AnnouncementWorker::new(
+                pool.clone(),
+                adapters.announcement.clone(),
+                config.announcements.clone(),
+            ),
+            storage_audit_worker: StorageAuditWorker::new(
+                pool.clone(),
+                adapters.attachment.clone(),
+                adapters.backup.clone(),
+                Arc::clone(&adapters.storage),
+                config.storage.clone(),
+                &config.attachment,
+                &config.backup,
+            ),
+            pool_adjuster_worker: PoolAdjusterWorker::new(pool.clone(), config.database.clone()),
+            db_write_probe_worker: DbWriteProbeWorker::new(health_service, config.health.db_write_probe_interval_secs),
+            ack_spill_worker: AckSpillWorker::new(
                 pool.clone(),
-                adapters.refresh.clone(),
-                config.auth.refresh_token_cleanup_interval_secs,
+                adapters.message.clone(),
+                ack_spill,
+                config.websocket.ack_spill_interval_secs,
+            ),
+            prekey_sampler_worker: PreKeySamplerWorker::new(
+                pool.clone(),
+                adapters.key.clone(),
+                config.messaging.pre_key_refill_threshold,
+                config.messaging.pre_key_sample_size,
+                config.messaging.pre_key_sample_interval_secs,
+            ),
+            delivery_slo_worker: DeliverySloWorker::new(
+                services.gateway_service.delivery_stats(),
+                config.telemetry.delivery_slo_interval_secs,
+            ),
+            instance_heartbeat_worker: InstanceHeartbeatWorker::new(
+                services.instance_service.clone(),
+                config.instance.heartbeat_interval_secs,
+            ),
+            redis_janitor_worker: RedisJanitorWorker::new(
+                adapters
+                    .notification
+                    .clone()
+                    .filter(|_| config.notifications.push_queue_backend == PushQueueBackend::Redis),
+                services.submission_cache.clone(),
+                services.ws_ticket_cache.clone(),
+                Arc::new(PostgresKeyValueStore::new(pool.clone())),
+                config.pubsub.janitor_interval_secs,
             ),
         }
     }
@@ -378,17 +829,31 @@ impl AppBuilder {
 ///
 /// # Errors
 /// Returns an error if migrations fail.
-#[tracing::instrument(skip(pool))]
+#[tracing::instrument(skip_all)]
 pub async fn run_migrations(pool: &adapters::database::DbPool) -> anyhow::Result<()> {
-    sqlx::migrate!().run(pool).await.map_err(Into::into)
+    MIGRATOR.run(pool).await.map_err(Into::into)
 }
 
-/// Initializes an S3 client from configuration.
-#[tracing::instrument(skip(config))]
-pub async fn initialize_s3_client(config: &StorageConfig) -> aws_sdk_s3::Client {
+/// The migrations in `migrations/`, embedded at build time.
+pub static MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!();
+
+/// Initializes an S3 client from configuration, under the outbound proxy, trusted roots and pins
+/// if any are set.
+///
+/// # Errors
+/// Returns an error if the outbound HTTP client cannot be built.
+#[tracing::instrument(skip_all)]
+pub async fn initialize_s3_client(
+    config: &StorageConfig,
+    outbound: &OutboundConfig,
+) -> anyhow::Result<aws_sdk_s3::Client> {
     let region_provider = aws_config::Region::new(config.region.clone());
     let mut config_loader = aws_config::defaults(aws_config::BehaviorVersion::latest()).region(region_provider);
 
+    if let Some(http_client) = adapters::http_client::build_s3_http_client(outbound)? {
+        config_loader = config_loader.http_client(http_client);
+    }
+
     if let Some(ref endpoint) = config.endpoint {
         config_loader = config_loader.endpoint_url(endpoint);
     }
@@ -400,7 +865,7 @@ pub async fn initialize_s3_client(config: &StorageConfig) -> aws_sdk_s3::Client
 
     let sdk_config = config_loader.load().await;
     let s3_config_builder = aws_sdk_s3::config::Builder::from(&sdk_config).force_path_style(config.force_path_style);
-    aws_sdk_s3::Client::from_conf(s3_config_builder.build())
+    Ok(aws_sdk_s3::Client::from_conf(s3_config_builder.build()))
 }
 
 /// Sets up a panic hook that logs the panic message and location.
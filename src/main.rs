@@ -1,68 +1,156 @@
 use anyhow::Context;
 use obscura_server::api::MgmtState;
-use obscura_server::config::Config;
-use obscura_server::{AppBuilder, adapters, telemetry};
+use obscura_server::api::mgmt_auth::MgmtAuth;
+use obscura_server::api::server::DrainWatch;
+use obscura_server::config::{Command, Config, IdempotencyBackend, OutboundConfig, ReportFormat};
+use obscura_server::{AppBuilder, adapters, conformance, instance_archive, telemetry};
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::watch;
 use tracing::Instrument;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let config = Config::load();
-    let telemetry_guard = telemetry::init_telemetry(&config.telemetry)?;
+    let mut config = Config::load();
+    if config.dev {
+        obscura_server::dev::apply_defaults(&mut config);
+    }
+    // The conformance suite only talks to a deployment, so the server's own settings are not checked.
+    if !matches!(config.command, Some(Command::Conformance { .. })) {
+        config.validate()?;
+    }
+    let instance_id = config.instance.ensure_id().to_string();
+    let telemetry_guard = telemetry::init_telemetry(&config.telemetry, &instance_id, &config.auth.jwt_secret)?;
+
+    if let Some(command) = config.command.take() {
+        let result = run_command(&config, command).await;
+        telemetry_guard.shutdown();
+        return result;
+    }
 
     obscura_server::setup_panic_hook();
 
-    let boot_span = tracing::info_span!("boot_server");
-    let (api_listener, mgmt_listener, app_router, mgmt_app, shutdown_tx, shutdown_rx, workers) = async {
+    let mgmt_tls = obscura_server::api::mgmt_tls::load_tls_config(&config.server)
+        .context("Invalid management TLS configuration")?;
+    if !MgmtAuth::new(&config.server).is_enabled() {
+        tracing::warn!("Management API authentication is not configured, relying on network isolation");
+    }
+    if config.notifications.user_channel_capacity.is_some() {
+        tracing::warn!(
+            "--notifications-user-channel-capacity is deprecated and ignored, and will be removed in the next release"
+        );
+    }
+
+    let boot_span = tracing::info_span!("boot_server", "service.instance.id" = %instance_id);
+    let (api_listener, mgmt_listener, app_router, mgmt_app, shutdown_tx, shutdown_rx, workers, drain_watch) = async {
         // Phase 1: Infrastructure Setup (Resources)
         let pool = adapters::database::init_pool(&config.database).await?;
         obscura_server::run_migrations(&pool).await?;
+        let replica_pool = adapters::database::init_replica_pool(&config.database)?;
 
         let (shutdown_tx, shutdown_rx) = watch::channel(false);
         obscura_server::spawn_signal_handler(shutdown_tx.clone());
 
-        let pubsub = adapters::redis::RedisClient::new(
-            &config.pubsub,
-            config.notifications.global_channel_capacity,
-            shutdown_rx.clone(),
-        )
-        .await?;
+        let pubsub = if config.uses_redis() {
+            Some(
+                adapters::redis::RedisClient::new(
+                    &config.pubsub,
+                    config.notifications.global_channel_capacity,
+                    shutdown_rx.clone(),
+                )
+                .await?,
+            )
+        } else {
+            tracing::info!("Both notification backends use Postgres, not connecting to Redis");
+            if config.messaging.idempotency_backend == IdempotencyBackend::Redis {
+                tracing::warn!("Redis is not used, idempotency responses will be kept in Postgres");
+            }
+            None
+        };
 
-        let s3_client = obscura_server::initialize_s3_client(&config.storage).await;
+        let s3_client = obscura_server::initialize_s3_client(&config.storage, &config.outbound).await?;
+        if config.dev {
+            tracing::warn!("Running in dev mode with demo data, do not use in production");
+            obscura_server::dev::ensure_bucket(&s3_client, &config.storage.bucket).await?;
+        }
 
         // Phase 2: Component Wiring (Pure logic, no side effects)
+        let http_client = adapters::http_client::build_client(&config.outbound)?;
+        let verification_sender: Arc<dyn adapters::verification::VerificationSender> =
+            if let Some(sender) =
+                adapters::verification::webhook::WebhookVerificationSender::new(&config.identifiers, http_client.clone())
+            {
+                Arc::new(sender)
+            } else {
+                tracing::warn!("Verification webhook not configured, identifier codes will not be delivered");
+                Arc::new(adapters::verification::LoggingVerificationSender)
+            };
         let push_provider: Arc<dyn adapters::push::PushProvider> = if config.fcm.is_configured() {
             tracing::info!("FCM credentials configured, using real FCM push provider");
-            Arc::new(
-                adapters::push::fcm::FcmPushProvider::new(&config.fcm)
+            let primary: Arc<dyn adapters::push::PushProvider> = Arc::new(
+                adapters::push::fcm::FcmPushProvider::new(&config.fcm, http_client.clone())
                     .context("Failed to initialize FCM push provider. Verify that OBSCURA_FCM_CREDENTIALS_FILE points to a valid service account JSON file")?,
-            )
+            );
+            if let Some(shadow_config) = config.fcm.shadow() {
+                tracing::info!(percent = config.fcm.shadow_percent, "Shadowing pushes to a second FCM project");
+                let shadow = adapters::push::fcm::FcmPushProvider::new(&shadow_config, http_client)
+                    .context("Failed to initialize shadow FCM push provider. Verify that OBSCURA_FCM_SHADOW_CREDENTIALS_FILE points to a valid service account JSON file")?;
+                Arc::new(adapters::push::multi::MultiPushProvider::new(
+                    primary,
+                    Some(Arc::new(shadow)),
+                    config.fcm.shadow_percent,
+                ))
+            } else {
+                primary
+            }
         } else {
             tracing::warn!("FCM credentials not configured, push notifications will be logged but not sent");
             Arc::new(adapters::push::LoggingPushProvider)
         };
-        let app = AppBuilder::new(config.clone())
+        let mut builder = AppBuilder::new(config.clone())
             .with_database(pool)
-            .with_pubsub(pubsub)
             .with_s3(s3_client)
             .with_push_provider(push_provider)
-            .with_shutdown_rx(shutdown_rx.clone())
-            .initialize()
-            .await?;
+            .with_verification_sender(verification_sender)
+            .with_shutdown_rx(shutdown_rx.clone());
+        if let Some(pubsub) = pubsub {
+            builder = builder.with_pubsub(pubsub);
+        }
+        if let Some(replica_pool) = replica_pool {
+            tracing::info!("Read replica configured for read-only mode");
+            builder = builder.with_replica_database(replica_pool);
+        }
+        let app = builder.initialize().await?;
+        app.health_service.log_migration_status().await;
+        if config.dev {
+            obscura_server::dev::seed(&app.services).await?;
+        }
 
         // Phase 3: Runtime Setup (Listeners and Routers)
+        let drain_watch = DrainWatch::new(app.services.in_flight_requests.clone(), app.sessions.clone());
+        let auth_service = app.services.auth_service.clone();
+        let instance_service = app.services.instance_service.clone();
         let app_router = obscura_server::api::app_router(&config, app.services, shutdown_rx.clone());
-        let mgmt_app = obscura_server::api::mgmt_router(MgmtState { health_service: app.health_service });
+        let mgmt_app = obscura_server::api::mgmt_router(&config, MgmtState {
+            health_service: app.health_service,
+            sessions: app.sessions,
+            support_service: app.support_service,
+            blocklist_service: app.blocklist_service,
+            feature_flag_service: app.feature_flag_service,
+            announcement_service: app.announcement_service,
+            auth_service,
+            instance_service,
+            metadata_index_service: app.metadata_index_service,
+        });
 
         let api_addr: SocketAddr = format!("{}:{}", config.server.host, config.server.port).parse()?;
         let mgmt_addr: SocketAddr = format!("{}:{}", config.server.host, config.server.mgmt_port).parse()?;
 
         tracing::info!(address = %api_addr, "listening");
-        tracing::info!(address = %mgmt_addr, "management server listening");
+        tracing::info!(address = %mgmt_addr, tls = mgmt_tls.is_some(), "management server listening");
 
-        let api_listener = tokio::net::TcpListener::bind(api_addr).await?;
+        let api_listener = obscura_server::api::server::bind(api_addr, &config.server)?;
         let mgmt_listener = tokio::net::TcpListener::bind(mgmt_addr).await?;
 
         Ok::<
@@ -74,9 +162,10 @@ async fn main() -> anyhow::Result<()> {
                 watch::Sender<bool>,
                 watch::Receiver<bool>,
                 obscura_server::Workers,
+                DrainWatch,
             ),
             anyhow::Error,
-        >((api_listener, mgmt_listener, app_router, mgmt_app, shutdown_tx, shutdown_rx, app.workers))
+        >((api_listener, mgmt_listener, app_router, mgmt_app, shutdown_tx, shutdown_rx, app.workers, drain_watch))
     }
     .instrument(boot_span)
     .await?;
@@ -84,20 +173,32 @@ async fn main() -> anyhow::Result<()> {
     // Phase 4: Start Runtime (Explicit Spawning and Listening)
     let worker_tasks = workers.spawn_all(shutdown_rx.clone());
 
-    let mut api_rx = shutdown_rx.clone();
-    let api_server = axum::serve(api_listener, app_router.into_make_service_with_connect_info::<SocketAddr>())
-        .with_graceful_shutdown(async move {
-            let _ = api_rx.wait_for(|&s| s).await;
-        });
+    let api_server = obscura_server::api::server::serve(api_listener, app_router, &config.server, shutdown_rx.clone());
 
     let mut mgmt_rx = shutdown_rx.clone();
-    let mgmt_server = axum::serve(mgmt_listener, mgmt_app.into_make_service_with_connect_info::<SocketAddr>())
-        .with_graceful_shutdown(async move {
-            let _ = mgmt_rx.wait_for(|&s| s).await;
-        });
+    let mgmt_server = async move {
+        match mgmt_tls {
+            Some(tls) => obscura_server::api::mgmt_tls::serve_tls(mgmt_listener, mgmt_app, tls, mgmt_rx).await,
+            None => {
+                axum::serve(mgmt_listener, mgmt_app.into_make_service_with_connect_info::<SocketAddr>())
+                    .with_graceful_shutdown(async move {
+                        let _ = mgmt_rx.wait_for(|&s| s).await;
+                    })
+                    .await
+            }
+        }
+    };
 
-    if let Err(e) = tokio::try_join!(api_server, mgmt_server) {
-        tracing::error!(error = %e, "Server error");
+    // Once shutdown begins, stop waiting on the servers as soon as their requests and sessions
+    // are gone, or when the drain runs out of time
+    let drain_timeout = Duration::from_secs(config.server.shutdown_timeout_secs);
+    tokio::select! {
+        result = async { tokio::try_join!(api_server, mgmt_server) } => {
+            if let Err(e) = result {
+                tracing::error!(error = %e, "Server error");
+            }
+        }
+        _ = drain_watch.wait(shutdown_rx.clone(), drain_timeout) => {}
     }
 
     // Phase 5: Graceful Shutdown Orchestration
@@ -108,7 +209,7 @@ async fn main() -> anyhow::Result<()> {
         } => {
             tracing::info!("Background tasks finished.");
         }
-        () = tokio::time::sleep(std::time::Duration::from_secs(config.server.shutdown_timeout_secs)) => {
+        () = tokio::time::sleep(Duration::from_secs(config.server.shutdown_timeout_secs)) => {
             tracing::warn!("Timeout waiting for background tasks to finish.");
         }
     }
@@ -116,3 +217,52 @@ async fn main() -> anyhow::Result<()> {
     telemetry_guard.shutdown();
     Ok(())
 }
+
+/// Runs an operator tool in place of the server.
+async fn run_command(config: &Config, command: Command) -> anyhow::Result<()> {
+    match command {
+        Command::ExportInstance { output } => {
+            let pool = adapters::database::init_pool(&config.database).await?;
+            let file = tokio::fs::File::create(&output)
+                .await
+                .with_context(|| format!("Failed to create {}", output.display()))?;
+            instance_archive::export(&pool, file).await?;
+        }
+        Command::ImportInstance { input } => {
+            let pool = adapters::database::init_pool(&config.database).await?;
+            let file =
+                tokio::fs::File::open(&input).await.with_context(|| format!("Failed to open {}", input.display()))?;
+            obscura_server::run_migrations(&pool).await?;
+            instance_archive::import(&pool, tokio::io::BufReader::new(file)).await?;
+        }
+        Command::Conformance { base_url, output, format, timeout_secs } => {
+            let timeout = Duration::from_secs(timeout_secs);
+            run_conformance(&base_url, &output, format, timeout, &config.outbound).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn run_conformance(
+    base_url: &str,
+    output: &std::path::Path,
+    format: ReportFormat,
+    timeout: Duration,
+    outbound: &OutboundConfig,
+) -> anyhow::Result<()> {
+    let report = conformance::Suite::new(base_url, timeout, outbound)?.run().await;
+    tokio::fs::write(output, report.render(format)?)
+        .await
+        .with_context(|| format!("Failed to write {}", output.display()))?;
+
+    let failures = report.failures();
+    tracing::info!(
+        cases = report.cases.len(),
+        failures,
+        skipped = report.skipped(),
+        report = %output.display(),
+        "Conformance run finished"
+    );
+    anyhow::ensure!(failures == 0, "{failures} of {} conformance cases failed", report.cases.len());
+    Ok(())
+}
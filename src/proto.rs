@@ -6,3 +6,16 @@ pub mod obscura {
         include!(concat!(env!("OUT_DIR"), "/obscura.v1.rs"));
     }
 }
+
+/// The schema in `proto/` and every message compiled from it, for the wire compatibility tests.
+#[cfg(feature = "testing")]
+pub mod compat {
+    /// The encoded `FileDescriptorSet` of `proto/`.
+    pub const FILE_DESCRIPTOR_SET: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/obscura_descriptor.bin"));
+
+    include!(concat!(env!("OUT_DIR"), "/obscura_messages.rs"));
+
+    fn reencode_as<M: prost::Message + Default>(bytes: &[u8]) -> Result<Vec<u8>, prost::DecodeError> {
+        Ok(M::decode(bytes)?.encode_to_vec())
+    }
+}
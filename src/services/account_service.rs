@@ -0,0 +1,131 @@
+use crate::adapters::database::DbPool;
+use crate::adapters::database::device_repo::DeviceRepository;
+use crate::adapters::database::instrumentation::TimedAcquire;
+use crate::adapters::database::user_repo::UserRepository;
+use crate::domain::notification::UserEvent;
+use crate::error::{AppError, Result};
+use crate::services::message_service::MessageService;
+use crate::services::notification_service::NotificationService;
+use crate::telemetry;
+use opentelemetry::{global, metrics::Counter};
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+
+#[derive(Clone, Debug)]
+struct Metrics {
+    accounts_deleted: Counter<u64>,
+    recipient_gone_notices: Counter<u64>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let meter = global::meter("obscura-server");
+        Self {
+            accounts_deleted: meter
+                .u64_counter("obscura_accounts_deleted_total")
+                .with_description("Total number of user accounts deleted")
+                .build(),
+            recipient_gone_notices: meter
+                .u64_counter("obscura_recipient_gone_notices_total")
+                .with_description("Sender devices told that a deleted account will not receive their pending messages")
+                .build(),
+        }
+    }
+}
+
+/// Account lifecycle: deletion and the tombstones that outlive it.
+#[derive(Clone, Debug)]
+pub struct AccountService {
+    pool: DbPool,
+    user_repo: UserRepository,
+    device_repo: DeviceRepository,
+    notifier: NotificationService,
+    message_service: Option<MessageService>,
+    username_reuse_grace: Duration,
+    deletion_notice_window: Duration,
+    metrics: Metrics,
+}
+
+impl AccountService {
+    #[must_use]
+    pub fn new(
+        pool: DbPool,
+        user_repo: UserRepository,
+        device_repo: DeviceRepository,
+        notifier: NotificationService,
+        username_reuse_grace_days: i64,
+        deletion_notice_window_hours: i64,
+    ) -> Self {
+        Self {
+            pool,
+            user_repo,
+            device_repo,
+            notifier,
+            message_service: None,
+            username_reuse_grace: Duration::days(username_reuse_grace_days.max(0)),
+            deletion_notice_window: Duration::hours(deletion_notice_window_hours.max(0)),
+            metrics: Metrics::new(),
+        }
+    }
+
+    /// Tells senders of recent pending messages when their recipient's account is deleted.
+    #[must_use]
+    pub(crate) fn with_message_service(mut self, message_service: MessageService) -> Self {
+        self.message_service = Some(message_service);
+        self
+    }
+
+    /// Deletes an account and everything attached to it, leaving a tombstone that reserves
+    /// the username for the grace period. Connected devices are disconnected, and other devices
+    /// with recent messages still pending for the account receive a `RecipientGone` notice.
+    ///
+    /// # Errors
+    /// Returns `AppError::NotFound` if the user does not exist.
+    /// Returns `AppError::Database` if the deletion fails.
+    #[tracing::instrument(skip_all, fields(user.id = %telemetry::id(user_id)), err(level = "warn"))]
+    pub(crate) async fn delete_account(&self, user_id: Uuid) -> Result<()> {
+        let mut tx = self.pool.begin_timed().await?;
+        let device_ids: Vec<Uuid> =
+            self.device_repo.find_by_user(&mut tx, user_id).await?.into_iter().map(|d| d.id).collect();
+
+        // Senders are found before the delete cascades away the messages they are waiting on
+        let notices = match &self.message_service {
+            Some(messages) if !self.deletion_notice_window.is_zero() && !device_ids.is_empty() => {
+                let since = OffsetDateTime::now_utc() - self.deletion_notice_window;
+                messages.queue_recipient_gone(&mut tx, user_id, &device_ids, since).await?
+            }
+            _ => Vec::new(),
+        };
+
+        let user = self.user_repo.delete(&mut tx, user_id).await?.ok_or(AppError::NotFound)?;
+        let reusable_at = OffsetDateTime::now_utc() + self.username_reuse_grace;
+        self.user_repo.create_tombstone(&mut tx, user_id, &user.username, reusable_at).await?;
+        self.user_repo.create_tombstone_notices(&mut tx, user_id, &notices).await?;
+        tx.commit().await?;
+
+        self.notifier.notify(&device_ids, UserEvent::Disconnect).await;
+        if !notices.is_empty() {
+            let senders: Vec<Uuid> = notices.iter().map(|(device_id, _)| *device_id).collect();
+            self.notifier.notify(&senders, UserEvent::MessageReceived).await;
+            self.metrics.recipient_gone_notices.add(senders.len() as u64, &[]);
+        }
+
+        tracing::info!(devices = device_ids.len(), notified_senders = notices.len(), "Account deleted");
+        self.metrics.accounts_deleted.add(1, &[]);
+        Ok(())
+    }
+
+    /// Distinguishes a deleted account from one that never existed.
+    ///
+    /// # Errors
+    /// Returns `AppError::Gone` if `user_id` belonged to a deleted account.
+    /// Returns `AppError::Database` if the lookup fails.
+    #[tracing::instrument(skip_all, fields(user.id = %telemetry::id(user_id)), err(level = "debug"))]
+    pub(crate) async fn ensure_not_deleted(&self, user_id: Uuid) -> Result<()> {
+        let mut conn = self.pool.acquire_timed().await?;
+        if self.user_repo.is_deleted(&mut conn, user_id).await? {
+            return Err(AppError::Gone("Account deleted".to_string()));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,220 @@
+use crate::adapters::database::DbPool;
+use crate::adapters::database::announcement_repo::AnnouncementRepository;
+use crate::adapters::database::instrumentation::TimedAcquire;
+use crate::config::AnnouncementConfig;
+use crate::domain::announcement::{Announcement, AnnouncementKind};
+use crate::domain::notification::UserEvent;
+use crate::error::{AppError, Result};
+use crate::proto::obscura::v1 as proto;
+use crate::services::notification_service::NotificationService;
+use crate::telemetry;
+use anyhow::Context;
+use base64::Engine;
+use ed25519_dalek::{Signer, SigningKey};
+use opentelemetry::{KeyValue, global, metrics::Counter};
+use prost::Message;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+
+/// Longest announcement body accepted, in characters.
+const MAX_BODY_CHARS: usize = 4096;
+
+/// Label mixed into the JWT secret when no announcement signing key is configured,
+/// so the derived key is never the raw secret.
+const DERIVED_KEY_LABEL: &[u8] = b"obscura-announcement-signing-key:";
+
+#[derive(Clone, Debug)]
+struct Metrics {
+    broadcast_total: Counter<u64>,
+    delivered_total: Counter<u64>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let meter = global::meter("obscura-server");
+        Self {
+            broadcast_total: meter
+                .u64_counter("obscura_announcements_broadcast_total")
+                .with_description("Announcements broadcast through the management API, labelled by kind")
+                .build(),
+            delivered_total: meter
+                .u64_counter("obscura_announcements_delivered_total")
+                .with_description("Announcements handed to users, labelled by route (live frame or queued envelope)")
+                .build(),
+        }
+    }
+}
+
+/// Signed server announcements broadcast to every user.
+///
+/// Connected sessions are woken to claim the announcement as a gateway frame. A receipt per
+/// user makes the first claim win, so a user sees each announcement once however many devices
+/// or instances are involved; `AnnouncementWorker` later queues it for everyone left.
+#[derive(Clone, Debug)]
+pub struct AnnouncementService {
+    pool: DbPool,
+    repo: AnnouncementRepository,
+    notifier: NotificationService,
+    signing_key: Arc<SigningKey>,
+    default_ttl: Duration,
+    metrics: Metrics,
+}
+
+impl AnnouncementService {
+    #[must_use]
+    pub fn new(
+        pool: DbPool,
+        repo: AnnouncementRepository,
+        notifier: NotificationService,
+        signing_key: SigningKey,
+        config: &AnnouncementConfig,
+    ) -> Self {
+        Self {
+            pool,
+            repo,
+            notifier,
+            signing_key: Arc::new(signing_key),
+            default_ttl: Duration::seconds(i64::try_from(config.ttl_secs).unwrap_or(i64::MAX)),
+            metrics: Metrics::new(),
+        }
+    }
+
+    /// Loads the configured signing key, or derives one from `fallback_secret` when none is set.
+    ///
+    /// # Errors
+    /// Returns an error if the configured key is not a base64-encoded 32-byte seed.
+    pub fn signing_key(config: &AnnouncementConfig, fallback_secret: &str) -> anyhow::Result<SigningKey> {
+        let Some(encoded) = &config.signing_key else {
+            let mut hasher = Sha256::new();
+            hasher.update(DERIVED_KEY_LABEL);
+            hasher.update(fallback_secret.as_bytes());
+            return Ok(SigningKey::from_bytes(&hasher.finalize().into()));
+        };
+
+        let seed = base64::engine::general_purpose::STANDARD
+            .decode(encoded.trim())
+            .context("Invalid announcement signing key: not base64")?;
+        let seed = <[u8; 32]>::try_from(seed)
+            .map_err(|_| anyhow::anyhow!("Invalid announcement signing key: expected a 32-byte seed"))?;
+        Ok(SigningKey::from_bytes(&seed))
+    }
+
+    /// The Ed25519 public key clients use to verify announcements.
+    #[must_use]
+    pub fn public_key(&self) -> [u8; 32] {
+        self.signing_key.verifying_key().to_bytes()
+    }
+
+    /// Signs and stores an announcement, then wakes every connected session to deliver it.
+    ///
+    /// # Errors
+    /// Returns `AppError::BadRequest` if the body is empty or too long, or the lifetime is zero.
+    /// Returns `AppError::Database` if the announcement cannot be stored.
+    #[tracing::instrument(skip_all, fields(announcement.kind = %kind, ?ttl_secs), err(level = "warn"))]
+    pub async fn broadcast(&self, kind: AnnouncementKind, body: &str, ttl_secs: Option<u64>) -> Result<Announcement> {
+        let body = body.trim();
+        if body.is_empty() {
+            return Err(AppError::BadRequest("Announcement body must not be empty".into()));
+        }
+        if body.chars().count() > MAX_BODY_CHARS {
+            return Err(AppError::BadRequest(format!("Announcement body exceeds {MAX_BODY_CHARS} characters")));
+        }
+        let ttl = match ttl_secs {
+            Some(0) => return Err(AppError::BadRequest("ttlSecs must be positive".into())),
+            Some(secs) => Duration::seconds(i64::try_from(secs).unwrap_or(i64::MAX)),
+            None => self.default_ttl,
+        };
+
+        let id = Uuid::now_v7();
+        let created_at = OffsetDateTime::now_utc();
+        let expires_at = created_at.saturating_add(ttl);
+
+        let announcement = proto::Announcement {
+            id: id.as_bytes().to_vec(),
+            kind: proto_kind(kind) as i32,
+            body: body.to_string(),
+            timestamp: unix_millis(created_at),
+            expires_at: unix_millis(expires_at),
+        }
+        .encode_to_vec();
+        let signature = self.signing_key.sign(&announcement).to_bytes().to_vec();
+        let payload = proto::SignedAnnouncement { announcement, signature: signature.clone() }.encode_to_vec();
+
+        {
+            let mut conn = self.pool.acquire_timed().await?;
+            self.repo.create(&mut conn, id, &payload, created_at, expires_at).await?;
+        }
+
+        self.notifier.broadcast(UserEvent::Announcement).await;
+
+        tracing::info!(announcement.id = %id, "Announcement broadcast");
+        self.metrics.broadcast_total.add(1, &[KeyValue::new("kind", kind.to_string())]);
+
+        Ok(Announcement { id, kind, body: body.to_string(), created_at, expires_at, signature })
+    }
+
+    /// Claims the live announcements the owner of `device_id` has not received yet.
+    /// Returns each as an encoded `SignedAnnouncement`, oldest first.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if the query fails.
+    #[tracing::instrument(skip_all, fields(device.id = %telemetry::id(device_id)), err)]
+    pub(crate) async fn claim_pending(&self, device_id: Uuid) -> Result<Vec<Vec<u8>>> {
+        let mut conn = self.pool.acquire_timed().await?;
+        let claimed = self.repo.claim_for_device(&mut conn, device_id).await?;
+
+        if !claimed.is_empty() {
+            self.metrics.delivered_total.add(claimed.len() as u64, &[KeyValue::new("route", "live")]);
+        }
+        Ok(claimed)
+    }
+}
+
+const fn proto_kind(kind: AnnouncementKind) -> proto::announcement::Kind {
+    match kind {
+        AnnouncementKind::Notice => proto::announcement::Kind::Notice,
+        AnnouncementKind::Maintenance => proto::announcement::Kind::Maintenance,
+        AnnouncementKind::Deprecation => proto::announcement::Kind::Deprecation,
+    }
+}
+
+fn unix_millis(ts: OffsetDateTime) -> u64 {
+    u64::try_from(ts.unix_timestamp_nanos() / 1_000_000).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::Verifier;
+
+    #[test]
+    fn test_signing_key_is_derived_from_fallback_secret() {
+        let config = AnnouncementConfig::default();
+        let a = AnnouncementService::signing_key(&config, "secret-a").expect("derived key");
+        let b = AnnouncementService::signing_key(&config, "secret-b").expect("derived key");
+        assert_eq!(
+            a.to_bytes(),
+            AnnouncementService::signing_key(&config, "secret-a").expect("derived key").to_bytes()
+        );
+        assert_ne!(a.to_bytes(), b.to_bytes());
+
+        let message = b"maintenance at noon";
+        assert!(a.verifying_key().verify(message, &a.sign(message)).is_ok());
+    }
+
+    #[test]
+    fn test_configured_signing_key_must_be_a_seed() {
+        let seed = [7u8; 32];
+        let config = AnnouncementConfig {
+            signing_key: Some(base64::engine::general_purpose::STANDARD.encode(seed)),
+            ..AnnouncementConfig::default()
+        };
+        let key = AnnouncementService::signing_key(&config, "ignored").expect("valid seed");
+        assert_eq!(key.to_bytes(), seed);
+
+        let config = AnnouncementConfig { signing_key: Some("AAAA".into()), ..AnnouncementConfig::default() };
+        assert!(AnnouncementService::signing_key(&config, "ignored").is_err());
+    }
+}
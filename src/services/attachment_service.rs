@@ -1,8 +1,14 @@
 use crate::adapters::database::DbPool;
 use crate::adapters::database::attachment_repo::AttachmentRepository;
+use crate::adapters::database::instrumentation::TimedAcquire;
 use crate::adapters::storage::{ObjectStorage, StorageError, StorageStream};
-use crate::config::AttachmentConfig;
+use crate::config::{AttachmentConfig, TierLimitTable};
+use crate::domain::attachment;
+use crate::domain::user::UserTier;
 use crate::error::{AppError, Result};
+use crate::services::gateway::upload_progress::UploadProgress;
+use crate::services::upload_guard::UploadGuard;
+use crate::telemetry;
 use opentelemetry::{
     global,
     metrics::{Counter, Histogram},
@@ -15,6 +21,7 @@ use uuid::Uuid;
 pub(crate) struct Metrics {
     pub(crate) uploaded_bytes: Counter<u64>,
     pub(crate) upload_size_bytes: Histogram<u64>,
+    pub(crate) abandoned_uploads_total: Counter<u64>,
 }
 
 impl Metrics {
@@ -29,6 +36,12 @@ impl Metrics {
                 .u64_histogram("obscura_attachment_upload_size_bytes")
                 .with_description("Distribution of attachment upload sizes")
                 .build(),
+            abandoned_uploads_total: meter
+                .u64_counter("obscura_attachment_uploads_abandoned_total")
+                .with_description(
+                    "Attachment uploads dropped before they were recorded, whose object was deleted right away",
+                )
+                .build(),
         }
     }
 }
@@ -39,7 +52,9 @@ pub struct AttachmentService {
     repo: AttachmentRepository,
     storage: Arc<dyn ObjectStorage>,
     attachment_config: AttachmentConfig,
+    tier_limits: TierLimitTable,
     ttl_days: i64,
+    upload_progress: Option<UploadProgress>,
     metrics: Metrics,
 }
 
@@ -60,54 +75,134 @@ impl AttachmentService {
         repo: AttachmentRepository,
         storage: Arc<dyn ObjectStorage>,
         attachment_config: AttachmentConfig,
+        tier_limits: TierLimitTable,
         ttl_days: i64,
     ) -> Self {
-        Self { pool, repo, storage, attachment_config, ttl_days, metrics: Metrics::new() }
+        Self {
+            pool,
+            repo,
+            storage,
+            attachment_config,
+            tier_limits,
+            ttl_days,
+            upload_progress: None,
+            metrics: Metrics::new(),
+        }
+    }
+
+    /// Reports upload progress to the uploading device's gateway session.
+    #[must_use]
+    pub(crate) fn with_upload_progress(mut self, upload_progress: UploadProgress) -> Self {
+        self.upload_progress = Some(upload_progress);
+        self
     }
 
-    /// Uploads an attachment to storage.
+    /// Uploads an attachment to storage on behalf of `owner` from `device`, up to the largest size
+    /// `tier` allows.
+    ///
+    /// With a `sha256` the object is stored content-addressed, so later uploads of the same
+    /// bytes can be registered through [`Self::register_by_digest`] instead. With
+    /// `report_progress` and a known length, progress is reported to the device's gateway session.
     ///
     /// # Errors
     /// Returns `AppError::BadRequest` if the attachment is too small.
     /// Returns `AppError::PayloadTooLarge` if the attachment is too large.
+    /// Returns `AppError::UnprocessableEntity` if the content does not match `sha256`.
     /// Returns `AppError::Internal` if there is an error during upload or database operation.
     #[tracing::instrument(
         err(level = "warn"),
-        skip(self, stream),
-        fields(attachment_id = tracing::field::Empty, attachment_size = tracing::field::Empty)
+        skip_all,
+        fields(
+            attachment_id = tracing::field::Empty,
+            attachment_size = tracing::field::Empty,
+            user.id = %telemetry::id(owner),
+            device.id = device.map(telemetry::id).map(tracing::field::display),
+            ?tier,
+            ?content_len,
+            ?sha256,
+            %report_progress
+        )
     )]
-    pub(crate) async fn upload(&self, content_len: Option<usize>, stream: StorageStream) -> Result<(Uuid, i64)> {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) async fn upload(
+        &self,
+        owner: Uuid,
+        device: Option<Uuid>,
+        tier: UserTier,
+        content_len: Option<usize>,
+        sha256: Option<[u8; 32]>,
+        stream: StorageStream,
+        report_progress: bool,
+    ) -> Result<(Uuid, i64)> {
+        let max_size_bytes = self.tier_limits.get(tier).attachment_max_size_bytes;
         if let Some(len) = content_len {
             tracing::Span::current().record("attachment_size", len);
             if len < self.attachment_config.min_size_bytes {
                 return Err(AppError::BadRequest("Attachment too small".into()));
             }
-            if len > self.attachment_config.max_size_bytes {
+            if len > max_size_bytes {
                 return Err(AppError::PayloadTooLarge);
             }
         }
 
-        let id = Uuid::new_v4();
-        let key = format!("{}{}", self.attachment_config.prefix, id);
+        let id = Uuid::now_v7();
+        let key = attachment::storage_key(&self.attachment_config.prefix, id, sha256.as_ref());
         tracing::Span::current().record("attachment_id", tracing::field::display(id));
 
-        let put_future = self.storage.put(
-            &key,
-            stream,
-            content_len,
-            self.attachment_config.min_size_bytes,
-            self.attachment_config.max_size_bytes,
-        );
-
-        let actual_len = put_future.await.map_err(|e| match e {
-            StorageError::ExceedsLimit => AppError::PayloadTooLarge,
-            StorageError::BelowMinSize => AppError::BadRequest("Attachment too small".into()),
-            _ => AppError::Internal,
-        })?;
+        let stream = match (&self.upload_progress, device.filter(|_| report_progress), content_len) {
+            (Some(upload_progress), Some(device_id), Some(len)) => upload_progress.track(device_id, id, len, stream),
+            _ => stream,
+        };
 
+        // A content-addressed object may already back other attachments, so it is never deleted
+        // here: its pending row is recorded first, under the digest lock the cleanup worker also
+        // takes, so the object counts as referenced while it is written. A dropped upload only
+        // removes that row and leaves unreferenced content to the cleanup and audit.
         let expires_at = OffsetDateTime::now_utc() + Duration::days(self.ttl_days);
-        let mut conn = self.pool.acquire().await?;
-        self.repo.create(&mut conn, id, expires_at).await?;
+        let guard = match sha256 {
+            None => self.delete_on_drop(key.clone()),
+            Some(digest) => {
+                let mut tx = self.pool.begin_timed().await?;
+                self.repo.lock_digests(&mut tx, &[digest.to_vec()]).await?;
+                self.repo.create_pending(&mut tx, id, owner, device, expires_at, &digest).await?;
+                tx.commit().await?;
+                self.discard_on_drop(id)
+            }
+        };
+
+        let put_future =
+            self.storage.put(&key, stream, content_len, self.attachment_config.min_size_bytes, max_size_bytes, sha256);
+
+        let actual_len = match put_future.await {
+            Ok(len) => len,
+            Err(e) => {
+                // The storage backend already removed a failed object of our own; a pending row
+                // for shared content is removed here.
+                guard.disarm();
+                if sha256.is_some() {
+                    self.discard_pending(id).await;
+                }
+                return Err(match e {
+                    StorageError::ExceedsLimit => AppError::PayloadTooLarge,
+                    StorageError::Unavailable => AppError::ServiceUnavailable,
+                    StorageError::TimedOut => AppError::GatewayTimeout,
+                    StorageError::BelowMinSize => AppError::BadRequest("Attachment too small".into()),
+                    StorageError::ChecksumMismatch => {
+                        AppError::UnprocessableEntity("Content does not match X-Content-SHA256".into())
+                    }
+                    _ => AppError::Internal,
+                });
+            }
+        };
+
+        let mut conn = self.pool.acquire_timed().await?;
+        let size_bytes = i64::try_from(actual_len).unwrap_or(i64::MAX);
+        if sha256.is_some() {
+            self.repo.finish_pending(&mut conn, id, size_bytes).await?;
+        } else {
+            self.repo.create(&mut conn, id, owner, device, size_bytes, expires_at).await?;
+        }
+        guard.disarm();
 
         tracing::debug!(attachment_id = %id, expires_at = %expires_at, "Attachment uploaded");
 
@@ -117,30 +212,111 @@ impl AttachmentService {
         Ok((id, expires_at.unix_timestamp()))
     }
 
+    /// Deletes the object stored under `key` if the upload is dropped before the attachment is
+    /// recorded, rather than leaving it to the orphan cleanup.
+    fn delete_on_drop(&self, key: String) -> UploadGuard {
+        let storage = Arc::clone(&self.storage);
+        UploadGuard::new(self.metrics.abandoned_uploads_total.clone(), async move {
+            if let Err(e) = storage.delete(&key).await {
+                tracing::warn!(error = %e, key = %key, "Failed to delete abandoned attachment upload");
+            }
+        })
+    }
+
+    /// Removes the pending row of a content-addressed upload if the upload is dropped before it
+    /// completes, so the content no longer counts as referenced by it.
+    fn discard_on_drop(&self, id: Uuid) -> UploadGuard {
+        let service = self.clone();
+        UploadGuard::new(self.metrics.abandoned_uploads_total.clone(), async move { service.discard_pending(id).await })
+    }
+
+    async fn discard_pending(&self, id: Uuid) {
+        let result = async {
+            let mut conn = self.pool.acquire_timed().await?;
+            self.repo.delete_pending(&mut conn, id).await
+        }
+        .await;
+        if let Err(e) = result {
+            tracing::warn!(error = %e, attachment_id = %id, "Failed to remove pending attachment, left to expire");
+        }
+    }
+
+    /// Registers a new attachment for `owner` from `device` with content that is already stored,
+    /// skipping the upload.
+    ///
+    /// Returns `None` if no live attachment holds content with this digest.
+    ///
+    /// # Errors
+    /// Returns `AppError::Internal` if the database operation fails.
+    #[tracing::instrument(
+        err(level = "warn"),
+        skip_all,
+        fields(attachment_id = tracing::field::Empty, user.id = %telemetry::id(owner))
+    )]
+    pub(crate) async fn register_by_digest(
+        &self,
+        owner: Uuid,
+        device: Option<Uuid>,
+        sha256: [u8; 32],
+    ) -> Result<Option<(Uuid, i64)>> {
+        let id = Uuid::now_v7();
+        let expires_at = OffsetDateTime::now_utc() + Duration::days(self.ttl_days);
+
+        let mut tx = self.pool.begin_timed().await?;
+        self.repo.lock_digests(&mut tx, &[sha256.to_vec()]).await?;
+        if !self.repo.create_for_digest(&mut tx, id, owner, device, expires_at, &sha256).await? {
+            return Ok(None);
+        }
+        tx.commit().await?;
+
+        tracing::Span::current().record("attachment_id", tracing::field::display(id));
+        tracing::debug!(attachment_id = %id, expires_at = %expires_at, "Attachment registered for existing content");
+
+        Ok(Some((id, expires_at.unix_timestamp())))
+    }
+
+    /// Extends the expiry of a live attachment for recipients that fetch it late, by `ttl_secs`
+    /// from now or the default attachment lifetime. The expiry never moves earlier and never
+    /// beyond the configured maximum lifetime since the attachment was created.
+    /// Returns the new expiry as a UNIX timestamp.
+    ///
+    /// # Errors
+    /// Returns `AppError::BadRequest` if `ttl_secs` is zero.
+    /// Returns `AppError::NotFound` if the attachment does not exist or has expired.
+    #[tracing::instrument(err(level = "debug"), skip_all, fields(attachment_id = %id, ?ttl_secs))]
+    pub(crate) async fn extend(&self, id: Uuid, ttl_secs: Option<u64>) -> Result<i64> {
+        let ttl = match ttl_secs {
+            Some(0) => return Err(AppError::BadRequest("ttlSecs must be positive".into())),
+            Some(secs) => Duration::seconds(i64::try_from(secs).unwrap_or(i64::MAX)),
+            None => Duration::days(self.ttl_days),
+        };
+        let requested = OffsetDateTime::now_utc().saturating_add(ttl);
+        let max_lifetime = Duration::days(self.attachment_config.max_lifetime_days.max(self.ttl_days));
+
+        let mut conn = self.pool.acquire_timed().await?;
+        let expires_at = self.repo.extend(&mut conn, id, requested, max_lifetime).await?.ok_or(AppError::NotFound)?;
+
+        tracing::debug!(expires_at = %expires_at, "Attachment expiry extended");
+        Ok(expires_at.unix_timestamp())
+    }
+
     /// Downloads an attachment from storage.
     #[tracing::instrument(
         err(level = "warn"),
-        skip(self),
+        skip_all,
         fields(attachment_id = %id, attachment_size = tracing::field::Empty)
     )]
     pub(crate) async fn download(&self, id: Uuid) -> Result<(u64, StorageStream)> {
         // 1. Check Existence & Expiry using Domain Logic
-        let mut conn = self.pool.acquire().await?;
-        match self.repo.find_by_id(&mut conn, id).await? {
-            Some(attachment) => {
-                if attachment.is_expired_at(OffsetDateTime::now_utc()) {
-                    return Err(AppError::NotFound);
-                }
-            }
-            None => return Err(AppError::NotFound),
-        }
+        let mut conn = self.pool.acquire_timed().await?;
+        let attachment = match self.repo.find_by_id(&mut conn, id).await? {
+            Some(attachment) if !attachment.is_expired_at(OffsetDateTime::now_utc()) => attachment,
+            _ => return Err(AppError::NotFound),
+        };
 
         // 2. Stream from Storage
-        let key = format!("{}{}", self.attachment_config.prefix, id);
-        let (content_length, stream) = self.storage.get(&key).await.map_err(|e| match e {
-            StorageError::NotFound => AppError::NotFound,
-            _ => AppError::Internal,
-        })?;
+        let key = attachment.storage_key(&self.attachment_config.prefix);
+        let (content_length, stream) = self.storage.get(&key).await?;
 
         tracing::Span::current().record("attachment_size", content_length);
 
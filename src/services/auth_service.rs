@@ -1,18 +1,23 @@
 use crate::adapters::database::DbPool;
 use crate::adapters::database::device_repo::DeviceRepository;
+use crate::adapters::database::instrumentation::TimedAcquire;
+use crate::adapters::database::push_token_repo::PushTokenRepository;
 use crate::adapters::database::refresh_token_repo::RefreshTokenRepository;
 use crate::adapters::database::user_repo::UserRepository;
 use crate::config::AuthConfig;
 use crate::domain::auth::{Claims, Jwt};
 use crate::domain::auth_session::AuthSession;
-use crate::error::{AppError, Result};
+use crate::domain::user::UserTier;
+use crate::domain::username;
+use crate::error::{AppError, AuthError, Result};
+use crate::telemetry;
 use argon2::{
     Argon2,
     password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString, rand_core::OsRng as PasswordOsRng},
 };
 use base64::Engine;
 use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, encode};
-use opentelemetry::{global, metrics::Counter};
+use opentelemetry::{KeyValue, global, metrics::Counter};
 use sha2::{Digest, Sha256};
 use sqlx::PgConnection;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
@@ -24,6 +29,8 @@ struct Metrics {
     login: Counter<u64>,
     refresh: Counter<u64>,
     logout: Counter<u64>,
+    refresh_tokens_deleted: Counter<u64>,
+    push_tokens_revoked: Counter<u64>,
 }
 
 impl Metrics {
@@ -47,6 +54,14 @@ impl Metrics {
                 .u64_counter("obscura_logouts_total")
                 .with_description("Total number of successful logout attempts")
                 .build(),
+            refresh_tokens_deleted: meter
+                .u64_counter("obscura_refresh_tokens_deleted_total")
+                .with_description("Expired refresh tokens deleted, by what triggered the cleanup")
+                .build(),
+            push_tokens_revoked: meter
+                .u64_counter("obscura_push_tokens_revoked_total")
+                .with_description("Push tokens cleared because their device's last session ended, by reason")
+                .build(),
         }
     }
 }
@@ -58,6 +73,7 @@ pub struct AuthService {
     user_repo: UserRepository,
     refresh_repo: RefreshTokenRepository,
     device_repo: DeviceRepository,
+    push_token_repo: PushTokenRepository,
     metrics: Metrics,
 }
 
@@ -69,25 +85,42 @@ impl AuthService {
         user_repo: UserRepository,
         refresh_repo: RefreshTokenRepository,
         device_repo: DeviceRepository,
+        push_token_repo: PushTokenRepository,
     ) -> Self {
-        Self { config, pool, user_repo, refresh_repo, device_repo, metrics: Metrics::new() }
+        Self { config, pool, user_repo, refresh_repo, device_repo, push_token_repo, metrics: Metrics::new() }
     }
 
-    /// Registers a new user account. Returns a user-only JWT (no `device_id`).
+    /// Registers a new user account under the normalized `username`. Returns a user-only JWT
+    /// (no `device_id`).
     ///
     /// # Errors
-    /// Returns `AppError::Conflict` if the username already exists.
+    /// Returns `AppError::Conflict` if the username already exists, belongs to an account
+    /// deleted within the reuse grace period, or, with the confusable check enabled, reads like
+    /// an existing username.
     /// Returns `AppError::Database` if any of the underlying operations fail.
     #[tracing::instrument(
-        skip(self, username, password),
+        skip_all,
         fields(user.id = tracing::field::Empty),
         err(level = "warn")
     )]
     pub(crate) async fn register(&self, username: String, password: String) -> Result<AuthSession> {
         let password_hash = self.hash_password(&password).await?;
-        let mut tx = self.pool.begin().await?;
+        let mut tx = self.pool.begin_timed().await?;
+        if self.user_repo.is_username_reserved(&mut tx, &username).await? {
+            return Err(AppError::Conflict("Username already exists".into()));
+        }
+        if self.config.username_confusable_check
+            && let Some(existing) =
+                self.user_repo.lock_and_find_by_skeleton(&mut tx, &username::skeleton(&username)).await?
+        {
+            return Err(AppError::Conflict(if existing == username {
+                "Username already exists".into()
+            } else {
+                "Username is too similar to an existing one".into()
+            }));
+        }
         let user = self.user_repo.create(&mut tx, &username, &password_hash).await?;
-        tracing::Span::current().record("user_id", tracing::field::display(user.id));
+        tracing::Span::current().record("user.id", tracing::field::display(telemetry::id(user.id)));
         let session = self.create_session(&mut tx, user.id, None).await?;
         tx.commit().await?;
         tracing::info!("User registered successfully");
@@ -99,10 +132,13 @@ impl AuthService {
     /// Otherwise returns a user-only JWT.
     ///
     /// # Errors
-    /// Returns `AppError::AuthError` if credentials are invalid.
+    /// Returns `AppError::Auth` if credentials are invalid.
     #[tracing::instrument(
-        skip(self, username, password),
-        fields(user.id = tracing::field::Empty),
+        skip_all,
+        fields(
+            user.id = tracing::field::Empty,
+            device.id = device_id.map(telemetry::id).map(tracing::field::display)
+        ),
         err(level = "warn")
     )]
     pub(crate) async fn login(
@@ -111,19 +147,19 @@ impl AuthService {
         password: String,
         device_id: Option<Uuid>,
     ) -> Result<AuthSession> {
-        let mut conn = self.pool.acquire().await?;
+        let mut conn = self.pool.acquire_timed().await?;
         let Some(user) = self.user_repo.find_by_username(&mut conn, &username).await? else {
             tracing::warn!("Login failed: user not found");
-            return Err(AppError::AuthError);
+            return Err(AuthError::InvalidCredentials.into());
         };
 
-        tracing::Span::current().record("user_id", tracing::field::display(user.id));
+        tracing::Span::current().record("user.id", tracing::field::display(telemetry::id(user.id)));
 
         let is_valid = self.verify_password(&password, &user.password_hash).await?;
 
         if !is_valid {
             tracing::warn!("Login failed: invalid password");
-            return Err(AppError::AuthError);
+            return Err(AuthError::InvalidCredentials.into());
         }
 
         // If device_id provided, validate it belongs to this user
@@ -131,7 +167,7 @@ impl AuthService {
             if self.device_repo.belongs_to_user(&mut conn, did, user.id).await? {
                 Some(did)
             } else {
-                tracing::warn!(device_id = %did, "Login with unknown device_id, issuing user-only JWT");
+                tracing::warn!(device.id = %telemetry::id(did), "Login with unknown device_id, issuing user-only JWT");
                 None
             }
         } else {
@@ -148,7 +184,7 @@ impl AuthService {
     ///
     /// # Errors
     /// Returns `AppError::Internal` if hashing fails.
-    #[tracing::instrument(err, skip(self, password))]
+    #[tracing::instrument(err, skip_all)]
     pub(crate) async fn hash_password(&self, password: &str) -> Result<String> {
         let password = password.to_string();
         tokio::task::spawn_blocking(move || {
@@ -164,7 +200,7 @@ impl AuthService {
     ///
     /// # Errors
     /// Returns `AppError::Internal` if verification logic fails.
-    #[tracing::instrument(err, skip(self, password, password_hash))]
+    #[tracing::instrument(err, skip_all)]
     pub(crate) async fn verify_password(&self, password: &str, password_hash: &str) -> Result<bool> {
         let password = password.to_string();
         let password_hash = password_hash.to_string();
@@ -176,11 +212,19 @@ impl AuthService {
         .map_err(|_| AppError::Internal)?
     }
 
-    /// Creates a new authenticated session with optional `device_id`.
+    /// Creates a new authenticated session with optional `device_id`. The access token carries
+    /// the user's current tier.
     ///
     /// # Errors
     /// Returns `AppError::Database` if the session cannot be saved.
-    #[tracing::instrument(err, skip(self, conn), fields(user.id = %user_id))]
+    #[tracing::instrument(
+        err,
+        skip_all,
+        fields(
+            user.id = %telemetry::id(user_id),
+            device.id = device_id.map(telemetry::id).map(tracing::field::display)
+        )
+    )]
     pub(crate) async fn create_session(
         &self,
         conn: &mut PgConnection,
@@ -192,7 +236,8 @@ impl AuthService {
         let exp = now.checked_add(self.config.access_token_ttl_secs).ok_or(AppError::Internal)?;
         let exp_usize = usize::try_from(exp).map_err(|_| AppError::Internal)?;
 
-        let claims = Claims::new(user_id, device_id, exp_usize);
+        let tier = self.user_repo.find_tier(conn, user_id).await?.unwrap_or_default();
+        let claims = Claims::new(user_id, device_id, exp_usize, tier);
         let jwt = self.encode_jwt(&claims)?;
 
         let refresh_token = Self::generate_opaque_token();
@@ -208,13 +253,14 @@ impl AuthService {
         })
     }
 
-    /// Refreshes an existing session. Preserves the `device_id` from the original refresh token.
+    /// Refreshes an existing session. Preserves the `device_id` from the original refresh token
+    /// and picks up the user's current tier.
     ///
     /// # Errors
-    /// Returns `AppError::AuthError` if the refresh token is invalid.
-    #[tracing::instrument(err, skip(self, refresh_token))]
+    /// Returns `AppError::Auth` if the refresh token is invalid.
+    #[tracing::instrument(err, skip_all, fields(user.id = tracing::field::Empty))]
     pub(crate) async fn refresh_session(&self, refresh_token: String) -> Result<AuthSession> {
-        let mut conn = self.pool.acquire().await?;
+        let mut conn = self.pool.acquire_timed().await?;
         let old_hash = Self::hash_opaque_token(&refresh_token);
         let new_refresh_token = Self::generate_opaque_token();
         let new_hash = Self::hash_opaque_token(&new_refresh_token);
@@ -223,16 +269,17 @@ impl AuthService {
             .refresh_repo
             .rotate_unexpired(&mut conn, &old_hash, &new_hash, self.config.refresh_token_ttl_days)
             .await?
-            .ok_or(AppError::AuthError)?;
+            .ok_or(AuthError::InvalidToken)?;
 
-        tracing::Span::current().record("user_id", tracing::field::display(user_id));
+        tracing::Span::current().record("user.id", tracing::field::display(telemetry::id(user_id)));
 
         let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::from_secs(0)).as_secs();
 
         let exp = now.checked_add(self.config.access_token_ttl_secs).ok_or(AppError::Internal)?;
         let exp_usize = usize::try_from(exp).map_err(|_| AppError::Internal)?;
 
-        let claims = Claims::new(user_id, device_id, exp_usize);
+        let tier = self.user_repo.find_tier(&mut conn, user_id).await?.unwrap_or_default();
+        let claims = Claims::new(user_id, device_id, exp_usize, tier);
         let new_jwt = self.encode_jwt(&claims)?;
 
         tracing::info!("Tokens rotated successfully");
@@ -246,32 +293,94 @@ impl AuthService {
         })
     }
 
-    /// Logs out a user by deleting their refresh token.
+    /// Moves a user to another tier. Access tokens already issued keep the old tier until they
+    /// are refreshed.
+    ///
+    /// # Errors
+    /// Returns `AppError::NotFound` if the user does not exist.
+    /// Returns `AppError::Database` if the update fails.
+    #[tracing::instrument(err(level = "warn"), skip_all, fields(user.id = %telemetry::id(user_id), ?tier))]
+    pub(crate) async fn set_user_tier(&self, user_id: Uuid, tier: UserTier) -> Result<()> {
+        let mut conn = self.pool.acquire_timed().await?;
+        if !self.user_repo.set_tier(&mut conn, user_id, tier).await? {
+            return Err(AppError::NotFound);
+        }
+        tracing::info!(%tier, "User tier changed");
+        Ok(())
+    }
+
+    /// Logs out a user by deleting their refresh token. If that was the last session of its
+    /// device, the device's push token is cleared too so it stops getting wakeups.
     ///
     /// # Errors
     /// Returns `AppError::Database` if the token cannot be deleted.
-    #[tracing::instrument(err, skip(self, refresh_token), fields(user.id = %user_id))]
+    #[tracing::instrument(err, skip_all, fields(user.id = %telemetry::id(user_id)))]
     pub(crate) async fn logout(&self, user_id: Uuid, refresh_token: String) -> Result<()> {
-        let mut conn = self.pool.acquire().await?;
+        let mut tx = self.pool.begin_timed().await?;
         let hash = Self::hash_opaque_token(&refresh_token);
-        self.refresh_repo.delete_owned(&mut conn, &hash, user_id).await?;
+        let device_id = self.refresh_repo.delete_owned(&mut tx, &hash, user_id).await?;
+        let revoked = match device_id {
+            Some(device_id) => self.push_token_repo.delete_for_signed_out_devices(&mut tx, &[device_id]).await?,
+            None => 0,
+        };
+        tx.commit().await?;
+
         self.metrics.logout.add(1, &[]);
+        self.metrics.push_tokens_revoked.add(revoked, &[KeyValue::new("reason", "logout")]);
         Ok(())
     }
 
-    /// Verifies a JWT access token and returns (`user_id`, `device_id`).
+    /// Deletes expired refresh tokens in batches of `--auth-refresh-token-cleanup-batch-size`,
+    /// stopping after `--auth-refresh-token-cleanup-max-batches` so one run never holds the
+    /// database for long; whatever is left goes in the next run. Returns how many were deleted.
+    /// Devices left without an unexpired session lose their push token in the same batch.
+    ///
+    /// `trigger` labels the deleted-count metric, e.g. `scheduled` or `manual`.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if a batch fails. Batches deleted before it stay deleted.
+    #[tracing::instrument(skip_all, err, fields(deleted = tracing::field::Empty, %trigger))]
+    pub async fn purge_expired_refresh_tokens(&self, trigger: &'static str) -> Result<u64> {
+        let batch_size = self.config.refresh_token_cleanup_batch_size;
+        let mut total = 0;
+        for _ in 0..self.config.refresh_token_cleanup_max_batches {
+            let mut tx = self.pool.begin_timed().await?;
+            let devices = self.refresh_repo.delete_expired(&mut tx, batch_size).await?;
+            let mut device_ids: Vec<Uuid> = devices.iter().flatten().copied().collect();
+            device_ids.sort_unstable();
+            device_ids.dedup();
+            let revoked = self.push_token_repo.delete_for_signed_out_devices(&mut tx, &device_ids).await?;
+            tx.commit().await?;
+
+            let deleted = devices.len() as u64;
+            total += deleted;
+            self.metrics.refresh_tokens_deleted.add(deleted, &[KeyValue::new("trigger", trigger)]);
+            self.metrics.push_tokens_revoked.add(revoked, &[KeyValue::new("reason", "expired")]);
+            if deleted < u64::try_from(batch_size).unwrap_or(0) {
+                break;
+            }
+        }
+
+        if total > 0 {
+            tracing::info!(count = %total, "Deleted expired refresh tokens");
+        }
+        tracing::Span::current().record("deleted", total);
+        Ok(total)
+    }
+
+    /// Verifies a JWT access token and returns its claims (user, device and expiry).
     ///
     /// # Errors
-    /// Returns `AppError::AuthError` if the token is invalid or expired.
-    pub(crate) fn verify_token(&self, jwt: &Jwt) -> Result<(Uuid, Option<Uuid>)> {
+    /// Returns `AppError::Auth` if the token is invalid or expired.
+    pub(crate) fn verify_token(&self, jwt: &Jwt) -> Result<Claims> {
         let token_data = decode::<Claims>(
             jwt.as_str(),
             &DecodingKey::from_secret(self.config.jwt_secret.as_bytes()),
             &Validation::default(),
         )
-        .map_err(|_| AppError::AuthError)?;
+        .map_err(|_| AuthError::InvalidToken)?;
 
-        Ok((token_data.claims.sub, token_data.claims.device_id))
+        Ok(token_data.claims)
     }
 
     fn encode_jwt(&self, claims: &Claims) -> Result<Jwt> {
@@ -297,6 +406,7 @@ impl AuthService {
 mod tests {
     use super::*;
     use crate::adapters::database::device_repo::DeviceRepository;
+    use crate::adapters::database::push_token_repo::PushTokenRepository;
     use crate::adapters::database::refresh_token_repo::RefreshTokenRepository;
     use crate::config::AuthConfig;
 
@@ -309,7 +419,14 @@ mod tests {
             max_devices_per_user: 10,
         };
         let pool = sqlx::PgPool::connect_lazy("postgres://localhost/test").expect("Valid test pool");
-        AuthService::new(config, pool, UserRepository::new(), RefreshTokenRepository::new(), DeviceRepository::new())
+        AuthService::new(
+            config,
+            pool,
+            UserRepository::new(),
+            RefreshTokenRepository::new(),
+            DeviceRepository::new(),
+            PushTokenRepository::new(),
+        )
     }
 
     #[tokio::test]
@@ -318,13 +435,15 @@ mod tests {
         let user_id = Uuid::new_v4();
         let device_id = Some(Uuid::new_v4());
         let exp = 10_000_000_000;
-        let claims = Claims::new(user_id, device_id, exp);
+        let claims = Claims::new(user_id, device_id, exp, UserTier::Paid);
 
         let jwt = service.encode_jwt(&claims).expect("Failed to encode JWT");
-        let (decoded_user, decoded_device) = service.verify_token(&jwt).expect("Failed to verify valid token");
+        let decoded = service.verify_token(&jwt).expect("Failed to verify valid token");
 
-        assert_eq!(user_id, decoded_user);
-        assert_eq!(device_id, decoded_device);
+        assert_eq!(user_id, decoded.sub);
+        assert_eq!(device_id, decoded.device_id);
+        assert_eq!(exp, decoded.exp);
+        assert_eq!(decoded.tier, UserTier::Paid);
     }
 
     #[tokio::test]
@@ -332,13 +451,14 @@ mod tests {
         let service = setup_service();
         let user_id = Uuid::new_v4();
         let exp = 10_000_000_000;
-        let claims = Claims::new(user_id, None, exp);
+        let claims = Claims::new(user_id, None, exp, UserTier::Free);
 
         let jwt = service.encode_jwt(&claims).expect("Failed to encode JWT");
-        let (decoded_user, decoded_device) = service.verify_token(&jwt).expect("Failed to verify valid token");
+        let decoded = service.verify_token(&jwt).expect("Failed to verify valid token");
 
-        assert_eq!(user_id, decoded_user);
-        assert_eq!(decoded_device, None);
+        assert_eq!(user_id, decoded.sub);
+        assert_eq!(decoded.device_id, None);
+        assert_eq!(decoded.tier, UserTier::Free);
     }
 
     #[tokio::test]
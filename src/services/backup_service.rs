@@ -1,9 +1,13 @@
 use crate::adapters::database::DbPool;
 use crate::adapters::database::backup_repo::BackupRepository;
-use crate::adapters::storage::{ObjectStorage, StorageError, StorageStream};
-use crate::config::BackupConfig;
+use crate::adapters::database::instrumentation::TimedAcquire;
+use crate::adapters::storage::{ObjectInfo, ObjectStorage, StorageError, StorageStream};
+use crate::config::{BackupConfig, TierLimitTable};
 use crate::domain::backup::BackupState;
+use crate::domain::user::UserTier;
 use crate::error::{AppError, Result};
+use crate::services::upload_guard::UploadGuard;
+use crate::telemetry;
 use opentelemetry::{
     global,
     metrics::{Counter, Histogram},
@@ -16,6 +20,7 @@ use uuid::Uuid;
 pub(crate) struct Metrics {
     pub(crate) uploaded_bytes: Counter<u64>,
     pub(crate) upload_size_bytes: Histogram<u64>,
+    pub(crate) abandoned_uploads_total: Counter<u64>,
 }
 
 impl Metrics {
@@ -30,6 +35,10 @@ impl Metrics {
                 .u64_histogram("obscura_backup_upload_size_bytes")
                 .with_description("Distribution of backup upload sizes")
                 .build(),
+            abandoned_uploads_total: meter
+                .u64_counter("obscura_backup_uploads_abandoned_total")
+                .with_description("Backup uploads that ended without committing, whose slot was released right away")
+                .build(),
         }
     }
 }
@@ -40,6 +49,7 @@ pub struct BackupService {
     repo: BackupRepository,
     storage: Arc<dyn ObjectStorage>,
     backup_config: BackupConfig,
+    tier_limits: TierLimitTable,
     metrics: Metrics,
 }
 
@@ -59,39 +69,44 @@ impl BackupService {
         repo: BackupRepository,
         storage: Arc<dyn ObjectStorage>,
         backup_config: BackupConfig,
+        tier_limits: TierLimitTable,
     ) -> Self {
-        Self { pool, repo, storage, backup_config, metrics: Metrics::new() }
+        Self { pool, repo, storage, backup_config, tier_limits, metrics: Metrics::new() }
     }
 
-    /// Handles the full backup upload workflow.
+    /// Handles the full backup upload workflow, accepting backups up to the largest size `tier` allows.
     ///
     /// # Errors
     /// Returns `AppError::BadRequest` if the backup is too small.
     /// Returns `AppError::PayloadTooLarge` if the backup is too large.
     /// Returns `AppError::PreconditionFailed` if the version does not match.
     /// Returns `AppError::Conflict` if another upload is in progress.
+    /// Returns `AppError::UnprocessableEntity` if the content does not match `sha256`.
     #[tracing::instrument(
         err(level = "warn"),
-        skip(self, stream),
-        fields(device.id = %device_id, version = %if_match_version)
+        skip_all,
+        fields(device.id = %telemetry::id(device_id), version = %if_match_version, ?tier, ?content_len, ?sha256)
     )]
     pub async fn handle_upload(
         &self,
         device_id: Uuid,
+        tier: UserTier,
         if_match_version: i32,
         content_len: Option<usize>,
+        sha256: Option<[u8; 32]>,
         stream: StorageStream,
     ) -> Result<i32> {
+        let max_size_bytes = self.tier_limits.get(tier).backup_max_size_bytes;
         if let Some(len) = content_len {
             if len < self.backup_config.min_size_bytes {
                 return Err(AppError::BadRequest("Backup too small".into()));
             }
-            if len > self.backup_config.max_size_bytes {
+            if len > max_size_bytes {
                 return Err(AppError::PayloadTooLarge);
             }
         }
 
-        let mut conn = self.pool.acquire().await.map_err(AppError::Database)?;
+        let mut conn = self.pool.acquire_timed().await.map_err(AppError::Database)?;
 
         let _ = self.repo.create_if_not_exists(&mut conn, device_id).await?;
 
@@ -123,32 +138,48 @@ impl BackupService {
 
         let pending_version = backup.pending_version.ok_or(AppError::Internal)?;
         let key = format!("{}{}/v{}", self.backup_config.prefix, device_id, pending_version);
+        let guard = self.release_on_drop(device_id, pending_version, key.clone());
 
-        let put_future = self.storage.put(
-            &key,
-            stream,
-            content_len,
-            self.backup_config.min_size_bytes,
-            self.backup_config.max_size_bytes,
-        );
+        let put_future =
+            self.storage.put(&key, stream, content_len, self.backup_config.min_size_bytes, max_size_bytes, sha256);
 
-        let actual_len = put_future.await.map_err(|e| match e {
-            StorageError::ExceedsLimit => AppError::PayloadTooLarge,
-            StorageError::BelowMinSize => AppError::BadRequest("Backup too small".into()),
-            _ => AppError::Internal,
-        })?;
+        let actual_len = match put_future.await {
+            Ok(len) => len,
+            Err(StorageError::ChecksumMismatch) => {
+                // The object was never committed, so release the slot and let the client retry right away.
+                guard.disarm();
+                let mut conn = self.pool.acquire_timed().await.map_err(AppError::Database)?;
+                self.repo.reset_stale(&mut conn, device_id).await?;
+                return Err(AppError::UnprocessableEntity("Content does not match X-Content-SHA256".into()));
+            }
+            Err(StorageError::TimedOut) => {
+                guard.disarm();
+                let mut conn = self.pool.acquire_timed().await.map_err(AppError::Database)?;
+                self.repo.reset_stale(&mut conn, device_id).await?;
+                return Err(AppError::GatewayTimeout);
+            }
+            Err(e) => {
+                return Err(match e {
+                    StorageError::ExceedsLimit => AppError::PayloadTooLarge,
+                    StorageError::Unavailable => AppError::ServiceUnavailable,
+                    StorageError::BelowMinSize => AppError::BadRequest("Backup too small".into()),
+                    _ => AppError::Internal,
+                });
+            }
+        };
 
-        let mut conn = self.pool.acquire().await.map_err(AppError::Database)?;
-        self.repo.commit_version(&mut conn, device_id, pending_version).await?;
+        let mut conn = self.pool.acquire_timed().await.map_err(AppError::Database)?;
+        let size_bytes = i64::try_from(actual_len).unwrap_or(i64::MAX);
+        let committed = self.repo.commit_version(&mut conn, device_id, pending_version, size_bytes).await?;
+        guard.disarm();
 
         // Record metrics
         self.metrics.uploaded_bytes.add(actual_len, &[]);
         self.metrics.upload_size_bytes.record(actual_len, &[]);
 
-        // Cleanup old version
-        let old_version = backup.current_version;
-        if old_version > 0 {
-            let old_key = format!("{}{}/v{}", self.backup_config.prefix, device_id, old_version);
+        // The replaced version stays restorable; only the one it pushes out of the window is deleted.
+        if committed && let Some(superseded) = backup.previous_version {
+            let old_key = format!("{}{}/v{}", self.backup_config.prefix, device_id, superseded);
             let storage = Arc::clone(&self.storage);
             tokio::spawn(async move {
                 let _ = storage.delete(&old_key).await;
@@ -158,13 +189,36 @@ impl BackupService {
         Ok(pending_version)
     }
 
+    /// Releases the reserved slot and deletes whatever was stored under `key` if the upload is
+    /// dropped before it commits, so the device can upload again without waiting out
+    /// `stale_threshold_mins`.
+    fn release_on_drop(&self, device_id: Uuid, pending_version: i32, key: String) -> UploadGuard {
+        let pool = self.pool.clone();
+        let repo = self.repo.clone();
+        let storage = Arc::clone(&self.storage);
+        UploadGuard::new(self.metrics.abandoned_uploads_total.clone(), async move {
+            let _ = storage.delete(&key).await;
+            let released = match pool.acquire_timed().await {
+                Ok(mut conn) => repo.release_slot(&mut conn, device_id, pending_version).await,
+                Err(e) => Err(AppError::Database(e)),
+            };
+            if let Err(e) = released {
+                tracing::warn!(
+                    error = %e,
+                    device.id = %telemetry::id(device_id),
+                    "Failed to release abandoned backup slot"
+                );
+            }
+        })
+    }
+
     /// Downloads the current backup for the device.
     ///
     /// # Errors
     /// Returns `AppError::NotFound` if no backup exists or the current version is 0.
-    #[tracing::instrument(err(level = "warn"), skip(self), fields(device.id = %device_id))]
+    #[tracing::instrument(err(level = "warn"), skip_all, fields(device.id = %telemetry::id(device_id)))]
     pub async fn download(&self, device_id: Uuid) -> Result<(i32, u64, StorageStream)> {
-        let mut conn = self.pool.acquire().await.map_err(AppError::Database)?;
+        let mut conn = self.pool.acquire_timed().await.map_err(AppError::Database)?;
         let backup = self.repo.find_by_device_id(&mut conn, device_id).await?;
 
         if let Some(backup) = backup {
@@ -173,10 +227,7 @@ impl BackupService {
             }
 
             let key = format!("{}{}/v{}", self.backup_config.prefix, device_id, backup.current_version);
-            let (len, stream) = self.storage.get(&key).await.map_err(|e| match e {
-                StorageError::NotFound => AppError::NotFound,
-                _ => AppError::Internal,
-            })?;
+            let (len, stream) = self.storage.get(&key).await?;
             tracing::debug!(version = %backup.current_version, size = %len, "Backup download started");
             Ok((backup.current_version, len, stream))
         } else {
@@ -184,13 +235,13 @@ impl BackupService {
         }
     }
 
-    /// Checks for the existence of a backup.
+    /// Checks for the existence of a backup, returning its version and object metadata.
     ///
     /// # Errors
     /// Returns `AppError::NotFound` if no backup exists or the current version is 0.
-    #[tracing::instrument(err(level = "warn"), skip(self), fields(device.id = %device_id))]
-    pub async fn head(&self, device_id: Uuid) -> Result<(i32, u64)> {
-        let mut conn = self.pool.acquire().await.map_err(AppError::Database)?;
+    #[tracing::instrument(err(level = "warn"), skip_all, fields(device.id = %telemetry::id(device_id)))]
+    pub async fn head(&self, device_id: Uuid) -> Result<(i32, ObjectInfo)> {
+        let mut conn = self.pool.acquire_timed().await.map_err(AppError::Database)?;
         let backup = self.repo.find_by_device_id(&mut conn, device_id).await?;
 
         if let Some(backup) = backup {
@@ -199,23 +250,52 @@ impl BackupService {
             }
 
             let key = format!("{}{}/v{}", self.backup_config.prefix, device_id, backup.current_version);
-            let len = self.storage.head(&key).await.map_err(|e| match e {
-                StorageError::NotFound => AppError::NotFound,
-                _ => AppError::Internal,
-            })?;
-            tracing::debug!(version = %backup.current_version, size = %len, "Backup metadata retrieved");
-            Ok((backup.current_version, len))
+            let info = self.storage.head(&key).await?;
+            tracing::debug!(version = %backup.current_version, size = %info.len, "Backup metadata retrieved");
+            Ok((backup.current_version, info))
         } else {
             Err(AppError::NotFound)
         }
     }
 
+    /// Rolls the backup back to the version replaced by the last upload or restore.
+    /// The version rolled back from becomes restorable in turn, so a restore can be undone.
+    ///
+    /// # Errors
+    /// Returns `AppError::NotFound` if there is no version to restore or the restore window has passed.
+    /// Returns `AppError::PreconditionFailed` if `if_match_version` does not match the current version.
+    /// Returns `AppError::Conflict` if an upload is in progress.
+    #[tracing::instrument(
+        err(level = "warn"),
+        skip_all,
+        fields(device.id = %telemetry::id(device_id), ?if_match_version)
+    )]
+    pub async fn restore(&self, device_id: Uuid, if_match_version: Option<i32>) -> Result<i32> {
+        let mut conn = self.pool.acquire_timed().await.map_err(AppError::Database)?;
+        let window_start = OffsetDateTime::now_utc() - Duration::hours(self.backup_config.restore_window_hours);
+
+        if let Some(restored) = self.repo.restore_previous(&mut conn, device_id, if_match_version, window_start).await?
+        {
+            tracing::info!(version = %restored.current_version, "Backup restored to previous version");
+            return Ok(restored.current_version);
+        }
+
+        let current = self.repo.find_by_device_id(&mut conn, device_id).await?.ok_or(AppError::NotFound)?;
+        if current.state == BackupState::Uploading {
+            return Err(AppError::Conflict("Upload in progress".into()));
+        }
+        if if_match_version.is_some_and(|v| v != current.current_version) {
+            return Err(AppError::PreconditionFailed);
+        }
+        Err(AppError::NotFound)
+    }
+
     /// Returns the current version of the device's backup if it exists.
     ///
     /// # Errors
     /// Returns `AppError::Database` if the query fails.
     pub async fn get_current_version(&self, device_id: Uuid) -> Result<Option<i32>> {
-        let mut conn = self.pool.acquire().await.map_err(AppError::Database)?;
+        let mut conn = self.pool.acquire_timed().await.map_err(AppError::Database)?;
         let backup = self.repo.find_by_device_id(&mut conn, device_id).await?;
         Ok(backup.map(|b| b.current_version))
     }
@@ -0,0 +1,202 @@
+use crate::adapters::database::DbPool;
+use crate::adapters::database::blocklist_repo::BlocklistRepository;
+use crate::adapters::database::instrumentation::TimedAcquire;
+use crate::domain::blocklist::{BlockedNetwork, BlocklistSource, parse_network};
+use crate::error::{AppError, Result};
+use anyhow::Context;
+use opentelemetry::{
+    KeyValue, global,
+    metrics::{Counter, Gauge},
+};
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+
+#[derive(Clone, Debug)]
+struct Metrics {
+    blocked_total: Counter<u64>,
+    entries: Gauge<i64>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let meter = global::meter("obscura-server");
+        Self {
+            blocked_total: meter
+                .u64_counter("obscura_blocklist_blocked_requests_total")
+                .with_description("Requests rejected because the client network is blocklisted")
+                .build(),
+            entries: meter
+                .i64_gauge("obscura_blocklist_entries")
+                .with_description("Networks currently on the blocklist, labelled by source")
+                .build(),
+        }
+    }
+}
+
+/// Operator-managed blocklist of client networks.
+///
+/// Entries come from an optional file read at startup and from the database, which the
+/// management API writes to. Both are held in memory so that the per-request check never
+/// touches the database; the managed entries are reloaded by `refresh`.
+#[derive(Clone, Debug)]
+pub struct BlocklistService {
+    pool: DbPool,
+    repo: BlocklistRepository,
+    file_entries: Arc<[BlockedNetwork]>,
+    managed_entries: Arc<RwLock<Vec<BlockedNetwork>>>,
+    metrics: Metrics,
+}
+
+impl BlocklistService {
+    #[must_use]
+    pub fn new(pool: DbPool, repo: BlocklistRepository, file_entries: Vec<BlockedNetwork>) -> Self {
+        Self {
+            pool,
+            repo,
+            file_entries: file_entries.into(),
+            managed_entries: Arc::new(RwLock::new(Vec::new())),
+            metrics: Metrics::new(),
+        }
+    }
+
+    /// Reads blocklist entries from a file with one CIDR or address per line.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read or a line is not a valid network.
+    pub fn read_file(path: &str) -> anyhow::Result<Vec<BlockedNetwork>> {
+        let contents =
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read blocklist file {path}"))?;
+        parse_file(&contents).with_context(|| format!("Invalid blocklist file {path}"))
+    }
+
+    /// Returns `true` if `ip` falls inside a blocked network, counting the rejection.
+    #[must_use]
+    pub fn is_blocked(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        let source = self.file_entries.iter().find(|e| e.network.contains(ip)).map(|e| e.source).or_else(|| {
+            self.managed_entries
+                .read()
+                .ok()
+                .and_then(|entries| entries.iter().find(|e| e.network.contains(ip)).map(|e| e.source))
+        });
+
+        if let Some(source) = source {
+            self.metrics.blocked_total.add(1, &[KeyValue::new("source", source.to_string())]);
+            return true;
+        }
+        false
+    }
+
+    /// Returns every entry currently enforced, file entries first.
+    #[must_use]
+    pub fn entries(&self) -> Vec<BlockedNetwork> {
+        let mut entries = self.file_entries.to_vec();
+        if let Ok(managed) = self.managed_entries.read() {
+            entries.extend(managed.iter().cloned());
+        }
+        entries
+    }
+
+    /// Reloads the managed entries from the database.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if the query fails; the previous entries stay in force.
+    #[tracing::instrument(skip_all, err)]
+    pub async fn refresh(&self) -> Result<()> {
+        let mut conn = self.pool.acquire_timed().await?;
+        let managed = self.repo.list(&mut conn).await?;
+
+        self.metrics.entries.record(
+            i64::try_from(managed.len()).unwrap_or(i64::MAX),
+            &[KeyValue::new("source", BlocklistSource::Managed.to_string())],
+        );
+        self.metrics.entries.record(
+            i64::try_from(self.file_entries.len()).unwrap_or(i64::MAX),
+            &[KeyValue::new("source", BlocklistSource::File.to_string())],
+        );
+
+        if let Ok(mut entries) = self.managed_entries.write() {
+            *entries = managed;
+        }
+        Ok(())
+    }
+
+    /// Blocks a network, or updates the reason of an existing entry.
+    ///
+    /// # Errors
+    /// Returns `AppError::BadRequest` if `network` is not a valid CIDR or address.
+    /// Returns `AppError::Database` if the entry cannot be stored.
+    #[tracing::instrument(skip_all, fields(%network, ?reason), err(level = "warn"))]
+    pub async fn add(&self, network: &str, reason: Option<&str>) -> Result<BlockedNetwork> {
+        let network = parse_network(network).map_err(AppError::BadRequest)?;
+
+        let entry = {
+            let mut conn = self.pool.acquire_timed().await?;
+            self.repo.upsert(&mut conn, network, reason).await?
+        };
+        tracing::info!(network = %network, "Network added to blocklist");
+
+        self.refresh().await?;
+        Ok(entry)
+    }
+
+    /// Unblocks a network previously added through the management API.
+    ///
+    /// # Errors
+    /// Returns `AppError::BadRequest` if `network` is not a valid CIDR or address.
+    /// Returns `AppError::Conflict` if the network is only defined in the blocklist file.
+    /// Returns `AppError::NotFound` if the network is not blocked.
+    #[tracing::instrument(skip_all, fields(%network), err(level = "warn"))]
+    pub async fn remove(&self, network: &str) -> Result<()> {
+        let network = parse_network(network).map_err(AppError::BadRequest)?;
+
+        let deleted = {
+            let mut conn = self.pool.acquire_timed().await?;
+            self.repo.delete(&mut conn, network).await?
+        };
+        if !deleted {
+            if self.file_entries.iter().any(|e| e.network == network) {
+                return Err(AppError::Conflict(format!("{network} is defined in the blocklist file")));
+            }
+            return Err(AppError::NotFound);
+        }
+        tracing::info!(network = %network, "Network removed from blocklist");
+
+        self.refresh().await
+    }
+}
+
+fn parse_file(contents: &str) -> anyhow::Result<Vec<BlockedNetwork>> {
+    contents
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            (!line.is_empty()).then_some((i + 1, line))
+        })
+        .map(|(line_no, line)| {
+            let network = parse_network(line).map_err(|e| anyhow::anyhow!("line {line_no}: {e}"))?;
+            Ok(BlockedNetwork { network, reason: None, source: BlocklistSource::File, created_at: None })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_file_skips_comments_and_blank_lines() {
+        let entries = parse_file("# abusive ranges\n\n198.51.100.0/24\n2001:db8::/32  # scanner\n203.0.113.9\n")
+            .expect("valid file");
+        let networks: Vec<String> = entries.iter().map(|e| e.network.to_string()).collect();
+        assert_eq!(networks, ["198.51.100.0/24", "2001:db8::/32", "203.0.113.9/32"]);
+        assert!(entries.iter().all(|e| e.source == BlocklistSource::File));
+    }
+
+    #[test]
+    fn test_parse_file_reports_bad_line() {
+        let err = parse_file("10.0.0.0/8\nbogus\n").expect_err("invalid line");
+        assert!(err.to_string().contains("line 2"));
+    }
+}
@@ -1,65 +1,57 @@
-use crate::domain::crypto::{PublicKey, Signature};
+use crate::adapters::crypto::{DalekVerifier, SignatureVerifier};
+use crate::domain::crypto::{KeyType, PublicKey, Signature};
 use crate::error::{AppError, Result};
-use ed25519_dalek::Verifier;
-use xeddsa::ConvertMont;
+use std::sync::Arc;
 
-#[derive(Clone, Debug, Default)]
-pub struct CryptoService;
+#[derive(Clone, Debug)]
+pub struct CryptoService {
+    verifier: Arc<dyn SignatureVerifier>,
+}
+
+impl Default for CryptoService {
+    fn default() -> Self {
+        Self::new(Arc::new(DalekVerifier))
+    }
+}
 
 impl CryptoService {
     #[must_use]
-    pub const fn new() -> Self {
-        Self
+    pub fn new(verifier: Arc<dyn SignatureVerifier>) -> Self {
+        Self { verifier }
     }
 
-    /// Verifies an `XEdDSA` signature.
+    /// Verifies a signature made by the owner of `public_key`.
     ///
-    /// `XEdDSA` is used to verify Ed25519 signatures against Curve25519 (Montgomery) public keys.
-    /// Because a Montgomery X-coordinate corresponds to two Edwards points (sign bit 0 and 1),
-    /// we try both to ensure compatibility with various client implementations and environments.
+    /// The key's prefix selects the scheme: Curve25519 keys are checked as `XEdDSA`,
+    /// Ed25519 keys as standard (strict) Ed25519.
     ///
     /// # Errors
     /// Returns `AppError::BadRequest` if the signature is invalid.
-    #[tracing::instrument(skip(self, public_key, message, signature), level = "debug")]
-    #[allow(clippy::unused_self)]
+    #[tracing::instrument(skip_all, level = "debug")]
     pub(crate) fn verify_signature(&self, public_key: &PublicKey, message: &[u8], signature: &Signature) -> Result<()> {
-        let pk = xeddsa::xed25519::PublicKey(*public_key.as_crypto_bytes());
-
-        let sig_bytes = signature.as_bytes();
-
-        // We must clear the 255th bit of 's' for standard Ed25519 libraries.
-        // XEdDSA uses this bit to represent the sign of the recovered point.
-        let mut sig_canonical = *sig_bytes;
-        sig_canonical[63] &= 0x7F;
-        let sig_obj = ed25519_dalek::Signature::from_bytes(&sig_canonical);
-
-        // Try both possible Edwards points for the Montgomery public key.
-        // Some client environments (like JS polyfills) may choose the alternative point.
-        for sign_bit in [0, 1] {
-            if let Ok(ed_pk_bytes) = pk.convert_mont(sign_bit)
-                && let Ok(ed_pk) = ed25519_dalek::VerifyingKey::from_bytes(&ed_pk_bytes)
-                && ed_pk.verify(message, &sig_obj).is_ok()
-            {
-                return Ok(());
-            }
-        }
+        let (key, signature) = (public_key.as_crypto_bytes(), signature.as_bytes());
+        let valid = match public_key.key_type() {
+            KeyType::Curve25519 => self.verifier.verify_xeddsa(key, message, signature),
+            KeyType::Ed25519 => self.verifier.verify_ed25519(key, message, signature),
+        };
 
-        Err(AppError::BadRequest("Invalid signature".into()))
+        if valid { Ok(()) } else { Err(AppError::BadRequest("Invalid signature".into())) }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::domain::crypto::DJB_KEY_PREFIX;
+    use crate::domain::crypto::{DJB_KEY_PREFIX, ED25519_KEY_PREFIX};
     use curve25519_dalek::edwards::CompressedEdwardsY;
+    use ed25519_dalek::Signer;
     use rand::Rng;
     use xeddsa::xed25519::PrivateKey;
     use xeddsa::{CalculateKeyPair, Sign};
 
     #[test]
     fn test_verify_signature_exhaustive_robustness() {
-        let service = CryptoService::new();
+        let service = CryptoService::default();
         let mut rng = rand::rng();
         let mut seed = [0u8; 32];
         rng.fill_bytes(&mut seed);
@@ -101,4 +93,26 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_verify_signature_ed25519() {
+        let service = CryptoService::default();
+        let mut seed = [0u8; 32];
+        rand::rng().fill_bytes(&mut seed);
+        let signing_key = ed25519_dalek::SigningKey::from_bytes(&seed);
+
+        let mut ik_wire = [0u8; 33];
+        ik_wire[0] = ED25519_KEY_PREFIX;
+        ik_wire[1..].copy_from_slice(signing_key.verifying_key().as_bytes());
+        let ik_pub = PublicKey::new(ik_wire);
+
+        let msg = [0x42u8; 33];
+        let sig = Signature::new(signing_key.sign(&msg).to_bytes());
+        assert!(service.verify_signature(&ik_pub, &msg, &sig).is_ok());
+        assert!(service.verify_signature(&ik_pub, &[0x43u8; 33], &sig).is_err(), "Wrong message must fail");
+
+        // The same Edwards bytes presented as a Curve25519 key must not verify a plain Ed25519 signature.
+        ik_wire[0] = DJB_KEY_PREFIX;
+        assert!(service.verify_signature(&PublicKey::new(ik_wire), &msg, &sig).is_err());
+    }
 }
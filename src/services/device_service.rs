@@ -1,6 +1,6 @@
 use crate::adapters::database::DbPool;
 use crate::adapters::database::device_repo::DeviceRepository;
-use crate::adapters::database::message_repo::MessageRepository;
+use crate::adapters::database::instrumentation::TimedAcquire;
 use crate::domain::auth_session::AuthSession;
 use crate::domain::crypto::PublicKey;
 use crate::domain::device::Device;
@@ -8,8 +8,10 @@ use crate::domain::keys::{OneTimePreKey, SignedPreKey};
 use crate::domain::notification::UserEvent;
 use crate::error::{AppError, Result};
 use crate::services::auth_service::AuthService;
-use crate::services::key_service::{KeyService, KeyUploadParams};
+use crate::services::key_service::{KeyService, KeyUploadParams, PreKeyUpload};
+use crate::services::message_service::MessageService;
 use crate::services::notification_service::NotificationService;
+use crate::telemetry;
 use opentelemetry::{global, metrics::Counter};
 use uuid::Uuid;
 
@@ -44,7 +46,7 @@ impl Metrics {
 pub struct DeviceService {
     pool: DbPool,
     device_repo: DeviceRepository,
-    message_repo: MessageRepository,
+    message_service: MessageService,
     key_service: KeyService,
     auth_service: AuthService,
     notifier: NotificationService,
@@ -54,10 +56,10 @@ pub struct DeviceService {
 
 impl DeviceService {
     #[must_use]
-    pub fn new(
+    pub(crate) fn new(
         pool: DbPool,
         device_repo: DeviceRepository,
-        message_repo: MessageRepository,
+        message_service: MessageService,
         key_service: KeyService,
         auth_service: AuthService,
         notifier: NotificationService,
@@ -66,7 +68,7 @@ impl DeviceService {
         Self {
             pool,
             device_repo,
-            message_repo,
+            message_service,
             key_service,
             auth_service,
             notifier,
@@ -80,8 +82,8 @@ impl DeviceService {
     /// # Errors
     /// Returns `AppError::Database` if any database operation fails.
     #[tracing::instrument(
-        skip(self, identity_key, signed_pre_key, one_time_pre_keys),
-        fields(user.id = %user_id, device.id = tracing::field::Empty),
+        skip_all,
+        fields(user.id = %telemetry::id(user_id), device.id = tracing::field::Empty, ?name, %registration_id),
         err(level = "warn")
     )]
     pub(crate) async fn create_device(
@@ -93,7 +95,7 @@ impl DeviceService {
         signed_pre_key: SignedPreKey,
         one_time_pre_keys: Vec<OneTimePreKey>,
     ) -> Result<AuthSession> {
-        let mut conn = self.pool.acquire().await?;
+        let mut conn = self.pool.acquire_timed().await?;
         let current_device_count = self.device_repo.count_by_user(&mut conn, user_id).await?;
         if current_device_count >= self.max_devices_per_user {
             return Err(AppError::Forbidden(format!(
@@ -103,12 +105,12 @@ impl DeviceService {
         }
         drop(conn);
 
-        let mut tx = self.pool.begin().await?;
+        let mut tx = self.pool.begin_timed().await?;
 
         // 1. Create Device
         let device = self.device_repo.create(&mut tx, user_id, name.as_deref()).await?;
 
-        tracing::Span::current().record("device.id", tracing::field::display(device.id));
+        tracing::Span::current().record("device.id", tracing::field::display(telemetry::id(device.id)));
 
         // 2. Upload Keys
         let key_params = KeyUploadParams {
@@ -119,6 +121,7 @@ impl DeviceService {
             one_time_pre_keys,
         };
 
+        let uploaded = key_params.one_time_pre_keys.len();
         self.key_service.upsert_keys(&mut tx, key_params).await?;
 
         // 3. Create Session with full JWT (includes device_id)
@@ -128,38 +131,51 @@ impl DeviceService {
 
         tracing::info!("Device provisioned successfully");
         self.metrics.devices_created.add(1, &[]);
+        self.key_service.record_upload(PreKeyUpload::Provision, uploaded);
 
         Ok(session)
     }
 
     /// Uploads new keys for an existing device. Handles takeover if identity key changes.
     ///
+    /// A takeover wipes the inbox in the same transaction as the key change, then disconnects the
+    /// device's sessions on every instance, cancels its scheduled push and deletes the stored
+    /// payloads of the wiped messages, so nothing meant for the old identity reaches the new one.
+    /// Attachments are left alone: they name no recipient, and the pointers to them in the wiped
+    /// messages are also held by the sender and the recipient's other devices, so they expire as
+    /// usual.
+    ///
     /// # Errors
     /// Returns `AppError::BadRequest` if key validation fails.
     /// Returns `AppError::Database` if the database operation fails.
     #[tracing::instrument(
-        skip(self, params),
-        fields(device.id = %params.device_id),
+        skip_all,
+        fields(device.id = %telemetry::id(params.device_id)),
         err(level = "warn")
     )]
     pub(crate) async fn upload_keys(&self, params: KeyUploadParams) -> Result<()> {
         let device_id = params.device_id;
+        let uploaded = params.one_time_pre_keys.len();
 
-        let mut tx = self.pool.begin().await?;
+        let mut tx = self.pool.begin_timed().await?;
 
         let is_takeover = self.key_service.upsert_keys(&mut tx, params).await?;
 
-        if is_takeover {
-            self.message_repo.delete_all_for_device(&mut tx, device_id).await?;
-        }
+        let offloaded =
+            if is_takeover { self.message_service.wipe_inbox(&mut tx, device_id).await? } else { Vec::new() };
 
         tx.commit().await?;
+        self.key_service
+            .record_upload(if is_takeover { PreKeyUpload::Takeover } else { PreKeyUpload::Refill }, uploaded);
 
         if is_takeover {
-            tracing::warn!("Device takeover detected");
+            tracing::warn!(offloaded_payloads = offloaded.len(), "Device takeover detected");
             self.metrics.takeovers.add(1, &[]);
 
             self.notifier.notify(&[device_id], UserEvent::Disconnect).await;
+            // The inbox it would announce is gone
+            self.notifier.cancel_pending_notifications(device_id).await;
+            self.message_service.purge_payloads(&offloaded).await;
         }
 
         Ok(())
@@ -169,9 +185,9 @@ impl DeviceService {
     ///
     /// # Errors
     /// Returns `AppError::Database` if the query fails.
-    #[tracing::instrument(skip(self), fields(user.id = %user_id), err)]
+    #[tracing::instrument(skip_all, fields(user.id = %telemetry::id(user_id)), err)]
     pub(crate) async fn list_devices(&self, user_id: Uuid) -> Result<Vec<Device>> {
-        let mut conn = self.pool.acquire().await?;
+        let mut conn = self.pool.acquire_timed().await?;
         self.device_repo.find_by_user(&mut conn, user_id).await
     }
 
@@ -179,9 +195,13 @@ impl DeviceService {
     ///
     /// # Errors
     /// Returns `AppError::NotFound` if the device doesn't exist or isn't owned by the user.
-    #[tracing::instrument(skip(self), fields(user.id = %user_id, device.id = %device_id), err)]
+    #[tracing::instrument(
+        skip_all,
+        fields(user.id = %telemetry::id(user_id), device.id = %telemetry::id(device_id)),
+        err
+    )]
     pub(crate) async fn delete_device(&self, device_id: Uuid, user_id: Uuid) -> Result<()> {
-        let mut conn = self.pool.acquire().await?;
+        let mut conn = self.pool.acquire_timed().await?;
         let deleted = self.device_repo.delete(&mut conn, device_id, user_id).await?;
 
         if !deleted {
@@ -198,9 +218,13 @@ impl DeviceService {
     ///
     /// # Errors
     /// Returns `AppError::NotFound` if the device doesn't exist or isn't owned by the user.
-    #[tracing::instrument(skip(self), fields(user.id = %user_id, device.id = %device_id), err)]
+    #[tracing::instrument(
+        skip_all,
+        fields(user.id = %telemetry::id(user_id), device.id = %telemetry::id(device_id)),
+        err
+    )]
     pub(crate) async fn get_device(&self, device_id: Uuid, user_id: Uuid) -> Result<Device> {
-        let mut conn = self.pool.acquire().await?;
+        let mut conn = self.pool.acquire_timed().await?;
         self.device_repo.find_by_id(&mut conn, device_id, user_id).await?.ok_or(AppError::NotFound)
     }
 
@@ -208,9 +232,13 @@ impl DeviceService {
     ///
     /// # Errors
     /// Returns `AppError::NotFound` if the device doesn't exist or isn't owned by the user.
-    #[tracing::instrument(skip(self), fields(user.id = %user_id, device.id = %device_id), err)]
+    #[tracing::instrument(
+        skip_all,
+        fields(user.id = %telemetry::id(user_id), device.id = %telemetry::id(device_id), ?name),
+        err
+    )]
     pub(crate) async fn update_device(&self, device_id: Uuid, user_id: Uuid, name: Option<String>) -> Result<Device> {
-        let mut conn = self.pool.acquire().await?;
+        let mut conn = self.pool.acquire_timed().await?;
         let device = self
             .device_repo
             .update_name(&mut conn, device_id, user_id, name.as_deref())
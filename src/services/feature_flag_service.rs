@@ -0,0 +1,134 @@
+use crate::adapters::database::DbPool;
+use crate::adapters::database::feature_flag_repo::FeatureFlagRepository;
+use crate::adapters::database::instrumentation::TimedAcquire;
+use crate::domain::feature_flag::{FeatureFlag, validate_name};
+use crate::error::{AppError, Result};
+use opentelemetry::{KeyValue, global, metrics::Counter};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use uuid::Uuid;
+
+#[derive(Clone, Debug)]
+struct Metrics {
+    evaluations_total: Counter<u64>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let meter = global::meter("obscura-server");
+        Self {
+            evaluations_total: meter
+                .u64_counter("obscura_feature_flag_evaluations_total")
+                .with_description("Feature flag evaluations, by flag and result")
+                .build(),
+        }
+    }
+}
+
+/// Feature flags stored in the database, for rolling features out without a restart.
+///
+/// Flags are held in memory so that evaluating one never touches the database; they are
+/// reloaded by `refresh`, and immediately on the instance that changed them.
+#[derive(Clone, Debug)]
+pub struct FeatureFlagService {
+    pool: DbPool,
+    repo: FeatureFlagRepository,
+    flags: Arc<RwLock<HashMap<String, FeatureFlag>>>,
+    metrics: Metrics,
+}
+
+impl FeatureFlagService {
+    #[must_use]
+    pub fn new(pool: DbPool, repo: FeatureFlagRepository) -> Self {
+        Self { pool, repo, flags: Arc::new(RwLock::new(HashMap::new())), metrics: Metrics::new() }
+    }
+
+    /// Returns `true` if the flag is on for `subject`, usually the user the feature acts for.
+    /// Unknown flags are off.
+    #[must_use]
+    pub fn is_enabled(&self, name: &str, subject: Uuid) -> bool {
+        let enabled = self
+            .flags
+            .read()
+            .ok()
+            .and_then(|flags| flags.get(name).map(|flag| flag.is_enabled_for(subject)))
+            .unwrap_or(false);
+
+        self.metrics.evaluations_total.add(
+            1,
+            &[KeyValue::new("flag", name.to_string()), KeyValue::new("result", if enabled { "on" } else { "off" })],
+        );
+        enabled
+    }
+
+    /// Returns every flag currently in force, ordered by name.
+    #[must_use]
+    pub fn flags(&self) -> Vec<FeatureFlag> {
+        let mut flags: Vec<FeatureFlag> =
+            self.flags.read().map(|flags| flags.values().cloned().collect()).unwrap_or_default();
+        flags.sort_by(|a, b| a.name.cmp(&b.name));
+        flags
+    }
+
+    /// Reloads the flags from the database.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if the query fails; the previous flags stay in force.
+    #[tracing::instrument(skip_all, err)]
+    pub async fn refresh(&self) -> Result<()> {
+        let mut conn = self.pool.acquire_timed().await?;
+        let flags = self.repo.list(&mut conn).await?;
+
+        if let Ok(mut current) = self.flags.write() {
+            *current = flags.into_iter().map(|flag| (flag.name.clone(), flag)).collect();
+        }
+        Ok(())
+    }
+
+    /// Creates or replaces a flag.
+    ///
+    /// # Errors
+    /// Returns `AppError::BadRequest` if the name is invalid or the rollout exceeds 100 percent.
+    /// Returns `AppError::Database` if the flag cannot be stored.
+    #[tracing::instrument(skip_all, fields(%name, %enabled, %rollout_percent), err(level = "warn"))]
+    pub async fn set(
+        &self,
+        name: &str,
+        enabled: bool,
+        rollout_percent: u8,
+        description: Option<&str>,
+    ) -> Result<FeatureFlag> {
+        validate_name(name).map_err(AppError::BadRequest)?;
+        if rollout_percent > 100 {
+            return Err(AppError::BadRequest("Rollout percent must be between 0 and 100".into()));
+        }
+
+        let flag = {
+            let mut conn = self.pool.acquire_timed().await?;
+            self.repo.upsert(&mut conn, name, enabled, rollout_percent, description).await?
+        };
+        tracing::info!(flag = %name, enabled, rollout_percent, "Feature flag updated");
+
+        self.refresh().await?;
+        Ok(flag)
+    }
+
+    /// Deletes a flag, turning it off everywhere.
+    ///
+    /// # Errors
+    /// Returns `AppError::NotFound` if the flag does not exist.
+    /// Returns `AppError::Database` if the deletion fails.
+    #[tracing::instrument(skip_all, fields(%name), err(level = "warn"))]
+    pub async fn remove(&self, name: &str) -> Result<()> {
+        let deleted = {
+            let mut conn = self.pool.acquire_timed().await?;
+            self.repo.delete(&mut conn, name).await?
+        };
+        if !deleted {
+            return Err(AppError::NotFound);
+        }
+        tracing::info!(flag = %name, "Feature flag deleted");
+
+        self.refresh().await
+    }
+}
@@ -1,14 +1,34 @@
+use crate::adapters::redis::AckSpill;
+use crate::adapters::redis::ack_spill::SpilledAck;
+use crate::adapters::retry::RetryPolicy;
+use crate::error::AppError;
 use crate::services::gateway::Metrics;
+use crate::services::gateway::delivery_tracker::DeliveredMark;
 use crate::services::message_service::MessageService;
+use crate::telemetry;
+use opentelemetry::KeyValue;
 use std::time::Duration;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
 use tracing::Instrument;
 use uuid::Uuid;
 
+/// Keeps acknowledgments from being lost when a delete fails: the batch is retried with
+/// backoff, then spilled to Redis for the spill worker to delete later. Without Redis there is
+/// nowhere to spill, and a batch that still fails is redelivered.
+#[derive(Clone, Debug)]
+pub(crate) struct AckPersistence {
+    pub(crate) retry: RetryPolicy,
+    pub(crate) spill: Option<AckSpill>,
+}
+
 /// `AckBatcher` decouples fast WebSocket ACKs from slow database deletes and
 /// reduces database overhead by batching multiple deletions into a single query.
+///
+/// Each flush also records how far the session has delivered, so redeliveries are recognised
+/// even if the instance dies before the session ends.
 pub struct AckBatcher {
     tx: mpsc::Sender<Uuid>,
+    delivered_tx: watch::Sender<Option<DeliveredMark>>,
     metrics: Metrics,
 }
 
@@ -16,23 +36,42 @@ impl AckBatcher {
     pub fn new(
         device_id: Uuid,
         message_service: MessageService,
+        persistence: Option<AckPersistence>,
         metrics: Metrics,
         buffer_size: usize,
         batch_size: usize,
         flush_interval_ms: u64,
     ) -> Self {
         let (tx, rx) = mpsc::channel(buffer_size);
+        let (delivered_tx, delivered_rx) = watch::channel(None);
 
-        let batcher_metrics = metrics.clone();
+        let flusher = Flusher {
+            device_id,
+            message_service,
+            persistence,
+            metrics: metrics.clone(),
+            delivered_rx,
+            recorded_seq: None,
+        };
         tokio::spawn(
             async move {
-                Self::run_background(device_id, rx, message_service, batcher_metrics, batch_size, flush_interval_ms)
-                    .await;
+                Self::run_background(rx, flusher, batch_size, flush_interval_ms).await;
             }
-            .instrument(tracing::info_span!("ack_batcher", "device.id" = %device_id)),
+            .instrument(tracing::info_span!("ack_batcher", "device.id" = %telemetry::id(device_id))),
         );
 
-        Self { tx, metrics }
+        Self { tx, delivered_tx, metrics }
+    }
+
+    /// Raises the delivered sequence recorded with the next flush.
+    pub(crate) fn mark_delivered(&self, mark: DeliveredMark) {
+        self.delivered_tx.send_if_modified(|current| {
+            let advanced = current.is_none_or(|current| mark.seq > current.seq);
+            if advanced {
+                *current = Some(mark);
+            }
+            advanced
+        });
     }
 
     pub fn push(&self, msg_ids: Vec<Uuid>) {
@@ -45,10 +84,8 @@ impl AckBatcher {
     }
 
     async fn run_background(
-        device_id: Uuid,
         mut rx: mpsc::Receiver<Uuid>,
-        message_service: MessageService,
-        metrics: Metrics,
+        mut flusher: Flusher,
         batch_size: usize,
         flush_interval_ms: u64,
     ) {
@@ -77,7 +114,7 @@ impl AckBatcher {
                             batch.push(id);
                         } else {
                             // Channel closed, flush whatever we have right now and exit
-                            Self::flush_batch(device_id, &message_service, &metrics, batch).await;
+                            flusher.flush(batch).await;
                             return;
                         }
                     }
@@ -85,17 +122,84 @@ impl AckBatcher {
                 }
             }
 
-            Self::flush_batch(device_id, &message_service, &metrics, batch).await;
+            flusher.flush(batch).await;
         }
     }
+}
+
+/// Deletes the acknowledged messages of one device.
+struct Flusher {
+    device_id: Uuid,
+    message_service: MessageService,
+    persistence: Option<AckPersistence>,
+    metrics: Metrics,
+    delivered_rx: watch::Receiver<Option<DeliveredMark>>,
+    /// Delivered sequence last written by this flusher.
+    recorded_seq: Option<i64>,
+}
+
+impl Flusher {
+    async fn flush(&mut self, batch: Vec<Uuid>) {
+        if batch.is_empty() {
+            return;
+        }
+        self.record_delivered().await;
+        tracing::debug!(batch_size = batch.len(), "Flushing ACK batch");
+        self.metrics.ack_batch_size.record(batch.len() as u64, &[]);
 
-    async fn flush_batch(device_id: Uuid, message_service: &MessageService, metrics: &Metrics, batch: Vec<Uuid>) {
-        if !batch.is_empty() {
-            tracing::debug!(batch_size = batch.len(), "Flushing ACK batch");
-            metrics.ack_batch_size.record(batch.len() as u64, &[]);
-            if let Err(e) = message_service.delete_batch(device_id, &batch).await {
-                tracing::error!(error = %e, "Failed to delete message batch");
+        // Deletes and delivery flags are idempotent, so any database failure is worth another attempt
+        let delete = || self.message_service.acknowledge_batch(self.device_id, &batch);
+        let result = match &self.persistence {
+            Some(persistence) => persistence.retry.run(delete, |e| matches!(e, AppError::Database(_))).await,
+            None => delete().await,
+        };
+        let Err(e) = result else { return };
+
+        let outcome = match self.persistence.as_ref().and_then(|persistence| persistence.spill.as_ref()) {
+            Some(spill) => {
+                let spilled = SpilledAck { device_id: self.device_id, message_ids: batch };
+                match spill.push(&spilled).await {
+                    Ok(()) => {
+                        tracing::warn!(
+                            error = %e,
+                            batch_size = spilled.message_ids.len(),
+                            "Failed to delete acknowledged messages, spilled them for a later retry"
+                        );
+                        "spilled"
+                    }
+                    Err(spill_error) => {
+                        tracing::error!(
+                            error = %e,
+                            spill.error = %spill_error,
+                            batch_size = spilled.message_ids.len(),
+                            "Failed to delete or spill acknowledged messages, they will be redelivered"
+                        );
+                        "lost"
+                    }
+                }
+            }
+            None => {
+                tracing::error!(
+                    error = %e,
+                    batch_size = batch.len(),
+                    "Failed to delete acknowledged messages, they will be redelivered"
+                );
+                "lost"
             }
+        };
+        self.metrics.ack_delete_failures_total.add(1, &[KeyValue::new("outcome", outcome)]);
+    }
+
+    /// Records the session's delivered sequence if it moved since the last flush. Failures are
+    /// left for the next flush or the end of the session.
+    async fn record_delivered(&mut self) {
+        let Some(mark) = *self.delivered_rx.borrow() else { return };
+        if self.recorded_seq.is_some_and(|recorded| mark.seq <= recorded) {
+            return;
+        }
+        match self.message_service.record_delivered_seq(self.device_id, mark.seq, mark.session_started_at).await {
+            Ok(()) => self.recorded_seq = Some(mark.seq),
+            Err(e) => tracing::warn!(error = %e, "Failed to record delivered sequence"),
         }
     }
 }
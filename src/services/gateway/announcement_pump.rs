@@ -0,0 +1,66 @@
+use crate::proto::obscura::v1 as proto;
+use crate::services::announcement_service::AnnouncementService;
+use crate::services::gateway::delivery_tracker::OutboundFrame;
+use crate::telemetry;
+use axum::extract::ws::Message as WsMessage;
+use prost::Message as ProstMessage;
+use tokio::sync::mpsc;
+use tracing::Instrument;
+use uuid::Uuid;
+
+/// `AnnouncementPump` claims announcements for the session's user in the background and
+/// forwards them as `Announcement` frames. Broadcast wake-ups arrive once per instance hop,
+/// so they are coalesced; the claim itself guarantees each announcement is sent once per user.
+pub struct AnnouncementPump {
+    notify_tx: mpsc::Sender<()>,
+}
+
+impl AnnouncementPump {
+    pub fn new(device_id: Uuid, service: AnnouncementService, outbound_tx: mpsc::Sender<OutboundFrame>) -> Self {
+        // Channel size 1 effectively coalesces notifications while a claim is in progress.
+        let (notify_tx, notify_rx) = mpsc::channel(1);
+
+        tokio::spawn(
+            async move {
+                Self::run_background(device_id, notify_rx, service, outbound_tx).await;
+            }
+            .instrument(tracing::info_span!("announcement_pump", "device.id" = %telemetry::id(device_id))),
+        );
+
+        Self { notify_tx }
+    }
+
+    pub fn notify(&self) {
+        let _ = self.notify_tx.try_send(());
+    }
+
+    async fn run_background(
+        device_id: Uuid,
+        mut rx: mpsc::Receiver<()>,
+        service: AnnouncementService,
+        outbound_tx: mpsc::Sender<OutboundFrame>,
+    ) {
+        while rx.recv().await.is_some() {
+            let payloads = match service.claim_pending(device_id).await {
+                Ok(payloads) => payloads,
+                Err(e) => {
+                    tracing::error!(error = %e, "Failed to claim pending announcements");
+                    continue;
+                }
+            };
+
+            for payload in payloads {
+                let Ok(signed) = proto::SignedAnnouncement::decode(payload.as_slice()) else {
+                    tracing::warn!("Skipping undecodable stored announcement");
+                    continue;
+                };
+                let frame =
+                    proto::WebSocketFrame { payload: Some(proto::web_socket_frame::Payload::Announcement(signed)) };
+                // If outbound_tx is closed (user disconnected), we just exit
+                if outbound_tx.send(WsMessage::Binary(frame.encode_to_vec().into()).into()).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
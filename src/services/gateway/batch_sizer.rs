@@ -0,0 +1,173 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Fraction of the outbound buffer that may be occupied before the client is
+/// considered slow and the fetch size is cut back.
+const PRESSURE_HIGH_WATERMARK: f64 = 0.5;
+
+/// Tracks how long the client takes to acknowledge delivered batches.
+///
+/// The pump stamps the time a batch leaves the server, and the session reports
+/// when the next ACK arrives. Only the most recent sample is kept; the sizer
+/// reacts to the current state of the link rather than its history.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct AckLatencyTracker {
+    inner: Arc<Mutex<AckLatencyState>>,
+}
+
+#[derive(Debug, Default)]
+struct AckLatencyState {
+    outstanding_since: Option<Instant>,
+    last_latency: Option<Duration>,
+}
+
+impl AckLatencyTracker {
+    /// Records that a batch was handed to the outbound buffer. Only the first unacknowledged
+    /// send is tracked so that a steady stream of batches does not reset the clock.
+    pub(crate) fn mark_sent(&self) {
+        if let Ok(mut state) = self.inner.lock()
+            && state.outstanding_since.is_none()
+        {
+            state.outstanding_since = Some(Instant::now());
+        }
+    }
+
+    /// Records that the client acknowledged delivered messages.
+    pub(crate) fn mark_acked(&self) {
+        if let Ok(mut state) = self.inner.lock()
+            && let Some(sent_at) = state.outstanding_since.take()
+        {
+            state.last_latency = Some(sent_at.elapsed());
+        }
+    }
+
+    /// Returns the effective ACK latency: the last completed sample, or the age of the
+    /// oldest outstanding batch if that is already longer.
+    pub(crate) fn current(&self) -> Option<Duration> {
+        let state = self.inner.lock().ok()?;
+        let pending = state.outstanding_since.map(|t| t.elapsed());
+        match (state.last_latency, pending) {
+            (Some(last), Some(pending)) => Some(last.max(pending)),
+            (last, pending) => last.or(pending),
+        }
+    }
+}
+
+/// AIMD controller for the number of messages fetched per database poll.
+///
+/// The limit grows additively while the client keeps up and a backlog remains, and is
+/// halved as soon as the outbound buffer fills up or ACKs start lagging behind.
+#[derive(Debug)]
+pub(crate) struct BatchSizer {
+    current: i64,
+    min: i64,
+    max: i64,
+    step: i64,
+    ack_latency_target: Duration,
+    ack_latency: AckLatencyTracker,
+}
+
+impl BatchSizer {
+    pub(crate) fn new(
+        initial: i64,
+        min: i64,
+        max: i64,
+        ack_latency_target: Duration,
+        ack_latency: AckLatencyTracker,
+    ) -> Self {
+        let min = min.max(1);
+        let max = max.max(min);
+        Self { current: initial.clamp(min, max), min, max, step: min, ack_latency_target, ack_latency }
+    }
+
+    pub(crate) const fn ack_latency(&self) -> &AckLatencyTracker {
+        &self.ack_latency
+    }
+
+    pub(crate) const fn limit(&self) -> i64 {
+        self.current
+    }
+
+    /// Adjusts the limit after a fetch.
+    ///
+    /// `pressure` is the occupied fraction of the outbound buffer (0.0 - 1.0), `backlog`
+    /// indicates the last fetch filled the limit and more messages are likely waiting.
+    pub(crate) fn observe(&mut self, pressure: f64, backlog: bool) -> i64 {
+        let ack_latency = self.ack_latency.current();
+        self.adjust(pressure, ack_latency, backlog)
+    }
+
+    fn adjust(&mut self, pressure: f64, ack_latency: Option<Duration>, backlog: bool) -> i64 {
+        let lagging = ack_latency.is_some_and(|l| l > self.ack_latency_target);
+
+        if pressure >= PRESSURE_HIGH_WATERMARK || lagging {
+            self.current = (self.current / 2).max(self.min);
+        } else if backlog {
+            self.current = self.current.saturating_add(self.step).min(self.max);
+        }
+
+        self.current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sizer() -> BatchSizer {
+        BatchSizer::new(50, 10, 200, Duration::from_millis(500), AckLatencyTracker::default())
+    }
+
+    #[test]
+    fn test_initial_limit_is_clamped() {
+        assert_eq!(BatchSizer::new(1000, 10, 200, Duration::from_secs(1), AckLatencyTracker::default()).limit(), 200);
+        assert_eq!(BatchSizer::new(1, 10, 200, Duration::from_secs(1), AckLatencyTracker::default()).limit(), 10);
+    }
+
+    #[test]
+    fn test_additive_increase_with_backlog() {
+        let mut s = sizer();
+        assert_eq!(s.adjust(0.0, None, true), 60);
+        assert_eq!(s.adjust(0.1, Some(Duration::from_millis(10)), true), 70);
+    }
+
+    #[test]
+    fn test_no_increase_without_backlog() {
+        let mut s = sizer();
+        assert_eq!(s.observe(0.0, false), 50);
+    }
+
+    #[test]
+    fn test_multiplicative_decrease_on_pressure() {
+        let mut s = sizer();
+        assert_eq!(s.adjust(0.75, None, true), 25);
+        assert_eq!(s.adjust(1.0, None, true), 12);
+        assert_eq!(s.adjust(1.0, None, true), 10);
+    }
+
+    #[test]
+    fn test_multiplicative_decrease_on_ack_latency() {
+        let mut s = sizer();
+        assert_eq!(s.adjust(0.0, Some(Duration::from_secs(2)), true), 25);
+    }
+
+    #[test]
+    fn test_increase_capped_at_max() {
+        let mut s = BatchSizer::new(195, 10, 200, Duration::from_secs(1), AckLatencyTracker::default());
+        assert_eq!(s.adjust(0.0, None, true), 200);
+        assert_eq!(s.adjust(0.0, None, true), 200);
+    }
+
+    #[test]
+    fn test_ack_latency_tracker() {
+        let tracker = AckLatencyTracker::default();
+        assert!(tracker.current().is_none());
+
+        tracker.mark_sent();
+        assert!(tracker.current().is_some());
+
+        tracker.mark_acked();
+        let sample = tracker.current().expect("latency sample should be recorded");
+        assert!(sample < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,133 @@
+use crate::proto::obscura::v1 as proto;
+use axum::extract::ws::{CloseFrame, Message as WsMessage};
+use prost::Message;
+use std::time::Duration;
+
+/// Why the server ended a gateway session.
+///
+/// Every reason maps to a stable WebSocket close code, and the `GoAway` frame sent just before
+/// the close repeats it together with reconnect guidance. Clients should branch on the code, not
+/// on the human-readable reason.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CloseReason {
+    /// The instance is shutting down. Reconnect after the hinted delay (1001).
+    ServerShutdown,
+    /// The client sent a frame that is not a valid `WebSocketFrame` (4000).
+    ProtocolError,
+    /// The access token expired without an `Auth` frame renewing it. Reconnect with a fresh ticket (4001).
+    AuthExpired,
+    /// A newer session for the same device took over. Do not reconnect (4002).
+    SessionReplaced,
+    /// The device's inbox was wiped by a key takeover or account deletion. Do not reconnect with
+    /// the old identity (4003).
+    InboxWiped,
+    /// The client exceeded the inbound frame rate. Reconnect after the hinted delay (4029).
+    RateLimited,
+    /// A dependency such as the database failed or timed out. Reconnect after the hinted delay (4503).
+    Unavailable,
+    /// The server failed in a way retrying soon will not fix. Reconnect after the hinted delay (1011).
+    InternalError,
+}
+
+impl CloseReason {
+    #[must_use]
+    pub const fn code(self) -> u16 {
+        match self {
+            Self::ServerShutdown => axum::extract::ws::close_code::AWAY,
+            Self::ProtocolError => 4000,
+            Self::AuthExpired => 4001,
+            Self::SessionReplaced => 4002,
+            Self::InboxWiped => 4003,
+            Self::RateLimited => 4029,
+            Self::Unavailable => 4503,
+            Self::InternalError => axum::extract::ws::close_code::ERROR,
+        }
+    }
+
+    #[must_use]
+    pub const fn description(self) -> &'static str {
+        match self {
+            Self::ServerShutdown => "Server shutting down",
+            Self::ProtocolError => "Invalid frame",
+            Self::AuthExpired => "Authentication expired",
+            Self::SessionReplaced => "Replaced by a newer session",
+            Self::InboxWiped => "Device inbox wiped",
+            Self::RateLimited => "Inbound frame rate exceeded",
+            Self::Unavailable => "Service temporarily unavailable",
+            Self::InternalError => "Internal server error",
+        }
+    }
+
+    /// Whether a client should open a new session at all after this close.
+    #[must_use]
+    pub const fn reconnect(self) -> bool {
+        !matches!(self, Self::SessionReplaced | Self::InboxWiped)
+    }
+
+    /// Label for the close metrics.
+    pub(crate) const fn as_str(self) -> &'static str {
+        match self {
+            Self::ServerShutdown => "server_shutdown",
+            Self::ProtocolError => "protocol_error",
+            Self::AuthExpired => "auth_expired",
+            Self::SessionReplaced => "session_replaced",
+            Self::InboxWiped => "inbox_wiped",
+            Self::RateLimited => "rate_limited",
+            Self::Unavailable => "unavailable",
+            Self::InternalError => "internal_error",
+        }
+    }
+
+    /// The frames that end a session: a `GoAway` carrying reconnect guidance, then the close frame.
+    pub(crate) fn farewell(self, retry_after: Duration) -> [WsMessage; 2] {
+        let go_away = proto::WebSocketFrame {
+            payload: Some(proto::web_socket_frame::Payload::GoAway(proto::GoAway {
+                code: u32::from(self.code()),
+                reason: self.description().to_string(),
+                reconnect: self.reconnect(),
+                retry_after_ms: u32::try_from(retry_after.as_millis()).unwrap_or(u32::MAX),
+            })),
+        };
+        let close = WsMessage::Close(Some(CloseFrame { code: self.code(), reason: self.description().into() }));
+        [WsMessage::Binary(go_away.encode_to_vec().into()), close]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_close_codes_are_distinct() {
+        let reasons = [
+            CloseReason::ServerShutdown,
+            CloseReason::ProtocolError,
+            CloseReason::AuthExpired,
+            CloseReason::SessionReplaced,
+            CloseReason::InboxWiped,
+            CloseReason::RateLimited,
+            CloseReason::Unavailable,
+            CloseReason::InternalError,
+        ];
+        let codes: std::collections::HashSet<u16> = reasons.iter().map(|r| r.code()).collect();
+        assert_eq!(codes.len(), reasons.len());
+    }
+
+    #[test]
+    #[allow(clippy::panic)]
+    fn test_farewell_repeats_code_in_go_away() {
+        let [go_away, close] = CloseReason::RateLimited.farewell(Duration::from_secs(4));
+
+        let WsMessage::Binary(bytes) = go_away else { panic!("GoAway must be a binary frame") };
+        let frame = proto::WebSocketFrame::decode(bytes.as_ref()).expect("valid frame");
+        let Some(proto::web_socket_frame::Payload::GoAway(go_away)) = frame.payload else {
+            panic!("Expected a GoAway payload")
+        };
+        assert_eq!(go_away.code, 4029);
+        assert!(go_away.reconnect);
+        assert_eq!(go_away.retry_after_ms, 4000);
+
+        let WsMessage::Close(Some(close)) = close else { panic!("Expected a close frame") };
+        assert_eq!(close.code, 4029);
+    }
+}
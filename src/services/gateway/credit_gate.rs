@@ -0,0 +1,60 @@
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+/// `CreditGate` tracks how many envelopes a client has said it can accept.
+///
+/// Clients that opt into credit-based flow control grant credit with `Credit` frames, and
+/// the message pump reserves credit before each fetch, waiting when none is left. Credit is
+/// never allowed to accumulate beyond `max_credit`, so a misbehaving client cannot force an
+/// unbounded drain.
+#[derive(Clone, Debug)]
+pub(crate) struct CreditGate {
+    permits: Arc<Semaphore>,
+    max_credit: usize,
+}
+
+impl CreditGate {
+    pub(crate) fn new(max_credit: usize) -> Self {
+        Self { permits: Arc::new(Semaphore::new(0)), max_credit: max_credit.clamp(1, Semaphore::MAX_PERMITS) }
+    }
+
+    /// Adds credit granted by the client, capped at the configured maximum.
+    pub(crate) fn grant(&self, envelopes: u32) {
+        let headroom = self.max_credit.saturating_sub(self.permits.available_permits());
+        let granted = usize::try_from(envelopes).unwrap_or(usize::MAX).min(headroom);
+        if granted > 0 {
+            self.permits.add_permits(granted);
+        }
+    }
+
+    /// Waits until at least one envelope of credit is available and reserves up to `max`.
+    /// Returns 0 once the gate has been closed.
+    pub(crate) async fn reserve(&self, max: usize) -> usize {
+        let Ok(first) = self.permits.acquire().await else {
+            return 0;
+        };
+        first.forget();
+
+        let extra = self.permits.available_permits().min(max.saturating_sub(1));
+        if extra > 0
+            && let Ok(permits) = self.permits.try_acquire_many(u32::try_from(extra).unwrap_or(u32::MAX))
+        {
+            let reserved = permits.num_permits();
+            permits.forget();
+            return 1 + reserved;
+        }
+        1
+    }
+
+    /// Returns credit that was reserved but not used because fewer envelopes were pending.
+    pub(crate) fn refund(&self, envelopes: usize) {
+        if envelopes > 0 {
+            self.permits.add_permits(envelopes);
+        }
+    }
+
+    /// Wakes any pending reservation so the pump can shut down with the session.
+    pub(crate) fn close(&self) {
+        self.permits.close();
+    }
+}
@@ -0,0 +1,306 @@
+use crate::services::gateway::Metrics;
+use axum::extract::ws::Message as WsMessage;
+use opentelemetry::KeyValue;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+/// Upper bound on delivered-but-unacknowledged messages timed per session, so a client
+/// that never ACKs cannot grow the map without limit.
+const MAX_TRACKED_DELIVERIES: usize = 10_000;
+
+/// An envelope carried by an outbound frame, with what delivery accounting needs to know about it.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct EnvelopeStamp {
+    pub(crate) id: Uuid,
+    /// Inbox sequence number of the message.
+    pub(crate) seq: i64,
+    pub(crate) enqueued_at: OffsetDateTime,
+}
+
+/// The highest inbox sequence a session wrote, and when that session connected. Messages queued
+/// before then reached it as `push`, later ones as `websocket`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct DeliveredMark {
+    pub(crate) seq: i64,
+    pub(crate) session_started_at: OffsetDateTime,
+}
+
+/// A frame queued for the socket, together with the envelopes it carries so their
+/// delivery can be timed once the frame is actually written.
+#[derive(Debug)]
+pub(crate) struct OutboundFrame {
+    pub(crate) message: WsMessage,
+    pub(crate) envelopes: Vec<EnvelopeStamp>,
+}
+
+impl From<WsMessage> for OutboundFrame {
+    fn from(message: WsMessage) -> Self {
+        Self { message, envelopes: Vec::new() }
+    }
+}
+
+/// Running totals of the envelopes this instance's sessions wrote and had acknowledged.
+///
+/// Shared between the gateway and the delivery SLO sampler, which turns the change between
+/// two samples into a success ratio.
+#[derive(Clone, Debug, Default)]
+pub struct DeliveryStats {
+    totals: Arc<DeliveryTotals>,
+}
+
+#[derive(Debug, Default)]
+struct DeliveryTotals {
+    delivered: AtomicU64,
+    redelivered: AtomicU64,
+    acked: AtomicU64,
+}
+
+/// A reading of [`DeliveryStats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DeliverySnapshot {
+    /// Envelopes written for the first time.
+    pub delivered: u64,
+    /// Envelopes written again after an earlier session already received them.
+    pub redelivered: u64,
+    /// Written envelopes the client acknowledged in the same session.
+    pub acked: u64,
+}
+
+impl DeliveryStats {
+    #[must_use]
+    pub fn snapshot(&self) -> DeliverySnapshot {
+        DeliverySnapshot {
+            delivered: self.totals.delivered.load(Ordering::Relaxed),
+            redelivered: self.totals.redelivered.load(Ordering::Relaxed),
+            acked: self.totals.acked.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// An envelope written to the socket and not acknowledged yet.
+#[derive(Debug, Clone, Copy)]
+struct Written {
+    at: Instant,
+    transport: &'static str,
+    seq: i64,
+}
+
+/// Records delivery for a single session: how long a message sat in the queue before it was
+/// written to the socket, how long the client took to ACK it, and whether it was a first
+/// delivery or a redelivery.
+///
+/// Redeliveries are told apart by sequence number: the highest sequence written to an earlier
+/// session is loaded when the session starts, and everything at or below it was seen before.
+#[derive(Debug)]
+pub(crate) struct DeliveryTracker {
+    connected_at: OffsetDateTime,
+    delivered: HashMap<Uuid, Written>,
+    /// Set once a delivery goes untracked because of the cap, after which the unacknowledged
+    /// messages are no longer known.
+    untracked: bool,
+    /// Sequence the session's message pump started fetching above.
+    fetched_after: Option<i64>,
+    previously_delivered_seq: Option<i64>,
+    highest_seq: Option<i64>,
+    /// Highest sequence written by this session itself.
+    written_seq: Option<i64>,
+    metrics: Metrics,
+    stats: DeliveryStats,
+}
+
+impl DeliveryTracker {
+    pub(crate) fn new(metrics: Metrics, stats: DeliveryStats, previously_delivered_seq: Option<i64>) -> Self {
+        Self {
+            connected_at: OffsetDateTime::now_utc(),
+            delivered: HashMap::new(),
+            untracked: false,
+            fetched_after: None,
+            previously_delivered_seq,
+            highest_seq: None,
+            written_seq: None,
+            metrics,
+            stats,
+        }
+    }
+
+    /// Picks up where a session closed on another instance left off: everything up to `cursor`
+    /// was written to the client, and the message pump starts above `fetched_after`.
+    pub(crate) fn resume(&mut self, cursor: Option<i64>, fetched_after: Option<i64>) {
+        self.highest_seq = self.highest_seq.max(cursor);
+        self.fetched_after = fetched_after;
+    }
+
+    /// Called once a frame carrying `envelopes` has been written to the socket.
+    pub(crate) fn record_delivered(&mut self, envelopes: &[EnvelopeStamp]) {
+        let now = OffsetDateTime::now_utc();
+        let written_at = Instant::now();
+
+        for envelope in envelopes {
+            let transport = self.transport(envelope.enqueued_at);
+            let attributes = [KeyValue::new("transport", transport)];
+            let queued_secs = (now - envelope.enqueued_at).as_seconds_f64().max(0.0);
+            self.metrics.queue_to_delivery_seconds.record(queued_secs, &attributes);
+
+            if self.previously_delivered_seq.is_some_and(|seq| envelope.seq <= seq) {
+                self.metrics.envelopes_redelivered_total.add(1, &attributes);
+                self.stats.totals.redelivered.fetch_add(1, Ordering::Relaxed);
+            } else {
+                self.metrics.envelopes_delivered_total.add(1, &attributes);
+                self.stats.totals.delivered.fetch_add(1, Ordering::Relaxed);
+            }
+            self.highest_seq = self.highest_seq.max(Some(envelope.seq));
+            self.written_seq = self.written_seq.max(Some(envelope.seq));
+
+            if self.delivered.len() < MAX_TRACKED_DELIVERIES {
+                self.delivered.insert(envelope.id, Written { at: written_at, transport, seq: envelope.seq });
+            } else {
+                self.untracked = true;
+            }
+        }
+    }
+
+    pub(crate) fn record_acked(&mut self, ids: &[Uuid]) {
+        for id in ids {
+            if let Some(written) = self.delivered.remove(id) {
+                let attributes = [KeyValue::new("transport", written.transport)];
+                self.metrics.delivery_to_ack_seconds.record(written.at.elapsed().as_secs_f64(), &attributes);
+                self.metrics.envelopes_acked_total.add(1, &attributes);
+                self.stats.totals.acked.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Highest sequence written to the client, including by the session this one resumed.
+    pub(crate) const fn highest_seq(&self) -> Option<i64> {
+        self.highest_seq
+    }
+
+    /// Sequence a session resuming this one should fetch above: just below the oldest message
+    /// still awaiting an ACK, since the client may never have received it. Messages are written
+    /// in sequence order, so everything this session wrote below that was acknowledged. Once a
+    /// delivery went untracked, the session hands on the point it started from.
+    pub(crate) fn resend_after(&self) -> Option<i64> {
+        if self.untracked {
+            return self.fetched_after;
+        }
+        match self.delivered.values().map(|written| written.seq).min() {
+            Some(oldest) => Some(oldest.saturating_sub(1)),
+            None => self.fetched_after.max(self.written_seq),
+        }
+    }
+
+    /// Highest sequence written in this session, if it is above the one the session started
+    /// with and so needs recording.
+    pub(crate) fn new_delivered_mark(&self) -> Option<DeliveredMark> {
+        self.highest_seq
+            .filter(|seq| self.previously_delivered_seq.is_none_or(|previous| *seq > previous))
+            .map(|seq| DeliveredMark { seq, session_started_at: self.connected_at })
+    }
+
+    /// Messages already waiting when the device connected were picked up because the app was
+    /// woken (usually by a push notification); anything queued later was delivered live.
+    fn transport(&self, enqueued_at: OffsetDateTime) -> &'static str {
+        if enqueued_at < self.connected_at { "push" } else { "websocket" }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stamp(id: Uuid, seq: i64) -> EnvelopeStamp {
+        EnvelopeStamp { id, seq, enqueued_at: OffsetDateTime::now_utc() }
+    }
+
+    #[test]
+    fn test_transport_classification() {
+        let tracker = DeliveryTracker::new(Metrics::new(), DeliveryStats::default(), None);
+        assert_eq!(tracker.transport(tracker.connected_at - time::Duration::seconds(5)), "push");
+        assert_eq!(tracker.transport(tracker.connected_at + time::Duration::milliseconds(1)), "websocket");
+    }
+
+    #[test]
+    fn test_ack_clears_tracked_delivery() {
+        let mut tracker = DeliveryTracker::new(Metrics::new(), DeliveryStats::default(), None);
+        let id = Uuid::new_v4();
+
+        tracker.record_delivered(&[stamp(id, 1)]);
+        assert!(tracker.delivered.contains_key(&id));
+
+        tracker.record_acked(&[id, Uuid::new_v4()]);
+        assert!(tracker.delivered.is_empty());
+    }
+
+    #[test]
+    fn test_tracked_deliveries_are_bounded() {
+        let mut tracker = DeliveryTracker::new(Metrics::new(), DeliveryStats::default(), None);
+        let envelopes: Vec<_> = (0..MAX_TRACKED_DELIVERIES + 10).map(|_| stamp(Uuid::new_v4(), 1)).collect();
+
+        tracker.record_delivered(&envelopes);
+        assert_eq!(tracker.delivered.len(), MAX_TRACKED_DELIVERIES);
+    }
+
+    #[test]
+    fn test_redeliveries_are_told_apart_by_sequence() {
+        let stats = DeliveryStats::default();
+        let mut tracker = DeliveryTracker::new(Metrics::new(), stats.clone(), Some(5));
+
+        let (old, new) = (Uuid::new_v4(), Uuid::new_v4());
+        tracker.record_delivered(&[stamp(old, 5), stamp(new, 6)]);
+        tracker.record_acked(&[old, new, Uuid::new_v4()]);
+
+        assert_eq!(stats.snapshot(), DeliverySnapshot { delivered: 1, redelivered: 1, acked: 2 });
+        assert_eq!(tracker.new_delivered_mark().map(|mark| mark.seq), Some(6));
+    }
+
+    #[test]
+    fn test_resumed_state_carries_over() {
+        let stats = DeliveryStats::default();
+        let mut tracker = DeliveryTracker::new(Metrics::new(), stats.clone(), Some(4));
+
+        tracker.resume(Some(7), Some(2));
+        assert_eq!(tracker.highest_seq(), Some(7));
+        assert_eq!(tracker.new_delivered_mark().map(|mark| mark.seq), Some(7));
+        assert_eq!(tracker.resend_after(), Some(2), "Nothing was sent again yet");
+
+        let (acked, pending) = (Uuid::new_v4(), Uuid::new_v4());
+        tracker.record_delivered(&[stamp(acked, 3), stamp(pending, 5), stamp(Uuid::new_v4(), 8)]);
+        tracker.record_acked(&[acked]);
+        assert_eq!(tracker.resend_after(), Some(4));
+        assert_eq!(stats.snapshot(), DeliverySnapshot { delivered: 2, redelivered: 1, acked: 1 });
+    }
+
+    #[test]
+    fn test_resend_point_follows_acknowledgements() {
+        let mut tracker = DeliveryTracker::new(Metrics::new(), DeliveryStats::default(), None);
+        assert_eq!(tracker.resend_after(), None);
+
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        tracker.record_delivered(&[stamp(first, 1), stamp(second, 2)]);
+        assert_eq!(tracker.resend_after(), Some(0));
+
+        tracker.record_acked(&[first, second]);
+        assert_eq!(tracker.resend_after(), Some(2));
+
+        let envelopes: Vec<_> = (3..).take(MAX_TRACKED_DELIVERIES + 1).map(|seq| stamp(Uuid::new_v4(), seq)).collect();
+        tracker.record_delivered(&envelopes);
+        assert_eq!(tracker.resend_after(), None, "Untracked deliveries fall back to where the session started");
+    }
+
+    #[test]
+    fn test_delivered_seq_is_only_recorded_when_it_advances() {
+        let mut tracker = DeliveryTracker::new(Metrics::new(), DeliveryStats::default(), Some(9));
+        assert_eq!(tracker.new_delivered_mark().map(|mark| mark.seq), None);
+
+        tracker.record_delivered(&[stamp(Uuid::new_v4(), 3)]);
+        assert_eq!(tracker.new_delivered_mark().map(|mark| mark.seq), None);
+
+        let mut tracker = DeliveryTracker::new(Metrics::new(), DeliveryStats::default(), None);
+        tracker.record_delivered(&[stamp(Uuid::new_v4(), -2)]);
+        assert_eq!(tracker.new_delivered_mark().map(|mark| mark.seq), Some(-2));
+    }
+}
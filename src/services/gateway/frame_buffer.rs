@@ -0,0 +1,48 @@
+use bytes::{Bytes, BytesMut};
+use prost::{EncodeError, Message};
+
+/// Reusable buffer that outbound frames are encoded into.
+///
+/// Each frame is split off as `Bytes` sharing the buffer's allocation. Once the socket has
+/// written and dropped every frame split from it, the next `reserve` reclaims that allocation
+/// instead of allocating afresh, so a busy session settles on one buffer.
+#[derive(Debug, Default)]
+pub(crate) struct FrameBuffer {
+    buf: BytesMut,
+}
+
+impl FrameBuffer {
+    /// Encodes `message` and returns it as a frame body.
+    ///
+    /// # Errors
+    /// Returns `EncodeError` if the buffer cannot hold the message, which the reservation rules out.
+    pub(crate) fn encode(&mut self, message: &impl Message) -> Result<Bytes, EncodeError> {
+        self.buf.reserve(message.encoded_len());
+        message.encode(&mut self.buf)?;
+        Ok(self.buf.split().freeze())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::obscura::v1 as proto;
+
+    #[test]
+    fn test_frames_decode_and_reuse_the_allocation() {
+        let mut frames = FrameBuffer::default();
+        let frame = proto::WebSocketFrame {
+            payload: Some(proto::web_socket_frame::Payload::EnvelopeBatch(proto::EnvelopeBatch {
+                envelopes: vec![proto::Envelope { message: vec![7; 512], ..Default::default() }],
+            })),
+        };
+
+        let first = frames.encode(&frame).expect("encodes");
+        assert_eq!(proto::WebSocketFrame::decode(first.clone()).expect("decodes"), frame);
+        let first_ptr = first.as_ptr();
+        drop(first);
+
+        let second = frames.encode(&frame).expect("encodes");
+        assert_eq!(second.as_ptr(), first_ptr, "the dropped frame's allocation should be reused");
+    }
+}
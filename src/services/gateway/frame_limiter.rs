@@ -0,0 +1,75 @@
+use std::time::{Duration, Instant};
+
+/// `FrameLimiter` is a token bucket bounding how fast a client may send frames on its session.
+///
+/// Every inbound frame takes one token. The bucket holds up to `burst` tokens and refills at
+/// `per_second`, so a client may briefly exceed the sustained rate (e.g. a burst of ACKs after
+/// a large batch) but cannot keep the session busy decoding frames indefinitely.
+#[derive(Debug)]
+pub(crate) struct FrameLimiter {
+    capacity: f64,
+    refill_per_sec: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl FrameLimiter {
+    pub(crate) fn new(burst: u32, per_second: u32) -> Self {
+        let capacity = f64::from(burst.max(1));
+        Self { capacity, refill_per_sec: f64::from(per_second.max(1)), tokens: capacity, last_refill: Instant::now() }
+    }
+
+    /// Takes a token for one inbound frame. Returns `false` once the client has exhausted its budget.
+    pub(crate) fn try_acquire(&mut self) -> bool {
+        self.try_acquire_at(Instant::now())
+    }
+
+    /// Time for an empty bucket to refill to a full burst, the backoff hinted to a client that ran it dry.
+    pub(crate) fn refill_time(&self) -> Duration {
+        Duration::from_secs_f64(self.capacity / self.refill_per_sec)
+    }
+
+    fn try_acquire_at(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = elapsed.mul_add(self.refill_per_sec, self.tokens).min(self.capacity);
+        self.last_refill = now;
+
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_burst_then_reject() {
+        let mut limiter = FrameLimiter::new(3, 1);
+        let now = limiter.last_refill;
+        assert!(limiter.try_acquire_at(now));
+        assert!(limiter.try_acquire_at(now));
+        assert!(limiter.try_acquire_at(now));
+        assert!(!limiter.try_acquire_at(now), "Burst must be bounded");
+    }
+
+    #[test]
+    fn test_refills_over_time_up_to_burst() {
+        let mut limiter = FrameLimiter::new(2, 10);
+        let start = limiter.last_refill;
+        assert!(limiter.try_acquire_at(start));
+        assert!(limiter.try_acquire_at(start));
+        assert!(!limiter.try_acquire_at(start));
+
+        assert!(limiter.try_acquire_at(start + Duration::from_millis(100)), "One token refills after 100ms");
+        assert!(!limiter.try_acquire_at(start + Duration::from_millis(100)));
+
+        let later = start + Duration::from_secs(60);
+        assert!(limiter.try_acquire_at(later));
+        assert!(limiter.try_acquire_at(later));
+        assert!(!limiter.try_acquire_at(later), "Idle time must not accumulate beyond the burst");
+    }
+}
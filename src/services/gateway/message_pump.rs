@@ -1,171 +1,256 @@
+use crate::domain::message::SystemCode;
 use crate::error::Result;
 use crate::proto::obscura::v1 as proto;
 use crate::services::gateway::Metrics;
+use crate::services::gateway::batch_sizer::BatchSizer;
+use crate::services::gateway::close_reason::CloseReason;
+use crate::services::gateway::credit_gate::CreditGate;
+use crate::services::gateway::delivery_tracker::{EnvelopeStamp, OutboundFrame};
+use crate::services::gateway::frame_buffer::FrameBuffer;
 use crate::services::message_service::MessageService;
+use crate::telemetry;
 use axum::extract::ws::Message as WsMessage;
 use opentelemetry::KeyValue;
 use prost::Message as ProstMessage;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
 use tracing::Instrument;
 use uuid::Uuid;
 
 /// `MessagePump` coalesces multiple delivery notifications into a single background
 /// database poll to avoid overwhelming the database with redundant queries.
+///
+/// A fetch that fails stops the pump, and [`Self::failed`] reports how the session should be
+/// closed, so the client reconnects instead of waiting on an inbox that is no longer read.
 pub struct MessagePump {
     notify_tx: mpsc::Sender<()>,
+    failed_rx: watch::Receiver<Option<CloseReason>>,
 }
 
 impl MessagePump {
+    /// Only messages above `start_after` are fetched, so a resumed session skips those its
+    /// client already acknowledged.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         device_id: Uuid,
+        start_after: Option<i64>,
         message_service: MessageService,
-        outbound_tx: mpsc::Sender<WsMessage>,
+        outbound_tx: mpsc::Sender<OutboundFrame>,
         metrics: Metrics,
-        batch_limit: i64,
+        sizer: BatchSizer,
+        credits: Option<CreditGate>,
         max_batch_bytes: usize,
     ) -> Self {
         // Channel size 1 effectively coalesces notifications while a fetch is in progress.
         let (notify_tx, notify_rx) = mpsc::channel(1);
+        let (failed_tx, failed_rx) = watch::channel(None);
 
+        let worker = PumpWorker {
+            device_id,
+            start_after,
+            message_service,
+            outbound_tx,
+            metrics,
+            sizer,
+            credits,
+            max_batch_bytes,
+            frames: FrameBuffer::default(),
+            failed_tx,
+        };
         tokio::spawn(
             async move {
-                Self::run_background(
-                    device_id,
-                    notify_rx,
-                    message_service,
-                    outbound_tx,
-                    metrics,
-                    batch_limit,
-                    max_batch_bytes,
-                )
-                .await;
+                worker.run(notify_rx).await;
             }
-            .instrument(tracing::info_span!("message_pump", "device.id" = %device_id)),
+            .instrument(tracing::info_span!("message_pump", "device.id" = %telemetry::id(device_id))),
         );
 
-        Self { notify_tx }
+        Self { notify_tx, failed_rx }
     }
 
     pub fn notify(&self) {
         let _ = self.notify_tx.try_send(());
     }
 
-    async fn run_background(
-        device_id: Uuid,
-        mut rx: mpsc::Receiver<()>,
-        message_service: MessageService,
-        outbound_tx: mpsc::Sender<WsMessage>,
-        metrics: Metrics,
-        limit: i64,
-        max_batch_bytes: usize,
-    ) {
-        let mut cursor: Option<(time::OffsetDateTime, Uuid)> = None;
+    /// Resolves with the close reason for the error that stopped the pump. Never resolves while
+    /// the pump is running.
+    pub async fn failed(&mut self) -> CloseReason {
+        loop {
+            if let Some(reason) = *self.failed_rx.borrow_and_update() {
+                return reason;
+            }
+            if self.failed_rx.changed().await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+/// State owned by the background task behind a `MessagePump`.
+struct PumpWorker {
+    device_id: Uuid,
+    start_after: Option<i64>,
+    message_service: MessageService,
+    outbound_tx: mpsc::Sender<OutboundFrame>,
+    metrics: Metrics,
+    sizer: BatchSizer,
+    credits: Option<CreditGate>,
+    max_batch_bytes: usize,
+    frames: FrameBuffer,
+    failed_tx: watch::Sender<Option<CloseReason>>,
+}
+
+impl PumpWorker {
+    async fn run(mut self, mut rx: mpsc::Receiver<()>) {
+        let mut cursor = self.start_after;
 
         while rx.recv().await.is_some() {
             // Continues fetching until the backlog is fully drained for the user.
-            while matches!(
-                Self::flush_batch(
-                    device_id,
-                    &message_service,
-                    &outbound_tx,
-                    &metrics,
-                    limit,
-                    max_batch_bytes,
-                    &mut cursor
-                )
-                .await,
-                Ok(true)
-            ) {}
+            loop {
+                let mut limit = self.sizer.limit();
+
+                // In credit mode the fetch never exceeds what the client has room for.
+                let mut reserved = 0;
+                if let Some(credits) = &self.credits {
+                    reserved = credits.reserve(usize::try_from(limit).unwrap_or(usize::MAX)).await;
+                    if reserved == 0 {
+                        return;
+                    }
+                    limit = limit.min(i64::try_from(reserved).unwrap_or(i64::MAX));
+                }
+
+                self.metrics.fetch_batch_limit.record(u64::try_from(limit).unwrap_or(0), &[]);
+
+                let fetched = self.flush_batch(limit, &mut cursor).await;
+
+                if let Some(credits) = &self.credits {
+                    credits.refund(reserved.saturating_sub(*fetched.as_ref().unwrap_or(&0)));
+                }
+                let fetched = match fetched {
+                    Ok(fetched) => fetched,
+                    Err(e) => {
+                        tracing::warn!(error = %e, "Failed to fetch pending messages, closing session");
+                        let _ = self.failed_tx.send(Some(e.close_reason()));
+                        return;
+                    }
+                };
+
+                if fetched == 0 {
+                    break;
+                }
+                self.sizer.ack_latency().mark_sent();
+
+                let backlog = fetched >= usize::try_from(limit).unwrap_or(usize::MAX);
+                self.sizer.observe(Self::buffer_pressure(&self.outbound_tx), backlog);
+
+                if !backlog {
+                    break;
+                }
+            }
         }
     }
 
+    /// Fraction of the outbound buffer currently occupied by frames the socket has not yet written.
+    #[allow(clippy::cast_precision_loss)]
+    fn buffer_pressure(outbound_tx: &mpsc::Sender<OutboundFrame>) -> f64 {
+        let max = outbound_tx.max_capacity();
+        if max == 0 {
+            return 0.0;
+        }
+        (max - outbound_tx.capacity()) as f64 / max as f64
+    }
+
     #[tracing::instrument(
         err(level = "debug"),
-        skip(service, outbound_tx, metrics, cursor),
-        fields(user.id = %device_id, batch_count = tracing::field::Empty)
+        skip_all,
+        fields(user.id = %telemetry::id(self.device_id), batch_count = tracing::field::Empty, %limit)
     )]
-    async fn flush_batch(
-        device_id: Uuid,
-        service: &MessageService,
-        outbound_tx: &mpsc::Sender<WsMessage>,
-        metrics: &Metrics,
-        limit: i64,
-        max_batch_bytes: usize,
-        cursor: &mut Option<(time::OffsetDateTime, Uuid)>,
-    ) -> Result<bool> {
-        let messages = service.fetch_pending_batch(device_id, *cursor, limit).await?;
+    async fn flush_batch(&mut self, limit: i64, cursor: &mut Option<i64>) -> Result<usize> {
+        let messages = self.message_service.fetch_pending_batch(self.device_id, *cursor, limit).await?;
 
         if messages.is_empty() {
-            return Ok(false);
+            return Ok(0);
         }
 
         let batch_size = messages.len();
         tracing::Span::current().record("batch.count", batch_size);
 
-        if let Some(last_msg) = messages.last()
-            && let Some(ts) = last_msg.created_at
-        {
-            *cursor = Some((ts, last_msg.id));
+        if let Some(last_msg) = messages.last() {
+            *cursor = Some(last_msg.seq);
         }
 
-        let envelopes: Vec<proto::Envelope> = messages
+        let now = time::OffsetDateTime::now_utc();
+        let envelopes: Vec<(proto::Envelope, EnvelopeStamp)> = messages
             .into_iter()
             .map(|msg| {
-                let timestamp = msg.created_at.map_or_else(
-                    || u64::try_from(time::OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000).unwrap_or(0),
-                    |ts| u64::try_from(ts.unix_timestamp_nanos() / 1_000_000).unwrap_or(0),
-                );
+                let enqueued_at = msg.created_at.unwrap_or(now);
+                let timestamp = u64::try_from(enqueued_at.unix_timestamp_nanos() / 1_000_000).unwrap_or(0);
 
-                proto::Envelope {
+                let envelope = proto::Envelope {
                     id: msg.id.as_bytes().to_vec(),
-                    sender_id: msg.sender_id.as_bytes().to_vec(),
+                    sender_id: msg.sender_id.map(|id| id.as_bytes().to_vec()).unwrap_or_default(),
                     timestamp,
                     message: msg.content,
-                    sender_device_id: msg.sender_device_id.as_bytes().to_vec(),
-                }
+                    sender_device_id: msg.sender_device_id.map(|id| id.as_bytes().to_vec()).unwrap_or_default(),
+                    pair_sequence: msg.pair_seq.and_then(|seq| u64::try_from(seq).ok()).unwrap_or(0),
+                    system_code: msg.system_code.map_or(proto::SystemCode::Unspecified, proto_system_code) as i32,
+                };
+                (envelope, EnvelopeStamp { id: msg.id, seq: msg.seq, enqueued_at })
             })
             .collect();
 
         // Split envelopes into sub-batches that stay under the WebSocket frame
         // size limit, sending each as a separate EnvelopeBatch frame.
         let mut current_batch: Vec<proto::Envelope> = Vec::new();
+        let mut current_stamps = Vec::new();
         let mut current_size: usize = 0;
 
-        for envelope in envelopes {
+        for (envelope, stamp) in envelopes {
             let envelope_size = envelope.encoded_len();
 
-            if !current_batch.is_empty() && current_size + envelope_size > max_batch_bytes {
-                Self::send_batch(std::mem::take(&mut current_batch), outbound_tx, metrics).await?;
+            if !current_batch.is_empty() && current_size + envelope_size > self.max_batch_bytes {
+                Self::send_batch(
+                    std::mem::take(&mut current_batch),
+                    std::mem::take(&mut current_stamps),
+                    &self.outbound_tx,
+                    &self.metrics,
+                    &mut self.frames,
+                )
+                .await?;
                 current_size = 0;
             }
 
             current_size += envelope_size;
             current_batch.push(envelope);
+            current_stamps.push(stamp);
         }
 
         if !current_batch.is_empty() {
-            Self::send_batch(current_batch, outbound_tx, metrics).await?;
+            Self::send_batch(current_batch, current_stamps, &self.outbound_tx, &self.metrics, &mut self.frames).await?;
         }
 
-        Ok(batch_size >= usize::try_from(limit).unwrap_or(usize::MAX))
+        Ok(batch_size)
     }
 
     async fn send_batch(
         envelopes: Vec<proto::Envelope>,
-        outbound_tx: &mpsc::Sender<WsMessage>,
+        stamps: Vec<EnvelopeStamp>,
+        outbound_tx: &mpsc::Sender<OutboundFrame>,
         metrics: &Metrics,
+        frames: &mut FrameBuffer,
     ) -> Result<bool> {
         let batch = proto::EnvelopeBatch { envelopes };
         let frame = proto::WebSocketFrame { payload: Some(proto::web_socket_frame::Payload::EnvelopeBatch(batch)) };
-        let mut buf = Vec::new();
 
-        if let Err(err) = frame.encode(&mut buf) {
-            metrics.outbound_dropped_total.add(1, &[KeyValue::new("reason", "encode_failed")]);
-            tracing::warn!(error = ?err, "failed to encode outbound websocket frame");
-            return Ok(false);
-        }
+        let bytes = match frames.encode(&frame) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                metrics.outbound_dropped_total.add(1, &[KeyValue::new("reason", "encode_failed")]);
+                tracing::warn!(error = ?err, "failed to encode outbound websocket frame");
+                return Ok(false);
+            }
+        };
 
-        if outbound_tx.send(WsMessage::Binary(buf.into())).await.is_err() {
+        let frame = OutboundFrame { message: WsMessage::Binary(bytes), envelopes: stamps };
+        if outbound_tx.send(frame).await.is_err() {
             metrics.outbound_dropped_total.add(1, &[KeyValue::new("reason", "channel_closed")]);
             return Ok(false);
         }
@@ -173,3 +258,11 @@ impl MessagePump {
         Ok(true)
     }
 }
+
+const fn proto_system_code(code: SystemCode) -> proto::SystemCode {
+    match code {
+        SystemCode::Announcement => proto::SystemCode::Announcement,
+        SystemCode::RecipientGone => proto::SystemCode::RecipientGone,
+        SystemCode::ServiceMessage => proto::SystemCode::ServiceMessage,
+    }
+}
@@ -1,18 +1,40 @@
 #![allow(unreachable_pub)]
 pub(crate) mod ack_batcher;
+pub(crate) mod announcement_pump;
+pub(crate) mod batch_sizer;
+pub mod close_reason;
+pub(crate) mod credit_gate;
+pub mod delivery_tracker;
+pub(crate) mod frame_buffer;
+pub(crate) mod frame_limiter;
 pub(crate) mod message_pump;
 pub(crate) mod prekey_pump;
+pub mod routing;
 pub(crate) mod session;
+pub(crate) mod session_limits;
+pub(crate) mod upload_progress;
 
-use crate::config::WsConfig;
+use crate::adapters::key_value::session_resume::ResumeState;
+use crate::adapters::key_value::{SessionRegistry, SessionResume};
+use crate::adapters::redis::AckSpill;
+use crate::adapters::retry::RetryPolicy;
+use crate::config::{SessionPolicy, WsConfig};
+use crate::domain::notification::UserEvent;
 use crate::proto::obscura::v1 as proto;
+use crate::services::announcement_service::AnnouncementService;
+use crate::services::auth_service::AuthService;
+use crate::services::gateway::ack_batcher::AckPersistence;
+use crate::services::gateway::delivery_tracker::DeliveryStats;
+use crate::services::gateway::routing::{RoutingHint, SessionCounter};
 use crate::services::gateway::session::Session;
+use crate::services::gateway::session_limits::{SessionLimit, SessionLimits, SessionSlot};
+use crate::services::gateway::upload_progress::UploadProgress;
 use crate::services::key_service::KeyService;
 use crate::services::message_service::MessageService;
 use crate::services::notification_service::NotificationService;
 use axum::extract::ws::{Message as WsMessage, WebSocket};
 use opentelemetry::{
-    global,
+    KeyValue, global,
     metrics::{Counter, Histogram, UpDownCounter},
 };
 use prost::Message as ProstMessage;
@@ -21,10 +43,19 @@ use uuid::Uuid;
 #[derive(Clone, Debug)]
 pub(crate) struct Metrics {
     pub(crate) ack_batch_size: Histogram<u64>,
+    pub(crate) ack_delete_failures_total: Counter<u64>,
     pub(crate) outbound_dropped_total: Counter<u64>,
     pub(crate) active_connections: UpDownCounter<i64>,
     pub(crate) ack_queue_dropped_total: Counter<u64>,
     pub(crate) acks_received_total: Counter<u64>,
+    pub(crate) fetch_batch_limit: Histogram<u64>,
+    pub(crate) queue_to_delivery_seconds: Histogram<f64>,
+    pub(crate) delivery_to_ack_seconds: Histogram<f64>,
+    pub(crate) envelopes_delivered_total: Counter<u64>,
+    pub(crate) envelopes_redelivered_total: Counter<u64>,
+    pub(crate) envelopes_acked_total: Counter<u64>,
+    pub(crate) closes_total: Counter<u64>,
+    pub(crate) sessions_refused_total: Counter<u64>,
 }
 
 impl Metrics {
@@ -36,6 +67,12 @@ impl Metrics {
                 .u64_histogram("obscura_websocket_ack_batch_size")
                 .with_description("Size of ACK batches processed")
                 .build(),
+            ack_delete_failures_total: meter
+                .u64_counter("obscura_websocket_ack_delete_failures_total")
+                .with_description(
+                    "ACK batches whose delete failed after retries, labelled by whether they were spilled or lost",
+                )
+                .build(),
             outbound_dropped_total: meter
                 .u64_counter("obscura_websocket_outbound_dropped_total")
                 .with_description("Total messages dropped due to full outbound buffer")
@@ -52,6 +89,42 @@ impl Metrics {
                 .u64_counter("obscura_websocket_acks_received_total")
                 .with_description("Total ACKs received from clients")
                 .build(),
+            fetch_batch_limit: meter
+                .u64_histogram("obscura_websocket_fetch_batch_limit")
+                .with_description("Adaptive message fetch limit chosen for each gateway database poll")
+                .build(),
+            queue_to_delivery_seconds: meter
+                .f64_histogram("obscura_message_queue_to_delivery_seconds")
+                .with_description("Time from a message being enqueued to its envelope being written to the socket")
+                .build(),
+            delivery_to_ack_seconds: meter
+                .f64_histogram("obscura_message_delivery_to_ack_seconds")
+                .with_description("Time from an envelope being written to the socket to the client acknowledging it")
+                .build(),
+            envelopes_delivered_total: meter
+                .u64_counter("obscura_envelopes_delivered_total")
+                .with_description("Envelopes written to a session for the first time, labelled by transport")
+                .build(),
+            envelopes_redelivered_total: meter
+                .u64_counter("obscura_envelopes_redelivered_total")
+                .with_description(
+                    "Envelopes written again after an earlier session already received them, labelled by transport",
+                )
+                .build(),
+            envelopes_acked_total: meter
+                .u64_counter("obscura_envelopes_acked_total")
+                .with_description(
+                    "Written envelopes the client acknowledged in the same session, labelled by transport",
+                )
+                .build(),
+            closes_total: meter
+                .u64_counter("obscura_websocket_server_closes_total")
+                .with_description("Sessions closed by the server, labelled by close reason")
+                .build(),
+            sessions_refused_total: meter
+                .u64_counter("obscura_websocket_sessions_refused_total")
+                .with_description("Gateway handshakes refused before the upgrade, labelled by reason")
+                .build(),
         }
     }
 }
@@ -62,47 +135,230 @@ impl Default for Metrics {
     }
 }
 
+/// A device's place in the session registry and under the session limits, decided before the
+/// WebSocket upgrade. The slot is given back when the claim is dropped.
+#[derive(Debug)]
+pub(crate) struct SessionClaim {
+    pub(crate) device_id: Uuid,
+    pub(crate) session_id: Uuid,
+    _slot: SessionSlot,
+}
+
+/// Why a gateway session was refused before the WebSocket upgrade.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SessionRefusal {
+    /// The session policy does not allow another session for the device.
+    DeviceConnected,
+    /// One of the session limits is reached.
+    Limit(SessionLimit),
+}
+
+impl SessionRefusal {
+    /// Label for the refusal metrics.
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::DeviceConnected => "session_policy",
+            Self::Limit(limit) => limit.as_str(),
+        }
+    }
+}
+
+/// What the client asked for when it opened a session.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SessionOptions {
+    pub(crate) credit_flow: bool,
+    /// Token the session leaves its delivery state under when the server shuts down.
+    pub(crate) resume_token: Option<String>,
+    /// Token of an earlier session whose delivery state this one picks up.
+    pub(crate) resume_from: Option<String>,
+}
+
 #[derive(Clone, Debug)]
 pub(crate) struct GatewayService {
+    auth_service: AuthService,
     message_service: MessageService,
     key_service: KeyService,
+    announcement_service: AnnouncementService,
     notifier: NotificationService,
     config: WsConfig,
+    routing_secret: String,
+    sessions: SessionCounter,
+    limits: SessionLimits,
+    registry: Option<SessionRegistry>,
+    resume: Option<SessionResume>,
+    upload_progress: Option<UploadProgress>,
+    ack_persistence: Option<AckPersistence>,
+    delivery_stats: DeliveryStats,
     metrics: Metrics,
 }
 
 impl GatewayService {
     #[must_use]
     pub(crate) fn new(
+        auth_service: AuthService,
         message_service: MessageService,
         key_service: KeyService,
+        announcement_service: AnnouncementService,
         notifier: NotificationService,
         config: WsConfig,
+        routing_secret: String,
     ) -> Self {
-        Self { message_service, key_service, notifier, config, metrics: Metrics::new() }
+        let limits = SessionLimits::new(config.max_sessions, config.max_sessions_per_user);
+        Self {
+            auth_service,
+            message_service,
+            key_service,
+            announcement_service,
+            notifier,
+            config,
+            routing_secret,
+            sessions: SessionCounter::default(),
+            limits,
+            registry: None,
+            resume: None,
+            upload_progress: None,
+            ack_persistence: None,
+            delivery_stats: DeliveryStats::default(),
+            metrics: Metrics::new(),
+        }
+    }
+
+    /// Enforces the session policy across instances through the shared registry.
+    /// Without one, sessions for the same device always coexist.
+    #[must_use]
+    pub(crate) fn with_session_registry(mut self, registry: SessionRegistry) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    /// Lets sessions receive progress frames for the attachments their device is uploading.
+    #[must_use]
+    pub(crate) fn with_session_resume(mut self, resume: SessionResume) -> Self {
+        self.resume = Some(resume);
+        self
+    }
+
+    #[must_use]
+    pub(crate) fn with_upload_progress(mut self, upload_progress: UploadProgress) -> Self {
+        self.upload_progress = Some(upload_progress);
+        self
+    }
+
+    /// Retries failed ACK deletes with `retry`, then spills the batches to `spill` if there is one.
+    /// Without it, a failed delete is logged and the messages are redelivered.
+    #[must_use]
+    pub(crate) fn with_ack_persistence(mut self, retry: RetryPolicy, spill: Option<AckSpill>) -> Self {
+        self.ack_persistence = Some(AckPersistence { retry, spill });
+        self
+    }
+
+    /// Takes a slot under the session limits and registers a new session for the device
+    /// according to the session policy.
+    ///
+    /// Registry failures are logged and let the session through, so a Redis outage does not
+    /// also keep devices offline.
+    pub(crate) async fn open_session(
+        &self,
+        device_id: Uuid,
+        user_id: Option<Uuid>,
+    ) -> Result<SessionClaim, SessionRefusal> {
+        let claim = self.claim_session(device_id, user_id).await;
+        if let Err(refusal) = &claim {
+            self.metrics.sessions_refused_total.add(1, &[KeyValue::new("reason", refusal.as_str())]);
+        }
+        claim
+    }
+
+    async fn claim_session(&self, device_id: Uuid, user_id: Option<Uuid>) -> Result<SessionClaim, SessionRefusal> {
+        let replaces = self.config.session_policy == SessionPolicy::Replace;
+        let slot = self.limits.acquire(device_id, user_id, replaces).map_err(SessionRefusal::Limit)?;
+        let session_id = Uuid::new_v4();
+        let claim = SessionClaim { device_id, session_id, _slot: slot };
+        let Some(registry) = &self.registry else {
+            return Ok(claim);
+        };
+
+        match self.config.session_policy {
+            SessionPolicy::Replace => match registry.claim(device_id, session_id).await {
+                // Older sessions, wherever they are, check the registry and close themselves.
+                Ok(()) => self.notifier.notify(&[device_id], UserEvent::SessionReplaced).await,
+                Err(e) => tracing::warn!(error = %e, "Failed to register gateway session"),
+            },
+            SessionPolicy::Reject => match registry.try_claim(device_id, session_id).await {
+                Ok(true) => {}
+                Ok(false) => return Err(SessionRefusal::DeviceConnected),
+                Err(e) => tracing::warn!(error = %e, "Failed to register gateway session"),
+            },
+            SessionPolicy::Multiple => {}
+        }
+        Ok(claim)
+    }
+
+    /// Seconds a client refused for a session limit is told to wait, spread over the shutdown
+    /// reconnect jitter so refused clients do not all come back at once.
+    pub(crate) fn refusal_retry_after_secs(&self) -> u64 {
+        rand::random_range(1..=self.config.shutdown_reconnect_jitter_ms.div_ceil(1000).max(1))
+    }
+
+    /// Whether sessions closed on shutdown leave state behind for their clients to resume.
+    pub(crate) const fn resumable(&self) -> bool {
+        self.resume.is_some()
+    }
+
+    /// Takes the state an earlier session left under `token`, provided it belongs to this device.
+    async fn take_resume_state(&self, device_id: Uuid, token: Option<&str>) -> Option<ResumeState> {
+        let (resume, token) = (self.resume.as_ref()?, token?);
+        match resume.take(token).await {
+            Ok(Some(state)) if state.device_id == device_id => Some(state),
+            Ok(Some(_)) => {
+                tracing::warn!("Ignoring resume token issued to another device");
+                None
+            }
+            Ok(None) => {
+                tracing::debug!("Resume token not found or expired, starting a fresh session");
+                None
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to load session resume state");
+                None
+            }
+        }
+    }
+
+    pub(crate) fn sessions(&self) -> SessionCounter {
+        self.sessions.clone()
+    }
+
+    pub(crate) fn delivery_stats(&self) -> DeliveryStats {
+        self.delivery_stats.clone()
+    }
+
+    pub(crate) fn routing_hint(&self, user_id: Uuid) -> RoutingHint {
+        RoutingHint::new(self.routing_secret.as_bytes(), user_id)
     }
 
     pub async fn handle_socket(
         &self,
         mut socket: WebSocket,
-        device_id: Uuid,
+        claim: SessionClaim,
+        auth_expires_at: usize,
         request_id: String,
+        options: SessionOptions,
         shutdown_rx: tokio::sync::watch::Receiver<bool>,
     ) {
         // Clients need to know if they are low on pre-keys immediately upon connection
         // to prevent exhausting their bundle during an active session.
-        match self.key_service.check_pre_key_status(device_id).await {
+        match self.key_service.check_pre_key_status(claim.device_id).await {
             Ok(Some(status)) => {
                 let frame = proto::WebSocketFrame {
                     payload: Some(proto::web_socket_frame::Payload::PreKeyStatus(proto::PreKeyStatus {
                         one_time_pre_key_count: status.one_time_pre_key_count,
                         min_threshold: status.min_threshold,
+                        max_pre_keys: status.max_pre_keys,
+                        recommended_upload_count: status.recommended_upload_count,
                     })),
                 };
-                let mut buf = Vec::new();
-                if frame.encode(&mut buf).is_ok() {
-                    let _ = socket.send(WsMessage::Binary(buf.into())).await;
-                }
+                let _ = socket.send(WsMessage::Binary(frame.encode_to_vec().into())).await;
             }
 
             Err(e) => {
@@ -112,15 +368,29 @@ impl GatewayService {
         }
 
         // 3. Hand over to Session
+        let resumed = self.take_resume_state(claim.device_id, options.resume_from.as_deref()).await;
+
         let session = Session {
-            device_id,
+            device_id: claim.device_id,
+            session_id: claim.session_id,
+            auth_expires_at,
             request_id,
             socket,
+            auth_service: self.auth_service.clone(),
             message_service: self.message_service.clone(),
             key_service: self.key_service.clone(),
+            announcement_service: self.announcement_service.clone(),
             notifier: self.notifier.clone(),
             metrics: self.metrics.clone(),
+            sessions: self.sessions.clone(),
+            registry: self.registry.clone().filter(|_| self.config.session_policy != SessionPolicy::Multiple),
+            resume: self.resume.clone().zip(options.resume_token),
+            resumed,
+            upload_progress: self.upload_progress.clone(),
+            ack_persistence: self.ack_persistence.clone(),
+            delivery_stats: self.delivery_stats.clone(),
             config: self.config.clone(),
+            credit_flow: options.credit_flow,
             shutdown_rx,
         };
 
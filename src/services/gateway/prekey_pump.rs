@@ -1,5 +1,7 @@
 use crate::proto::obscura::v1 as proto;
+use crate::services::gateway::delivery_tracker::OutboundFrame;
 use crate::services::key_service::KeyService;
+use crate::telemetry;
 use axum::extract::ws::Message as WsMessage;
 use prost::Message as ProstMessage;
 use std::time::Duration;
@@ -18,7 +20,7 @@ impl PreKeyPump {
     pub fn new(
         device_id: Uuid,
         key_service: KeyService,
-        outbound_tx: mpsc::Sender<WsMessage>,
+        outbound_tx: mpsc::Sender<OutboundFrame>,
         debounce_interval_ms: u64,
     ) -> Self {
         // Channel size 1 effectively drops notifications while a fetch is in progress or sleeping.
@@ -28,7 +30,7 @@ impl PreKeyPump {
             async move {
                 Self::run_background(device_id, notify_rx, key_service, outbound_tx, debounce_interval_ms).await;
             }
-            .instrument(tracing::info_span!("prekey_pump", "device.id" = %device_id)),
+            .instrument(tracing::info_span!("prekey_pump", "device.id" = %telemetry::id(device_id))),
         );
 
         Self { notify_tx }
@@ -42,7 +44,7 @@ impl PreKeyPump {
         device_id: Uuid,
         mut rx: mpsc::Receiver<()>,
         key_service: KeyService,
-        outbound_tx: mpsc::Sender<WsMessage>,
+        outbound_tx: mpsc::Sender<OutboundFrame>,
         debounce_interval_ms: u64,
     ) {
         while rx.recv().await.is_some() {
@@ -59,14 +61,13 @@ impl PreKeyPump {
                         payload: Some(proto::web_socket_frame::Payload::PreKeyStatus(proto::PreKeyStatus {
                             one_time_pre_key_count: status.one_time_pre_key_count,
                             min_threshold: status.min_threshold,
+                            max_pre_keys: status.max_pre_keys,
+                            recommended_upload_count: status.recommended_upload_count,
                         })),
                     };
-                    let mut buf = Vec::new();
-                    if frame.encode(&mut buf).is_ok() {
-                        // If outbound_tx is closed (user disconnected), we just break and exit
-                        if outbound_tx.send(WsMessage::Binary(buf.into())).await.is_err() {
-                            break;
-                        }
+                    // If outbound_tx is closed (user disconnected), we just break and exit
+                    if outbound_tx.send(WsMessage::Binary(frame.encode_to_vec().into()).into()).await.is_err() {
+                        break;
                     }
                 }
                 Ok(None) => {
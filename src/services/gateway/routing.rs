@@ -0,0 +1,72 @@
+use crate::adapters::crypto::hmac_sha256;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use uuid::Uuid;
+
+/// Number of live WebSocket sessions held by this instance.
+///
+/// Shared between the gateway and the management API so operators can see how
+/// sessions are spread across instances when tuning load balancer affinity.
+#[derive(Clone, Debug, Default)]
+pub struct SessionCounter {
+    active: Arc<AtomicUsize>,
+}
+
+impl SessionCounter {
+    #[must_use]
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn increment(&self) {
+        self.active.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn decrement(&self) {
+        self.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A stable, signed key a load balancer can hash on to pin a user to one instance.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct RoutingHint {
+    pub(crate) key: String,
+    pub(crate) signature: String,
+}
+
+impl RoutingHint {
+    /// Derives the hint for a user. The key is a hash of the user id so it reveals nothing
+    /// about the account; the signature lets the load balancer reject forged keys.
+    pub(crate) fn new(secret: &[u8], user_id: Uuid) -> Self {
+        let key = hex::encode(&Sha256::digest(user_id.as_bytes())[..16]);
+        let signature = hex::encode(hmac_sha256(secret, key.as_bytes()));
+        Self { key, signature }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routing_hint_is_stable_per_user() {
+        let user_id = Uuid::new_v4();
+        let a = RoutingHint::new(b"secret", user_id);
+        let b = RoutingHint::new(b"secret", user_id);
+        assert_eq!(a, b);
+        assert_eq!(a.key.len(), 32);
+
+        assert_ne!(a.key, RoutingHint::new(b"secret", Uuid::new_v4()).key);
+        assert_ne!(a.signature, RoutingHint::new(b"other", user_id).signature);
+    }
+
+    #[test]
+    fn test_session_counter() {
+        let counter = SessionCounter::default();
+        counter.increment();
+        counter.clone().increment();
+        counter.decrement();
+        assert_eq!(counter.active(), 1);
+    }
+}
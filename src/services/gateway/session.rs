@@ -1,37 +1,80 @@
+use crate::adapters::key_value::session_resume::ResumeState;
+use crate::adapters::key_value::{SessionRegistry, SessionResume};
 use crate::config::WsConfig;
+use crate::domain::auth::Jwt;
 use crate::domain::notification::UserEvent;
 use crate::proto::obscura::v1 as proto;
-use crate::services::gateway::{Metrics, ack_batcher::AckBatcher, message_pump::MessagePump, prekey_pump::PreKeyPump};
+use crate::services::announcement_service::AnnouncementService;
+use crate::services::auth_service::AuthService;
+use crate::services::gateway::{
+    Metrics,
+    ack_batcher::{AckBatcher, AckPersistence},
+    announcement_pump::AnnouncementPump,
+    batch_sizer::{AckLatencyTracker, BatchSizer},
+    close_reason::CloseReason,
+    credit_gate::CreditGate,
+    delivery_tracker::{DeliveryStats, DeliveryTracker},
+    frame_limiter::FrameLimiter,
+    message_pump::MessagePump,
+    prekey_pump::PreKeyPump,
+    routing::SessionCounter,
+    upload_progress::UploadProgress,
+};
 use crate::services::key_service::KeyService;
 use crate::services::message_service::MessageService;
 use crate::services::notification_service::NotificationService;
+use crate::telemetry;
 use axum::extract::ws::{Message as WsMessage, WebSocket};
 use futures::{SinkExt, StreamExt};
+use opentelemetry::KeyValue;
 use prost::Message;
-use tokio::sync::{broadcast, mpsc};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
 use uuid::Uuid;
 
+/// Reconnect hint for clients closed for sending invalid frames or after a server error retrying
+/// will not soon fix, so neither side spins.
+const PROTOCOL_ERROR_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+const MAX_AUTH_WINDOW_SECS: u64 = 365 * 24 * 60 * 60;
+
 pub struct Session {
     pub device_id: Uuid,
+    pub session_id: Uuid,
+    /// Expiry of the access token the session was opened with, as a Unix timestamp.
+    pub auth_expires_at: usize,
     pub request_id: String,
     pub socket: WebSocket,
+    pub auth_service: AuthService,
     pub message_service: MessageService,
     pub key_service: KeyService,
+    pub announcement_service: AnnouncementService,
     pub notifier: NotificationService,
     pub metrics: Metrics,
+    pub sessions: SessionCounter,
+    /// Set when the session policy allows a device only one session.
+    pub registry: Option<SessionRegistry>,
+    /// Where the session leaves its delivery state, and under which token, when the server shuts down.
+    pub resume: Option<(SessionResume, String)>,
+    /// State left by the session this one resumes.
+    pub resumed: Option<ResumeState>,
+    pub upload_progress: Option<UploadProgress>,
+    pub(crate) ack_persistence: Option<AckPersistence>,
+    pub delivery_stats: DeliveryStats,
     pub config: WsConfig,
+    pub credit_flow: bool,
     pub shutdown_rx: tokio::sync::watch::Receiver<bool>,
 }
 
 impl Session {
     #[tracing::instrument(
         name = "websocket_session",
-        skip(self),
+        skip_all,
         fields(
-            device.id = %self.device_id,
-            request.id = %self.request_id,
+            device.id = %telemetry::id(self.device_id),
+            request.id = telemetry::bounded(&self.request_id),
             otel.kind = "server",
-            ws.session_id = %Uuid::new_v4()
+            ws.session_id = %self.session_id
         )
     )]
     #[allow(clippy::too_many_lines)]
@@ -39,10 +82,31 @@ impl Session {
         // Destructuring allows independent mutable access to fields while the socket
         // is split into sink and stream halves.
         let Self {
-            device_id, socket, message_service, key_service, notifier, metrics, config, mut shutdown_rx, ..
+            device_id,
+            session_id,
+            auth_expires_at,
+            socket,
+            auth_service,
+            message_service,
+            key_service,
+            announcement_service,
+            notifier,
+            metrics,
+            sessions,
+            registry,
+            resume,
+            resumed,
+            upload_progress,
+            ack_persistence,
+            delivery_stats,
+            config,
+            credit_flow,
+            mut shutdown_rx,
+            ..
         } = self;
 
         metrics.active_connections.add(1, &[]);
+        sessions.increment();
         tracing::info!("WebSocket connected");
 
         // Immediately cancel any pending push notifications since the device is now connected.
@@ -58,43 +122,82 @@ impl Session {
         let ack_batcher = AckBatcher::new(
             device_id,
             message_service.clone(),
+            ack_persistence,
             metrics.clone(),
             config.ack_buffer_size,
             config.ack_batch_size,
             config.ack_flush_interval_ms,
         );
 
-        let message_pump = MessagePump::new(
+        let ack_latency = AckLatencyTracker::default();
+        let batch_sizer = BatchSizer::new(
+            config.message_fetch_batch_size,
+            config.message_fetch_batch_min,
+            config.message_fetch_batch_max,
+            Duration::from_millis(config.ack_latency_target_ms),
+            ack_latency.clone(),
+        );
+
+        // Clients that opt into credit-based flow control receive nothing until they grant credit.
+        let credits = credit_flow.then(|| CreditGate::new(config.max_credit));
+
+        let mut message_pump = MessagePump::new(
             device_id,
+            resumed.as_ref().and_then(|state| state.fetch_after),
             message_service.clone(),
             outbound_tx.clone(),
             metrics.clone(),
-            config.message_fetch_batch_size,
+            batch_sizer,
+            credits.clone(),
             config.max_batch_bytes,
         );
 
         let prekey_pump =
             PreKeyPump::new(device_id, key_service.clone(), outbound_tx.clone(), config.prekey_debounce_interval_ms);
 
+        let announcement_pump = AnnouncementPump::new(device_id, announcement_service, outbound_tx.clone());
+
+        if let Some(upload_progress) = &upload_progress {
+            upload_progress.register(device_id, session_id, outbound_tx.clone());
+        }
+
+        let delivered_seq = match message_service.delivered_seq(device_id).await {
+            Ok(seq) => seq,
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to load delivered sequence, counting every envelope as new");
+                None
+            }
+        };
+        let mut deliveries = DeliveryTracker::new(metrics.clone(), delivery_stats, delivered_seq);
+        if let Some(state) = resumed {
+            tracing::info!(cursor = ?state.cursor, fetch_after = ?state.fetch_after, "Resuming an earlier session");
+            deliveries.resume(state.cursor, state.fetch_after);
+        }
+        let mut inbound_limiter = FrameLimiter::new(config.inbound_frame_burst, config.inbound_frames_per_second);
+
         message_pump.notify();
+        // Announcements broadcast while the device was connecting are picked up here.
+        announcement_pump.notify();
 
         let mut last_seen = tokio::time::Instant::now();
-        let mut ping_interval = tokio::time::interval(std::time::Duration::from_secs(config.ping_interval_secs.max(1)));
+        let mut ping_interval = tokio::time::interval(Duration::from_secs(config.ping_interval_secs.max(1)));
         // First tick happens immediately, we skip it to start probing after the first interval.
         ping_interval.tick().await;
         ping_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
 
+        // Extended by Auth frames carrying a fresh token for this device.
+        let auth_deadline = tokio::time::sleep_until(deadline_for(auth_expires_at));
+        tokio::pin!(auth_deadline);
+
+        // Set when the server ends the session, so the client gets a GoAway and a close code.
+        let mut close_reason = None;
+
         loop {
             // Priority is given to shutdown and high-frequency events to ensure
             // the server remains responsive to control signals.
             if *shutdown_rx.borrow() {
                 tracing::info!("Shutdown signal received, closing WebSocket");
-                let _ = ws_sink
-                    .send(WsMessage::Close(Some(axum::extract::ws::CloseFrame {
-                        code: axum::extract::ws::close_code::AWAY,
-                        reason: "Server shutting down".into(),
-                    })))
-                    .await;
+                close_reason = Some(CloseReason::ServerShutdown);
                 break;
             }
 
@@ -105,7 +208,7 @@ impl Session {
 
                 _ = ping_interval.tick() => {
                     let now = tokio::time::Instant::now();
-                    let timeout = std::time::Duration::from_secs(config.ping_interval_secs + config.ping_timeout_secs);
+                    let timeout = Duration::from_secs(config.ping_interval_secs + config.ping_timeout_secs);
 
                     if now.duration_since(last_seen) > timeout {
                         tracing::warn!(
@@ -115,56 +218,105 @@ impl Session {
                         break;
                     }
 
+                    // Also catches a takeover whose notification this instance missed.
+                    if let Some(registry) = &registry {
+                        match registry.refresh(device_id, session_id).await {
+                            Ok(true) => {}
+                            Ok(false) => {
+                                tracing::info!("Device was taken over by a newer session, closing WebSocket");
+                                close_reason = Some(CloseReason::SessionReplaced);
+                                break;
+                            }
+                            Err(e) => tracing::warn!(error = %e, "Failed to refresh session registration"),
+                        }
+                    }
+
                     if ws_sink.send(WsMessage::Ping(Vec::new().into())).await.is_err() {
                         break;
                     }
                 }
 
+                reason = message_pump.failed() => {
+                    close_reason = Some(reason);
+                    break;
+                }
+
+                () = &mut auth_deadline => {
+                    tracing::info!("Access token expired without renewal, closing WebSocket");
+                    close_reason = Some(CloseReason::AuthExpired);
+                    break;
+                }
+
                 msg = ws_stream.next() => {
                     let continue_loop = match msg {
+                        Some(Ok(msg)) if !matches!(msg, WsMessage::Close(_)) && !inbound_limiter.try_acquire() => {
+                            tracing::warn!("Client exceeded the inbound frame rate, closing WebSocket");
+                            close_reason = Some(CloseReason::RateLimited);
+                            false
+                        }
                         Some(Ok(msg)) => {
                             last_seen = tokio::time::Instant::now();
                             match msg {
                                 WsMessage::Binary(bin) => {
                                     if let Ok(frame) = proto::WebSocketFrame::decode(bin.as_ref()) {
-                                        if let Some(proto::web_socket_frame::Payload::Ack(ack)) = frame.payload {
-                                            let mut uuids = Vec::new();
+                                        match frame.payload {
+                                            Some(proto::web_socket_frame::Payload::Ack(ack)) => {
+                                                if !ack.message_ids.is_empty() {
+                                                    metrics.acks_received_total.add(1, &[]);
+                                                }
+                                                let uuids = parse_ack_ids(ack.message_ids);
 
-                                            if !ack.message_ids.is_empty() {
-                                                metrics.acks_received_total.add(1, &[]);
+                                                if !uuids.is_empty() {
+                                                    ack_latency.mark_acked();
+                                                    deliveries.record_acked(&uuids);
+                                                    // Immediately cancel push notifications to avoid "phantom buzzes"
+                                                    // Run as fire-and-forget task to avoid blocking the WebSocket loop
+                                                    let notifier_clone = notifier.clone();
+                                                    tokio::spawn(async move {
+                                                        notifier_clone.cancel_pending_notifications(device_id).await;
+                                                    });
+                                                    ack_batcher.push(uuids);
+                                                }
+                                            }
+                                            Some(proto::web_socket_frame::Payload::Auth(auth)) => {
+                                                match auth_service.verify_token(&Jwt::new(auth.token)) {
+                                                    Ok(claims) if claims.device_id == Some(device_id) => {
+                                                        let renewed = deadline_for(claims.exp);
+                                                        if renewed > auth_deadline.deadline() {
+                                                            auth_deadline.as_mut().reset(renewed);
+                                                        }
+                                                        tracing::debug!("WebSocket session re-authenticated");
+                                                    }
+                                                    Ok(_) => {
+                                                        tracing::warn!("Received Auth frame with a token for another device");
+                                                    }
+                                                    Err(_) => {
+                                                        tracing::warn!("Received Auth frame with an invalid or expired token");
+                                                    }
+                                                }
                                             }
-                                            for id_bytes in ack.message_ids {
-                                                if let Ok(id) = Uuid::from_slice(&id_bytes) {
-                                                    uuids.push(id);
+                                            Some(proto::web_socket_frame::Payload::Credit(credit)) => {
+                                                if let Some(credits) = &credits {
+                                                    credits.grant(credit.envelopes);
                                                 } else {
-                                                    tracing::warn!(
-                                                        len = id_bytes.len(),
-                                                        hex = %hex::encode(&id_bytes),
-                                                        "Received ACK with invalid UUID bytes in list (expected 16)"
-                                                    );
+                                                    tracing::warn!("Received Credit frame without credit flow control");
                                                 }
                                             }
-
-                                            if !uuids.is_empty() {
-                                                // Immediately cancel push notifications to avoid "phantom buzzes"
-                                                // Run as fire-and-forget task to avoid blocking the WebSocket loop
-                                                let notifier_clone = notifier.clone();
-                                                tokio::spawn(async move {
-                                                    notifier_clone.cancel_pending_notifications(device_id).await;
-                                                });
-                                                ack_batcher.push(uuids);
+                                            _ => {
+                                                tracing::warn!("Received unexpected Protobuf payload type");
                                             }
-                                        } else {
-                                            tracing::warn!("Received unexpected Protobuf payload type");
                                         }
+                                        true
                                     } else {
-                                        tracing::warn!("Failed to decode WebSocket frame");
+                                        tracing::warn!("Failed to decode WebSocket frame, closing WebSocket");
+                                        close_reason = Some(CloseReason::ProtocolError);
+                                        false
                                     }
-                                    true
                                 }
                                 WsMessage::Text(t) => {
-                                    tracing::warn!("Received unexpected text message: {}", t);
-                                    true
+                                    tracing::warn!("Received unexpected text message, closing WebSocket: {}", t);
+                                    close_reason = Some(CloseReason::ProtocolError);
+                                    false
                                 }
                                 WsMessage::Ping(_) => {
                                     tracing::debug!("Received heartbeat ping from client");
@@ -186,31 +338,51 @@ impl Session {
 
                 msg = outbound_rx.recv() => {
                     match msg {
-                        Some(msg) => {
-                            if ws_sink.send(msg).await.is_err() { break; }
+                        Some(frame) => {
+                            if ws_sink.send(frame.message).await.is_err() { break; }
+                            deliveries.record_delivered(&frame.envelopes);
+                            if let Some(mark) = deliveries.new_delivered_mark() {
+                                ack_batcher.mark_delivered(mark);
+                            }
                         }
                         None => break,
                     }
                 }
 
-                result = notification_rx.recv() => {
-                    let continue_loop = match result {
-                        Ok(UserEvent::MessageReceived) => {
+                event = notification_rx.recv() => {
+                    let continue_loop = match event {
+                        UserEvent::MessageReceived => {
                             message_pump.notify();
                             true
                         }
-                        Err(broadcast::error::RecvError::Lagged(_)) => {
-                            // If the channel lagged (burst of events), we safely trigger both pumps
-                            // because we don't know which event we missed. The pumps will debounce.
-                            message_pump.notify();
+                        UserEvent::PreKeyLow => {
                             prekey_pump.notify();
                             true
                         }
-                        Ok(UserEvent::PreKeyLow) => {
-                            prekey_pump.notify();
+                        UserEvent::Announcement => {
+                            announcement_pump.notify();
                             true
                         }
-                        Ok(UserEvent::Disconnect) | Err(broadcast::error::RecvError::Closed) => false,
+                        UserEvent::Disconnect => {
+                            close_reason = Some(CloseReason::InboxWiped);
+                            false
+                        }
+                        UserEvent::SessionReplaced => match &registry {
+                            // The new session itself may see the event, so only a different owner counts.
+                            Some(registry) => match registry.owner(device_id).await {
+                                Ok(Some(owner)) if owner != session_id => {
+                                    tracing::info!("Device was taken over by a newer session, closing WebSocket");
+                                    close_reason = Some(CloseReason::SessionReplaced);
+                                    false
+                                }
+                                Ok(_) => true,
+                                Err(e) => {
+                                    tracing::warn!(error = %e, "Failed to look up session owner");
+                                    true
+                                }
+                            },
+                            None => true,
+                        },
                     };
 
                      if !continue_loop { break; }
@@ -218,9 +390,83 @@ impl Session {
             }
         }
 
+        if let Some(credits) = &credits {
+            credits.close();
+        }
+        // Saved before the GoAway so that a client told to come back at once finds it.
+        if close_reason == Some(CloseReason::ServerShutdown)
+            && let Some((resume, token)) = &resume
+        {
+            let state =
+                ResumeState { device_id, cursor: deliveries.highest_seq(), fetch_after: deliveries.resend_after() };
+            if let Err(e) = resume.save(token, &state).await {
+                tracing::warn!(error = %e, "Failed to save session resume state");
+            }
+        }
+        if let Some(reason) = close_reason {
+            let retry_after = match reason {
+                CloseReason::ServerShutdown | CloseReason::Unavailable => {
+                    // Spread reconnects so that every client of a draining instance does not return at once.
+                    Duration::from_millis(rand::random_range(0..=config.shutdown_reconnect_jitter_ms))
+                }
+                CloseReason::RateLimited => inbound_limiter.refill_time(),
+                CloseReason::ProtocolError | CloseReason::InternalError => PROTOCOL_ERROR_RETRY_AFTER,
+                CloseReason::AuthExpired | CloseReason::SessionReplaced | CloseReason::InboxWiped => Duration::ZERO,
+            };
+            metrics.closes_total.add(1, &[KeyValue::new("reason", reason.as_str())]);
+            for frame in reason.farewell(retry_after) {
+                if ws_sink.send(frame).await.is_err() {
+                    break;
+                }
+            }
+        }
         let _ = ws_sink.close().await;
 
+        // ACK flushes record it as the session goes; this also covers envelopes nobody acknowledged.
+        if let Some(mark) = deliveries.new_delivered_mark()
+            && let Err(e) = message_service.record_delivered_seq(device_id, mark.seq, mark.session_started_at).await
+        {
+            tracing::warn!(error = %e, "Failed to record delivered sequence");
+        }
+
+        if let Some(upload_progress) = &upload_progress {
+            upload_progress.unregister(device_id, session_id);
+        }
+
+        if let Some(registry) = &registry
+            && let Err(e) = registry.release(device_id, session_id).await
+        {
+            tracing::warn!(error = %e, "Failed to release session registration");
+        }
+
         metrics.active_connections.add(-1, &[]);
+        sessions.decrement();
         tracing::info!("WebSocket disconnected");
     }
 }
+
+/// Converts a token expiry in Unix seconds into a deadline on the Tokio clock.
+fn deadline_for(expires_at: usize) -> tokio::time::Instant {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let remaining = u64::try_from(expires_at).unwrap_or(u64::MAX).saturating_sub(now);
+    // Capped so that a far-future expiry cannot overflow the clock.
+    tokio::time::Instant::now() + Duration::from_secs(remaining.min(MAX_AUTH_WINDOW_SECS))
+}
+
+/// Message IDs of an ACK frame, skipping (and logging) any that are not 16 bytes.
+pub(crate) fn parse_ack_ids(message_ids: Vec<Vec<u8>>) -> Vec<Uuid> {
+    message_ids
+        .into_iter()
+        .filter_map(|id_bytes| {
+            let id = Uuid::from_slice(&id_bytes).ok();
+            if id.is_none() {
+                tracing::warn!(
+                    len = id_bytes.len(),
+                    hex = %hex::encode(&id_bytes),
+                    "Received ACK with invalid UUID bytes in list (expected 16)"
+                );
+            }
+            id
+        })
+        .collect()
+}
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::sync::{Arc, Mutex, PoisonError};
+use uuid::Uuid;
+
+/// The limit that kept a session from opening.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SessionLimit {
+    /// The instance holds as many sessions as it is configured for.
+    Instance,
+    /// The user already holds as many sessions on this instance as one user may.
+    User,
+}
+
+impl SessionLimit {
+    /// Label for the refusal metrics.
+    pub(crate) const fn as_str(self) -> &'static str {
+        match self {
+            Self::Instance => "instance_limit",
+            Self::User => "user_limit",
+        }
+    }
+}
+
+/// Caps the gateway sessions this instance holds, in total and per user, so a flood of
+/// connections cannot exhaust a small host. A limit of 0 disables it.
+///
+/// Slots are taken before the WebSocket upgrade, so handshakes in progress count too. A device
+/// reconnecting under the replace policy is let through even at a limit, since its new session
+/// displaces the one it already holds here. Only one such replacement is let through at a time,
+/// so a device reconnecting in a loop cannot pile up sessions past the limits.
+#[derive(Clone, Debug)]
+pub(crate) struct SessionLimits {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    max_sessions: usize,
+    max_per_user: usize,
+    held: Mutex<Held>,
+}
+
+#[derive(Debug, Default)]
+struct Held {
+    total: usize,
+    /// Only kept while the per-user limit is enabled.
+    per_user: HashMap<Uuid, usize>,
+    per_device: HashMap<Uuid, usize>,
+}
+
+impl SessionLimits {
+    pub(crate) fn new(max_sessions: usize, max_per_user: usize) -> Self {
+        Self { inner: Arc::new(Inner { max_sessions, max_per_user, held: Mutex::default() }) }
+    }
+
+    /// Takes a slot for a session of `device_id` and `user_id`, or reports the limit in the way.
+    /// A session whose user is unknown only counts against the instance limit.
+    ///
+    /// With `replaces` set, a device holding exactly one session here is not refused: its old
+    /// session is about to close, and refusing would leave the device stuck behind it. While that
+    /// replacement is pending the device holds two, and further sessions meet the limits again.
+    pub(crate) fn acquire(
+        &self,
+        device_id: Uuid,
+        user_id: Option<Uuid>,
+        replaces: bool,
+    ) -> Result<SessionSlot, SessionLimit> {
+        let inner = &self.inner;
+        let mut held = inner.held.lock().unwrap_or_else(PoisonError::into_inner);
+        let replacing = replaces && held.per_device.get(&device_id) == Some(&1);
+        if !replacing && inner.max_sessions > 0 && held.total >= inner.max_sessions {
+            return Err(SessionLimit::Instance);
+        }
+
+        let user_id = user_id.filter(|_| inner.max_per_user > 0);
+        if let Some(user_id) = user_id {
+            let sessions = held.per_user.entry(user_id).or_default();
+            if !replacing && *sessions >= inner.max_per_user {
+                return Err(SessionLimit::User);
+            }
+            *sessions += 1;
+        }
+        held.total += 1;
+        *held.per_device.entry(device_id).or_default() += 1;
+        Ok(SessionSlot { inner: Arc::clone(inner), device_id, user_id })
+    }
+}
+
+/// A session's place under the limits, given back when dropped.
+#[derive(Debug)]
+pub(crate) struct SessionSlot {
+    inner: Arc<Inner>,
+    device_id: Uuid,
+    user_id: Option<Uuid>,
+}
+
+impl Drop for SessionSlot {
+    fn drop(&mut self) {
+        let mut held = self.inner.held.lock().unwrap_or_else(PoisonError::into_inner);
+        held.total = held.total.saturating_sub(1);
+        release(&mut held.per_device, self.device_id);
+        if let Some(user_id) = self.user_id {
+            release(&mut held.per_user, user_id);
+        }
+    }
+}
+
+fn release(counts: &mut HashMap<Uuid, usize>, key: Uuid) {
+    if let Entry::Occupied(mut sessions) = counts.entry(key) {
+        *sessions.get_mut() -= 1;
+        if *sessions.get() == 0 {
+            sessions.remove();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instance_limit_frees_slots_on_drop() {
+        let limits = SessionLimits::new(2, 0);
+        let first = limits.acquire(Uuid::new_v4(), Some(Uuid::new_v4()), false).expect("first slot");
+        let _second = limits.acquire(Uuid::new_v4(), None, false).expect("second slot");
+        assert_eq!(limits.acquire(Uuid::new_v4(), Some(Uuid::new_v4()), false).err(), Some(SessionLimit::Instance));
+
+        drop(first);
+        assert!(limits.acquire(Uuid::new_v4(), None, false).is_ok());
+    }
+
+    #[test]
+    fn test_user_limit_is_per_user() {
+        let limits = SessionLimits::new(0, 1);
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+        let slot = limits.acquire(Uuid::new_v4(), Some(alice), false).expect("alice's slot");
+        assert_eq!(limits.acquire(Uuid::new_v4(), Some(alice), false).err(), Some(SessionLimit::User));
+        let _bob = limits.acquire(Uuid::new_v4(), Some(bob), false).expect("bob's slot");
+        // Sessions of unknown users are not held against anyone
+        let _unknown = limits.acquire(Uuid::new_v4(), None, false).expect("unattributed slot");
+
+        drop(slot);
+        assert!(!limits.inner.held.lock().expect("held lock").per_user.contains_key(&alice));
+        assert!(limits.acquire(Uuid::new_v4(), Some(alice), false).is_ok());
+    }
+
+    #[test]
+    fn test_replacing_device_is_not_held_back_by_its_own_session() {
+        let limits = SessionLimits::new(1, 1);
+        let (alice, phone, laptop) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let old = limits.acquire(phone, Some(alice), true).expect("first session");
+
+        // Another device is still held to the limits, as is the same device when not replacing
+        assert_eq!(limits.acquire(laptop, Some(alice), true).err(), Some(SessionLimit::Instance));
+        assert_eq!(limits.acquire(phone, Some(alice), false).err(), Some(SessionLimit::Instance));
+
+        let new = limits.acquire(phone, Some(alice), true).expect("replacing session");
+        // Only one replacement at a time gets past the limits
+        assert_eq!(limits.acquire(phone, Some(alice), true).err(), Some(SessionLimit::Instance));
+        drop(old);
+        assert_eq!(limits.acquire(laptop, Some(alice), true).err(), Some(SessionLimit::Instance));
+
+        drop(new);
+        let held = limits.inner.held.lock().expect("held lock");
+        assert_eq!(held.total, 0);
+        assert!(held.per_device.is_empty() && held.per_user.is_empty());
+    }
+}
@@ -0,0 +1,121 @@
+use crate::adapters::storage::StorageStream;
+use crate::proto::obscura::v1 as proto;
+use crate::services::gateway::delivery_tracker::OutboundFrame;
+use axum::extract::ws::Message as WsMessage;
+use dashmap::DashMap;
+use futures::StreamExt;
+use prost::Message as ProstMessage;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+/// Routes attachment upload progress to the uploader's gateway session on this instance.
+///
+/// Progress is best-effort: uploads from devices without a local session, or whose outbound
+/// buffer is full, simply go unreported.
+#[derive(Clone, Debug)]
+pub(crate) struct UploadProgress {
+    sessions: Arc<DashMap<Uuid, (Uuid, mpsc::Sender<OutboundFrame>)>>,
+    step_percent: u8,
+}
+
+impl UploadProgress {
+    #[must_use]
+    pub(crate) fn new(step_percent: u8) -> Self {
+        Self { sessions: Arc::new(DashMap::new()), step_percent: step_percent.min(100) }
+    }
+
+    /// Makes `outbound_tx` the destination for the device's progress frames, replacing any older session.
+    pub(crate) fn register(&self, device_id: Uuid, session_id: Uuid, outbound_tx: mpsc::Sender<OutboundFrame>) {
+        if self.step_percent > 0 {
+            self.sessions.insert(device_id, (session_id, outbound_tx));
+        }
+    }
+
+    /// Removes the session, leaving a newer session for the same device in place.
+    pub(crate) fn unregister(&self, device_id: Uuid, session_id: Uuid) {
+        self.sessions.remove_if(&device_id, |_, (owner, _)| *owner == session_id);
+    }
+
+    /// Wraps an upload of `total` bytes so that every `step_percent` of it received is reported
+    /// to the device's session, if it has one when the step is reached.
+    pub(crate) fn track(
+        &self,
+        device_id: Uuid,
+        attachment_id: Uuid,
+        total: usize,
+        stream: StorageStream,
+    ) -> StorageStream {
+        if self.step_percent == 0 || total == 0 {
+            return stream;
+        }
+
+        let sessions = Arc::clone(&self.sessions);
+        let step = u64::from(self.step_percent);
+        let total = total as u64;
+        let mut received = 0u64;
+        let mut next_percent = step;
+
+        stream
+            .inspect(move |chunk| {
+                let Ok(chunk) = chunk else { return };
+                received += chunk.len() as u64;
+                let percent = (received.saturating_mul(100) / total).min(100);
+                if percent < next_percent {
+                    return;
+                }
+                next_percent = (percent / step + 1) * step;
+
+                let Some(outbound_tx) = sessions.get(&device_id).map(|entry| entry.1.clone()) else { return };
+                let frame = proto::WebSocketFrame {
+                    payload: Some(proto::web_socket_frame::Payload::UploadProgress(proto::UploadProgress {
+                        attachment_id: attachment_id.as_bytes().to_vec(),
+                        bytes_received: received,
+                        total_bytes: total,
+                        percent: u32::try_from(percent).unwrap_or(100),
+                    })),
+                };
+                // A progress frame is superseded by the next one, so it is never worth waiting for buffer space.
+                let _ = outbound_tx.try_send(WsMessage::Binary(frame.encode_to_vec().into()).into());
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    fn decode_percent(frame: &OutboundFrame) -> u32 {
+        let WsMessage::Binary(bin) = &frame.message else { panic!("Expected a binary frame") };
+        match proto::WebSocketFrame::decode(bin.as_ref()).ok().and_then(|f| f.payload) {
+            Some(proto::web_socket_frame::Payload::UploadProgress(progress)) => progress.percent,
+            other => panic!("Expected an upload progress frame, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_progress_is_reported_in_steps() {
+        let progress = UploadProgress::new(25);
+        let (device_id, session_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let (tx, mut rx) = mpsc::channel(16);
+        progress.register(device_id, session_id, tx);
+
+        let chunks: Vec<Result<Bytes, std::io::Error>> = (0..10).map(|_| Ok(Bytes::from_static(&[0u8; 10]))).collect();
+        let stream = progress.track(device_id, Uuid::new_v4(), 100, futures::stream::iter(chunks).boxed());
+        assert_eq!(stream.count().await, 10);
+
+        let mut percents = Vec::new();
+        while let Ok(frame) = rx.try_recv() {
+            percents.push(decode_percent(&frame));
+        }
+        assert_eq!(percents, vec![30, 50, 80, 100]);
+
+        // A stale session does not remove its replacement
+        progress.unregister(device_id, Uuid::new_v4());
+        assert!(progress.sessions.contains_key(&device_id));
+        progress.unregister(device_id, session_id);
+        assert!(!progress.sessions.contains_key(&device_id));
+    }
+}
@@ -1,15 +1,24 @@
 use crate::adapters::database::DbPool;
+use crate::adapters::database::availability::DbAvailability;
+use crate::adapters::database::instrumentation::AcquireTotals;
+use crate::adapters::push::PushProvider;
 use crate::adapters::redis::RedisClient;
 use crate::config::HealthConfig;
+use crate::error::Result;
 use aws_sdk_s3::Client;
 use opentelemetry::{KeyValue, global, metrics::Gauge};
+use sqlx::migrate::Migrator;
+use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
 use tokio::time::timeout;
 
 #[derive(Clone, Debug)]
 pub struct Metrics {
     pub status: Gauge<i64>,
+    pub schema_version: Gauge<i64>,
+    pub db_read_only: Gauge<i64>,
 }
 
 impl Metrics {
@@ -21,6 +30,14 @@ impl Metrics {
                 .i64_gauge("obscura_health_status")
                 .with_description("Status of health checks (1 for ok, 0 for error)")
                 .build(),
+            schema_version: meter
+                .i64_gauge("obscura_db_schema_version")
+                .with_description("Highest database migration applied, as last read by this instance")
+                .build(),
+            db_read_only: meter
+                .i64_gauge("obscura_db_read_only")
+                .with_description("Whether this instance is in read-only mode because the primary cannot take writes")
+                .build(),
         }
     }
 }
@@ -31,13 +48,159 @@ impl Default for Metrics {
     }
 }
 
+/// Database pool occupancy, with acquire outcomes since startup.
+#[derive(Clone, Copy, Debug)]
+pub struct PoolStats {
+    pub size: u32,
+    pub idle: u32,
+    pub max_connections: u32,
+    pub acquires: u64,
+    pub mean_acquire_wait: Duration,
+    pub acquire_timeouts: u64,
+}
+
+/// A migration embedded in this binary.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MigrationInfo {
+    pub version: i64,
+    pub description: String,
+}
+
+/// How the database schema compares to the migrations embedded in this binary.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MigrationStatus {
+    /// Highest migration applied successfully, if any.
+    pub current_version: Option<i64>,
+    /// Highest migration this binary embeds.
+    pub latest_version: Option<i64>,
+    /// Embedded migrations not applied yet.
+    pub pending: Vec<MigrationInfo>,
+    /// Migrations that started but never finished. The migrator refuses to run until they are
+    /// repaired by hand.
+    pub dirty: Vec<i64>,
+    /// Applied migrations whose embedded file has changed since.
+    pub modified: Vec<i64>,
+    /// Applied migrations this binary does not embed, usually because a newer release ran them.
+    pub unknown: Vec<i64>,
+}
+
+impl MigrationStatus {
+    /// Whether the schema is exactly what this binary expects. Unknown migrations do not count
+    /// against it, as a newer release migrating during a rollout is expected.
+    #[must_use]
+    pub fn is_up_to_date(&self) -> bool {
+        self.pending.is_empty() && self.dirty.is_empty() && self.modified.is_empty()
+    }
+
+    /// Compares the embedded migrations with the rows of the migrations table.
+    fn compare(embedded: &[EmbeddedMigration], applied: &[AppliedMigration]) -> Self {
+        let applied_by_version: HashMap<i64, &AppliedMigration> = applied.iter().map(|a| (a.version, a)).collect();
+        let embedded_versions: Vec<i64> = embedded.iter().map(|m| m.version).collect();
+
+        let mut status = Self {
+            current_version: applied.iter().filter(|a| a.success).map(|a| a.version).max(),
+            latest_version: embedded_versions.iter().copied().max(),
+            dirty: applied.iter().filter(|a| !a.success).map(|a| a.version).collect(),
+            unknown: applied.iter().map(|a| a.version).filter(|v| !embedded_versions.contains(v)).collect(),
+            ..Self::default()
+        };
+        for migration in embedded {
+            match applied_by_version.get(&migration.version) {
+                None => status
+                    .pending
+                    .push(MigrationInfo { version: migration.version, description: migration.description.clone() }),
+                Some(applied) if applied.success && applied.checksum != migration.checksum => {
+                    status.modified.push(migration.version);
+                }
+                Some(_) => {}
+            }
+        }
+        status
+    }
+}
+
+/// A migration as embedded in this binary.
+#[derive(Debug)]
+struct EmbeddedMigration {
+    version: i64,
+    description: String,
+    checksum: Vec<u8>,
+}
+
+impl EmbeddedMigration {
+    fn all(migrator: &Migrator) -> Vec<Self> {
+        migrator
+            .iter()
+            .filter(|m| !m.migration_type.is_down_migration())
+            .map(|m| Self { version: m.version, description: m.description.to_string(), checksum: m.checksum.to_vec() })
+            .collect()
+    }
+}
+
+/// A row of the migrations table.
+#[derive(Debug, sqlx::FromRow)]
+struct AppliedMigration {
+    version: i64,
+    success: bool,
+    checksum: Vec<u8>,
+}
+
+/// Whether the database takes writes, as reported by the readiness probe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DatabaseMode {
+    ReadWrite,
+    ReadOnly,
+}
+
+impl DatabaseMode {
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::ReadWrite => "read_write",
+            Self::ReadOnly => "read_only",
+        }
+    }
+}
+
+/// Counts consecutive write probe outcomes that disagree with the current mode, so that a
+/// single slow or failed probe does not flip the instance into read-only mode and back.
+#[derive(Debug, Default)]
+struct WriteProbe {
+    read_only: bool,
+    streak: u32,
+}
+
+impl WriteProbe {
+    /// Records a probe outcome. Returns the new mode once `threshold` outcomes in a row called
+    /// for it.
+    fn observe(&mut self, writable: bool, threshold: u32) -> Option<DatabaseMode> {
+        if writable != self.read_only {
+            self.streak = 0;
+            return None;
+        }
+        self.streak += 1;
+        if self.streak < threshold.max(1) {
+            return None;
+        }
+        self.streak = 0;
+        self.read_only = !writable;
+        Some(if self.read_only { DatabaseMode::ReadOnly } else { DatabaseMode::ReadWrite })
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct HealthService {
     pool: DbPool,
     s3_client: Client,
-    pubsub: Arc<RedisClient>,
+    /// `None` when Redis is not used.
+    pubsub: Option<Arc<RedisClient>>,
+    push: Arc<dyn PushProvider>,
     storage_bucket: String,
     config: HealthConfig,
+    /// Last push credential check and when it ran, reused until the check interval elapses.
+    push_check: Arc<Mutex<Option<(Instant, Result<(), String>)>>>,
+    availability: DbAvailability,
+    write_probe: Arc<Mutex<WriteProbe>>,
     metrics: Metrics,
 }
 
@@ -46,21 +209,47 @@ impl HealthService {
     pub fn new(
         pool: DbPool,
         s3_client: Client,
-        pubsub: Arc<RedisClient>,
+        pubsub: Option<Arc<RedisClient>>,
+        push: Arc<dyn PushProvider>,
         storage_bucket: String,
         config: HealthConfig,
     ) -> Self {
-        Self { pool, s3_client, pubsub, storage_bucket, config, metrics: Metrics::new() }
+        Self {
+            pool,
+            s3_client,
+            pubsub,
+            push,
+            storage_bucket,
+            config,
+            push_check: Arc::new(Mutex::new(None)),
+            availability: DbAvailability::default(),
+            write_probe: Arc::new(Mutex::new(WriteProbe::default())),
+            metrics: Metrics::new(),
+        }
+    }
+
+    /// Shares read-only mode with the services and handlers holding `availability`.
+    #[must_use]
+    pub fn with_availability(mut self, availability: DbAvailability) -> Self {
+        self.availability = availability;
+        self
+    }
+
+    #[must_use]
+    pub fn database_mode(&self) -> DatabaseMode {
+        if self.availability.is_read_only() { DatabaseMode::ReadOnly } else { DatabaseMode::ReadWrite }
     }
 
-    /// Checks database connectivity.
+    /// Checks database connectivity. In read-only mode this checks the pool reads are served
+    /// from, since the instance stays useful as long as they work.
     ///
     /// # Errors
     /// Returns a string describing the failure if the database is unreachable.
     pub async fn check_db(&self) -> Result<(), String> {
         let db_timeout = Duration::from_millis(self.config.db_timeout_ms);
+        let pool = self.availability.read_pool(&self.pool);
 
-        match timeout(db_timeout, sqlx::query("SELECT 1").execute(&self.pool)).await {
+        match timeout(db_timeout, sqlx::query("SELECT 1").execute(pool)).await {
             Ok(Ok(_)) => {
                 self.metrics.status.record(1, &[KeyValue::new("component", "database")]);
                 Ok(())
@@ -76,6 +265,102 @@ impl HealthService {
         }
     }
 
+    #[must_use]
+    pub fn pool_stats(&self) -> PoolStats {
+        let totals = AcquireTotals::current();
+        PoolStats {
+            size: self.pool.size(),
+            idle: u32::try_from(self.pool.num_idle()).unwrap_or(u32::MAX),
+            max_connections: self.pool.options().get_max_connections(),
+            acquires: totals.acquires,
+            mean_acquire_wait: totals.mean_wait(),
+            acquire_timeouts: totals.timeouts,
+        }
+    }
+
+    /// Checks that the primary takes writes and feeds the outcome to the read-only state
+    /// machine: read-only mode is entered after `db_write_probe_threshold` failed probes in a
+    /// row and left after as many passed ones.
+    pub async fn probe_writes(&self) {
+        let result = self.check_writable().await;
+        if let Err(e) = &result {
+            tracing::debug!(error = %e, "Database write probe failed");
+        }
+
+        let mut probe = self.write_probe.lock().await;
+        let transition = probe.observe(result.is_ok(), self.config.db_write_probe_threshold);
+        drop(probe);
+
+        let Some(mode) = transition else { return };
+        let read_only = mode == DatabaseMode::ReadOnly;
+        if !self.availability.set_read_only(read_only) {
+            return;
+        }
+        self.metrics.db_read_only.record(i64::from(read_only), &[]);
+        if read_only {
+            tracing::error!(
+                error = %result.err().unwrap_or_default(),
+                replica = self.availability.replica().is_some(),
+                "Primary database cannot take writes, entering read-only mode"
+            );
+        } else {
+            tracing::info!("Primary database takes writes again, leaving read-only mode");
+        }
+    }
+
+    /// Whether the primary is reachable and neither in recovery nor set to read-only.
+    async fn check_writable(&self) -> Result<(), String> {
+        let db_timeout = Duration::from_millis(self.config.db_timeout_ms);
+        let query = sqlx::query_scalar::<_, bool>(
+            "SELECT pg_is_in_recovery() OR current_setting('transaction_read_only') = 'on'",
+        );
+
+        match timeout(db_timeout, query.fetch_one(&self.pool)).await {
+            Ok(Ok(false)) => Ok(()),
+            Ok(Ok(true)) => Err("Database is in recovery or read-only".to_string()),
+            Ok(Err(e)) => Err(format!("Database connection failed: {e:?}")),
+            Err(_) => Err("Database connection timed out".to_string()),
+        }
+    }
+
+    /// Compares the database schema with the migrations embedded in this binary, so operators
+    /// can confirm that every replica of a rollout sees the schema it expects.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if the migrations table cannot be read.
+    pub async fn migration_status(&self) -> Result<MigrationStatus> {
+        let applied = sqlx::query_as::<_, AppliedMigration>(
+            "SELECT version, success, checksum FROM _sqlx_migrations ORDER BY version",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let status = MigrationStatus::compare(&EmbeddedMigration::all(&crate::MIGRATOR), &applied);
+        if let Some(version) = status.current_version {
+            self.metrics.schema_version.record(version, &[]);
+        }
+        Ok(status)
+    }
+
+    /// Logs the schema version at startup, warning if it differs from what this binary expects.
+    pub async fn log_migration_status(&self) {
+        match self.migration_status().await {
+            Ok(status) if status.is_up_to_date() && status.unknown.is_empty() => {
+                tracing::info!(schema.version = ?status.current_version, "Database schema is up to date");
+            }
+            Ok(status) => tracing::warn!(
+                schema.version = ?status.current_version,
+                schema.latest = ?status.latest_version,
+                pending = ?status.pending.iter().map(|m| m.version).collect::<Vec<_>>(),
+                dirty = ?status.dirty,
+                modified = ?status.modified,
+                unknown = ?status.unknown,
+                "Database schema differs from the migrations in this build"
+            ),
+            Err(e) => tracing::warn!(error = %e, "Failed to read database migration status"),
+        }
+    }
+
     /// Checks S3 connectivity.
     ///
     /// # Errors
@@ -99,14 +384,13 @@ impl HealthService {
         }
     }
 
-    /// Checks `PubSub` connectivity.
-    ///
-    /// # Errors
-    /// Returns a string describing the failure if `PubSub` is unreachable.
-    pub async fn check_pubsub(&self) -> Result<(), String> {
+    /// Checks `PubSub` connectivity, describing the failure if `PubSub` is unreachable.
+    /// Returns `None` when Redis is not used.
+    pub async fn check_pubsub(&self) -> Option<Result<(), String>> {
+        let pubsub = self.pubsub.as_ref()?;
         let pubsub_timeout = Duration::from_millis(self.config.pubsub_timeout_ms);
 
-        match timeout(pubsub_timeout, self.pubsub.ping()).await {
+        Some(match timeout(pubsub_timeout, pubsub.ping()).await {
             Ok(Ok(())) => {
                 self.metrics.status.record(1, &[KeyValue::new("component", "pubsub")]);
                 Ok(())
@@ -119,6 +403,121 @@ impl HealthService {
                 self.metrics.status.record(0, &[KeyValue::new("component", "pubsub")]);
                 Err("PubSub connection timed out".to_string())
             }
+        })
+    }
+
+    /// Checks that the push provider accepts its credentials, reusing a recent result.
+    /// Returns `None` when the check is disabled.
+    ///
+    /// Concurrent probes wait for a single check instead of each calling the provider.
+    pub async fn check_push(&self) -> Option<Result<(), String>> {
+        if self.config.push_check_interval_secs == 0 {
+            return None;
         }
+        let interval = Duration::from_secs(self.config.push_check_interval_secs);
+
+        let mut last = self.push_check.lock().await;
+        let result = match &*last {
+            Some((checked_at, result)) if checked_at.elapsed() < interval => result.clone(),
+            _ => {
+                let push_timeout = Duration::from_millis(self.config.push_timeout_ms);
+                let result = match timeout(push_timeout, self.push.check_credentials()).await {
+                    Ok(Ok(())) => Ok(()),
+                    Ok(Err(e)) => Err(format!("Push provider credential check failed: {e}")),
+                    Err(_) => Err("Push provider credential check timed out".to_string()),
+                };
+                *last = Some((Instant::now(), result.clone()));
+                result
+            }
+        };
+        drop(last);
+
+        self.metrics.status.record(i64::from(result.is_ok()), &[KeyValue::new("component", "push")]);
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_probe_needs_a_streak_to_change_mode() {
+        let mut probe = WriteProbe::default();
+
+        assert_eq!(probe.observe(false, 3), None);
+        assert_eq!(probe.observe(false, 3), None);
+        // A passed probe resets the streak
+        assert_eq!(probe.observe(true, 3), None);
+        assert_eq!(probe.observe(false, 3), None);
+        assert_eq!(probe.observe(false, 3), None);
+        assert_eq!(probe.observe(false, 3), Some(DatabaseMode::ReadOnly));
+        assert_eq!(probe.observe(false, 3), None);
+
+        assert_eq!(probe.observe(true, 3), None);
+        assert_eq!(probe.observe(true, 3), None);
+        assert_eq!(probe.observe(true, 3), Some(DatabaseMode::ReadWrite));
+    }
+
+    #[test]
+    fn test_write_probe_threshold_of_zero_acts_at_once() {
+        let mut probe = WriteProbe::default();
+        assert_eq!(probe.observe(false, 0), Some(DatabaseMode::ReadOnly));
+        assert_eq!(probe.observe(true, 0), Some(DatabaseMode::ReadWrite));
+    }
+
+    fn embedded(versions: &[i64]) -> Vec<EmbeddedMigration> {
+        versions
+            .iter()
+            .map(|&version| EmbeddedMigration { version, description: format!("m{version}"), checksum: vec![1] })
+            .collect()
+    }
+
+    fn applied(version: i64, success: bool) -> AppliedMigration {
+        AppliedMigration { version, success, checksum: vec![1] }
+    }
+
+    #[test]
+    fn test_fully_migrated_schema_is_up_to_date() {
+        let migrations = embedded(&[1, 2]);
+        let status = MigrationStatus::compare(&migrations, &[applied(1, true), applied(2, true)]);
+        assert!(status.is_up_to_date());
+        assert_eq!(status.current_version, Some(2));
+        assert_eq!(status.latest_version, Some(2));
+    }
+
+    #[test]
+    fn test_unapplied_migrations_are_pending() {
+        let migrations = embedded(&[1, 2, 3]);
+        let status = MigrationStatus::compare(&migrations, &[applied(1, true)]);
+        assert!(!status.is_up_to_date());
+        assert_eq!(status.pending.iter().map(|m| m.version).collect::<Vec<_>>(), [2, 3]);
+        assert_eq!(status.pending[0].description, "m2");
+    }
+
+    #[test]
+    fn test_failed_migration_is_dirty() {
+        let migrations = embedded(&[1, 2]);
+        let status = MigrationStatus::compare(&migrations, &[applied(1, true), applied(2, false)]);
+        assert_eq!(status.dirty, [2]);
+        assert_eq!(status.current_version, Some(1));
+        assert!(!status.is_up_to_date());
+    }
+
+    #[test]
+    fn test_changed_migration_is_modified() {
+        let migrations = embedded(&[1]);
+        let status =
+            MigrationStatus::compare(&migrations, &[AppliedMigration { version: 1, success: true, checksum: vec![2] }]);
+        assert_eq!(status.modified, [1]);
+    }
+
+    #[test]
+    fn test_migrations_from_a_newer_release_are_unknown() {
+        let migrations = embedded(&[1]);
+        let status = MigrationStatus::compare(&migrations, &[applied(1, true), applied(2, true)]);
+        assert_eq!(status.unknown, [2]);
+        assert_eq!(status.current_version, Some(2));
+        assert!(status.is_up_to_date());
     }
 }
@@ -0,0 +1,231 @@
+use crate::adapters::crypto::{constant_time_eq, hmac_sha256};
+use crate::adapters::database::DbPool;
+use crate::adapters::database::identifier_repo::{IdentifierRepository, IssuedCode, ResendLimits};
+use crate::adapters::database::instrumentation::TimedAcquire;
+use crate::adapters::verification::VerificationSender;
+use crate::config::IdentifierConfig;
+use crate::domain::identifier::{Identifier, IdentifierKind};
+use crate::error::{AppError, Result};
+use crate::telemetry;
+use opentelemetry::{KeyValue, global, metrics::Counter};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::Duration;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+/// Digits in a verification code.
+const CODE_DIGITS: u32 = 6;
+
+#[derive(Clone, Debug)]
+struct Metrics {
+    codes_sent_total: Counter<u64>,
+    codes_throttled_total: Counter<u64>,
+    verifications_total: Counter<u64>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let meter = global::meter("obscura-server");
+        Self {
+            codes_sent_total: meter
+                .u64_counter("obscura_identifier_codes_sent_total")
+                .with_description("Identifier verification codes handed to the sender")
+                .build(),
+            codes_throttled_total: meter
+                .u64_counter("obscura_identifier_codes_throttled_total")
+                .with_description("Identifier verification codes refused by the resend limits")
+                .build(),
+            verifications_total: meter
+                .u64_counter("obscura_identifier_verifications_total")
+                .with_description("Identifier verification attempts, by result")
+                .build(),
+        }
+    }
+
+    fn verification(&self, kind: IdentifierKind, result: &'static str) {
+        self.verifications_total.add(1, &[KeyValue::new("kind", kind.as_str()), KeyValue::new("result", result)]);
+    }
+}
+
+/// Binds optional email addresses and phone numbers to accounts once the user proves they receive
+/// a code sent to them.
+///
+/// Identifiers are stored as a hash keyed by a server secret, so a database dump alone does not
+/// reveal them, while the same identifier still hashes the same way for recovery and discovery
+/// lookups. Codes are stored salted and are never logged. Without a hash secret identifiers
+/// cannot be added at all, rather than being keyed by a secret that exists for something else.
+#[derive(Clone, Debug)]
+pub struct IdentifierService {
+    pool: DbPool,
+    repo: IdentifierRepository,
+    sender: Arc<dyn VerificationSender>,
+    hash_secret: Option<String>,
+    code_ttl: Duration,
+    limits: ResendLimits,
+    metrics: Metrics,
+}
+
+impl IdentifierService {
+    #[must_use]
+    pub fn new(
+        pool: DbPool,
+        repo: IdentifierRepository,
+        sender: Arc<dyn VerificationSender>,
+        config: &IdentifierConfig,
+    ) -> Self {
+        Self {
+            pool,
+            repo,
+            sender,
+            hash_secret: config.hash_secret.clone(),
+            code_ttl: Duration::from_secs(config.code_ttl_secs),
+            limits: ResendLimits {
+                cooldown: Duration::from_secs(config.resend_cooldown_secs),
+                window: Duration::from_secs(config.code_window_secs),
+                max_codes: config.max_codes_per_window,
+                max_attempts: config.code_max_attempts,
+            },
+            metrics: Metrics::new(),
+        }
+    }
+
+    /// Binds `value` to the user as their identifier of this kind, replacing any previous one,
+    /// and sends it a verification code. The identifier is unverified until the code is
+    /// confirmed with [`Self::verify`].
+    ///
+    /// # Errors
+    /// Returns `AppError::BadRequest` if `value` is not a valid identifier of this kind.
+    /// Returns `AppError::RateLimited` if a code was sent too recently, or too many this window.
+    /// Returns `AppError::ServiceUnavailable` if no hash secret is configured or the code could
+    /// not be sent.
+    /// Returns `AppError::Database` if the database operation fails.
+    #[tracing::instrument(
+        skip_all,
+        fields(kind = kind.as_str(), user.id = %telemetry::id(user_id), %discoverable),
+        err(level = "warn")
+    )]
+    pub async fn add(&self, user_id: Uuid, kind: IdentifierKind, value: &str, discoverable: bool) -> Result<()> {
+        let normalized = kind.normalize(value).map_err(AppError::BadRequest)?;
+        let identifier_hash = self.identifier_hash(kind, &normalized)?;
+        let code = format!("{:0width$}", rand::random_range(0..10u32.pow(CODE_DIGITS)), width = CODE_DIGITS as usize);
+        let code_salt = rand::random::<[u8; 16]>();
+        let code_hash = Self::code_hash(&code_salt, &identifier_hash, &code);
+
+        let mut conn = self.pool.acquire_timed().await?;
+        let issued = self
+            .repo
+            .start_verification(
+                &mut conn,
+                user_id,
+                kind,
+                IssuedCode {
+                    identifier_hash: &identifier_hash,
+                    code_salt: &code_salt,
+                    code_hash: &code_hash,
+                    expires_at: OffsetDateTime::now_utc() + self.code_ttl,
+                    discoverable,
+                },
+                &self.limits,
+            )
+            .await?;
+        if !issued {
+            let retry_after_secs = self.repo.resend_retry_after(&mut conn, user_id, kind, &self.limits).await?;
+            self.metrics.codes_throttled_total.add(1, &[KeyValue::new("kind", kind.as_str())]);
+            return Err(AppError::RateLimited { retry_after_secs });
+        }
+        drop(conn);
+
+        if let Err(e) = self.sender.send_code(kind, &normalized, &code).await {
+            tracing::error!(error = %e, "Failed to send identifier verification code");
+            return Err(AppError::ServiceUnavailable);
+        }
+        self.metrics.codes_sent_total.add(1, &[KeyValue::new("kind", kind.as_str())]);
+        Ok(())
+    }
+
+    /// Confirms the code sent for the user's identifier of this kind.
+    ///
+    /// # Errors
+    /// Returns `AppError::NotFound` if no code is outstanding, including after too many wrong guesses.
+    /// Returns `AppError::Gone` if the code has expired.
+    /// Returns `AppError::BadRequest` if the code is wrong.
+    /// Returns `AppError::Conflict` if the identifier was verified for another account meanwhile.
+    /// Returns `AppError::Database` if the database operation fails.
+    #[tracing::instrument(
+        skip_all,
+        fields(kind = kind.as_str(), user.id = %telemetry::id(user_id)),
+        err(level = "warn")
+    )]
+    pub async fn verify(&self, user_id: Uuid, kind: IdentifierKind, code: &str) -> Result<()> {
+        let mut tx = self.pool.begin_timed().await?;
+        let Some(pending) = self.repo.lock_pending(&mut tx, user_id, kind).await? else {
+            return Err(AppError::NotFound);
+        };
+        if pending.code_expires_at <= OffsetDateTime::now_utc() {
+            self.metrics.verification(kind, "expired");
+            return Err(AppError::Gone("Verification code expired".into()));
+        }
+        let presented = Self::code_hash(&pending.code_salt, &pending.identifier_hash, code.trim());
+        if !constant_time_eq(&presented, &pending.code_hash) {
+            self.repo.record_failed_attempt(&mut tx, user_id, kind, self.limits.max_attempts).await?;
+            tx.commit().await?;
+            self.metrics.verification(kind, "wrong_code");
+            tracing::debug!(attempts = pending.attempts + 1, "Wrong identifier verification code");
+            return Err(AppError::BadRequest("Verification code is incorrect".into()));
+        }
+
+        if let Err(e) = self.repo.mark_verified(&mut tx, user_id, kind).await {
+            if matches!(e, AppError::Conflict(_)) {
+                self.metrics.verification(kind, "conflict");
+            }
+            return Err(e);
+        }
+        tx.commit().await?;
+        self.metrics.verification(kind, "verified");
+        tracing::info!("Identifier verified");
+        Ok(())
+    }
+
+    /// Lists the user's identifiers.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if the query fails.
+    pub async fn list(&self, user_id: Uuid) -> Result<Vec<Identifier>> {
+        let mut conn = self.pool.acquire_timed().await?;
+        self.repo.list(&mut conn, user_id).await
+    }
+
+    /// Unbinds the user's identifier of this kind.
+    ///
+    /// # Errors
+    /// Returns `AppError::NotFound` if none is bound.
+    /// Returns `AppError::Database` if the deletion fails.
+    #[tracing::instrument(
+        skip_all,
+        fields(kind = kind.as_str(), user.id = %telemetry::id(user_id)),
+        err(level = "warn")
+    )]
+    pub async fn remove(&self, user_id: Uuid, kind: IdentifierKind) -> Result<()> {
+        let mut conn = self.pool.acquire_timed().await?;
+        if self.repo.delete(&mut conn, user_id, kind).await? { Ok(()) } else { Err(AppError::NotFound) }
+    }
+
+    /// Keyed hash of a normalized identifier. The kind is part of the message so an email and a
+    /// phone number can never collide.
+    fn identifier_hash(&self, kind: IdentifierKind, normalized: &str) -> Result<Vec<u8>> {
+        let Some(secret) = &self.hash_secret else {
+            tracing::warn!("Identifier rejected, --identifier-hash-secret is not set");
+            return Err(AppError::ServiceUnavailable);
+        };
+        Ok(hmac_sha256(secret.as_bytes(), format!("{kind}:{normalized}").as_bytes()))
+    }
+
+    fn code_hash(salt: &[u8], identifier_hash: &[u8], code: &str) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        hasher.update(salt);
+        hasher.update(identifier_hash);
+        hasher.update(code.as_bytes());
+        hasher.finalize().to_vec()
+    }
+}
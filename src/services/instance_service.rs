@@ -0,0 +1,66 @@
+use crate::adapters::key_value::InstanceRegistry;
+use crate::domain::instance::InstanceInfo;
+use crate::services::gateway::routing::SessionCounter;
+use time::OffsetDateTime;
+
+/// This instance's identity, and the peers running alongside it.
+///
+/// The ID is fixed for the life of the process and shows up in logs, traces and the peer list,
+/// so a delivery problem reported by one instance can be traced to the others.
+#[derive(Clone, Debug)]
+pub struct InstanceService {
+    registry: InstanceRegistry,
+    sessions: SessionCounter,
+    id: String,
+    started_at: OffsetDateTime,
+}
+
+impl InstanceService {
+    #[must_use]
+    pub fn new(registry: InstanceRegistry, sessions: SessionCounter, id: String) -> Self {
+        Self { registry, sessions, id, started_at: OffsetDateTime::now_utc() }
+    }
+
+    #[must_use]
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// What this instance announces to its peers right now.
+    #[must_use]
+    pub fn info(&self) -> InstanceInfo {
+        InstanceInfo {
+            id: self.id.clone(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            started_at: self.started_at,
+            active_sessions: self.sessions.active(),
+            heartbeat_at: OffsetDateTime::now_utc(),
+        }
+    }
+
+    /// Announces this instance to its peers.
+    ///
+    /// # Errors
+    /// Returns an error if the registry cannot be reached.
+    pub async fn heartbeat(&self) -> anyhow::Result<()> {
+        self.registry.heartbeat(&self.info()).await
+    }
+
+    /// Takes this instance off the peer list ahead of its announcement expiring.
+    ///
+    /// # Errors
+    /// Returns an error if the registry cannot be reached.
+    pub async fn deregister(&self) -> anyhow::Result<()> {
+        self.registry.deregister(&self.id).await
+    }
+
+    /// Lists the live instances, this one included, ordered by ID.
+    ///
+    /// # Errors
+    /// Returns an error if the registry cannot be reached.
+    pub async fn peers(&self) -> anyhow::Result<Vec<InstanceInfo>> {
+        let mut peers = self.registry.list().await?;
+        peers.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(peers)
+    }
+}
@@ -1,19 +1,34 @@
 use crate::adapters::database::DbPool;
+use crate::adapters::database::availability::DbAvailability;
+use crate::adapters::database::instrumentation::TimedAcquire;
 use crate::adapters::database::key_repo::KeyRepository;
+use crate::adapters::retry::{RetryPolicy, is_transient_db_error};
 use crate::config::MessagingConfig;
-use crate::domain::crypto::PublicKey;
-use crate::domain::keys::{OneTimePreKey, PreKeyBundle, PreKeyStatus, SignedPreKey};
-use crate::domain::notification::UserEvent;
+use crate::domain::crypto::{KeyType, PublicKey};
+use crate::domain::feature_flag::PREKEY_REFILL_PUSH;
+use crate::domain::keys::{
+    KeyField, KeyIssue, KeyValidationReport, OneTimePreKey, OneTimePreKeyClaim, PreKeyBundle, PreKeyReservation,
+    PreKeyStatus, SignedPreKey,
+};
+use crate::domain::notification::{EventContext, PushKind, UserEvent};
 use crate::error::{AppError, Result};
 use crate::services::crypto_service::CryptoService;
+use crate::services::feature_flag_service::FeatureFlagService;
 use crate::services::notification_service::NotificationService;
-use opentelemetry::{global, metrics::Counter};
+use crate::telemetry;
+use opentelemetry::{KeyValue, global, metrics::Counter};
 use sqlx::PgConnection;
+use std::time::Duration;
+use time::OffsetDateTime;
 use uuid::Uuid;
 
 #[derive(Clone, Debug)]
 struct Metrics {
     prekey_low_total: Counter<u64>,
+    prekey_reservations_total: Counter<u64>,
+    prekey_fetches_total: Counter<u64>,
+    prekey_uploads_total: Counter<u64>,
+    prekeys_uploaded_total: Counter<u64>,
 }
 
 impl Metrics {
@@ -24,6 +39,43 @@ impl Metrics {
                 .u64_counter("obscura_prekey_threshold_reached_total")
                 .with_description("Events where devices dipped below prekey threshold")
                 .build(),
+            prekey_reservations_total: meter
+                .u64_counter("obscura_prekey_reservations_total")
+                .with_description("Bundle fetches that reserved one-time prekeys for a prefetching sender")
+                .build(),
+            prekey_fetches_total: meter
+                .u64_counter("obscura_prekey_fetches_total")
+                .with_description("Pre-key bundles served, by what happened to the device's one-time prekey pool")
+                .build(),
+            prekey_uploads_total: meter
+                .u64_counter("obscura_prekey_uploads_total")
+                .with_description("Pre-key uploads applied, by kind of upload")
+                .build(),
+            prekeys_uploaded_total: meter
+                .u64_counter("obscura_prekeys_uploaded_total")
+                .with_description("One-time prekeys added to device pools, by kind of upload")
+                .build(),
+        }
+    }
+}
+
+/// What a key upload did to the device's pools, for the upload metrics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum PreKeyUpload {
+    /// The first keys of a newly provisioned device.
+    Provision,
+    /// New keys under the device's existing identity.
+    Refill,
+    /// A new identity, which replaced every stored key.
+    Takeover,
+}
+
+impl PreKeyUpload {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Provision => "provision",
+            Self::Refill => "refill",
+            Self::Takeover => "takeover",
         }
     }
 }
@@ -35,6 +87,9 @@ pub struct KeyService {
     crypto_service: CryptoService,
     notifier: NotificationService,
     config: MessagingConfig,
+    retry: RetryPolicy,
+    availability: DbAvailability,
+    feature_flags: Option<FeatureFlagService>,
     metrics: Metrics,
 }
 
@@ -55,133 +110,214 @@ impl KeyService {
         crypto_service: CryptoService,
         notifier: NotificationService,
         config: MessagingConfig,
+        retry: RetryPolicy,
     ) -> Self {
-        Self { pool, repo, crypto_service, notifier, config, metrics: Metrics::new() }
+        Self {
+            pool,
+            repo,
+            crypto_service,
+            notifier,
+            config,
+            retry,
+            availability: DbAvailability::default(),
+            feature_flags: None,
+            metrics: Metrics::new(),
+        }
+    }
+
+    /// Consults `feature_flags` for features rolled out per user. Without it every flag is off.
+    #[must_use]
+    pub fn with_feature_flags(mut self, feature_flags: FeatureFlagService) -> Self {
+        self.feature_flags = Some(feature_flags);
+        self
+    }
+
+    /// Serves bundle fetches in read-only mode per `availability`.
+    #[must_use]
+    pub fn with_availability(mut self, availability: DbAvailability) -> Self {
+        self.availability = availability;
+        self
     }
 
     /// Fetches one pre-key bundle per device owned by the specified user.
     /// Emits notifications if any device drops below the minimum threshold.
     ///
+    /// With `reserve`, the one-time pre-keys are held rather than consumed, and the returned
+    /// reservation must be redeemed (see `redeem_reservations`) before it expires.
+    ///
+    /// In read-only mode the bundles are read from the replica, if any, and carry no one-time
+    /// pre-keys, since those cannot be consumed.
+    ///
     /// # Errors
     /// Returns `AppError::Database` if database query fails.
-    #[tracing::instrument(skip(self), fields(user.id = %user_id), err)]
-    pub(crate) async fn get_pre_key_bundles_for_user(&self, user_id: Uuid) -> Result<Vec<PreKeyBundle>> {
-        let mut conn = self.pool.begin().await?;
-
-        let results = self.repo.get_all_bundles_for_user(&mut conn, user_id).await?;
+    #[tracing::instrument(skip_all, fields(user.id = %telemetry::id(user_id), %reserve), err)]
+    pub(crate) async fn get_pre_key_bundles_for_user(
+        &self,
+        user_id: Uuid,
+        reserve: bool,
+    ) -> Result<(Vec<PreKeyBundle>, Option<PreKeyReservation>)> {
+        if self.availability.is_read_only() {
+            let mut conn = self.availability.read_pool(&self.pool).acquire_timed().await?;
+            let results = self.repo.get_all_bundles_for_user(&mut conn, user_id, OneTimePreKeyClaim::Skip).await?;
+            self.metrics
+                .prekey_fetches_total
+                .add(u64::try_from(results.len()).unwrap_or(u64::MAX), &[KeyValue::new("result", "read_only")]);
+            return Ok((results.into_iter().map(|(bundle, _)| bundle).collect(), None));
+        }
 
-        conn.commit().await?;
+        let reservation = reserve.then(|| PreKeyReservation {
+            token: Uuid::new_v4(),
+            expires_at: OffsetDateTime::now_utc() + Duration::from_secs(self.config.pre_key_reservation_ttl_secs),
+        });
+
+        // Concurrent fetches for the same user race to consume one-time pre-keys and can deadlock.
+        let results = self
+            .retry
+            .run(
+                || async {
+                    let mut conn = self.pool.begin_timed().await?;
+                    let claim = reservation.as_ref().map_or(OneTimePreKeyClaim::Consume, OneTimePreKeyClaim::Reserve);
+                    let results = self.repo.get_all_bundles_for_user(&mut conn, user_id, claim).await?;
+                    conn.commit().await?;
+                    Ok::<_, AppError>(results)
+                },
+                is_transient_db_error,
+            )
+            .await?;
 
         let mut bundles = Vec::new();
 
         // 2. Check thresholds asynchronously, so we don't hold the DB transaction or slow down the response
         for (bundle, remaining_opt) in results {
+            let result = match (&bundle.one_time_pre_key, &reservation) {
+                (None, _) => "exhausted",
+                (Some(_), None) => "consumed",
+                (Some(_), Some(_)) => "reserved",
+            };
+            self.metrics.prekey_fetches_total.add(1, &[KeyValue::new("result", result)]);
             if let Some(remaining) = remaining_opt
                 && remaining < i64::from(self.config.pre_key_refill_threshold)
             {
                 self.metrics.prekey_low_total.add(1, &[]);
                 tracing::warn!(
-                    device.id = %bundle.device_id,
+                    device.id = %telemetry::id(bundle.device_id),
                     remaining = %remaining,
                     "Pre-keys falling below minimum threshold"
                 );
-                self.notifier.notify(&[bundle.device_id], UserEvent::PreKeyLow).await;
+                let context = EventContext::with_count(u32::try_from(remaining).unwrap_or(0));
+                let push = Some(self.refill_push(user_id));
+                self.notifier.notify_with_push(&[bundle.device_id], UserEvent::PreKeyLow, context, push).await;
             }
             bundles.push(bundle);
         }
 
-        Ok(bundles)
+        let reservation = reservation.filter(|_| bundles.iter().any(|b| b.one_time_pre_key.is_some()));
+        if reservation.is_some() {
+            self.metrics.prekey_reservations_total.add(1, &[]);
+        }
+
+        Ok((bundles, reservation))
+    }
+
+    /// Finalizes prefetched bundles: deletes the one-time pre-keys held by the given reservations.
+    /// Unknown or already redeemed tokens are ignored.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if the deletion fails.
+    #[tracing::instrument(skip_all, fields(tokens = tokens.len()), err)]
+    pub(crate) async fn redeem_reservations(&self, tokens: &[Uuid]) -> Result<()> {
+        if tokens.is_empty() {
+            return Ok(());
+        }
+        let mut conn = self.pool.acquire_timed().await?;
+        let redeemed = self.repo.redeem_reservations(&mut conn, tokens).await?;
+        tracing::debug!(redeemed, "Redeemed pre-key reservations");
+        Ok(())
     }
 
     /// Fetches the identity key for a device.
     ///
     /// # Errors
     /// Returns `AppError::Database` if the database operation fails.
-    #[tracing::instrument(err, skip(self), fields(device.id = %device_id))]
+    #[tracing::instrument(err, skip_all, fields(device.id = %telemetry::id(device_id)))]
     pub async fn fetch_identity_key(&self, device_id: Uuid) -> Result<Option<PublicKey>> {
-        let mut conn = self.pool.acquire().await?;
+        let mut conn = self.pool.acquire_timed().await?;
         self.repo.fetch_identity_key(&mut conn, device_id).await
     }
 
-    /// Checks if a device needs to refill their one-time pre-keys.
+    /// Checks if a device needs to refill their one-time pre-keys, and by how many.
     ///
     /// # Errors
     /// Returns `AppError::Database` if the database operation fails.
-    #[tracing::instrument(err, skip(self), fields(device.id = %device_id))]
+    #[tracing::instrument(err, skip_all, fields(device.id = %telemetry::id(device_id)))]
     pub async fn check_pre_key_status(&self, device_id: Uuid) -> Result<Option<PreKeyStatus>> {
-        let mut conn = self.pool.acquire().await?;
+        let mut conn = self.pool.acquire_timed().await?;
         let count = self.repo.count_one_time_pre_keys(&mut conn, device_id).await?;
         if count < i64::from(self.config.pre_key_refill_threshold) {
             self.metrics.prekey_low_total.add(1, &[]);
 
+            let count = i32::try_from(count).unwrap_or(i32::MAX);
+            let max_pre_keys = i32::try_from(self.config.max_pre_keys).unwrap_or(i32::MAX);
+            let per_request = i32::try_from(self.config.max_pre_keys_per_request).unwrap_or(i32::MAX);
+
             Ok(Some(PreKeyStatus {
-                one_time_pre_key_count: i32::try_from(count).unwrap_or(i32::MAX),
+                one_time_pre_key_count: count,
                 min_threshold: self.config.pre_key_refill_threshold,
+                max_pre_keys,
+                recommended_upload_count: max_pre_keys.saturating_sub(count).clamp(0, per_request),
             }))
         } else {
             Ok(None)
         }
     }
 
+    /// Counts an upload once its transaction has committed.
+    pub(crate) fn record_upload(&self, upload: PreKeyUpload, one_time_pre_keys: usize) {
+        let labels = [KeyValue::new("result", upload.as_str())];
+        self.metrics.prekey_uploads_total.add(1, &labels);
+        self.metrics.prekeys_uploaded_total.add(u64::try_from(one_time_pre_keys).unwrap_or(u64::MAX), &labels);
+    }
+
     /// Internal implementation that accepts a mutable connection.
-    #[tracing::instrument(level = "debug", skip(self, conn, params), err(level = "debug"))]
+    #[tracing::instrument(level = "debug", skip_all, err(level = "debug"))]
     pub(crate) async fn upsert_keys(&self, conn: &mut PgConnection, params: KeyUploadParams) -> Result<bool> {
-        let mut is_takeover = false;
-
-        // 1. Identify/Verify Identity Key
-        let ik = if let Some(new_ik) = params.identity_key {
-            // Fetch existing identity key with LOCK
-            let existing_ik_opt = self.repo.fetch_identity_key_for_update(&mut *conn, params.device_id).await?;
+        // 1. Validate against the stored identity key (locked for the rest of the transaction)
+        let stored_ik = self.repo.fetch_identity_key_for_update(&mut *conn, params.device_id).await?;
+        let report = self
+            .check_upload(
+                &mut *conn,
+                params.device_id,
+                stored_ik.as_ref(),
+                params.identity_key.as_ref(),
+                Some(&params.signed_pre_key),
+                &params.one_time_pre_keys,
+            )
+            .await?;
+        if let Some(issue) = report.issues.into_iter().next() {
+            return Err(AppError::BadRequest(issue.message));
+        }
 
-            if let Some(existing_ik) = existing_ik_opt {
-                if existing_ik != new_ik {
-                    tracing::info!("Device takeover detected: identity key has changed");
-                    is_takeover = true;
-                }
+        let is_takeover = report.is_takeover;
+        if is_takeover {
+            if stored_ik.is_some() {
+                tracing::info!("Device takeover detected: identity key has changed");
             } else {
                 tracing::info!("New identity key for device");
-                is_takeover = true;
-            }
-
-            self.verify_keys(&new_ik, &params.signed_pre_key)?;
-            new_ik
-        } else {
-            // Must exist
-            let ik = self
-                .repo
-                .fetch_identity_key_for_update(&mut *conn, params.device_id)
-                .await?
-                .ok_or_else(|| AppError::BadRequest("Identity key missing".into()))?;
-
-            // Verify signature with the stored key
-            self.verify_keys(&ik, &params.signed_pre_key)?;
-            ik
-        };
-
-        // 2. Monotonic ID Check (Prevent Replay / Rollback)
-        if !is_takeover {
-            let max_id = self.repo.find_max_signed_pre_key_id(&mut *conn, params.device_id).await?;
-            if let Some(current_max) = max_id
-                && params.signed_pre_key.key_id <= current_max
-            {
-                return Err(AppError::BadRequest(format!(
-                    "Signed Pre-Key ID {} must be greater than current ID {}",
-                    params.signed_pre_key.key_id, current_max
-                )));
             }
         }
+        let ik = params
+            .identity_key
+            .as_ref()
+            .or(stored_ik.as_ref())
+            .expect("identity key presence is checked by check_upload");
 
-        // 3. Limit Check (Atomic within transaction)
+        // 2. Limit Check (Atomic within transaction)
         let current_count =
             if is_takeover { 0 } else { self.repo.count_one_time_pre_keys(&mut *conn, params.device_id).await? };
 
         let new_keys_count = i64::try_from(params.one_time_pre_keys.len()).unwrap_or(i64::MAX);
 
-        if new_keys_count > self.config.max_pre_keys {
-            return Err(AppError::BadRequest(format!("Batch too large. Limit is {}", self.config.max_pre_keys)));
-        }
-
-        // 4. Handle Takeover Cleanup
+        // 3. Handle Takeover Cleanup
         if is_takeover {
             let reg_id =
                 params.registration_id.expect("registration_id must be present for takeover (validated at boundary)");
@@ -192,7 +328,7 @@ impl KeyService {
             // Note: Message deletion and notification are now handled by the orchestrator (DeviceService).
 
             // Upsert Identity Key
-            self.repo.upsert_identity_key(&mut *conn, params.device_id, &ik, reg_id).await?;
+            self.repo.upsert_identity_key(&mut *conn, params.device_id, ik, reg_id).await?;
         } else {
             // If not a takeover, we might need to prune old keys to make room for new ones
             if current_count + new_keys_count > self.config.max_pre_keys {
@@ -201,7 +337,7 @@ impl KeyService {
             }
         }
 
-        // 5. Common flow: Upsert Keys
+        // 4. Common flow: Upsert Keys
         self.repo
             .upsert_signed_pre_key(
                 &mut *conn,
@@ -212,7 +348,7 @@ impl KeyService {
             )
             .await?;
 
-        // 6. Cleanup old Signed Pre-Keys
+        // 5. Cleanup old Signed Pre-Keys
         if !is_takeover {
             self.repo
                 .delete_signed_pre_keys_older_than(&mut *conn, params.device_id, params.signed_pre_key.key_id)
@@ -224,6 +360,99 @@ impl KeyService {
         Ok(is_takeover)
     }
 
+    /// Runs the checks of [`Self::upsert_keys`] against an upload without applying it, collecting
+    /// every problem instead of stopping at the first. Parts of the upload that failed to decode
+    /// are passed as `None` and skipped.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if the device's stored keys cannot be read.
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(device.id = %telemetry::id(device_id)),
+        err(level = "debug")
+    )]
+    pub(crate) async fn validate_upload(
+        &self,
+        device_id: Uuid,
+        identity_key: Option<&PublicKey>,
+        signed_pre_key: Option<&SignedPreKey>,
+        one_time_pre_keys: &[OneTimePreKey],
+    ) -> Result<KeyValidationReport> {
+        let mut conn = self.pool.acquire_timed().await?;
+        let stored_ik = self.repo.fetch_identity_key(&mut conn, device_id).await?;
+        self.check_upload(&mut conn, device_id, stored_ik.as_ref(), identity_key, signed_pre_key, one_time_pre_keys)
+            .await
+    }
+
+    /// The rules a key upload must pass, shared by [`Self::upsert_keys`], which rejects on the
+    /// first issue, and [`Self::validate_upload`], which reports them all. The first issue is the one
+    /// an upload is rejected with.
+    async fn check_upload(
+        &self,
+        conn: &mut PgConnection,
+        device_id: Uuid,
+        stored_ik: Option<&PublicKey>,
+        identity_key: Option<&PublicKey>,
+        signed_pre_key: Option<&SignedPreKey>,
+        one_time_pre_keys: &[OneTimePreKey],
+    ) -> Result<KeyValidationReport> {
+        let is_takeover = identity_key.is_some_and(|ik| stored_ik != Some(ik));
+        let mut issues = Vec::new();
+
+        // Only identity keys may be Ed25519; pre-keys are used for X25519 agreement.
+        if let Some(spk) = signed_pre_key.filter(|spk| spk.public_key.key_type() != KeyType::Curve25519) {
+            issues.push(KeyIssue::new(KeyField::SignedPreKey, Some(spk.key_id), "Pre-keys must be Curve25519 keys"));
+        }
+        issues.extend(
+            one_time_pre_keys
+                .iter()
+                .filter(|k| k.public_key.key_type() != KeyType::Curve25519)
+                .map(|k| KeyIssue::new(KeyField::OneTimePreKeys, Some(k.key_id), "Pre-keys must be Curve25519 keys")),
+        );
+
+        if let Some(spk) = signed_pre_key {
+            match identity_key.or(stored_ik) {
+                Some(ik) => match self.verify_keys(ik, spk) {
+                    Ok(()) => {}
+                    Err(AppError::BadRequest(message)) => {
+                        issues.push(KeyIssue::new(KeyField::SignedPreKey, Some(spk.key_id), message));
+                    }
+                    Err(e) => return Err(e),
+                },
+                None => issues.push(KeyIssue::new(KeyField::IdentityKey, None, "Identity key missing")),
+            }
+
+            // Monotonic ID Check (Prevent Replay / Rollback)
+            if !is_takeover {
+                let max_id = self.repo.find_max_signed_pre_key_id(&mut *conn, device_id).await?;
+                if let Some(message) = stale_signed_pre_key_id(spk.key_id, max_id) {
+                    issues.push(KeyIssue::new(KeyField::SignedPreKey, Some(spk.key_id), message));
+                }
+            }
+        }
+
+        if i64::try_from(one_time_pre_keys.len()).unwrap_or(i64::MAX) > self.config.max_pre_keys {
+            issues.push(KeyIssue::new(
+                KeyField::OneTimePreKeys,
+                None,
+                format!("Batch too large. Limit is {}", self.config.max_pre_keys),
+            ));
+        }
+
+        Ok(KeyValidationReport { is_takeover, issues })
+    }
+
+    /// The push that asks `user_id`'s devices to refill their pre-keys. Clients that predate the
+    /// `refill_prekeys` action are woken with an ordinary check until the flag reaches them.
+    fn refill_push(&self, user_id: Uuid) -> PushKind {
+        if self.feature_flags.as_ref().is_some_and(|flags| flags.is_enabled(PREKEY_REFILL_PUSH, user_id)) {
+            PushKind::PreKeyLow
+        } else {
+            PushKind::Check
+        }
+    }
+
     fn verify_keys(&self, ik: &PublicKey, signed_pre_key: &SignedPreKey) -> Result<()> {
         // libsignal-protocol-typescript's generateSignedPreKey signs the 33-byte publicKey ArrayBuffer.
         // However, some versions or test polyfills might sign the 32-byte raw key.
@@ -238,3 +467,11 @@ impl KeyService {
         self.crypto_service.verify_signature(ik, raw_32, &signed_pre_key.signature)
     }
 }
+
+/// Explains why a signed pre-key with `key_id` may not replace one with `current_max`, since a
+/// lower ID would roll the device back to an older key.
+fn stale_signed_pre_key_id(key_id: i32, current_max: Option<i32>) -> Option<String> {
+    current_max
+        .filter(|&max| key_id <= max)
+        .map(|max| format!("Signed Pre-Key ID {key_id} must be greater than current ID {max}"))
+}
@@ -1,20 +1,41 @@
 use crate::adapters::database::DbPool;
+use crate::adapters::database::availability::DbAvailability;
+use crate::adapters::database::instrumentation::TimedAcquire;
 use crate::adapters::database::message_repo::MessageRepository;
+use crate::adapters::database::metadata_index_repo::MetadataIndexRepository;
+use crate::adapters::storage::{ObjectStorage, StorageError, StorageStream};
 use crate::config::MessagingConfig;
-use crate::domain::message::{FailedSubmission, Message, RawSubmission, SubmissionErrorCode, SubmissionOutcome};
+use crate::domain::message::{
+    FailedSubmission, Message, MessagePayload, RawSubmission, SubmissionErrorCode, SubmissionOutcome, SystemCode,
+    payload_storage_key,
+};
 use crate::domain::notification::UserEvent;
-use crate::error::Result;
+use crate::error::{AppError, Result};
+use crate::proto::obscura::v1 as proto;
 use crate::services::notification_service::NotificationService;
+use crate::telemetry;
+use bytes::Bytes;
+use futures::{StreamExt, TryStreamExt, stream};
 use opentelemetry::{
     KeyValue, global,
     metrics::{Counter, Histogram},
 };
+use prost::Message as ProstMessage;
+use sqlx::PgConnection;
+use std::collections::HashSet;
+use std::sync::Arc;
+use time::{Duration, OffsetDateTime};
 use uuid::Uuid;
 
+/// Offloaded payloads uploaded or downloaded at once for a single batch.
+const PAYLOAD_TRANSFER_CONCURRENCY: usize = 8;
+
 #[derive(Clone, Debug)]
 pub(crate) struct Metrics {
     pub(crate) sent_total: Counter<u64>,
     pub(crate) fetch_batch_size: Histogram<u64>,
+    pub(crate) offloaded_total: Counter<u64>,
+    pub(crate) service_messages_total: Counter<u64>,
 }
 
 impl Metrics {
@@ -29,40 +50,96 @@ impl Metrics {
                 .u64_histogram("obscura_message_fetch_batch_size")
                 .with_description("Number of messages fetched in a single batch")
                 .build(),
+            offloaded_total: meter
+                .u64_counter("obscura_messages_offloaded_total")
+                .with_description("Total message payloads stored in object storage instead of the database")
+                .build(),
+            service_messages_total: meter
+                .u64_counter("obscura_service_messages_total")
+                .with_description("Total system envelopes queued for devices through the inbound webhook")
+                .build(),
         }
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub(crate) struct MessageService {
     pool: DbPool,
     repo: MessageRepository,
+    storage: Arc<dyn ObjectStorage>,
     notifier: NotificationService,
+    config: MessagingConfig,
     ttl_days: i64,
+    availability: DbAvailability,
+    metadata_index: Option<MetadataIndexRepository>,
     metrics: Metrics,
 }
 
+impl std::fmt::Debug for MessageService {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MessageService")
+            .field("repo", &self.repo)
+            .field("notifier", &self.notifier)
+            .field("config", &self.config)
+            .field("ttl_days", &self.ttl_days)
+            .field("metadata_index", &self.metadata_index.is_some())
+            .field("metrics", &self.metrics)
+            .finish_non_exhaustive()
+    }
+}
+
 impl MessageService {
     #[must_use]
     pub(crate) fn new(
         pool: DbPool,
         repo: MessageRepository,
+        storage: Arc<dyn ObjectStorage>,
         notifier: NotificationService,
-        _config: MessagingConfig,
+        config: MessagingConfig,
         ttl_days: i64,
     ) -> Self {
-        Self { pool, repo, notifier, ttl_days, metrics: Metrics::new() }
+        Self {
+            pool,
+            repo,
+            storage,
+            notifier,
+            config,
+            ttl_days,
+            availability: DbAvailability::default(),
+            metadata_index: None,
+            metrics: Metrics::new(),
+        }
+    }
+
+    /// Serves message downloads in read-only mode per `availability`.
+    #[must_use]
+    pub(crate) fn with_availability(mut self, availability: DbAvailability) -> Self {
+        self.availability = availability;
+        self
+    }
+
+    /// Records the metadata of every message sent by a user in `index`, in the same transaction
+    /// that queues it.
+    #[must_use]
+    pub(crate) fn with_metadata_index(mut self, index: MetadataIndexRepository) -> Self {
+        self.metadata_index = Some(index);
+        self
     }
 
     /// Processes a batch of raw submissions.
-    /// Performs structural validation, device checking, and bulk insertion.
+    /// Performs structural validation, device checking, payload offloading, and bulk insertion.
     ///
     /// # Errors
-    /// Returns `AppError::Database` if any database operation fails.
+    /// Returns `AppError::Database` if any database operation fails, or a storage error if a large
+    /// payload cannot be offloaded.
     #[tracing::instrument(
         err(level = "warn"),
-        skip(self, submissions),
-        fields(sender_id = %sender_id, sender_device_id = %sender_device_id, count = submissions.len())
+        skip_all,
+        fields(
+            count = submissions.len(),
+            user.id = %telemetry::id(sender_id),
+            device.id = %telemetry::id(sender_device_id)
+        )
     )]
     pub(crate) async fn send(
         &self,
@@ -76,46 +153,25 @@ impl MessageService {
 
         // Pass 1: Structural Validation
         for raw in submissions {
-            let Ok(submission_id) = Uuid::from_slice(&raw.submission_id) else {
-                failed_submissions.push(FailedSubmission {
-                    submission_id: raw.submission_id,
-                    error_code: SubmissionErrorCode::MalformedSubmissionId,
-                    error_message: "Invalid submission_id UUID bytes (expected 16)".to_string(),
-                });
-                continue;
-            };
-
-            let Ok(device_id) = Uuid::from_slice(&raw.device_id) else {
-                failed_submissions.push(FailedSubmission {
-                    submission_id: raw.submission_id,
-                    error_code: SubmissionErrorCode::MalformedDeviceId,
-                    error_message: "Invalid device_id UUID bytes (expected 16)".to_string(),
-                });
-                continue;
-            };
-
-            if raw.message.is_empty() {
-                failed_submissions.push(FailedSubmission {
-                    submission_id: raw.submission_id,
-                    error_code: SubmissionErrorCode::MessageMissing,
-                    error_message: "Missing message payload".to_string(),
-                });
-                continue;
+            match raw.validate() {
+                Ok((submission_id, device_id, message)) => {
+                    device_ids_to_check.insert(device_id);
+                    potential_valid.push((device_id, submission_id, message));
+                }
+                Err(failed) => failed_submissions.push(failed),
             }
-
-            device_ids_to_check.insert(device_id);
-            potential_valid.push((device_id, submission_id, raw.message));
         }
 
         if potential_valid.is_empty() {
-            return Ok(SubmissionOutcome { failed_submissions });
+            return Ok(SubmissionOutcome { receipts: Vec::new(), failed_submissions });
         }
 
         // Pass 2: Business Validation (Device Existence)
-        let mut tx = self.pool.begin().await?;
         let check_ids: Vec<Uuid> = device_ids_to_check.into_iter().collect();
-        let valid_devices_set: std::collections::HashSet<Uuid> =
-            self.repo.check_devices_exist(&mut tx, &check_ids).await?.into_iter().collect();
+        let valid_devices_set: std::collections::HashSet<Uuid> = {
+            let mut conn = self.pool.acquire_timed().await?;
+            self.repo.check_devices_exist(&mut conn, &check_ids).await?.into_iter().collect()
+        };
 
         let mut to_insert = Vec::with_capacity(potential_valid.len());
         for (d_id, s_id, msg) in potential_valid {
@@ -130,56 +186,347 @@ impl MessageService {
             }
         }
 
-        // Pass 3: Bulk Insert
+        // Pass 3: Offload large payloads, then bulk insert
+        let mut receipts = Vec::new();
         if !to_insert.is_empty() {
-            let inserted =
-                self.repo.create_batch(&mut tx, sender_id, sender_device_id, to_insert, self.ttl_days).await?;
+            let submission_ids: Vec<Uuid> = to_insert.iter().map(|(_, s_id, _)| *s_id).collect();
+            let to_insert = self.offload_payloads(to_insert).await?;
+
+            let mut tx = self.pool.begin_timed().await?;
+            receipts = self.repo.create_batch(&mut tx, sender_id, sender_device_id, to_insert, self.ttl_days).await?;
+            if let Some(index) = &self.metadata_index {
+                let message_ids: Vec<Uuid> = receipts.iter().map(|r| r.message_id).collect();
+                index.record_messages(&mut tx, &message_ids).await?;
+            }
             tx.commit().await?;
 
-            self.metrics.sent_total.add(inserted.len() as u64, &[KeyValue::new("status", "success")]);
+            self.metrics.sent_total.add(receipts.len() as u64, &[KeyValue::new("status", "success")]);
 
             // Notify target devices
-            let inserted_device_ids: Vec<Uuid> = inserted.into_iter().map(|(id, _)| id).collect();
+            let inserted_device_ids: Vec<Uuid> = receipts.iter().map(|r| r.device_id).collect();
             self.notifier.notify(&inserted_device_ids, UserEvent::MessageReceived).await;
+
+            // Retried submissions keep the receipt of their first attempt while it is still queued
+            let inserted: std::collections::HashSet<Uuid> = receipts.iter().map(|r| r.submission_id).collect();
+            let duplicates: Vec<Uuid> = submission_ids.into_iter().filter(|id| !inserted.contains(id)).collect();
+            if !duplicates.is_empty() {
+                let mut conn = self.pool.acquire_timed().await?;
+                receipts.extend(self.repo.fetch_receipts(&mut conn, sender_device_id, &duplicates).await?);
+            }
         }
 
-        Ok(SubmissionOutcome { failed_submissions })
+        Ok(SubmissionOutcome { receipts, failed_submissions })
     }
 
-    /// Fetches a batch of pending messages for a device.
+    /// Fetches a batch of pending messages for a device, loading offloaded payloads from storage.
+    /// In read-only mode the batch is read from the replica, if any.
     ///
     /// # Errors
-    /// Returns `AppError::Database` if the query fails.
+    /// Returns `AppError::Database` if the query fails, or a storage error if a payload cannot be read.
     #[tracing::instrument(
         err(level = "warn"),
-        skip(self),
-        fields(device.id = %device_id, batch_limit = %limit)
+        skip_all,
+        fields(device.id = %telemetry::id(device_id), batch_limit = %limit, ?cursor)
     )]
     pub(crate) async fn fetch_pending_batch(
         &self,
         device_id: Uuid,
-        cursor: Option<(time::OffsetDateTime, Uuid)>,
+        cursor: Option<i64>,
         limit: i64,
     ) -> Result<Vec<Message>> {
-        let mut conn = self.pool.acquire().await?;
-        let messages = self.repo.fetch_pending_batch(&mut conn, device_id, cursor, limit).await?;
+        let mut conn = self.availability.read_pool(&self.pool).acquire_timed().await?;
+        let mut messages = self.repo.fetch_pending_batch(&mut conn, device_id, cursor, limit).await?;
+        drop(conn);
+
+        if messages.iter().any(|msg| msg.offloaded) {
+            messages = self.load_payloads(device_id, messages).await?;
+        }
 
         self.metrics.fetch_batch_size.record(messages.len() as u64, &[]);
 
         Ok(messages)
     }
 
-    /// Deletes a batch of messages.
+    /// Settles a batch of acknowledged messages, deleting them or, during the ACK grace period,
+    /// keeping them for redelivery.
     ///
     /// # Errors
-    /// Returns `AppError::Database` if the deletion fails.
+    /// Returns `AppError::Database` if the update or deletion fails.
+    #[tracing::instrument(
+        err,
+        skip_all,
+        fields(batch_count = message_ids.len(), device.id = %telemetry::id(device_id))
+    )]
+    pub(crate) async fn acknowledge_batch(&self, device_id: Uuid, message_ids: &[Uuid]) -> Result<()> {
+        let mut conn = self.pool.acquire_timed().await?;
+        self.repo.acknowledge_batch(&mut conn, device_id, message_ids).await
+    }
+
+    /// Highest inbox sequence number previously written to one of the device's sessions.
+    /// Envelopes at or below it are redeliveries. Read from the replica in read-only mode.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if the query fails.
+    pub(crate) async fn delivered_seq(&self, device_id: Uuid) -> Result<Option<i64>> {
+        let mut conn = self.availability.read_pool(&self.pool).acquire_timed().await?;
+        self.repo.fetch_delivered_seq(&mut conn, device_id).await
+    }
+
+    /// Records that envelopes up to `seq` have been written to one of the device's sessions, by a
+    /// session that connected at `session_started_at`.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if the update fails.
+    pub(crate) async fn record_delivered_seq(
+        &self,
+        device_id: Uuid,
+        seq: i64,
+        session_started_at: OffsetDateTime,
+    ) -> Result<()> {
+        let mut conn = self.pool.acquire_timed().await?;
+        self.repo.record_delivered_seq(&mut conn, device_id, seq, session_started_at).await
+    }
+
+    /// Puts messages the device acknowledged within the ACK grace period back in its queue, for a
+    /// client that lost them in a crash. They are delivered again on the device's next gateway
+    /// connection. Returns how many were found.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if the update fails.
+    #[tracing::instrument(
+        err,
+        skip_all,
+        fields(device.id = %telemetry::id(device_id), batch_count = message_ids.len())
+    )]
+    pub(crate) async fn redeliver(&self, device_id: Uuid, message_ids: &[Uuid]) -> Result<u64> {
+        let mut conn = self.pool.acquire_timed().await?;
+        self.repo.redeliver(&mut conn, device_id, message_ids).await
+    }
+
+    /// Queues a message sealed by the external service `service` as a `ServiceMessage` system
+    /// envelope for every device of `user_ids`. The server never reads `message`; it only wraps
+    /// it with the service name and time. Returns the number of devices it was queued for.
+    ///
+    /// Every envelope is queued under `submission_id`, so a retry of the same submission skips
+    /// the devices that still hold it.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if a query fails.
     #[tracing::instrument(
         err,
-        skip(self),
-        fields(batch_count = message_ids.len())
+        skip_all,
+        fields(service = %service, users = user_ids.len())
     )]
-    pub(crate) async fn delete_batch(&self, device_id: Uuid, message_ids: &[Uuid]) -> Result<()> {
-        let mut conn = self.pool.acquire().await?;
-        self.repo.delete_batch(&mut conn, device_id, message_ids).await
+    pub(crate) async fn queue_service_message(
+        &self,
+        service: &str,
+        submission_id: Uuid,
+        user_ids: &[Uuid],
+        message: Vec<u8>,
+    ) -> Result<u64> {
+        let mut tx = self.pool.begin_timed().await?;
+        let device_ids = self.repo.find_user_devices(&mut tx, user_ids).await?;
+        if device_ids.is_empty() {
+            return Ok(0);
+        }
+
+        let now = OffsetDateTime::now_utc();
+        let notice = proto::ServiceMessage {
+            service: service.to_string(),
+            message,
+            sent_at: u64::try_from(now.unix_timestamp_nanos() / 1_000_000).unwrap_or(0),
+        }
+        .encode_to_vec();
+        let envelopes = device_ids.iter().map(|device_id| (*device_id, notice.clone())).collect();
+
+        let expires_at = now + Duration::days(self.ttl_days);
+        let inserted = self
+            .repo
+            .create_system_batch(&mut tx, SystemCode::ServiceMessage, envelopes, expires_at, Some(submission_id))
+            .await?;
+        tx.commit().await?;
+
+        if inserted > 0 {
+            self.metrics.service_messages_total.add(inserted, &[KeyValue::new("service", service.to_string())]);
+            self.notifier.notify(&device_ids, UserEvent::MessageReceived).await;
+        }
+        Ok(device_ids.len() as u64)
+    }
+
+    /// Tells every other device with messages still pending for `device_ids`, queued since `since`,
+    /// that the recipient account `user_id` is gone, listing the submissions that will never be
+    /// delivered. Runs in the caller's transaction, before the recipient's messages are deleted.
+    /// Returns each notified sender device with its number of undelivered submissions.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if a query fails.
+    #[tracing::instrument(err, skip_all, fields(user.id = %telemetry::id(user_id), ?since))]
+    pub(crate) async fn queue_recipient_gone(
+        &self,
+        conn: &mut PgConnection,
+        user_id: Uuid,
+        device_ids: &[Uuid],
+        since: OffsetDateTime,
+    ) -> Result<Vec<(Uuid, usize)>> {
+        let senders = self.repo.find_pending_senders(conn, device_ids, since).await?;
+        if senders.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let now = OffsetDateTime::now_utc();
+        let deleted_at = u64::try_from(now.unix_timestamp_nanos() / 1_000_000).unwrap_or(0);
+        let mut notified = Vec::with_capacity(senders.len());
+        let envelopes = senders
+            .into_iter()
+            .map(|(sender_device_id, submission_ids)| {
+                notified.push((sender_device_id, submission_ids.len()));
+                let notice = proto::RecipientGone {
+                    user_id: user_id.as_bytes().to_vec(),
+                    submission_ids: submission_ids.iter().map(|id| id.as_bytes().to_vec()).collect(),
+                    deleted_at,
+                };
+                (sender_device_id, notice.encode_to_vec())
+            })
+            .collect();
+
+        let expires_at = now + Duration::days(self.ttl_days);
+        self.repo.create_system_batch(conn, SystemCode::RecipientGone, envelopes, expires_at, None).await?;
+        Ok(notified)
+    }
+
+    /// Deletes every pending message of a device within `conn`'s transaction. Returns the IDs of
+    /// offloaded payloads to pass to [`Self::purge_payloads`] once the transaction commits.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if the deletion fails.
+    pub(crate) async fn wipe_inbox(&self, conn: &mut PgConnection, device_id: Uuid) -> Result<Vec<Uuid>> {
+        self.repo.delete_all_for_device(conn, device_id).await
+    }
+
+    /// Deletes the stored payloads of messages that no longer exist, and forgets them.
+    ///
+    /// Best effort: payloads that cannot be deleted now stay registered, and the cleanup worker
+    /// removes them later.
+    #[tracing::instrument(skip_all, fields(count = message_ids.len()))]
+    pub(crate) async fn purge_payloads(&self, message_ids: &[Uuid]) {
+        if message_ids.is_empty() {
+            return;
+        }
+        let keys: Vec<String> =
+            message_ids.iter().map(|id| payload_storage_key(&self.config.payload_prefix, *id)).collect();
+        let failed: HashSet<String> = match self.storage.delete_many(&keys).await {
+            Ok(failed) => failed.into_iter().collect(),
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to delete message payloads, leaving them to cleanup");
+                return;
+            }
+        };
+
+        let deleted: Vec<Uuid> =
+            message_ids.iter().zip(&keys).filter(|(_, key)| !failed.contains(*key)).map(|(id, _)| *id).collect();
+        let forgotten = match self.pool.acquire_timed().await {
+            Ok(mut conn) => self.repo.delete_payloads(&mut conn, &deleted).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = forgotten {
+            tracing::warn!(error = %e, "Failed to forget deleted message payloads, leaving them to cleanup");
+        }
+    }
+
+    /// Moves payloads above the offload threshold to object storage, leaving the rest inline.
+    ///
+    /// Payloads are registered before they are uploaded, so the cleanup worker removes the object
+    /// if its message is never inserted.
+    async fn offload_payloads(&self, messages: Vec<(Uuid, Uuid, Bytes)>) -> Result<Vec<(Uuid, Uuid, MessagePayload)>> {
+        let threshold = self.config.payload_offload_threshold_bytes;
+        let mut prepared = Vec::with_capacity(messages.len());
+        let mut uploads = Vec::new();
+
+        for (device_id, submission_id, content) in messages {
+            if threshold == 0 || content.len() <= threshold {
+                prepared.push((device_id, submission_id, MessagePayload::Inline(content)));
+                continue;
+            }
+            let size = i32::try_from(content.len()).map_err(|_| AppError::PayloadTooLarge)?;
+            let message_id = Uuid::now_v7();
+            prepared.push((device_id, submission_id, MessagePayload::Offloaded { message_id, size }));
+            uploads.push((message_id, content));
+        }
+
+        if uploads.is_empty() {
+            return Ok(prepared);
+        }
+
+        let message_ids: Vec<Uuid> = uploads.iter().map(|(id, _)| *id).collect();
+        let mut conn = self.pool.acquire_timed().await?;
+        self.repo.register_payloads(&mut conn, &message_ids).await?;
+        drop(conn);
+
+        stream::iter(uploads)
+            .map(|(message_id, content)| self.upload_payload(message_id, content))
+            .buffer_unordered(PAYLOAD_TRANSFER_CONCURRENCY)
+            .try_collect::<Vec<()>>()
+            .await?;
+
+        self.metrics.offloaded_total.add(message_ids.len() as u64, &[]);
+        Ok(prepared)
+    }
+
+    async fn upload_payload(&self, message_id: Uuid, content: Bytes) -> Result<()> {
+        let len = content.len();
+        let body: StorageStream = stream::once(std::future::ready(Ok::<_, std::io::Error>(content))).boxed();
+        let key = payload_storage_key(&self.config.payload_prefix, message_id);
+        self.storage.put(&key, body, Some(len), 0, len, None).await?;
+        Ok(())
+    }
+
+    /// Fills in the content of offloaded messages. Messages whose payload is gone from storage can
+    /// never be delivered, so they are deleted and left out of the batch.
+    async fn load_payloads(&self, device_id: Uuid, mut messages: Vec<Message>) -> Result<Vec<Message>> {
+        let offloaded: Vec<Uuid> = messages.iter().filter(|msg| msg.offloaded).map(|msg| msg.id).collect();
+        let payloads: Vec<Option<Vec<u8>>> = stream::iter(offloaded)
+            .map(|message_id| self.read_payload(message_id))
+            .buffered(PAYLOAD_TRANSFER_CONCURRENCY)
+            .try_collect()
+            .await?;
+
+        let mut payloads = payloads.into_iter();
+        let mut missing = Vec::new();
+        messages.retain_mut(|msg| {
+            if !msg.offloaded {
+                return true;
+            }
+            if let Some(content) = payloads.next().flatten() {
+                msg.content = content;
+                return true;
+            }
+            missing.push(msg.id);
+            false
+        });
+
+        // In read-only mode they are only left out, and deleted by a later fetch
+        if !missing.is_empty() && !self.availability.is_read_only() {
+            tracing::warn!(count = missing.len(), "Deleting messages whose offloaded payload is missing");
+            let mut conn = self.pool.acquire_timed().await?;
+            self.repo.delete_batch(&mut conn, device_id, &missing).await?;
+        }
+
+        Ok(messages)
+    }
+
+    /// Reads an offloaded payload, or `None` if the object does not exist.
+    async fn read_payload(&self, message_id: Uuid) -> Result<Option<Vec<u8>>> {
+        let key = payload_storage_key(&self.config.payload_prefix, message_id);
+        let (len, mut body) = match self.storage.get(&key).await {
+            Ok(object) => object,
+            Err(StorageError::NotFound) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut content = Vec::with_capacity(usize::try_from(len).unwrap_or(0));
+        while let Some(chunk) = body.try_next().await.map_err(|_| AppError::Internal)? {
+            content.extend_from_slice(&chunk);
+        }
+        Ok(Some(content))
     }
 }
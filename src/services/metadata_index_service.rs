@@ -0,0 +1,154 @@
+use crate::adapters::database::DbPool;
+use crate::adapters::database::instrumentation::TimedAcquire;
+use crate::adapters::database::metadata_index_repo::MetadataIndexRepository;
+use crate::config::MetadataIndexConfig;
+use crate::domain::metadata_index::{MessageMetadata, MetadataQuery, MetadataQueryAudit};
+use crate::error::{AppError, Result};
+use opentelemetry::{global, metrics::Counter};
+use time::{Duration, OffsetDateTime};
+
+/// Longest operator name or query reason accepted, in characters.
+const MAX_AUDIT_FIELD_CHARS: usize = 1024;
+
+/// Audited queries listed at once when the caller does not ask for fewer.
+const DEFAULT_AUDIT_PAGE_SIZE: u32 = 100;
+
+/// Entries deleted per statement when enforcing the retention period.
+const PURGE_BATCH_SIZE: i64 = 5000;
+
+#[derive(Clone, Debug)]
+struct Metrics {
+    queries_total: Counter<u64>,
+    purged_total: Counter<u64>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let meter = global::meter("obscura-server");
+        Self {
+            queries_total: meter
+                .u64_counter("obscura_metadata_index_queries_total")
+                .with_description("Audited operator queries run against the message metadata index")
+                .build(),
+            purged_total: meter
+                .u64_counter("obscura_metadata_index_purged_total")
+                .with_description("Metadata index entries deleted after the retention period")
+                .build(),
+        }
+    }
+}
+
+/// Operator queries over the message metadata index, for answering requests scoped to who
+/// messaged whom and when.
+///
+/// Entries never hold content. Every query must name an account, a reason and the operator
+/// running it, and is written to an audit log in the same transaction that reads the entries,
+/// so no result is returned without its audit record.
+#[derive(Clone, Debug)]
+pub struct MetadataIndexService {
+    pool: DbPool,
+    repo: MetadataIndexRepository,
+    max_results: u32,
+    retention: Duration,
+    metrics: Metrics,
+}
+
+impl MetadataIndexService {
+    #[must_use]
+    pub fn new(pool: DbPool, repo: MetadataIndexRepository, config: &MetadataIndexConfig) -> Self {
+        Self {
+            pool,
+            repo,
+            max_results: config.max_results,
+            retention: Duration::days(config.retention_days),
+            metrics: Metrics::new(),
+        }
+    }
+
+    /// Returns up to `limit` entries matching `query`, capped at `--metadata-index-max-results`,
+    /// together with the audit record of the query.
+    ///
+    /// # Errors
+    /// Returns `AppError::BadRequest` if the operator or reason is missing or too long, or the
+    /// query names no account or has an inverted time range.
+    /// Returns `AppError::Database` if the index cannot be read or the audit record written.
+    #[tracing::instrument(skip_all, fields(%operator, ?client_cert, ?query, ?limit), err(level = "warn"))]
+    pub async fn search(
+        &self,
+        operator: &str,
+        reason: &str,
+        client_cert: Option<&str>,
+        query: MetadataQuery,
+        limit: Option<u32>,
+    ) -> Result<(MetadataQueryAudit, Vec<MessageMetadata>)> {
+        let operator = audit_field("operator", operator)?;
+        let reason = audit_field("reason", reason)?;
+        query.validate().map_err(AppError::BadRequest)?;
+        let limit = limit.unwrap_or(self.max_results).clamp(1, self.max_results);
+
+        let mut tx = self.pool.begin_timed().await?;
+        let entries = self.repo.search(&mut tx, &query, i64::from(limit)).await?;
+        let result_count = i32::try_from(entries.len()).unwrap_or(i32::MAX);
+        let audit = self.repo.record_query(&mut tx, operator, reason, client_cert, &query, result_count).await?;
+        tx.commit().await?;
+
+        tracing::info!(
+            query.id = %audit.id,
+            operator = %audit.operator,
+            reason = %audit.reason,
+            results = result_count,
+            "Metadata index queried"
+        );
+        self.metrics.queries_total.add(1, &[]);
+        Ok((audit, entries))
+    }
+
+    /// Lists the most recent audited queries, newest first.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if the audit log cannot be read.
+    #[tracing::instrument(skip_all, fields(?limit), err)]
+    pub async fn list_queries(&self, limit: Option<u32>) -> Result<Vec<MetadataQueryAudit>> {
+        let limit = limit.unwrap_or(DEFAULT_AUDIT_PAGE_SIZE).clamp(1, self.max_results);
+        let mut conn = self.pool.acquire_timed().await?;
+        self.repo.list_queries(&mut conn, i64::from(limit)).await
+    }
+
+    /// Deletes every entry older than `--metadata-index-retention-days`. Returns how many were deleted.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if a batch fails. Batches deleted before it stay deleted.
+    #[tracing::instrument(skip_all, err, fields(deleted = tracing::field::Empty))]
+    pub async fn purge_expired(&self) -> Result<u64> {
+        let cutoff = OffsetDateTime::now_utc() - self.retention;
+        let mut total = 0;
+        loop {
+            let mut conn = self.pool.acquire_timed().await?;
+            let deleted = self.repo.delete_older_than(&mut conn, cutoff, PURGE_BATCH_SIZE).await?;
+            drop(conn);
+
+            total += deleted;
+            self.metrics.purged_total.add(deleted, &[]);
+            if deleted < u64::try_from(PURGE_BATCH_SIZE).unwrap_or(0) {
+                break;
+            }
+        }
+
+        if total > 0 {
+            tracing::info!(count = %total, "Deleted metadata index entries past retention");
+        }
+        tracing::Span::current().record("deleted", total);
+        Ok(total)
+    }
+}
+
+fn audit_field<'a>(name: &str, value: &'a str) -> Result<&'a str> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(AppError::BadRequest(format!("{name} must not be empty")));
+    }
+    if value.chars().count() > MAX_AUDIT_FIELD_CHARS {
+        return Err(AppError::BadRequest(format!("{name} exceeds {MAX_AUDIT_FIELD_CHARS} characters")));
+    }
+    Ok(value)
+}
@@ -1,12 +1,23 @@
+pub mod account_service;
+pub mod announcement_service;
 pub mod attachment_service;
 pub mod auth_service;
 pub mod backup_service;
+pub mod blocklist_service;
 pub mod crypto_service;
 pub mod device_service;
+pub mod feature_flag_service;
 pub mod gateway;
 pub mod health_service;
+pub mod identifier_service;
+pub mod instance_service;
 pub mod key_service;
 pub mod message_service;
+pub mod metadata_index_service;
+pub mod notification_mailbox;
 pub mod notification_service;
 pub mod push_token_service;
 pub mod rate_limit_service;
+pub mod support_service;
+pub mod upload_guard;
+pub mod usage_service;
@@ -0,0 +1,123 @@
+use crate::domain::notification::UserEvent;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio::sync::Notify;
+
+/// Every event kind, in the order a session handles them when several are pending at once.
+/// Closing events come first, since nothing else matters once the session is ending.
+const DELIVERY_ORDER: [UserEvent; 5] = [
+    UserEvent::Disconnect,
+    UserEvent::SessionReplaced,
+    UserEvent::MessageReceived,
+    UserEvent::PreKeyLow,
+    UserEvent::Announcement,
+];
+
+const fn bit(event: UserEvent) -> u8 {
+    1 << (event as u8)
+}
+
+#[derive(Debug, Default)]
+struct Slot {
+    pending: AtomicU8,
+    notify: Notify,
+}
+
+/// Realtime events for one device, kept as the set of event kinds each subscriber has yet to see.
+///
+/// Events carry no payload, so repeats of a kind collapse into one pending flag instead of
+/// filling a queue. A slow session therefore never misses a kind outright: it sees every kind
+/// posted since its last receive at least once, however many were posted.
+#[derive(Debug, Default)]
+pub(crate) struct Mailbox {
+    slots: Mutex<Vec<Arc<Slot>>>,
+}
+
+impl Mailbox {
+    pub(crate) fn subscribe(self: &Arc<Self>) -> MailboxReceiver {
+        let slot = Arc::new(Slot::default());
+        self.slots.lock().unwrap_or_else(PoisonError::into_inner).push(Arc::clone(&slot));
+        MailboxReceiver { mailbox: Arc::clone(self), slot, taken: 0 }
+    }
+
+    /// Marks `event` pending for every subscriber. Returns the number of subscribers reached
+    /// and how many of them already had it pending.
+    pub(crate) fn post(&self, event: UserEvent) -> (usize, usize) {
+        let slots = self.slots.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut coalesced = 0;
+        for slot in slots.iter() {
+            if slot.pending.fetch_or(bit(event), Ordering::AcqRel) & bit(event) != 0 {
+                coalesced += 1;
+            }
+            slot.notify.notify_one();
+        }
+        (slots.len(), coalesced)
+    }
+
+    pub(crate) fn subscriber_count(&self) -> usize {
+        self.slots.lock().unwrap_or_else(PoisonError::into_inner).len()
+    }
+}
+
+/// A session's view of its device's [`Mailbox`]. Dropping it unsubscribes.
+#[derive(Debug)]
+pub struct MailboxReceiver {
+    mailbox: Arc<Mailbox>,
+    slot: Arc<Slot>,
+    taken: u8,
+}
+
+impl MailboxReceiver {
+    /// Waits for the next pending event kind.
+    ///
+    /// Cancel safe: kinds taken from the mailbox but not yet returned are kept for the next call.
+    pub async fn recv(&mut self) -> UserEvent {
+        loop {
+            if let Some(event) = DELIVERY_ORDER.into_iter().find(|event| self.taken & bit(*event) != 0) {
+                self.taken &= !bit(event);
+                return event;
+            }
+            self.taken = self.slot.pending.swap(0, Ordering::AcqRel);
+            if self.taken == 0 {
+                self.slot.notify.notified().await;
+            }
+        }
+    }
+}
+
+impl Drop for MailboxReceiver {
+    fn drop(&mut self) {
+        self.mailbox.slots.lock().unwrap_or_else(PoisonError::into_inner).retain(|slot| !Arc::ptr_eq(slot, &self.slot));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_bursts_coalesce_without_losing_any_kind() {
+        let mailbox = Arc::new(Mailbox::default());
+        let mut rx = mailbox.subscribe();
+
+        for _ in 0..1000 {
+            mailbox.post(UserEvent::MessageReceived);
+        }
+        mailbox.post(UserEvent::PreKeyLow);
+        assert_eq!(mailbox.post(UserEvent::MessageReceived), (1, 1));
+
+        assert_eq!(rx.recv().await, UserEvent::MessageReceived);
+        assert_eq!(rx.recv().await, UserEvent::PreKeyLow);
+        assert!(tokio::time::timeout(Duration::from_millis(50), rx.recv()).await.is_err(), "Nothing else is pending");
+
+        // Every subscriber of the device sees the event
+        let mut other = mailbox.subscribe();
+        mailbox.post(UserEvent::SessionReplaced);
+        assert_eq!(rx.recv().await, UserEvent::SessionReplaced);
+        assert_eq!(other.recv().await, UserEvent::SessionReplaced);
+
+        drop(other);
+        assert_eq!(mailbox.subscriber_count(), 1);
+    }
+}
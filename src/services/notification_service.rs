@@ -1,13 +1,15 @@
-use crate::adapters::redis::NotificationRepository;
+use crate::adapters::push_queue::PushJobQueue;
+use crate::adapters::realtime::RealtimeBus;
 use crate::config::NotificationConfig;
-use crate::domain::notification::UserEvent;
+use crate::domain::notification::{EventContext, PushKind, UserEvent};
+use crate::services::notification_mailbox::{Mailbox, MailboxReceiver};
+use crate::telemetry;
 use dashmap::DashMap;
 use opentelemetry::{
     KeyValue, global,
     metrics::{Counter, Histogram, UpDownCounter},
 };
 use std::sync::Arc;
-use tokio::sync::broadcast;
 use uuid::Uuid;
 
 #[derive(Clone, Debug)]
@@ -15,6 +17,8 @@ struct Metrics {
     sends_total: Counter<u64>,
     received_total: Counter<u64>,
     unrouted_total: Counter<u64>,
+    fast_path_total: Counter<u64>,
+    coalesced_total: Counter<u64>,
     active_channels: UpDownCounter<i64>,
     cleanup_duration_seconds: Histogram<f64>,
     cleanup_reclaimed_total: Counter<u64>,
@@ -36,6 +40,14 @@ impl Metrics {
                 .u64_counter("obscura_notifications_unrouted_total")
                 .with_description("Notifications received from PubSub with no local subscribers")
                 .build(),
+            fast_path_total: meter
+                .u64_counter("obscura_notifications_fast_path_total")
+                .with_description("Realtime notifications by route: delivered in-process (local) or via PubSub")
+                .build(),
+            coalesced_total: meter
+                .u64_counter("obscura_notifications_coalesced_total")
+                .with_description("Events merged into the same kind of event still pending for a session")
+                .build(),
             active_channels: meter
                 .i64_up_down_counter("obscura_notification_channels")
                 .with_description("Number of active local notification channels")
@@ -54,39 +66,55 @@ impl Metrics {
 
 #[derive(Clone, Debug)]
 pub struct NotificationService {
-    repo: Arc<NotificationRepository>,
-    channels: Arc<DashMap<Uuid, broadcast::Sender<UserEvent>>>,
-    user_channel_capacity: usize,
+    repo: Arc<dyn RealtimeBus>,
+    push_queue: Arc<dyn PushJobQueue>,
+    channels: Arc<DashMap<Uuid, Arc<Mailbox>>>,
     push_delay_secs: u64,
+    always_publish: bool,
+    event_context: bool,
     metrics: Metrics,
 }
 
 impl NotificationService {
     /// Creates a new notification service handle.
     #[must_use]
-    pub fn new(repo: Arc<NotificationRepository>, config: &NotificationConfig) -> Self {
+    pub fn new(repo: Arc<dyn RealtimeBus>, push_queue: Arc<dyn PushJobQueue>, config: &NotificationConfig) -> Self {
         Self {
             repo,
+            push_queue,
             channels: Arc::new(DashMap::new()),
-            user_channel_capacity: config.user_channel_capacity,
             push_delay_secs: config.push_delay_secs,
+            always_publish: config.always_publish,
+            event_context: config.event_context,
             metrics: Metrics::new(),
         }
     }
 
     /// Dispatches an external real-time notification to local subscribers.
     pub fn dispatch_event(&self, notification: &crate::domain::notification::RealtimeNotification) {
-        let device_id = notification.device_id;
         let event = notification.event;
         let event_label = format!("{event:?}");
 
         self.metrics.received_total.add(1, &[KeyValue::new("event", event_label.clone())]);
 
-        if let Some(tx) = self.channels.get(&device_id) {
-            tracing::trace!(%device_id, ?event, "Dispatched notification to local channel");
-            let _ = tx.send(event);
+        let Some(device_id) = notification.device_id else {
+            let delivered = self.deliver_all_local(event);
+            tracing::trace!(?event, delivered, "Dispatched broadcast notification to local channels");
+            return;
+        };
+
+        if let Some(mailbox) = self.channels.get(&device_id) {
+            let context = &notification.context;
+            tracing::trace!(
+                device.id = %telemetry::id(device_id),
+                ?event,
+                count = context.visible_count(),
+                sender = context.visible_sender().map(telemetry::id).map(tracing::field::display),
+                "Dispatched notification to local channel"
+            );
+            self.post(&mailbox, event);
         } else {
-            tracing::debug!(%device_id, ?event, "No local subscriber for notification");
+            tracing::debug!(device.id = %telemetry::id(device_id), ?event, "No local subscriber for notification");
             self.metrics.unrouted_total.add(1, &[KeyValue::new("event", event_label)]);
         }
     }
@@ -97,8 +125,8 @@ impl NotificationService {
         tracing::debug!("Starting notification channel cleanup cycle");
         let mut reclaimed_this_cycle = 0;
 
-        self.channels.retain(|_, sender| {
-            let active = sender.receiver_count() > 0;
+        self.channels.retain(|_, mailbox| {
+            let active = mailbox.subscriber_count() > 0;
             if !active {
                 self.metrics.active_channels.add(-1, &[]);
                 reclaimed_this_cycle += 1;
@@ -116,47 +144,121 @@ impl NotificationService {
         tracing::debug!(duration_secs = %duration, "Notification channel cleanup cycle completed");
     }
 
-    #[tracing::instrument(skip(self), fields(device.id = %device_id))]
-    pub async fn subscribe(&self, device_id: Uuid) -> broadcast::Receiver<UserEvent> {
-        let tx = self
-            .channels
+    /// Subscribes to the realtime events of a device on this instance.
+    ///
+    /// Repeated events of one kind coalesce while the subscriber is busy, so a slow session sees
+    /// each kind at least once rather than every occurrence.
+    #[tracing::instrument(skip_all, fields(device.id = %telemetry::id(device_id)))]
+    pub async fn subscribe(&self, device_id: Uuid) -> MailboxReceiver {
+        self.channels
             .entry(device_id)
             .or_insert_with(|| {
                 self.metrics.active_channels.add(1, &[]);
-                let (tx, _rx) = broadcast::channel(self.user_channel_capacity);
-                tx
+                Arc::new(Mailbox::default())
             })
-            .value()
-            .clone();
-
-        tx.subscribe()
+            .subscribe()
     }
 
-    #[tracing::instrument(skip(self, recipients), fields(count = recipients.len(), event = ?event))]
     pub async fn notify(&self, recipients: &[Uuid], event: UserEvent) {
+        self.notify_with_context(recipients, event, EventContext::default()).await;
+    }
+
+    /// Like [`Self::notify`], attaching `context` for sessions on other instances. The context is
+    /// only published with `--notifications-event-context`, as instances that predate it drop
+    /// events carrying one.
+    pub async fn notify_with_context(&self, recipients: &[Uuid], event: UserEvent, context: EventContext) {
+        self.notify_with_push(recipients, event, context, PushKind::for_event(event)).await;
+    }
+
+    /// Like [`Self::notify_with_context`], scheduling a push of kind `push` rather than the one
+    /// the event usually warrants, or none.
+    #[tracing::instrument(skip_all, fields(count = recipients.len(), event = ?event, ?push))]
+    pub async fn notify_with_push(
+        &self,
+        recipients: &[Uuid],
+        event: UserEvent,
+        context: EventContext,
+        push: Option<PushKind>,
+    ) {
         if recipients.is_empty() {
             return;
         }
 
-        // Fast Path: WebSocket/PubSub
-        if let Err(e) = self.repo.publish_realtime(recipients, event).await {
-            tracing::error!(error = %e, "Failed to batch publish to PubSub");
-            self.metrics.sends_total.add(recipients.len() as u64, &[KeyValue::new("status", "error")]);
-        } else {
-            self.metrics.sends_total.add(recipients.len() as u64, &[KeyValue::new("status", "sent")]);
+        // Fast Path: sessions on this instance are woken directly, everyone else via PubSub
+        let mut remote = Vec::with_capacity(recipients.len());
+        let mut local_hits = 0u64;
+        for &device_id in recipients {
+            if self.deliver_local(device_id, event) {
+                local_hits += 1;
+                // A device can hold sessions on other instances too, and a disconnect must reach
+                // every one of them
+                if !self.always_publish && event != UserEvent::Disconnect {
+                    continue;
+                }
+            }
+            remote.push(device_id);
+        }
+        self.metrics.fast_path_total.add(local_hits, &[KeyValue::new("route", "local")]);
+        self.metrics.fast_path_total.add(recipients.len() as u64 - local_hits, &[KeyValue::new("route", "pubsub")]);
+
+        if !remote.is_empty() {
+            let context = if self.event_context { context } else { EventContext::default() };
+            if let Err(e) = self.repo.publish_realtime(&remote, event, &context).await {
+                tracing::error!(error = %e, "Failed to batch publish to PubSub");
+                self.metrics.sends_total.add(remote.len() as u64, &[KeyValue::new("status", "error")]);
+            } else {
+                self.metrics.sends_total.add(remote.len() as u64, &[KeyValue::new("status", "sent")]);
+            }
         }
 
-        // Slow Path: Scheduled Push Fallback
-        if (event == UserEvent::MessageReceived || event == UserEvent::PreKeyLow)
-            && let Err(e) = self.repo.push_jobs(recipients, self.push_delay_secs).await
+        // Slow Path: Scheduled Push Fallback, collapsed by the provider per kind
+        if let Some(kind) = push
+            && let Err(e) = self.push_queue.push_jobs(recipients, self.push_delay_secs, kind).await
         {
             tracing::error!(error = %e, "Failed to batch schedule push notifications");
         }
     }
 
-    #[tracing::instrument(skip(self), fields(device.id = %device_id))]
+    /// Sends an event to every session on every instance.
+    ///
+    /// Local sessions are woken directly and again when the `PubSub` copy comes back, so
+    /// this is only suitable for events whose handling is idempotent.
+    #[tracing::instrument(skip_all, fields(event = ?event))]
+    pub async fn broadcast(&self, event: UserEvent) {
+        let delivered = self.deliver_all_local(event);
+        self.metrics.fast_path_total.add(delivered, &[KeyValue::new("route", "local")]);
+
+        if let Err(e) = self.repo.publish_broadcast(event).await {
+            tracing::error!(error = %e, "Failed to publish broadcast to PubSub");
+            self.metrics.sends_total.add(1, &[KeyValue::new("status", "error")]);
+        } else {
+            self.metrics.sends_total.add(1, &[KeyValue::new("status", "sent")]);
+        }
+    }
+
+    /// Wakes every session on this instance. Returns the number of sessions reached.
+    fn deliver_all_local(&self, event: UserEvent) -> u64 {
+        self.channels.iter().map(|entry| u64::from(self.post(entry.value(), event))).sum()
+    }
+
+    /// Wakes a session on this instance without going through `PubSub`.
+    /// Returns false if the device has no live local subscriber.
+    fn deliver_local(&self, device_id: Uuid, event: UserEvent) -> bool {
+        self.channels.get(&device_id).is_some_and(|mailbox| self.post(&mailbox, event))
+    }
+
+    /// Posts an event to a local mailbox. Returns false if it has no subscribers.
+    fn post(&self, mailbox: &Mailbox, event: UserEvent) -> bool {
+        let (reached, coalesced) = mailbox.post(event);
+        if coalesced > 0 {
+            self.metrics.coalesced_total.add(coalesced as u64, &[KeyValue::new("event", format!("{event:?}"))]);
+        }
+        reached > 0
+    }
+
+    #[tracing::instrument(skip_all, fields(device.id = %telemetry::id(device_id)))]
     pub async fn cancel_pending_notifications(&self, device_id: Uuid) {
-        if let Err(e) = self.repo.cancel_job(device_id).await {
+        if let Err(e) = self.push_queue.cancel_job(device_id).await {
             tracing::error!(error = %e, "Failed to cancel pending push notification");
         }
     }
@@ -165,7 +267,32 @@ impl NotificationService {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use tokio::sync::watch;
+    use crate::adapters::redis::NotificationRepository;
+    use crate::domain::notification::RealtimeNotification;
+    use std::sync::Mutex;
+    use tokio::sync::{broadcast, watch};
+
+    /// Records the context of every event published to other instances.
+    #[derive(Debug, Default)]
+    struct RecordingBus {
+        published: Mutex<Vec<EventContext>>,
+    }
+
+    #[async_trait::async_trait]
+    impl RealtimeBus for RecordingBus {
+        async fn publish_realtime(&self, _: &[Uuid], _: UserEvent, context: &EventContext) -> anyhow::Result<()> {
+            self.published.lock().expect("lock").push(*context);
+            Ok(())
+        }
+
+        async fn publish_broadcast(&self, _: UserEvent) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        async fn subscribe_realtime(&self) -> anyhow::Result<broadcast::Receiver<RealtimeNotification>> {
+            Ok(broadcast::channel(1).1)
+        }
+    }
 
     #[tokio::test]
     async fn test_run_cleanup_reclaims_stale_channels() {
@@ -180,17 +307,14 @@ mod tests {
                 .expect("Redis client creation");
 
         let repo = Arc::new(NotificationRepository::new(pubsub, &config));
-        let service = NotificationService::new(repo, &config);
+        let service = NotificationService::new(Arc::clone(&repo) as Arc<dyn RealtimeBus>, repo, &config);
 
         // 1. Setup channels
         let user_id_active = Uuid::new_v4();
         let user_id_stale = Uuid::new_v4();
 
-        let (tx_active, _rx_active) = broadcast::channel(10);
-        let (tx_stale, rx_stale) = broadcast::channel(10);
-
-        service.channels.insert(user_id_active, tx_active);
-        service.channels.insert(user_id_stale, tx_stale);
+        let _rx_active = service.subscribe(user_id_active).await;
+        let rx_stale = service.subscribe(user_id_stale).await;
 
         // 2. Make one stale by dropping its last receiver
         drop(rx_stale);
@@ -206,4 +330,55 @@ mod tests {
         assert!(service.channels.contains_key(&user_id_active), "Active channel should remain");
         assert!(!service.channels.contains_key(&user_id_stale), "Stale channel should be gone");
     }
+
+    #[tokio::test]
+    async fn test_notify_delivers_local_subscribers_in_process() {
+        crate::telemetry::init_test_telemetry();
+
+        let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+        let config = NotificationConfig::default();
+
+        let pubsub =
+            crate::adapters::redis::RedisClient::new(&crate::config::PubSubConfig::default(), 1024, shutdown_rx)
+                .await
+                .expect("Redis client creation");
+
+        let repo = Arc::new(NotificationRepository::new(pubsub, &config));
+        let service = NotificationService::new(Arc::clone(&repo) as Arc<dyn RealtimeBus>, repo, &config);
+
+        let device_id = Uuid::new_v4();
+        let mut rx = service.subscribe(device_id).await;
+
+        service.notify(&[device_id], UserEvent::MessageReceived).await;
+
+        // The event is already in the channel: no PubSub round trip was needed.
+        let event = tokio::time::timeout(std::time::Duration::ZERO, rx.recv()).await;
+        assert_eq!(event.expect("Event should be delivered in-process"), UserEvent::MessageReceived);
+        service.cancel_pending_notifications(device_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_event_context_is_only_published_when_enabled() {
+        crate::telemetry::init_test_telemetry();
+
+        let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+        let pubsub =
+            crate::adapters::redis::RedisClient::new(&crate::config::PubSubConfig::default(), 1024, shutdown_rx)
+                .await
+                .expect("Redis client creation");
+
+        for event_context in [false, true] {
+            let config = NotificationConfig { event_context, ..NotificationConfig::default() };
+            let bus = Arc::new(RecordingBus::default());
+            let push_queue = Arc::new(NotificationRepository::new(Arc::clone(&pubsub), &config));
+            let service = NotificationService::new(Arc::clone(&bus) as Arc<dyn RealtimeBus>, push_queue, &config);
+
+            let device_id = Uuid::new_v4();
+            service.notify_with_context(&[device_id], UserEvent::PreKeyLow, EventContext::with_count(3)).await;
+            service.cancel_pending_notifications(device_id).await;
+
+            let expected = if event_context { EventContext::with_count(3) } else { EventContext::default() };
+            assert_eq!(*bus.published.lock().expect("lock"), vec![expected]);
+        }
+    }
 }
@@ -1,4 +1,5 @@
 use crate::adapters::database::DbPool;
+use crate::adapters::database::instrumentation::TimedAcquire;
 use crate::adapters::database::push_token_repo::PushTokenRepository;
 use crate::error::Result;
 use uuid::Uuid;
@@ -21,7 +22,7 @@ impl PushTokenService {
     /// Returns an error if the database operation fails.
     pub async fn register_token(&self, device_id: Uuid, token: String) -> Result<()> {
         // Here we could add validation (e.g. token format checks)
-        let mut conn = self.pool.acquire().await?;
+        let mut conn = self.pool.acquire_timed().await?;
         self.repo.upsert_token(&mut conn, device_id, &token).await
     }
 }
@@ -1,15 +1,27 @@
+use crate::config::{RateLimitConfig, TierLimitTable, TierRateLimit};
+use crate::domain::user::UserTier;
+use crate::error::AppError;
 use axum::extract::ConnectInfo;
 use axum::http::{Request, StatusCode};
+use dashmap::DashMap;
 use ipnetwork::IpNetwork;
 use opentelemetry::{KeyValue, global, metrics::Counter};
-use std::net::{IpAddr, SocketAddr};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 use tower_governor::GovernorError;
 use tower_governor::key_extractor::KeyExtractor;
 use tracing::warn;
+use uuid::Uuid;
+
+/// Checks between sweeps of users whose budget has fully refilled.
+const USER_SWEEP_EVERY: u64 = 4096;
 
 #[derive(Clone, Debug)]
 pub struct Metrics {
     pub(crate) decisions_total: Counter<u64>,
+    pub(crate) user_throttled_total: Counter<u64>,
 }
 
 impl Metrics {
@@ -21,6 +33,10 @@ impl Metrics {
                 .u64_counter("obscura_rate_limit_decisions_total")
                 .with_description("Rate limit decisions (allowed/throttled)")
                 .build(),
+            user_throttled_total: meter
+                .u64_counter("obscura_rate_limit_user_throttled_total")
+                .with_description("Requests rejected by a user's tier rate limit, labelled by tier")
+                .build(),
         }
     }
 }
@@ -31,15 +47,39 @@ impl Default for Metrics {
     }
 }
 
+/// Keys rate limit buckets by client IP.
+///
+/// IPv6 clients are bucketed by network prefix rather than by address: a single subscriber
+/// is routinely handed a whole /64 (or more), so per-address keys would let them rotate
+/// source addresses for an effectively unlimited budget.
 #[derive(Clone, Debug)]
 pub struct IpKeyExtractor {
     pub(crate) trusted_proxies: Vec<IpNetwork>,
+    pub(crate) ipv6_prefix_len: u8,
 }
 
 impl IpKeyExtractor {
     #[must_use]
-    pub(crate) const fn new(trusted_proxies: Vec<IpNetwork>) -> Self {
-        Self { trusted_proxies }
+    pub(crate) const fn new(trusted_proxies: Vec<IpNetwork>, ipv6_prefix_len: u8) -> Self {
+        Self { trusted_proxies, ipv6_prefix_len }
+    }
+
+    /// Returns a copy of this extractor that aggregates IPv6 clients by a different prefix length.
+    #[must_use]
+    pub(crate) fn with_ipv6_prefix_len(&self, ipv6_prefix_len: u8) -> Self {
+        Self { trusted_proxies: self.trusted_proxies.clone(), ipv6_prefix_len }
+    }
+
+    /// Maps a client IP to its rate limit key. IPv4 addresses (including IPv4-mapped IPv6)
+    /// are keyed individually; other IPv6 addresses are truncated to the configured prefix.
+    #[must_use]
+    pub(crate) fn rate_limit_key(&self, ip: IpAddr) -> IpAddr {
+        match ip {
+            IpAddr::V4(_) => ip,
+            IpAddr::V6(v6) => {
+                v6.to_ipv4_mapped().map_or_else(|| IpAddr::V6(mask_ipv6(v6, self.ipv6_prefix_len)), IpAddr::V4)
+            }
+        }
     }
 
     #[must_use]
@@ -75,20 +115,80 @@ impl KeyExtractor for IpKeyExtractor {
             .map(|ConnectInfo(addr)| addr.ip())
             .ok_or(GovernorError::UnableToExtractKey)?;
 
-        Ok(self.identify_client_ip(req.headers(), peer_ip))
+        Ok(self.rate_limit_key(self.identify_client_ip(req.headers(), peer_ip)))
+    }
+}
+
+fn mask_ipv6(ip: Ipv6Addr, prefix_len: u8) -> Ipv6Addr {
+    let bits = u128::from(ip);
+    let mask = u128::MAX.checked_shl(128 - u32::from(prefix_len.min(128))).unwrap_or(0);
+    Ipv6Addr::from(bits & mask)
+}
+
+/// Per-user request budgets for the tiers that set a rate limit, tracked with GCRA: each user's
+/// entry is the theoretical arrival time of their next request, and a request is allowed while
+/// that lies no more than `burst - 1` intervals in the future.
+#[derive(Clone, Debug)]
+pub struct UserRateLimiter {
+    tiers: TierLimitTable,
+    arrivals: Arc<DashMap<Uuid, Instant>>,
+    checks: Arc<AtomicU64>,
+}
+
+impl UserRateLimiter {
+    #[must_use]
+    pub(crate) fn new(tiers: TierLimitTable) -> Self {
+        Self { tiers, arrivals: Arc::new(DashMap::new()), checks: Arc::new(AtomicU64::new(0)) }
+    }
+
+    /// Spends one request of `user_id`'s budget. Returns how long to wait if it is exhausted.
+    pub(crate) fn check(&self, user_id: Uuid, tier: UserTier) -> Result<(), Duration> {
+        let Some(limit) = self.tiers.get(tier).rate_limit else { return Ok(()) };
+        let now = Instant::now();
+        if self.checks.fetch_add(1, Ordering::Relaxed) % USER_SWEEP_EVERY == USER_SWEEP_EVERY - 1 {
+            self.arrivals.retain(|_, arrival| *arrival > now);
+        }
+
+        let mut arrival = self.arrivals.entry(user_id).or_insert(now);
+        *arrival = Self::spend(limit, (*arrival).max(now), now)?;
+        Ok(())
+    }
+
+    /// Admits a request at `now` given the theoretical `arrival` time, returning the next arrival
+    /// time, or how long to wait if the budget is exhausted.
+    fn spend(limit: TierRateLimit, arrival: Instant, now: Instant) -> Result<Instant, Duration> {
+        let interval = Duration::from_secs(1) / limit.per_second.max(1);
+        let tolerance = interval * limit.burst.max(1).saturating_sub(1);
+        let ahead = arrival.saturating_duration_since(now);
+        if ahead > tolerance { Err(ahead - tolerance) } else { Ok(arrival + interval) }
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct RateLimitService {
     pub extractor: IpKeyExtractor,
+    pub auth_extractor: IpKeyExtractor,
+    pub users: UserRateLimiter,
     pub metrics: Metrics,
 }
 
 impl RateLimitService {
     #[must_use]
-    pub fn new(trusted_proxies: Vec<IpNetwork>) -> Self {
-        Self { extractor: IpKeyExtractor::new(trusted_proxies), metrics: Metrics::new() }
+    pub fn new(trusted_proxies: Vec<IpNetwork>, config: &RateLimitConfig, tiers: TierLimitTable) -> Self {
+        let extractor = IpKeyExtractor::new(trusted_proxies, config.ipv6_prefix_len);
+        let auth_extractor = extractor.with_ipv6_prefix_len(config.auth_ipv6_prefix_len);
+        Self { extractor, auth_extractor, users: UserRateLimiter::new(tiers), metrics: Metrics::new() }
+    }
+
+    /// Enforces the rate limit of `user_id`'s tier, if it has one.
+    ///
+    /// # Errors
+    /// Returns `AppError::RateLimited` if the user has spent their budget.
+    pub(crate) fn check_user(&self, user_id: Uuid, tier: UserTier) -> Result<(), AppError> {
+        self.users.check(user_id, tier).map_err(|wait| {
+            self.metrics.user_throttled_total.add(1, &[KeyValue::new("tier", tier.as_str())]);
+            AppError::RateLimited { retry_after_secs: wait.as_secs().max(1) }
+        })
     }
 
     pub fn log_decision(&self, status: StatusCode, ratelimit_after: Option<String>) {
@@ -111,7 +211,7 @@ mod tests {
 
     fn extractor_with_trusted(cidrs: &[&str]) -> IpKeyExtractor {
         let trusted = cidrs.iter().map(|c| c.parse().expect("valid CIDR")).collect();
-        IpKeyExtractor::new(trusted)
+        IpKeyExtractor::new(trusted, 64)
     }
 
     #[test]
@@ -170,4 +270,82 @@ mod tests {
         let extractor = extractor_with_trusted(&["192.168.1.0/24"]);
         assert!(!extractor.is_trusted(&"10.0.0.1".parse().expect("valid IP")));
     }
+
+    #[test]
+    fn test_ipv4_key_is_the_address() {
+        let extractor = extractor_with_trusted(&[]);
+        let ip: IpAddr = "203.0.113.5".parse().expect("valid IP");
+        assert_eq!(extractor.rate_limit_key(ip), ip);
+    }
+
+    #[test]
+    fn test_ipv6_addresses_in_same_prefix_share_a_key() {
+        let extractor = extractor_with_trusted(&[]);
+        let a: IpAddr = "2001:db8:1:2:aaaa::1".parse().expect("valid IP");
+        let b: IpAddr = "2001:db8:1:2:ffff:ffff:ffff:ffff".parse().expect("valid IP");
+        let other: IpAddr = "2001:db8:1:3::1".parse().expect("valid IP");
+        let expected: IpAddr = "2001:db8:1:2::".parse().expect("valid IP");
+
+        assert_eq!(extractor.rate_limit_key(a), expected);
+        assert_eq!(extractor.rate_limit_key(b), expected);
+        assert_ne!(extractor.rate_limit_key(other), expected);
+    }
+
+    #[test]
+    fn test_auth_prefix_aggregates_wider() {
+        let extractor = extractor_with_trusted(&[]).with_ipv6_prefix_len(48);
+        let a: IpAddr = "2001:db8:1:2::1".parse().expect("valid IP");
+        let b: IpAddr = "2001:db8:1:ff::1".parse().expect("valid IP");
+        assert_eq!(extractor.rate_limit_key(a), extractor.rate_limit_key(b));
+    }
+
+    #[test]
+    fn test_ipv4_mapped_ipv6_keyed_as_ipv4() {
+        let extractor = extractor_with_trusted(&[]);
+        let mapped: IpAddr = "::ffff:203.0.113.5".parse().expect("valid IP");
+        let expected: IpAddr = "203.0.113.5".parse().expect("valid IP");
+        assert_eq!(extractor.rate_limit_key(mapped), expected);
+    }
+
+    fn tiers_with_paid_limit(per_second: u32, burst: u32) -> TierLimitTable {
+        let mut tiers = crate::config::Config::default().tier_limits();
+        tiers.paid.rate_limit = Some(TierRateLimit { per_second, burst });
+        tiers
+    }
+
+    #[test]
+    fn test_user_budget_allows_the_burst_then_waits_one_interval() {
+        let limit = TierRateLimit { per_second: 2, burst: 3 };
+        let now = Instant::now();
+        let mut arrival = now;
+        for _ in 0..3 {
+            arrival = UserRateLimiter::spend(limit, arrival, now).expect("burst request is allowed");
+        }
+
+        assert_eq!(UserRateLimiter::spend(limit, arrival, now), Err(Duration::from_millis(500)));
+
+        let later = now + Duration::from_millis(500);
+        assert!(UserRateLimiter::spend(limit, arrival, later).is_ok());
+    }
+
+    #[test]
+    fn test_user_limits_apply_per_tier_and_per_user() {
+        let limiter = UserRateLimiter::new(tiers_with_paid_limit(1, 1));
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+
+        assert!(limiter.check(alice, UserTier::Paid).is_ok());
+        assert!(limiter.check(alice, UserTier::Paid).is_err());
+        assert!(limiter.check(bob, UserTier::Paid).is_ok());
+        for _ in 0..10 {
+            assert!(limiter.check(alice, UserTier::Free).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_ipv6_prefix_edge_lengths() {
+        let ip: Ipv6Addr = "2001:db8::1".parse().expect("valid IP");
+        assert_eq!(mask_ipv6(ip, 128), ip);
+        assert_eq!(mask_ipv6(ip, 0), Ipv6Addr::UNSPECIFIED);
+        assert_eq!(mask_ipv6(ip, 200), ip);
+    }
 }
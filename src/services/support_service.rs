@@ -0,0 +1,223 @@
+use crate::adapters::database::DbPool;
+use crate::adapters::database::attachment_repo::AttachmentRepository;
+use crate::adapters::database::backup_repo::BackupRepository;
+use crate::adapters::database::device_repo::DeviceRepository;
+use crate::adapters::database::instrumentation::TimedAcquire;
+use crate::adapters::database::message_repo::MessageRepository;
+use crate::adapters::database::push_token_repo::PushTokenRepository;
+use crate::adapters::database::user_repo::UserRepository;
+use crate::domain::backup::Backup;
+use crate::domain::message::InboxSummary;
+use crate::domain::user::{User, UserTier};
+use crate::error::{AppError, Result};
+use crate::telemetry;
+use std::collections::{HashMap, HashSet};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 500;
+
+/// Everything support needs to reason about one device's delivery state.
+#[derive(Debug, Clone)]
+pub struct DeviceDiagnostics {
+    pub device_id: Uuid,
+    pub inbox: InboxSummary,
+    /// Live attachments the device uploaded.
+    pub attachments: i64,
+    pub push_token_registered: bool,
+    pub backup: Option<Backup>,
+}
+
+/// An account as listed to operators, without its credentials.
+#[derive(Debug, Clone)]
+pub struct UserListing {
+    pub user_id: Uuid,
+    pub username: String,
+    pub tier: UserTier,
+    pub created_at: Option<OffsetDateTime>,
+}
+
+/// A device's pending queue together with the account that owns it.
+#[derive(Debug, Clone)]
+pub struct InboxListing {
+    pub user_id: Uuid,
+    pub inbox: InboxSummary,
+}
+
+/// One page of a keyset-paginated listing. `next_cursor` is `None` on the last page.
+#[derive(Debug, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+/// Read-only inspection of a user's queues for the management API.
+///
+/// Only counts, timestamps and state are returned; message content, push tokens and
+/// key material never leave this service.
+#[derive(Clone, Debug)]
+pub struct SupportService {
+    pool: DbPool,
+    user_repo: UserRepository,
+    device_repo: DeviceRepository,
+    message_repo: MessageRepository,
+    backup_repo: BackupRepository,
+    push_token_repo: PushTokenRepository,
+    attachment_repo: AttachmentRepository,
+}
+
+impl SupportService {
+    #[must_use]
+    pub const fn new(
+        pool: DbPool,
+        user_repo: UserRepository,
+        device_repo: DeviceRepository,
+        message_repo: MessageRepository,
+        backup_repo: BackupRepository,
+        push_token_repo: PushTokenRepository,
+        attachment_repo: AttachmentRepository,
+    ) -> Self {
+        Self { pool, user_repo, device_repo, message_repo, backup_repo, push_token_repo, attachment_repo }
+    }
+
+    /// Summarizes the pending queue, uploaded attachments, push registration and backup state of
+    /// every device of a user.
+    ///
+    /// # Errors
+    /// Returns `AppError::NotFound` if the user has no devices.
+    /// Returns `AppError::Database` if a query fails.
+    #[tracing::instrument(skip_all, fields(user.id = %telemetry::id(user_id)), err(level = "warn"))]
+    pub async fn inspect_user(&self, user_id: Uuid) -> Result<Vec<DeviceDiagnostics>> {
+        let mut conn = self.pool.acquire_timed().await?;
+
+        let devices = self.device_repo.find_by_user(&mut conn, user_id).await?;
+        if devices.is_empty() {
+            return Err(AppError::NotFound);
+        }
+        let device_ids: Vec<Uuid> = devices.iter().map(|d| d.id).collect();
+
+        let mut inboxes: HashMap<Uuid, InboxSummary> = self
+            .message_repo
+            .summarize_inboxes(&mut conn, &device_ids)
+            .await?
+            .into_iter()
+            .map(|summary| (summary.device_id, summary))
+            .collect();
+
+        let attachments: HashMap<Uuid, i64> =
+            self.attachment_repo.count_by_device(&mut conn, &device_ids).await?.into_iter().collect();
+
+        let with_push: HashSet<Uuid> = self
+            .push_token_repo
+            .find_tokens_for_devices(&mut conn, &device_ids)
+            .await?
+            .into_iter()
+            .map(|(device_id, _)| device_id)
+            .collect();
+
+        let mut diagnostics = Vec::with_capacity(device_ids.len());
+        for device_id in device_ids {
+            let backup = self.backup_repo.find_by_device_id(&mut conn, device_id).await?;
+            let inbox = inboxes.remove(&device_id).unwrap_or(InboxSummary {
+                device_id,
+                pending_messages: 0,
+                pending_bytes: 0,
+                oldest_message_at: None,
+                next_expiry_at: None,
+            });
+            diagnostics.push(DeviceDiagnostics {
+                device_id,
+                inbox,
+                attachments: attachments.get(&device_id).copied().unwrap_or(0),
+                push_token_registered: with_push.contains(&device_id),
+                backup,
+            });
+        }
+
+        Ok(diagnostics)
+    }
+
+    /// Lists accounts in registration order, resuming after `cursor`.
+    ///
+    /// # Errors
+    /// Returns `AppError::BadRequest` if the cursor is malformed.
+    /// Returns `AppError::Database` if the query fails.
+    #[tracing::instrument(skip_all, fields(?cursor, ?limit), err(level = "warn"))]
+    pub async fn list_users(&self, cursor: Option<&str>, limit: Option<u32>) -> Result<Page<UserListing>> {
+        let after = cursor.map(parse_user_cursor).transpose()?;
+        let limit = page_size(limit);
+        let mut conn = self.pool.acquire_timed().await?;
+        let users = self.user_repo.list_after(&mut conn, after, fetch_size(limit)).await?;
+        Ok(user_page(users, limit))
+    }
+
+    /// Lists accounts newest registration first, resuming after `cursor`.
+    ///
+    /// # Errors
+    /// Returns `AppError::BadRequest` if the cursor is malformed.
+    /// Returns `AppError::Database` if the query fails.
+    #[tracing::instrument(skip_all, fields(?cursor, ?limit), err(level = "warn"))]
+    pub async fn list_recent_users(&self, cursor: Option<&str>, limit: Option<u32>) -> Result<Page<UserListing>> {
+        let before = cursor.map(parse_user_cursor).transpose()?;
+        let limit = page_size(limit);
+        let mut conn = self.pool.acquire_timed().await?;
+        let users = self.user_repo.list_before(&mut conn, before, fetch_size(limit)).await?;
+        Ok(user_page(users, limit))
+    }
+
+    /// Lists the devices with the largest pending queues, resuming after `cursor`. Each page is
+    /// measured when it is read, so the listing works against a read-only database.
+    ///
+    /// # Errors
+    /// Returns `AppError::BadRequest` if the cursor is malformed.
+    /// Returns `AppError::Database` if the query fails.
+    #[tracing::instrument(skip_all, fields(?cursor, ?limit), err(level = "warn"))]
+    pub async fn list_largest_inboxes(&self, cursor: Option<&str>, limit: Option<u32>) -> Result<Page<InboxListing>> {
+        let after = cursor.map(parse_inbox_cursor).transpose()?;
+        let limit = page_size(limit);
+        let mut conn = self.pool.acquire_timed().await?;
+        let mut rows = self.message_repo.largest_inboxes(&mut conn, after, fetch_size(limit)).await?;
+
+        let next_cursor = has_more(&mut rows, limit)
+            .then(|| rows.last().map(|(_, inbox)| format!("{}.{}", inbox.pending_messages, inbox.device_id)))
+            .flatten();
+        let items = rows.into_iter().map(|(user_id, inbox)| InboxListing { user_id, inbox }).collect();
+        Ok(Page { items, next_cursor })
+    }
+}
+
+fn page_size(limit: Option<u32>) -> usize {
+    limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE) as usize
+}
+
+/// One row beyond the page is fetched to learn whether another page follows.
+fn fetch_size(limit: usize) -> i64 {
+    i64::try_from(limit + 1).unwrap_or(i64::MAX)
+}
+
+fn has_more<T>(rows: &mut Vec<T>, limit: usize) -> bool {
+    let more = rows.len() > limit;
+    rows.truncate(limit);
+    more
+}
+
+fn user_page(mut users: Vec<User>, limit: usize) -> Page<UserListing> {
+    let next_cursor = has_more(&mut users, limit).then(|| users.last().map(|u| u.id.to_string())).flatten();
+    let items = users
+        .into_iter()
+        .map(|u| UserListing { user_id: u.id, username: u.username, tier: u.tier, created_at: u.created_at })
+        .collect();
+    Page { items, next_cursor }
+}
+
+fn parse_user_cursor(cursor: &str) -> Result<Uuid> {
+    Uuid::parse_str(cursor).map_err(|_| AppError::BadRequest("Invalid cursor".into()))
+}
+
+fn parse_inbox_cursor(cursor: &str) -> Result<(i64, Uuid)> {
+    cursor
+        .split_once('.')
+        .and_then(|(count, device_id)| Some((count.parse().ok()?, Uuid::parse_str(device_id).ok()?)))
+        .ok_or_else(|| AppError::BadRequest("Invalid cursor".into()))
+}
@@ -0,0 +1,92 @@
+use futures::future::BoxFuture;
+use opentelemetry::metrics::Counter;
+use std::future::Future;
+
+/// Cleans up after an upload that did not complete, most often because the request timed out and
+/// its future was dropped mid-transfer, instead of leaving the pending state to the cleanup
+/// workers.
+///
+/// Unless disarmed, dropping the guard spawns the cleanup and counts it on `abandoned_total`.
+#[must_use]
+pub(crate) struct UploadGuard {
+    cleanup: Option<BoxFuture<'static, ()>>,
+    abandoned_total: Counter<u64>,
+}
+
+impl std::fmt::Debug for UploadGuard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UploadGuard").field("armed", &self.cleanup.is_some()).finish_non_exhaustive()
+    }
+}
+
+impl UploadGuard {
+    pub(crate) fn new(abandoned_total: Counter<u64>, cleanup: impl Future<Output = ()> + Send + 'static) -> Self {
+        Self { cleanup: Some(Box::pin(cleanup)), abandoned_total }
+    }
+
+    /// The upload completed or was already cleaned up; nothing runs on drop.
+    pub(crate) fn disarm(mut self) {
+        self.cleanup = None;
+    }
+}
+
+impl Drop for UploadGuard {
+    fn drop(&mut self) {
+        let Some(cleanup) = self.cleanup.take() else {
+            return;
+        };
+        self.abandoned_total.add(1, &[]);
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn(cleanup);
+            }
+            Err(_) => tracing::warn!("Upload abandoned outside the runtime, leaving cleanup to the workers"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::global;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    fn guard(ran: &Arc<AtomicBool>) -> UploadGuard {
+        let counter = global::meter("obscura-server").u64_counter("test_upload_abandoned_total").build();
+        let ran = Arc::clone(ran);
+        UploadGuard::new(counter, async move { ran.store(true, Ordering::SeqCst) })
+    }
+
+    #[tokio::test]
+    async fn test_dropped_upload_is_cleaned_up() {
+        let ran = Arc::new(AtomicBool::new(false));
+        let upload = tokio::spawn({
+            let guard = guard(&ran);
+            async move {
+                let _guard = guard;
+                std::future::pending::<()>().await;
+            }
+        });
+
+        // Aborting drops the upload future mid-flight, like a request timeout does.
+        upload.abort();
+        let _ = upload.await;
+        let cleaned_up = tokio::time::timeout(Duration::from_secs(1), async {
+            while !ran.load(Ordering::SeqCst) {
+                tokio::task::yield_now().await;
+            }
+        });
+        assert!(cleaned_up.await.is_ok(), "Cleanup should run once the upload is dropped");
+    }
+
+    #[tokio::test]
+    async fn test_disarmed_guard_does_nothing() {
+        let ran = Arc::new(AtomicBool::new(false));
+        guard(&ran).disarm();
+
+        tokio::task::yield_now().await;
+        assert!(!ran.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,105 @@
+use crate::adapters::database::DbPool;
+use crate::adapters::database::attachment_repo::AttachmentRepository;
+use crate::adapters::database::backup_repo::BackupRepository;
+use crate::adapters::database::device_repo::DeviceRepository;
+use crate::adapters::database::instrumentation::TimedAcquire;
+use crate::adapters::database::key_repo::KeyRepository;
+use crate::adapters::database::message_repo::MessageRepository;
+use crate::adapters::key_value::KeyValueCache;
+use crate::domain::usage::StorageUsage;
+use crate::error::Result;
+use crate::telemetry;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+/// Reports how much storage each user holds, so clients can show it before limits are hit.
+///
+/// Results are cached per user for a short while, since the sums scan every device's rows.
+#[derive(Clone, Debug)]
+pub struct UsageService {
+    pool: DbPool,
+    device_repo: DeviceRepository,
+    attachment_repo: AttachmentRepository,
+    backup_repo: BackupRepository,
+    message_repo: MessageRepository,
+    key_repo: KeyRepository,
+    cache: Option<KeyValueCache>,
+}
+
+impl UsageService {
+    /// Creates the service. Without a `cache`, usage is computed on every request.
+    #[must_use]
+    pub const fn new(
+        pool: DbPool,
+        device_repo: DeviceRepository,
+        attachment_repo: AttachmentRepository,
+        backup_repo: BackupRepository,
+        message_repo: MessageRepository,
+        key_repo: KeyRepository,
+        cache: Option<KeyValueCache>,
+    ) -> Self {
+        Self { pool, device_repo, attachment_repo, backup_repo, message_repo, key_repo, cache }
+    }
+
+    /// Reports the storage a user holds, from the cache if it was computed recently.
+    /// A cache that cannot be reached is skipped rather than failing the request.
+    ///
+    /// # Errors
+    /// Returns `AppError::Database` if a query fails.
+    #[tracing::instrument(skip_all, fields(user.id = %telemetry::id(user_id)), err(level = "warn"))]
+    pub(crate) async fn usage(&self, user_id: Uuid) -> Result<StorageUsage> {
+        let key = user_id.to_string();
+        if let Some(cache) = &self.cache {
+            match cache.get(&key).await {
+                Ok(Some(bytes)) => match serde_json::from_slice(&bytes) {
+                    Ok(usage) => return Ok(usage),
+                    Err(e) => tracing::debug!(error = %e, "Ignoring unreadable cached storage usage"),
+                },
+                Ok(None) => {}
+                Err(e) => tracing::warn!(error = %e, "Failed to read cached storage usage"),
+            }
+        }
+
+        let usage = self.compute(user_id).await?;
+
+        if let Some(cache) = &self.cache
+            && let Ok(bytes) = serde_json::to_vec(&usage)
+            && let Err(e) = cache.set(&key, &bytes).await
+        {
+            tracing::warn!(error = %e, "Failed to cache storage usage");
+        }
+        Ok(usage)
+    }
+
+    async fn compute(&self, user_id: Uuid) -> Result<StorageUsage> {
+        let mut conn = self.pool.acquire_timed().await?;
+        let device_ids: Vec<Uuid> =
+            self.device_repo.find_by_user(&mut conn, user_id).await?.into_iter().map(|d| d.id).collect();
+
+        let attachment_bytes = self.attachment_repo.total_size_for_owner(&mut conn, user_id).await?;
+        let backup_bytes = self
+            .backup_repo
+            .find_by_device_ids(&mut conn, &device_ids)
+            .await?
+            .iter()
+            .filter(|b| b.current_version > 0)
+            .filter_map(|b| b.size_bytes)
+            .sum();
+        let pending_messages = self
+            .message_repo
+            .summarize_inboxes(&mut conn, &device_ids)
+            .await?
+            .iter()
+            .map(|inbox| inbox.pending_messages)
+            .sum();
+        let one_time_pre_keys = self.key_repo.count_one_time_pre_keys_for_devices(&mut conn, &device_ids).await?;
+
+        Ok(StorageUsage {
+            attachment_bytes,
+            backup_bytes,
+            pending_messages,
+            one_time_pre_keys,
+            computed_at: OffsetDateTime::now_utc().unix_timestamp(),
+        })
+    }
+}
@@ -1,19 +1,90 @@
-use crate::config::{LogFormat, TelemetryConfig};
+use crate::adapters::database::instrumentation::{QueryInstrumentationLayer, REPOSITORY_TARGET};
+use crate::api::access_log::ACCESS_LOG_TARGET;
+use crate::config::{AccessLogOutput, LogFormat, TelemetryConfig, TraceSamplingRule, UserIdMode};
+use anyhow::Context;
 use opentelemetry::logs::{AnyValue, LogRecord, Logger, LoggerProvider, Severity};
-use opentelemetry::{KeyValue, global};
+use opentelemetry::{KeyValue, Value, global};
 use opentelemetry_otlp::WithExportConfig;
 use opentelemetry_sdk::{
     Resource,
+    error::OTelSdkResult,
     logs::BatchLogProcessor,
     logs::SdkLoggerProvider,
     metrics::PeriodicReader,
     metrics::SdkMeterProvider,
     propagation::TraceContextPropagator,
-    trace::{BatchSpanProcessor, Sampler, SdkTracerProvider},
+    trace::{BatchSpanProcessor, Sampler, SdkTracerProvider, ShouldSample, SpanData, SpanExporter},
 };
 use opentelemetry_semantic_conventions::resource::{SERVICE_NAME, SERVICE_VERSION};
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+use std::time::Duration;
 use tracing_opentelemetry::OpenTelemetryLayer;
-use tracing_subscriber::{EnvFilter, Registry, layer::SubscriberExt, util::SubscriberInitExt};
+use tracing_subscriber::{
+    EnvFilter, Layer as _, Registry, filter::Targets, layer::SubscriberExt, util::SubscriberInitExt,
+};
+use uuid::Uuid;
+
+/// Domain separation for the user ID hash, so it cannot be matched against other digests of the secret.
+const USER_ID_HASH_LABEL: &[u8] = b"obscura-telemetry-user-id-v1";
+
+/// How identifiers and free-form strings are written to spans and logs, set once by
+/// `init_telemetry`. Until then IDs are written in full and strings are not capped.
+static ATTRIBUTE_POLICY: OnceLock<AttributePolicy> = OnceLock::new();
+
+#[derive(Debug)]
+struct AttributePolicy {
+    user_ids: UserIdMode,
+    hash_key: [u8; 32],
+    max_length: usize,
+}
+
+/// A user or device ID as it may appear in telemetry, following --telemetry-user-ids.
+#[derive(Clone, Copy, Debug)]
+pub struct TelemetryId(Uuid);
+
+impl std::fmt::Display for TelemetryId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Some(policy) = ATTRIBUTE_POLICY.get() else {
+            return std::fmt::Display::fmt(&self.0, f);
+        };
+        match policy.user_ids {
+            UserIdMode::Full => std::fmt::Display::fmt(&self.0, f),
+            UserIdMode::Truncate => f.write_str(&self.0.simple().to_string()[..8]),
+            UserIdMode::Hash => f.write_str(&hash_id(&policy.hash_key, self.0)),
+        }
+    }
+}
+
+/// Wraps a user or device ID for a span field or log event.
+///
+/// Instrumented functions use `skip_all`, so an ID reaches a span only through a field that
+/// wraps it here.
+#[must_use]
+pub const fn id(id: Uuid) -> TelemetryId {
+    TelemetryId(id)
+}
+
+/// Cuts a client-supplied string down to --telemetry-max-attribute-length bytes.
+#[must_use]
+pub fn bounded(value: &str) -> &str {
+    match ATTRIBUTE_POLICY.get() {
+        Some(policy) => truncate(value, policy.max_length),
+        None => value,
+    }
+}
+
+fn truncate(value: &str, max_length: usize) -> &str {
+    if max_length == 0 { value } else { &value[..value.floor_char_boundary(max_length)] }
+}
+
+/// Hashes an ID with a server-side key; 8 bytes keep collisions rare enough to follow one user.
+fn hash_id(key: &[u8; 32], id: Uuid) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(key);
+    hasher.update(id.as_bytes());
+    hex::encode(&hasher.finalize()[..8])
+}
 
 /// A guard that ensures OpenTelemetry providers are properly shut down and flushed when dropped.
 // ... (TelemetryGuard implementation remains the same)
@@ -48,23 +119,21 @@ impl TelemetryGuard {
 /// Initializes the OpenTelemetry tracing, metrics, and logging providers and hooks them into the tracing subscriber.
 ///
 /// # Errors
-/// Returns an error if any of the OTLP exporters fail to initialize.
+/// Returns an error if any of the OTLP exporters fail to initialize or the access log file cannot be opened.
 ///
 /// # Panics
 /// Panics if the default `EnvFilter` or tracing subscriber cannot be initialized.
-pub fn init_telemetry(config: &TelemetryConfig) -> anyhow::Result<TelemetryGuard> {
-    // 1. Build the Registry with EnvFilter
-    let filter = EnvFilter::try_from_default_env()
-        .unwrap_or_else(|_| "info".into())
-        .add_directive("sqlx=warn".parse().expect("Invalid directive for sqlx"))
-        .add_directive("tower_http=warn".parse().expect("Invalid directive for tower_http"))
-        .add_directive("hyper=warn".parse().expect("Invalid directive for hyper"))
-        .add_directive("opentelemetry=warn".parse().expect("Invalid directive for opentelemetry"))
-        .add_directive("opentelemetry_sdk=warn".parse().expect("Invalid directive for opentelemetry_sdk"));
-
-    let registry = Registry::default().with(filter);
-
-    // 2. Initialize OTLP Layers (Optional)
+pub fn init_telemetry(config: &TelemetryConfig, instance_id: &str, secret: &str) -> anyhow::Result<TelemetryGuard> {
+    let mut hasher = Sha256::new();
+    hasher.update(USER_ID_HASH_LABEL);
+    hasher.update(secret.as_bytes());
+    let _ = ATTRIBUTE_POLICY.set(AttributePolicy {
+        user_ids: config.user_ids,
+        hash_key: hasher.finalize().into(),
+        max_length: config.max_attribute_length,
+    });
+
+    // 1. Initialize OTLP Layers (Optional)
     let (otel_layer, logger_layer, guard) = if let Some(endpoint) = &config.otlp_endpoint
         && !endpoint.is_empty()
     {
@@ -76,6 +145,7 @@ pub fn init_telemetry(config: &TelemetryConfig) -> anyhow::Result<TelemetryGuard
             .with_attributes(vec![
                 KeyValue::new(SERVICE_NAME, service_name),
                 KeyValue::new(SERVICE_VERSION, service_version),
+                KeyValue::new("service.instance.id", instance_id.to_string()),
             ])
             .build();
 
@@ -83,85 +153,222 @@ pub fn init_telemetry(config: &TelemetryConfig) -> anyhow::Result<TelemetryGuard
         global::set_text_map_propagator(TraceContextPropagator::new());
 
         // Setup Tracing
-        let exporter = opentelemetry_otlp::SpanExporter::builder()
-            .with_tonic()
-            .with_endpoint(endpoint.clone())
-            .with_timeout(std::time::Duration::from_secs(config.export_timeout_secs))
-            .build()?;
-
-        let tracer_provider = SdkTracerProvider::builder()
-            .with_resource(resource.clone())
-            .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(config.trace_sampling_ratio))))
-            .with_span_processor(BatchSpanProcessor::builder(exporter).build())
-            .build();
+        // The provider is kept even when traces are not exported, so spans still carry IDs for log correlation.
+        let mut tracer_builder = SdkTracerProvider::builder().with_resource(resource.clone()).with_sampler(
+            Sampler::ParentBased(Box::new(RuleSampler::new(&config.trace_sampling_rules, config.trace_sampling_ratio))),
+        );
+        if config.otlp_traces_enabled {
+            let exporter = opentelemetry_otlp::SpanExporter::builder()
+                .with_tonic()
+                .with_endpoint(endpoint.clone())
+                .with_timeout(Duration::from_secs(config.export_timeout_secs))
+                .build()?;
+            let exporter = BoundedSpanExporter { inner: exporter, max_length: config.max_attribute_length };
+            tracer_builder = tracer_builder.with_span_processor(BatchSpanProcessor::builder(exporter).build());
+        }
+        let tracer_provider = tracer_builder.build();
 
         let tracer = opentelemetry::trace::TracerProvider::tracer(&tracer_provider, service_name);
         global::set_tracer_provider(tracer_provider.clone());
 
         // Setup Metrics
-        let exporter = opentelemetry_otlp::MetricExporter::builder()
-            .with_tonic()
-            .with_endpoint(endpoint.clone())
-            .with_timeout(std::time::Duration::from_secs(config.export_timeout_secs))
-            .build()?;
-
-        let reader = PeriodicReader::builder(exporter)
-            .with_interval(std::time::Duration::from_secs(config.metrics_export_interval_secs))
-            .build();
-        let meter_provider = SdkMeterProvider::builder().with_resource(resource.clone()).with_reader(reader).build();
-        global::set_meter_provider(meter_provider.clone());
+        let meter_provider = if config.otlp_metrics_enabled {
+            let exporter = opentelemetry_otlp::MetricExporter::builder()
+                .with_tonic()
+                .with_endpoint(endpoint.clone())
+                .with_timeout(Duration::from_secs(config.export_timeout_secs))
+                .build()?;
+
+            let reader = PeriodicReader::builder(exporter)
+                .with_interval(Duration::from_secs(config.metrics_export_interval_secs))
+                .build();
+            let meter_provider =
+                SdkMeterProvider::builder().with_resource(resource.clone()).with_reader(reader).build();
+            global::set_meter_provider(meter_provider.clone());
+            Some(meter_provider)
+        } else {
+            None
+        };
 
         // Setup Logging
-        let exporter = opentelemetry_otlp::LogExporter::builder()
-            .with_tonic()
-            .with_endpoint(endpoint.clone())
-            .with_timeout(std::time::Duration::from_secs(config.export_timeout_secs))
-            .build()?;
-
-        let logger_provider = SdkLoggerProvider::builder()
-            .with_resource(resource)
-            .with_log_processor(BatchLogProcessor::builder(exporter).build())
-            .build();
-
-        let logger = logger_provider.logger("obscura-server");
-        let layer = OtelLogLayer::new(logger);
-
-        let guard = TelemetryGuard {
-            tracer: Some(tracer_provider),
-            meter: Some(meter_provider),
-            logger: Some(logger_provider),
+        let (logger_provider, layer) = if config.otlp_logs_enabled {
+            let exporter = opentelemetry_otlp::LogExporter::builder()
+                .with_tonic()
+                .with_endpoint(endpoint.clone())
+                .with_timeout(Duration::from_secs(config.export_timeout_secs))
+                .build()?;
+
+            let logger_provider = SdkLoggerProvider::builder()
+                .with_resource(resource)
+                .with_log_processor(BatchLogProcessor::builder(exporter).build())
+                .build();
+
+            let logger = logger_provider.logger("obscura-server");
+            (Some(logger_provider), Some(OtelLogLayer::new(logger)))
+        } else {
+            (None, None)
         };
 
-        (Some(OpenTelemetryLayer::new(tracer)), Some(layer), guard)
+        let guard = TelemetryGuard { tracer: Some(tracer_provider), meter: meter_provider, logger: logger_provider };
+
+        (Some(OpenTelemetryLayer::new(tracer)), layer, guard)
     } else {
         let guard = TelemetryGuard { tracer: None, meter: None, logger: None };
         (None, None, guard)
     };
 
-    // 3. Compose Layers
-    let registry = registry.with(otel_layer).with(logger_layer);
+    // 2. Compose Layers
+    // The EnvFilter is applied per layer rather than globally so that the query instrumentation
+    // still sees the debug-level repository spans when the log level is `info`.
+    let query_layer = QueryInstrumentationLayer::new(Duration::from_millis(config.slow_query_threshold_ms))
+        .with_filter(Targets::new().with_target(REPOSITORY_TARGET, tracing::Level::DEBUG));
+    // Access records bypass `RUST_LOG` and always use JSON, whatever the regular log format is.
+    let access_filter = || Targets::new().with_target(ACCESS_LOG_TARGET, tracing::Level::INFO);
+    let access_json =
+        || tracing_subscriber::fmt::layer().json().flatten_event(true).with_current_span(false).with_span_list(false);
+    let (access_stdout_layer, access_file_layer) = match config.access_log {
+        AccessLogOutput::Off => (None, None),
+        AccessLogOutput::Stdout => {
+            (Some(access_json().with_writer(std::io::stdout).with_filter(access_filter())), None)
+        }
+        AccessLogOutput::File => {
+            let path = config
+                .access_log_file
+                .as_deref()
+                .context("--telemetry-access-log-file is required when the access log output is file")?;
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Failed to open access log file {path}"))?;
+            (None, Some(access_json().with_writer(std::sync::Mutex::new(file)).with_filter(access_filter())))
+        }
+    };
+
+    let registry = Registry::default()
+        .with(query_layer)
+        .with(access_stdout_layer)
+        .with(access_file_layer)
+        .with(otel_layer.with_filter(env_filter()))
+        .with(logger_layer.with_filter(env_filter()));
 
     match config.log_format {
         LogFormat::Text => {
-            registry.with(tracing_subscriber::fmt::layer()).init();
+            registry.with(tracing_subscriber::fmt::layer().with_filter(env_filter())).init();
         }
         LogFormat::Json => {
-            registry.with(tracing_subscriber::fmt::layer().json()).init();
+            registry.with(tracing_subscriber::fmt::layer().json().with_filter(env_filter())).init();
         }
     }
 
     Ok(guard)
 }
 
+/// Builds the log filter from `RUST_LOG`, defaulting to `info` and quieting noisy dependencies.
+/// Access log records are always excluded; they have their own writer.
+///
+/// # Panics
+/// Panics if one of the built-in directives fails to parse.
+fn env_filter() -> EnvFilter {
+    EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| "info".into())
+        .add_directive("sqlx=warn".parse().expect("Invalid directive for sqlx"))
+        .add_directive("tower_http=warn".parse().expect("Invalid directive for tower_http"))
+        .add_directive("hyper=warn".parse().expect("Invalid directive for hyper"))
+        .add_directive("opentelemetry=warn".parse().expect("Invalid directive for opentelemetry"))
+        .add_directive("opentelemetry_sdk=warn".parse().expect("Invalid directive for opentelemetry_sdk"))
+        .add_directive(format!("{ACCESS_LOG_TARGET}=off").parse().expect("Invalid directive for the access log"))
+}
+
 /// Initializes a no-op telemetry provider for tests to silence warnings.
 pub fn init_test_telemetry() {
     let provider = SdkMeterProvider::builder().build();
     global::set_meter_provider(provider);
 }
 
+/// Samples new traces with the ratio of the first rule matching the root span, falling back to the
+/// global ratio. Only sees the attributes recorded when the span is created, such as `http.route`.
+#[derive(Clone, Debug)]
+struct RuleSampler {
+    rules: Vec<(TraceSamplingRule, Sampler)>,
+    default: Sampler,
+}
+
+impl RuleSampler {
+    fn new(rules: &[TraceSamplingRule], default_ratio: f64) -> Self {
+        Self {
+            rules: rules.iter().map(|rule| (rule.clone(), Sampler::TraceIdRatioBased(rule.ratio))).collect(),
+            default: Sampler::TraceIdRatioBased(default_ratio),
+        }
+    }
+}
+
+impl ShouldSample for RuleSampler {
+    fn should_sample(
+        &self,
+        parent_context: Option<&opentelemetry::Context>,
+        trace_id: opentelemetry::trace::TraceId,
+        name: &str,
+        span_kind: &opentelemetry::trace::SpanKind,
+        attributes: &[KeyValue],
+        links: &[opentelemetry::trace::Link],
+    ) -> opentelemetry::trace::SamplingResult {
+        let route = attributes.iter().find(|kv| kv.key.as_str() == "http.route").map(|kv| kv.value.as_str());
+        let sampler = self
+            .rules
+            .iter()
+            .find(|(rule, _)| rule.matches(name, route.as_deref()))
+            .map_or(&self.default, |(_, sampler)| sampler);
+        sampler.should_sample(parent_context, trace_id, name, span_kind, attributes, links)
+    }
+}
+
+/// Caps the string attributes of exported spans and their events at
+/// --telemetry-max-attribute-length, whichever field they were recorded from.
+#[derive(Debug)]
+struct BoundedSpanExporter<E> {
+    inner: E,
+    max_length: usize,
+}
+
+impl<E: SpanExporter> SpanExporter for BoundedSpanExporter<E> {
+    async fn export(&self, mut batch: Vec<SpanData>) -> OTelSdkResult {
+        for span in &mut batch {
+            bound_attributes(&mut span.attributes, self.max_length);
+            for event in &mut span.events.events {
+                bound_attributes(&mut event.attributes, self.max_length);
+            }
+        }
+        self.inner.export(batch).await
+    }
+
+    fn shutdown_with_timeout(&mut self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn force_flush(&mut self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+fn bound_attributes(attributes: &mut [KeyValue], max_length: usize) {
+    for attribute in attributes {
+        if let Value::String(value) = &attribute.value
+            && truncate(value.as_str(), max_length).len() < value.as_str().len()
+        {
+            attribute.value = Value::String(truncate(value.as_str(), max_length).to_string().into());
+        }
+    }
+}
+
 /// A custom tracing layer that bridges tracing events to OpenTelemetry logs.
 /// It specifically handles the "empty message" issue by promoting the 'error' field
 /// to the log body if the message is empty (common when using #[instrument(err)]).
+/// Field values are capped at --telemetry-max-attribute-length.
 struct OtelLogLayer<L: Logger> {
     logger: L,
 }
@@ -241,9 +448,9 @@ impl tracing::field::Visit for OtelLogVisitor {
         if name == "message" {
             self.message = val;
         } else if name == "error" {
-            self.error = val;
+            self.error = bounded(&val).to_string();
         } else {
-            self.attributes.push((name.to_string(), val));
+            self.attributes.push((name.to_string(), bounded(&val).to_string()));
         }
     }
 
@@ -252,9 +459,41 @@ impl tracing::field::Visit for OtelLogVisitor {
         if name == "message" {
             self.message = value.to_string();
         } else if name == "error" {
-            self.error = value.to_string();
+            self.error = bounded(value).to_string();
         } else {
-            self.attributes.push((name.to_string(), value.to_string()));
+            self.attributes.push((name.to_string(), bounded(value).to_string()));
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate_keeps_char_boundaries() {
+        assert_eq!(truncate("abcdef", 4), "abcd");
+        assert_eq!(truncate("abc", 4), "abc");
+        assert_eq!(truncate("abcdef", 0), "abcdef");
+        assert_eq!(truncate("aé", 2), "a");
+    }
+
+    #[test]
+    fn test_hash_id_is_keyed_and_stable() {
+        let id = Uuid::new_v4();
+        let (a, b) = ([1; 32], [2; 32]);
+
+        assert_eq!(hash_id(&a, id), hash_id(&a, id));
+        assert_ne!(hash_id(&a, id), hash_id(&b, id));
+        assert_ne!(hash_id(&a, id), hash_id(&a, Uuid::new_v4()));
+        assert_eq!(hash_id(&a, id).len(), 16);
+    }
+
+    #[test]
+    fn test_bound_attributes_cuts_strings_only() {
+        let mut attributes = vec![KeyValue::new("request.id", "x".repeat(300)), KeyValue::new("count", 12_345_678_i64)];
+        bound_attributes(&mut attributes, 256);
+        assert_eq!(attributes[0].value.as_str().len(), 256);
+        assert_eq!(attributes[1].value, Value::I64(12_345_678));
+    }
+}
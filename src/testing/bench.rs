@@ -0,0 +1,116 @@
+//! Entry points into the services for the benches in `benches/`, which run against the same
+//! containers as the integration tests but cannot reach the crate-private service methods.
+
+use crate::adapters::database::key_repo::KeyRepository;
+use crate::domain::crypto::PublicKey;
+use crate::domain::keys::OneTimePreKey;
+use crate::domain::message::RawSubmission;
+use crate::services::gateway::Metrics;
+use crate::services::gateway::ack_batcher::AckBatcher;
+use crate::testing::{TestApp, TestUser, generate_username};
+use futures::future::join_all;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// How often `flush_acks` checks whether the batch has been deleted.
+const FLUSH_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// A test app with a sender and a recipient, and direct handles on the services under bench.
+#[derive(Debug)]
+pub struct BenchApp {
+    pub app: TestApp,
+    pub sender: TestUser,
+    pub recipient: TestUser,
+    next_key_id: AtomicI32,
+}
+
+impl BenchApp {
+    /// Starts a server with [`crate::testing::get_test_config`] and registers the two users.
+    pub async fn spawn() -> Self {
+        let app = TestApp::spawn().await;
+        let sender = app.register_user(&generate_username("bench_sender")).await;
+        let recipient = app.register_user(&generate_username("bench_recipient")).await;
+        Self { app, sender, recipient, next_key_id: AtomicI32::new(1_000) }
+    }
+
+    /// Queues `count` messages of `payload` from the sender to the recipient through
+    /// `MessageService::send`, returning the IDs of the queued messages.
+    pub async fn send_batch(&self, count: usize, payload: &[u8]) -> Vec<Uuid> {
+        let payload = bytes::Bytes::copy_from_slice(payload);
+        let submissions = (0..count)
+            .map(|_| RawSubmission {
+                submission_id: Uuid::new_v4().as_bytes().to_vec(),
+                device_id: self.recipient.device_id.as_bytes().to_vec(),
+                message: payload.clone(),
+            })
+            .collect();
+
+        let outcome =
+            self.app.message_service.send(self.sender.user_id, self.sender.device_id, submissions).await.unwrap();
+        assert!(outcome.failed_submissions.is_empty(), "Bench submissions were rejected");
+        outcome.receipts.into_iter().map(|r| r.message_id).collect()
+    }
+
+    /// Fetches up to `limit` of the recipient's pending messages, returning how many there were.
+    pub async fn fetch_batch(&self, limit: i64) -> usize {
+        self.app.message_service.fetch_pending_batch(self.recipient.device_id, None, limit).await.unwrap().len()
+    }
+
+    /// Acknowledges `message_ids` through an `AckBatcher` sized to flush them as one batch, and
+    /// waits until they are deleted.
+    pub async fn flush_acks(&self, message_ids: Vec<Uuid>) {
+        let count = message_ids.len();
+        let batcher = AckBatcher::new(
+            self.recipient.device_id,
+            self.app.message_service.clone(),
+            None,
+            Metrics::new(),
+            count.max(1),
+            count.max(1),
+            60_000,
+        );
+        batcher.push(message_ids.clone());
+
+        loop {
+            let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM messages WHERE id = ANY($1)")
+                .bind(&message_ids)
+                .fetch_one(&self.app.pool)
+                .await
+                .unwrap();
+            if remaining == 0 {
+                return;
+            }
+            tokio::time::sleep(FLUSH_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Uploads `count` more one-time pre-keys for the recipient. The keys are random bytes, since
+    /// fetching a bundle never checks them.
+    pub async fn add_pre_keys(&self, count: usize) {
+        let keys: Vec<OneTimePreKey> = (0..count)
+            .map(|_| {
+                let mut bytes = [0u8; 33];
+                bytes[0] = 0x05;
+                bytes[1..].copy_from_slice(&rand::random::<[u8; 32]>());
+                OneTimePreKey {
+                    key_id: self.next_key_id.fetch_add(1, Ordering::Relaxed),
+                    public_key: PublicKey::new(bytes),
+                }
+            })
+            .collect();
+
+        let mut conn = self.app.pool.acquire().await.unwrap();
+        KeyRepository::new().insert_one_time_pre_keys(&mut conn, self.recipient.device_id, &keys).await.unwrap();
+    }
+
+    /// Fetches the recipient's pre-key bundle `concurrency` times at once, each consuming a
+    /// one-time pre-key, so the fetches contend for the same rows.
+    pub async fn fetch_bundles_concurrently(&self, concurrency: usize) {
+        let fetches =
+            (0..concurrency).map(|_| self.app.key_service.get_pre_key_bundles_for_user(self.recipient.user_id, false));
+        for result in join_all(fetches).await {
+            result.unwrap();
+        }
+    }
+}
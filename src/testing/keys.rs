@@ -0,0 +1,129 @@
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use rand::Rng;
+use serde_json::json;
+use xeddsa::xed25519::PrivateKey;
+use xeddsa::{CalculateKeyPair, Sign};
+
+/// Random private key bytes for an XEd25519 identity or pre-key.
+pub fn generate_signing_key() -> [u8; 32] {
+    let mut rng = rand::rng();
+    let mut bytes = [0u8; 32];
+    rng.fill_bytes(&mut bytes);
+    bytes
+}
+
+/// A signed pre-key in wire format and its signature by the identity key.
+pub fn generate_signed_pre_key(identity_key_bytes: &[u8; 32]) -> (Vec<u8>, Vec<u8>) {
+    // Generate SPK
+    let spk_bytes = generate_signing_key();
+    let spk_priv = PrivateKey(spk_bytes);
+    let (_, spk_pub_ed) = spk_priv.calculate_key_pair(0);
+    // Convert to Montgomery and add prefix
+    let spk_pub_mont =
+        curve25519_dalek::edwards::CompressedEdwardsY(spk_pub_ed).decompress().unwrap().to_montgomery().to_bytes();
+    let mut spk_pub_wire = [0u8; 33];
+    spk_pub_wire[0] = 0x05;
+    spk_pub_wire[1..].copy_from_slice(&spk_pub_mont);
+
+    // Sign SPK pub key (33-byte wire format) with Identity Key
+    let ik_priv = PrivateKey(*identity_key_bytes);
+    let mut rng = rand::rng();
+    let signature: [u8; 64] = ik_priv.sign(&spk_pub_wire, &mut rng);
+
+    (spk_pub_wire.to_vec(), signature.to_vec())
+}
+
+/// A legacy registration payload carrying both credentials and keys, with the identity private key.
+pub fn generate_registration_payload(
+    username: &str,
+    password: &str,
+    reg_id: u32,
+    otpk_count: usize,
+) -> (serde_json::Value, [u8; 32]) {
+    let identity_key = generate_signing_key();
+    let ik_priv = PrivateKey(identity_key);
+    let (_, ik_pub_ed) = ik_priv.calculate_key_pair(0);
+    let ik_pub_mont =
+        curve25519_dalek::edwards::CompressedEdwardsY(ik_pub_ed).decompress().unwrap().to_montgomery().to_bytes();
+    let mut ik_pub_wire = [0u8; 33];
+    ik_pub_wire[0] = 0x05;
+    ik_pub_wire[1..].copy_from_slice(&ik_pub_mont);
+
+    let (spk_pub, spk_sig) = generate_signed_pre_key(&identity_key);
+
+    let mut otpk = Vec::new();
+    for i in 0..otpk_count {
+        let key_bytes = generate_signing_key();
+        let key_priv = PrivateKey(key_bytes);
+        let (_, key_pub_ed) = key_priv.calculate_key_pair(0);
+        let key_pub_mont =
+            curve25519_dalek::edwards::CompressedEdwardsY(key_pub_ed).decompress().unwrap().to_montgomery().to_bytes();
+        let mut key_pub_wire = [0u8; 33];
+        key_pub_wire[0] = 0x05;
+        key_pub_wire[1..].copy_from_slice(&key_pub_mont);
+
+        otpk.push(json!({
+            "keyId": i,
+            "publicKey": STANDARD.encode(key_pub_wire)
+        }));
+    }
+
+    let payload = json!({
+        "username": username,
+        "password": password,
+        "registrationId": reg_id,
+        "identityKey": STANDARD.encode(ik_pub_wire),
+        "signedPreKey": {
+            "keyId": 1,
+            "publicKey": STANDARD.encode(&spk_pub),
+            "signature": STANDARD.encode(&spk_sig)
+        },
+        "oneTimePreKeys": otpk
+    });
+
+    (payload, identity_key)
+}
+
+/// Generates a device creation payload (keys without username/password) for POST /v1/devices.
+pub fn generate_device_payload(reg_id: u32, otpk_count: usize) -> (serde_json::Value, [u8; 32]) {
+    let identity_key = generate_signing_key();
+    let ik_priv = PrivateKey(identity_key);
+    let (_, ik_pub_ed) = ik_priv.calculate_key_pair(0);
+    let ik_pub_mont =
+        curve25519_dalek::edwards::CompressedEdwardsY(ik_pub_ed).decompress().unwrap().to_montgomery().to_bytes();
+    let mut ik_pub_wire = [0u8; 33];
+    ik_pub_wire[0] = 0x05;
+    ik_pub_wire[1..].copy_from_slice(&ik_pub_mont);
+
+    let (spk_pub, spk_sig) = generate_signed_pre_key(&identity_key);
+
+    let mut otpk = Vec::new();
+    for i in 0..otpk_count {
+        let key_bytes = generate_signing_key();
+        let key_priv = PrivateKey(key_bytes);
+        let (_, key_pub_ed) = key_priv.calculate_key_pair(0);
+        let key_pub_mont =
+            curve25519_dalek::edwards::CompressedEdwardsY(key_pub_ed).decompress().unwrap().to_montgomery().to_bytes();
+        let mut key_pub_wire = [0u8; 33];
+        key_pub_wire[0] = 0x05;
+        key_pub_wire[1..].copy_from_slice(&key_pub_mont);
+
+        otpk.push(json!({
+            "keyId": i,
+            "publicKey": STANDARD.encode(key_pub_wire)
+        }));
+    }
+
+    let payload = json!({
+        "registrationId": reg_id,
+        "identityKey": STANDARD.encode(ik_pub_wire),
+        "signedPreKey": {
+            "keyId": 1,
+            "publicKey": STANDARD.encode(&spk_pub),
+            "signature": STANDARD.encode(&spk_sig)
+        },
+        "oneTimePreKeys": otpk
+    });
+
+    (payload, identity_key)
+}
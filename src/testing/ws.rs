@@ -0,0 +1,83 @@
+use crate::proto::obscura::v1 as proto;
+use futures::SinkExt;
+use prost::Message as ProstMessage;
+use std::time::Duration;
+use tokio_tungstenite::{WebSocketStream, tungstenite::protocol::Message};
+
+/// A gateway connection whose incoming frames are sorted into a channel per kind.
+///
+/// Every frame, including those also delivered to a typed channel, is copied to `rx_raw`.
+#[derive(Debug)]
+pub struct TestWsClient {
+    pub sink:
+        futures::stream::SplitSink<WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>, Message>,
+    pub rx_env: tokio::sync::mpsc::UnboundedReceiver<proto::Envelope>,
+    pub rx_status: tokio::sync::mpsc::UnboundedReceiver<proto::PreKeyStatus>,
+    pub rx_announcement: tokio::sync::mpsc::UnboundedReceiver<proto::SignedAnnouncement>,
+    pub rx_pong: tokio::sync::mpsc::UnboundedReceiver<tokio_tungstenite::tungstenite::Bytes>,
+    pub rx_raw: tokio::sync::mpsc::UnboundedReceiver<Result<Message, tokio_tungstenite::tungstenite::Error>>,
+}
+
+impl TestWsClient {
+    pub async fn receive_pong(&mut self) -> Option<Vec<u8>> {
+        tokio::time::timeout(Duration::from_secs(5), self.rx_pong.recv()).await.ok().flatten().map(|b| b.to_vec())
+    }
+
+    pub async fn receive_envelope(&mut self) -> Option<proto::Envelope> {
+        self.receive_envelope_timeout(Duration::from_secs(5)).await
+    }
+
+    pub async fn receive_envelope_timeout(&mut self, timeout: Duration) -> Option<proto::Envelope> {
+        tokio::time::timeout(timeout, self.rx_env.recv()).await.ok().flatten()
+    }
+
+    pub async fn receive_prekey_status(&mut self) -> Option<proto::PreKeyStatus> {
+        self.receive_prekey_status_timeout(Duration::from_secs(5)).await
+    }
+
+    pub async fn receive_prekey_status_timeout(&mut self, timeout: Duration) -> Option<proto::PreKeyStatus> {
+        tokio::time::timeout(timeout, self.rx_status.recv()).await.ok().flatten()
+    }
+
+    pub async fn receive_announcement_timeout(&mut self, timeout: Duration) -> Option<proto::SignedAnnouncement> {
+        tokio::time::timeout(timeout, self.rx_announcement.recv()).await.ok().flatten()
+    }
+
+    pub async fn receive_raw_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Option<Result<Message, tokio_tungstenite::tungstenite::Error>> {
+        tokio::time::timeout(timeout, self.rx_raw.recv()).await.ok().flatten()
+    }
+
+    pub async fn send_ack(&mut self, message_id: Vec<u8>) {
+        let ack = proto::AckMessage { message_ids: vec![message_id] };
+        let frame = proto::WebSocketFrame { payload: Some(proto::web_socket_frame::Payload::Ack(ack)) };
+        let mut buf = Vec::new();
+        frame.encode(&mut buf).unwrap();
+        self.sink.send(Message::Binary(buf.into())).await.unwrap();
+    }
+
+    pub async fn send_credit(&mut self, envelopes: u32) {
+        let credit = proto::Credit { envelopes };
+        let frame = proto::WebSocketFrame { payload: Some(proto::web_socket_frame::Payload::Credit(credit)) };
+        let mut buf = Vec::new();
+        frame.encode(&mut buf).unwrap();
+        self.sink.send(Message::Binary(buf.into())).await.unwrap();
+    }
+
+    pub async fn send_auth(&mut self, token: &str) {
+        let auth = proto::Auth { token: token.to_string() };
+        let frame = proto::WebSocketFrame { payload: Some(proto::web_socket_frame::Payload::Auth(auth)) };
+        let mut buf = Vec::new();
+        frame.encode(&mut buf).unwrap();
+        self.sink.send(Message::Binary(buf.into())).await.unwrap();
+    }
+
+    /// Sends a ping and waits for a pong to ensure the session is fully established
+    /// and any initial processing (like the first message poll) is complete.
+    pub async fn ensure_subscribed(&mut self) {
+        self.sink.send(Message::Ping(vec![1].into())).await.unwrap();
+        self.receive_pong().await.expect("Session did not become ready in time");
+    }
+}
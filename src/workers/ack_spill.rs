@@ -0,0 +1,119 @@
+use crate::adapters::database::DbPool;
+use crate::adapters::database::instrumentation::TimedAcquire;
+use crate::adapters::database::message_repo::MessageRepository;
+use crate::adapters::redis::AckSpill;
+use crate::adapters::redis::ack_spill::SpilledAck;
+use opentelemetry::{
+    global,
+    metrics::{Counter, Gauge},
+};
+use std::time::Duration;
+use tracing::Instrument;
+
+/// Spilled batches taken off the list per round trip.
+const SPILL_TAKE_BATCH: usize = 100;
+
+#[derive(Clone, Debug)]
+struct Metrics {
+    replayed_total: Counter<u64>,
+    backlog: Gauge<u64>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let meter = global::meter("obscura-server");
+        Self {
+            replayed_total: meter
+                .u64_counter("obscura_ack_spill_replayed_total")
+                .with_description("Spilled ACK batches whose messages were deleted on a later attempt")
+                .build(),
+            backlog: meter
+                .u64_gauge("obscura_ack_spill_backlog")
+                .with_description("Spilled ACK batches waiting to be deleted")
+                .build(),
+        }
+    }
+}
+
+/// Deletes the acknowledged messages whose delete failed in the gateway and was spilled to
+/// Redis. A batch that fails again is requeued with the rest of those taken, ahead of newer
+/// spills, and the round ends, since the database is most likely still unavailable.
+#[derive(Debug)]
+pub struct AckSpillWorker {
+    pool: DbPool,
+    repo: MessageRepository,
+    spill: Option<AckSpill>,
+    interval_secs: u64,
+    metrics: Metrics,
+}
+
+impl AckSpillWorker {
+    /// `spill` is `None` when Redis is not used, and nothing is ever spilled.
+    #[must_use]
+    pub fn new(pool: DbPool, repo: MessageRepository, spill: Option<AckSpill>, interval_secs: u64) -> Self {
+        Self { pool, repo, spill, interval_secs, metrics: Metrics::new() }
+    }
+
+    pub async fn run(self, mut shutdown: tokio::sync::watch::Receiver<bool>) {
+        if self.interval_secs == 0 {
+            tracing::info!("ACK spill replay is disabled (interval = 0)");
+            return;
+        }
+        if self.spill.is_none() {
+            tracing::info!("ACK spill replay is disabled (Redis is not used)");
+            return;
+        }
+
+        let mut interval = tokio::time::interval(Duration::from_secs(self.interval_secs));
+
+        while !*shutdown.borrow() {
+            tokio::select! {
+                _ = interval.tick() => {
+                    if let Err(e) = self.replay()
+                        .instrument(tracing::debug_span!("run_ack_spill_replay"))
+                        .await
+                    {
+                        tracing::error!(error = %e, "ACK spill replay failed");
+                    }
+                }
+                _ = shutdown.changed() => {}
+            }
+        }
+        tracing::info!("ACK spill replay loop shutting down...");
+    }
+
+    /// Deletes spilled batches until the list is empty or a delete fails.
+    ///
+    /// # Errors
+    /// Returns an error if Redis cannot be read, or if a failed batch cannot be put back.
+    pub async fn replay(&self) -> anyhow::Result<()> {
+        let Some(spill) = &self.spill else { return Ok(()) };
+        loop {
+            let batches = spill.take(SPILL_TAKE_BATCH).await?;
+            if batches.is_empty() {
+                break;
+            }
+
+            for (done, batch) in batches.iter().enumerate() {
+                if let Err(e) = self.delete(batch).await {
+                    tracing::warn!(error = %e, "Spilled ACK batch still cannot be deleted, retrying later");
+                    spill.requeue(&batches[done..]).await?;
+                    return Self::record_backlog(spill, &self.metrics).await;
+                }
+                self.metrics.replayed_total.add(1, &[]);
+            }
+        }
+        Self::record_backlog(spill, &self.metrics).await
+    }
+
+    async fn delete(&self, batch: &SpilledAck) -> crate::error::Result<()> {
+        let mut conn = self.pool.acquire_timed().await?;
+        self.repo.acknowledge_batch(&mut conn, batch.device_id, &batch.message_ids).await
+    }
+
+    async fn record_backlog(spill: &AckSpill, metrics: &Metrics) -> anyhow::Result<()> {
+        let backlog = spill.len().await?;
+        metrics.backlog.record(backlog as u64, &[]);
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+use crate::adapters::database::DbPool;
+use crate::adapters::database::announcement_repo::AnnouncementRepository;
+use crate::adapters::database::instrumentation::TimedAcquire;
+use crate::config::AnnouncementConfig;
+use crate::error::Result;
+use opentelemetry::{KeyValue, global, metrics::Counter};
+use std::time::Duration as StdDuration;
+use time::{Duration, OffsetDateTime};
+use tracing::Instrument;
+
+#[derive(Clone, Debug)]
+struct Metrics {
+    delivered_total: Counter<u64>,
+    expired_total: Counter<u64>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let meter = global::meter("obscura-server");
+        Self {
+            delivered_total: meter
+                .u64_counter("obscura_announcements_delivered_total")
+                .with_description("Announcements handed to users, labelled by route (live frame or queued envelope)")
+                .build(),
+            expired_total: meter
+                .u64_counter("obscura_announcements_expired_total")
+                .with_description("Announcements deleted after their lifetime ended")
+                .build(),
+        }
+    }
+}
+
+/// Queues announcements as system envelopes for users who did not receive them live,
+/// once the offline delay has passed, and deletes announcements that have expired.
+#[derive(Debug)]
+pub struct AnnouncementWorker {
+    pool: DbPool,
+    repo: AnnouncementRepository,
+    config: AnnouncementConfig,
+    metrics: Metrics,
+}
+
+impl AnnouncementWorker {
+    #[must_use]
+    pub fn new(pool: DbPool, repo: AnnouncementRepository, config: AnnouncementConfig) -> Self {
+        Self { pool, repo, config, metrics: Metrics::new() }
+    }
+
+    pub async fn run(self, mut shutdown: tokio::sync::watch::Receiver<bool>) {
+        let mut interval = tokio::time::interval(StdDuration::from_secs(self.config.worker_interval_secs.max(1)));
+
+        while !*shutdown.borrow() {
+            tokio::select! {
+                _ = interval.tick() => {
+                    async {
+                        if let Err(e) = self.queue_for_offline_users().await {
+                            tracing::error!(error = %e, "Queueing announcements failed");
+                        }
+                        if let Err(e) = self.delete_expired().await {
+                            tracing::error!(error = %e, "Announcement cleanup failed");
+                        }
+                    }
+                    .instrument(tracing::info_span!("run_announcement_worker"))
+                    .await;
+                }
+                _ = shutdown.changed() => {}
+            }
+        }
+        tracing::info!("Announcement worker shutting down...");
+    }
+
+    /// Queues every announcement older than the offline delay for the users that have not received it.
+    /// Returns the number of envelopes queued.
+    ///
+    /// # Errors
+    /// Returns an error if the database operations fail.
+    #[tracing::instrument(err, skip_all, fields(envelopes = tracing::field::Empty))]
+    pub async fn queue_for_offline_users(&self) -> Result<u64> {
+        let created_before = OffsetDateTime::now_utc()
+            - Duration::seconds(i64::try_from(self.config.offline_delay_secs).unwrap_or(i64::MAX));
+        let mut total = 0;
+
+        loop {
+            // Marking the announcement and queueing its envelopes commit together, so a crash
+            // between the two leaves it to be picked up again.
+            let mut tx = self.pool.begin_timed().await?;
+            let Some((id, payload, expires_at)) =
+                self.repo.take_unqueued(&mut tx, created_before, 1).await?.into_iter().next()
+            else {
+                break;
+            };
+            let queued = self.repo.queue_for_remaining_users(&mut tx, id, &payload, expires_at).await?;
+            tx.commit().await?;
+
+            tracing::info!(announcement.id = %id, envelopes = queued, "Announcement queued for offline users");
+            self.metrics.delivered_total.add(queued, &[KeyValue::new("route", "queued")]);
+            total += queued;
+        }
+
+        tracing::Span::current().record("envelopes", total);
+        Ok(total)
+    }
+
+    /// Deletes announcements whose lifetime has ended.
+    ///
+    /// # Errors
+    /// Returns an error if the database operation fails.
+    #[tracing::instrument(err, skip_all)]
+    pub async fn delete_expired(&self) -> Result<u64> {
+        let mut conn = self.pool.acquire_timed().await?;
+        let deleted = self.repo.delete_expired(&mut conn).await?;
+        if deleted > 0 {
+            tracing::info!(count = deleted, "Deleted expired announcements");
+            self.metrics.expired_total.add(deleted, &[]);
+        }
+        Ok(deleted)
+    }
+}
@@ -1,12 +1,16 @@
 use crate::adapters::database::DbPool;
 use crate::adapters::database::attachment_repo::AttachmentRepository;
+use crate::adapters::database::instrumentation::TimedAcquire;
 use crate::adapters::storage::ObjectStorage;
 use crate::config::AttachmentConfig;
+use crate::domain::attachment::Attachment;
 use crate::error::Result;
 use opentelemetry::{global, metrics::Counter};
+use std::collections::HashSet;
 use std::sync::Arc;
 use std::time::Duration as StdDuration;
 use tracing::Instrument;
+use uuid::Uuid;
 
 #[derive(Clone, Debug)]
 struct Metrics {
@@ -95,50 +99,30 @@ impl AttachmentCleanupWorker {
     /// Returns an error if the database or storage operations fail.
     #[tracing::instrument(
         err,
-        skip(self),
+        skip_all,
         fields(total_deleted = tracing::field::Empty)
     )]
     pub async fn cleanup_batch(&self) -> Result<u64> {
+        let limit = i64::try_from(self.attachment_config.cleanup_batch_size).unwrap_or(i64::MAX);
         let mut total_deleted = 0;
+        let mut after = None;
         loop {
-            // Fetch expired attachments
-            let mut conn = self.pool.acquire().await?;
-            let ids = self
-                .repo
-                .fetch_expired(&mut conn, i64::try_from(self.attachment_config.cleanup_batch_size).unwrap_or(i64::MAX))
-                .await?;
-
-            if ids.is_empty() {
-                break;
-            }
+            // Page through expired attachments so rows whose objects could not be deleted are not refetched
+            let mut conn = self.pool.acquire_timed().await?;
+            let attachments = self.repo.fetch_expired(&mut conn, after, limit).await?;
 
-            tracing::info!(count = %ids.len(), "Found expired attachments to delete");
+            let Some(last) = attachments.last() else {
+                break;
+            };
+            after = Some(last.id);
 
-            let count = ids.len();
-            for id in ids {
-                let key = format!("{}{}", self.attachment_config.prefix, id);
-                let res: Result<bool> = async {
-                    // Delete object from Storage first to avoid orphaned files
-                    let res = self.storage.delete(&key).await;
+            tracing::info!(count = %attachments.len(), "Found expired attachments to delete");
 
-                    if let Err(e) = res {
-                        tracing::warn!(error = ?e, key = %key, "Storage delete error");
-                        return Ok(false); // Skip DB delete if storage failed
-                    }
+            drop(conn);
+            let deleted = self.delete_page(&attachments).await?;
+            total_deleted += deleted;
 
-                    // Only delete from DB if storage deletion was successful
-                    let mut conn = self.pool.acquire().await?;
-                    self.repo.delete(&mut conn, id).await?;
-                    Ok(true)
-                }
-                .instrument(tracing::debug_span!("delete_attachment", attachment_id = %id))
-                .await;
-
-                if matches!(res, Ok(true)) {
-                    total_deleted += 1;
-                }
-            }
-            tracing::info!(deleted_count = %count, "Attachment cleanup batch completed successfully");
+            tracing::info!(deleted_count = %deleted, "Attachment cleanup batch completed successfully");
         }
 
         if total_deleted > 0 {
@@ -147,4 +131,56 @@ impl AttachmentCleanupWorker {
 
         Ok(total_deleted)
     }
+
+    /// Deletes the objects of a page of expired attachments in storage batches, then the rows
+    /// whose objects are gone. Returns the number of rows deleted.
+    ///
+    /// The page's content digests stay locked until the rows are gone, so an upload or link to
+    /// shared content either lands before the check and keeps the object, or waits and writes
+    /// it again.
+    async fn delete_page(&self, attachments: &[Attachment]) -> Result<u64> {
+        let ids: Vec<Uuid> = attachments.iter().map(|a| a.id).collect();
+        let mut tx = self.pool.begin_timed().await?;
+
+        // Content-addressed objects stay until the last attachment referencing them is gone
+        let digests: Vec<Vec<u8>> = attachments.iter().filter_map(|a| a.content_digest).map(Vec::from).collect();
+        let shared: HashSet<Vec<u8>> = if digests.is_empty() {
+            HashSet::new()
+        } else {
+            self.repo.lock_digests(&mut tx, &digests).await?;
+            self.repo.find_shared_digests(&mut tx, &digests, &ids).await?.into_iter().collect()
+        };
+
+        let keys: Vec<String> = attachments
+            .iter()
+            .filter(|a| a.content_digest.is_none_or(|digest| !shared.contains(digest.as_slice())))
+            .map(|a| a.storage_key(&self.attachment_config.prefix))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+
+        // Delete objects from Storage first to avoid orphaned files
+        let failed: HashSet<String> = match self.storage.delete_many(&keys).await {
+            Ok(failed) => failed.into_iter().collect(),
+            Err(e) => {
+                tracing::warn!(error = ?e, count = keys.len(), "Storage batch delete error");
+                return Ok(0); // Skip DB delete if storage failed
+            }
+        };
+        if !failed.is_empty() {
+            tracing::warn!(count = failed.len(), "Some attachment objects could not be deleted");
+        }
+
+        // Only delete rows whose objects are gone or still in use
+        let deletable: Vec<Uuid> = attachments
+            .iter()
+            .filter(|a| !failed.contains(&a.storage_key(&self.attachment_config.prefix)))
+            .map(|a| a.id)
+            .collect();
+
+        // Attachments can only be extended while live, so a row fetched as expired stays expired
+        let deleted = self.repo.delete_expired(&mut tx, &deletable).await?;
+        tx.commit().await?;
+        Ok(deleted)
+    }
 }
@@ -1,8 +1,10 @@
 use crate::adapters::database::DbPool;
 use crate::adapters::database::backup_repo::BackupRepository;
+use crate::adapters::database::instrumentation::TimedAcquire;
 use crate::adapters::storage::ObjectStorage;
 use crate::config::BackupConfig;
 use crate::error::{AppError, Result};
+use crate::telemetry;
 use opentelemetry::{global, metrics::Counter};
 use std::sync::Arc;
 use std::time::Duration as StdDuration;
@@ -13,6 +15,7 @@ use tracing::Instrument;
 struct Metrics {
     cleanup_runs: Counter<u64>,
     cleaned_items: Counter<u64>,
+    purged_versions: Counter<u64>,
     errors: Counter<u64>,
 }
 
@@ -22,6 +25,10 @@ impl Metrics {
         Self {
             cleanup_runs: meter.u64_counter("obscura_backup_cleanup_runs").build(),
             cleaned_items: meter.u64_counter("obscura_backup_cleanup_cleaned").build(),
+            purged_versions: meter
+                .u64_counter("obscura_backup_versions_purged_total")
+                .with_description("Replaced backup versions hard-deleted after the restore window")
+                .build(),
             errors: meter.u64_counter("obscura_backup_cleanup_errors").build(),
         }
     }
@@ -76,6 +83,17 @@ impl BackupCleanupWorker {
                                 self.metrics.errors.add(1, &[]);
                             }
                         }
+                        match self.purge_expired_versions().await {
+                            Ok(count) => {
+                                if count > 0 {
+                                    self.metrics.purged_versions.add(count, &[]);
+                                }
+                            }
+                            Err(e) => {
+                                tracing::error!(error = %e, "Backup version purge failed");
+                                self.metrics.errors.add(1, &[]);
+                            }
+                        }
                     }
                     .instrument(tracing::info_span!("run_backup_cleanup"))
                     .await;
@@ -92,7 +110,7 @@ impl BackupCleanupWorker {
     /// Returns an error if the database or storage operations fail.
     #[tracing::instrument(
         err,
-        skip(self),
+        skip_all,
         fields(total_cleaned = tracing::field::Empty)
     )]
     pub async fn cleanup_stale(&self) -> Result<u64> {
@@ -100,7 +118,7 @@ impl BackupCleanupWorker {
         let threshold = OffsetDateTime::now_utc() - Duration::minutes(self.backup_config.stale_threshold_mins);
 
         loop {
-            let mut conn = self.pool.acquire().await.map_err(AppError::Database)?;
+            let mut conn = self.pool.acquire_timed().await.map_err(AppError::Database)?;
             let stale_backups = self.repo.fetch_stale_uploads(&mut conn, threshold, 50).await?;
 
             if stale_backups.is_empty() {
@@ -121,7 +139,11 @@ impl BackupCleanupWorker {
                 }
 
                 if let Err(e) = self.repo.reset_stale(&mut conn, device_id).await {
-                    tracing::error!(error = ?e, "device.id" = %device_id, "Failed to reset stale backup in DB");
+                    tracing::error!(
+                        error = ?e,
+                        "device.id" = %telemetry::id(device_id),
+                        "Failed to reset stale backup in DB"
+                    );
                 } else {
                     total_cleaned += 1;
                 }
@@ -134,4 +156,43 @@ impl BackupCleanupWorker {
 
         Ok(total_cleaned)
     }
+
+    /// Hard-deletes replaced backup versions whose restore window has passed.
+    ///
+    /// # Errors
+    /// Returns an error if the database operations fail.
+    #[tracing::instrument(
+        err,
+        skip_all,
+        fields(total_purged = tracing::field::Empty)
+    )]
+    pub async fn purge_expired_versions(&self) -> Result<u64> {
+        let mut total_purged = 0;
+        let threshold = OffsetDateTime::now_utc() - Duration::hours(self.backup_config.restore_window_hours);
+
+        loop {
+            // Detach first: once the row no longer points at the version, a restore cannot race the delete.
+            let mut conn = self.pool.acquire_timed().await.map_err(AppError::Database)?;
+            let expired = self.repo.take_expired_previous_versions(&mut conn, threshold, 50).await?;
+            drop(conn);
+
+            if expired.is_empty() {
+                break;
+            }
+
+            for (device_id, version) in expired {
+                let key = format!("{}{}/v{}", self.backup_config.prefix, device_id, version);
+                if let Err(e) = self.storage.delete(&key).await {
+                    tracing::warn!(error = ?e, key = %key, "Failed to delete expired backup version from storage");
+                }
+                total_purged += 1;
+            }
+        }
+
+        if total_purged > 0 {
+            tracing::Span::current().record("total_purged", total_purged);
+        }
+
+        Ok(total_purged)
+    }
 }
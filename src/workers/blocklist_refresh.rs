@@ -0,0 +1,41 @@
+use crate::services::blocklist_service::BlocklistService;
+use std::time::Duration;
+use tracing::Instrument;
+
+/// Reloads API-managed blocklist entries so that changes made through another instance take effect here.
+#[derive(Debug)]
+pub struct BlocklistRefreshWorker {
+    blocklist_service: BlocklistService,
+    refresh_interval_secs: u64,
+}
+
+impl BlocklistRefreshWorker {
+    #[must_use]
+    pub const fn new(blocklist_service: BlocklistService, refresh_interval_secs: u64) -> Self {
+        Self { blocklist_service, refresh_interval_secs }
+    }
+
+    pub async fn run(self, mut shutdown: tokio::sync::watch::Receiver<bool>) {
+        if self.refresh_interval_secs == 0 {
+            tracing::info!("Blocklist refresh is disabled (interval = 0)");
+            return;
+        }
+
+        let mut interval = tokio::time::interval(Duration::from_secs(self.refresh_interval_secs));
+
+        while !*shutdown.borrow() {
+            tokio::select! {
+                _ = interval.tick() => {
+                    if let Err(e) = self.blocklist_service.refresh()
+                        .instrument(tracing::debug_span!("run_blocklist_refresh"))
+                        .await
+                    {
+                        tracing::error!(error = ?e, "Blocklist refresh failed, keeping previous entries");
+                    }
+                }
+                _ = shutdown.changed() => {}
+            }
+        }
+        tracing::info!("Blocklist refresh loop shutting down...");
+    }
+}
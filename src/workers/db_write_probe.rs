@@ -0,0 +1,39 @@
+use crate::services::health_service::HealthService;
+use std::time::Duration;
+use tracing::Instrument;
+
+/// Periodically checks that the primary database takes writes, switching the instance into and
+/// out of read-only mode.
+#[derive(Debug)]
+pub struct DbWriteProbeWorker {
+    health_service: HealthService,
+    interval_secs: u64,
+}
+
+impl DbWriteProbeWorker {
+    #[must_use]
+    pub const fn new(health_service: HealthService, interval_secs: u64) -> Self {
+        Self { health_service, interval_secs }
+    }
+
+    pub async fn run(self, mut shutdown: tokio::sync::watch::Receiver<bool>) {
+        if self.interval_secs == 0 {
+            tracing::info!("Database write probe is disabled (interval = 0)");
+            return;
+        }
+
+        let mut interval = tokio::time::interval(Duration::from_secs(self.interval_secs));
+
+        while !*shutdown.borrow() {
+            tokio::select! {
+                _ = interval.tick() => {
+                    self.health_service.probe_writes()
+                        .instrument(tracing::debug_span!("run_db_write_probe"))
+                        .await;
+                }
+                _ = shutdown.changed() => {}
+            }
+        }
+        tracing::info!("Database write probe loop shutting down...");
+    }
+}
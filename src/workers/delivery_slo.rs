@@ -0,0 +1,114 @@
+use crate::services::gateway::delivery_tracker::{DeliverySnapshot, DeliveryStats};
+use opentelemetry::{global, metrics::Gauge};
+use std::time::Duration;
+
+#[derive(Clone, Debug)]
+struct Metrics {
+    success_ratio: Gauge<f64>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let meter = global::meter("obscura-server");
+        Self {
+            success_ratio: meter
+                .f64_gauge("obscura_delivery_success_ratio")
+                .with_description(
+                    "Share of envelopes written by this instance's sessions in the last sample interval that were acknowledged",
+                )
+                .build(),
+        }
+    }
+}
+
+/// Periodically derives a delivery success ratio from the gateway's delivery totals, for an SLO
+/// on message delivery. The counters show volume; this shows how much of it clients confirmed.
+#[derive(Debug)]
+pub struct DeliverySloWorker {
+    stats: DeliveryStats,
+    interval_secs: u64,
+    last: DeliverySnapshot,
+    metrics: Metrics,
+}
+
+impl DeliverySloWorker {
+    #[must_use]
+    pub fn new(stats: DeliveryStats, interval_secs: u64) -> Self {
+        let last = stats.snapshot();
+        Self { stats, interval_secs, last, metrics: Metrics::new() }
+    }
+
+    pub async fn run(mut self, mut shutdown: tokio::sync::watch::Receiver<bool>) {
+        if self.interval_secs == 0 {
+            tracing::info!("Delivery SLO sampling is disabled (interval = 0)");
+            return;
+        }
+
+        let mut interval = tokio::time::interval(Duration::from_secs(self.interval_secs));
+        // The first tick completes immediately and would cover no time at all.
+        interval.tick().await;
+
+        while !*shutdown.borrow() {
+            tokio::select! {
+                _ = interval.tick() => {
+                    self.sample();
+                }
+                _ = shutdown.changed() => {}
+            }
+        }
+        tracing::info!("Delivery SLO sampler loop shutting down...");
+    }
+
+    /// Records the share of envelopes written since the previous sample that were acknowledged.
+    /// Intervals without deliveries leave the gauge unchanged. ACKs can trail the deliveries
+    /// they confirm into the next interval, so the ratio is capped at 1.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn sample(&mut self) -> Option<f64> {
+        let current = self.stats.snapshot();
+        let written = (current.delivered - self.last.delivered) + (current.redelivered - self.last.redelivered);
+        let acked = current.acked - self.last.acked;
+        self.last = current;
+
+        if written == 0 {
+            return None;
+        }
+        let ratio = (acked as f64 / written as f64).min(1.0);
+        self.metrics.success_ratio.record(ratio, &[]);
+        tracing::debug!(written, acked, ratio, "Sampled delivery success ratio");
+        Some(ratio)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::gateway::Metrics as GatewayMetrics;
+    use crate::services::gateway::delivery_tracker::{DeliveryTracker, EnvelopeStamp};
+    use time::OffsetDateTime;
+    use uuid::Uuid;
+
+    fn deliver(tracker: &mut DeliveryTracker, count: usize) -> Vec<Uuid> {
+        let ids: Vec<Uuid> = (0..count).map(|_| Uuid::new_v4()).collect();
+        let stamps: Vec<EnvelopeStamp> =
+            ids.iter().map(|id| EnvelopeStamp { id: *id, seq: 1, enqueued_at: OffsetDateTime::now_utc() }).collect();
+        tracker.record_delivered(&stamps);
+        ids
+    }
+
+    #[test]
+    fn test_ratio_covers_only_the_last_interval() {
+        let stats = DeliveryStats::default();
+        let mut tracker = DeliveryTracker::new(GatewayMetrics::new(), stats.clone(), None);
+        let mut worker = DeliverySloWorker::new(stats, 60);
+        assert_eq!(worker.sample(), None);
+
+        let ids = deliver(&mut tracker, 4);
+        tracker.record_acked(&ids[..3]);
+        assert_eq!(worker.sample(), Some(0.75));
+
+        // The last ACK arrives an interval late, alongside a fully acknowledged delivery
+        let late = deliver(&mut tracker, 1);
+        tracker.record_acked(&[ids[3], late[0]]);
+        assert_eq!(worker.sample(), Some(1.0));
+    }
+}
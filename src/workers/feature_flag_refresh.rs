@@ -0,0 +1,41 @@
+use crate::services::feature_flag_service::FeatureFlagService;
+use std::time::Duration;
+use tracing::Instrument;
+
+/// Reloads feature flags so that changes made through another instance take effect here.
+#[derive(Debug)]
+pub struct FeatureFlagRefreshWorker {
+    feature_flag_service: FeatureFlagService,
+    refresh_interval_secs: u64,
+}
+
+impl FeatureFlagRefreshWorker {
+    #[must_use]
+    pub const fn new(feature_flag_service: FeatureFlagService, refresh_interval_secs: u64) -> Self {
+        Self { feature_flag_service, refresh_interval_secs }
+    }
+
+    pub async fn run(self, mut shutdown: tokio::sync::watch::Receiver<bool>) {
+        if self.refresh_interval_secs == 0 {
+            tracing::info!("Feature flag refresh is disabled (interval = 0)");
+            return;
+        }
+
+        let mut interval = tokio::time::interval(Duration::from_secs(self.refresh_interval_secs));
+
+        while !*shutdown.borrow() {
+            tokio::select! {
+                _ = interval.tick() => {
+                    if let Err(e) = self.feature_flag_service.refresh()
+                        .instrument(tracing::debug_span!("run_feature_flag_refresh"))
+                        .await
+                    {
+                        tracing::error!(error = ?e, "Feature flag refresh failed, keeping previous flags");
+                    }
+                }
+                _ = shutdown.changed() => {}
+            }
+        }
+        tracing::info!("Feature flag refresh loop shutting down...");
+    }
+}
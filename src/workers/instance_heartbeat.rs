@@ -0,0 +1,40 @@
+use crate::services::instance_service::InstanceService;
+use std::time::Duration;
+use tracing::Instrument;
+
+/// Keeps this instance on the peer list while it runs, and takes it off on shutdown.
+#[derive(Debug)]
+pub struct InstanceHeartbeatWorker {
+    instance_service: InstanceService,
+    interval_secs: u64,
+}
+
+impl InstanceHeartbeatWorker {
+    #[must_use]
+    pub const fn new(instance_service: InstanceService, interval_secs: u64) -> Self {
+        Self { instance_service, interval_secs }
+    }
+
+    pub async fn run(self, mut shutdown: tokio::sync::watch::Receiver<bool>) {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.interval_secs.max(1)));
+
+        while !*shutdown.borrow() {
+            tokio::select! {
+                _ = interval.tick() => {
+                    if let Err(e) = self.instance_service.heartbeat()
+                        .instrument(tracing::debug_span!("instance_heartbeat"))
+                        .await
+                    {
+                        tracing::warn!(error = %e, "Instance heartbeat failed");
+                    }
+                }
+                _ = shutdown.changed() => {}
+            }
+        }
+
+        if let Err(e) = self.instance_service.deregister().await {
+            tracing::warn!(error = %e, "Failed to deregister instance, it will expire instead");
+        }
+        tracing::info!("Instance heartbeat loop shutting down...");
+    }
+}
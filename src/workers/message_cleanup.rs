@@ -1,40 +1,96 @@
 use crate::adapters::database::DbPool;
+use crate::adapters::database::instrumentation::TimedAcquire;
 use crate::adapters::database::message_repo::MessageRepository;
-use crate::config::MessagingConfig;
+use crate::adapters::storage::ObjectStorage;
+use crate::config::{MessagingConfig, TierLimitTable};
+use crate::domain::message::payload_storage_key;
+use crate::domain::user::UserTier;
 use crate::error::AppError;
-use opentelemetry::{global, metrics::Counter};
+use opentelemetry::{KeyValue, global, metrics::Counter};
+use std::collections::HashSet;
+use std::sync::Arc;
 use std::time::Duration;
 use tracing::Instrument;
+use uuid::Uuid;
+
+/// How long an offloaded payload is left alone after registration, so an upload still in flight
+/// is not deleted before its message row is inserted.
+const PAYLOAD_UPLOAD_GRACE_SECS: i64 = 3600;
+/// Orphaned payloads deleted per page.
+const PAYLOAD_CLEANUP_BATCH_SIZE: i64 = 1000;
 
 #[derive(Clone, Debug)]
 struct Metrics {
+    expired_unacked: Counter<u64>,
     inbox_overflow: Counter<u64>,
+    delivered_purged: Counter<u64>,
+    payloads_deleted: Counter<u64>,
 }
 
 impl Metrics {
     fn new() -> Self {
         let meter = global::meter("obscura-server");
         Self {
+            expired_unacked: meter
+                .u64_counter("obscura_messages_expired_unacked_total")
+                .with_description(
+                    "Messages that expired before they were acknowledged, labelled by whether a session received them and transport",
+                )
+                .build(),
             inbox_overflow: meter
                 .u64_counter("obscura_messages_overflow_total")
                 .with_description("Total messages deleted due to inbox overflow")
                 .build(),
+            delivered_purged: meter
+                .u64_counter("obscura_messages_delivered_purged_total")
+                .with_description("Total acknowledged messages deleted once their redelivery grace period passed")
+                .build(),
+            payloads_deleted: meter
+                .u64_counter("obscura_message_payloads_deleted_total")
+                .with_description("Total offloaded message payloads deleted from storage")
+                .build(),
         }
     }
 }
 
-#[derive(Debug)]
 pub struct MessageCleanupWorker {
     pool: DbPool,
     repo: MessageRepository,
+    storage: Arc<dyn ObjectStorage>,
     config: MessagingConfig,
+    inbox_limits: [(UserTier, i64); 3],
     metrics: Metrics,
 }
 
+impl std::fmt::Debug for MessageCleanupWorker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MessageCleanupWorker")
+            .field("repo", &self.repo)
+            .field("config", &self.config)
+            .field("inbox_limits", &self.inbox_limits)
+            .field("metrics", &self.metrics)
+            .finish_non_exhaustive()
+    }
+}
+
 impl MessageCleanupWorker {
     #[must_use]
-    pub fn new(pool: DbPool, repo: MessageRepository, config: MessagingConfig) -> Self {
-        Self { pool, repo, config, metrics: Metrics::new() }
+    pub fn new(
+        pool: DbPool,
+        repo: MessageRepository,
+        storage: Arc<dyn ObjectStorage>,
+        config: MessagingConfig,
+    ) -> Self {
+        let inbox_limits = UserTier::ALL.map(|tier| (tier, config.max_inbox_size));
+        Self { pool, repo, storage, config, inbox_limits, metrics: Metrics::new() }
+    }
+
+    /// Prunes each device's inbox to the limit of its owner's tier rather than to
+    /// `--messaging-inbox-max-size`.
+    #[must_use]
+    pub fn with_tier_limits(mut self, tier_limits: &TierLimitTable) -> Self {
+        self.inbox_limits = UserTier::ALL.map(|tier| (tier, tier_limits.get(tier).max_inbox_size));
+        self
     }
 
     pub async fn run(self, mut shutdown: tokio::sync::watch::Receiver<bool>) {
@@ -56,38 +112,82 @@ impl MessageCleanupWorker {
         tracing::info!("Message cleanup loop shutting down...");
     }
 
-    /// Periodically cleans up expired messages and enforces inbox limits.
+    /// Periodically cleans up expired messages, purges acknowledged messages past their grace
+    /// period, enforces inbox limits and deletes the offloaded payloads of messages that are gone.
     ///
     /// # Errors
     /// Returns an error if the database connection or query fails.
     #[tracing::instrument(
-        skip(self),
+        skip_all,
         err,
-        fields(expired_deleted = tracing::field::Empty, overflow_deleted = tracing::field::Empty)
+        fields(
+            expired_deleted = tracing::field::Empty,
+            delivered_deleted = tracing::field::Empty,
+            overflow_deleted = tracing::field::Empty,
+            payloads_deleted = tracing::field::Empty
+        )
     )]
     pub async fn perform_cleanup(&self) -> Result<(), AppError> {
         tracing::debug!("Running message cleanup (expiry + limits)...");
 
         // Delete messages exceeding TTL
-        let res_expiry = if let Ok(mut conn) = self.pool.acquire().await {
+        let res_expiry = if let Ok(mut conn) = self.pool.acquire_timed().await {
             self.repo.delete_expired(&mut conn).await
         } else {
             Err(AppError::Internal)
         };
 
         match res_expiry {
+            Ok(expired) => {
+                if expired.total > 0 {
+                    let unacked = expired.unacked_delivered_push
+                        + expired.unacked_delivered_websocket
+                        + expired.unacked_undelivered;
+                    tracing::info!(
+                        count = %expired.total,
+                        unacked = %unacked,
+                        "Deleted expired messages"
+                    );
+                    tracing::Span::current().record("expired_deleted", expired.total);
+                }
+                // Messages no session received were waiting on a push to wake the device
+                for (delivered, transport, count) in [
+                    ("true", "push", expired.unacked_delivered_push),
+                    ("true", "websocket", expired.unacked_delivered_websocket),
+                    ("false", "push", expired.unacked_undelivered),
+                ] {
+                    if count > 0 {
+                        self.metrics.expired_unacked.add(
+                            count,
+                            &[KeyValue::new("delivered", delivered), KeyValue::new("transport", transport)],
+                        );
+                    }
+                }
+            }
+            Err(e) => tracing::error!(error = ?e, "Cleanup error (expiry)"),
+        }
+
+        // Purge acknowledged messages kept for redelivery
+        let res_delivered = if let Ok(mut conn) = self.pool.acquire_timed().await {
+            self.repo.delete_delivered(&mut conn).await
+        } else {
+            Err(AppError::Internal)
+        };
+
+        match res_delivered {
             Ok(count) => {
                 if count > 0 {
-                    tracing::info!(count = %count, "Deleted expired messages");
-                    tracing::Span::current().record("expired_deleted", count);
+                    tracing::info!(count = %count, "Purged delivered messages");
+                    self.metrics.delivered_purged.add(count, &[]);
+                    tracing::Span::current().record("delivered_deleted", count);
                 }
             }
-            Err(e) => tracing::error!(error = ?e, "Cleanup error (expiry)"),
+            Err(e) => tracing::error!(error = ?e, "Cleanup error (delivered)"),
         }
 
-        // Enforce global inbox size limits (prune oldest messages)
-        let res_overflow = if let Ok(mut conn) = self.pool.acquire().await {
-            self.repo.delete_global_overflow(&mut conn, self.config.max_inbox_size).await
+        // Enforce per-tier inbox size limits (prune oldest messages)
+        let res_overflow = if let Ok(mut conn) = self.pool.acquire_timed().await {
+            self.repo.delete_tier_overflow(&mut conn, &self.inbox_limits).await
         } else {
             Err(AppError::Internal)
         };
@@ -103,6 +203,61 @@ impl MessageCleanupWorker {
             Err(e) => tracing::error!(error = ?e, "Cleanup error (overflow)"),
         }
 
+        // Runs last so payloads of messages removed above are collected in the same pass
+        match self.cleanup_payloads().await {
+            Ok(count) => {
+                if count > 0 {
+                    tracing::info!(count = %count, "Deleted orphaned message payloads");
+                    self.metrics.payloads_deleted.add(count, &[]);
+                    tracing::Span::current().record("payloads_deleted", count);
+                }
+            }
+            Err(e) => tracing::error!(error = ?e, "Cleanup error (payloads)"),
+        }
+
         Ok(())
     }
+
+    /// Deletes the stored payloads of offloaded messages that were acknowledged, expired, pruned or
+    /// never inserted, then forgets them. Returns the number of payloads removed.
+    ///
+    /// # Errors
+    /// Returns an error if the database connection or query fails.
+    pub async fn cleanup_payloads(&self) -> Result<u64, AppError> {
+        let mut total = 0;
+        loop {
+            let mut conn = self.pool.acquire_timed().await?;
+            let ids = self
+                .repo
+                .fetch_orphaned_payloads(&mut conn, PAYLOAD_UPLOAD_GRACE_SECS, PAYLOAD_CLEANUP_BATCH_SIZE)
+                .await?;
+            if ids.is_empty() {
+                break;
+            }
+
+            let keys: Vec<String> =
+                ids.iter().map(|id| payload_storage_key(&self.config.payload_prefix, *id)).collect();
+            let failed: HashSet<String> = match self.storage.delete_many(&keys).await {
+                Ok(failed) => failed.into_iter().collect(),
+                Err(e) => {
+                    tracing::warn!(error = ?e, count = keys.len(), "Storage batch delete error");
+                    break;
+                }
+            };
+
+            let deleted: Vec<Uuid> =
+                ids.iter().zip(&keys).filter(|(_, key)| !failed.contains(*key)).map(|(id, _)| *id).collect();
+            total += self.repo.delete_payloads(&mut conn, &deleted).await?;
+
+            // Failed objects would be refetched first, so leave them to the next run
+            if !failed.is_empty() {
+                tracing::warn!(count = failed.len(), "Some message payloads could not be deleted");
+                break;
+            }
+            if ids.len() < usize::try_from(PAYLOAD_CLEANUP_BATCH_SIZE).unwrap_or(usize::MAX) {
+                break;
+            }
+        }
+        Ok(total)
+    }
 }
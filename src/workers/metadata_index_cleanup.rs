@@ -0,0 +1,51 @@
+use crate::config::MetadataIndexConfig;
+use crate::error::Result;
+use crate::services::metadata_index_service::MetadataIndexService;
+use std::time::Duration;
+use tracing::Instrument;
+
+/// Deletes metadata index entries older than the retention period. Runs whether or not the
+/// index is enabled, so entries recorded before it was turned off still expire.
+#[derive(Debug)]
+pub struct MetadataIndexCleanupWorker {
+    service: MetadataIndexService,
+    interval_secs: u64,
+}
+
+impl MetadataIndexCleanupWorker {
+    #[must_use]
+    pub const fn new(service: MetadataIndexService, config: &MetadataIndexConfig) -> Self {
+        Self { service, interval_secs: config.cleanup_interval_secs }
+    }
+
+    pub async fn run(self, mut shutdown: tokio::sync::watch::Receiver<bool>) {
+        if self.interval_secs == 0 {
+            tracing::info!("Metadata index cleanup is disabled (interval = 0)");
+            return;
+        }
+
+        let mut interval = tokio::time::interval(Duration::from_secs(self.interval_secs));
+        while !*shutdown.borrow() {
+            tokio::select! {
+                _ = interval.tick() => {
+                    if let Err(e) = self.perform_cleanup()
+                        .instrument(tracing::info_span!("run_metadata_index_cleanup"))
+                        .await
+                    {
+                        tracing::error!(error = ?e, "Metadata index cleanup iteration failed");
+                    }
+                }
+                _ = shutdown.changed() => {}
+            }
+        }
+        tracing::info!("Metadata index cleanup loop shutting down...");
+    }
+
+    /// Deletes entries past the retention period, in batches.
+    ///
+    /// # Errors
+    /// Returns an error if the database connection or query fails.
+    pub async fn perform_cleanup(&self) -> Result<u64> {
+        self.service.purge_expired().await
+    }
+}
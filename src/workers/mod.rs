@@ -1,13 +1,37 @@
+pub mod ack_spill;
+pub mod announcement;
 pub mod attachment_cleanup;
 pub mod backup_cleanup;
+pub mod blocklist_refresh;
+pub mod db_write_probe;
+pub mod delivery_slo;
+pub mod feature_flag_refresh;
+pub mod instance_heartbeat;
 pub mod message_cleanup;
+pub mod metadata_index_cleanup;
 pub mod notification;
+pub mod pool_adjuster;
+pub mod prekey_sampler;
 pub mod push_notification;
+pub mod redis_janitor;
 pub mod refresh_token_cleanup;
+pub mod storage_audit;
 
+pub use ack_spill::AckSpillWorker;
+pub use announcement::AnnouncementWorker;
 pub use attachment_cleanup::AttachmentCleanupWorker;
 pub use backup_cleanup::BackupCleanupWorker;
+pub use blocklist_refresh::BlocklistRefreshWorker;
+pub use db_write_probe::DbWriteProbeWorker;
+pub use delivery_slo::DeliverySloWorker;
+pub use feature_flag_refresh::FeatureFlagRefreshWorker;
+pub use instance_heartbeat::InstanceHeartbeatWorker;
 pub use message_cleanup::MessageCleanupWorker;
+pub use metadata_index_cleanup::MetadataIndexCleanupWorker;
 pub use notification::NotificationWorker;
+pub use pool_adjuster::PoolAdjusterWorker;
+pub use prekey_sampler::PreKeySamplerWorker;
 pub use push_notification::PushNotificationWorker;
+pub use redis_janitor::RedisJanitorWorker;
 pub use refresh_token_cleanup::RefreshTokenCleanupWorker;
+pub use storage_audit::StorageAuditWorker;
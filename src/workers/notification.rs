@@ -1,5 +1,6 @@
-use crate::adapters::redis::NotificationRepository;
+use crate::adapters::realtime::RealtimeBus;
 use crate::services::notification_service::NotificationService;
+use crate::telemetry;
 use opentelemetry::{global, metrics::Counter};
 use std::sync::Arc;
 use std::time::Duration;
@@ -27,18 +28,14 @@ impl Metrics {
 #[derive(Debug)]
 pub struct NotificationWorker {
     service: NotificationService,
-    repo: Arc<NotificationRepository>,
+    repo: Arc<dyn RealtimeBus>,
     cleanup_interval_secs: u64,
     metrics: Option<Metrics>,
 }
 
 impl NotificationWorker {
     #[must_use]
-    pub const fn new(
-        service: NotificationService,
-        repo: Arc<NotificationRepository>,
-        cleanup_interval_secs: u64,
-    ) -> Self {
+    pub const fn new(service: NotificationService, repo: Arc<dyn RealtimeBus>, cleanup_interval_secs: u64) -> Self {
         Self { service, repo, cleanup_interval_secs, metrics: None }
     }
 
@@ -80,7 +77,11 @@ impl NotificationWorker {
                                     m.processed_total.add(1, &[]);
                                 }
                             }
-                            .instrument(tracing::debug_span!("dispatch_notification", %device_id, %event))
+                            .instrument(tracing::debug_span!(
+                                "dispatch_notification",
+                                device.id = device_id.map(telemetry::id).map(tracing::field::display),
+                                %event
+                            ))
                             .await;
                         }
                         Err(broadcast::error::RecvError::Lagged(n)) => {
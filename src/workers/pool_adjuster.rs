@@ -0,0 +1,168 @@
+use crate::adapters::database::DbPool;
+use crate::adapters::database::instrumentation::{AcquireTotals, waiting_acquires};
+use crate::config::{DatabaseConfig, PoolAdjustMode};
+use opentelemetry::{
+    global,
+    metrics::{Counter, Gauge},
+};
+use std::time::Duration;
+
+#[derive(Clone, Debug)]
+struct Metrics {
+    contended_intervals_total: Counter<u64>,
+    warm_floor: Gauge<u64>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let meter = global::meter("obscura-server");
+        Self {
+            contended_intervals_total: meter
+                .u64_counter("obscura_db_pool_contended_intervals_total")
+                .with_description("Pool adjuster intervals in which acquires timed out or waited too long")
+                .build(),
+            warm_floor: meter
+                .u64_gauge("obscura_db_pool_warm_floor")
+                .with_description("Connections the pool adjuster keeps open, at least the configured minimum")
+                .build(),
+        }
+    }
+}
+
+/// Watches pool acquire waits and, when the pool stays contended for several intervals,
+/// warns with its state or keeps more connections warm.
+///
+/// The pool's own minimum cannot change after it is built, so scaling up opens connections
+/// by checking them out and returning them; the pool then keeps them until they sit idle for
+/// the idle timeout, and the adjuster reopens them while its floor stays raised.
+#[derive(Debug)]
+pub struct PoolAdjusterWorker {
+    pool: DbPool,
+    config: DatabaseConfig,
+    floor: u32,
+    contended_streak: u32,
+    calm_streak: u32,
+    metrics: Metrics,
+}
+
+impl PoolAdjusterWorker {
+    #[must_use]
+    pub fn new(pool: DbPool, config: DatabaseConfig) -> Self {
+        Self {
+            pool,
+            floor: config.min_connections,
+            config,
+            contended_streak: 0,
+            calm_streak: 0,
+            metrics: Metrics::new(),
+        }
+    }
+
+    pub async fn run(mut self, mut shutdown: tokio::sync::watch::Receiver<bool>) {
+        if self.config.pool_adjust_mode == PoolAdjustMode::Off || self.config.pool_adjust_interval_secs == 0 {
+            tracing::info!("Database pool adjuster is disabled");
+            return;
+        }
+
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.pool_adjust_interval_secs));
+        let mut last = AcquireTotals::current();
+
+        while !*shutdown.borrow() {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let now = AcquireTotals::current();
+                    self.adjust(now.since(last)).await;
+                    last = now;
+                }
+                _ = shutdown.changed() => {}
+            }
+        }
+        tracing::info!("Database pool adjuster shutting down...");
+    }
+
+    /// Folds one interval's acquires into the contention streaks and acts on them.
+    async fn adjust(&mut self, interval: AcquireTotals) {
+        let contended = interval.timeouts > 0
+            || (interval.acquires > 0
+                && interval.mean_wait() >= Duration::from_millis(self.config.pool_contention_wait_ms));
+
+        if contended {
+            self.metrics.contended_intervals_total.add(1, &[]);
+            self.contended_streak += 1;
+            self.calm_streak = 0;
+        } else {
+            self.contended_streak = 0;
+            self.calm_streak += 1;
+        }
+
+        let intervals = self.config.pool_contention_intervals.max(1);
+        let scale = self.config.pool_adjust_mode == PoolAdjustMode::Scale;
+        if self.contended_streak >= intervals {
+            self.contended_streak = 0;
+            if scale {
+                self.floor = (self.floor + self.step()).min(self.config.max_connections);
+            }
+            tracing::warn!(
+                size = self.pool.size(),
+                idle = self.pool.num_idle(),
+                max_connections = self.config.max_connections,
+                mean_wait_ms = interval.mean_wait().as_millis(),
+                timeouts = interval.timeouts,
+                warm_floor = self.floor,
+                "Database pool is under sustained contention"
+            );
+        } else if self.calm_streak >= intervals && self.floor > self.config.min_connections {
+            self.calm_streak = 0;
+            self.floor = self.floor.saturating_sub(self.step()).max(self.config.min_connections);
+            tracing::info!(warm_floor = self.floor, "Database pool contention subsided, lowering warm floor");
+        }
+
+        self.metrics.warm_floor.record(u64::from(self.floor), &[]);
+        if scale {
+            self.warm().await;
+        }
+    }
+
+    fn step(&self) -> u32 {
+        self.config.max_connections.div_ceil(10).max(1)
+    }
+
+    /// Opens connections until the pool holds at least the floor. Idle connections have to be
+    /// checked out too, since the pool only opens a connection when none is idle.
+    ///
+    /// Connections are checked out one at a time, and warming stops as soon as a request is
+    /// waiting for one, so the adjuster never holds connections live requests need.
+    async fn warm(&self) {
+        // The pool maintains its configured minimum on its own
+        let size = self.pool.size();
+        if self.floor <= self.config.min_connections || size >= self.floor {
+            return;
+        }
+
+        let idle = u32::try_from(self.pool.num_idle()).unwrap_or(u32::MAX);
+        let wanted = (self.floor - size).saturating_add(idle);
+        let mut checked_out = Vec::new();
+        for _ in 0..wanted {
+            if waiting_acquires() > 0 {
+                tracing::debug!("Requests are waiting for connections, pausing pool warming");
+                break;
+            }
+            // Untimed checkouts, so warming never registers as contention
+            match self.pool.acquire().await {
+                Ok(conn) => checked_out.push(conn),
+                Err(e) => {
+                    tracing::debug!(error = %e, "Failed to open a connection while warming the pool");
+                    break;
+                }
+            }
+        }
+        let checked_out_count = checked_out.len();
+        drop(checked_out);
+        tracing::debug!(
+            checked_out = checked_out_count,
+            size = self.pool.size(),
+            warm_floor = self.floor,
+            "Warmed database pool"
+        );
+    }
+}
@@ -0,0 +1,98 @@
+use crate::adapters::database::DbPool;
+use crate::adapters::database::instrumentation::TimedAcquire;
+use crate::adapters::database::key_repo::KeyRepository;
+use opentelemetry::{global, metrics::Gauge};
+use std::time::Duration;
+use tracing::Instrument;
+
+#[derive(Clone, Debug)]
+struct Metrics {
+    users_below_threshold: Gauge<u64>,
+    devices_below_threshold: Gauge<u64>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let meter = global::meter("obscura-server");
+        Self {
+            users_below_threshold: meter
+                .u64_gauge("obscura_prekey_users_below_threshold")
+                .with_description("Users with at least one device below the one-time prekey refill threshold")
+                .build(),
+            devices_below_threshold: meter
+                .u64_gauge("obscura_prekey_devices_below_threshold")
+                .with_description("Devices below the one-time prekey refill threshold")
+                .build(),
+        }
+    }
+}
+
+/// Periodically counts the users whose one-time prekey pools are below the refill threshold.
+/// The fetch and upload counters show the flow of prekeys; this shows whether the pools keep up.
+///
+/// Each sample walks every user a page at a time, returning the connection between pages, so no
+/// single query scans the whole table and the gauges always reflect a complete count.
+#[derive(Debug)]
+pub struct PreKeySamplerWorker {
+    pool: DbPool,
+    repo: KeyRepository,
+    threshold: i32,
+    sample_size: i64,
+    interval_secs: u64,
+    metrics: Metrics,
+}
+
+impl PreKeySamplerWorker {
+    #[must_use]
+    pub fn new(pool: DbPool, repo: KeyRepository, threshold: i32, sample_size: i64, interval_secs: u64) -> Self {
+        Self { pool, repo, threshold, sample_size, interval_secs, metrics: Metrics::new() }
+    }
+
+    pub async fn run(self, mut shutdown: tokio::sync::watch::Receiver<bool>) {
+        if self.interval_secs == 0 {
+            tracing::info!("Prekey sampling is disabled (interval = 0)");
+            return;
+        }
+
+        let mut interval = tokio::time::interval(Duration::from_secs(self.interval_secs));
+
+        while !*shutdown.borrow() {
+            tokio::select! {
+                _ = interval.tick() => {
+                    if let Err(e) = self.sample()
+                        .instrument(tracing::debug_span!("run_prekey_sample"))
+                        .await
+                    {
+                        tracing::error!(error = %e, "Prekey sampling failed");
+                    }
+                }
+                _ = shutdown.changed() => {}
+            }
+        }
+        tracing::info!("Prekey sampler loop shutting down...");
+    }
+
+    /// Counts the users and devices below the threshold, walking every user a page at a time,
+    /// and records and returns the totals. Nothing is recorded if a page fails.
+    ///
+    /// # Errors
+    /// Returns an error if the database connection or query fails.
+    pub async fn sample(&self) -> crate::error::Result<(u64, u64)> {
+        let (mut users, mut devices, mut after) = (0, 0, None);
+        loop {
+            let mut conn = self.pool.acquire_timed().await?;
+            let page = self.repo.count_below_threshold(&mut conn, self.threshold, after, self.sample_size).await?;
+            users += page.users_below_threshold;
+            devices += page.devices_below_threshold;
+            after = page.last_user_id;
+            if i64::try_from(page.scanned_users).unwrap_or(i64::MAX) < self.sample_size {
+                break;
+            }
+        }
+
+        self.metrics.users_below_threshold.record(users, &[]);
+        self.metrics.devices_below_threshold.record(devices, &[]);
+        tracing::debug!(users, devices, "Sampled prekey pools below the refill threshold");
+        Ok((users, devices))
+    }
+}
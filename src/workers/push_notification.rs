@@ -1,9 +1,13 @@
 use crate::adapters::database::DbPool;
+use crate::adapters::database::instrumentation::TimedAcquire;
 use crate::adapters::database::push_token_repo::PushTokenRepository;
-use crate::adapters::push::{PushError, PushProvider};
-use crate::adapters::redis::NotificationRepository;
+use crate::adapters::push::{PushError, PushFeedback, PushProvider};
+use crate::adapters::push_queue::{DeliveryClaim, PushJobQueue};
 use crate::config::NotificationConfig;
+use crate::domain::notification::PushKind;
+use crate::telemetry;
 use opentelemetry::{KeyValue, global, metrics::Counter};
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::{Semaphore, mpsc};
@@ -15,6 +19,12 @@ struct Metrics {
     sent: Counter<u64>,
     errors: Counter<u64>,
     invalidated_tokens: Counter<u64>,
+    duplicates_suppressed: Counter<u64>,
+    leases_lost: Counter<u64>,
+    lease_extensions: Counter<u64>,
+    feedback: Counter<u64>,
+    rescheduled: Counter<u64>,
+    backoffs: Counter<u64>,
 }
 
 impl Metrics {
@@ -23,7 +33,7 @@ impl Metrics {
         Self {
             sent: meter
                 .u64_counter("obscura_push_notifications_sent_total")
-                .with_description("Total number of push notifications successfully sent")
+                .with_description("Total number of push notifications successfully sent, by kind")
                 .build(),
             errors: meter
                 .u64_counter("obscura_push_notification_errors_total")
@@ -33,6 +43,36 @@ impl Metrics {
                 .u64_counter("obscura_push_invalid_tokens_total")
                 .with_description("Total number of push tokens removed due to being unregistered")
                 .build(),
+            duplicates_suppressed: meter
+                .u64_counter("obscura_push_duplicates_suppressed_total")
+                .with_description(
+                    "Total number of re-leased push jobs skipped because an earlier lease already sent them",
+                )
+                .build(),
+            leases_lost: meter
+                .u64_counter("obscura_push_leases_lost_total")
+                .with_description(
+                    "Total number of leased push jobs dropped unsent because they were cancelled or completed meanwhile",
+                )
+                .build(),
+            lease_extensions: meter
+                .u64_counter("obscura_push_lease_extensions_total")
+                .with_description("Total number of push job leases extended while a send was in flight")
+                .build(),
+            feedback: meter
+                .u64_counter("obscura_push_feedback_total")
+                .with_description("Total number of failed sends by the provider's verdict, labelled by outcome")
+                .build(),
+            rescheduled: meter
+                .u64_counter("obscura_push_rescheduled_total")
+                .with_description("Total number of push jobs rescheduled to the delay the provider asked for")
+                .build(),
+            backoffs: meter
+                .u64_counter("obscura_push_rate_limit_backoffs_total")
+                .with_description(
+                    "Total number of times the worker paused sending because the provider rate limited it",
+                )
+                .build(),
         }
     }
 }
@@ -40,7 +80,7 @@ impl Metrics {
 #[derive(Debug)]
 pub struct PushNotificationWorker {
     pool: DbPool,
-    repo: Arc<NotificationRepository>,
+    repo: Arc<dyn PushJobQueue>,
     provider: Arc<dyn PushProvider>,
     token_repo: PushTokenRepository,
     interval_secs: u64,
@@ -48,6 +88,7 @@ pub struct PushNotificationWorker {
     invalid_token_cleanup_interval_secs: u64,
     invalid_token_cleanup_batch_size: usize,
     invalid_token_cleanup_channel_capacity: usize,
+    rate_limit_backoff: Duration,
     semaphore: Arc<Semaphore>,
     metrics: Metrics,
 }
@@ -55,7 +96,7 @@ pub struct PushNotificationWorker {
 impl PushNotificationWorker {
     pub fn new(
         pool: DbPool,
-        repo: Arc<NotificationRepository>,
+        repo: Arc<dyn PushJobQueue>,
         provider: Arc<dyn PushProvider>,
         token_repo: PushTokenRepository,
         config: &NotificationConfig,
@@ -70,6 +111,7 @@ impl PushNotificationWorker {
             invalid_token_cleanup_interval_secs: config.invalid_token_cleanup_interval_secs,
             invalid_token_cleanup_batch_size: config.invalid_token_cleanup_batch_size,
             invalid_token_cleanup_channel_capacity: config.invalid_token_cleanup_channel_capacity,
+            rate_limit_backoff: Duration::from_secs(config.rate_limit_backoff_secs),
             semaphore: Arc::new(Semaphore::new(config.worker_concurrency)),
             metrics: Metrics::new(),
         }
@@ -78,10 +120,12 @@ impl PushNotificationWorker {
     pub async fn run(self, mut shutdown: tokio::sync::watch::Receiver<bool>) {
         let mut interval = tokio::time::interval(Duration::from_secs(self.interval_secs));
         let mut cleanup_interval = tokio::time::interval(Duration::from_secs(self.invalid_token_cleanup_interval_secs));
-        let (invalid_token_tx, mut invalid_token_rx) =
-            mpsc::channel::<String>(self.invalid_token_cleanup_channel_capacity);
+        let (feedback_tx, mut feedback_rx) =
+            mpsc::channel::<(String, PushFeedback)>(self.invalid_token_cleanup_channel_capacity);
 
         let mut cleanup_batch = Vec::new();
+        // Set while the provider is rate limiting us; no jobs are leased until it passes.
+        let mut paused_until: Option<tokio::time::Instant> = None;
 
         tracing::info!("Push notification worker started");
 
@@ -90,11 +134,16 @@ impl PushNotificationWorker {
                 _ = shutdown.changed() => break,
 
                 _ = interval.tick() => {
-                    if let Err(e) = self.process_due_jobs(invalid_token_tx.clone())
-                        .instrument(tracing::debug_span!("process_push_jobs"))
-                        .await
-                    {
-                        tracing::error!(error = %e, "Failed to process due notification jobs");
+                    if paused_until.is_some_and(|until| tokio::time::Instant::now() < until) {
+                        tracing::debug!("Push provider is rate limiting, skipping this tick");
+                    } else {
+                        paused_until = None;
+                        if let Err(e) = self.process_due_jobs(feedback_tx.clone())
+                            .instrument(tracing::debug_span!("process_push_jobs"))
+                            .await
+                        {
+                            tracing::error!(error = %e, "Failed to process due notification jobs");
+                        }
                     }
                 }
 
@@ -106,12 +155,29 @@ impl PushNotificationWorker {
                     .await;
                 }
 
-                res = invalid_token_rx.recv() => {
-                    if let Some(token) = res {
-                        cleanup_batch.push(token);
-                        if cleanup_batch.len() >= self.invalid_token_cleanup_batch_size {
-                            Self::flush_invalid_tokens(&self.pool, &self.token_repo, &mut cleanup_batch).await;
+                res = feedback_rx.recv() => {
+                    match res {
+                        Some((token, PushFeedback::InvalidToken)) => {
+                            cleanup_batch.push(token);
+                            if cleanup_batch.len() >= self.invalid_token_cleanup_batch_size {
+                                Self::flush_invalid_tokens(&self.pool, &self.token_repo, &mut cleanup_batch).await;
+                            }
+                        }
+                        Some((_, PushFeedback::RateLimited { retry_after })) => {
+                            let until = tokio::time::Instant::now() + retry_after.unwrap_or(self.rate_limit_backoff);
+                            // Sends already in flight report too; keep the longest pause asked for.
+                            if paused_until.is_none_or(|current| current < until) {
+                                if paused_until.is_none() {
+                                    self.metrics.backoffs.add(1, &[]);
+                                }
+                                tracing::warn!(
+                                    pause_secs = until.duration_since(tokio::time::Instant::now()).as_secs(),
+                                    "Push provider is rate limiting, pausing sends"
+                                );
+                                paused_until = Some(until);
+                            }
                         }
+                        Some((_, PushFeedback::RetryLater { .. })) | None => {}
                     }
                 }
             }
@@ -123,14 +189,14 @@ impl PushNotificationWorker {
         Self::flush_invalid_tokens(&self.pool, &self.token_repo, &mut cleanup_batch).await;
     }
 
-    #[tracing::instrument(level = "debug", skip(pool, repo, batch))]
+    #[tracing::instrument(level = "debug", skip_all)]
     async fn flush_invalid_tokens(pool: &DbPool, repo: &PushTokenRepository, batch: &mut Vec<String>) {
         if batch.is_empty() {
             return;
         }
 
         let count = batch.len();
-        match pool.acquire().await {
+        match pool.acquire_timed().await {
             Ok(mut conn) => {
                 if let Err(e) = repo.delete_tokens_batch(&mut conn, batch).await {
                     tracing::error!(error = %e, "Failed to delete invalid token batch");
@@ -145,10 +211,13 @@ impl PushNotificationWorker {
 
     /// Processes a batch of due push notification jobs.
     ///
+    /// Failed sends report the provider's verdict on `feedback_tx` when the worker loop has to
+    /// act on it: invalid tokens are queued for deletion and rate limits pause sending.
+    ///
     /// # Errors
     /// Returns an error if the scheduler or database operation fails.
-    #[tracing::instrument(level = "debug", skip(self, invalid_token_tx), name = "process_due_jobs", err)]
-    pub async fn process_due_jobs(&self, invalid_token_tx: mpsc::Sender<String>) -> anyhow::Result<()> {
+    #[tracing::instrument(level = "debug", skip_all, name = "process_due_jobs", err)]
+    pub async fn process_due_jobs(&self, feedback_tx: mpsc::Sender<(String, PushFeedback)>) -> anyhow::Result<()> {
         let available = self.semaphore.available_permits();
         if available == 0 {
             return Ok(());
@@ -157,18 +226,20 @@ impl PushNotificationWorker {
         // We poll up to 'available' jobs, which is naturally capped by worker_concurrency
         let limit = available.cast_signed();
         // If the worker crashes, the job will become visible again after this period.
-        let device_ids = self.repo.lease_due_jobs(limit, self.visibility_timeout_secs).await?;
+        let jobs = self.repo.lease_due_jobs(limit, self.visibility_timeout_secs).await?;
 
-        if device_ids.is_empty() {
+        if jobs.is_empty() {
             tracing::debug!("No due push notification jobs found");
             return Ok(());
         }
 
-        tracing::info!(count = device_ids.len(), "Processing leased push notifications");
+        tracing::info!(count = jobs.len(), "Processing leased push notifications");
+        let kinds: HashMap<Uuid, PushKind> = jobs.iter().map(|job| (job.device_id, job.kind)).collect();
+        let device_ids: Vec<Uuid> = jobs.iter().map(|job| job.device_id).collect();
 
         // 1. Batch lookup tokens for all devices
         let device_token_pairs = {
-            let mut conn = self.pool.acquire().await?;
+            let mut conn = self.pool.acquire_timed().await?;
             self.token_repo.find_tokens_for_devices(&mut conn, &device_ids).await?
         };
 
@@ -178,17 +249,19 @@ impl PushNotificationWorker {
         // 2. Identify and remove jobs for devices who have no token
         for device_id in &device_ids {
             if !devices_with_tokens.contains(device_id) {
-                tracing::info!(%device_id, "Device has no registered push token, removing job");
+                tracing::info!(device.id = %telemetry::id(device_id), "Device has no registered push token, removing job");
                 let _ = self.repo.delete_job(*device_id).await;
             }
         }
 
         // 3. Dispatch concurrently, bounded by the semaphore
         for (device_id, token) in device_token_pairs {
+            let kind = kinds.get(&device_id).copied().unwrap_or_default();
             let provider = Arc::clone(&self.provider);
             let repo = Arc::clone(&self.repo);
             let metrics = self.metrics.clone();
-            let tx = invalid_token_tx.clone();
+            let tx = feedback_tx.clone();
+            let lease_secs = self.visibility_timeout_secs;
 
             // Acquire a permit before spawning.
             let permit = Arc::clone(&self.semaphore)
@@ -200,39 +273,150 @@ impl PushNotificationWorker {
                 async move {
                     let _permit = permit;
 
-                    match provider.send_push(&token).await {
-                        Ok(()) => {
-                            tracing::debug!("Push notification sent successfully");
-                            metrics.sent.add(1, &[]);
-                            // Success: Remove job from Redis
+                    // The marker survives a worker crash, so a job re-leased after its push
+                    // already went out is completed without contacting the provider again.
+                    match repo.claim_delivery(device_id).await {
+                        Ok(DeliveryClaim::Claimed) => {}
+                        Ok(DeliveryClaim::AlreadySent) => {
+                            tracing::info!("Push already sent by an earlier lease, completing job");
+                            metrics.duplicates_suppressed.add(1, &[]);
                             let _ = repo.delete_job(device_id).await;
+                            return;
                         }
-                        Err(PushError::Unregistered) => {
-                            tracing::info!("Token unregistered, reporting to invalid token cleanup");
-                            metrics.invalidated_tokens.add(1, &[]);
-
-                            // Definitively failed: Remove job from Redis anyway
-                            let _ = repo.delete_job(device_id).await;
-
-                            // Send to cleanup for batch DB deletion
-                            let _ = tx.send(token).await;
+                        // Whatever ended the lease also settled the job, so it is left alone.
+                        Ok(DeliveryClaim::LeaseLost) => {
+                            tracing::debug!("Push job is no longer leased, skipping it");
+                            metrics.leases_lost.add(1, &[]);
+                            return;
                         }
-                        Err(PushError::QuotaExceeded) => {
-                            tracing::warn!("Push quota exceeded, allowing visibility timeout to trigger retry");
-                            metrics.errors.add(1, &[KeyValue::new("reason", "quota_exceeded")]);
-                            // We do NOT delete the job; it will be retried when the lease expires.
+                        Err(e) => {
+                            tracing::warn!(error = %e, "Failed to claim push delivery, will retry");
+                            return;
                         }
-                        Err(PushError::Other(e)) => {
-                            tracing::error!(error = %e, "Failed to send push notification, will retry");
-                            metrics.errors.add(1, &[KeyValue::new("reason", "other")]);
-                            // We do NOT delete the job; it will be retried when the lease expires.
+                    }
+
+                    match Self::send_with_heartbeat(
+                        provider.as_ref(),
+                        repo.as_ref(),
+                        &metrics,
+                        device_id,
+                        &token,
+                        kind,
+                        lease_secs,
+                    )
+                    .await
+                    {
+                        Ok(()) => {
+                            tracing::debug!("Push notification sent successfully");
+                            metrics.sent.add(1, &[KeyValue::new("kind", kind.as_str())]);
+                            // Success: Remove job from Redis
+                            let _ = repo.delete_job(device_id).await;
                         }
+                        Err(e) => Self::follow_up(repo.as_ref(), &tx, &metrics, device_id, kind, token, &e).await,
                     }
                 }
-                .instrument(tracing::debug_span!("dispatch_push", %device_id)),
+                .instrument(
+                    tracing::debug_span!("dispatch_push", device.id = %telemetry::id(device_id), kind = kind.as_str()),
+                ),
             );
         }
 
         Ok(())
     }
+
+    /// Acts on a failed send according to the provider's verdict.
+    async fn follow_up(
+        repo: &dyn PushJobQueue,
+        feedback_tx: &mpsc::Sender<(String, PushFeedback)>,
+        metrics: &Metrics,
+        device_id: Uuid,
+        kind: PushKind,
+        token: String,
+        error: &PushError,
+    ) {
+        let feedback = error.feedback();
+        metrics.feedback.add(1, &[KeyValue::new("outcome", feedback.as_str())]);
+
+        let retry_after = match feedback {
+            PushFeedback::InvalidToken => {
+                tracing::info!("Token unregistered, reporting to invalid token cleanup");
+                metrics.invalidated_tokens.add(1, &[]);
+                // Definitively failed: the job can never succeed
+                let _ = repo.delete_job(device_id).await;
+                let _ = feedback_tx.send((token, feedback)).await;
+                return;
+            }
+            PushFeedback::RateLimited { retry_after } => {
+                tracing::warn!(error = %error, ?retry_after, "Push provider is rate limiting");
+                metrics.errors.add(1, &[KeyValue::new("reason", "quota_exceeded")]);
+                retry_after
+            }
+            PushFeedback::RetryLater { retry_after } => {
+                let reason = if matches!(error, PushError::Unavailable) { "unavailable" } else { "other" };
+                tracing::warn!(error = %error, ?retry_after, "Failed to send push notification, will retry");
+                metrics.errors.add(1, &[KeyValue::new("reason", reason)]);
+                retry_after
+            }
+        };
+
+        // Without a hint the job stays leased and is retried once the lease expires.
+        let rescheduled = match retry_after {
+            Some(delay) => match repo.push_jobs(&[device_id], delay.as_secs().max(1), kind).await {
+                Ok(()) => {
+                    metrics.rescheduled.add(1, &[]);
+                    true
+                }
+                Err(e) => {
+                    tracing::warn!(error = %e, "Failed to reschedule push job, leaving it for lease expiry");
+                    false
+                }
+            },
+            None => false,
+        };
+        if !rescheduled {
+            let _ = repo.release_delivery(device_id).await;
+        }
+
+        if matches!(feedback, PushFeedback::RateLimited { .. }) {
+            let _ = feedback_tx.send((token, feedback)).await;
+        }
+    }
+
+    /// Sends a push while periodically extending the job's lease, so a provider call that
+    /// outlives the visibility timeout cannot cause another worker to pick up the same job.
+    async fn send_with_heartbeat(
+        provider: &dyn PushProvider,
+        repo: &dyn PushJobQueue,
+        metrics: &Metrics,
+        device_id: Uuid,
+        token: &str,
+        kind: PushKind,
+        lease_secs: u64,
+    ) -> Result<(), PushError> {
+        let send = provider.send_push(token, kind);
+        tokio::pin!(send);
+
+        let mut heartbeat = tokio::time::interval(Duration::from_secs((lease_secs / 3).max(1)));
+        heartbeat.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        // The first tick completes immediately; the lease was just taken, so skip it.
+        heartbeat.tick().await;
+        let mut leased = true;
+
+        loop {
+            tokio::select! {
+                res = &mut send => return res,
+
+                _ = heartbeat.tick(), if leased => {
+                    match repo.extend_lease(device_id, lease_secs).await {
+                        Ok(true) => metrics.lease_extensions.add(1, &[]),
+                        Ok(false) => {
+                            tracing::debug!("Job is no longer leased, stopping heartbeat");
+                            leased = false;
+                        }
+                        Err(e) => tracing::warn!(error = %e, "Failed to extend push job lease"),
+                    }
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,106 @@
+use crate::adapters::key_value::{KeyValueCache, KeyValueStore};
+use crate::adapters::redis::NotificationRepository;
+use crate::adapters::submission_cache::SubmissionCache;
+use opentelemetry::{KeyValue, global, metrics::Counter};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::Instrument;
+
+#[derive(Clone, Debug)]
+struct Metrics {
+    orphans_total: Counter<u64>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let meter = global::meter("obscura-server");
+        Self {
+            orphans_total: meter
+                .u64_counter("obscura_redis_orphans_total")
+                .with_description(
+                    "Redis entries a crash left without an owner or an expiry, repaired by the janitor, by kind",
+                )
+                .build(),
+        }
+    }
+}
+
+/// Repairs Redis state that a crashed instance or worker can leave behind: push job leases,
+/// delivery markers and kinds whose job is gone, and cached idempotency responses and gateway
+/// tickets without an expiry. Orphaned job state is deleted; TTL-less keys get their TTL back.
+///
+/// When the shared key-value state lives in Postgres instead, the sweep deletes its expired rows.
+#[derive(Debug)]
+pub struct RedisJanitorWorker {
+    push_jobs: Option<Arc<NotificationRepository>>,
+    submission_cache: SubmissionCache,
+    ticket_cache: KeyValueCache,
+    key_value: Arc<dyn KeyValueStore>,
+    interval_secs: u64,
+    metrics: Metrics,
+}
+
+impl RedisJanitorWorker {
+    /// `push_jobs` is `None` when push jobs are not kept in Redis.
+    #[must_use]
+    pub fn new(
+        push_jobs: Option<Arc<NotificationRepository>>,
+        submission_cache: SubmissionCache,
+        ticket_cache: KeyValueCache,
+        key_value: Arc<dyn KeyValueStore>,
+        interval_secs: u64,
+    ) -> Self {
+        Self { push_jobs, submission_cache, ticket_cache, key_value, interval_secs, metrics: Metrics::new() }
+    }
+
+    pub async fn run(self, mut shutdown: tokio::sync::watch::Receiver<bool>) {
+        if self.interval_secs == 0 {
+            tracing::info!("Redis janitor is disabled (interval = 0)");
+            return;
+        }
+
+        let mut interval = tokio::time::interval(Duration::from_secs(self.interval_secs));
+
+        while !*shutdown.borrow() {
+            tokio::select! {
+                _ = interval.tick() => {
+                    if let Err(e) = self.sweep()
+                        .instrument(tracing::debug_span!("run_redis_janitor"))
+                        .await
+                    {
+                        tracing::error!(error = %e, "Redis janitor sweep failed");
+                    }
+                }
+                _ = shutdown.changed() => {}
+            }
+        }
+        tracing::info!("Redis janitor loop shutting down...");
+    }
+
+    /// Scans the keys this server owns once, repairing what it finds.
+    ///
+    /// # Errors
+    /// Returns an error if the store cannot be scanned or updated.
+    pub async fn sweep(&self) -> anyhow::Result<()> {
+        if let Some(push_jobs) = &self.push_jobs {
+            let orphans = push_jobs.prune_orphaned_job_state().await?;
+            self.record("push_lease", orphans.leases);
+            self.record("push_delivery_marker", orphans.delivery_markers);
+            self.record("push_kind", orphans.kinds);
+        }
+        self.record("idempotency_no_ttl", self.submission_cache.enforce_ttl().await?);
+        self.record("ws_ticket_no_ttl", self.ticket_cache.enforce_ttl().await?);
+        let purged = self.key_value.purge_expired().await?;
+        if purged > 0 {
+            tracing::debug!(purged, "Deleted expired key-value entries");
+        }
+        Ok(())
+    }
+
+    fn record(&self, kind: &'static str, count: u64) {
+        if count > 0 {
+            tracing::warn!(kind, count, "Repaired orphaned Redis entries");
+            self.metrics.orphans_total.add(count, &[KeyValue::new("kind", kind)]);
+        }
+    }
+}
@@ -1,20 +1,26 @@
-use crate::adapters::database::DbPool;
-use crate::adapters::database::refresh_token_repo::RefreshTokenRepository;
-use crate::error::AppError;
+use crate::config::AuthConfig;
+use crate::error::Result;
+use crate::services::auth_service::AuthService;
 use std::time::Duration;
 use tracing::Instrument;
 
+/// Deletes expired refresh tokens on a jittered schedule. Runs can also be started on demand
+/// from the management API, which calls the same [`AuthService::purge_expired_refresh_tokens`].
 #[derive(Debug)]
 pub struct RefreshTokenCleanupWorker {
-    pool: DbPool,
-    repo: RefreshTokenRepository,
+    auth_service: AuthService,
     cleanup_interval_secs: u64,
+    jitter_secs: u64,
 }
 
 impl RefreshTokenCleanupWorker {
     #[must_use]
-    pub const fn new(pool: DbPool, repo: RefreshTokenRepository, cleanup_interval_secs: u64) -> Self {
-        Self { pool, repo, cleanup_interval_secs }
+    pub const fn new(auth_service: AuthService, config: &AuthConfig) -> Self {
+        Self {
+            auth_service,
+            cleanup_interval_secs: config.refresh_token_cleanup_interval_secs,
+            jitter_secs: config.refresh_token_cleanup_jitter_secs,
+        }
     }
 
     pub async fn run(self, mut shutdown: tokio::sync::watch::Receiver<bool>) {
@@ -23,17 +29,18 @@ impl RefreshTokenCleanupWorker {
             return;
         }
 
-        let mut interval = tokio::time::interval(Duration::from_secs(self.cleanup_interval_secs));
-
+        // The first run only waits out the jitter, like the first tick of a plain interval.
+        let mut delay = self.jitter();
         while !*shutdown.borrow() {
             tokio::select! {
-                _ = interval.tick() => {
+                () = tokio::time::sleep(delay) => {
                     if let Err(e) = self.perform_cleanup()
                         .instrument(tracing::info_span!("run_refresh_token_cleanup"))
                         .await
                     {
                         tracing::error!(error = ?e, "Refresh token cleanup iteration failed");
                     }
+                    delay = Duration::from_secs(self.cleanup_interval_secs) + self.jitter();
                 }
                 _ = shutdown.changed() => {}
             }
@@ -41,30 +48,15 @@ impl RefreshTokenCleanupWorker {
         tracing::info!("Refresh token cleanup loop shutting down...");
     }
 
-    /// Periodically cleans up expired refresh tokens.
+    /// Deletes expired refresh tokens, in batches.
     ///
     /// # Errors
     /// Returns an error if the database connection or query fails.
-    #[tracing::instrument(skip(self), err, fields(expired_deleted = tracing::field::Empty))]
-    pub async fn perform_cleanup(&self) -> Result<(), AppError> {
-        tracing::debug!("Running refresh token cleanup...");
-
-        let res = if let Ok(mut conn) = self.pool.acquire().await {
-            self.repo.delete_expired(&mut conn).await
-        } else {
-            Err(AppError::Internal)
-        };
-
-        match res {
-            Ok(count) => {
-                if count > 0 {
-                    tracing::info!(count = %count, "Deleted expired refresh tokens");
-                    tracing::Span::current().record("expired_deleted", count);
-                }
-            }
-            Err(e) => tracing::error!(error = ?e, "Cleanup error (refresh tokens)"),
-        }
+    pub async fn perform_cleanup(&self) -> Result<u64> {
+        self.auth_service.purge_expired_refresh_tokens("scheduled").await
+    }
 
-        Ok(())
+    fn jitter(&self) -> Duration {
+        Duration::from_secs(rand::random_range(0..=self.jitter_secs))
     }
 }
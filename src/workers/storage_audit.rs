@@ -0,0 +1,448 @@
+use crate::adapters::database::DbPool;
+use crate::adapters::database::attachment_repo::AttachmentRepository;
+use crate::adapters::database::backup_repo::BackupRepository;
+use crate::adapters::database::instrumentation::TimedAcquire;
+use crate::adapters::storage::{ObjectStorage, ObjectSummary, StorageError};
+use crate::config::{AttachmentConfig, AuditReconcile, BackupConfig, StorageConfig};
+use crate::error::{AppError, Result};
+use crate::telemetry;
+use opentelemetry::{KeyValue, global, metrics::Counter};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration as StdDuration;
+use time::{Duration, OffsetDateTime};
+use tracing::Instrument;
+use uuid::Uuid;
+
+const ATTACHMENT: &str = "attachment";
+const BACKUP: &str = "backup";
+
+#[derive(Clone, Debug)]
+struct Metrics {
+    runs: Counter<u64>,
+    orphaned_objects: Counter<u64>,
+    dangling_rows: Counter<u64>,
+    reconciled: Counter<u64>,
+    errors: Counter<u64>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let meter = global::meter("obscura-server");
+        Self {
+            runs: meter
+                .u64_counter("obscura_storage_audit_runs_total")
+                .with_description("Total number of completed storage consistency audits")
+                .build(),
+            orphaned_objects: meter
+                .u64_counter("obscura_storage_audit_orphaned_objects_total")
+                .with_description("Stored objects found without a database row referencing them")
+                .build(),
+            dangling_rows: meter
+                .u64_counter("obscura_storage_audit_dangling_rows_total")
+                .with_description("Database rows found whose stored object is missing")
+                .build(),
+            reconciled: meter
+                .u64_counter("obscura_storage_audit_reconciled_total")
+                .with_description("Orphaned objects and dangling rows removed by the storage audit")
+                .build(),
+            errors: meter
+                .u64_counter("obscura_storage_audit_errors_total")
+                .with_description("Total number of errors encountered during storage audits")
+                .build(),
+        }
+    }
+}
+
+/// Findings of a single storage audit.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StorageAuditReport {
+    pub checked_rows: u64,
+    pub dangling_rows: u64,
+    pub checked_objects: u64,
+    pub orphaned_objects: u64,
+    pub reconciled: u64,
+}
+
+/// Where each prefix listing resumes, so successive audits walk the whole bucket.
+#[derive(Debug, Default)]
+struct ListingCursors {
+    attachments: Option<String>,
+    backups: Option<String>,
+}
+
+/// Periodically compares a sample of attachment and backup rows with the objects in storage,
+/// and a page of stored objects with the rows, to catch drift left by crashes between the two.
+#[derive(Clone)]
+pub struct StorageAuditWorker {
+    pool: DbPool,
+    attachment_repo: AttachmentRepository,
+    backup_repo: BackupRepository,
+    storage: Arc<dyn ObjectStorage>,
+    storage_config: StorageConfig,
+    attachment_prefix: String,
+    backup_prefix: String,
+    cursors: Arc<Mutex<ListingCursors>>,
+    metrics: Metrics,
+}
+
+impl std::fmt::Debug for StorageAuditWorker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StorageAuditWorker")
+            .field("attachment_prefix", &self.attachment_prefix)
+            .field("backup_prefix", &self.backup_prefix)
+            .field("metrics", &self.metrics)
+            .finish_non_exhaustive()
+    }
+}
+
+impl StorageAuditWorker {
+    #[must_use]
+    pub fn new(
+        pool: DbPool,
+        attachment_repo: AttachmentRepository,
+        backup_repo: BackupRepository,
+        storage: Arc<dyn ObjectStorage>,
+        storage_config: StorageConfig,
+        attachment_config: &AttachmentConfig,
+        backup_config: &BackupConfig,
+    ) -> Self {
+        Self {
+            pool,
+            attachment_repo,
+            backup_repo,
+            storage,
+            storage_config,
+            attachment_prefix: attachment_config.prefix.clone(),
+            backup_prefix: backup_config.prefix.clone(),
+            cursors: Arc::new(Mutex::new(ListingCursors::default())),
+            metrics: Metrics::new(),
+        }
+    }
+
+    pub async fn run(self, mut shutdown: tokio::sync::watch::Receiver<bool>) {
+        if self.storage_config.audit_interval_secs == 0 {
+            tracing::info!("Storage audit disabled");
+            return;
+        }
+
+        // The first audit waits a full interval rather than adding load to startup
+        let period = StdDuration::from_secs(self.storage_config.audit_interval_secs);
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+
+        while !*shutdown.borrow() {
+            tokio::select! {
+                _ = interval.tick() => {
+                    async {
+                        tracing::debug!("Running storage audit...");
+
+                        match self.audit().await {
+                            Ok(report) => {
+                                self.metrics.runs.add(1, &[]);
+                                if report.dangling_rows > 0 || report.orphaned_objects > 0 {
+                                    tracing::warn!(report = ?report, "Storage audit found inconsistencies");
+                                }
+                            }
+                            Err(e) => {
+                                tracing::error!(error = %e, "Storage audit failed");
+                                self.metrics.errors.add(1, &[]);
+                            }
+                        }
+                    }
+                    .instrument(tracing::info_span!("run_storage_audit"))
+                    .await;
+                }
+                _ = shutdown.changed() => {}
+            }
+        }
+        tracing::info!("Storage audit loop shutting down...");
+    }
+
+    /// Runs a single audit.
+    ///
+    /// # Errors
+    /// Returns an error if the database or storage operations fail.
+    #[tracing::instrument(err, skip_all)]
+    pub async fn audit(&self) -> Result<StorageAuditReport> {
+        let mut report = StorageAuditReport::default();
+        self.audit_attachment_rows(&mut report).await?;
+        self.audit_backup_rows(&mut report).await?;
+        self.audit_attachment_objects(&mut report).await?;
+        self.audit_backup_objects(&mut report).await?;
+        Ok(report)
+    }
+
+    fn sample_size(&self) -> i64 {
+        i64::try_from(self.storage_config.audit_sample_size).unwrap_or(i64::MAX)
+    }
+
+    /// Checks that a sample of live attachments still has its object. Sampling starts at a
+    /// random ID, which spreads successive audits over the table.
+    async fn audit_attachment_rows(&self, report: &mut StorageAuditReport) -> Result<()> {
+        let mut conn = self.pool.acquire_timed().await?;
+        let from = sample_start(self.attachment_repo.lowest_id(&mut conn).await?);
+        let attachments = self.attachment_repo.sample_live(&mut conn, from, self.sample_size()).await?;
+
+        let mut dangling = Vec::new();
+        for attachment in &attachments {
+            let key = attachment.storage_key(&self.attachment_prefix);
+            if !self.exists(&key).await? {
+                tracing::warn!(attachment_id = %attachment.id, key = %key, "Attachment row has no stored object");
+                dangling.push(attachment.id);
+            }
+        }
+
+        report.checked_rows += attachments.len() as u64;
+        report.dangling_rows += dangling.len() as u64;
+        self.metrics.dangling_rows.add(dangling.len() as u64, &[KeyValue::new("kind", ATTACHMENT)]);
+
+        if self.storage_config.audit_reconcile == AuditReconcile::All && !dangling.is_empty() {
+            let removed = self.attachment_repo.delete_many(&mut conn, &dangling).await?;
+            report.reconciled += removed;
+            self.metrics.reconciled.add(removed, &[KeyValue::new("kind", ATTACHMENT)]);
+        }
+        Ok(())
+    }
+
+    /// Checks that a sample of backups still has the object of its current version.
+    /// Dangling backups are only reported: repairing one means picking the version the device
+    /// falls back to, which is left to an operator.
+    async fn audit_backup_rows(&self, report: &mut StorageAuditReport) -> Result<()> {
+        let mut conn = self.pool.acquire_timed().await?;
+        let from = sample_start(self.backup_repo.lowest_device_id(&mut conn).await?);
+        let backups = self.backup_repo.sample_committed(&mut conn, from, self.sample_size()).await?;
+
+        let mut dangling = 0;
+        for backup in &backups {
+            let key = format!("{}{}/v{}", self.backup_prefix, backup.device_id, backup.current_version);
+            if !self.exists(&key).await? {
+                tracing::warn!(
+                    device.id = %telemetry::id(backup.device_id),
+                    key = %key,
+                    "Backup row has no stored object"
+                );
+                dangling += 1;
+            }
+        }
+
+        report.checked_rows += backups.len() as u64;
+        report.dangling_rows += dangling;
+        self.metrics.dangling_rows.add(dangling, &[KeyValue::new("kind", BACKUP)]);
+        Ok(())
+    }
+
+    async fn audit_attachment_objects(&self, report: &mut StorageAuditReport) -> Result<()> {
+        let objects = self.list_next(&self.attachment_prefix, |cursors| &mut cursors.attachments).await?;
+        let candidates = self.settled(&objects);
+
+        let mut ids = HashMap::new();
+        let mut digests = HashMap::new();
+        for key in &candidates {
+            match parse_attachment_key(&self.attachment_prefix, key) {
+                Some(AttachmentKey::Id(id)) => {
+                    ids.insert(id, key.clone());
+                }
+                Some(AttachmentKey::Digest(digest)) => {
+                    digests.insert(digest, key.clone());
+                }
+                None => tracing::debug!(key = %key, "Skipping unrecognized attachment object"),
+            }
+        }
+
+        // Shared content stays locked until its orphans are gone, so an upload of the same bytes
+        // cannot record a row for an object that is about to be deleted
+        let mut tx = self.pool.begin_timed().await?;
+        let id_list: Vec<Uuid> = ids.keys().copied().collect();
+        for id in self.attachment_repo.find_existing_ids(&mut tx, &id_list).await? {
+            ids.remove(&id);
+        }
+        let digest_list: Vec<Vec<u8>> = digests.keys().cloned().collect();
+        if !digest_list.is_empty() {
+            self.attachment_repo.lock_digests(&mut tx, &digest_list).await?;
+            for digest in self.attachment_repo.find_shared_digests(&mut tx, &digest_list, &[]).await? {
+                digests.remove(&digest);
+            }
+        }
+
+        let orphaned: Vec<String> = ids.into_values().chain(digests.into_values()).collect();
+        report.checked_objects += objects.len() as u64;
+        self.handle_orphans(&orphaned, ATTACHMENT, report).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn audit_backup_objects(&self, report: &mut StorageAuditReport) -> Result<()> {
+        let objects = self.list_next(&self.backup_prefix, |cursors| &mut cursors.backups).await?;
+        let candidates: Vec<(String, Uuid, i32)> = self
+            .settled(&objects)
+            .into_iter()
+            .filter_map(|key| {
+                let parsed = parse_backup_key(&self.backup_prefix, &key);
+                if parsed.is_none() {
+                    tracing::debug!(key = %key, "Skipping unrecognized backup object");
+                }
+                parsed.map(|(device_id, version)| (key, device_id, version))
+            })
+            .collect();
+
+        let device_ids: Vec<Uuid> = candidates.iter().map(|(_, device_id, _)| *device_id).collect();
+        let mut conn = self.pool.acquire_timed().await?;
+        let backups: HashMap<Uuid, _> = self
+            .backup_repo
+            .find_by_device_ids(&mut conn, &device_ids)
+            .await?
+            .into_iter()
+            .map(|backup| (backup.device_id, backup))
+            .collect();
+
+        // Any version a backup row still names, including an upload in progress, is in use
+        let orphaned: Vec<String> = candidates
+            .into_iter()
+            .filter(|(_, device_id, version)| {
+                backups.get(device_id).is_none_or(|backup| {
+                    backup.current_version != *version
+                        && backup.pending_version != Some(*version)
+                        && backup.previous_version != Some(*version)
+                })
+            })
+            .map(|(key, _, _)| key)
+            .collect();
+
+        report.checked_objects += objects.len() as u64;
+        self.handle_orphans(&orphaned, BACKUP, report).await
+    }
+
+    async fn handle_orphans(
+        &self,
+        orphaned: &[String],
+        kind: &'static str,
+        report: &mut StorageAuditReport,
+    ) -> Result<()> {
+        for key in orphaned {
+            tracing::warn!(key = %key, kind, "Stored object has no database row");
+        }
+        report.orphaned_objects += orphaned.len() as u64;
+        self.metrics.orphaned_objects.add(orphaned.len() as u64, &[KeyValue::new("kind", kind)]);
+
+        if self.storage_config.audit_reconcile != AuditReconcile::Off && !orphaned.is_empty() {
+            let failed = self.storage.delete_many(orphaned).await.map_err(storage_error)?;
+            let removed = orphaned.len().saturating_sub(failed.len()) as u64;
+            report.reconciled += removed;
+            self.metrics.reconciled.add(removed, &[KeyValue::new("kind", kind)]);
+        }
+        Ok(())
+    }
+
+    /// Lists the next page of objects under `prefix`, wrapping around to the start once the end is reached.
+    async fn list_next(
+        &self,
+        prefix: &str,
+        cursor: impl Fn(&mut ListingCursors) -> &mut Option<String>,
+    ) -> Result<Vec<ObjectSummary>> {
+        let start_after = self.cursors.lock().ok().and_then(|mut cursors| cursor(&mut *cursors).clone());
+        let limit = self.storage_config.audit_sample_size;
+        let objects = self.storage.list(prefix, start_after.as_deref(), limit).await.map_err(storage_error)?;
+
+        if let Ok(mut cursors) = self.cursors.lock() {
+            *cursor(&mut *cursors) =
+                if objects.len() < limit { None } else { objects.last().map(|object| object.key.clone()) };
+        }
+        Ok(objects)
+    }
+
+    /// Keys of the objects old enough that a missing row cannot be an upload still being recorded.
+    fn settled(&self, objects: &[ObjectSummary]) -> Vec<String> {
+        let min_age = Duration::seconds(i64::try_from(self.storage_config.audit_min_age_secs).unwrap_or(i64::MAX));
+        let cutoff = OffsetDateTime::now_utc() - min_age;
+        objects
+            .iter()
+            .filter(|object| object.last_modified.is_some_and(|modified| modified <= cutoff))
+            .map(|object| object.key.clone())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        match self.storage.head(key).await {
+            Ok(_) => Ok(true),
+            Err(StorageError::NotFound) => Ok(false),
+            Err(e) => Err(storage_error(e)),
+        }
+    }
+}
+
+fn storage_error(e: StorageError) -> AppError {
+    AppError::InternalMsg(format!("Storage audit: {e}"))
+}
+
+enum AttachmentKey {
+    Id(Uuid),
+    Digest(Vec<u8>),
+}
+
+fn parse_attachment_key(prefix: &str, key: &str) -> Option<AttachmentKey> {
+    let name = key.strip_prefix(prefix)?;
+    if let Some(digest) = name.strip_prefix("sha256/") {
+        return hex::decode(digest).ok().filter(|bytes| bytes.len() == 32).map(AttachmentKey::Digest);
+    }
+    Uuid::parse_str(name).ok().map(AttachmentKey::Id)
+}
+
+fn parse_backup_key(prefix: &str, key: &str) -> Option<(Uuid, i32)> {
+    let (device_id, version) = key.strip_prefix(prefix)?.split_once("/v")?;
+    Some((Uuid::parse_str(device_id).ok()?, version.parse().ok()?))
+}
+
+/// Picks a random ID to start a sample at.
+///
+/// Time-ordered IDs all fall in the narrow range between the oldest row's creation and now, so a
+/// start drawn from the whole ID space would nearly always land outside it and every audit would
+/// sample the same rows. With time-ordered IDs the start is drawn from that range instead.
+fn sample_start(lowest: Option<Uuid>) -> Uuid {
+    let Some((secs, nanos)) = lowest.and_then(|id| id.get_timestamp()).map(|ts| ts.to_unix()) else {
+        return Uuid::new_v4();
+    };
+    let oldest_ms = secs.saturating_mul(1000).saturating_add(u64::from(nanos / 1_000_000));
+    let now_ms = u64::try_from(OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000).unwrap_or(0);
+    let ms = rand::random_range(oldest_ms..=now_ms.max(oldest_ms));
+    Uuid::new_v7(uuid::Timestamp::from_unix(
+        uuid::NoContext,
+        ms / 1000,
+        u32::try_from(ms % 1000).unwrap_or(0) * 1_000_000,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_object_keys() {
+        let id = Uuid::new_v4();
+        assert!(matches!(
+            parse_attachment_key("attachments/", &format!("attachments/{id}")),
+            Some(AttachmentKey::Id(parsed)) if parsed == id
+        ));
+        assert!(matches!(
+            parse_attachment_key("attachments/", &format!("attachments/sha256/{}", "ab".repeat(32))),
+            Some(AttachmentKey::Digest(digest)) if digest.len() == 32
+        ));
+        assert!(parse_attachment_key("attachments/", "attachments/sha256/abcd").is_none());
+        assert!(parse_attachment_key("attachments/", &format!("other/{id}")).is_none());
+
+        assert_eq!(parse_backup_key("backups/", &format!("backups/{id}/v3")), Some((id, 3)));
+        assert_eq!(parse_backup_key("backups/", &format!("backups/{id}/latest")), None);
+    }
+
+    #[test]
+    fn test_sample_start_stays_within_time_ordered_range() {
+        let lowest = Uuid::new_v7(uuid::Timestamp::from_unix(uuid::NoContext, 1_700_000_000, 0));
+        for _ in 0..100 {
+            let start = sample_start(Some(lowest));
+            assert!(start >= lowest && start <= Uuid::now_v7());
+        }
+        // Random IDs carry no creation time to bound the range with
+        assert_eq!(sample_start(Some(Uuid::new_v4())).get_version_num(), 4);
+    }
+}
@@ -0,0 +1,132 @@
+#![allow(
+    clippy::unwrap_used,
+    clippy::panic,
+    clippy::todo,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate,
+    missing_debug_implementations,
+    clippy::cast_precision_loss,
+    clippy::clone_on_ref_ptr,
+    clippy::match_same_arms,
+    clippy::items_after_statements,
+    unreachable_pub,
+    clippy::print_stdout,
+    clippy::similar_names
+)]
+use obscura_server::proto::obscura::v1 as proto;
+use prost::Message;
+use reqwest::StatusCode;
+use serde_json::json;
+
+mod common;
+
+#[tokio::test]
+async fn test_deleted_account_is_gone_and_username_reserved() {
+    let app = common::TestApp::spawn().await;
+    let username = common::generate_username("deleted");
+    let deleted = app.register_user(&username).await;
+    let contact = app.register_user(&common::generate_username("contact")).await;
+
+    let resp = app
+        .client
+        .delete(format!("{}/v1/users/me", app.server_url))
+        .header("Authorization", format!("Bearer {}", deleted.token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+    let resp = app
+        .client
+        .get(format!("{}/v1/users/{}", app.server_url, deleted.user_id))
+        .header("Authorization", format!("Bearer {}", contact.token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::GONE, "Former contacts must learn the account was deleted");
+
+    let resp = app
+        .client
+        .get(format!("{}/v1/users/{}", app.server_url, uuid::Uuid::new_v4()))
+        .header("Authorization", format!("Bearer {}", contact.token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND, "Unknown users must stay indistinguishable from missing keys");
+
+    let resp = app
+        .client
+        .post(format!("{}/v1/users", app.server_url))
+        .json(&json!({ "username": username, "password": "password12345" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::CONFLICT, "Username must stay reserved during the grace period");
+
+    let resp = app
+        .client
+        .delete(format!("{}/v1/users/me", app.server_url))
+        .header("Authorization", format!("Bearer {}", deleted.token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_username_reusable_after_grace_period() {
+    let mut config = common::get_test_config();
+    config.auth.username_reuse_grace_days = 0;
+    let app = common::TestApp::spawn_with_config(config).await;
+    let username = common::generate_username("reuse");
+    let original = app.register_user(&username).await;
+
+    let resp = app
+        .client
+        .delete(format!("{}/v1/users/me", app.server_url))
+        .header("Authorization", format!("Bearer {}", original.token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+    let successor = app.register_user(&username).await;
+    assert_ne!(successor.user_id, original.user_id);
+}
+
+#[tokio::test]
+async fn test_senders_learn_their_pending_messages_are_undeliverable() {
+    let app = common::TestApp::spawn().await;
+    let sender = app.register_user(&common::generate_username("gone_sender")).await;
+    let deleted = app.register_user(&common::generate_username("gone_recipient")).await;
+
+    app.send_messages(&sender.token, &[(deleted.device_id, b"first"), (deleted.device_id, b"second")]).await;
+
+    let resp = app
+        .client
+        .delete(format!("{}/v1/users/me", app.server_url))
+        .header("Authorization", format!("Bearer {}", deleted.token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+    let mut ws = app.connect_ws(&sender.token).await;
+    let envelope = ws.receive_envelope().await.expect("Sender was not told the recipient is gone");
+    assert!(envelope.sender_id.is_empty(), "Notices are system envelopes");
+    assert_eq!(envelope.system_code, proto::SystemCode::RecipientGone as i32);
+
+    let notice = proto::RecipientGone::decode(envelope.message.as_slice()).unwrap();
+    assert_eq!(notice.user_id, deleted.user_id.as_bytes().to_vec());
+    assert_eq!(notice.submission_ids.len(), 2);
+
+    let undelivered: i32 = sqlx::query_scalar(
+        "SELECT undelivered FROM account_tombstone_notices WHERE user_id = $1 AND sender_device_id = $2",
+    )
+    .bind(deleted.user_id)
+    .bind(sender.device_id)
+    .fetch_one(&app.pool)
+    .await
+    .unwrap();
+    assert_eq!(undelivered, 2);
+}
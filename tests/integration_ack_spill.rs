@@ -0,0 +1,81 @@
+#![allow(
+    clippy::unwrap_used,
+    clippy::panic,
+    clippy::todo,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate,
+    missing_debug_implementations,
+    clippy::cast_precision_loss,
+    clippy::clone_on_ref_ptr,
+    clippy::match_same_arms,
+    clippy::items_after_statements,
+    unreachable_pub,
+    clippy::print_stdout,
+    clippy::similar_names
+)]
+use common::TestApp;
+use obscura_server::adapters::database::message_repo::MessageRepository;
+use obscura_server::adapters::redis::AckSpill;
+use obscura_server::adapters::redis::ack_spill::SpilledAck;
+use obscura_server::workers::AckSpillWorker;
+
+mod common;
+
+fn spill(app: &TestApp, max_batches: usize) -> AckSpill {
+    AckSpill::new(app.redis(), app.config.websocket.ack_spill_key.clone(), max_batches)
+}
+
+#[tokio::test]
+async fn test_spilled_acks_are_deleted_on_replay() {
+    let app = TestApp::spawn().await;
+    let sender = app.register_user(&common::generate_username("spill_sender")).await;
+    let recipient = app.register_user(&common::generate_username("spill_recipient")).await;
+    app.send_message(&sender.token, recipient.device_id, b"acknowledged during an outage").await;
+
+    let message_id: uuid::Uuid = sqlx::query_scalar("SELECT id FROM messages WHERE device_id = $1")
+        .bind(recipient.device_id)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+
+    let spill = spill(&app, 100);
+    spill.push(&SpilledAck { device_id: recipient.device_id, message_ids: vec![message_id] }).await.unwrap();
+    assert_eq!(spill.len().await.unwrap(), 1);
+
+    let worker = AckSpillWorker::new(app.pool.clone(), MessageRepository::new(), Some(spill.clone()), 1);
+    worker.replay().await.unwrap();
+
+    app.assert_message_count(recipient.device_id, 0).await;
+    assert_eq!(spill.len().await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_spill_drops_the_oldest_batches_beyond_its_cap() {
+    let app = TestApp::spawn().await;
+    let spill = spill(&app, 2);
+    let batches: Vec<SpilledAck> = (0..3)
+        .map(|_| SpilledAck { device_id: uuid::Uuid::new_v4(), message_ids: vec![uuid::Uuid::new_v4()] })
+        .collect();
+    for batch in &batches {
+        spill.push(batch).await.unwrap();
+    }
+
+    assert_eq!(spill.take(10).await.unwrap(), batches[1..]);
+    assert!(spill.take(10).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_requeued_batches_go_first_and_are_not_trimmed() {
+    let app = TestApp::spawn().await;
+    let spill = spill(&app, 2);
+    let batches: Vec<SpilledAck> = (0..4)
+        .map(|_| SpilledAck { device_id: uuid::Uuid::new_v4(), message_ids: vec![uuid::Uuid::new_v4()] })
+        .collect();
+    spill.push(&batches[2]).await.unwrap();
+    spill.push(&batches[3]).await.unwrap();
+
+    // Taken earlier by a replay that failed, and put back into a list that is already full
+    spill.requeue(&batches[..2]).await.unwrap();
+
+    assert_eq!(spill.take(10).await.unwrap(), batches);
+}
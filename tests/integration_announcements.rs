@@ -0,0 +1,110 @@
+#![allow(
+    clippy::unwrap_used,
+    clippy::panic,
+    clippy::todo,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate,
+    missing_debug_implementations,
+    clippy::cast_precision_loss,
+    clippy::clone_on_ref_ptr,
+    clippy::match_same_arms,
+    clippy::items_after_statements,
+    unreachable_pub,
+    clippy::print_stdout,
+    clippy::similar_names
+)]
+mod common;
+
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use ed25519_dalek::Verifier;
+use obscura_server::adapters::database::announcement_repo::AnnouncementRepository;
+use obscura_server::proto::obscura::v1 as proto;
+use obscura_server::workers::AnnouncementWorker;
+use prost::Message;
+use serde_json::json;
+use std::time::Duration;
+use uuid::Uuid;
+
+fn verify(signed: &proto::SignedAnnouncement, public_key: &str) -> proto::Announcement {
+    let key: [u8; 32] = STANDARD.decode(public_key).unwrap().try_into().unwrap();
+    let signature: [u8; 64] = signed.signature.clone().try_into().unwrap();
+    ed25519_dalek::VerifyingKey::from_bytes(&key)
+        .unwrap()
+        .verify(&signed.announcement, &ed25519_dalek::Signature::from_bytes(&signature))
+        .expect("Announcement signature must verify");
+    proto::Announcement::decode(signed.announcement.as_slice()).unwrap()
+}
+
+#[tokio::test]
+async fn test_announcement_reaches_online_and_offline_users_once() {
+    let mut config = common::get_test_config();
+    config.announcements.offline_delay_secs = 0;
+    let app = common::TestApp::spawn_with_config(config.clone()).await;
+
+    let online = app.register_user(&common::generate_username("announce_online")).await;
+    let offline = app.register_user(&common::generate_username("announce_offline")).await;
+
+    let mut ws = app.connect_ws(&online.token).await;
+    ws.ensure_subscribed().await;
+
+    let resp = app
+        .client
+        .post(format!("{}/announcements", app.mgmt_url))
+        .json(&json!({ "kind": "MAINTENANCE", "body": "Down for maintenance at 02:00 UTC", "ttlSecs": 3600 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let id = Uuid::parse_str(body["id"].as_str().unwrap()).unwrap();
+    let public_key = body["publicKey"].as_str().unwrap().to_string();
+
+    // 1. The connected user gets a live, signed frame
+    let signed = ws.receive_announcement_timeout(Duration::from_secs(5)).await.expect("Live announcement frame");
+    let announcement = verify(&signed, &public_key);
+    assert_eq!(announcement.id, id.as_bytes().to_vec());
+    assert_eq!(announcement.body, "Down for maintenance at 02:00 UTC");
+    assert_eq!(announcement.kind, proto::announcement::Kind::Maintenance as i32);
+
+    // 2. Everyone else gets it queued as a system envelope; the online user is skipped
+    let worker = AnnouncementWorker::new(app.pool.clone(), AnnouncementRepository::new(), config.announcements.clone());
+    assert!(worker.queue_for_offline_users().await.unwrap() >= 1);
+
+    let system_envelopes = |device_id: Uuid| {
+        sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM messages WHERE device_id = $1 AND sender_id IS NULL AND submission_id = $2",
+        )
+        .bind(device_id)
+        .bind(id)
+        .fetch_one(&app.pool)
+    };
+    assert_eq!(system_envelopes(online.device_id).await.unwrap(), 0, "Online user already has it");
+    assert_eq!(system_envelopes(offline.device_id).await.unwrap(), 1);
+
+    let mut offline_ws = app.connect_ws(&offline.token).await;
+    let envelope = offline_ws.receive_envelope().await.expect("System envelope");
+    assert!(envelope.sender_id.is_empty(), "System envelopes have no sender");
+    let signed = proto::SignedAnnouncement::decode(envelope.message.as_slice()).unwrap();
+    assert_eq!(verify(&signed, &public_key).id, id.as_bytes().to_vec());
+    assert!(
+        offline_ws.receive_announcement_timeout(Duration::from_millis(500)).await.is_none(),
+        "Queued announcements are not sent again as frames"
+    );
+
+    // 3. Reconnecting does not redeliver
+    drop(ws);
+    let mut ws = app.connect_ws(&online.token).await;
+    ws.ensure_subscribed().await;
+    assert!(ws.receive_announcement_timeout(Duration::from_millis(500)).await.is_none());
+}
+
+#[tokio::test]
+async fn test_announcement_validation() {
+    let app = common::TestApp::spawn().await;
+    let post =
+        |payload: serde_json::Value| app.client.post(format!("{}/announcements", app.mgmt_url)).json(&payload).send();
+
+    assert_eq!(post(json!({ "kind": "GOSSIP", "body": "hello" })).await.unwrap().status(), 400);
+    assert_eq!(post(json!({ "kind": "NOTICE", "body": "   " })).await.unwrap().status(), 400);
+    assert_eq!(post(json!({ "kind": "NOTICE", "body": "hi", "ttlSecs": 0 })).await.unwrap().status(), 400);
+}
@@ -13,7 +13,11 @@
     clippy::print_stdout,
     clippy::similar_names
 )]
+use obscura_server::proto::obscura::v1 as proto;
+use prost::Message as _;
 use reqwest::StatusCode;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::protocol::Message;
 use uuid::Uuid;
 
 mod common;
@@ -194,6 +198,42 @@ async fn test_attachment_min_size() {
     assert_eq!(resp_ok.status(), StatusCode::CREATED);
 }
 
+#[tokio::test]
+async fn test_attachment_content_sha256_mismatch() {
+    use sha2::{Digest, Sha256};
+
+    let mut config = common::get_test_config();
+    config.storage.bucket = format!("test-att-sha-{}", &Uuid::new_v4().to_string()[..8]);
+
+    let app = common::TestApp::spawn_with_config(config.clone()).await;
+    common::ensure_storage_bucket(&app.s3_client, &config.storage.bucket).await;
+
+    let user = app.register_user(&common::generate_username("att_sha")).await;
+    let content = vec![7u8; 64];
+
+    let resp = app
+        .client
+        .post(format!("{}/v1/attachments", app.server_url))
+        .header("Authorization", format!("Bearer {}", user.token))
+        .header("X-Content-SHA256", hex::encode(Sha256::digest([0u8; 64])))
+        .body(content.clone())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let resp_ok = app
+        .client
+        .post(format!("{}/v1/attachments", app.server_url))
+        .header("Authorization", format!("Bearer {}", user.token))
+        .header("X-Content-SHA256", hex::encode(Sha256::digest(&content)))
+        .body(content)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp_ok.status(), StatusCode::CREATED);
+}
+
 #[tokio::test]
 async fn test_attachment_conditional_download() {
     let mut config = common::get_test_config();
@@ -241,3 +281,387 @@ async fn test_attachment_conditional_download() {
     assert_eq!(resp_200.status(), StatusCode::OK);
     assert_eq!(resp_200.bytes().await.unwrap(), content.to_vec());
 }
+
+#[tokio::test]
+async fn test_attachment_dedup_by_digest() {
+    use obscura_server::adapters::database::attachment_repo::AttachmentRepository;
+    use obscura_server::adapters::storage::S3Storage;
+    use obscura_server::workers::AttachmentCleanupWorker;
+    use sha2::{Digest, Sha256};
+    use std::sync::Arc;
+
+    let mut config = common::get_test_config();
+    config.storage.bucket = format!("test-att-dedup-{}", &Uuid::new_v4().to_string()[..8]);
+
+    let app = common::TestApp::spawn_with_config(config.clone()).await;
+    common::ensure_storage_bucket(&app.s3_client, &config.storage.bucket).await;
+
+    let user = app.register_user(&common::generate_username("att_dedup")).await;
+    let content = b"Forwarded attachment".to_vec();
+    let digest = hex::encode(Sha256::digest(&content));
+    let by_digest_url = format!("{}/v1/attachments/by-digest/{}", app.server_url, digest);
+
+    // 1. Nothing stored yet: the client has to upload
+    let resp_miss =
+        app.client.post(&by_digest_url).header("Authorization", format!("Bearer {}", user.token)).send().await.unwrap();
+    assert_eq!(resp_miss.status(), StatusCode::NOT_FOUND);
+
+    // 2. Upload with a digest returns the content key
+    let resp_up = app
+        .client
+        .post(format!("{}/v1/attachments", app.server_url))
+        .header("Authorization", format!("Bearer {}", user.token))
+        .header("X-Content-SHA256", &digest)
+        .body(content.clone())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp_up.status(), StatusCode::CREATED);
+    let up_json: serde_json::Value = resp_up.json().await.unwrap();
+    assert_eq!(up_json["contentKey"], digest);
+    let original_id: Uuid = up_json["id"].as_str().unwrap().parse().unwrap();
+
+    // 3. A second attachment is registered without uploading
+    let resp_hit =
+        app.client.post(&by_digest_url).header("Authorization", format!("Bearer {}", user.token)).send().await.unwrap();
+    assert_eq!(resp_hit.status(), StatusCode::CREATED);
+    let hit_json: serde_json::Value = resp_hit.json().await.unwrap();
+    assert_eq!(hit_json["contentKey"], digest);
+    assert!(hit_json["expiresAt"].is_i64());
+    let linked_id: Uuid = hit_json["id"].as_str().unwrap().parse().unwrap();
+    assert_ne!(linked_id, original_id);
+
+    let resp_down = app
+        .client
+        .get(format!("{}/v1/attachments/{}", app.server_url, linked_id))
+        .header("Authorization", format!("Bearer {}", user.token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp_down.status(), StatusCode::OK);
+    assert_eq!(resp_down.bytes().await.unwrap(), content);
+
+    // 4. Expiring the original keeps the shared object for the linked attachment
+    let expire = |id: Uuid| {
+        sqlx::query("UPDATE attachments SET expires_at = NOW() - INTERVAL '1 day' WHERE id = $1")
+            .bind(id)
+            .execute(&app.pool)
+    };
+    expire(original_id).await.unwrap();
+
+    let storage_adapter = Arc::new(S3Storage::new(app.s3_client.clone(), config.storage.bucket.clone()));
+    let worker = AttachmentCleanupWorker::new(
+        app.pool.clone(),
+        AttachmentRepository::new(),
+        storage_adapter,
+        config.attachment.clone(),
+    );
+    worker.cleanup_batch().await.expect("Worker cleanup failed");
+
+    let key = format!("{}sha256/{}", config.attachment.prefix, digest);
+    let head_res = app.s3_client.head_object().bucket(&config.storage.bucket).key(&key).send().await;
+    assert!(head_res.is_ok(), "Shared object should survive while referenced");
+
+    // 5. Once the last reference expires the object is deleted
+    expire(linked_id).await.unwrap();
+    worker.cleanup_batch().await.expect("Worker cleanup failed");
+
+    let head_res = app.s3_client.head_object().bucket(&config.storage.bucket).key(&key).send().await;
+    assert!(head_res.is_err(), "Shared object should be deleted with its last reference");
+
+    let resp_gone =
+        app.client.post(&by_digest_url).header("Authorization", format!("Bearer {}", user.token)).send().await.unwrap();
+    assert_eq!(resp_gone.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_pending_digest_upload_keeps_shared_object() {
+    use obscura_server::adapters::database::attachment_repo::AttachmentRepository;
+    use obscura_server::adapters::storage::S3Storage;
+    use obscura_server::workers::AttachmentCleanupWorker;
+    use sha2::{Digest, Sha256};
+    use std::sync::Arc;
+
+    let mut config = common::get_test_config();
+    config.storage.bucket = format!("test-att-pending-{}", &Uuid::new_v4().to_string()[..8]);
+
+    let app = common::TestApp::spawn_with_config(config.clone()).await;
+    common::ensure_storage_bucket(&app.s3_client, &config.storage.bucket).await;
+
+    let user = app.register_user(&common::generate_username("att_pending")).await;
+    let content = b"Content uploaded twice".to_vec();
+    let digest = Sha256::digest(&content).to_vec();
+    let digest_hex = hex::encode(&digest);
+
+    let resp = app
+        .client
+        .post(format!("{}/v1/attachments", app.server_url))
+        .header("Authorization", format!("Bearer {}", user.token))
+        .header("X-Content-SHA256", &digest_hex)
+        .body(content.clone())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let original_id: Uuid = resp.json::<serde_json::Value>().await.unwrap()["id"].as_str().unwrap().parse().unwrap();
+
+    // A second upload of the same bytes is in flight: its row is recorded, its object not yet rewritten
+    sqlx::query(
+        "INSERT INTO attachments (id, owner_id, size_bytes, expires_at, content_digest, pending)
+         VALUES ($1, $2, 0, NOW() + INTERVAL '1 day', $3, TRUE)",
+    )
+    .bind(Uuid::now_v7())
+    .bind(user.user_id)
+    .bind(&digest)
+    .execute(&app.pool)
+    .await
+    .unwrap();
+    sqlx::query("UPDATE attachments SET expires_at = NOW() - INTERVAL '1 day' WHERE id = $1")
+        .bind(original_id)
+        .execute(&app.pool)
+        .await
+        .unwrap();
+
+    let storage_adapter = Arc::new(S3Storage::new(app.s3_client.clone(), config.storage.bucket.clone()));
+    let worker = AttachmentCleanupWorker::new(
+        app.pool.clone(),
+        AttachmentRepository::new(),
+        storage_adapter,
+        config.attachment.clone(),
+    );
+    worker.cleanup_batch().await.expect("Worker cleanup failed");
+
+    let key = format!("{}sha256/{}", config.attachment.prefix, digest_hex);
+    let head_res = app.s3_client.head_object().bucket(&config.storage.bucket).key(&key).send().await;
+    assert!(head_res.is_ok(), "An upload in flight should keep the shared object");
+
+    // Content is only reused once an upload of it has completed
+    let resp = app
+        .client
+        .post(format!("{}/v1/attachments/by-digest/{}", app.server_url, digest_hex))
+        .header("Authorization", format!("Bearer {}", user.token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_attachment_cleanup_in_batches() {
+    use obscura_server::adapters::database::attachment_repo::AttachmentRepository;
+    use obscura_server::adapters::storage::S3Storage;
+    use obscura_server::workers::AttachmentCleanupWorker;
+    use std::sync::Arc;
+    use time::{Duration, OffsetDateTime};
+
+    let mut config = common::get_test_config();
+    config.storage.bucket = format!("test-att-batch-{}", &Uuid::new_v4().to_string()[..8]);
+    config.attachment.cleanup_batch_size = 2;
+
+    let app = common::TestApp::spawn_with_config(config.clone()).await;
+    common::ensure_storage_bucket(&app.s3_client, &config.storage.bucket).await;
+
+    // 1. Seed more expired attachments than fit in one batch, plus one whose object is already gone
+    let expires_at = OffsetDateTime::now_utc() - Duration::days(1);
+    let ids: Vec<Uuid> = (0..5).map(|_| Uuid::new_v4()).collect();
+    for (i, id) in ids.iter().enumerate() {
+        sqlx::query("INSERT INTO attachments (id, expires_at) VALUES ($1, $2)")
+            .bind(id)
+            .bind(expires_at)
+            .execute(&app.pool)
+            .await
+            .unwrap();
+
+        if i > 0 {
+            app.s3_client
+                .put_object()
+                .bucket(&config.storage.bucket)
+                .key(format!("{}{}", config.attachment.prefix, id))
+                .body(aws_sdk_s3::primitives::ByteStream::from(b"expired data".to_vec()))
+                .send()
+                .await
+                .unwrap();
+        }
+    }
+
+    // 2. Run the worker
+    let storage_adapter = Arc::new(S3Storage::new(app.s3_client.clone(), config.storage.bucket.clone()));
+    let worker = AttachmentCleanupWorker::new(
+        app.pool.clone(),
+        AttachmentRepository::new(),
+        storage_adapter,
+        config.attachment.clone(),
+    );
+    let deleted_count = worker.cleanup_batch().await.expect("Worker cleanup failed");
+    assert!(deleted_count >= 5);
+
+    // 3. Every row and object is gone
+    let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM attachments WHERE id = ANY($1)")
+        .bind(&ids)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining, 0, "All expired attachment records should be deleted");
+
+    for id in &ids {
+        let key = format!("{}{}", config.attachment.prefix, id);
+        let head_res = app.s3_client.head_object().bucket(&config.storage.bucket).key(&key).send().await;
+        assert!(head_res.is_err(), "Attachment object {key} should be deleted from S3");
+    }
+}
+
+#[tokio::test]
+async fn test_attachment_upload_progress_frames() {
+    let mut config = common::get_test_config();
+    config.storage.bucket = format!("test-bucket-{}", &Uuid::new_v4().to_string()[..8]);
+    config.attachment.progress_step_percent = 25;
+
+    let app = common::TestApp::spawn_with_config(config.clone()).await;
+    common::ensure_storage_bucket(&app.s3_client, &config.storage.bucket).await;
+
+    let user = app.register_user(&common::generate_username("att_progress")).await;
+    let mut ws = app.connect_ws(&user.token).await;
+    ws.ensure_subscribed().await;
+
+    let chunks: Vec<Result<Vec<u8>, std::io::Error>> = (0..4).map(|_| Ok(vec![7u8; 1024])).collect();
+    let resp = app
+        .client
+        .post(format!("{}/v1/attachments", app.server_url))
+        .header("Authorization", format!("Bearer {}", user.token))
+        .header("Content-Length", "4096")
+        .header("X-Upload-Progress", "true")
+        .body(reqwest::Body::wrap_stream(futures::stream::iter(chunks)))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let json: serde_json::Value = resp.json().await.unwrap();
+    let attachment_id = Uuid::parse_str(json["id"].as_str().unwrap()).unwrap();
+
+    let mut progress = Vec::new();
+    while let Some(Ok(msg)) = ws.receive_raw_timeout(Duration::from_secs(2)).await {
+        let Message::Binary(bin) = msg else { continue };
+        if let Ok(proto::WebSocketFrame { payload: Some(proto::web_socket_frame::Payload::UploadProgress(p)) }) =
+            proto::WebSocketFrame::decode(bin.as_ref())
+        {
+            progress.push(p);
+        }
+        if progress.last().is_some_and(|p| p.percent == 100) {
+            break;
+        }
+    }
+
+    let last = progress.last().expect("No upload progress frames received");
+    assert_eq!(last.attachment_id, attachment_id.as_bytes().to_vec());
+    assert_eq!(last.percent, 100);
+    assert_eq!(last.bytes_received, 4096);
+    assert_eq!(last.total_bytes, 4096);
+    assert!(progress.windows(2).all(|w| w[0].percent < w[1].percent));
+
+    // Without the header the upload is not reported
+    let resp = app
+        .client
+        .post(format!("{}/v1/attachments", app.server_url))
+        .header("Authorization", format!("Bearer {}", user.token))
+        .body(vec![7u8; 4096])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    while let Some(Ok(msg)) = ws.receive_raw_timeout(Duration::from_millis(500)).await {
+        if let Message::Binary(bin) = msg {
+            let frame = proto::WebSocketFrame::decode(bin.as_ref()).unwrap();
+            assert!(!matches!(frame.payload, Some(proto::web_socket_frame::Payload::UploadProgress(_))));
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_attachment_expiry_extension() {
+    use obscura_server::adapters::database::attachment_repo::AttachmentRepository;
+    use obscura_server::adapters::storage::S3Storage;
+    use obscura_server::workers::AttachmentCleanupWorker;
+    use std::sync::Arc;
+
+    let mut config = common::get_test_config();
+    config.storage.bucket = format!("test-att-extend-{}", &Uuid::new_v4().to_string()[..8]);
+    config.ttl_days = 1;
+    config.attachment.max_lifetime_days = 3;
+
+    let app = common::TestApp::spawn_with_config(config.clone()).await;
+    common::ensure_storage_bucket(&app.s3_client, &config.storage.bucket).await;
+
+    let user = app.register_user(&common::generate_username("att_extend")).await;
+    let content = b"late recipient";
+    let resp_up = app
+        .client
+        .post(format!("{}/v1/attachments", app.server_url))
+        .header("Authorization", format!("Bearer {}", user.token))
+        .header("Content-Length", content.len().to_string())
+        .body(content.to_vec())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp_up.status(), StatusCode::CREATED);
+    let up_json: serde_json::Value = resp_up.json().await.unwrap();
+    let attachment_id = up_json["id"].as_str().unwrap().to_string();
+    let original_expiry = up_json["expiresAt"].as_i64().unwrap();
+
+    let extend = |id: String, body: serde_json::Value| {
+        app.client
+            .post(format!("{}/v1/attachments/{}/extend", app.server_url, id))
+            .header("Authorization", format!("Bearer {}", user.token))
+            .json(&body)
+            .send()
+    };
+
+    // 1. Extending moves the expiry later
+    let resp = extend(attachment_id.clone(), serde_json::json!({ "ttlSecs": 2 * 86400 })).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let json: serde_json::Value = resp.json().await.unwrap();
+    let extended = json["expiresAt"].as_i64().unwrap();
+    assert!(extended >= original_expiry + 86400 - 5, "Expiry should move about a day later");
+
+    // 2. A shorter request never pulls the expiry earlier
+    let resp = extend(attachment_id.clone(), serde_json::json!({ "ttlSecs": 60 })).await.unwrap();
+    let json: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(json["expiresAt"].as_i64().unwrap(), extended);
+
+    // 3. The expiry is capped at the maximum lifetime since upload
+    let resp = extend(attachment_id.clone(), serde_json::json!({ "ttlSecs": 30 * 86400 })).await.unwrap();
+    let json: serde_json::Value = resp.json().await.unwrap();
+    let capped = json["expiresAt"].as_i64().unwrap();
+    assert!(capped <= original_expiry + 2 * 86400 + 5, "Expiry should stop at the maximum lifetime");
+
+    // 4. Invalid and unknown attachments are rejected
+    let resp = extend(attachment_id.clone(), serde_json::json!({ "ttlSecs": 0 })).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let resp = extend(Uuid::new_v4().to_string(), serde_json::json!({})).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    // 5. Once expired, the attachment can no longer be extended and the worker removes it
+    let id = Uuid::parse_str(&attachment_id).unwrap();
+    sqlx::query("UPDATE attachments SET expires_at = NOW() - INTERVAL '1 minute' WHERE id = $1")
+        .bind(id)
+        .execute(&app.pool)
+        .await
+        .unwrap();
+    let resp = extend(attachment_id.clone(), serde_json::json!({})).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let storage_adapter = Arc::new(S3Storage::new(app.s3_client.clone(), config.storage.bucket.clone()));
+    let worker = AttachmentCleanupWorker::new(
+        app.pool.clone(),
+        AttachmentRepository::new(),
+        storage_adapter,
+        config.attachment.clone(),
+    );
+    worker.cleanup_batch().await.expect("Worker cleanup failed");
+
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM attachments WHERE id = $1)")
+        .bind(id)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert!(!exists, "Expired attachment should be cleaned up");
+}
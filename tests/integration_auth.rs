@@ -89,6 +89,63 @@ async fn test_logout_revokes_refresh_token() {
     assert_eq!(resp_fail.status(), StatusCode::UNAUTHORIZED, "Refresh token should be revoked after logout");
 }
 
+async fn register_push_token(app: &common::TestApp, token: &str, push_token: &str) {
+    let resp = app
+        .client
+        .put(format!("{}/v1/push-tokens", app.server_url))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(&json!({ "token": push_token }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+async fn logout(app: &common::TestApp, token: &str, refresh_token: &str) {
+    let resp = app
+        .client
+        .delete(format!("{}/v1/sessions", app.server_url))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(&json!({ "refreshToken": refresh_token }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+async fn push_token_exists(app: &common::TestApp, device_id: uuid::Uuid) -> bool {
+    sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM push_tokens WHERE device_id = $1)")
+        .bind(device_id)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_logout_clears_push_token_after_last_device_session() {
+    let app = common::TestApp::spawn().await;
+    let username = common::generate_username("logout_push");
+    let user = app.register_user(&username).await;
+    register_push_token(&app, &user.token, "logout_push_token").await;
+
+    // A second session on the same device, e.g. the app logged in again after a reinstall
+    let resp = app
+        .client
+        .post(format!("{}/v1/sessions", app.server_url))
+        .json(&json!({ "username": username, "password": "password12345", "deviceId": user.device_id }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let second: serde_json::Value = resp.json().await.unwrap();
+
+    logout(&app, &user.token, &user.refresh_token).await;
+    assert!(push_token_exists(&app, user.device_id).await, "The device still has a live session");
+
+    logout(&app, second["token"].as_str().unwrap(), second["refreshToken"].as_str().unwrap()).await;
+    assert!(!push_token_exists(&app, user.device_id).await, "A signed-out device must not get wakeups");
+}
+
 #[tokio::test]
 async fn test_refresh_token_expiration() {
     // 1. Setup app with 0-day TTL (immediate expiration)
@@ -140,6 +197,36 @@ async fn test_password_strength() {
     assert_eq!(resp.status(), StatusCode::CREATED);
 }
 
+#[tokio::test]
+async fn test_validation_errors_name_each_field() {
+    let app = common::TestApp::spawn().await;
+
+    let resp = app
+        .client
+        .post(format!("{}/v1/users", app.server_url))
+        .json(&json!({ "username": "no spaces allowed", "password": "short" }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["code"], "validation_failed");
+    let fields: Vec<&str> = body["fields"].as_array().unwrap().iter().map(|f| f["field"].as_str().unwrap()).collect();
+    assert_eq!(fields, ["username", "password"]);
+
+    let resp = app
+        .client
+        .post(format!("{}/v1/sessions/refresh", app.server_url))
+        .json(&json!({ "refreshToken": "not a token" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["fields"][0]["field"], "refreshToken");
+}
+
 #[tokio::test]
 async fn test_request_id_propagation() {
     let app = common::TestApp::spawn().await;
@@ -218,3 +305,78 @@ async fn test_login_with_device_id() {
 
     assert_eq!(resp_keys.status(), StatusCode::OK, "Should be able to use device-scoped token on /keys");
 }
+
+#[tokio::test]
+async fn test_protobuf_auth_and_key_endpoints() {
+    use obscura_server::proto::obscura::v1 as proto;
+    use prost::Message as _;
+
+    const PROTOBUF: &str = "application/x-protobuf";
+
+    let app = common::TestApp::spawn().await;
+    let username = common::generate_username("proto_auth");
+    let user = app.register_user_with_keys(&username, 321, 2).await;
+
+    // 1. Login with a protobuf body; the response follows the request encoding
+    let login = proto::LoginRequest {
+        username: username.clone(),
+        password: "password12345".to_string(),
+        device_id: user.device_id.as_bytes().to_vec(),
+    };
+    let resp = app
+        .client
+        .post(format!("{}/v1/sessions", app.server_url))
+        .header("Content-Type", PROTOBUF)
+        .body(login.encode_to_vec())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers()["content-type"], PROTOBUF);
+    let session = proto::AuthResponse::decode(resp.bytes().await.unwrap()).unwrap();
+    assert_eq!(session.device_id, user.device_id.as_bytes().to_vec());
+
+    // 2. Refresh with protobuf but ask for JSON back
+    let refresh = proto::RefreshSessionRequest { refresh_token: session.refresh_token };
+    let resp = app
+        .client
+        .post(format!("{}/v1/sessions/refresh", app.server_url))
+        .header("Content-Type", PROTOBUF)
+        .header("Accept", "application/json")
+        .body(refresh.encode_to_vec())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert!(body["token"].is_string());
+
+    // 3. Fetch bundles as protobuf
+    let resp = app
+        .client
+        .get(format!("{}/v1/users/{}", app.server_url, user.user_id))
+        .header("Authorization", format!("Bearer {}", session.token))
+        .header("Accept", PROTOBUF)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let bundles = proto::PreKeyBundles::decode(resp.bytes().await.unwrap()).unwrap();
+    assert_eq!(bundles.bundles.len(), 1);
+    let bundle = &bundles.bundles[0];
+    assert_eq!(bundle.device_id, user.device_id.as_bytes().to_vec());
+    assert_eq!(bundle.registration_id, 321);
+    assert_eq!(bundle.identity_key.len(), 33);
+    assert!(bundle.one_time_pre_key.is_some());
+
+    // 4. Malformed protobuf bodies are rejected as bad requests
+    let resp = app
+        .client
+        .post(format!("{}/v1/sessions", app.server_url))
+        .header("Content-Type", PROTOBUF)
+        .body(vec![0xff; 8])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
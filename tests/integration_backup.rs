@@ -407,18 +407,18 @@ async fn test_backup_version_rotation_and_cleanup() {
     let head_v2 = app.s3_client.head_object().bucket(&config.storage.bucket).key(&key_v2).send().await;
     assert!(head_v2.is_ok(), "v2 should exist in S3");
 
-    // 3. Verify v1 is GONE (Cleanup)
-    // The cleanup is fired in a background task, so we might need a small wait
-    let mut v1_deleted = false;
-    for _ in 0..10 {
-        let head_v1_check = app.s3_client.head_object().bucket(&config.storage.bucket).key(&key_v1).send().await;
-        if head_v1_check.is_err() {
-            v1_deleted = true;
-            break;
-        }
-        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-    }
-    assert!(v1_deleted, "Old version v1 should have been deleted from S3");
+    // 3. v1 is soft-deleted: kept for the restore window, then purged by the worker
+    let head_v1_kept = app.s3_client.head_object().bucket(&config.storage.bucket).key(&key_v1).send().await;
+    assert!(head_v1_kept.is_ok(), "v1 should be kept within the restore window");
+
+    let mut purge_config = config.backup.clone();
+    purge_config.restore_window_hours = 0;
+    let storage_adapter = Arc::new(S3Storage::new(app.s3_client.clone(), config.storage.bucket.clone()));
+    let worker = BackupCleanupWorker::new(app.pool.clone(), BackupRepository::new(), storage_adapter, purge_config);
+    assert!(worker.purge_expired_versions().await.expect("Purge failed") >= 1);
+
+    let head_v1_check = app.s3_client.head_object().bucket(&config.storage.bucket).key(&key_v1).send().await;
+    assert!(head_v1_check.is_err(), "Old version v1 should have been deleted from S3");
 
     // 4. Verify Final DB State
     let version: (i32,) = sqlx::query_as("SELECT current_version FROM backups WHERE device_id = $1")
@@ -725,3 +725,144 @@ async fn test_backup_stale_takeover_via_db_state() {
     assert_eq!(dl.status(), StatusCode::OK);
     assert_eq!(dl.bytes().await.unwrap(), b"Takeover backup data".as_ref());
 }
+
+#[tokio::test]
+async fn test_backup_content_sha256_verification() {
+    use sha2::{Digest, Sha256};
+
+    let mut config = common::get_test_config();
+    config.storage.bucket = format!("test-backup-sha-{}", &Uuid::new_v4().to_string()[..8]);
+    config.backup.min_size_bytes = 0;
+
+    let app = common::TestApp::spawn_with_config(config.clone()).await;
+    common::ensure_storage_bucket(&app.s3_client, &config.storage.bucket).await;
+
+    let user = app.register_user(&common::generate_username("backup_sha")).await;
+    let content = b"Checksummed backup";
+    let digest = hex::encode(Sha256::digest(content));
+
+    // 1. Mismatching digest is rejected and nothing is committed
+    let resp = app
+        .client
+        .post(format!("{}/v1/backup", app.server_url))
+        .header("Authorization", format!("Bearer {}", user.token))
+        .header("If-None-Match", "*")
+        .header("X-Content-SHA256", hex::encode(Sha256::digest(b"something else")))
+        .body(content.to_vec())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let resp_head = app
+        .client
+        .head(format!("{}/v1/backup", app.server_url))
+        .header("Authorization", format!("Bearer {}", user.token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp_head.status(), StatusCode::NOT_FOUND);
+
+    // 2. Malformed digest is a client error
+    let resp = app
+        .client
+        .post(format!("{}/v1/backup", app.server_url))
+        .header("Authorization", format!("Bearer {}", user.token))
+        .header("If-None-Match", "*")
+        .header("X-Content-SHA256", "not-hex")
+        .body(content.to_vec())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    // 3. Matching digest succeeds immediately after the failed attempt and is reported by HEAD
+    let resp = app
+        .client
+        .post(format!("{}/v1/backup", app.server_url))
+        .header("Authorization", format!("Bearer {}", user.token))
+        .header("If-None-Match", "*")
+        .header("X-Content-SHA256", &digest)
+        .body(content.to_vec())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp_head = app
+        .client
+        .head(format!("{}/v1/backup", app.server_url))
+        .header("Authorization", format!("Bearer {}", user.token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp_head.status(), StatusCode::OK);
+    assert_eq!(resp_head.headers().get("X-Content-SHA256").unwrap().to_str().unwrap(), digest);
+}
+
+#[tokio::test]
+async fn test_backup_restore_previous_version() {
+    let mut config = common::get_test_config();
+    config.storage.bucket = format!("test-restore-{}", &Uuid::new_v4().to_string()[..8]);
+    config.backup.min_size_bytes = 0;
+
+    let app = common::TestApp::spawn_with_config(config.clone()).await;
+    common::ensure_storage_bucket(&app.s3_client, &config.storage.bucket).await;
+
+    let user = app.register_user(&common::generate_username("restore")).await;
+    let upload = |precondition: (&'static str, &'static str), body: &'static [u8]| {
+        app.client
+            .post(format!("{}/v1/backup", app.server_url))
+            .header("Authorization", format!("Bearer {}", user.token))
+            .header(precondition.0, precondition.1)
+            .body(body)
+            .send()
+    };
+    let restore = || {
+        app.client
+            .post(format!("{}/v1/backup/restore", app.server_url))
+            .header("Authorization", format!("Bearer {}", user.token))
+            .send()
+    };
+    let download = || async {
+        app.client
+            .get(format!("{}/v1/backup", app.server_url))
+            .header("Authorization", format!("Bearer {}", user.token))
+            .send()
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap()
+    };
+
+    assert_eq!(upload(("If-None-Match", "*"), b"first backup").await.unwrap().status(), StatusCode::OK);
+    assert_eq!(restore().await.unwrap().status(), StatusCode::NOT_FOUND, "Nothing to restore yet");
+
+    assert_eq!(upload(("If-Match", "1"), b"second backup").await.unwrap().status(), StatusCode::OK);
+
+    let resp = restore().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers().get("ETag").unwrap(), "\"1\"");
+    assert_eq!(download().await.as_ref(), b"first backup");
+
+    // Undo the restore
+    let resp = restore().await.unwrap();
+    assert_eq!(resp.headers().get("ETag").unwrap(), "\"2\"");
+    assert_eq!(download().await.as_ref(), b"second backup");
+
+    // Uploading after a restore never reuses the version number of the kept object
+    assert_eq!(restore().await.unwrap().status(), StatusCode::OK);
+    let resp = upload(("If-Match", "1"), b"third backup").await.unwrap();
+    assert_eq!(resp.headers().get("ETag").unwrap(), "\"3\"");
+
+    let resp = app
+        .client
+        .post(format!("{}/v1/backup/restore", app.server_url))
+        .header("Authorization", format!("Bearer {}", user.token))
+        .header("If-Match", "1")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::PRECONDITION_FAILED);
+}
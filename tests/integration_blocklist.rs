@@ -0,0 +1,80 @@
+#![allow(
+    clippy::unwrap_used,
+    clippy::panic,
+    clippy::todo,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate,
+    missing_debug_implementations,
+    clippy::cast_precision_loss,
+    clippy::clone_on_ref_ptr,
+    clippy::match_same_arms,
+    clippy::items_after_statements,
+    unreachable_pub,
+    clippy::print_stdout,
+    clippy::similar_names
+)]
+mod common;
+
+use common::TestApp;
+use serde_json::json;
+use uuid::Uuid;
+
+/// A documentation-range network unique to this run, so parallel tests sharing the database are unaffected.
+fn unique_network() -> (String, String) {
+    let b = Uuid::new_v4().into_bytes();
+    let (hi, lo) = (u16::from_be_bytes([b[0], b[1]]), u16::from_be_bytes([b[2], b[3]]));
+    (format!("2001:db8:{hi:x}:{lo:x}::/64"), format!("2001:db8:{hi:x}:{lo:x}::42"))
+}
+
+#[tokio::test]
+async fn test_blocked_network_is_rejected_until_unblocked() {
+    let app = TestApp::spawn().await;
+    let (network, client_ip) = unique_network();
+    let probe = || app.client.get(format!("{}/openapi.yaml", app.server_url)).header("x-forwarded-for", &client_ip);
+
+    assert_eq!(probe().send().await.unwrap().status(), 200);
+
+    let resp = app
+        .client
+        .post(format!("{}/blocklist", app.mgmt_url))
+        .json(&json!({ "network": network, "reason": "credential stuffing" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    let entry: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(entry["network"], network);
+    assert_eq!(entry["source"], "MANAGED");
+
+    assert_eq!(probe().send().await.unwrap().status(), 403);
+    assert_eq!(
+        app.client.get(format!("{}/openapi.yaml", app.server_url)).send().await.unwrap().status(),
+        200,
+        "Other clients must not be affected"
+    );
+
+    let list: serde_json::Value =
+        app.client.get(format!("{}/blocklist", app.mgmt_url)).send().await.unwrap().json().await.unwrap();
+    assert!(list["entries"].as_array().unwrap().iter().any(|e| e["network"] == network));
+
+    let resp = app.client.delete(format!("{}/blocklist?network={network}", app.mgmt_url)).send().await.unwrap();
+    assert_eq!(resp.status(), 204);
+    assert_eq!(probe().send().await.unwrap().status(), 200);
+
+    let resp = app.client.delete(format!("{}/blocklist?network={network}", app.mgmt_url)).send().await.unwrap();
+    assert_eq!(resp.status(), 404);
+}
+
+#[tokio::test]
+async fn test_invalid_network_is_rejected() {
+    let app = TestApp::spawn().await;
+
+    let resp = app
+        .client
+        .post(format!("{}/blocklist", app.mgmt_url))
+        .json(&json!({ "network": "not-a-network" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+}
@@ -1,6 +1,7 @@
 use crate::common::TestApp;
-use obscura_server::adapters::redis::cache::RedisCache;
-use std::sync::Arc;
+use obscura_server::adapters::key_value::KeyValueCache;
+use obscura_server::adapters::redis::RedisClient;
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
 mod common;
@@ -11,8 +12,8 @@ async fn test_redis_cache_basic_operations() {
 
     // Create a new cache instance pointing to the same redis pool
     // Note: We need to access the pubsub client from the app
-    let redis_client = Arc::clone(&app.resources.pubsub);
-    let cache = RedisCache::new(redis_client, "test:cache:".to_string(), 60);
+    let redis_client = app.redis();
+    let cache = KeyValueCache::new(redis_client, "test:cache:".to_string(), 60);
 
     let key = Uuid::new_v4().to_string();
     let value = b"hello world".to_vec();
@@ -39,8 +40,8 @@ async fn test_redis_cache_basic_operations() {
 #[tokio::test]
 async fn test_redis_cache_expiration() {
     let app = TestApp::spawn().await;
-    let redis_client = Arc::clone(&app.resources.pubsub);
-    let cache = RedisCache::new(redis_client, "test:cache:expire:".to_string(), 1);
+    let redis_client = app.redis();
+    let cache = KeyValueCache::new(redis_client, "test:cache:expire:".to_string(), 1);
 
     let key = Uuid::new_v4().to_string();
     let value = b"temporary".to_vec();
@@ -55,3 +56,28 @@ async fn test_redis_cache_expiration() {
     let result = cache.get(&key).await.expect("Failed to get");
     assert_eq!(result, None, "Key should have expired");
 }
+
+#[tokio::test]
+async fn test_request_pool_commands_time_out() {
+    let mut config = common::get_test_config();
+    config.pubsub.request_pool_size = 2;
+    config.pubsub.command_timeout_ms = 200;
+    let redis = RedisClient::new(&config.pubsub, 16, tokio::sync::watch::channel(false).1)
+        .await
+        .expect("Failed to connect to Redis");
+
+    // Every pooled connection serves commands
+    for _ in 0..2 {
+        let mut conn = redis.request_conn();
+        let pong: String = redis::cmd("PING").query_async(&mut conn).await.expect("Failed to ping");
+        assert_eq!(pong, "PONG");
+    }
+
+    // A reply slower than the command timeout fails instead of stalling the caller
+    let mut conn = redis.request_conn();
+    let started = Instant::now();
+    let result: redis::RedisResult<Option<Vec<String>>> =
+        redis::cmd("BLPOP").arg(format!("test:cache:block:{}", Uuid::new_v4())).arg(3).query_async(&mut conn).await;
+    assert!(result.is_err(), "Blocking command should time out");
+    assert!(started.elapsed() < Duration::from_secs(2), "Timeout should fire long before the reply");
+}
@@ -14,12 +14,15 @@
     clippy::similar_names
 )]
 use obscura_server::adapters::database::message_repo::MessageRepository;
+use obscura_server::adapters::storage::S3Storage;
 use obscura_server::config::MessagingConfig;
 use obscura_server::workers::MessageCleanupWorker;
+use std::sync::Arc;
 use time::{Duration, OffsetDateTime};
 use uuid::Uuid;
 
 mod common;
+use common::TestApp;
 
 #[tokio::test]
 async fn test_message_cleanup_worker_full_orchestration() {
@@ -30,7 +33,11 @@ async fn test_message_cleanup_worker_full_orchestration() {
         ..MessagingConfig::default()
     };
 
-    let worker = MessageCleanupWorker::new(pool.clone(), repo.clone(), config);
+    let test_config = common::get_test_config();
+    let s3_client = obscura_server::initialize_s3_client(&test_config.storage, &test_config.outbound).await.unwrap();
+    let storage = Arc::new(S3Storage::new(s3_client, test_config.storage.bucket));
+
+    let worker = MessageCleanupWorker::new(pool.clone(), repo.clone(), storage, config);
 
     // --- Part 1: Overflow Pruning ---
     let user_a = Uuid::new_v4();
@@ -46,13 +53,14 @@ async fn test_message_cleanup_worker_full_orchestration() {
     // Seed 3 messages (exceeds limit of 2)
     for i in 0..3 {
         let msg_id = Uuid::new_v4();
-        sqlx::query("INSERT INTO messages (id, submission_id, sender_id, sender_device_id, device_id, content, created_at, expires_at) VALUES ($1, $6, $2, $2, $2, $3, $4, $5)")
+        sqlx::query("INSERT INTO messages (id, submission_id, sender_id, sender_device_id, device_id, content, created_at, expires_at, seq) VALUES ($1, $6, $2, $2, $2, $3, $4, $5, $7)")
             .bind(msg_id)
             .bind(user_a)
             .bind(format!("msg {i}").into_bytes())
             .bind(OffsetDateTime::now_utc() + Duration::seconds(i))
             .bind(OffsetDateTime::now_utc() + Duration::days(1))
             .bind(Uuid::new_v4())
+            .bind(i)
             .execute(&pool)
             .await
             .unwrap();
@@ -72,7 +80,7 @@ async fn test_message_cleanup_worker_full_orchestration() {
     // Insert one expired message
     let expired_msg_id = Uuid::new_v4();
     let expired_time = OffsetDateTime::now_utc() - Duration::days(1);
-    sqlx::query("INSERT INTO messages (id, submission_id, sender_id, sender_device_id, device_id, content, expires_at) VALUES ($1, $5, $2, $2, $2, $3, $4)")
+    sqlx::query("INSERT INTO messages (id, submission_id, sender_id, sender_device_id, device_id, content, expires_at, seq) VALUES ($1, $5, $2, $2, $2, $3, $4, 1)")
         .bind(expired_msg_id)
         .bind(user_b)
         .bind(b"expired content".to_vec())
@@ -85,7 +93,7 @@ async fn test_message_cleanup_worker_full_orchestration() {
     // Insert one active message
     let active_msg_id = Uuid::new_v4();
     let active_time = OffsetDateTime::now_utc() + Duration::days(1);
-    sqlx::query("INSERT INTO messages (id, submission_id, sender_id, sender_device_id, device_id, content, expires_at) VALUES ($1, $5, $2, $2, $2, $3, $4)")
+    sqlx::query("INSERT INTO messages (id, submission_id, sender_id, sender_device_id, device_id, content, expires_at, seq) VALUES ($1, $5, $2, $2, $2, $3, $4, 2)")
         .bind(active_msg_id)
         .bind(user_b)
         .bind(b"active content".to_vec())
@@ -128,3 +136,78 @@ async fn test_message_cleanup_worker_full_orchestration() {
         .unwrap();
     assert!(active_exists, "Active message should still exist");
 }
+
+#[tokio::test]
+async fn test_refresh_token_cleanup_deletes_expired_in_batches() {
+    let mut config = common::get_test_config();
+    config.auth.refresh_token_cleanup_batch_size = 2;
+    config.auth.refresh_token_cleanup_max_batches = 10_000;
+    let app = TestApp::spawn_with_config(config).await;
+    let user = app.register_user(&common::generate_username("rt_gc")).await;
+
+    let expired: Vec<String> = (0..5).map(|_| format!("expired_{}", Uuid::new_v4())).collect();
+    for hash in &expired {
+        sqlx::query("INSERT INTO refresh_tokens (token_hash, user_id, expires_at) VALUES ($1, $2, $3)")
+            .bind(hash)
+            .bind(user.user_id)
+            .bind(OffsetDateTime::now_utc() - Duration::hours(1))
+            .execute(&app.pool)
+            .await
+            .unwrap();
+    }
+
+    let resp = app.client.post(format!("{}/maintenance/refresh-tokens/cleanup", app.mgmt_url)).send().await.unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert!(body["deleted"].as_u64().unwrap() >= 5, "Expected every seeded token to be counted: {body}");
+
+    let remaining: i64 = sqlx::query_scalar("SELECT count(*) FROM refresh_tokens WHERE token_hash = ANY($1)")
+        .bind(&expired)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining, 0, "Expired tokens should be deleted across batches");
+
+    // Sessions from registration are unexpired and kept
+    let live: i64 = sqlx::query_scalar("SELECT count(*) FROM refresh_tokens WHERE user_id = $1")
+        .bind(user.user_id)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert!(live >= 1, "Unexpired refresh tokens should be kept");
+}
+
+#[tokio::test]
+async fn test_refresh_token_cleanup_clears_push_token_of_signed_out_device() {
+    let app = TestApp::spawn().await;
+    let active = app.register_user(&common::generate_username("rt_push_a")).await;
+    let stale = app.register_user(&common::generate_username("rt_push_s")).await;
+    for user in [&active, &stale] {
+        let resp = app
+            .client
+            .put(format!("{}/v1/push-tokens", app.server_url))
+            .header("Authorization", format!("Bearer {}", user.token))
+            .json(&serde_json::json!({ "token": format!("push_{}", user.device_id) }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+    }
+
+    sqlx::query("UPDATE refresh_tokens SET expires_at = $2 WHERE user_id = $1")
+        .bind(stale.user_id)
+        .bind(OffsetDateTime::now_utc() - Duration::hours(1))
+        .execute(&app.pool)
+        .await
+        .unwrap();
+
+    let resp = app.client.post(format!("{}/maintenance/refresh-tokens/cleanup", app.mgmt_url)).send().await.unwrap();
+    assert_eq!(resp.status(), 200);
+
+    let remaining: Vec<Uuid> = sqlx::query_scalar("SELECT device_id FROM push_tokens WHERE device_id = ANY($1)")
+        .bind(vec![active.device_id, stale.device_id])
+        .fetch_all(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining, vec![active.device_id], "Only the device whose sessions expired loses its push token");
+}
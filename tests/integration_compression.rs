@@ -0,0 +1,90 @@
+#![allow(
+    clippy::unwrap_used,
+    clippy::panic,
+    clippy::todo,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate,
+    missing_debug_implementations,
+    clippy::cast_precision_loss,
+    clippy::clone_on_ref_ptr,
+    clippy::match_same_arms,
+    clippy::items_after_statements,
+    unreachable_pub,
+    clippy::print_stdout,
+    clippy::similar_names
+)]
+mod common;
+
+use common::TestApp;
+use obscura_server::config::RouteClass;
+use obscura_server::proto::obscura::v1 as proto;
+use prost::Message;
+use uuid::Uuid;
+
+/// A batch addressed to unknown devices, so the response lists every submission as failed
+/// and is large enough to be compressed.
+fn failing_batch(count: usize) -> Vec<u8> {
+    let messages = (0..count)
+        .map(|_| proto::send_message_request::Submission {
+            submission_id: Uuid::new_v4().as_bytes().to_vec(),
+            device_id: Uuid::new_v4().as_bytes().to_vec(),
+            message: b"payload".to_vec().into(),
+        })
+        .collect();
+    proto::SendMessageRequest { messages }.encode_to_vec()
+}
+
+async fn send_batch(app: &TestApp, token: &str, body: Vec<u8>) -> reqwest::Response {
+    app.client
+        .post(format!("{}/v1/messages", app.server_url))
+        .header("Authorization", format!("Bearer {token}"))
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .header("Content-Type", "application/x-protobuf")
+        .header("Accept-Encoding", "zstd")
+        .body(body)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_protobuf_response_zstd_round_trip() {
+    let app = TestApp::spawn().await;
+    let user = app.register_user(&common::generate_username("compress")).await;
+
+    let resp = send_batch(&app, &user.token, failing_batch(50)).await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["content-type"], "application/x-protobuf");
+    assert_eq!(resp.headers()["content-encoding"], "zstd");
+
+    let compressed = resp.bytes().await.unwrap();
+    let decoded = zstd::decode_all(compressed.as_ref()).unwrap();
+    let response = proto::SendMessageResponse::decode(decoded.as_slice()).unwrap();
+    assert_eq!(response.failed_submissions.len(), 50);
+}
+
+#[tokio::test]
+async fn test_small_responses_are_not_compressed() {
+    let app = TestApp::spawn().await;
+    let user = app.register_user(&common::generate_username("compress_small")).await;
+
+    let resp = send_batch(&app, &user.token, failing_batch(1)).await;
+    assert_eq!(resp.status(), 200);
+    assert!(resp.headers().get("content-encoding").is_none());
+    let response = proto::SendMessageResponse::decode(resp.bytes().await.unwrap()).unwrap();
+    assert_eq!(response.failed_submissions.len(), 1);
+}
+
+#[tokio::test]
+async fn test_compression_can_be_disabled_per_route_class() {
+    let mut config = common::get_test_config();
+    config.compression.disabled_routes = vec![RouteClass::Api];
+    let app = TestApp::spawn_with_config(config).await;
+    let user = app.register_user(&common::generate_username("compress_off")).await;
+
+    let resp = send_batch(&app, &user.token, failing_batch(50)).await;
+    assert_eq!(resp.status(), 200);
+    assert!(resp.headers().get("content-encoding").is_none());
+    let response = proto::SendMessageResponse::decode(resp.bytes().await.unwrap()).unwrap();
+    assert_eq!(response.failed_submissions.len(), 50);
+}
@@ -0,0 +1,46 @@
+#![allow(
+    clippy::unwrap_used,
+    clippy::panic,
+    clippy::todo,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate,
+    missing_debug_implementations,
+    clippy::cast_precision_loss,
+    clippy::clone_on_ref_ptr,
+    clippy::match_same_arms,
+    clippy::items_after_statements,
+    unreachable_pub,
+    clippy::print_stdout,
+    clippy::similar_names
+)]
+use obscura_server::config::OutboundConfig;
+use obscura_server::conformance::{CASES, Outcome, Suite};
+use std::time::Duration;
+
+mod common;
+use common::TestApp;
+
+#[tokio::test]
+async fn test_conformance_suite_passes_against_the_server() {
+    let app = TestApp::spawn().await;
+
+    let report = Suite::new(&app.server_url, Duration::from_secs(5), &app.config.outbound).unwrap().run().await;
+
+    let names: Vec<&str> = report.cases.iter().map(|case| case.name).collect();
+    assert_eq!(names, CASES);
+    let unpassed: Vec<_> = report.cases.iter().filter(|case| case.outcome != Outcome::Passed).collect();
+    assert!(unpassed.is_empty(), "Cases did not pass: {unpassed:?}");
+}
+
+#[tokio::test]
+async fn test_conformance_suite_skips_everything_after_failed_registration() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    drop(listener);
+
+    let report = Suite::new(&base_url, Duration::from_secs(1), &OutboundConfig::default()).unwrap().run().await;
+
+    assert!(matches!(report.cases[0].outcome, Outcome::Failed { .. }));
+    assert_eq!(report.failures(), 1);
+    assert_eq!(report.skipped(), CASES.len() - 1);
+}
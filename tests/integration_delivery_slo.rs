@@ -0,0 +1,101 @@
+#![allow(
+    clippy::unwrap_used,
+    clippy::panic,
+    clippy::todo,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate,
+    missing_debug_implementations,
+    clippy::cast_precision_loss,
+    clippy::clone_on_ref_ptr,
+    clippy::match_same_arms,
+    clippy::items_after_statements,
+    unreachable_pub,
+    clippy::print_stdout,
+    clippy::similar_names
+)]
+use std::time::Duration;
+use uuid::Uuid;
+
+mod common;
+use common::TestApp;
+
+async fn delivered_seq(app: &TestApp, device_id: Uuid) -> Option<i64> {
+    sqlx::query_scalar("SELECT delivered_seq FROM inbox_sequences WHERE device_id = $1")
+        .bind(device_id)
+        .fetch_optional(&app.pool)
+        .await
+        .unwrap()
+        .flatten()
+}
+
+async fn delivered_seq_is(app: &TestApp, device_id: Uuid, seq: i64) -> bool {
+    delivered_seq(app, device_id).await == Some(seq)
+}
+
+#[tokio::test]
+async fn test_session_records_highest_delivered_sequence() {
+    let app = TestApp::spawn().await;
+    let alice = app.register_user(&common::generate_username("slo_a")).await;
+    let bob = app.register_user(&common::generate_username("slo_b")).await;
+
+    app.send_message(&alice.token, bob.device_id, b"first").await;
+    app.send_message(&alice.token, bob.device_id, b"second").await;
+    let last_seq: i64 = sqlx::query_scalar("SELECT MAX(seq) FROM messages WHERE device_id = $1")
+        .bind(bob.device_id)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(delivered_seq(&app, bob.device_id).await, None);
+
+    // Received but never acknowledged, so the next session delivers them again
+    let mut ws = app.connect_ws(&bob.token).await;
+    ws.receive_envelope().await.expect("first delivered");
+    ws.receive_envelope().await.expect("second delivered");
+    drop(ws);
+
+    let recorded = app.wait_until(|| delivered_seq_is(&app, bob.device_id, last_seq), Duration::from_secs(5)).await;
+    assert!(recorded, "Closing the session should record the highest delivered sequence");
+
+    let mut ws = app.connect_ws(&bob.token).await;
+    let env = ws.receive_envelope().await.expect("redelivered");
+    assert_eq!(env.message, b"first");
+    ws.send_ack(env.id.clone()).await;
+    drop(ws);
+
+    // A session that only repeats old envelopes leaves the mark where it was
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(delivered_seq(&app, bob.device_id).await, Some(last_seq));
+}
+
+#[tokio::test]
+async fn test_ack_flush_records_delivered_sequence_before_session_ends() {
+    let app = TestApp::spawn().await;
+    let alice = app.register_user(&common::generate_username("slo_flush_a")).await;
+    let bob = app.register_user(&common::generate_username("slo_flush_b")).await;
+
+    app.send_message(&alice.token, bob.device_id, b"first").await;
+    app.send_message(&alice.token, bob.device_id, b"second").await;
+    let last_seq: i64 = sqlx::query_scalar("SELECT MAX(seq) FROM messages WHERE device_id = $1")
+        .bind(bob.device_id)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+
+    // Only the first is acknowledged, but the flush records everything written so far
+    let mut ws = app.connect_ws(&bob.token).await;
+    let first = ws.receive_envelope().await.expect("first delivered");
+    ws.receive_envelope().await.expect("second delivered");
+    ws.send_ack(first.id.clone()).await;
+
+    let recorded = app.wait_until(|| delivered_seq_is(&app, bob.device_id, last_seq), Duration::from_secs(5)).await;
+    assert!(recorded, "An ACK flush should record the delivered sequence while the session is open");
+
+    let session_at: Option<time::OffsetDateTime> =
+        sqlx::query_scalar("SELECT delivered_session_at FROM inbox_sequences WHERE device_id = $1")
+            .bind(bob.device_id)
+            .fetch_one(&app.pool)
+            .await
+            .unwrap();
+    assert!(session_at.is_some(), "The session's start should be recorded with the sequence");
+    drop(ws);
+}
@@ -0,0 +1,70 @@
+#![allow(
+    clippy::unwrap_used,
+    clippy::panic,
+    clippy::missing_panics_doc,
+    missing_debug_implementations,
+    unreachable_pub
+)]
+mod common;
+
+use common::TestApp;
+use obscura_server::dev;
+use reqwest::StatusCode;
+use serde_json::json;
+use std::sync::Arc;
+
+async fn seed(app: &TestApp) -> Vec<dev::DevUser> {
+    let (_shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let services = obscura_server::AppBuilder::new(app.config.clone())
+        .with_database(app.pool.clone())
+        .with_pubsub(app.redis())
+        .with_s3(app.s3_client.clone())
+        .with_push_provider(Arc::new(common::SharedMockPushProvider))
+        .with_shutdown_rx(shutdown_rx)
+        .initialize()
+        .await
+        .unwrap()
+        .services;
+    dev::seed(&services).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_seeded_users_can_log_in_and_fetch_each_other() {
+    let app = TestApp::spawn().await;
+
+    let seeded = seed(&app).await;
+    assert_eq!(seeded.iter().map(|u| u.username).collect::<Vec<_>>(), dev::DEV_USERS);
+
+    // Seeding again reuses the accounts and devices
+    let reseeded = seed(&app).await;
+    for (first, second) in seeded.iter().zip(&reseeded) {
+        assert_eq!(first.user_id, second.user_id);
+        assert_eq!(first.device_id, second.device_id);
+    }
+
+    let (alice, bob) = (&seeded[0], &seeded[1]);
+    let resp = app
+        .client
+        .post(format!("{}/v1/sessions", app.server_url))
+        .json(&json!({
+            "username": alice.username,
+            "password": dev::DEV_PASSWORD,
+            "deviceId": alice.device_id.to_string()
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let token = resp.json::<serde_json::Value>().await.unwrap()["token"].as_str().unwrap().to_string();
+
+    let resp = app
+        .client
+        .get(format!("{}/v1/users/{}", app.server_url, bob.user_id))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let bundles = resp.json::<serde_json::Value>().await.unwrap();
+    assert!(bundles.to_string().contains(&bob.device_id.to_string()), "Expected bob's device in {bundles}");
+}
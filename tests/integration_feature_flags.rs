@@ -0,0 +1,118 @@
+#![allow(
+    clippy::unwrap_used,
+    clippy::panic,
+    clippy::todo,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate,
+    missing_debug_implementations,
+    clippy::cast_precision_loss,
+    clippy::clone_on_ref_ptr,
+    clippy::match_same_arms,
+    clippy::items_after_statements,
+    unreachable_pub,
+    clippy::print_stdout,
+    clippy::similar_names
+)]
+mod common;
+
+use common::{TestApp, TestUser};
+use obscura_server::config::PushQueueBackend;
+use serde_json::json;
+use uuid::Uuid;
+
+/// A flag name unique to this run, so parallel tests sharing the database are unaffected.
+fn unique_flag() -> String {
+    format!("test_{}", Uuid::new_v4().simple())
+}
+
+#[tokio::test]
+async fn test_feature_flag_lifecycle() {
+    let app = TestApp::spawn().await;
+    let name = unique_flag();
+    let url = format!("{}/feature-flags/{name}", app.mgmt_url);
+
+    let resp = app
+        .client
+        .put(&url)
+        .json(&json!({ "enabled": true, "rolloutPercent": 40, "description": "new ack frames" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let flag: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(flag["name"], name);
+    assert_eq!(flag["enabled"], true);
+    assert_eq!(flag["rolloutPercent"], 40);
+
+    let list: serde_json::Value =
+        app.client.get(format!("{}/feature-flags", app.mgmt_url)).send().await.unwrap().json().await.unwrap();
+    assert!(list["flags"].as_array().unwrap().iter().any(|f| f["name"] == name && f["rolloutPercent"] == 40));
+
+    let resp = app.client.put(&url).json(&json!({ "enabled": false })).send().await.unwrap();
+    let flag: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(flag["enabled"], false);
+    assert_eq!(flag["rolloutPercent"], 100);
+    assert!(flag["description"].is_null());
+
+    assert_eq!(app.client.delete(&url).send().await.unwrap().status(), 204);
+    assert_eq!(app.client.delete(&url).send().await.unwrap().status(), 404);
+}
+
+#[tokio::test]
+async fn test_invalid_feature_flag_is_rejected() {
+    let app = TestApp::spawn().await;
+
+    let resp = app
+        .client
+        .put(format!("{}/feature-flags/Not-Valid", app.mgmt_url))
+        .json(&json!({ "enabled": true }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+
+    let resp = app
+        .client
+        .put(format!("{}/feature-flags/{}", app.mgmt_url, unique_flag()))
+        .json(&json!({ "enabled": true, "rolloutPercent": 101 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+}
+
+/// Registers a user with 20 pre-keys and has `fetcher` take one, which leaves the device below the
+/// refill threshold. Returns the kind of push scheduled for it.
+async fn push_kind_after_running_low(app: &TestApp, fetcher: &TestUser, prefix: &str) -> String {
+    let owner = app.register_user_with_keys(&common::generate_username(prefix), 123, 20).await;
+    let resp = app
+        .client
+        .get(format!("{}/v1/users/{}", app.server_url, owner.user_id))
+        .header("Authorization", format!("Bearer {}", fetcher.token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    sqlx::query_scalar("SELECT kind FROM push_jobs WHERE device_id = $1")
+        .bind(owner.device_id)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_prekey_refill_push_follows_its_flag() {
+    let mut config = common::get_test_config();
+    config.notifications.push_queue_backend = PushQueueBackend::Postgres;
+    let app = TestApp::spawn_with_config(config).await;
+    let alice = app.register_user(&common::generate_username("flag_alice")).await;
+
+    assert_eq!(push_kind_after_running_low(&app, &alice, "flag_off").await, "check");
+
+    let url = format!("{}/feature-flags/prekey_refill_push", app.mgmt_url);
+    let resp = app.client.put(&url).json(&json!({ "enabled": true, "rolloutPercent": 100 })).send().await.unwrap();
+    assert_eq!(resp.status(), 200);
+    let kind = push_kind_after_running_low(&app, &alice, "flag_on").await;
+    app.client.delete(&url).send().await.unwrap();
+    assert_eq!(kind, "prekey_low");
+}
@@ -67,7 +67,7 @@ async fn test_full_system_flow() {
         messages.push(proto::send_message_request::Submission {
             submission_id: Uuid::new_v4().as_bytes().to_vec(),
             device_id: receiver.device_id.as_bytes().to_vec(),
-            message: content.clone(),
+            message: content.clone().into(),
         });
     }
 
@@ -76,7 +76,7 @@ async fn test_full_system_flow() {
     messages.push(proto::send_message_request::Submission {
         submission_id: Uuid::new_v4().as_bytes().to_vec(),
         device_id: invalid_device_id.as_bytes().to_vec(),
-        message: b"Invalid".to_vec(),
+        message: b"Invalid".to_vec().into(),
     });
 
     // Next 29 Valid
@@ -86,7 +86,7 @@ async fn test_full_system_flow() {
         messages.push(proto::send_message_request::Submission {
             submission_id: Uuid::new_v4().as_bytes().to_vec(),
             device_id: receiver.device_id.as_bytes().to_vec(),
-            message: content.clone(),
+            message: content.clone().into(),
         });
     }
 
@@ -16,7 +16,9 @@
 mod common;
 
 use common::TestApp;
-use futures::StreamExt;
+use futures::{SinkExt, StreamExt};
+use obscura_server::proto::obscura::v1 as proto;
+use prost::Message as _;
 use std::time::Duration;
 use tokio_tungstenite::tungstenite::protocol::Message;
 
@@ -111,3 +113,320 @@ async fn test_heartbeat_timeout_closes_connection() {
     assert!(closed, "Connection was not closed after timeout. Received: {messages:?}");
     println!("Successfully verified connection closure. Buffered messages: {messages:?}");
 }
+
+#[tokio::test]
+async fn test_credit_flow_control_limits_delivery() {
+    let app = TestApp::spawn().await;
+    let sender = app.register_user(&common::generate_username("credit_sender")).await;
+    let receiver = app.register_user(&common::generate_username("credit_receiver")).await;
+
+    let messages: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i]).collect();
+    let submissions: Vec<(uuid::Uuid, &[u8])> = messages.iter().map(|m| (receiver.device_id, m.as_slice())).collect();
+    app.send_messages(&sender.token, &submissions).await;
+
+    let mut client = app.connect_ws_with_query(&receiver.token, "&credit=true").await;
+
+    // Nothing is delivered until the client grants credit.
+    assert!(client.receive_envelope_timeout(Duration::from_millis(500)).await.is_none());
+
+    client.send_credit(2).await;
+    for _ in 0..2 {
+        assert!(client.receive_envelope().await.is_some(), "Expected envelope within granted credit");
+    }
+    assert!(
+        client.receive_envelope_timeout(Duration::from_millis(500)).await.is_none(),
+        "Server delivered beyond granted credit"
+    );
+
+    client.send_credit(10).await;
+    for _ in 0..3 {
+        assert!(client.receive_envelope().await.is_some(), "Expected remaining envelopes after more credit");
+    }
+}
+
+#[tokio::test]
+async fn test_routing_hint_and_session_counts() {
+    let app = TestApp::spawn().await;
+    let alice = app.register_user(&common::generate_username("route_a")).await;
+    let bob = app.register_user(&common::generate_username("route_b")).await;
+
+    let route = |token: &str| {
+        let req = app
+            .client
+            .get(format!("{}/v1/gateway/route", app.server_url))
+            .header("Authorization", format!("Bearer {token}"));
+        async move {
+            let resp = req.send().await.unwrap();
+            assert_eq!(resp.status(), 200);
+            resp.json::<serde_json::Value>().await.unwrap()
+        }
+    };
+
+    let first = route(&alice.token).await;
+    let second = route(&alice.token).await;
+    assert_eq!(first, second, "Routing hint must be stable for a user");
+    assert_eq!(first["routingKey"].as_str().unwrap().len(), 32);
+    assert_ne!(first["routingKey"], route(&bob.token).await["routingKey"]);
+
+    let sessions = || async {
+        let resp = app.client.get(format!("{}/sessions", app.mgmt_url)).send().await.unwrap();
+        assert_eq!(resp.status(), 200);
+        resp.json::<serde_json::Value>().await.unwrap()["activeSessions"].as_u64().unwrap()
+    };
+
+    assert_eq!(sessions().await, 0);
+    let _client = app.connect_ws(&alice.token).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(sessions().await, 1);
+}
+
+#[tokio::test]
+async fn test_inbound_frame_flood_closes_session() {
+    let mut config = common::get_test_config();
+    config.websocket.inbound_frame_burst = 5;
+    config.websocket.inbound_frames_per_second = 1;
+
+    let app = TestApp::spawn_with_config(config).await;
+    let user = app.register_user(&common::generate_username("frame_flood")).await;
+    let mut client = app.connect_ws(&user.token).await;
+
+    for _ in 0..20 {
+        if client.sink.send(Message::Ping(vec![1].into())).await.is_err() {
+            break;
+        }
+    }
+
+    assert_eq!(
+        wait_for_close(&mut client, Duration::from_secs(5)).await,
+        Some(4029),
+        "Flooding client must be closed as rate limited"
+    );
+}
+
+/// Waits up to `timeout` for the server to close the connection and returns the close code,
+/// or `None` if the connection is still open.
+async fn wait_for_close(client: &mut common::TestWsClient, timeout: Duration) -> Option<u16> {
+    let start = std::time::Instant::now();
+    while start.elapsed() < timeout {
+        match client.receive_raw_timeout(Duration::from_millis(200)).await {
+            Some(Ok(Message::Close(frame))) => return Some(frame.map_or(1005, |f| u16::from(f.code))),
+            Some(Err(_)) => return Some(1006),
+            Some(Ok(_)) | None => {}
+        }
+    }
+    None
+}
+
+#[tokio::test]
+async fn test_session_closes_when_token_expires() {
+    let mut config = common::get_test_config();
+    config.auth.access_token_ttl_secs = 2;
+
+    let app = TestApp::spawn_with_config(config).await;
+    let user = app.register_user(&common::generate_username("auth_expiry")).await;
+    let mut client = app.connect_ws(&user.token).await;
+
+    assert_eq!(wait_for_close(&mut client, Duration::from_secs(5)).await, Some(4001));
+}
+
+#[tokio::test]
+async fn test_auth_frame_extends_session() {
+    let mut config = common::get_test_config();
+    config.auth.access_token_ttl_secs = 4;
+
+    let app = TestApp::spawn_with_config(config).await;
+    let user = app.register_user(&common::generate_username("auth_refresh")).await;
+    let other = app.register_user(&common::generate_username("auth_other")).await;
+    let mut client = app.connect_ws(&user.token).await;
+    let connected_at = std::time::Instant::now();
+
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    let resp = app
+        .client
+        .post(format!("{}/v1/sessions/refresh", app.server_url))
+        .json(&serde_json::json!({ "refreshToken": user.refresh_token }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let fresh_token = resp.json::<serde_json::Value>().await.unwrap()["token"].as_str().unwrap().to_string();
+
+    // A token for another device must not extend this session.
+    client.send_auth(&other.token).await;
+    client.send_auth(&fresh_token).await;
+
+    let original_deadline = Duration::from_millis(4500).saturating_sub(connected_at.elapsed());
+    assert_eq!(
+        wait_for_close(&mut client, original_deadline).await,
+        None,
+        "Session must outlive the original token after an Auth frame"
+    );
+    assert_eq!(wait_for_close(&mut client, Duration::from_secs(5)).await, Some(4001));
+}
+
+#[tokio::test]
+async fn test_invalid_frame_sends_go_away_then_closes() {
+    let app = TestApp::spawn().await;
+    let user = app.register_user(&common::generate_username("protocol_error")).await;
+    let mut client = app.connect_ws(&user.token).await;
+    client.ensure_subscribed().await;
+
+    client.sink.send(Message::Binary(vec![0xff; 8].into())).await.unwrap();
+
+    let mut go_away = None;
+    let start = std::time::Instant::now();
+    while start.elapsed() < Duration::from_secs(5) {
+        match client.receive_raw_timeout(Duration::from_millis(200)).await {
+            Some(Ok(Message::Binary(bin))) => {
+                if let Ok(proto::WebSocketFrame { payload: Some(proto::web_socket_frame::Payload::GoAway(g)) }) =
+                    proto::WebSocketFrame::decode(bin.as_ref())
+                {
+                    go_away = Some(g);
+                }
+            }
+            Some(Ok(Message::Close(frame))) => {
+                assert_eq!(frame.map(|f| u16::from(f.code)), Some(4000));
+                break;
+            }
+            Some(Ok(_)) | None => {}
+            Some(Err(e)) => panic!("Connection failed before a close frame: {e}"),
+        }
+    }
+
+    let go_away = go_away.expect("GoAway must precede the close frame");
+    assert_eq!(go_away.code, 4000);
+    assert!(go_away.reconnect);
+    assert!(go_away.retry_after_ms > 0);
+}
+
+#[tokio::test]
+async fn test_new_session_replaces_existing_one() {
+    let app = TestApp::spawn().await;
+    let user = app.register_user(&common::generate_username("session_replace")).await;
+    let sender = app.register_user(&common::generate_username("session_replace_sender")).await;
+
+    let mut old = app.connect_ws(&user.token).await;
+    old.ensure_subscribed().await;
+    let mut new = app.connect_ws(&user.token).await;
+    new.ensure_subscribed().await;
+
+    assert_eq!(wait_for_close(&mut old, Duration::from_secs(5)).await, Some(4002));
+
+    app.send_message(&sender.token, user.device_id, b"after takeover").await;
+    let env = new.receive_envelope().await.expect("The newest session receives messages");
+    assert_eq!(env.message, b"after takeover");
+}
+
+#[tokio::test]
+async fn test_reject_policy_refuses_second_session() {
+    let mut config = common::get_test_config();
+    config.websocket.session_policy = obscura_server::config::SessionPolicy::Reject;
+    let app = TestApp::spawn_with_config(config).await;
+    let user = app.register_user(&common::generate_username("session_reject")).await;
+
+    let mut first = app.connect_ws(&user.token).await;
+    first.ensure_subscribed().await;
+
+    let resp = app
+        .client
+        .post(format!("{}/v1/gateway/ticket", app.server_url))
+        .header("Authorization", format!("Bearer {}", user.token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let url = format!("{}?ticket={}", app.ws_url, body["ticket"].as_str().unwrap());
+
+    match tokio_tungstenite::connect_async(url).await {
+        Err(tokio_tungstenite::tungstenite::Error::Http(resp)) => assert_eq!(resp.status(), 409),
+        Err(e) => panic!("Expected HTTP 409, got: {e:?}"),
+        Ok(_) => panic!("A second session must be refused while the first is open"),
+    }
+
+    // The existing session is unaffected, and the device can connect again once it ends
+    first.ensure_subscribed().await;
+    first.sink.close().await.unwrap();
+    drop(first);
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let mut second = app.connect_ws(&user.token).await;
+    second.ensure_subscribed().await;
+}
+
+/// Opens a gateway connection for `token` that the server is expected to refuse, returning the
+/// status and whether a `Retry-After` was set.
+async fn refused_handshake(app: &TestApp, token: &str) -> (u16, bool) {
+    let body = app.request_ticket(token).await;
+    let url = format!("{}?ticket={}", app.ws_url, body["ticket"].as_str().unwrap());
+    match tokio_tungstenite::connect_async(url).await {
+        Err(tokio_tungstenite::tungstenite::Error::Http(resp)) => {
+            (resp.status().as_u16(), resp.headers().contains_key("retry-after"))
+        }
+        Err(e) => panic!("Expected an HTTP refusal, got: {e:?}"),
+        Ok(_) => panic!("The session should have been refused"),
+    }
+}
+
+#[tokio::test]
+async fn test_instance_session_limit_refuses_with_503() {
+    let mut config = common::get_test_config();
+    config.websocket.max_sessions = 1;
+    let app = TestApp::spawn_with_config(config).await;
+    let first_user = app.register_user(&common::generate_username("limit_instance_a")).await;
+    let second_user = app.register_user(&common::generate_username("limit_instance_b")).await;
+
+    let mut first = app.connect_ws(&first_user.token).await;
+    first.ensure_subscribed().await;
+
+    assert_eq!(refused_handshake(&app, &second_user.token).await, (503, true));
+
+    // The slot is given back when the session ends
+    first.sink.close().await.unwrap();
+    drop(first);
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let mut second = app.connect_ws(&second_user.token).await;
+    second.ensure_subscribed().await;
+}
+
+#[tokio::test]
+async fn test_user_session_limit_refuses_with_429() {
+    let mut config = common::get_test_config();
+    config.websocket.session_policy = obscura_server::config::SessionPolicy::Multiple;
+    config.websocket.max_sessions_per_user = 1;
+    let app = TestApp::spawn_with_config(config).await;
+    let user = app.register_user(&common::generate_username("limit_user")).await;
+    let other = app.register_user(&common::generate_username("limit_user_other")).await;
+
+    let mut first = app.connect_ws(&user.token).await;
+    first.ensure_subscribed().await;
+
+    assert_eq!(refused_handshake(&app, &user.token).await, (429, true));
+
+    // Other users are not affected
+    let mut unaffected = app.connect_ws(&other.token).await;
+    unaffected.ensure_subscribed().await;
+    first.ensure_subscribed().await;
+}
+
+#[tokio::test]
+async fn test_reconnect_under_replace_is_not_held_back_by_user_limit() {
+    let mut config = common::get_test_config();
+    config.websocket.session_policy = obscura_server::config::SessionPolicy::Replace;
+    config.websocket.max_sessions_per_user = 1;
+    let app = TestApp::spawn_with_config(config).await;
+    let user = app.register_user(&common::generate_username("limit_replace")).await;
+    let sender = app.register_user(&common::generate_username("limit_replace_sender")).await;
+
+    let mut old = app.connect_ws(&user.token).await;
+    old.ensure_subscribed().await;
+
+    // The device's own session does not count against it when it reconnects
+    let mut new = app.connect_ws(&user.token).await;
+    new.ensure_subscribed().await;
+    assert_eq!(wait_for_close(&mut old, Duration::from_secs(5)).await, Some(4002));
+
+    app.send_message(&sender.token, user.device_id, b"after reconnect").await;
+    let env = new.receive_envelope().await.expect("The reconnected session receives messages");
+    assert_eq!(env.message, b"after reconnect");
+}
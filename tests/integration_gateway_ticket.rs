@@ -43,17 +43,21 @@ async fn test_generate_websocket_ticket() {
     let ticket = body["ticket"].as_str().unwrap();
     assert!(!ticket.is_empty(), "Ticket should not be empty");
 
-    let cache = obscura_server::adapters::redis::RedisCache::new(
-        std::sync::Arc::clone(&app.resources.pubsub),
-        "ws:ticket:".to_string(),
-        30,
-    );
+    let cache = obscura_server::adapters::key_value::KeyValueCache::new(app.redis(), "ws:ticket:".to_string(), 30);
 
     // 3. Verify the ticket was saved in Redis
     let redis_ticket = cache.get(ticket).await.expect("Failed to query Redis").expect("Ticket not found in Redis");
 
-    let cached_device_id = String::from_utf8(redis_ticket).expect("Invalid UTF-8 in cached device ID");
+    // The ticket records the device, the expiry of the token it was issued with and the user
+    let cached = String::from_utf8(redis_ticket).expect("Invalid UTF-8 in cached ticket");
+    let parts: Vec<&str> = cached.split(':').collect();
+    let [cached_device_id, expires_at, resume_token, cached_user_id] = parts[..] else {
+        panic!("Ticket should be device_id:expiry:resume_token:user_id, got {cached}");
+    };
     assert_eq!(cached_device_id, user.device_id.to_string(), "Cached device ID does not match");
+    assert_eq!(cached_user_id, user.user_id.to_string(), "Cached user ID does not match");
+    assert!(expires_at.parse::<u64>().is_ok(), "Cached token expiry should be a Unix timestamp");
+    assert_eq!(body["resumeToken"], resume_token, "The resume token is returned to the client");
 }
 
 #[tokio::test]
@@ -91,11 +95,7 @@ async fn test_websocket_with_invalid_device_id_in_cache() {
     let app = common::TestApp::spawn().await;
 
     // Write a non-UUID string directly into the ticket cache
-    let cache = obscura_server::adapters::redis::RedisCache::new(
-        std::sync::Arc::clone(&app.resources.pubsub),
-        "ws:ticket:".to_string(),
-        30,
-    );
+    let cache = obscura_server::adapters::key_value::KeyValueCache::new(app.redis(), "ws:ticket:".to_string(), 30);
     let ticket = "test-corrupt-ticket";
     cache.set(ticket, b"not-a-valid-uuid").await.expect("Failed to seed cache");
 
@@ -117,11 +117,7 @@ async fn test_websocket_with_invalid_utf8_in_cache() {
     let app = common::TestApp::spawn().await;
 
     // Write invalid UTF-8 bytes directly into the ticket cache
-    let cache = obscura_server::adapters::redis::RedisCache::new(
-        std::sync::Arc::clone(&app.resources.pubsub),
-        "ws:ticket:".to_string(),
-        30,
-    );
+    let cache = obscura_server::adapters::key_value::KeyValueCache::new(app.redis(), "ws:ticket:".to_string(), 30);
     let ticket = "test-invalid-utf8-ticket";
     cache.set(ticket, &[0xFF, 0xFE, 0x80, 0x81]).await.expect("Failed to seed cache");
 
@@ -13,10 +13,14 @@
     clippy::print_stdout,
     clippy::similar_names
 )]
+use async_trait::async_trait;
 use axum::http::StatusCode;
+use obscura_server::adapters::push::{LoggingPushProvider, PushError, PushProvider};
 use obscura_server::config::HealthConfig;
+use obscura_server::domain::notification::PushKind;
 use obscura_server::services::health_service::HealthService;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 mod common;
 
 #[tokio::test]
@@ -43,6 +47,29 @@ async fn test_readyz_happy_path() {
     assert_eq!(body["status"], "ok");
     assert_eq!(body["database"], "ok");
     assert_eq!(body["storage"], "ok");
+
+    let pool = &body["databasePool"];
+    assert_eq!(pool["maxConnections"], app.config.database.max_connections);
+    assert!(pool["size"].as_u64().unwrap() <= pool["maxConnections"].as_u64().unwrap());
+    assert!(pool["meanAcquireWaitMs"].is_number());
+    assert!(pool["acquireTimeouts"].is_u64());
+}
+
+#[tokio::test]
+async fn test_migration_status_reports_current_schema() {
+    let app = common::TestApp::spawn().await;
+
+    let resp = app.client.get(format!("{}/migrations", app.mgmt_url)).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // The harness runs every migration before the server starts
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["upToDate"], true);
+    assert_eq!(body["pending"], serde_json::json!([]));
+    assert_eq!(body["dirty"], serde_json::json!([]));
+    assert_eq!(body["modified"], serde_json::json!([]));
+    assert!(body["latestVersion"].as_i64().unwrap() >= 16);
+    assert!(body["currentVersion"].as_i64().unwrap() >= body["latestVersion"].as_i64().unwrap());
 }
 
 #[tokio::test]
@@ -94,7 +121,7 @@ async fn create_unreachable_s3_client() -> aws_sdk_s3::Client {
         force_path_style: true,
         ..Default::default()
     };
-    obscura_server::initialize_s3_client(&config).await
+    obscura_server::initialize_s3_client(&config, &obscura_server::config::OutboundConfig::default()).await.unwrap()
 }
 
 /// Helper to create a DB pool that will hang on queries (non-routable address).
@@ -114,9 +141,10 @@ async fn test_health_check_db_timeout() {
     let health = HealthService::new(
         create_unreachable_pool(),
         app.s3_client.clone(),
-        Arc::clone(&app.resources.pubsub),
+        Some(app.redis()),
+        Arc::new(LoggingPushProvider),
         app.config.storage.bucket.clone(),
-        HealthConfig { db_timeout_ms: 50, storage_timeout_ms: 2000, pubsub_timeout_ms: 2000 },
+        HealthConfig { db_timeout_ms: 50, storage_timeout_ms: 2000, pubsub_timeout_ms: 2000, ..Default::default() },
     );
 
     let result = health.check_db().await;
@@ -132,9 +160,10 @@ async fn test_health_check_storage_timeout() {
     let health = HealthService::new(
         app.pool.clone(),
         create_unreachable_s3_client().await,
-        Arc::clone(&app.resources.pubsub),
+        Some(app.redis()),
+        Arc::new(LoggingPushProvider),
         "test-bucket".to_string(),
-        HealthConfig { db_timeout_ms: 2000, storage_timeout_ms: 50, pubsub_timeout_ms: 2000 },
+        HealthConfig { db_timeout_ms: 2000, storage_timeout_ms: 50, pubsub_timeout_ms: 2000, ..Default::default() },
     );
 
     let result = health.check_storage().await;
@@ -161,3 +190,65 @@ async fn test_readyz_database_and_storage_error() {
     assert_eq!(body["database"], "error");
     assert_eq!(body["storage"], "error");
 }
+
+#[derive(Debug, Default)]
+struct RejectedCredentialsProvider {
+    checks: AtomicUsize,
+}
+
+#[async_trait]
+impl PushProvider for RejectedCredentialsProvider {
+    async fn send_push(&self, _token: &str, _kind: PushKind) -> Result<(), PushError> {
+        Ok(())
+    }
+
+    async fn check_credentials(&self) -> Result<(), PushError> {
+        self.checks.fetch_add(1, Ordering::SeqCst);
+        Err(PushError::Other(anyhow::anyhow!("invalid_grant")))
+    }
+}
+
+#[tokio::test]
+async fn test_health_check_push_is_cached() {
+    let app = common::TestApp::spawn().await;
+    let provider = Arc::new(RejectedCredentialsProvider::default());
+
+    let health = HealthService::new(
+        app.pool.clone(),
+        app.s3_client.clone(),
+        Some(app.redis()),
+        provider.clone(),
+        app.config.storage.bucket.clone(),
+        HealthConfig { push_check_interval_secs: 60, ..Default::default() },
+    );
+
+    let first = health.check_push().await.expect("push check is enabled");
+    assert!(first.unwrap_err().contains("invalid_grant"));
+    assert!(health.check_push().await.expect("push check is enabled").is_err());
+    assert_eq!(provider.checks.load(Ordering::SeqCst), 1, "Result was not reused");
+
+    let disabled = HealthService::new(
+        app.pool.clone(),
+        app.s3_client.clone(),
+        Some(app.redis()),
+        provider.clone(),
+        app.config.storage.bucket.clone(),
+        HealthConfig::default(),
+    );
+    assert!(disabled.check_push().await.is_none());
+}
+
+#[tokio::test]
+async fn test_readyz_reports_push_check() {
+    let mut config = common::get_test_config();
+    config.health.push_check_interval_secs = 60;
+
+    let app = common::TestApp::spawn_with_config(config).await;
+    common::ensure_storage_bucket(&app.s3_client, &app.config.storage.bucket).await;
+
+    let resp = app.client.get(format!("{}/readyz", app.mgmt_url)).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["push"], "ok");
+}
@@ -0,0 +1,213 @@
+#![allow(
+    clippy::unwrap_used,
+    clippy::panic,
+    clippy::todo,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate,
+    missing_debug_implementations,
+    clippy::cast_precision_loss,
+    clippy::clone_on_ref_ptr,
+    clippy::match_same_arms,
+    clippy::items_after_statements,
+    unreachable_pub,
+    clippy::print_stdout,
+    clippy::similar_names
+)]
+use reqwest::StatusCode;
+use serde_json::json;
+use uuid::Uuid;
+
+mod common;
+
+#[tokio::test]
+async fn test_identifier_add_verify_remove() {
+    let app = common::TestApp::spawn().await;
+    let alice = app.register_user(&common::generate_username("ident_alice")).await;
+    let bob = app.register_user(&common::generate_username("ident_bob")).await;
+    let email = format!("{}@example.org", Uuid::new_v4().simple());
+
+    let add = |token: String, value: String| {
+        let app = &app;
+        async move {
+            app.client
+                .post(format!("{}/v1/identifiers", app.server_url))
+                .bearer_auth(token)
+                .json(&json!({ "kind": "email", "value": value, "discoverable": true }))
+                .send()
+                .await
+                .unwrap()
+        }
+    };
+    let verify = |token: String, code: String| {
+        let app = &app;
+        async move {
+            app.client
+                .post(format!("{}/v1/identifiers/email/verify", app.server_url))
+                .bearer_auth(token)
+                .json(&json!({ "code": code }))
+                .send()
+                .await
+                .unwrap()
+        }
+    };
+
+    // Invalid values are rejected before anything is sent.
+    let resp = add(alice.token.clone(), "not-an-email".to_string()).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    // The code goes to the normalized address.
+    let resp = add(alice.token.clone(), format!("  {} ", email.to_uppercase())).await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    let code = common::verification_codes().get(&email).unwrap().clone();
+
+    let wrong = if code == "000000" { "111111" } else { "000000" };
+    assert_eq!(verify(alice.token.clone(), wrong.to_string()).await.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(verify(alice.token.clone(), code).await.status(), StatusCode::NO_CONTENT);
+
+    let resp =
+        app.client.get(format!("{}/v1/identifiers", app.server_url)).bearer_auth(&alice.token).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let identifiers = body["identifiers"].as_array().unwrap();
+    assert_eq!(identifiers.len(), 1);
+    assert_eq!(identifiers[0]["kind"], "email");
+    assert_eq!(identifiers[0]["verified"], true);
+    assert_eq!(identifiers[0]["discoverable"], true);
+    assert!(identifiers[0].get("value").is_none(), "Only a hash of the identifier is stored");
+
+    let stored: Vec<u8> = sqlx::query_scalar("SELECT identifier_hash FROM user_identifiers WHERE user_id = $1")
+        .bind(alice.user_id)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert!(!stored.windows(email.len()).any(|w| w == email.as_bytes()));
+
+    // Another account can claim the address, but not verify it while Alice holds it.
+    assert_eq!(add(bob.token.clone(), email.clone()).await.status(), StatusCode::ACCEPTED);
+    let code = common::verification_codes().get(&email).unwrap().clone();
+    assert_eq!(verify(bob.token.clone(), code).await.status(), StatusCode::CONFLICT);
+
+    let resp = app
+        .client
+        .delete(format!("{}/v1/identifiers/email", app.server_url))
+        .bearer_auth(&alice.token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+    // Once Alice lets go, Bob's still-outstanding code verifies.
+    let code = common::verification_codes().get(&email).unwrap().clone();
+    assert_eq!(verify(bob.token.clone(), code).await.status(), StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn test_identifier_code_is_invalidated_after_max_attempts() {
+    let mut config = common::get_test_config();
+    config.identifiers.code_max_attempts = 2;
+    let app = common::TestApp::spawn_with_config(config).await;
+    let user = app.register_user(&common::generate_username("ident_attempts")).await;
+    let phone = format!("+1555{:07}", Uuid::new_v4().as_u128() % 10_000_000);
+
+    let resp = app
+        .client
+        .post(format!("{}/v1/identifiers", app.server_url))
+        .bearer_auth(&user.token)
+        .json(&json!({ "kind": "phone", "value": phone }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    let code = common::verification_codes().get(&phone).unwrap().clone();
+    let wrong = if code == "000000" { "111111" } else { "000000" };
+
+    for expected in [StatusCode::BAD_REQUEST, StatusCode::BAD_REQUEST, StatusCode::NOT_FOUND] {
+        let resp = app
+            .client
+            .post(format!("{}/v1/identifiers/phone/verify", app.server_url))
+            .bearer_auth(&user.token)
+            .json(&json!({ "code": wrong }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), expected);
+    }
+
+    // Even the right code no longer works.
+    let resp = app
+        .client
+        .post(format!("{}/v1/identifiers/phone/verify", app.server_url))
+        .bearer_auth(&user.token)
+        .json(&json!({ "code": code }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_identifier_resends_are_throttled() {
+    let mut config = common::get_test_config();
+    config.identifiers.code_max_attempts = 2;
+    config.identifiers.resend_cooldown_secs = 0;
+    config.identifiers.max_codes_per_window = 3;
+    let app = common::TestApp::spawn_with_config(config).await;
+    let user = app.register_user(&common::generate_username("ident_resend")).await;
+    let email = format!("{}@example.org", Uuid::new_v4().simple());
+    let (app, user) = (&app, &user);
+
+    let add = || async {
+        app.client
+            .post(format!("{}/v1/identifiers", app.server_url))
+            .bearer_auth(&user.token)
+            .json(&json!({ "kind": "email", "value": email }))
+            .send()
+            .await
+            .unwrap()
+    };
+    let verify = |code: String| async move {
+        app.client
+            .post(format!("{}/v1/identifiers/email/verify", app.server_url))
+            .bearer_auth(&user.token)
+            .json(&json!({ "code": code }))
+            .send()
+            .await
+            .unwrap()
+    };
+
+    // A wrong guess against the first code still counts against the second.
+    assert_eq!(add().await.status(), StatusCode::ACCEPTED);
+    let code = common::verification_codes().get(&email).unwrap().clone();
+    let wrong = if code == "000000" { "111111" } else { "000000" };
+    assert_eq!(verify(wrong.to_string()).await.status(), StatusCode::BAD_REQUEST);
+
+    assert_eq!(add().await.status(), StatusCode::ACCEPTED);
+    let code = common::verification_codes().get(&email).unwrap().clone();
+    let wrong = if code == "000000" { "111111" } else { "000000" };
+    assert_eq!(verify(wrong.to_string()).await.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(verify(code).await.status(), StatusCode::NOT_FOUND);
+
+    // With the guesses spent, no new code is sent until the window restarts.
+    let resp = add().await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = resp.headers()["retry-after"].to_str().unwrap().parse().unwrap();
+    assert!(retry_after > 60, "Retry-After should point at the window end, got {retry_after}");
+}
+
+#[tokio::test]
+async fn test_identifier_resend_cooldown() {
+    let app = common::TestApp::spawn().await;
+    let user = app.register_user(&common::generate_username("ident_cooldown")).await;
+
+    for (i, expected) in [StatusCode::ACCEPTED, StatusCode::TOO_MANY_REQUESTS].into_iter().enumerate() {
+        let resp = app
+            .client
+            .post(format!("{}/v1/identifiers", app.server_url))
+            .bearer_auth(&user.token)
+            .json(&json!({ "kind": "email", "value": format!("{}-{i}@example.org", Uuid::new_v4().simple()) }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), expected);
+    }
+}
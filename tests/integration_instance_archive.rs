@@ -0,0 +1,103 @@
+#![allow(
+    clippy::unwrap_used,
+    clippy::panic,
+    clippy::todo,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate,
+    missing_debug_implementations,
+    clippy::cast_precision_loss,
+    clippy::clone_on_ref_ptr,
+    clippy::match_same_arms,
+    clippy::items_after_statements,
+    unreachable_pub,
+    clippy::print_stdout,
+    clippy::similar_names
+)]
+use obscura_server::instance_archive;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+mod common;
+use common::TestApp;
+
+/// Creates an empty, migrated database next to the test database.
+async fn fresh_database(pool: &PgPool) -> (PgPool, String) {
+    let name = format!("obscura_import_{}", Uuid::new_v4().simple());
+    sqlx::query(&format!("CREATE DATABASE {name}")).execute(pool).await.unwrap();
+    let target = PgPool::connect_with(pool.connect_options().as_ref().clone().database(&name)).await.unwrap();
+    obscura_server::run_migrations(&target).await.unwrap();
+    (target, name)
+}
+
+async fn drop_database(pool: &PgPool, target: PgPool, name: &str) {
+    target.close().await;
+    sqlx::query(&format!("DROP DATABASE {name}")).execute(pool).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_export_import_round_trip() {
+    let app = TestApp::spawn().await;
+    let user = app.register_user_with_keys(&common::generate_username("archive"), 4242, 3).await;
+
+    let mut archive = Vec::new();
+    let exported = instance_archive::export(&app.pool, &mut archive).await.unwrap();
+    assert!(exported.users >= 1);
+    assert!(exported.one_time_pre_keys >= 3);
+
+    let (target, name) = fresh_database(&app.pool).await;
+    let imported = instance_archive::import(&target, archive.as_slice()).await.unwrap();
+    assert_eq!(imported, exported);
+
+    let (source_hash,): (String,) = sqlx::query_as("SELECT password_hash FROM users WHERE id = $1")
+        .bind(user.user_id)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    let (target_hash,): (String,) = sqlx::query_as("SELECT password_hash FROM users WHERE id = $1")
+        .bind(user.user_id)
+        .fetch_one(&target)
+        .await
+        .unwrap();
+    assert_eq!(target_hash, source_hash);
+
+    let identity_query = "SELECT identity_key, registration_id FROM identity_keys WHERE device_id = $1";
+    let source_identity: (Vec<u8>, i32) =
+        sqlx::query_as(identity_query).bind(user.device_id).fetch_one(&app.pool).await.unwrap();
+    let target_identity: (Vec<u8>, i32) =
+        sqlx::query_as(identity_query).bind(user.device_id).fetch_one(&target).await.unwrap();
+    assert_eq!(target_identity, source_identity);
+    assert_eq!(target_identity.1, 4242);
+
+    let one_time_keys: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM one_time_pre_keys WHERE device_id = $1")
+        .bind(user.device_id)
+        .fetch_one(&target)
+        .await
+        .unwrap();
+    assert_eq!(one_time_keys, 3);
+
+    // A database that already has accounts is never merged into.
+    let err = instance_archive::import(&target, archive.as_slice()).await.unwrap_err();
+    assert!(err.to_string().contains("already has accounts"), "unexpected error: {err:#}");
+
+    drop_database(&app.pool, target, &name).await;
+}
+
+#[tokio::test]
+async fn test_truncated_archive_is_not_imported() {
+    let app = TestApp::spawn().await;
+    app.register_user(&common::generate_username("archive_cut")).await;
+
+    let mut archive = Vec::new();
+    instance_archive::export(&app.pool, &mut archive).await.unwrap();
+    let text = String::from_utf8(archive).unwrap();
+    let truncated: String = text.lines().take(text.lines().count() - 1).map(|line| format!("{line}\n")).collect();
+
+    let (target, name) = fresh_database(&app.pool).await;
+    let err = instance_archive::import(&target, truncated.as_bytes()).await.unwrap_err();
+    assert!(err.to_string().contains("truncated"), "unexpected error: {err:#}");
+
+    let users: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users").fetch_one(&target).await.unwrap();
+    assert_eq!(users, 0);
+
+    drop_database(&app.pool, target, &name).await;
+}
@@ -0,0 +1,58 @@
+#![allow(
+    clippy::unwrap_used,
+    clippy::panic,
+    clippy::todo,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate,
+    missing_debug_implementations,
+    clippy::cast_precision_loss,
+    clippy::clone_on_ref_ptr,
+    clippy::match_same_arms,
+    clippy::items_after_statements,
+    unreachable_pub,
+    clippy::print_stdout,
+    clippy::similar_names
+)]
+use std::time::Duration;
+use uuid::Uuid;
+
+mod common;
+use common::TestApp;
+
+async fn list_instances(app: &TestApp) -> serde_json::Value {
+    app.client.get(format!("{}/instances", app.mgmt_url)).send().await.unwrap().json().await.unwrap()
+}
+
+#[tokio::test]
+async fn test_instances_see_each_other() {
+    let mut config_a = common::get_test_config();
+    let id_a = format!("test-a-{}", Uuid::new_v4().simple());
+    config_a.instance.id = Some(id_a.clone());
+    let mut config_b = common::get_test_config();
+    let id_b = format!("test-b-{}", Uuid::new_v4().simple());
+    config_b.instance.id = Some(id_b.clone());
+
+    let app_a = TestApp::spawn_with_workers(config_a).await;
+    let _app_b = TestApp::spawn_with_workers(config_b).await;
+
+    let both_listed = app_a
+        .wait_until(
+            || async {
+                let list = list_instances(&app_a).await;
+                let ids: Vec<&str> =
+                    list["instances"].as_array().unwrap().iter().filter_map(|i| i["id"].as_str()).collect();
+                ids.contains(&id_a.as_str()) && ids.contains(&id_b.as_str())
+            },
+            Duration::from_secs(5),
+        )
+        .await;
+    assert!(both_listed, "Both instances should be listed");
+
+    let list = list_instances(&app_a).await;
+    assert_eq!(list["instanceId"], id_a.as_str());
+    let entry = list["instances"].as_array().unwrap().iter().find(|i| i["id"] == id_b.as_str()).unwrap();
+    assert_eq!(entry["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(entry["activeSessions"], 0);
+    assert!(entry["startedAt"].is_string());
+    assert!(entry["lastHeartbeatAt"].is_string());
+}
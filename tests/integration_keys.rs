@@ -185,6 +185,45 @@ async fn test_key_limit_enforced() {
     assert_eq!(count, 50, "Total keys should be capped at 50");
 }
 
+#[tokio::test]
+async fn test_per_request_key_limit_enforced() {
+    let mut config = common::get_test_config();
+    config.messaging.max_pre_keys_per_request = 10;
+    let app = TestApp::spawn_with_config(config).await;
+
+    let resp = app
+        .client
+        .post(format!("{}/v1/users", app.server_url))
+        .json(&json!({ "username": common::generate_username("bulk"), "password": "password12345" }))
+        .send()
+        .await
+        .unwrap();
+    let user_token = resp.json::<serde_json::Value>().await.unwrap()["token"].as_str().unwrap().to_string();
+
+    let (device_payload, _) = common::generate_device_payload(123, 11);
+    let resp = app
+        .client
+        .post(format!("{}/v1/devices", app.server_url))
+        .header("Authorization", format!("Bearer {user_token}"))
+        .json(&device_payload)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), 413);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["code"], "too_many_pre_keys");
+    assert_eq!(body["limit"], 10);
+
+    let user = app.register_user_with_keys(&common::generate_username("bulk"), 123, 10).await;
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM one_time_pre_keys WHERE device_id = $1")
+        .bind(user.device_id)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 10, "A batch at the limit must be accepted");
+}
+
 #[tokio::test]
 async fn test_key_rotation_monotonic_check() {
     let app = TestApp::spawn().await;
@@ -254,16 +293,12 @@ async fn test_prekey_status_low_keys() {
     let mut ws = app.connect_ws(&user.token).await;
     let status = ws.receive_prekey_status().await.expect("Did not receive PreKeyStatus");
     assert_eq!(status.one_time_pre_key_count, 0);
+    assert_eq!(status.max_pre_keys, 100);
+    assert_eq!(status.recommended_upload_count, 100);
 }
 
-#[tokio::test]
-async fn test_device_takeover_success() {
-    let app = TestApp::spawn().await;
-    let username = common::generate_username("takeover");
-    let user = app.register_user_with_keys(&username, 111, 1).await;
-
-    app.send_message(&user.token, user.device_id, b"hello").await;
-
+/// Uploads a fresh identity for the device behind `token`, taking it over.
+async fn take_over(app: &TestApp, token: &str) {
     let new_identity_key = common::generate_signing_key();
     let (new_spk_pub, new_spk_sig) = common::generate_signed_pre_key(&new_identity_key);
     let (_, ik_pub_ed) = PrivateKey(new_identity_key).calculate_key_pair(0);
@@ -276,7 +311,7 @@ async fn test_device_takeover_success() {
     new_ik_wire.insert(0, 0x05);
 
     let resp = app.client.post(format!("{}/v1/devices/keys", app.server_url))
-        .header("Authorization", format!("Bearer {}", user.token))
+        .header("Authorization", format!("Bearer {}", token))
         .json(&json!({
             "identityKey": STANDARD.encode(&new_ik_wire),
             "registrationId": 456,
@@ -285,11 +320,102 @@ async fn test_device_takeover_success() {
         })).send().await.unwrap();
 
     assert_eq!(resp.status(), 200);
+}
+
+#[tokio::test]
+async fn test_device_takeover_success() {
+    let app = TestApp::spawn().await;
+    let username = common::generate_username("takeover");
+    let user = app.register_user_with_keys(&username, 111, 1).await;
+
+    app.send_message(&user.token, user.device_id, b"hello").await;
+
+    take_over(&app, &user.token).await;
 
     // Verify inbox is WIPED on takeover
     app.assert_message_count(user.device_id, 0).await;
 }
 
+#[tokio::test]
+async fn test_device_takeover_cancels_push_and_purges_payloads() {
+    let mut config = common::get_test_config();
+    config.storage.bucket = format!("test-takeover-{}", &uuid::Uuid::new_v4().to_string()[..8]);
+    config.messaging.payload_offload_threshold_bytes = 64;
+    config.notifications.push_delay_secs = 3600;
+    let app = TestApp::spawn_with_config(config.clone()).await;
+    common::ensure_storage_bucket(&app.s3_client, &config.storage.bucket).await;
+
+    let user = app.register_user(&common::generate_username("takeover_wipe")).await;
+    app.send_message(&user.token, user.device_id, &[7; 4096]).await;
+
+    let message_id: uuid::Uuid = sqlx::query_scalar("SELECT id FROM messages WHERE device_id = $1")
+        .bind(user.device_id)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    let key = format!("{}{}", config.messaging.payload_prefix, message_id);
+    let push_score = || async {
+        let mut conn = app.redis().publisher();
+        redis::cmd("ZSCORE")
+            .arg(&config.notifications.push_queue_key)
+            .arg(user.device_id.to_string())
+            .query_async::<Option<f64>>(&mut conn)
+            .await
+            .unwrap()
+    };
+    assert!(push_score().await.is_some(), "Push was not scheduled");
+
+    take_over(&app, &user.token).await;
+
+    app.assert_message_count(user.device_id, 0).await;
+    assert!(push_score().await.is_none(), "Scheduled push survived the takeover");
+    assert!(
+        app.s3_client.head_object().bucket(&config.storage.bucket).key(&key).send().await.is_err(),
+        "Offloaded payload survived the takeover"
+    );
+    let registered: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM message_payloads WHERE message_id = $1")
+        .bind(message_id)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(registered, 0);
+}
+
+#[tokio::test]
+async fn test_device_takeover_keeps_attachments() {
+    let mut config = common::get_test_config();
+    config.storage.bucket = format!("test-takeover-{}", &uuid::Uuid::new_v4().to_string()[..8]);
+    let app = TestApp::spawn_with_config(config.clone()).await;
+    common::ensure_storage_bucket(&app.s3_client, &config.storage.bucket).await;
+
+    let sender = app.register_user(&common::generate_username("takeover_att_sender")).await;
+    let user = app.register_user(&common::generate_username("takeover_att")).await;
+    let resp = app
+        .client
+        .post(format!("{}/v1/attachments", app.server_url))
+        .header("Authorization", format!("Bearer {}", sender.token))
+        .body(b"shared with every recipient device".to_vec())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    let attachment_id = resp.json::<serde_json::Value>().await.unwrap()["id"].as_str().unwrap().to_string();
+    app.send_message(&sender.token, user.device_id, attachment_id.as_bytes()).await;
+
+    take_over(&app, &user.token).await;
+
+    // The pointer went with the inbox, but the sender and other recipients still hold it
+    app.assert_message_count(user.device_id, 0).await;
+    let resp = app
+        .client
+        .get(format!("{}/v1/attachments/{}", app.server_url, attachment_id))
+        .header("Authorization", format!("Bearer {}", sender.token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+}
+
 #[tokio::test]
 async fn test_upload_keys_bad_signature() {
     let app = TestApp::spawn().await;
@@ -316,6 +442,157 @@ async fn test_upload_keys_bad_signature() {
     assert_eq!(resp.status(), 400);
 }
 
+#[tokio::test]
+async fn test_validate_keys_reports_valid_upload_without_storing() {
+    let app = TestApp::spawn().await;
+    let user = app.register_user_with_keys(&common::generate_username("validate_ok"), 123, 0).await;
+
+    let (spk_pub, spk_sig) = common::generate_signed_pre_key(&user.identity_key);
+    let resp = app.client.post(format!("{}/v1/keys/validate", app.server_url))
+        .header("Authorization", format!("Bearer {}", user.token))
+        .json(&json!({
+            "signedPreKey": { "keyId": 7, "publicKey": STANDARD.encode(&spk_pub), "signature": STANDARD.encode(&spk_sig) },
+            "oneTimePreKeys": [{ "keyId": 1, "publicKey": STANDARD.encode(&spk_pub) }]
+        })).send().await.unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body, json!({ "valid": true, "takeover": false, "errors": [] }));
+
+    let signed: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM signed_pre_keys WHERE device_id = $1 AND id = 7")
+        .bind(user.device_id)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    let one_time: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM one_time_pre_keys WHERE device_id = $1")
+        .bind(user.device_id)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!((signed, one_time), (0, 0), "Validation must not store keys");
+}
+
+#[tokio::test]
+async fn test_validate_keys_reports_each_problem() {
+    let app = TestApp::spawn().await;
+    let user = app.register_user_with_keys(&common::generate_username("validate_bad"), 123, 0).await;
+
+    let (otpk_pub, _) = common::generate_signed_pre_key(&user.identity_key);
+    let resp = app.client.post(format!("{}/v1/keys/validate", app.server_url))
+        .header("Authorization", format!("Bearer {}", user.token))
+        .json(&json!({
+            // Replays the registered ID 1 with a signature that does not verify
+            "signedPreKey": { "keyId": 1, "publicKey": STANDARD.encode([0x05; 33]), "signature": STANDARD.encode([0x00; 64]) },
+            "oneTimePreKeys": [
+                { "keyId": 1, "publicKey": STANDARD.encode(&otpk_pub) },
+                { "keyId": 1, "publicKey": STANDARD.encode(&otpk_pub) },
+                { "keyId": 2, "publicKey": "not base64" },
+                { "keyId": 3, "publicKey": STANDARD.encode([0x06; 33]) }
+            ]
+        })).send().await.unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["valid"], false);
+
+    let mut problems: Vec<(String, Option<i64>)> = body["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| (e["field"].as_str().unwrap().to_string(), e["keyId"].as_i64()))
+        .collect();
+    problems.sort();
+    assert_eq!(
+        problems,
+        vec![
+            ("oneTimePreKeys".to_string(), Some(1)),
+            ("oneTimePreKeys".to_string(), Some(2)),
+            ("oneTimePreKeys".to_string(), Some(3)),
+            ("signedPreKey".to_string(), Some(1)),
+            ("signedPreKey".to_string(), Some(1)),
+        ],
+        "Unexpected errors: {body}"
+    );
+}
+
+#[tokio::test]
+async fn test_validate_keys_agrees_with_upload() {
+    let app = TestApp::spawn().await;
+    let user = app.register_user_with_keys(&common::generate_username("validate_agree"), 123, 0).await;
+
+    // A correctly signed key that replays the registered ID 1
+    let (spk_pub, spk_sig) = common::generate_signed_pre_key(&user.identity_key);
+    let payload = json!({
+        "signedPreKey": { "keyId": 1, "publicKey": STANDARD.encode(&spk_pub), "signature": STANDARD.encode(&spk_sig) },
+        "oneTimePreKeys": []
+    });
+
+    let resp = app
+        .client
+        .post(format!("{}/v1/keys/validate", app.server_url))
+        .header("Authorization", format!("Bearer {}", user.token))
+        .json(&payload)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let report: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(report["errors"].as_array().unwrap().len(), 1, "Unexpected errors: {report}");
+
+    let resp = app
+        .client
+        .post(format!("{}/v1/devices/keys", app.server_url))
+        .header("Authorization", format!("Bearer {}", user.token))
+        .json(&payload)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["error"], report["errors"][0]["message"]);
+}
+
+#[tokio::test]
+async fn test_validate_keys_detects_takeover() {
+    let app = TestApp::spawn().await;
+    let user = app.register_user_with_keys(&common::generate_username("validate_takeover"), 123, 0).await;
+
+    let new_identity_key = common::generate_signing_key();
+    let (spk_pub, spk_sig) = common::generate_signed_pre_key(&new_identity_key);
+    let (_, ik_pub_ed) = PrivateKey(new_identity_key).calculate_key_pair(0);
+    let mut ik_wire = curve25519_dalek::edwards::CompressedEdwardsY(ik_pub_ed)
+        .decompress()
+        .unwrap()
+        .to_montgomery()
+        .to_bytes()
+        .to_vec();
+    ik_wire.insert(0, 0x05);
+
+    let resp = app.client.post(format!("{}/v1/keys/validate", app.server_url))
+        .header("Authorization", format!("Bearer {}", user.token))
+        .json(&json!({
+            "identityKey": STANDARD.encode(&ik_wire),
+            "signedPreKey": { "keyId": 1, "publicKey": STANDARD.encode(&spk_pub), "signature": STANDARD.encode(&spk_sig) },
+            "oneTimePreKeys": []
+        })).send().await.unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["takeover"], true);
+    // A takeover resets the signed pre-key ID, but still needs a registration ID
+    assert_eq!(
+        body["errors"],
+        json!([{
+            "field": "registrationId",
+            "message": "registrationId is required when identityKey is provided"
+        }])
+    );
+
+    let stored: Vec<u8> = sqlx::query_scalar("SELECT identity_key FROM identity_keys WHERE device_id = $1")
+        .bind(user.device_id)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_ne!(stored, ik_wire, "Validation must not replace the identity key");
+}
+
 #[tokio::test]
 async fn test_fetch_keys_multiple_devices() {
     let app = TestApp::spawn().await;
@@ -378,3 +655,227 @@ async fn test_fetch_keys_multiple_devices() {
     let d2_bundle = bundles.iter().find(|b| b["deviceId"] == device2_id);
     assert!(d2_bundle.is_some(), "Bundle for device 2 not found");
 }
+
+#[tokio::test]
+async fn test_ed25519_identity_key_accepted() {
+    use ed25519_dalek::Signer;
+
+    let app = TestApp::spawn().await;
+    let contact = app.register_user(&common::generate_username("ed_contact")).await;
+
+    let resp = app
+        .client
+        .post(format!("{}/v1/users", app.server_url))
+        .json(&json!({ "username": common::generate_username("ed25519"), "password": "password12345" }))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let user_token = body["token"].as_str().unwrap().to_string();
+
+    // Plain Ed25519 identity (0x06 prefix) signing a regular Curve25519 signed pre-key.
+    let signing_key = ed25519_dalek::SigningKey::from_bytes(&common::generate_signing_key());
+    let mut ik_wire = [0u8; 33];
+    ik_wire[0] = 0x06;
+    ik_wire[1..].copy_from_slice(signing_key.verifying_key().as_bytes());
+
+    let (mut device_payload, _) = common::generate_device_payload(123, 1);
+    let spk_pub = STANDARD.decode(device_payload["signedPreKey"]["publicKey"].as_str().unwrap()).unwrap();
+    device_payload["identityKey"] = json!(STANDARD.encode(ik_wire));
+    device_payload["signedPreKey"]["signature"] = json!(STANDARD.encode(signing_key.sign(&spk_pub).to_bytes()));
+
+    let resp = app
+        .client
+        .post(format!("{}/v1/devices", app.server_url))
+        .header("Authorization", format!("Bearer {user_token}"))
+        .json(&device_payload)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 201, "Ed25519 identity should be accepted: {}", resp.text().await.unwrap());
+
+    let user_id = sqlx::query_scalar::<_, uuid::Uuid>(
+        "SELECT user_id FROM identity_keys ik JOIN devices d ON d.id = ik.device_id WHERE ik.identity_key = $1",
+    )
+    .bind(ik_wire.as_slice())
+    .fetch_one(&app.pool)
+    .await
+    .unwrap();
+    let resp = app
+        .client
+        .get(format!("{}/v1/users/{user_id}", app.server_url))
+        .header("Authorization", format!("Bearer {}", contact.token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let bundles: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(bundles[0]["identityKey"], STANDARD.encode(ik_wire));
+
+    // Pre-keys must stay Curve25519 even for Ed25519 identities.
+    let (mut bad_payload, _) = common::generate_device_payload(124, 0);
+    bad_payload["identityKey"] = json!(STANDARD.encode(ik_wire));
+    let mut bad_spk = spk_pub.clone();
+    bad_spk[0] = 0x06;
+    bad_payload["signedPreKey"]["publicKey"] = json!(STANDARD.encode(&bad_spk));
+    bad_payload["signedPreKey"]["signature"] = json!(STANDARD.encode(signing_key.sign(&bad_spk).to_bytes()));
+
+    let resp = app
+        .client
+        .post(format!("{}/v1/devices", app.server_url))
+        .header("Authorization", format!("Bearer {user_token}"))
+        .json(&bad_payload)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+}
+
+#[tokio::test]
+async fn test_reserved_bundle_consumed_on_redeem() {
+    use obscura_server::proto::obscura::v1 as proto;
+    use prost::Message;
+
+    let app = TestApp::spawn().await;
+    let bob = app.register_user_with_keys(&common::generate_username("reserve_bob"), 123, 2).await;
+    let alice = app.register_user(&common::generate_username("reserve_alice")).await;
+    let charlie = app.register_user(&common::generate_username("reserve_charlie")).await;
+
+    let fetch = |token: String, query: &'static str| {
+        app.client
+            .get(format!("{}/v1/users/{}{query}", app.server_url, bob.user_id))
+            .header("Authorization", format!("Bearer {token}"))
+            .send()
+    };
+    let stored_keys = || async {
+        sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM one_time_pre_keys WHERE device_id = $1")
+            .bind(bob.device_id)
+            .fetch_one(&app.pool)
+            .await
+            .unwrap()
+    };
+
+    let reserved: serde_json::Value = fetch(alice.token.clone(), "?reserve=true").await.unwrap().json().await.unwrap();
+    let token = reserved[0]["consumptionToken"].as_str().expect("reserved bundle carries a token").to_string();
+    assert!(reserved[0]["reservedUntil"].is_string());
+    assert_eq!(stored_keys().await, 2, "A reserved key must not be deleted yet");
+
+    let plain: serde_json::Value = fetch(charlie.token.clone(), "").await.unwrap().json().await.unwrap();
+    assert!(plain[0].get("consumptionToken").is_none());
+    assert_ne!(
+        plain[0]["oneTimePreKey"]["keyId"], reserved[0]["oneTimePreKey"]["keyId"],
+        "A reserved key must not be handed to another sender"
+    );
+    assert_eq!(stored_keys().await, 1);
+
+    let request = proto::SendMessageRequest {
+        messages: vec![proto::send_message_request::Submission {
+            submission_id: uuid::Uuid::new_v4().as_bytes().to_vec(),
+            device_id: bob.device_id.as_bytes().to_vec(),
+            message: b"prekey message".to_vec().into(),
+        }],
+    };
+    let resp = app
+        .client
+        .post(format!("{}/v1/messages", app.server_url))
+        .header("Authorization", format!("Bearer {}", alice.token))
+        .header("Idempotency-Key", uuid::Uuid::new_v4().to_string())
+        .header("Consumption-Token", &token)
+        .header("Content-Type", "application/x-protobuf")
+        .body(request.encode_to_vec())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(stored_keys().await, 0, "Redeeming the token must delete the reserved key");
+}
+
+#[tokio::test]
+async fn test_lapsed_reservation_goes_to_the_next_sender() {
+    use obscura_server::proto::obscura::v1 as proto;
+    use prost::Message;
+
+    let app = TestApp::spawn().await;
+    let bob = app.register_user_with_keys(&common::generate_username("lapse_bob"), 123, 1).await;
+    let alice = app.register_user(&common::generate_username("lapse_alice")).await;
+    let charlie = app.register_user(&common::generate_username("lapse_charlie")).await;
+
+    let reserve = |token: String| {
+        app.client
+            .get(format!("{}/v1/users/{}?reserve=true", app.server_url, bob.user_id))
+            .header("Authorization", format!("Bearer {token}"))
+            .send()
+    };
+    let send = |token: String, consumption_token: String| {
+        let request = proto::SendMessageRequest {
+            messages: vec![proto::send_message_request::Submission {
+                submission_id: uuid::Uuid::new_v4().as_bytes().to_vec(),
+                device_id: bob.device_id.as_bytes().to_vec(),
+                message: b"prekey message".to_vec().into(),
+            }],
+        };
+        app.client
+            .post(format!("{}/v1/messages", app.server_url))
+            .header("Authorization", format!("Bearer {token}"))
+            .header("Idempotency-Key", uuid::Uuid::new_v4().to_string())
+            .header("Consumption-Token", consumption_token)
+            .header("Content-Type", "application/x-protobuf")
+            .body(request.encode_to_vec())
+            .send()
+    };
+    let stored_keys = || async {
+        sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM one_time_pre_keys WHERE device_id = $1")
+            .bind(bob.device_id)
+            .fetch_one(&app.pool)
+            .await
+            .unwrap()
+    };
+
+    let first: serde_json::Value = reserve(alice.token.clone()).await.unwrap().json().await.unwrap();
+    let first_token = first[0]["consumptionToken"].as_str().expect("reserved bundle carries a token").to_string();
+
+    // The reservation runs out while Alice's first message is still on its way.
+    sqlx::query("UPDATE one_time_pre_keys SET reserved_until = NOW() - INTERVAL '1 second' WHERE device_id = $1")
+        .bind(bob.device_id)
+        .execute(&app.pool)
+        .await
+        .unwrap();
+
+    let second: serde_json::Value = reserve(charlie.token.clone()).await.unwrap().json().await.unwrap();
+    assert_eq!(
+        second[0]["oneTimePreKey"]["keyId"], first[0]["oneTimePreKey"]["keyId"],
+        "A lapsed reservation must free its key for the next sender"
+    );
+    let second_token = second[0]["consumptionToken"].as_str().expect("reserved bundle carries a token").to_string();
+    assert_ne!(second_token, first_token);
+
+    // Alice's late message is still accepted, but her token no longer holds the key.
+    assert_eq!(send(alice.token.clone(), first_token).await.unwrap().status(), 200);
+    assert_eq!(stored_keys().await, 1, "A lapsed token must not take the key from the new reservation");
+
+    assert_eq!(send(charlie.token.clone(), second_token).await.unwrap().status(), 200);
+    assert_eq!(stored_keys().await, 0, "The new reservation must still be redeemable");
+}
+
+#[tokio::test]
+async fn test_prekey_sampler_counts_users_below_threshold() {
+    use obscura_server::adapters::database::key_repo::KeyRepository;
+    use obscura_server::workers::PreKeySamplerWorker;
+
+    let app = TestApp::spawn().await;
+    let user = app.register_user_with_keys(&common::generate_username("sampler_low"), 321, 0).await;
+
+    // No pool is ever below zero keys.
+    let none = PreKeySamplerWorker::new(app.pool.clone(), KeyRepository::new(), 0, i64::MAX, 1);
+    assert_eq!(none.sample().await.unwrap(), (0, 0));
+
+    // Smaller pages still walk every user within one sample.
+    let paged = PreKeySamplerWorker::new(app.pool.clone(), KeyRepository::new(), 1, 100, 1);
+    let (users, devices) = paged.sample().await.unwrap();
+    assert!(users >= 1 && devices >= users, "Expected {} to be counted, got {users} users", user.user_id);
+
+    // The new device has no one-time prekeys, so it is below a threshold of one.
+    let sampler = PreKeySamplerWorker::new(app.pool.clone(), KeyRepository::new(), 1, i64::MAX, 1);
+    let (users, devices) = sampler.sample().await.unwrap();
+    assert!(users >= 1 && devices >= users, "Expected {} to be counted, got {users} users", user.user_id);
+}
@@ -0,0 +1,75 @@
+#![allow(
+    clippy::unwrap_used,
+    clippy::panic,
+    clippy::todo,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate,
+    missing_debug_implementations,
+    clippy::cast_precision_loss,
+    clippy::clone_on_ref_ptr,
+    clippy::match_same_arms,
+    clippy::items_after_statements,
+    unreachable_pub,
+    clippy::print_stdout,
+    clippy::similar_names
+)]
+use reqwest::StatusCode;
+use serde_json::json;
+use std::time::Duration;
+use uuid::Uuid;
+
+mod common;
+use common::TestApp;
+
+async fn redeliver(app: &TestApp, token: &str) -> reqwest::Response {
+    app.client
+        .post(format!("{}/v1/messages/redeliver", app.server_url))
+        .bearer_auth(token)
+        .json(&json!({ "messageIds": [Uuid::new_v4()] }))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_low_priority_routes_are_shed_while_sends_go_through() {
+    let mut config = common::get_test_config();
+    // Every served request is slower than this, so the first full window trips it
+    config.load_shed.p99_latency_ms = 1;
+    config.load_shed.window_secs = 1;
+    config.load_shed.min_samples = 1;
+    let app = TestApp::spawn_with_config(config).await;
+    let alice = app.register_user(&common::generate_username("shed_a")).await;
+    let bob = app.register_user(&common::generate_username("shed_b")).await;
+
+    app.send_message(&alice.token, bob.device_id, b"before").await;
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    let resp = redeliver(&app, &bob.token).await;
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(resp.headers().get("retry-after").unwrap(), "1");
+
+    // Interactive traffic is never shed
+    app.send_message(&alice.token, bob.device_id, b"during").await;
+    app.assert_message_count(bob.device_id, 2).await;
+}
+
+#[tokio::test]
+async fn test_shed_routes_are_listed_only_when_enabled() {
+    let app = TestApp::spawn().await;
+    let resp = app.client.get(format!("{}/debug/routes", app.mgmt_url)).send().await.unwrap();
+    let tree: serde_json::Value = resp.json().await.unwrap();
+    let redeliver = tree["routes"].as_array().unwrap().iter().find(|r| r["path"] == "/v1/messages/redeliver").unwrap();
+    assert!(
+        redeliver["layers"].as_array().unwrap().iter().all(|l| l["name"] != "load_shed" && l["name"] != "load_monitor")
+    );
+
+    let mut config = common::get_test_config();
+    config.load_shed.db_pool_percent = 90;
+    let app = TestApp::spawn_with_config(config).await;
+    let resp = app.client.get(format!("{}/debug/routes", app.mgmt_url)).send().await.unwrap();
+    let tree: serde_json::Value = resp.json().await.unwrap();
+    let validate = tree["routes"].as_array().unwrap().iter().find(|r| r["path"] == "/v1/keys/validate").unwrap();
+    assert!(validate["layers"].as_array().unwrap().iter().any(|l| l["name"] == "load_shed"));
+    assert!(validate["layers"].as_array().unwrap().iter().any(|l| l["name"] == "load_monitor"));
+}
@@ -0,0 +1,94 @@
+#![allow(
+    clippy::unwrap_used,
+    clippy::panic,
+    clippy::todo,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate,
+    missing_debug_implementations,
+    clippy::cast_precision_loss,
+    clippy::clone_on_ref_ptr,
+    clippy::match_same_arms,
+    clippy::items_after_statements,
+    unreachable_pub,
+    clippy::print_stdout,
+    clippy::similar_names
+)]
+use obscura_server::adapters::database::message_repo::MessageRepository;
+use obscura_server::adapters::storage::S3Storage;
+use obscura_server::workers::MessageCleanupWorker;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+mod common;
+
+async fn object_exists(app: &common::TestApp, bucket: &str, key: &str) -> bool {
+    app.s3_client.head_object().bucket(bucket).key(key).send().await.is_ok()
+}
+
+#[tokio::test]
+async fn test_large_message_is_offloaded_and_cleaned_up() {
+    let mut config = common::get_test_config();
+    config.storage.bucket = format!("test-msg-offload-{}", &Uuid::new_v4().to_string()[..8]);
+    config.messaging.payload_offload_threshold_bytes = 64;
+    config.websocket.ack_flush_interval_ms = 100;
+
+    let app = common::TestApp::spawn_with_config(config.clone()).await;
+    common::ensure_storage_bucket(&app.s3_client, &config.storage.bucket).await;
+
+    let user_a = app.register_user(&common::generate_username("alice_offload")).await;
+    let user_b = app.register_user(&common::generate_username("bob_offload")).await;
+
+    let small = b"small enough to stay inline".to_vec();
+    let large: Vec<u8> = (0..4096u32).map(|i| u8::try_from(i % 251).unwrap()).collect();
+    app.send_message(&user_a.token, user_b.device_id, &small).await;
+    app.send_message(&user_a.token, user_b.device_id, &large).await;
+
+    // Only the large message is kept out of the database
+    let rows: Vec<(Uuid, i32, Option<i32>)> = sqlx::query_as(
+        "SELECT id, octet_length(content), payload_size FROM messages WHERE device_id = $1 ORDER BY created_at",
+    )
+    .bind(user_b.device_id)
+    .fetch_all(&app.pool)
+    .await
+    .unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].2, None);
+    assert_eq!(rows[1].1, 0);
+    assert_eq!(rows[1].2, Some(4096));
+
+    let offloaded_id = rows[1].0;
+    let key = format!("{}{}", config.messaging.payload_prefix, offloaded_id);
+    assert!(object_exists(&app, &config.storage.bucket, &key).await, "Payload was not uploaded");
+
+    // Delivery is transparent to the recipient
+    let mut ws = app.connect_ws(&user_b.token).await;
+    let first = ws.receive_envelope().await.expect("Did not receive inline message");
+    let second = ws.receive_envelope().await.expect("Did not receive offloaded message");
+    assert_eq!(first.message, small);
+    assert_eq!(second.message, large);
+    assert_eq!(second.id, offloaded_id.as_bytes().to_vec());
+
+    ws.send_ack(first.id).await;
+    ws.send_ack(second.id).await;
+    app.assert_message_count(user_b.device_id, 0).await;
+
+    // Skip the upload grace period and run the cleanup
+    sqlx::query("UPDATE message_payloads SET created_at = NOW() - INTERVAL '2 hours' WHERE message_id = $1")
+        .bind(offloaded_id)
+        .execute(&app.pool)
+        .await
+        .unwrap();
+
+    let storage = Arc::new(S3Storage::new(app.s3_client.clone(), config.storage.bucket.clone()));
+    let worker = MessageCleanupWorker::new(app.pool.clone(), MessageRepository::new(), storage, config.messaging);
+    assert!(worker.cleanup_payloads().await.unwrap() >= 1);
+
+    assert!(!object_exists(&app, &config.storage.bucket, &key).await, "Payload was not deleted");
+    let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM message_payloads WHERE message_id = $1")
+        .bind(offloaded_id)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining, 0);
+}
@@ -17,6 +17,7 @@ mod common;
 
 use common::TestApp;
 use futures::SinkExt;
+use obscura_server::config::IdempotencyBackend;
 use obscura_server::proto::obscura::v1 as proto;
 use prost::Message as ProstMessage;
 use serde_json::json;
@@ -86,6 +87,71 @@ async fn test_message_pagination_large_backlog() {
     assert_eq!(received_ids.len(), message_count, "Did not receive all messages");
 }
 
+#[tokio::test]
+async fn test_sharded_inbox_delivers_in_order() {
+    let mut config = common::get_test_config();
+    config.messaging.inbox_shards = 4;
+    let app = TestApp::spawn_with_config(config).await;
+
+    let user_a = app.register_user(&common::generate_username("alice_shard")).await;
+    let user_b = app.register_user(&common::generate_username("bob_shard")).await;
+
+    let message_count = 120;
+    for i in 0..message_count {
+        app.send_message(&user_a.token, user_b.device_id, format!("Message {i}").as_bytes()).await;
+    }
+
+    let shards: Vec<(i16,)> = sqlx::query_as("SELECT DISTINCT shard FROM messages WHERE device_id = $1")
+        .bind(user_b.device_id)
+        .fetch_all(&app.pool)
+        .await
+        .unwrap();
+    assert!(shards.len() > 1, "Messages were not spread across shards");
+
+    let mut ws = app.connect_ws(&user_b.token).await;
+    let mut received = Vec::new();
+    while received.len() < message_count {
+        let Some(env) = ws.receive_envelope_timeout(Duration::from_millis(1000)).await else {
+            break;
+        };
+        received.push(String::from_utf8(env.message).unwrap());
+    }
+
+    let expected: Vec<String> = (0..message_count).map(|i| format!("Message {i}")).collect();
+    assert_eq!(received, expected, "Sharded inbox did not deliver every message in send order");
+}
+
+#[tokio::test]
+async fn test_lowering_shard_count_still_delivers_every_shard() {
+    let app = TestApp::spawn().await;
+    let user_a = app.register_user(&common::generate_username("alice_unshard")).await;
+    let user_b = app.register_user(&common::generate_username("bob_unshard")).await;
+
+    let message_count = 12;
+    for i in 0..message_count {
+        app.send_message(&user_a.token, user_b.device_id, format!("Message {i}").as_bytes()).await;
+    }
+
+    // As if the messages were written while the shard count was higher than it is now
+    sqlx::query("UPDATE messages SET shard = (seq % 5)::SMALLINT WHERE device_id = $1")
+        .bind(user_b.device_id)
+        .execute(&app.pool)
+        .await
+        .unwrap();
+
+    let mut ws = app.connect_ws(&user_b.token).await;
+    let mut received = Vec::new();
+    while received.len() < message_count {
+        let Some(env) = ws.receive_envelope_timeout(Duration::from_millis(1000)).await else {
+            break;
+        };
+        received.push(String::from_utf8(env.message).unwrap());
+    }
+
+    let expected: Vec<String> = (0..message_count).map(|i| format!("Message {i}")).collect();
+    assert_eq!(received, expected, "Messages in shards above the configured count were not delivered");
+}
+
 #[tokio::test]
 async fn test_ack_batching_behavior() {
     let mut config = common::get_test_config();
@@ -136,7 +202,7 @@ async fn test_send_message_recipient_not_found() {
         messages: vec![proto::send_message_request::Submission {
             submission_id: submission_id.as_bytes().to_vec(),
             device_id: bad_id.as_bytes().to_vec(),
-            message: b"Hello".to_vec(),
+            message: b"Hello".to_vec().into(),
         }],
     };
     let mut buf = Vec::new();
@@ -185,7 +251,18 @@ async fn test_send_message_malformed_protobuf() {
 
 #[tokio::test]
 async fn test_message_idempotency() {
-    let app = TestApp::spawn().await;
+    assert_retry_returns_cached_response(&TestApp::spawn().await).await;
+}
+
+#[tokio::test]
+async fn test_message_idempotency_with_memory_cache() {
+    let mut config = common::get_test_config();
+    config.messaging.idempotency_backend = IdempotencyBackend::Memory;
+    config.messaging.idempotency_memory_cap_bytes = 4096;
+    assert_retry_returns_cached_response(&TestApp::spawn_with_config(config).await).await;
+}
+
+async fn assert_retry_returns_cached_response(app: &TestApp) {
     let user_a = app.register_user(&common::generate_username("alice_idem")).await;
     let user_b = app.register_user(&common::generate_username("bob_idem")).await;
 
@@ -196,7 +273,7 @@ async fn test_message_idempotency() {
         messages: vec![proto::send_message_request::Submission {
             submission_id: Uuid::new_v4().as_bytes().to_vec(),
             device_id: user_b.device_id.as_bytes().to_vec(),
-            message: content.clone(),
+            message: content.clone().into(),
         }],
     };
     let mut buf = Vec::new();
@@ -257,19 +334,19 @@ async fn test_batch_partial_success() {
             proto::send_message_request::Submission {
                 submission_id: submission_id_b.as_bytes().to_vec(),
                 device_id: user_b.device_id.as_bytes().to_vec(),
-                message: b"Msg for Bob".to_vec(),
+                message: b"Msg for Bob".to_vec().into(),
             },
             // 2. Invalid (Bad ID)
             proto::send_message_request::Submission {
                 submission_id: submission_id_bad.as_bytes().to_vec(),
                 device_id: bad_id.as_bytes().to_vec(),
-                message: b"Msg for Nowhere".to_vec(),
+                message: b"Msg for Nowhere".to_vec().into(),
             },
             // 3. Valid (Charlie)
             proto::send_message_request::Submission {
                 submission_id: submission_id_c.as_bytes().to_vec(),
                 device_id: user_c.device_id.as_bytes().to_vec(),
-                message: b"Msg for Charlie".to_vec(),
+                message: b"Msg for Charlie".to_vec().into(),
             },
         ],
     };
@@ -343,7 +420,7 @@ async fn test_batch_too_large() {
         messages.push(proto::send_message_request::Submission {
             submission_id: Uuid::new_v4().as_bytes().to_vec(),
             device_id: user.device_id.as_bytes().to_vec(),
-            message: b"Msg".to_vec(),
+            message: b"Msg".to_vec().into(),
         });
     }
 
@@ -464,7 +541,7 @@ async fn test_send_message_invalid_uuid_bytes() {
         messages: vec![proto::send_message_request::Submission {
             submission_id: Uuid::new_v4().as_bytes().to_vec(), // Valid
             device_id: vec![4, 5, 6],                          // Invalid length
-            message: b"Hello".to_vec(),
+            message: b"Hello".to_vec().into(),
         }],
     };
     let mut buf = Vec::new();
@@ -528,7 +605,7 @@ async fn test_send_message_malformed_submission_id() {
         messages: vec![proto::send_message_request::Submission {
             submission_id: vec![1, 2, 3], // Invalid length
             device_id: recipient.device_id.as_bytes().to_vec(),
-            message: b"Hello".to_vec(),
+            message: b"Hello".to_vec().into(),
         }],
     };
     let mut buf = Vec::new();
@@ -565,7 +642,7 @@ async fn test_send_message_missing_payload() {
         messages: vec![proto::send_message_request::Submission {
             submission_id: Uuid::new_v4().as_bytes().to_vec(),
             device_id: recipient.device_id.as_bytes().to_vec(),
-            message: Vec::new(), // Missing payload
+            message: prost::bytes::Bytes::new(), // Missing payload
         }],
     };
     let mut buf = Vec::new();
@@ -645,3 +722,114 @@ async fn test_multi_device_messaging() {
     let env1 = ws1.receive_envelope_timeout(Duration::from_millis(500)).await;
     assert!(env1.is_none(), "Device 1 should NOT receive the message meant for Device 2");
 }
+
+#[tokio::test]
+async fn test_send_message_receipts() {
+    let app = TestApp::spawn().await;
+    let user_a = app.register_user(&common::generate_username("alice_receipt")).await;
+    let user_b = app.register_user(&common::generate_username("bob_receipt")).await;
+
+    async fn send(app: &TestApp, token: &str, device_id: Uuid, submission_ids: &[Uuid]) -> proto::SendMessageResponse {
+        let request = proto::SendMessageRequest {
+            messages: submission_ids
+                .iter()
+                .map(|id| proto::send_message_request::Submission {
+                    submission_id: id.as_bytes().to_vec(),
+                    device_id: device_id.as_bytes().to_vec(),
+                    message: b"Hello".to_vec().into(),
+                })
+                .collect(),
+        };
+        let resp = app
+            .client
+            .post(format!("{}/v1/messages", app.server_url))
+            .header("Authorization", format!("Bearer {token}"))
+            .header("Idempotency-Key", Uuid::new_v4().to_string())
+            .header("Content-Type", "application/x-protobuf")
+            .body(request.encode_to_vec())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+        proto::SendMessageResponse::decode(resp.bytes().await.unwrap()).unwrap()
+    }
+
+    let first: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+    let response = send(&app, &user_a.token, user_b.device_id, &first).await;
+    assert!(response.failed_submissions.is_empty());
+    assert_eq!(response.receipts.len(), 3);
+
+    // Sequence numbers follow the batch order
+    let mut sequences = Vec::new();
+    for submission_id in &first {
+        let receipt = response.receipts.iter().find(|r| r.submission_id == submission_id.as_bytes().to_vec()).unwrap();
+        assert_eq!(receipt.device_id, user_b.device_id.as_bytes().to_vec());
+        assert!(receipt.timestamp > 0);
+
+        let stored: (Uuid, i64) =
+            sqlx::query_as("SELECT id, seq FROM messages WHERE sender_device_id = $1 AND submission_id = $2")
+                .bind(user_a.device_id)
+                .bind(submission_id)
+                .fetch_one(&app.pool)
+                .await
+                .unwrap();
+        assert_eq!(receipt.message_id, stored.0.as_bytes().to_vec());
+        assert_eq!(receipt.sequence, u64::try_from(stored.1).unwrap());
+        sequences.push(receipt.sequence);
+    }
+    assert!(sequences.windows(2).all(|w| w[0] < w[1]));
+
+    // Later batches continue the recipient's sequence
+    let later = send(&app, &user_a.token, user_b.device_id, &[Uuid::new_v4()]).await;
+    assert!(later.receipts[0].sequence > sequences[2]);
+
+    // A retried submission gets the receipt of its first attempt
+    let retried = send(&app, &user_a.token, user_b.device_id, &first[..1]).await;
+    assert_eq!(retried.receipts.len(), 1);
+    assert_eq!(retried.receipts[0].submission_id, first[0].as_bytes().to_vec());
+    assert_eq!(retried.receipts[0].sequence, sequences[0]);
+    app.assert_message_count(user_b.device_id, 4).await;
+
+    // The retry used up no numbers, so both sequences continue without a gap
+    let next = send(&app, &user_a.token, user_b.device_id, &[Uuid::new_v4()]).await;
+    assert_eq!(next.receipts[0].sequence, later.receipts[0].sequence + 1);
+    let pair_seq: i64 =
+        sqlx::query_scalar("SELECT last_seq FROM pair_sequences WHERE sender_device_id = $1 AND device_id = $2")
+            .bind(user_a.device_id)
+            .bind(user_b.device_id)
+            .fetch_one(&app.pool)
+            .await
+            .unwrap();
+    assert_eq!(pair_seq, 5);
+}
+
+#[tokio::test]
+async fn test_pair_sequence_orders_each_conversation() {
+    let app = TestApp::spawn().await;
+    let user_a = app.register_user(&common::generate_username("alice_pair")).await;
+    let user_c = app.register_user(&common::generate_username("carol_pair")).await;
+    let user_b = app.register_user(&common::generate_username("bob_pair")).await;
+
+    app.send_message(&user_a.token, user_b.device_id, b"a1").await;
+    app.send_message(&user_c.token, user_b.device_id, b"c1").await;
+    app.send_messages(&user_a.token, &[(user_b.device_id, b"a2".as_slice()), (user_b.device_id, b"a3".as_slice())])
+        .await;
+
+    let mut ws = app.connect_ws(&user_b.token).await;
+    let mut received = Vec::new();
+    for _ in 0..4 {
+        let env = ws.receive_envelope().await.expect("Did not receive message");
+        received.push((env.message, env.pair_sequence));
+    }
+
+    // Delivered in the order queued, each pair numbered from one
+    assert_eq!(received, vec![(b"a1".to_vec(), 1), (b"c1".to_vec(), 1), (b"a2".to_vec(), 2), (b"a3".to_vec(), 3)]);
+
+    let sequences: Vec<i64> =
+        sqlx::query_scalar("SELECT seq FROM messages WHERE device_id = $1 ORDER BY created_at, seq")
+            .bind(user_b.device_id)
+            .fetch_all(&app.pool)
+            .await
+            .unwrap();
+    assert!(sequences.windows(2).all(|w| w[0] < w[1]));
+}
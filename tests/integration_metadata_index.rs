@@ -0,0 +1,180 @@
+#![allow(
+    clippy::unwrap_used,
+    clippy::panic,
+    clippy::todo,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate,
+    missing_debug_implementations,
+    clippy::cast_precision_loss,
+    clippy::clone_on_ref_ptr,
+    clippy::match_same_arms,
+    clippy::items_after_statements,
+    unreachable_pub,
+    clippy::print_stdout,
+    clippy::similar_names
+)]
+use obscura_server::adapters::database::metadata_index_repo::MetadataIndexRepository;
+use obscura_server::services::metadata_index_service::MetadataIndexService;
+use obscura_server::workers::MetadataIndexCleanupWorker;
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use std::time::Duration;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+mod common;
+use common::TestApp;
+
+fn indexed_config() -> obscura_server::config::Config {
+    let mut config = common::get_test_config();
+    config.metadata_index.enabled = true;
+    config
+}
+
+async fn search(app: &TestApp, body: Value) -> reqwest::Response {
+    app.client.post(format!("{}/metadata-index/search", app.mgmt_url)).json(&body).send().await.unwrap()
+}
+
+async fn inbox_is_empty(app: &TestApp, device_id: Uuid) -> bool {
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM messages WHERE device_id = $1")
+        .bind(device_id)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    count == 0
+}
+
+#[tokio::test]
+async fn test_index_outlives_delivery_and_every_search_is_audited() {
+    let app = TestApp::spawn_with_config(indexed_config()).await;
+    let alice = app.register_user(&common::generate_username("meta_alice")).await;
+    let bob = app.register_user(&common::generate_username("meta_bob")).await;
+
+    app.send_message(&alice.token, bob.device_id, b"hello").await;
+    app.send_message(&alice.token, bob.device_id, b"hi!!").await;
+
+    let mut ws = app.connect_ws(&bob.token).await;
+    for _ in 0..2 {
+        let env = ws.receive_envelope().await.expect("message delivered");
+        ws.send_ack(env.id).await;
+    }
+    assert!(app.wait_until(|| inbox_is_empty(&app, bob.device_id), Duration::from_secs(5)).await);
+
+    let resp =
+        search(&app, json!({ "operator": "jdoe", "reason": "case 114", "senderId": alice.user_id.to_string() })).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = resp.json().await.unwrap();
+    let messages = body["messages"].as_array().unwrap();
+    assert_eq!(messages.len(), 2, "Acknowledged messages stay in the index: {body}");
+    for entry in messages {
+        assert_eq!(entry["senderDeviceId"], alice.device_id.to_string());
+        assert_eq!(entry["recipientId"], bob.user_id.to_string());
+        assert_eq!(entry["recipientDeviceId"], bob.device_id.to_string());
+        assert!(entry.get("content").is_none() && entry.get("message").is_none());
+    }
+    let sizes: Vec<i64> = messages.iter().map(|m| m["sizeBytes"].as_i64().unwrap()).collect();
+    assert_eq!(sizes, vec![5, 4]);
+    assert_eq!(body["query"]["operator"], "jdoe");
+    assert_eq!(body["query"]["resultCount"], 2);
+
+    let future = (OffsetDateTime::now_utc() + time::Duration::hours(1))
+        .format(&time::format_description::well_known::Rfc3339)
+        .unwrap();
+    let resp = search(
+        &app,
+        json!({ "operator": "jdoe", "reason": "case 114", "recipientId": bob.user_id.to_string(), "since": future }),
+    )
+    .await;
+    let body: Value = resp.json().await.unwrap();
+    assert!(body["messages"].as_array().unwrap().is_empty());
+
+    let resp = app.client.get(format!("{}/metadata-index/queries", app.mgmt_url)).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = resp.json().await.unwrap();
+    let queries: Vec<&Value> = body["queries"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|q| q["senderId"] == alice.user_id.to_string() || q["recipientId"] == bob.user_id.to_string())
+        .collect();
+    assert_eq!(queries.len(), 2);
+    assert_eq!(queries[0]["recipientId"], bob.user_id.to_string(), "Newest query first");
+    assert_eq!(queries[0]["resultCount"], 0);
+    assert_eq!(queries[1]["reason"], "case 114");
+    assert_eq!(queries[1]["resultCount"], 2);
+}
+
+#[tokio::test]
+async fn test_search_requires_scope_operator_and_reason() {
+    let app = TestApp::spawn_with_config(indexed_config()).await;
+    let user_id = Uuid::new_v4().to_string();
+
+    for body in [
+        json!({ "operator": "jdoe", "reason": "case 115" }),
+        json!({ "operator": "jdoe", "reason": "  ", "senderId": user_id }),
+        json!({ "operator": "", "reason": "case 115", "senderId": user_id }),
+        json!({ "operator": "jdoe", "reason": "case 115", "senderId": "not-a-uuid" }),
+        json!({ "operator": "jdoe", "reason": "case 115", "senderId": user_id, "since": "yesterday" }),
+    ] {
+        assert_eq!(search(&app, body.clone()).await.status(), StatusCode::BAD_REQUEST, "{body}");
+    }
+
+    let audited: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM metadata_index_queries WHERE sender_id = $1")
+        .bind(Uuid::parse_str(&user_id).unwrap())
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(audited, 0, "Rejected searches read nothing and are not recorded");
+}
+
+#[tokio::test]
+async fn test_disabled_index_records_nothing_and_is_not_served() {
+    let app = TestApp::spawn().await;
+    let alice = app.register_user(&common::generate_username("meta_off_a")).await;
+    let bob = app.register_user(&common::generate_username("meta_off_b")).await;
+    app.send_message(&alice.token, bob.device_id, b"unindexed").await;
+
+    let resp = search(&app, json!({ "operator": "jdoe", "reason": "case 116", "senderId": alice.user_id })).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let indexed: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM message_metadata WHERE sender_id = $1")
+        .bind(alice.user_id)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(indexed, 0);
+}
+
+#[tokio::test]
+async fn test_cleanup_deletes_entries_past_retention() {
+    let config = indexed_config();
+    let app = TestApp::spawn_with_config(config.clone()).await;
+    let sender_id = Uuid::new_v4();
+    let (old, recent) = (Uuid::new_v4(), Uuid::new_v4());
+    for (message_id, age_days) in [(old, config.metadata_index.retention_days + 1), (recent, 1)] {
+        sqlx::query(
+            r#"
+            INSERT INTO message_metadata
+                (message_id, sender_id, sender_device_id, recipient_id, recipient_device_id, sent_at, size_bytes)
+            VALUES ($1, $2, $2, $2, $2, $3, 10)
+            "#,
+        )
+        .bind(message_id)
+        .bind(sender_id)
+        .bind(OffsetDateTime::now_utc() - time::Duration::days(age_days))
+        .execute(&app.pool)
+        .await
+        .unwrap();
+    }
+
+    let service = MetadataIndexService::new(app.pool.clone(), MetadataIndexRepository::new(), &config.metadata_index);
+    let worker = MetadataIndexCleanupWorker::new(service, &config.metadata_index);
+    assert!(worker.perform_cleanup().await.unwrap() >= 1);
+
+    let remaining: Vec<Uuid> = sqlx::query_scalar("SELECT message_id FROM message_metadata WHERE sender_id = $1")
+        .bind(sender_id)
+        .fetch_all(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining, vec![recent]);
+}
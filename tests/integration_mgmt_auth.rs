@@ -0,0 +1,37 @@
+#![allow(
+    clippy::unwrap_used,
+    clippy::panic,
+    clippy::todo,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate,
+    missing_debug_implementations,
+    clippy::cast_precision_loss,
+    clippy::clone_on_ref_ptr,
+    clippy::match_same_arms,
+    clippy::items_after_statements,
+    unreachable_pub,
+    clippy::print_stdout,
+    clippy::similar_names
+)]
+use axum::http::StatusCode;
+mod common;
+
+#[tokio::test]
+async fn test_mgmt_token_guards_admin_endpoints_but_not_probes() {
+    let mut config = common::get_test_config();
+    config.server.mgmt_auth_token = Some("mgmt-test-token".to_string());
+    let app = common::TestApp::spawn_with_config(config).await;
+
+    let resp = app.client.get(format!("{}/livez", app.mgmt_url)).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK, "Probes must stay reachable without credentials");
+
+    let resp = app.client.get(format!("{}/sessions", app.mgmt_url)).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let resp = app.client.get(format!("{}/sessions", app.mgmt_url)).bearer_auth("wrong-token").send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let resp =
+        app.client.get(format!("{}/sessions", app.mgmt_url)).bearer_auth("mgmt-test-token").send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+}
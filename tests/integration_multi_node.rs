@@ -168,7 +168,9 @@ async fn test_multi_node_disconnect_notification() {
 
 #[tokio::test]
 async fn test_distributed_fan_out_disconnect() {
-    let config = common::get_test_config();
+    let mut config = common::get_test_config();
+    // Alice keeps several sessions open at once
+    config.websocket.session_policy = obscura_server::config::SessionPolicy::Multiple;
     let app_a = common::TestApp::spawn_with_workers(config.clone()).await;
     let app_b = common::TestApp::spawn_with_workers(config.clone()).await;
     let app_c = common::TestApp::spawn_with_workers(config).await;
@@ -19,6 +19,7 @@ use async_trait::async_trait;
 use common::{SharedMockPushProvider, TestApp, notification_counts};
 use obscura_server::adapters::push::{PushError, PushProvider};
 use obscura_server::adapters::redis::NotificationRepository;
+use obscura_server::domain::notification::PushKind;
 use obscura_server::workers::PushNotificationWorker;
 use serde_json::json;
 use std::sync::{
@@ -195,8 +196,7 @@ async fn test_delivery_exactly_once_under_competition() {
 
     // Access internal components via Resources/Config
     let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
-    let notification_repo =
-        Arc::new(NotificationRepository::new(app.resources.pubsub.clone(), &app.config.notifications));
+    let notification_repo = Arc::new(NotificationRepository::new(app.redis(), &app.config.notifications));
 
     // Spawn 10 competing workers
     for i in 0..10 {
@@ -218,7 +218,7 @@ async fn test_delivery_exactly_once_under_competition() {
 
     tokio::time::sleep(Duration::from_millis(100)).await;
 
-    let _: anyhow::Result<()> = notification_repo.push_jobs(&[user_id], 0).await;
+    let _: anyhow::Result<()> = notification_repo.push_jobs(&[user_id], 0, PushKind::Check).await;
 
     let start = std::time::Instant::now();
     while start.elapsed() < Duration::from_secs(10) {
@@ -280,7 +280,7 @@ struct ConcurrencyMockProvider;
 
 #[async_trait]
 impl PushProvider for ConcurrencyMockProvider {
-    async fn send_push(&self, _token: &str) -> Result<(), PushError> {
+    async fn send_push(&self, _token: &str, _kind: PushKind) -> Result<(), PushError> {
         let current = IN_FLIGHT_COUNT.fetch_add(1, Ordering::SeqCst) + 1;
 
         loop {
@@ -313,8 +313,7 @@ async fn test_notification_worker_concurrency_limit() {
     let app = TestApp::spawn_with_config(config).await;
     let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
 
-    let notification_repo =
-        Arc::new(NotificationRepository::new(app.resources.pubsub.clone(), &app.config.notifications));
+    let notification_repo = Arc::new(NotificationRepository::new(app.redis(), &app.config.notifications));
 
     let worker = PushNotificationWorker::new(
         app.pool.clone(),
@@ -347,7 +346,7 @@ async fn test_notification_worker_concurrency_limit() {
             let token_repo = obscura_server::adapters::database::push_token_repo::PushTokenRepository::new();
             token_repo.upsert_token(&mut conn, user_id, &format!("token:{user_id}")).await.unwrap();
         }
-        let _: anyhow::Result<()> = notification_repo.push_jobs(&[user_id], 0).await;
+        let _: anyhow::Result<()> = notification_repo.push_jobs(&[user_id], 0, PushKind::Check).await;
     }
 
     tokio::time::sleep(Duration::from_secs(5)).await;
@@ -458,3 +457,35 @@ async fn test_notification_lag_recovery() {
 
     assert_eq!(received, message_count, "Should receive all {message_count} messages despite notification lag");
 }
+
+#[tokio::test]
+async fn test_postgres_realtime_bus_round_trip() {
+    use obscura_server::adapters::realtime::{PostgresRealtimeBus, RealtimeBus};
+    use obscura_server::domain::notification::{EventContext, UserEvent};
+
+    let pool = common::get_test_pool().await;
+    let mut config = common::get_test_config();
+    config.notifications.postgres_channel = format!("obscura_test_{}", Uuid::new_v4().simple());
+    let (_shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let bus = PostgresRealtimeBus::new(pool, &config.notifications, shutdown_rx);
+
+    let mut rx = bus.subscribe_realtime().await.expect("listen on the channel");
+    let device_ids = [Uuid::new_v4(), Uuid::new_v4()];
+    bus.publish_realtime(&device_ids, UserEvent::PreKeyLow, &EventContext::with_count(2)).await.unwrap();
+    bus.publish_broadcast(UserEvent::Announcement).await.unwrap();
+
+    let mut received = Vec::new();
+    for _ in 0..3 {
+        let notification =
+            tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.expect("event arrives").unwrap();
+        received.push((notification.device_id, notification.event, notification.context.count));
+    }
+    assert_eq!(
+        received,
+        [
+            (Some(device_ids[0]), UserEvent::PreKeyLow, Some(2)),
+            (Some(device_ids[1]), UserEvent::PreKeyLow, Some(2)),
+            (None, UserEvent::Announcement, None),
+        ]
+    );
+}
@@ -0,0 +1,339 @@
+#![allow(
+    clippy::unwrap_used,
+    clippy::panic,
+    clippy::todo,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate,
+    missing_debug_implementations,
+    clippy::cast_precision_loss,
+    clippy::clone_on_ref_ptr,
+    clippy::match_same_arms,
+    clippy::items_after_statements,
+    unreachable_pub,
+    clippy::print_stdout,
+    clippy::similar_names
+)]
+//! Guards the wire format clients depend on. `tests/fixtures/proto/schema.txt` records every
+//! field's number and type, and `tests/fixtures/proto/messages/` holds one encoded sample of
+//! each message, recorded when the message was added. Both are checked against the schema the
+//! server is built from, so a renumbered field or an incompatible type change fails here
+//! instead of on deployed clients.
+//!
+//! A missing fixture fails the test like a different one does. Setting `OBSCURA_BLESS_PROTO`
+//! rewrites the schema snapshot and records samples for messages that have none.
+
+use obscura_server::proto::compat;
+use prost::Message;
+use prost::encoding::{WireType, decode_key, decode_varint, encode_key, encode_varint};
+use prost_types::field_descriptor_proto::{Label, Type};
+use prost_types::{DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FileDescriptorSet};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+/// Set to accept a reviewed change to the schema as the new baseline.
+const BLESS_VAR: &str = "OBSCURA_BLESS_PROTO";
+
+fn blessing() -> bool {
+    std::env::var_os(BLESS_VAR).is_some()
+}
+
+/// Nesting depth at which samples stop filling in message fields, so recursive messages end.
+const MAX_DEPTH: usize = 3;
+
+fn fixtures() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/proto")
+}
+
+fn is_map_entry(message: &DescriptorProto) -> bool {
+    message.options.as_ref().is_some_and(prost_types::MessageOptions::map_entry)
+}
+
+/// A decoded field, compared by number and value rather than by where it sits in the encoding.
+#[derive(Debug, PartialEq, Eq)]
+enum Value {
+    Varint(u64),
+    Fixed(Vec<u8>),
+    Bytes(Vec<u8>),
+    Message(Vec<(u32, Value)>),
+}
+
+/// Messages and enums of the compiled schema, keyed by fully qualified name (`.obscura.v1.Envelope`).
+struct Schema {
+    messages: BTreeMap<String, DescriptorProto>,
+    enums: BTreeMap<String, EnumDescriptorProto>,
+}
+
+impl Schema {
+    fn load() -> Self {
+        let descriptors = FileDescriptorSet::decode(compat::FILE_DESCRIPTOR_SET).unwrap();
+        let mut schema = Self { messages: BTreeMap::new(), enums: BTreeMap::new() };
+        for file in descriptors.file {
+            let scope = format!(".{}", file.package());
+            for enumeration in file.enum_type {
+                schema.enums.insert(format!("{scope}.{}", enumeration.name()), enumeration);
+            }
+            for message in file.message_type {
+                schema.add_message(&scope, message);
+            }
+        }
+        schema
+    }
+
+    fn add_message(&mut self, scope: &str, message: DescriptorProto) {
+        let full_name = format!("{scope}.{}", message.name());
+        for enumeration in &message.enum_type {
+            self.enums.insert(format!("{full_name}.{}", enumeration.name()), enumeration.clone());
+        }
+        for nested in &message.nested_type {
+            self.add_message(&full_name, nested.clone());
+        }
+        self.messages.insert(full_name, message);
+    }
+
+    fn message(&self, type_name: &str) -> &DescriptorProto {
+        self.messages.get(type_name).unwrap_or_else(|| panic!("Unknown message {type_name}"))
+    }
+
+    /// One line per field and enum value, in a stable order, so a change reads as a line diff.
+    fn render(&self) -> String {
+        let mut out = String::new();
+        for (name, message) in &self.messages {
+            let mut fields: Vec<&FieldDescriptorProto> = message.field.iter().collect();
+            fields.sort_by_key(|field| field.number());
+            for field in fields {
+                let label = match field.label() {
+                    Label::Repeated => "repeated",
+                    Label::Required => "required",
+                    Label::Optional if field.proto3_optional() => "optional",
+                    Label::Optional => "singular",
+                };
+                let kind = match field.r#type() {
+                    Type::Message | Type::Enum => field.type_name().to_owned(),
+                    scalar => scalar.as_str_name().trim_start_matches("TYPE_").to_lowercase(),
+                };
+                let oneof = match field.oneof_index {
+                    Some(index) if !field.proto3_optional() => {
+                        format!(" oneof {}", message.oneof_decl[usize::try_from(index).unwrap()].name())
+                    }
+                    _ => String::new(),
+                };
+                writeln!(out, "{name}.{} = {} {label} {kind}{oneof}", field.name(), field.number()).unwrap();
+            }
+        }
+        for (name, enumeration) in &self.enums {
+            for value in &enumeration.value {
+                writeln!(out, "{name}.{} = {}", value.name(), value.number()).unwrap();
+            }
+        }
+        out
+    }
+
+    /// An encoding of `message` with every field set to a value other than its default, and
+    /// the first member of each oneof set.
+    fn sample(&self, message: &DescriptorProto, depth: usize) -> Vec<u8> {
+        let mut fields: Vec<&FieldDescriptorProto> = message.field.iter().collect();
+        fields.sort_by_key(|field| field.number());
+        let mut oneofs = HashSet::new();
+        let mut out = Vec::new();
+        for field in fields {
+            if let Some(index) = field.oneof_index
+                && !field.proto3_optional()
+                && !oneofs.insert(index)
+            {
+                continue;
+            }
+            self.sample_field(field, depth, &mut out);
+        }
+        out
+    }
+
+    fn sample_field(&self, field: &FieldDescriptorProto, depth: usize, out: &mut Vec<u8>) {
+        let number = u32::try_from(field.number()).unwrap();
+        let repeated = field.label() == Label::Repeated;
+        match field.r#type() {
+            Type::Message => {
+                let nested = self.message(field.type_name());
+                // A map entry's value sits one level further down
+                let limit = if is_map_entry(nested) { MAX_DEPTH - 1 } else { MAX_DEPTH };
+                if depth + 1 >= limit {
+                    if !repeated {
+                        put_bytes(out, number, &[]);
+                    }
+                    return;
+                }
+                put_bytes(out, number, &self.sample(nested, depth + 1));
+            }
+            Type::String | Type::Bytes => put_bytes(out, number, field.name().as_bytes()),
+            Type::Group => panic!("Groups are not supported: {}", field.name()),
+            scalar => {
+                let Some((wire_type, value)) = self.sample_scalar(field, scalar) else {
+                    return;
+                };
+                let packed = field.options.as_ref().and_then(|options| options.packed).unwrap_or(true);
+                if repeated && packed {
+                    put_bytes(out, number, &value);
+                } else {
+                    encode_key(number, wire_type, out);
+                    out.extend_from_slice(&value);
+                }
+            }
+        }
+    }
+
+    /// `None` for an enum with no value besides zero, which has nothing to send.
+    fn sample_scalar(&self, field: &FieldDescriptorProto, kind: Type) -> Option<(WireType, Vec<u8>)> {
+        let varint = |value: u64| {
+            let mut buf = Vec::new();
+            encode_varint(value, &mut buf);
+            buf
+        };
+        Some(match kind {
+            Type::Double => (WireType::SixtyFourBit, 1.5f64.to_le_bytes().to_vec()),
+            Type::Float => (WireType::ThirtyTwoBit, 1.5f32.to_le_bytes().to_vec()),
+            Type::Fixed64 | Type::Sfixed64 => (WireType::SixtyFourBit, 1u64.to_le_bytes().to_vec()),
+            Type::Fixed32 | Type::Sfixed32 => (WireType::ThirtyTwoBit, 1u32.to_le_bytes().to_vec()),
+            // Zigzag encoding turns 1 into 2
+            Type::Sint32 | Type::Sint64 => (WireType::Varint, varint(2)),
+            Type::Enum => {
+                let enumeration = &self.enums[field.type_name()];
+                let value = enumeration.value.iter().map(|value| value.number()).find(|&number| number != 0)?;
+                (WireType::Varint, varint(i64::from(value).cast_unsigned()))
+            }
+            _ => (WireType::Varint, varint(1)),
+        })
+    }
+
+    /// The fields of an encoded `message` sorted by number, or `None` if it is not valid protobuf.
+    fn canonical(&self, message: &DescriptorProto, mut bytes: &[u8]) -> Option<Vec<(u32, Value)>> {
+        let mut fields = Vec::new();
+        while !bytes.is_empty() {
+            let (number, wire_type) = decode_key(&mut bytes).ok()?;
+            let value = match wire_type {
+                WireType::Varint => Value::Varint(decode_varint(&mut bytes).ok()?),
+                WireType::SixtyFourBit | WireType::ThirtyTwoBit => {
+                    let len = if wire_type == WireType::SixtyFourBit { 8 } else { 4 };
+                    let (value, rest) = bytes.split_at_checked(len)?;
+                    bytes = rest;
+                    Value::Fixed(value.to_vec())
+                }
+                WireType::LengthDelimited => {
+                    let len = usize::try_from(decode_varint(&mut bytes).ok()?).ok()?;
+                    let (payload, rest) = bytes.split_at_checked(len)?;
+                    bytes = rest;
+                    message
+                        .field
+                        .iter()
+                        .find(|field| i32::try_from(number) == Ok(field.number()) && field.r#type() == Type::Message)
+                        .and_then(|field| self.canonical(self.message(field.type_name()), payload))
+                        .map_or_else(|| Value::Bytes(payload.to_vec()), Value::Message)
+                }
+                WireType::StartGroup | WireType::EndGroup => return None,
+            };
+            fields.push((number, value));
+        }
+        // Stable, so repeated values keep their order
+        fields.sort_by_key(|(number, _)| *number);
+        Some(fields)
+    }
+}
+
+fn put_bytes(out: &mut Vec<u8>, number: u32, value: &[u8]) {
+    encode_key(number, WireType::LengthDelimited, out);
+    encode_varint(u64::try_from(value.len()).unwrap(), out);
+    out.extend_from_slice(value);
+}
+
+#[test]
+fn test_schema_matches_snapshot() {
+    let path = fixtures().join("schema.txt");
+    let current = Schema::load().render();
+
+    if blessing() {
+        std::fs::create_dir_all(fixtures()).unwrap();
+        std::fs::write(&path, &current).unwrap();
+        return;
+    }
+    let recorded = std::fs::read_to_string(&path).unwrap_or_else(|e| {
+        panic!("No schema snapshot at {}: {e}. Record it with `just proto-bless` and commit it.", path.display())
+    });
+    if recorded == current {
+        return;
+    }
+
+    let before: HashSet<&str> = recorded.lines().collect();
+    let after: HashSet<&str> = current.lines().collect();
+    let removed: Vec<&str> = recorded.lines().filter(|line| !after.contains(line)).collect();
+    let added: Vec<&str> = current.lines().filter(|line| !before.contains(line)).collect();
+    panic!(
+        "The schema in proto/ differs from {}.\n\n\
+         Removed or changed:\n  {}\n\nAdded:\n  {}\n\n\
+         Adding fields is safe. Renumbering a field or changing its type breaks deployed clients: reserve the old \
+         number and add a new field instead. Once the change is reviewed, accept it with \
+         `{BLESS_VAR}=1 cargo test --test integration_proto_compat`.",
+        path.display(),
+        removed.join("\n  "),
+        added.join("\n  "),
+    );
+}
+
+#[test]
+fn test_recorded_messages_round_trip() {
+    let schema = Schema::load();
+    let dir = fixtures().join("messages");
+    if blessing() {
+        std::fs::create_dir_all(&dir).unwrap();
+    }
+
+    let mut failures = Vec::new();
+    for (name, message) in schema.messages.iter().filter(|(_, message)| !is_map_entry(message)) {
+        let full_name = name.trim_start_matches('.');
+        let path = dir.join(format!("{full_name}.bin"));
+        let recorded = match std::fs::read(&path) {
+            Ok(recorded) => recorded,
+            // Only new messages are recorded, an existing sample is what deployed clients send
+            Err(_) if blessing() => {
+                let sample = schema.sample(message, 0);
+                std::fs::write(&path, &sample).unwrap();
+                sample
+            }
+            Err(e) => {
+                failures.push(format!("{full_name}: no recorded message ({e}), record it with `just proto-bless`"));
+                continue;
+            }
+        };
+
+        let reencoded = match compat::reencode(full_name, &recorded) {
+            Some(Ok(reencoded)) => reencoded,
+            Some(Err(e)) => {
+                failures.push(format!("{full_name}: the recorded message no longer decodes: {e}"));
+                continue;
+            }
+            None => {
+                failures.push(format!("{full_name}: no generated type"));
+                continue;
+            }
+        };
+        // Anything the current type does not know is dropped on the way through
+        if schema.canonical(message, &recorded) != schema.canonical(message, &reencoded) {
+            failures.push(format!("{full_name}: fields were lost or changed decoding the recorded message"));
+        }
+    }
+
+    for entry in std::fs::read_dir(&dir).into_iter().flatten() {
+        let path = entry.unwrap().path();
+        let stem = path.file_stem().unwrap().to_string_lossy();
+        if !schema.messages.contains_key(&format!(".{stem}")) {
+            failures.push(format!("{stem}: recorded, but no longer in the schema"));
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "Recorded messages in {} no longer match the schema:\n  {}\n\n\
+         A deployed client may still send or expect these encodings. If the break is intended, delete the \
+         fixture and run `just proto-bless` to record the message as it is now.",
+        dir.display(),
+        failures.join("\n  "),
+    );
+}
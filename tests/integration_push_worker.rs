@@ -17,8 +17,10 @@ mod common;
 
 use async_trait::async_trait;
 use obscura_server::adapters::database::push_token_repo::PushTokenRepository;
-use obscura_server::adapters::push::{PushError, PushProvider};
+use obscura_server::adapters::push::{PushError, PushFeedback, PushProvider};
+use obscura_server::adapters::push_queue::{DeliveryClaim, PushJob};
 use obscura_server::adapters::redis::NotificationRepository;
+use obscura_server::domain::notification::PushKind;
 use obscura_server::workers::PushNotificationWorker;
 use std::sync::Arc;
 use uuid::Uuid;
@@ -28,7 +30,7 @@ struct FailingPushProvider;
 
 #[async_trait]
 impl PushProvider for FailingPushProvider {
-    async fn send_push(&self, _token: &str) -> Result<(), PushError> {
+    async fn send_push(&self, _token: &str, _kind: PushKind) -> Result<(), PushError> {
         Err(PushError::Unregistered)
     }
 }
@@ -68,7 +70,7 @@ async fn test_push_worker_invalidates_unregistered_tokens() {
     let pubsub =
         obscura_server::adapters::redis::RedisClient::new(&config.pubsub, 1024, shutdown_rx.clone()).await.unwrap();
     let notification_repo = Arc::new(NotificationRepository::new(pubsub.clone(), &config.notifications));
-    let _: anyhow::Result<()> = notification_repo.push_jobs(&[user_id], 0).await;
+    let _: anyhow::Result<()> = notification_repo.push_jobs(&[user_id], 0, PushKind::Check).await;
 
     // 3. Setup Worker with FAILING provider and START it
     let worker = PushNotificationWorker::new(
@@ -109,7 +111,7 @@ struct MockPushProvider;
 
 #[async_trait]
 impl PushProvider for MockPushProvider {
-    async fn send_push(&self, _token: &str) -> Result<(), PushError> {
+    async fn send_push(&self, _token: &str, _kind: PushKind) -> Result<(), PushError> {
         Ok(())
     }
 }
@@ -147,7 +149,7 @@ async fn test_push_worker_removes_job_when_user_has_no_token() {
     let notification_repo = Arc::new(NotificationRepository::new(redis_client.clone(), &config.notifications));
 
     // Push the job
-    notification_repo.push_jobs(&[user_id], 0).await.unwrap();
+    notification_repo.push_jobs(&[user_id], 0, PushKind::Check).await.unwrap();
 
     // 3. Start Worker
     let worker = PushNotificationWorker::new(
@@ -178,3 +180,276 @@ async fn test_push_worker_removes_job_when_user_has_no_token() {
         "Job should have been removed because user has no token, but it was still present: {leased:?}"
     );
 }
+
+#[tokio::test]
+async fn test_job_rescheduled_during_lease_survives_completion() {
+    common::setup_tracing();
+    let mut config = common::get_test_config();
+    config.notifications.push_queue_key = format!("{}-reschedule", config.notifications.push_queue_key);
+
+    let (_shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let redis_client =
+        obscura_server::adapters::redis::RedisClient::new(&config.pubsub, 1024, shutdown_rx).await.unwrap();
+    let notification_repo = NotificationRepository::new(redis_client, &config.notifications);
+    let device_id = Uuid::new_v4();
+
+    notification_repo.push_jobs(&[device_id], 0, PushKind::Check).await.unwrap();
+    let leased = notification_repo.lease_due_jobs(10, 30).await.unwrap();
+    assert_eq!(leased, vec![PushJob { device_id, kind: PushKind::Check }]);
+
+    // A new message arrives while the push for the previous one is still in flight.
+    notification_repo.push_jobs(&[device_id], 0, PushKind::Check).await.unwrap();
+
+    // Completing the stale lease must not drop the freshly scheduled job.
+    notification_repo.delete_job(device_id).await.unwrap();
+
+    let leased = notification_repo.lease_due_jobs(10, 30).await.unwrap();
+    assert_eq!(
+        leased,
+        vec![PushJob { device_id, kind: PushKind::Check }],
+        "Rescheduled job should still be due after the old lease completed"
+    );
+
+    notification_repo.delete_job(device_id).await.unwrap();
+    let leased = notification_repo.lease_due_jobs(10, 30).await.unwrap();
+    assert!(leased.is_empty());
+}
+
+#[tokio::test]
+async fn test_prekey_low_job_upgrades_waiting_check() {
+    common::setup_tracing();
+    let mut config = common::get_test_config();
+    config.notifications.push_queue_key = format!("{}-prekey-low", config.notifications.push_queue_key);
+
+    let (_shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let redis_client =
+        obscura_server::adapters::redis::RedisClient::new(&config.pubsub, 1024, shutdown_rx).await.unwrap();
+    let notification_repo = NotificationRepository::new(redis_client, &config.notifications);
+    let device_id = Uuid::new_v4();
+
+    // A message check is waiting well into the future.
+    notification_repo.push_jobs(&[device_id], 3600, PushKind::Check).await.unwrap();
+    assert!(notification_repo.lease_due_jobs(10, 30).await.unwrap().is_empty());
+
+    // Running out of pre-keys makes it due now and switches its kind.
+    notification_repo.push_jobs(&[device_id], 0, PushKind::PreKeyLow).await.unwrap();
+    let leased = notification_repo.lease_due_jobs(10, 30).await.unwrap();
+    assert_eq!(leased, vec![PushJob { device_id, kind: PushKind::PreKeyLow }]);
+
+    // A later check does not downgrade it.
+    notification_repo.push_jobs(&[device_id], 0, PushKind::Check).await.unwrap();
+    let leased = notification_repo.lease_due_jobs(10, 30).await.unwrap();
+    assert_eq!(leased, vec![PushJob { device_id, kind: PushKind::PreKeyLow }]);
+
+    // Once completed, the device's next job is a plain check again.
+    notification_repo.delete_job(device_id).await.unwrap();
+    notification_repo.push_jobs(&[device_id], 0, PushKind::Check).await.unwrap();
+    let leased = notification_repo.lease_due_jobs(10, 30).await.unwrap();
+    assert_eq!(leased, vec![PushJob { device_id, kind: PushKind::Check }]);
+    notification_repo.delete_job(device_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_delivered_job_is_not_sent_again_after_lease_expiry() {
+    common::setup_tracing();
+    let mut config = common::get_test_config();
+    config.notifications.push_queue_key = format!("{}-delivered", config.notifications.push_queue_key);
+
+    let (_shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let redis_client =
+        obscura_server::adapters::redis::RedisClient::new(&config.pubsub, 1024, shutdown_rx).await.unwrap();
+    let notification_repo = NotificationRepository::new(redis_client, &config.notifications);
+    let device_id = Uuid::new_v4();
+
+    notification_repo.push_jobs(&[device_id], 0, PushKind::Check).await.unwrap();
+    assert_eq!(
+        notification_repo.lease_due_jobs(10, 0).await.unwrap(),
+        vec![PushJob { device_id, kind: PushKind::Check }]
+    );
+
+    // Simulate a worker that sent the push and crashed before completing the job.
+    assert_eq!(notification_repo.claim_delivery(device_id).await.unwrap(), DeliveryClaim::Claimed);
+
+    // The lease has expired, so another worker picks the job up again.
+    assert_eq!(
+        notification_repo.lease_due_jobs(10, 30).await.unwrap(),
+        vec![PushJob { device_id, kind: PushKind::Check }]
+    );
+    assert_eq!(
+        notification_repo.claim_delivery(device_id).await.unwrap(),
+        DeliveryClaim::AlreadySent,
+        "Delivered job must not be claimed twice"
+    );
+
+    // A new event resets the marker so the next push goes out.
+    notification_repo.push_jobs(&[device_id], 0, PushKind::Check).await.unwrap();
+    assert_eq!(
+        notification_repo.lease_due_jobs(10, 30).await.unwrap(),
+        vec![PushJob { device_id, kind: PushKind::Check }]
+    );
+    assert_eq!(notification_repo.claim_delivery(device_id).await.unwrap(), DeliveryClaim::Claimed);
+
+    notification_repo.delete_job(device_id).await.unwrap();
+    assert_eq!(
+        notification_repo.claim_delivery(device_id).await.unwrap(),
+        DeliveryClaim::LeaseLost,
+        "A completed job is not claimed as already sent"
+    );
+}
+
+#[tokio::test]
+async fn test_extend_lease_only_applies_to_leased_jobs() {
+    common::setup_tracing();
+    let mut config = common::get_test_config();
+    config.notifications.push_queue_key = format!("{}-extend", config.notifications.push_queue_key);
+
+    let (_shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let redis_client =
+        obscura_server::adapters::redis::RedisClient::new(&config.pubsub, 1024, shutdown_rx).await.unwrap();
+    let notification_repo = NotificationRepository::new(redis_client, &config.notifications);
+    let device_id = Uuid::new_v4();
+
+    assert!(!notification_repo.extend_lease(device_id, 30).await.unwrap());
+
+    notification_repo.push_jobs(&[device_id], 0, PushKind::Check).await.unwrap();
+    assert_eq!(
+        notification_repo.lease_due_jobs(10, 0).await.unwrap(),
+        vec![PushJob { device_id, kind: PushKind::Check }]
+    );
+    assert!(notification_repo.extend_lease(device_id, 30).await.unwrap());
+
+    // The extended lease keeps the job hidden from other workers.
+    assert!(notification_repo.lease_due_jobs(10, 30).await.unwrap().is_empty());
+
+    notification_repo.delete_job(device_id).await.unwrap();
+    assert!(!notification_repo.extend_lease(device_id, 30).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_postgres_push_queue_lease_lifecycle() {
+    use obscura_server::adapters::push_queue::{PostgresPushJobQueue, PushJobQueue};
+
+    let pool = common::get_test_pool().await;
+    let device_id = Uuid::new_v4();
+
+    sqlx::query("INSERT INTO users (id, username, password_hash) VALUES ($1, $2, 'hash')")
+        .bind(device_id)
+        .bind(format!("pgq_{}", &device_id.to_string()[..8]))
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO devices (id, user_id) VALUES ($1, $1)").bind(device_id).execute(&pool).await.unwrap();
+
+    let queue = PostgresPushJobQueue::new(pool.clone());
+
+    // Unknown devices are skipped rather than failing the batch.
+    queue.push_jobs(&[device_id, Uuid::new_v4()], 0, PushKind::Check).await.unwrap();
+
+    let leased = queue.lease_due_jobs(100, 30).await.unwrap();
+    assert!(leased.iter().any(|job| job.device_id == device_id));
+    assert!(
+        !queue.lease_due_jobs(100, 30).await.unwrap().iter().any(|job| job.device_id == device_id),
+        "Leased job must be hidden"
+    );
+
+    assert!(queue.extend_lease(device_id, 30).await.unwrap());
+    assert_eq!(queue.claim_delivery(device_id).await.unwrap(), DeliveryClaim::Claimed);
+    assert_eq!(queue.claim_delivery(device_id).await.unwrap(), DeliveryClaim::AlreadySent);
+
+    // Rescheduling while leased keeps the job alive past the stale completion.
+    queue.push_jobs(&[device_id], 0, PushKind::Check).await.unwrap();
+    queue.delete_job(device_id).await.unwrap();
+    let leased = queue.lease_due_jobs(100, 30).await.unwrap();
+    assert!(leased.iter().any(|job| job.device_id == device_id), "Rescheduled job should still be due");
+    assert_eq!(
+        queue.claim_delivery(device_id).await.unwrap(),
+        DeliveryClaim::Claimed,
+        "Rescheduled job starts without a delivery marker"
+    );
+
+    queue.delete_job(device_id).await.unwrap();
+    assert_eq!(queue.claim_delivery(device_id).await.unwrap(), DeliveryClaim::LeaseLost);
+
+    // A pre-key refill push pulls a waiting check forward and takes its kind.
+    queue.push_jobs(&[device_id], 3600, PushKind::Check).await.unwrap();
+    queue.push_jobs(&[device_id], 0, PushKind::PreKeyLow).await.unwrap();
+    let leased = queue.lease_due_jobs(100, 30).await.unwrap();
+    assert!(leased.contains(&PushJob { device_id, kind: PushKind::PreKeyLow }));
+
+    queue.delete_job(device_id).await.unwrap();
+    let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM push_jobs WHERE device_id = $1")
+        .bind(device_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining, 0);
+}
+
+#[derive(Debug, Default)]
+struct ThrottledPushProvider;
+
+#[async_trait]
+impl PushProvider for ThrottledPushProvider {
+    async fn send_push(&self, _token: &str, _kind: PushKind) -> Result<(), PushError> {
+        Err(PushError::QuotaExceeded { retry_after: Some(std::time::Duration::from_secs(120)) })
+    }
+}
+
+#[tokio::test]
+async fn test_rate_limited_push_is_rescheduled_to_retry_after() {
+    common::setup_tracing();
+    let mut config = common::get_test_config();
+    config.notifications.push_queue_key = format!("{}-throttled", config.notifications.push_queue_key);
+    // Far shorter than the provider's hint, so a job left to its lease would come back too soon
+    config.notifications.visibility_timeout_secs = 5;
+
+    let pool = common::get_test_pool().await;
+    let user_id = Uuid::new_v4();
+    let token = format!("throttled_{user_id}");
+
+    sqlx::query("INSERT INTO users (id, username, password_hash) VALUES ($1, $2, 'hash')")
+        .bind(user_id)
+        .bind(format!("thr_{}", &user_id.to_string()[..8]))
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO devices (id, user_id) VALUES ($1, $1)").bind(user_id).execute(&pool).await.unwrap();
+    {
+        let mut conn = pool.acquire().await.unwrap();
+        PushTokenRepository::new().upsert_token(&mut conn, user_id, &token).await.unwrap();
+    }
+
+    let (_shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let redis_client =
+        obscura_server::adapters::redis::RedisClient::new(&config.pubsub, 1024, shutdown_rx).await.unwrap();
+    let notification_repo = Arc::new(NotificationRepository::new(redis_client.clone(), &config.notifications));
+    notification_repo.push_jobs(&[user_id], 0, PushKind::Check).await.unwrap();
+
+    let worker = PushNotificationWorker::new(
+        pool.clone(),
+        notification_repo.clone(),
+        Arc::new(ThrottledPushProvider),
+        PushTokenRepository::new(),
+        &config.notifications,
+    );
+    let (tx, mut rx) = tokio::sync::mpsc::channel(10);
+    worker.process_due_jobs(tx).await.unwrap();
+
+    // The rate limit is reported so the worker loop can pause sending
+    let (reported_token, feedback) =
+        tokio::time::timeout(std::time::Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+    assert_eq!(reported_token, token);
+    assert_eq!(feedback, PushFeedback::RateLimited { retry_after: Some(std::time::Duration::from_secs(120)) });
+
+    // The job waits out the provider's hint rather than the lease
+    let score: f64 = redis::cmd("ZSCORE")
+        .arg(&config.notifications.push_queue_key)
+        .arg(user_id.to_string())
+        .query_async(&mut redis_client.publisher())
+        .await
+        .unwrap();
+    let now = time::OffsetDateTime::now_utc().unix_timestamp() as f64;
+    assert!(score >= now + 100.0, "Job should be rescheduled about 120s out, got {score} vs now {now}");
+
+    notification_repo.delete_job(user_id).await.unwrap();
+}
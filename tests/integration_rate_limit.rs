@@ -297,7 +297,8 @@ async fn test_rate_limit_retry_after_header() {
 
     let ip = "7.7.7.7";
 
-    app.client
+    let resp = app
+        .client
         .get(format!("{}/v1/users/{}", app.server_url, Uuid::new_v4()))
         .header("Authorization", format!("Bearer {}", user.token))
         .header("X-Forwarded-For", ip)
@@ -305,6 +306,11 @@ async fn test_rate_limit_retry_after_header() {
         .await
         .unwrap();
 
+    assert_eq!(resp.headers()["ratelimit-limit"], "1");
+    assert_eq!(resp.headers()["ratelimit-remaining"], "0");
+    assert_eq!(resp.headers()["ratelimit-reset"], "1");
+    assert!(resp.headers().get("retry-after").is_none(), "Allowed requests should not carry Retry-After");
+
     let resp = app
         .client
         .get(format!("{}/v1/users/{}", app.server_url, Uuid::new_v4()))
@@ -318,4 +324,7 @@ async fn test_rate_limit_retry_after_header() {
 
     let retry_after = resp.headers().get("retry-after");
     assert!(retry_after.is_some(), "Retry-After header should be present");
+    assert_eq!(retry_after.unwrap(), "1", "Retry-After should round the sub-second wait up");
+    assert_eq!(resp.headers()["ratelimit-remaining"], "0");
+    assert!(resp.headers().get("x-ratelimit-after").is_none(), "Governor headers should be replaced");
 }
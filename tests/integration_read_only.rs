@@ -0,0 +1,94 @@
+#![allow(
+    clippy::unwrap_used,
+    clippy::panic,
+    clippy::todo,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate,
+    missing_debug_implementations,
+    clippy::cast_precision_loss,
+    clippy::clone_on_ref_ptr,
+    clippy::match_same_arms,
+    clippy::items_after_statements,
+    unreachable_pub,
+    clippy::print_stdout,
+    clippy::similar_names
+)]
+use common::TestApp;
+use reqwest::StatusCode;
+
+mod common;
+
+async fn fetch_bundles(app: &TestApp, token: &str, user_id: uuid::Uuid) -> serde_json::Value {
+    let resp = app
+        .client
+        .get(format!("{}/v1/users/{}", app.server_url, user_id))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    resp.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn test_read_only_mode_serves_bundles_and_rejects_writes() {
+    let app = TestApp::spawn().await;
+    let alice = app.register_user_with_keys(&common::generate_username("ro_alice"), 123, 3).await;
+    let bob = app.register_user(&common::generate_username("ro_bob")).await;
+
+    app.db_availability.set_read_only(true);
+
+    // Bundles are served without one-time pre-keys, which would need a write to consume
+    let bundles = fetch_bundles(&app, &bob.token, alice.user_id).await;
+    assert_eq!(bundles.as_array().unwrap().len(), 1);
+    assert!(bundles[0]["oneTimePreKey"].is_null());
+    assert!(!bundles[0]["signedPreKey"].is_null());
+
+    let resp = app
+        .client
+        .post(format!("{}/v1/gateway/ticket", app.server_url))
+        .header("Authorization", format!("Bearer {}", bob.token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let retry_after =
+        app.config.health.db_write_probe_interval_secs * u64::from(app.config.health.db_write_probe_threshold);
+    assert_eq!(resp.headers()["retry-after"], retry_after.to_string().as_str());
+
+    let health: serde_json::Value =
+        app.client.get(format!("{}/readyz", app.mgmt_url)).send().await.unwrap().json().await.unwrap();
+    assert_eq!(health["databaseMode"], "read_only");
+    assert_eq!(health["database"], "ok");
+
+    app.db_availability.set_read_only(false);
+
+    let bundles = fetch_bundles(&app, &bob.token, alice.user_id).await;
+    assert!(!bundles[0]["oneTimePreKey"].is_null());
+    let usage: serde_json::Value = app
+        .client
+        .get(format!("{}/v1/users/me/usage", app.server_url))
+        .header("Authorization", format!("Bearer {}", alice.token))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    // The fetch in read-only mode left the pool untouched
+    assert_eq!(usage["oneTimePreKeys"], 2);
+}
+
+#[tokio::test]
+async fn test_messages_are_downloadable_in_read_only_mode() {
+    let app = TestApp::spawn().await;
+    let alice = app.register_user(&common::generate_username("ro_sender")).await;
+    let bob = app.register_user(&common::generate_username("ro_recipient")).await;
+    app.send_message(&alice.token, bob.device_id, b"sent before the outage").await;
+
+    app.db_availability.set_read_only(true);
+
+    let mut ws = app.connect_ws(&bob.token).await;
+    let envelope = ws.receive_envelope().await.expect("message delivered in read-only mode");
+    assert_eq!(envelope.message, b"sent before the outage");
+}
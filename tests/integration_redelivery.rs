@@ -0,0 +1,117 @@
+#![allow(
+    clippy::unwrap_used,
+    clippy::panic,
+    clippy::todo,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate,
+    missing_debug_implementations,
+    clippy::cast_precision_loss,
+    clippy::clone_on_ref_ptr,
+    clippy::match_same_arms,
+    clippy::items_after_statements,
+    unreachable_pub,
+    clippy::print_stdout,
+    clippy::similar_names
+)]
+use obscura_server::adapters::database::message_repo::MessageRepository;
+use obscura_server::adapters::storage::S3Storage;
+use obscura_server::workers::MessageCleanupWorker;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+mod common;
+use common::TestApp;
+
+async fn redeliver(app: &TestApp, token: &str, message_ids: &[Uuid]) -> serde_json::Value {
+    let resp = app
+        .client
+        .post(format!("{}/v1/messages/redeliver", app.server_url))
+        .bearer_auth(token)
+        .json(&json!({ "messageIds": message_ids }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    resp.json().await.unwrap()
+}
+
+async fn delivered_at_is_set(app: &TestApp, message_id: Uuid) -> bool {
+    sqlx::query_scalar("SELECT delivered_at IS NOT NULL FROM messages WHERE id = $1")
+        .bind(message_id)
+        .fetch_optional(&app.pool)
+        .await
+        .unwrap()
+        .unwrap_or(false)
+}
+
+#[tokio::test]
+async fn test_acknowledged_message_can_be_redelivered() {
+    let mut config = common::get_test_config();
+    config.messaging.ack_grace_period_secs = 300;
+    let app = TestApp::spawn_with_config(config).await;
+
+    let alice = app.register_user(&common::generate_username("redeliver_a")).await;
+    let bob = app.register_user(&common::generate_username("redeliver_b")).await;
+    app.send_message(&alice.token, bob.device_id, b"keep me").await;
+
+    let mut ws = app.connect_ws(&bob.token).await;
+    let env = ws.receive_envelope().await.expect("message delivered");
+    let message_id = Uuid::from_slice(&env.id).unwrap();
+    ws.send_ack(env.id.clone()).await;
+
+    // Acknowledged, but kept for the grace period rather than deleted
+    let flagged = app.wait_until(|| delivered_at_is_set(&app, message_id), Duration::from_secs(5)).await;
+    assert!(flagged, "ACK should flag the message as delivered");
+    app.assert_message_count(bob.device_id, 0).await;
+    drop(ws);
+
+    // The client crashed before storing the message and asks for it again
+    let body = redeliver(&app, &bob.token, &[message_id, Uuid::new_v4()]).await;
+    assert_eq!(body["redelivered"], 1);
+
+    let mut ws = app.connect_ws(&bob.token).await;
+    let env = ws.receive_envelope().await.expect("message redelivered");
+    assert_eq!(env.id, message_id.as_bytes().to_vec());
+    assert_eq!(env.message, b"keep me");
+
+    // Another device cannot pull it back into its own queue
+    let body = redeliver(&app, &alice.token, &[message_id]).await;
+    assert_eq!(body["redelivered"], 0);
+}
+
+#[tokio::test]
+async fn test_delivered_messages_are_purged_after_grace_period() {
+    let mut config = common::get_test_config();
+    config.messaging.ack_grace_period_secs = 1;
+    let app = TestApp::spawn_with_config(config.clone()).await;
+
+    let alice = app.register_user(&common::generate_username("purge_a")).await;
+    let bob = app.register_user(&common::generate_username("purge_b")).await;
+    app.send_message(&alice.token, bob.device_id, b"short lived").await;
+
+    let mut ws = app.connect_ws(&bob.token).await;
+    let env = ws.receive_envelope().await.expect("message delivered");
+    let message_id = Uuid::from_slice(&env.id).unwrap();
+    ws.send_ack(env.id.clone()).await;
+    let flagged = app.wait_until(|| delivered_at_is_set(&app, message_id), Duration::from_secs(5)).await;
+    assert!(flagged, "ACK should flag the message as delivered");
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let body = redeliver(&app, &bob.token, &[message_id]).await;
+    assert_eq!(body["redelivered"], 0, "The grace period has passed");
+
+    let s3_client = obscura_server::initialize_s3_client(&config.storage, &config.outbound).await.unwrap();
+    let storage = Arc::new(S3Storage::new(s3_client, config.storage.bucket.clone()));
+    let repo = MessageRepository::new().with_ack_grace_period(config.messaging.ack_grace_period_secs);
+    let worker = MessageCleanupWorker::new(app.pool.clone(), repo, storage, config.messaging.clone());
+    worker.perform_cleanup().await.unwrap();
+
+    let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM messages WHERE id = $1")
+        .bind(message_id)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining, 0);
+}
@@ -0,0 +1,126 @@
+#![allow(
+    clippy::unwrap_used,
+    clippy::panic,
+    clippy::todo,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate,
+    missing_debug_implementations,
+    clippy::cast_precision_loss,
+    clippy::clone_on_ref_ptr,
+    clippy::match_same_arms,
+    clippy::items_after_statements,
+    unreachable_pub,
+    clippy::print_stdout,
+    clippy::similar_names
+)]
+use obscura_server::adapters::key_value::{KeyValueCache, KeyValueStore, PostgresKeyValueStore};
+use obscura_server::adapters::redis::NotificationRepository;
+use obscura_server::adapters::submission_cache::SubmissionCache;
+use obscura_server::domain::notification::PushKind;
+use obscura_server::workers::RedisJanitorWorker;
+use std::sync::Arc;
+use uuid::Uuid;
+
+mod common;
+use common::TestApp;
+
+async fn ttl(conn: &mut redis::aio::ConnectionManager, key: &str) -> i64 {
+    redis::cmd("TTL").arg(key).query_async(conn).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_janitor_prunes_orphaned_job_state() {
+    let mut config = common::get_test_config();
+    // Sweeping here would race the one under test
+    config.pubsub.janitor_interval_secs = 0;
+    let app = TestApp::spawn_with_config(config).await;
+    let repo = Arc::new(NotificationRepository::new(app.redis(), &app.config.notifications));
+    let queue_key = &app.config.notifications.push_queue_key;
+
+    // A job far enough out that the push worker leaves it alone, with state of its own
+    let device_id = Uuid::new_v4();
+    repo.push_jobs(&[device_id], 3600, PushKind::PreKeyLow).await.unwrap();
+    let queued = device_id.to_string();
+    // and state for a job that is gone, as left by a worker that died after it was cancelled
+    let orphan = Uuid::new_v4().to_string();
+    let mut conn = app.redis().publisher();
+    for suffix in ["leases", "delivered", "kinds"] {
+        for field in [&queued, &orphan] {
+            let _: i64 = redis::cmd("HSET")
+                .arg(format!("{queue_key}:{suffix}"))
+                .arg(field)
+                .arg("1")
+                .query_async(&mut conn)
+                .await
+                .unwrap();
+        }
+    }
+
+    let orphans = repo.prune_orphaned_job_state().await.unwrap();
+    assert_eq!((orphans.leases, orphans.delivery_markers, orphans.kinds), (1, 1, 1));
+
+    for suffix in ["leases", "delivered", "kinds"] {
+        let key = format!("{queue_key}:{suffix}");
+        let has_orphan: bool = redis::cmd("HEXISTS").arg(&key).arg(&orphan).query_async(&mut conn).await.unwrap();
+        let has_queued: bool = redis::cmd("HEXISTS").arg(&key).arg(&queued).query_async(&mut conn).await.unwrap();
+        assert!(!has_orphan, "{suffix} still holds the orphan");
+        assert!(has_queued, "{suffix} lost the entry of the queued job");
+    }
+
+    let again = repo.prune_orphaned_job_state().await.unwrap();
+    assert_eq!((again.leases, again.delivery_markers, again.kinds), (0, 0, 0));
+}
+
+#[tokio::test]
+async fn test_janitor_restores_missing_ttls() {
+    let app = TestApp::spawn().await;
+    let run_id = Uuid::new_v4().simple().to_string();
+    let idempotency_prefix = format!("test:janitor:{run_id}:idempotency:");
+    let ticket_prefix = format!("test:janitor:{run_id}:ticket:");
+    let submissions = KeyValueCache::new(app.redis(), idempotency_prefix.clone(), 600);
+    let tickets = KeyValueCache::new(app.redis(), ticket_prefix.clone(), 30);
+
+    submissions.set("written", b"response").await.unwrap();
+    let mut conn = app.redis().publisher();
+    for key in [format!("{idempotency_prefix}stuck"), format!("{ticket_prefix}stuck")] {
+        let _: () = redis::cmd("SET").arg(&key).arg("value").query_async(&mut conn).await.unwrap();
+    }
+
+    let worker = RedisJanitorWorker::new(
+        None,
+        SubmissionCache::new(Arc::new(submissions), 1024),
+        tickets,
+        Arc::new(PostgresKeyValueStore::new(app.pool.clone())),
+        1,
+    );
+    worker.sweep().await.unwrap();
+
+    let stuck = ttl(&mut conn, &format!("{idempotency_prefix}stuck")).await;
+    assert!((1..=600).contains(&stuck), "idempotency TTL was {stuck}");
+    let stuck = ttl(&mut conn, &format!("{ticket_prefix}stuck")).await;
+    assert!((1..=30).contains(&stuck), "ticket TTL was {stuck}");
+    assert!(ttl(&mut conn, &format!("{idempotency_prefix}written")).await > 0);
+}
+
+#[tokio::test]
+async fn test_janitor_purges_expired_postgres_entries() {
+    let app = TestApp::spawn().await;
+    let store = Arc::new(PostgresKeyValueStore::new(app.pool.clone()));
+    let prefix = format!("test:janitor:{}:", Uuid::new_v4().simple());
+    store.set(&format!("{prefix}expired"), b"old", 0).await.unwrap();
+    store.set(&format!("{prefix}live"), b"new", 600).await.unwrap();
+
+    let tickets = KeyValueCache::new(store.clone(), format!("{prefix}ticket:"), 30);
+    let submissions = KeyValueCache::new(store.clone(), format!("{prefix}idempotency:"), 600);
+    let worker =
+        RedisJanitorWorker::new(None, SubmissionCache::new(Arc::new(submissions), 1024), tickets, store.clone(), 1);
+    worker.sweep().await.unwrap();
+
+    let keys: Vec<String> = sqlx::query_scalar("SELECT key FROM key_value_entries WHERE starts_with(key, $1)")
+        .bind(&prefix)
+        .fetch_all(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(keys, vec![format!("{prefix}live")]);
+    assert_eq!(store.get(&format!("{prefix}live")).await.unwrap(), Some(b"new".to_vec()));
+}
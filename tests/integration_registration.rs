@@ -91,3 +91,38 @@ async fn test_register_flow() {
 
     assert_eq!(resp_keys.status(), StatusCode::OK);
 }
+
+#[tokio::test]
+async fn test_usernames_are_normalized_and_confusables_rejected() {
+    let app = common::TestApp::spawn().await;
+    let suffix = &uuid::Uuid::new_v4().simple().to_string()[..8];
+    let register = |username: String| {
+        app.client
+            .post(format!("{}/v1/users", app.server_url))
+            .json(&json!({ "username": username, "password": "password12345" }))
+            .send()
+    };
+
+    let resp = register(format!("paypal_{suffix}")).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    // Full-width and upper-case letters normalize to the same username
+    let resp = register(format!("ＰＡＹＰＡＬ_{suffix}")).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["error"], "Username already exists");
+
+    let resp = register(format!("paypa1_{suffix}")).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["error"], "Username is too similar to an existing one");
+
+    let resp = app
+        .client
+        .post(format!("{}/v1/sessions", app.server_url))
+        .json(&json!({ "username": format!("PayPal_{suffix}"), "password": "password12345" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK, "Login should normalize the username too");
+}
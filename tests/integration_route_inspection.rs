@@ -0,0 +1,57 @@
+#![allow(
+    clippy::unwrap_used,
+    clippy::panic,
+    clippy::todo,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate,
+    missing_debug_implementations,
+    clippy::cast_precision_loss,
+    clippy::clone_on_ref_ptr,
+    clippy::match_same_arms,
+    clippy::items_after_statements,
+    unreachable_pub,
+    clippy::print_stdout,
+    clippy::similar_names
+)]
+mod common;
+use common::TestApp;
+
+fn route<'a>(tree: &'a serde_json::Value, path: &str) -> &'a serde_json::Value {
+    tree["routes"].as_array().unwrap().iter().find(|r| r["path"] == path).unwrap()
+}
+
+fn layer_names(route: &serde_json::Value) -> Vec<&str> {
+    route["layers"].as_array().unwrap().iter().map(|l| l["name"].as_str().unwrap()).collect()
+}
+
+#[tokio::test]
+async fn test_route_dump_shows_layers_and_timeouts() {
+    let mut config = common::get_test_config();
+    config.server.request_timeout_secs = 7;
+    config.attachment.request_timeout_secs = 11;
+    config.concurrency.attachment_upload_limit = 3;
+    let app = TestApp::spawn_with_config(config).await;
+
+    let resp = app.client.get(format!("{}/debug/routes", app.mgmt_url)).send().await.unwrap();
+    assert_eq!(resp.status(), 200);
+    let tree: serde_json::Value = resp.json().await.unwrap();
+
+    let messages = route(&tree, "/v1/messages");
+    assert_eq!(messages["class"], "api");
+    assert_eq!(messages["methods"], serde_json::json!(["POST"]));
+    let names = layer_names(messages);
+    assert_eq!(names[..2], ["in_flight", "set_request_id"]);
+    let rate_limit = names.iter().position(|n| *n == "rate_limit").unwrap();
+    assert_eq!(names[rate_limit + 1], "timeout");
+    assert_eq!(messages["layers"][rate_limit + 1]["timeoutSecs"], 7);
+
+    let upload = route(&tree, "/v1/attachments");
+    assert_eq!(upload["class"], "storage");
+    let layers = upload["layers"].as_array().unwrap();
+    assert!(layers.iter().any(|l| l["name"] == "timeout" && l["timeoutSecs"] == 11));
+    assert_eq!(layers.last().unwrap()["name"], "concurrency_limit");
+    assert_eq!(layers.last().unwrap()["maxInFlight"], 3);
+
+    let docs = route(&tree, "/openapi.yaml");
+    assert!(docs["class"].is_null());
+}
@@ -16,6 +16,7 @@
 use std::time::Duration;
 use tokio_tungstenite::tungstenite::protocol::Message;
 use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use uuid::Uuid;
 
 mod common;
 
@@ -45,3 +46,79 @@ async fn test_graceful_websocket_shutdown() {
 
     assert!(close_received, "Did not receive graceful close frame within timeout");
 }
+
+#[tokio::test]
+async fn test_drain_finishes_once_websocket_sessions_close() {
+    let app = common::TestApp::spawn().await;
+    let user = app.register_user(&common::generate_username("drain_user")).await;
+    let mut ws = app.connect_ws(&user.token).await;
+    ws.ensure_subscribed().await;
+
+    let drain = tokio::spawn(app.drain_watch.clone().wait(app.shutdown_tx.subscribe(), Duration::from_secs(10)));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(!drain.is_finished(), "Nothing drains before shutdown");
+
+    let _ = app.shutdown_tx.send(true);
+    let drained = tokio::time::timeout(Duration::from_secs(5), drain).await.expect("drain ends before its timeout");
+    assert!(drained.unwrap(), "The session closed, so the drain should finish early");
+}
+
+#[tokio::test]
+async fn test_session_resumes_on_another_instance_after_shutdown() {
+    let config = common::get_test_config();
+    let old = common::TestApp::spawn_with_config(config.clone()).await;
+    let new = common::TestApp::spawn_with_config(config).await;
+    let sender = old.register_user(&common::generate_username("resume_sender")).await;
+    let user = old.register_user(&common::generate_username("resume_user")).await;
+
+    for payload in [b"first", b"other", b"third"] {
+        old.send_message(&sender.token, user.device_id, payload).await;
+    }
+
+    let ticket = old.request_ticket(&user.token).await;
+    let resume_token = ticket["resumeToken"].as_str().expect("resume token issued").to_string();
+    let mut ws = old.connect_ws_with_ticket(ticket["ticket"].as_str().unwrap(), "").await;
+    let first = ws.receive_envelope().await.expect("backlog delivered");
+    assert_eq!(first.message, b"first");
+    ws.send_ack(first.id.clone()).await;
+    old.assert_message_count(user.device_id, 2).await;
+
+    // The other two were written to the socket, but the client never reads them.
+    let _ = old.shutdown_tx.send(true);
+    while !matches!(ws.receive_raw_timeout(Duration::from_secs(5)).await, Some(Ok(Message::Close(_))) | None) {}
+
+    new.send_message(&sender.token, user.device_id, b"fourth").await;
+    let ticket = new.request_ticket(&user.token).await;
+    let next_token = ticket["resumeToken"].as_str().unwrap().to_string();
+    let mut ws =
+        new.connect_ws_with_ticket(ticket["ticket"].as_str().unwrap(), &format!("&resume={resume_token}")).await;
+
+    let mut received = Vec::new();
+    for _ in 0..3 {
+        received.push(ws.receive_envelope().await.expect("unacknowledged and new messages delivered"));
+    }
+    let payloads: Vec<&[u8]> = received.iter().map(|env| env.message.as_slice()).collect();
+    assert_eq!(payloads, [b"other".as_slice(), b"third", b"fourth"]);
+    assert!(
+        ws.receive_envelope_timeout(Duration::from_millis(500)).await.is_none(),
+        "The acknowledged message is not sent again"
+    );
+
+    // The next shutdown hands on a point just below the oldest message still unacknowledged.
+    ws.send_ack(received[0].id.clone()).await;
+    new.assert_message_count(user.device_id, 2).await;
+    let _ = new.shutdown_tx.send(true);
+    while !matches!(ws.receive_raw_timeout(Duration::from_secs(5)).await, Some(Ok(Message::Close(_))) | None) {}
+
+    let resume = obscura_server::adapters::key_value::SessionResume::new(new.redis(), "ws:resume:".to_string(), 300);
+    let state = resume.take(&next_token).await.unwrap().expect("state saved on shutdown");
+    assert_eq!(state.device_id, user.device_id);
+    let third_seq: i64 = sqlx::query_scalar("SELECT seq FROM messages WHERE id = $1")
+        .bind(Uuid::from_slice(&received[1].id).unwrap())
+        .fetch_one(&new.pool)
+        .await
+        .unwrap();
+    assert_eq!(state.fetch_after, Some(third_seq - 1));
+    assert!(state.cursor >= Some(third_seq));
+    assert!(resume.take(&resume_token).await.unwrap().is_none(), "Resume state is single use");
+}
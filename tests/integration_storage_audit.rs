@@ -0,0 +1,119 @@
+#![allow(
+    clippy::unwrap_used,
+    clippy::panic,
+    clippy::todo,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate,
+    missing_debug_implementations,
+    clippy::cast_precision_loss,
+    clippy::clone_on_ref_ptr,
+    clippy::match_same_arms,
+    clippy::items_after_statements,
+    unreachable_pub,
+    clippy::print_stdout,
+    clippy::similar_names
+)]
+use obscura_server::adapters::database::attachment_repo::AttachmentRepository;
+use obscura_server::adapters::database::backup_repo::BackupRepository;
+use obscura_server::adapters::storage::S3Storage;
+use obscura_server::config::AuditReconcile;
+use obscura_server::workers::StorageAuditWorker;
+use std::sync::Arc;
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+
+mod common;
+
+async fn put_object(app: &common::TestApp, bucket: &str, key: &str) {
+    app.s3_client
+        .put_object()
+        .bucket(bucket)
+        .key(key)
+        .body(aws_sdk_s3::primitives::ByteStream::from(b"audit data".to_vec()))
+        .send()
+        .await
+        .unwrap();
+}
+
+async fn object_exists(app: &common::TestApp, bucket: &str, key: &str) -> bool {
+    app.s3_client.head_object().bucket(bucket).key(key).send().await.is_ok()
+}
+
+#[tokio::test]
+async fn test_storage_audit_finds_and_reconciles_drift() {
+    let mut config = common::get_test_config();
+    config.storage.bucket = format!("test-audit-{}", &Uuid::new_v4().to_string()[..8]);
+    config.storage.audit_min_age_secs = 0;
+    // Beyond what validation allows, so every row of the shared test database is sampled. The
+    // bucket is new, so its objects still fit in one listing.
+    config.storage.audit_sample_size = 100_000;
+
+    let app = common::TestApp::spawn_with_config(config.clone()).await;
+    common::ensure_storage_bucket(&app.s3_client, &config.storage.bucket).await;
+    let bucket = config.storage.bucket.clone();
+    let expires_at = OffsetDateTime::now_utc() + Duration::days(1);
+
+    // 1. A consistent attachment: row and object
+    let healthy_id = Uuid::new_v4();
+    sqlx::query("INSERT INTO attachments (id, expires_at) VALUES ($1, $2)")
+        .bind(healthy_id)
+        .bind(expires_at)
+        .execute(&app.pool)
+        .await
+        .unwrap();
+    let healthy_key = format!("{}{}", config.attachment.prefix, healthy_id);
+    put_object(&app, &bucket, &healthy_key).await;
+
+    // 2. A dangling row: the object was never stored
+    let dangling_id = Uuid::new_v4();
+    sqlx::query("INSERT INTO attachments (id, expires_at) VALUES ($1, $2)")
+        .bind(dangling_id)
+        .bind(expires_at)
+        .execute(&app.pool)
+        .await
+        .unwrap();
+
+    // 3. Orphaned objects: an attachment and a backup nobody references
+    let orphan_key = format!("{}{}", config.attachment.prefix, Uuid::new_v4());
+    put_object(&app, &bucket, &orphan_key).await;
+    let orphan_backup_key = format!("{}{}/v1", config.backup.prefix, Uuid::new_v4());
+    put_object(&app, &bucket, &orphan_backup_key).await;
+
+    let storage = Arc::new(S3Storage::new(app.s3_client.clone(), bucket.clone()));
+    let worker = |config: obscura_server::config::Config| {
+        StorageAuditWorker::new(
+            app.pool.clone(),
+            AttachmentRepository::new(),
+            BackupRepository::new(),
+            storage.clone(),
+            config.storage.clone(),
+            &config.attachment,
+            &config.backup,
+        )
+    };
+
+    // 4. Report-only audit leaves everything in place
+    let report = worker(config.clone()).audit().await.expect("Audit failed");
+    assert!(report.dangling_rows >= 1);
+    assert_eq!(report.orphaned_objects, 2);
+    assert_eq!(report.reconciled, 0);
+    assert!(object_exists(&app, &bucket, &orphan_key).await);
+
+    // 5. Reconciling objects removes the orphans and nothing else. Rows are not reconciled here:
+    // the database is shared with other tests, whose objects live in other buckets.
+    let mut reconcile_config = config.clone();
+    reconcile_config.storage.audit_reconcile = AuditReconcile::Objects;
+    let report = worker(reconcile_config).audit().await.expect("Audit failed");
+    assert_eq!(report.reconciled, 2);
+
+    assert!(!object_exists(&app, &bucket, &orphan_key).await, "Orphaned attachment object should be deleted");
+    assert!(!object_exists(&app, &bucket, &orphan_backup_key).await, "Orphaned backup object should be deleted");
+    assert!(object_exists(&app, &bucket, &healthy_key).await, "Referenced object must be kept");
+
+    let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM attachments WHERE id = ANY($1)")
+        .bind(vec![healthy_id, dangling_id])
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining, 2, "Rows are kept when only objects are reconciled");
+}
@@ -37,7 +37,7 @@ async fn test_s3_storage_faulty_stream_resilience() {
 
     // 2. Attempt 'put'
     let key = "faulty-test-key";
-    let res = storage.put(key, faulty_stream, None, 0, 1024).await;
+    let res = storage.put(key, faulty_stream, None, 0, 1024, None).await;
 
     // 3. Verify it failed
     assert!(res.is_err(), "Storage 'put' should fail on faulty stream");
@@ -64,7 +64,7 @@ async fn test_s3_storage_max_size_enforcement() {
 
     // 2. Attempt 'put'
     let key = "too-large-key";
-    let res = storage.put(key, stream, None, 0, 100).await;
+    let res = storage.put(key, stream, None, 0, 100, None).await;
 
     // 3. Verify it failed
     assert!(res.is_err(), "Storage 'put' should fail when exceeding max size");
@@ -90,7 +90,7 @@ async fn test_s3_storage_min_size_enforcement() {
 
     // 2. Attempt 'put'
     let key = "too-small-key";
-    let res = storage.put(key, stream, None, 10, 100).await;
+    let res = storage.put(key, stream, None, 10, 100, None).await;
 
     // 3. Verify it failed (min_size violation returns error)
     assert!(res.is_err(), "Storage 'put' should fail when below min size");
@@ -99,3 +99,97 @@ async fn test_s3_storage_min_size_enforcement() {
     let head_res = app.s3_client.head_object().bucket(&config.storage.bucket).key(key).send().await;
     assert!(head_res.is_err(), "No object should remain in S3 when below min size");
 }
+
+#[tokio::test]
+async fn test_s3_storage_stalled_stream_times_out() {
+    use obscura_server::adapters::storage::StorageError;
+    use std::time::{Duration, Instant};
+
+    let mut config = common::get_test_config();
+    config.storage.bucket = format!("test-stalled-{}", &Uuid::new_v4().to_string()[..8]);
+
+    let app = common::TestApp::spawn_with_config(config.clone()).await;
+    common::ensure_storage_bucket(&app.s3_client, &config.storage.bucket).await;
+
+    let storage = Arc::new(
+        S3Storage::new(app.s3_client.clone(), config.storage.bucket.clone())
+            .with_timeouts(Duration::from_millis(500), Duration::from_secs(30)),
+    );
+
+    // 1. A stream that sends one chunk and then never makes progress again
+    let stalled_stream = stream::iter(vec![Ok(bytes::Bytes::from("first chunk"))]).chain(stream::pending()).boxed();
+
+    // 2. The upload is aborted after the idle timeout instead of hanging
+    let key = "stalled-test-key";
+    let started = Instant::now();
+    let res = storage.put(key, stalled_stream, None, 0, 1024, None).await;
+
+    assert!(matches!(res, Err(StorageError::TimedOut)), "Stalled upload should time out, got {res:?}");
+    assert!(started.elapsed() < Duration::from_secs(10), "Stalled upload should be aborted promptly");
+
+    // 3. Nothing was committed
+    let head_res = app.s3_client.head_object().bucket(&config.storage.bucket).key(key).send().await;
+    assert!(head_res.is_err(), "No object should have been committed to S3 for a stalled upload");
+}
+
+#[tokio::test]
+async fn test_s3_storage_splits_oversized_chunks() {
+    let mut config = common::get_test_config();
+    config.storage.bucket = format!("test-chunks-{}", &Uuid::new_v4().to_string()[..8]);
+
+    let app = common::TestApp::spawn_with_config(config.clone()).await;
+    common::ensure_storage_bucket(&app.s3_client, &config.storage.bucket).await;
+
+    // A buffer far smaller than the single chunk the client sends
+    let storage = Arc::new(
+        S3Storage::new(app.s3_client.clone(), config.storage.bucket.clone()).with_upload_buffer(16 * 1024, 32 * 1024),
+    );
+    let data: Vec<u8> = (0..1024 * 1024u32).map(|i| u8::try_from(i % 251).unwrap()).collect();
+    let stream = stream::iter(vec![Ok(bytes::Bytes::from(data.clone()))]).boxed();
+
+    let key = "oversized-chunk-key";
+    let uploaded = storage.put(key, stream, None, 0, 2 * 1024 * 1024, None).await.unwrap();
+    assert_eq!(uploaded, u64::try_from(data.len()).unwrap());
+
+    let (_, download) = storage.get(key).await.unwrap();
+    let stored: Vec<u8> = download.map(|chunk| chunk.unwrap().to_vec()).concat().await;
+    assert_eq!(stored, data, "The object should arrive whole and in order");
+}
+
+#[tokio::test]
+async fn test_s3_storage_failure_stops_reading_upload() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    let app = common::TestApp::spawn_with_config(common::get_test_config()).await;
+
+    // The bucket does not exist, so S3 rejects the upload while the client is still sending
+    let bucket = format!("test-missing-{}", &Uuid::new_v4().to_string()[..8]);
+    let storage = Arc::new(S3Storage::new(app.s3_client.clone(), bucket));
+
+    /// Records when the upload lets go of the client's body.
+    struct DropFlag(Arc<AtomicBool>);
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+    let dropped = Arc::new(AtomicBool::new(false));
+
+    // One chunk, then a client that never finishes; no idle timeout is set to end it
+    let never_finishing = stream::unfold((DropFlag(Arc::clone(&dropped)), true), |(flag, first)| async move {
+        if first {
+            Some((Ok::<_, std::io::Error>(bytes::Bytes::from(vec![0u8; 1024])), (flag, false)))
+        } else {
+            std::future::pending().await
+        }
+    })
+    .boxed();
+
+    let put = storage.put("key", never_finishing, None, 0, 1024 * 1024, None);
+    let res = tokio::time::timeout(Duration::from_secs(10), put)
+        .await
+        .expect("A failed upload should return without waiting for the client to finish");
+    assert!(res.is_err(), "Upload to a missing bucket should fail");
+    assert!(dropped.load(Ordering::SeqCst), "The client's body should be released once S3 fails");
+}
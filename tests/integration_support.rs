@@ -0,0 +1,151 @@
+#![allow(
+    clippy::unwrap_used,
+    clippy::panic,
+    clippy::todo,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate,
+    missing_debug_implementations,
+    clippy::cast_precision_loss,
+    clippy::clone_on_ref_ptr,
+    clippy::match_same_arms,
+    clippy::items_after_statements,
+    unreachable_pub,
+    clippy::print_stdout,
+    clippy::similar_names
+)]
+mod common;
+
+use common::TestApp;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn test_inbox_diagnostics_report_queue_shape_without_content() {
+    let app = TestApp::spawn().await;
+    let alice = app.register_user(&common::generate_username("support_alice")).await;
+    let bob = app.register_user(&common::generate_username("support_bob")).await;
+
+    app.send_messages(&alice.token, &[(bob.device_id, b"secret one"), (bob.device_id, b"secret two")]).await;
+
+    let resp = app.client.get(format!("{}/debug/users/{}/inbox", app.mgmt_url, bob.user_id)).send().await.unwrap();
+    assert_eq!(resp.status(), 200);
+    let body = resp.text().await.unwrap();
+    assert!(!body.contains("secret"), "Diagnostics must not expose message content");
+
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(json["userId"], bob.user_id.to_string());
+    let devices = json["devices"].as_array().unwrap();
+    assert_eq!(devices.len(), 1);
+
+    let device = &devices[0];
+    assert_eq!(device["deviceId"], bob.device_id.to_string());
+    assert_eq!(device["pendingMessages"], 2);
+    assert!(device["pendingBytes"].as_i64().unwrap() > 0);
+    assert!(device["oldestMessageAgeSecs"].as_i64().unwrap() >= 0);
+    assert_eq!(device["attachments"], 0);
+    assert_eq!(device["pushTokenRegistered"], false);
+    assert!(device["backup"].is_null());
+
+    let resp = app.client.get(format!("{}/debug/users/{}/inbox", app.mgmt_url, Uuid::new_v4())).send().await.unwrap();
+    assert_eq!(resp.status(), 404);
+}
+
+#[tokio::test]
+async fn test_inbox_diagnostics_count_attachments_per_device() {
+    let app = TestApp::spawn().await;
+    common::ensure_storage_bucket(&app.s3_client, &app.config.storage.bucket).await;
+    let alice = app.register_user(&common::generate_username("support_uploader")).await;
+    let bob = app.register_user(&common::generate_username("support_idle")).await;
+
+    for content in [b"first attachment".as_slice(), b"second attachment"] {
+        let resp = app
+            .client
+            .post(format!("{}/v1/attachments", app.server_url))
+            .header("Authorization", format!("Bearer {}", alice.token))
+            .header("Content-Length", content.len().to_string())
+            .body(content.to_vec())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 201);
+    }
+
+    let attachments = |user_id: Uuid| {
+        let url = format!("{}/debug/users/{user_id}/inbox", app.mgmt_url);
+        let client = app.client.clone();
+        async move {
+            let json: serde_json::Value = client.get(url).send().await.unwrap().json().await.unwrap();
+            json["devices"][0]["attachments"].as_i64().unwrap()
+        }
+    };
+    assert_eq!(attachments(alice.user_id).await, 2);
+    assert_eq!(attachments(bob.user_id).await, 0);
+
+    // Expired attachments no longer count
+    sqlx::query("UPDATE attachments SET expires_at = NOW() - INTERVAL '1 second' WHERE device_id = $1")
+        .bind(alice.device_id)
+        .execute(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(attachments(alice.user_id).await, 0);
+}
+
+async fn walk_listing(app: &TestApp, path: &str, key: &str) -> Vec<serde_json::Value> {
+    let mut items = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let url = match &cursor {
+            Some(c) => format!("{}{path}?limit=50&cursor={c}", app.mgmt_url),
+            None => format!("{}{path}?limit=50", app.mgmt_url),
+        };
+        let json: serde_json::Value = app.client.get(url).send().await.unwrap().json().await.unwrap();
+        items.extend(json[key].as_array().unwrap().iter().cloned());
+        match json["nextCursor"].as_str() {
+            Some(next) => cursor = Some(next.to_string()),
+            None => return items,
+        }
+    }
+}
+
+fn position(items: &[serde_json::Value], field: &str, id: Uuid) -> usize {
+    items.iter().position(|item| item[field] == id.to_string()).unwrap()
+}
+
+#[tokio::test]
+async fn test_admin_listings_paginate_with_cursors() {
+    let app = TestApp::spawn().await;
+    let alice = app.register_user(&common::generate_username("listing_alice")).await;
+    let bob = app.register_user(&common::generate_username("listing_bob")).await;
+    let carol = app.register_user(&common::generate_username("listing_carol")).await;
+
+    // Every account appears exactly once, in registration order or its reverse
+    let users = walk_listing(&app, "/users", "users").await;
+    let mut ids: Vec<&str> = users.iter().map(|u| u["userId"].as_str().unwrap()).collect();
+    ids.dedup();
+    assert_eq!(ids.len(), users.len(), "A user was repeated across pages");
+    assert!(position(&users, "userId", alice.user_id) < position(&users, "userId", bob.user_id));
+    assert!(position(&users, "userId", bob.user_id) < position(&users, "userId", carol.user_id));
+    assert!(users.iter().all(|u| u.get("passwordHash").is_none()));
+
+    let first: serde_json::Value =
+        app.client.get(format!("{}/users/recent?limit=2", app.mgmt_url)).send().await.unwrap().json().await.unwrap();
+    assert_eq!(first["users"].as_array().unwrap().len(), 2);
+    assert!(first["nextCursor"].is_string());
+
+    let recent = walk_listing(&app, "/users/recent", "users").await;
+    assert!(position(&recent, "userId", carol.user_id) < position(&recent, "userId", alice.user_id));
+
+    app.send_messages(
+        &alice.token,
+        &[(bob.device_id, b"one"), (bob.device_id, b"two"), (bob.device_id, b"three"), (carol.device_id, b"four")],
+    )
+    .await;
+
+    let inboxes = walk_listing(&app, "/inboxes/largest", "inboxes").await;
+    let bob_inbox = &inboxes[position(&inboxes, "deviceId", bob.device_id)];
+    assert_eq!(bob_inbox["userId"], bob.user_id.to_string());
+    assert_eq!(bob_inbox["pendingMessages"], 3);
+    assert!(position(&inboxes, "deviceId", bob.device_id) < position(&inboxes, "deviceId", carol.device_id));
+
+    let resp = app.client.get(format!("{}/inboxes/largest?cursor=garbage", app.mgmt_url)).send().await.unwrap();
+    assert_eq!(resp.status(), 400);
+}
@@ -0,0 +1,159 @@
+#![allow(
+    clippy::unwrap_used,
+    clippy::panic,
+    clippy::todo,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate,
+    missing_debug_implementations,
+    clippy::cast_precision_loss,
+    clippy::clone_on_ref_ptr,
+    clippy::match_same_arms,
+    clippy::items_after_statements,
+    unreachable_pub,
+    clippy::print_stdout,
+    clippy::similar_names
+)]
+use obscura_server::adapters::database::message_repo::MessageRepository;
+use obscura_server::adapters::storage::S3Storage;
+use obscura_server::workers::MessageCleanupWorker;
+use reqwest::StatusCode;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+mod common;
+use common::{TestApp, TestUser};
+
+async fn set_tier(app: &TestApp, user_id: Uuid, tier: &str) {
+    let resp = app
+        .client
+        .put(format!("{}/users/{user_id}/tier", app.mgmt_url))
+        .json(&json!({ "tier": tier }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["tier"], tier);
+}
+
+async fn refresh(app: &TestApp, user: &TestUser) -> String {
+    let resp = app
+        .client
+        .post(format!("{}/v1/sessions/refresh", app.server_url))
+        .json(&json!({ "refreshToken": user.refresh_token }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    body["token"].as_str().unwrap().to_string()
+}
+
+async fn upload_attachment(app: &TestApp, token: &str, len: usize) -> StatusCode {
+    app.client
+        .post(format!("{}/v1/attachments", app.server_url))
+        .header("Authorization", format!("Bearer {token}"))
+        .header("Content-Length", len.to_string())
+        .body(vec![0u8; len])
+        .send()
+        .await
+        .unwrap()
+        .status()
+}
+
+async fn lookup_user(app: &TestApp, token: &str, forwarded_for: &str) -> reqwest::Response {
+    app.client
+        .get(format!("{}/v1/users/{}", app.server_url, Uuid::new_v4()))
+        .header("Authorization", format!("Bearer {token}"))
+        .header("X-Forwarded-For", forwarded_for)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_paid_tier_attachment_limit_applies_after_refresh() {
+    let mut config = common::get_test_config();
+    config.attachment.max_size_bytes = 100;
+    config.tiers.limits = vec!["paid.attachment-max-size-bytes=1000".parse().unwrap()];
+    let app = TestApp::spawn_with_config(config).await;
+    let user = app.register_user(&common::generate_username("tier_upload")).await;
+
+    assert_eq!(upload_attachment(&app, &user.token, 500).await, StatusCode::PAYLOAD_TOO_LARGE);
+
+    set_tier(&app, user.user_id, "paid").await;
+    assert_eq!(
+        upload_attachment(&app, &user.token, 500).await,
+        StatusCode::PAYLOAD_TOO_LARGE,
+        "The old token still carries the free tier"
+    );
+
+    let token = refresh(&app, &user).await;
+    assert_eq!(upload_attachment(&app, &token, 500).await, StatusCode::CREATED);
+    assert_eq!(upload_attachment(&app, &token, 1001).await, StatusCode::PAYLOAD_TOO_LARGE);
+
+    let resp = app
+        .client
+        .put(format!("{}/users/{}/tier", app.mgmt_url, Uuid::new_v4()))
+        .json(&json!({ "tier": "paid" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_tier_rate_limit_follows_the_user_across_addresses() {
+    let mut config = common::get_test_config();
+    config.tiers.limits =
+        vec!["free.rate-limit-per-second=1".parse().unwrap(), "free.rate-limit-burst=2".parse().unwrap()];
+    let app = TestApp::spawn_with_config(config).await;
+    let alice = app.register_user(&common::generate_username("tier_rate_a")).await;
+    let bob = app.register_user(&common::generate_username("tier_rate_b")).await;
+    // Provisioning the device spent part of the budget, so let it refill
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    assert_eq!(lookup_user(&app, &alice.token, "1.1.1.1").await.status(), StatusCode::NOT_FOUND);
+    assert_eq!(lookup_user(&app, &alice.token, "2.2.2.2").await.status(), StatusCode::NOT_FOUND);
+
+    let resp = lookup_user(&app, &alice.token, "3.3.3.3").await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS, "The budget is shared by every address");
+    assert!(resp.headers().contains_key("retry-after"));
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["code"], "rate_limited");
+
+    assert_eq!(lookup_user(&app, &bob.token, "1.1.1.1").await.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_inbox_overflow_uses_the_owner_tier_limit() {
+    let mut config = common::get_test_config();
+    config.tiers.limits = vec!["paid.inbox-max-size=2".parse().unwrap()];
+    let app = TestApp::spawn_with_config(config.clone()).await;
+    let sender = app.register_user(&common::generate_username("tier_inbox_s")).await;
+    let free = app.register_user(&common::generate_username("tier_inbox_f")).await;
+    let paid = app.register_user(&common::generate_username("tier_inbox_p")).await;
+    set_tier(&app, paid.user_id, "paid").await;
+
+    for i in 0..4 {
+        let payload = format!("msg_{i}").into_bytes();
+        app.send_messages(&sender.token, &[(free.device_id, payload.as_slice()), (paid.device_id, payload.as_slice())])
+            .await;
+    }
+
+    let s3_client = obscura_server::initialize_s3_client(&config.storage, &config.outbound).await.unwrap();
+    let storage = Arc::new(S3Storage::new(s3_client, config.storage.bucket.clone()));
+    let worker =
+        MessageCleanupWorker::new(app.pool.clone(), MessageRepository::new(), storage, config.messaging.clone())
+            .with_tier_limits(&config.tier_limits());
+    worker.perform_cleanup().await.unwrap();
+
+    app.assert_message_count(free.device_id, 4).await;
+    app.assert_message_count(paid.device_id, 2).await;
+
+    let mut ws = app.connect_ws(&paid.token).await;
+    let env = ws.receive_envelope().await.expect("message delivered");
+    assert_eq!(env.message, b"msg_2", "The oldest messages are pruned first");
+}
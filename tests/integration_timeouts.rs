@@ -90,6 +90,59 @@ async fn test_backup_upload_timeout() {
     assert_eq!(resp.status(), StatusCode::REQUEST_TIMEOUT);
 }
 
+#[tokio::test]
+async fn test_backup_upload_timeout_releases_slot() {
+    let mut config = common::get_test_config();
+    config.backup.request_timeout_secs = 2;
+
+    let app = common::TestApp::spawn_with_config(config.clone()).await;
+    common::ensure_storage_bucket(&app.s3_client, &config.storage.bucket).await;
+
+    let username = common::generate_username("timeout_release_backup");
+    let user = app.register_user(&username).await;
+
+    let stalled_stream = stream::unfold(0, |state| async move {
+        if state == 0 {
+            tokio::time::sleep(Duration::from_millis(3000)).await;
+            Some((Ok::<_, std::io::Error>(bytes::Bytes::from(vec![0u8; 40])), 1))
+        } else {
+            None
+        }
+    });
+
+    let resp = app
+        .client
+        .post(format!("{}/v1/backup", app.server_url))
+        .header("Authorization", format!("Bearer {}", user.token))
+        .header("If-None-Match", "*")
+        .header("Content-Length", "40")
+        .body(reqwest::Body::wrap_stream(stalled_stream))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::REQUEST_TIMEOUT);
+
+    // The abandoned upload's slot is released right away, long before it would go stale.
+    let mut status = StatusCode::CONFLICT;
+    for _ in 0..20 {
+        status = app
+            .client
+            .post(format!("{}/v1/backup", app.server_url))
+            .header("Authorization", format!("Bearer {}", user.token))
+            .header("If-None-Match", "*")
+            .body(vec![1u8; 40])
+            .send()
+            .await
+            .unwrap()
+            .status();
+        if status != StatusCode::CONFLICT {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(status, StatusCode::OK);
+}
+
 #[tokio::test]
 async fn test_attachment_upload_timeout() {
     let mut config = common::get_test_config();
@@ -0,0 +1,97 @@
+#![allow(
+    clippy::unwrap_used,
+    clippy::panic,
+    clippy::todo,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate,
+    missing_debug_implementations,
+    clippy::cast_precision_loss,
+    clippy::clone_on_ref_ptr,
+    clippy::match_same_arms,
+    clippy::items_after_statements,
+    unreachable_pub,
+    clippy::print_stdout,
+    clippy::similar_names
+)]
+use common::TestApp;
+use reqwest::StatusCode;
+use uuid::Uuid;
+
+mod common;
+
+async fn get_usage(app: &TestApp, token: &str) -> serde_json::Value {
+    let resp = app
+        .client
+        .get(format!("{}/v1/users/me/usage", app.server_url))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    resp.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn test_usage_reports_stored_data() {
+    let mut config = common::get_test_config();
+    config.storage.bucket = format!("test-usage-{}", &Uuid::new_v4().to_string()[..8]);
+    config.backup.min_size_bytes = 0;
+    config.usage_cache_ttl_secs = 0;
+    let app = TestApp::spawn_with_config(config.clone()).await;
+    common::ensure_storage_bucket(&app.s3_client, &config.storage.bucket).await;
+
+    let user = app.register_user_with_keys(&common::generate_username("usage"), 123, 5).await;
+    let usage = get_usage(&app, &user.token).await;
+    assert_eq!(usage["attachmentBytes"], 0);
+    assert_eq!(usage["backupBytes"], 0);
+    assert_eq!(usage["pendingMessages"], 0);
+    assert_eq!(usage["oneTimePreKeys"], 5);
+
+    let resp = app
+        .client
+        .post(format!("{}/v1/attachments", app.server_url))
+        .header("Authorization", format!("Bearer {}", user.token))
+        .header("Content-Length", "11")
+        .body(b"hello world".to_vec())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    let resp = app
+        .client
+        .post(format!("{}/v1/backup", app.server_url))
+        .header("Authorization", format!("Bearer {}", user.token))
+        .header("If-None-Match", "*")
+        .body(vec![1u8; 42])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    app.send_message(&user.token, user.device_id, b"one").await;
+    app.send_message(&user.token, user.device_id, b"two").await;
+
+    let usage = get_usage(&app, &user.token).await;
+    assert_eq!(usage["attachmentBytes"], 11);
+    assert_eq!(usage["backupBytes"], 42);
+    assert_eq!(usage["pendingMessages"], 2);
+    assert_eq!(usage["oneTimePreKeys"], 5);
+}
+
+#[tokio::test]
+async fn test_usage_is_cached() {
+    let mut config = common::get_test_config();
+    config.usage_cache_ttl_secs = 60;
+    let app = TestApp::spawn_with_config(config).await;
+
+    let user = app.register_user(&common::generate_username("usage_cache")).await;
+    let first = get_usage(&app, &user.token).await;
+    assert_eq!(first["pendingMessages"], 0);
+
+    app.send_message(&user.token, user.device_id, b"hello").await;
+
+    // Served from the cache until it expires
+    let second = get_usage(&app, &user.token).await;
+    assert_eq!(second, first);
+}
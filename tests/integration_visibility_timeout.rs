@@ -19,6 +19,7 @@ use async_trait::async_trait;
 use common::{SharedMockPushProvider, TestApp, notification_counts};
 use obscura_server::adapters::push::{PushError, PushProvider};
 use obscura_server::adapters::redis::NotificationRepository;
+use obscura_server::domain::notification::PushKind;
 use obscura_server::workers::PushNotificationWorker;
 use std::sync::Arc;
 use std::time::Duration;
@@ -29,9 +30,9 @@ struct TransientFailureProvider;
 
 #[async_trait]
 impl PushProvider for TransientFailureProvider {
-    async fn send_push(&self, _token: &str) -> Result<(), PushError> {
+    async fn send_push(&self, _token: &str, _kind: PushKind) -> Result<(), PushError> {
         // Simulate a transient network error
-        Err(PushError::Other(anyhow::anyhow!("Temporary failure")))
+        Err(PushError::Transient(anyhow::anyhow!("Temporary failure")))
     }
 }
 
@@ -72,7 +73,7 @@ async fn test_push_visibility_timeout_retry() {
             .await
             .unwrap();
     let notification_repo = Arc::new(NotificationRepository::new(pubsub.clone(), &config.notifications));
-    notification_repo.push_jobs(&[user_id], 0).await.unwrap();
+    notification_repo.push_jobs(&[user_id], 0, PushKind::Check).await.unwrap();
 
     // 3. Run worker with FAILING provider
     let failing_worker = PushNotificationWorker::new(
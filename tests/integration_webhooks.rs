@@ -0,0 +1,172 @@
+#![allow(
+    clippy::unwrap_used,
+    clippy::panic,
+    clippy::todo,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate,
+    missing_debug_implementations,
+    clippy::cast_precision_loss,
+    clippy::clone_on_ref_ptr,
+    clippy::match_same_arms,
+    clippy::items_after_statements,
+    unreachable_pub,
+    clippy::print_stdout,
+    clippy::similar_names
+)]
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use obscura_server::config::WebhookService;
+use obscura_server::proto::obscura::v1 as proto;
+use prost::Message;
+use reqwest::StatusCode;
+use serde_json::json;
+use uuid::Uuid;
+
+mod common;
+use common::TestApp;
+
+const BILLING_TOKEN: &str = "billing-service-token-0123456789abcdef";
+
+async fn spawn_with_billing() -> TestApp {
+    let mut config = common::get_test_config();
+    config.webhooks.services = vec![WebhookService { name: "billing".to_string(), token: BILLING_TOKEN.to_string() }];
+    config.webhooks.max_message_bytes = 64;
+    TestApp::spawn_with_config(config).await
+}
+
+#[tokio::test]
+async fn test_service_message_is_queued_for_every_device() {
+    let app = spawn_with_billing().await;
+    let user = app.register_user(&common::generate_username("webhook_user")).await;
+
+    let resp = app
+        .client
+        .post(format!("{}/v1/webhooks/messages", app.server_url))
+        .bearer_auth(BILLING_TOKEN)
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&json!({ "userIds": [user.user_id, Uuid::new_v4()], "message": STANDARD.encode(b"sealed notice") }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["queued"], 1);
+
+    let mut ws = app.connect_ws(&user.token).await;
+    let envelope = ws.receive_envelope().await.expect("Service message was not delivered");
+    assert!(envelope.sender_id.is_empty(), "Service messages are system envelopes");
+    assert_eq!(envelope.system_code, proto::SystemCode::ServiceMessage as i32);
+
+    let notice = proto::ServiceMessage::decode(envelope.message.as_slice()).unwrap();
+    assert_eq!(notice.service, "billing");
+    assert_eq!(notice.message, b"sealed notice");
+    assert!(notice.sent_at > 0);
+}
+
+#[tokio::test]
+async fn test_webhook_rejects_unknown_tokens_and_oversized_messages() {
+    let app = spawn_with_billing().await;
+    let user = app.register_user(&common::generate_username("webhook_reject")).await;
+    let url = format!("{}/v1/webhooks/messages", app.server_url);
+    let body = json!({ "userIds": [user.user_id], "message": STANDARD.encode(b"sealed notice") });
+
+    let resp = app.client.post(&url).json(&body).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    // The token is checked before the body is parsed
+    let resp = app.client.post(&url).header("Content-Type", "application/json").body("{").send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    // A user's own session token is not a service token
+    let resp = app.client.post(&url).bearer_auth(&user.token).json(&body).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let oversized = json!({ "userIds": [user.user_id], "message": STANDARD.encode([0u8; 65]) });
+    let resp = app
+        .client
+        .post(&url)
+        .bearer_auth(BILLING_TOKEN)
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&oversized)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    app.assert_message_count(user.device_id, 0).await;
+}
+
+#[tokio::test]
+async fn test_webhook_retries_are_deduplicated_by_idempotency_key() {
+    let app = spawn_with_billing().await;
+    let user = app.register_user(&common::generate_username("webhook_retry")).await;
+    let url = format!("{}/v1/webhooks/messages", app.server_url);
+    let body = json!({ "userIds": [user.user_id], "message": STANDARD.encode(b"sealed notice") });
+
+    let resp = app.client.post(&url).bearer_auth(BILLING_TOKEN).json(&body).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "The idempotency key is required");
+
+    let key = Uuid::new_v4().to_string();
+    for _ in 0..2 {
+        let resp = app
+            .client
+            .post(&url)
+            .bearer_auth(BILLING_TOKEN)
+            .header("Idempotency-Key", &key)
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        let body: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(body["queued"], 1);
+    }
+
+    app.assert_message_count(user.device_id, 1).await;
+}
+
+#[tokio::test]
+async fn test_concurrent_webhook_retries_queue_the_message_once() {
+    let mut config = common::get_test_config();
+    config.webhooks.services = vec![WebhookService { name: "billing".to_string(), token: BILLING_TOKEN.to_string() }];
+    // No response is cached, so only the database can tell the retries apart
+    config.messaging.idempotency_max_response_bytes = 0;
+    let app = TestApp::spawn_with_config(config).await;
+    let user = app.register_user(&common::generate_username("webhook_race")).await;
+    let url = format!("{}/v1/webhooks/messages", app.server_url);
+    let body = json!({ "userIds": [user.user_id], "message": STANDARD.encode(b"sealed notice") });
+
+    let key = Uuid::new_v4().to_string();
+    let send = || app.client.post(&url).bearer_auth(BILLING_TOKEN).header("Idempotency-Key", &key).json(&body).send();
+    let responses = futures::future::join_all((0..4).map(|_| send())).await;
+    for resp in responses {
+        assert_eq!(resp.unwrap().status(), StatusCode::ACCEPTED);
+    }
+    app.assert_message_count(user.device_id, 1).await;
+
+    // Another key is another message
+    let resp = app
+        .client
+        .post(&url)
+        .bearer_auth(BILLING_TOKEN)
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    app.assert_message_count(user.device_id, 2).await;
+}
+
+#[tokio::test]
+async fn test_webhook_is_not_served_without_services() {
+    let app = TestApp::spawn().await;
+    let resp = app
+        .client
+        .post(format!("{}/v1/webhooks/messages", app.server_url))
+        .bearer_auth(BILLING_TOKEN)
+        .json(&json!({ "userIds": [Uuid::new_v4()], "message": STANDARD.encode(b"x") }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
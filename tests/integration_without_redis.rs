@@ -0,0 +1,66 @@
+#![allow(
+    clippy::unwrap_used,
+    clippy::panic,
+    clippy::missing_panics_doc,
+    missing_debug_implementations,
+    unreachable_pub
+)]
+use common::TestApp;
+use obscura_server::config::{PushQueueBackend, RealtimeBackend};
+use reqwest::StatusCode;
+use std::time::Duration;
+
+mod common;
+
+fn postgres_only_config() -> obscura_server::config::Config {
+    let mut config = common::get_test_config();
+    config.notifications.push_queue_backend = PushQueueBackend::Postgres;
+    config.notifications.realtime_backend = RealtimeBackend::Postgres;
+    // Nothing listens here, so any use of Redis would fail
+    config.pubsub.url = "redis://192.0.2.1:6379".to_string();
+    config
+}
+
+#[tokio::test]
+async fn test_server_runs_without_redis_when_notifications_use_postgres() {
+    let app = TestApp::spawn_with_config(postgres_only_config()).await;
+    assert!(app.resources.pubsub.is_none());
+    common::ensure_storage_bucket(&app.s3_client, &app.config.storage.bucket).await;
+
+    let alice = app.register_user(&common::generate_username("no_redis_alice")).await;
+    let bob = app.register_user(&common::generate_username("no_redis_bob")).await;
+
+    // Tickets and session ownership live in Postgres
+    let mut ws = app.connect_ws(&bob.token).await;
+    let owner: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM key_value_entries WHERE key = $1")
+        .bind(format!("ws:session:{}", bob.device_id))
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(owner, 1, "Session ownership was not recorded");
+
+    app.send_message(&alice.token, bob.device_id, b"delivered without redis").await;
+    let envelope = ws.receive_envelope_timeout(Duration::from_secs(5)).await.expect("message was not delivered");
+    ws.send_ack(envelope.id).await;
+    assert!(
+        app.wait_until(
+            || async {
+                sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM messages WHERE device_id = $1")
+                    .bind(bob.device_id)
+                    .fetch_one(&app.pool)
+                    .await
+                    .unwrap()
+                    == 0
+            },
+            Duration::from_secs(5),
+        )
+        .await,
+        "ACK was not applied"
+    );
+
+    let resp = app.client.get(format!("{}/readyz", app.mgmt_url)).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["status"], "ok");
+    assert!(body.get("pubsub").is_none(), "Readiness reported a Redis check: {body}");
+}